@@ -25,7 +25,8 @@
 //! ```
 //!
 //! 2. Call the `datatest_stable::harness!(testfn, root, pattern)` macro with the following
-//! parameters:
+//!    parameters:
+//!
 //! * `testfn` - The test function to be executed on each matching input. This function must have
 //!   the type `fn(&Path) -> datatest_stable::Result<()>`
 //! * `root` - The path to the root directory where the input files (fixtures) live. This path is
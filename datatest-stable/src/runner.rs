@@ -1,10 +1,11 @@
 // Copyright (c) The Diem Core Contributors
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-#![allow(clippy::integer_arithmetic)]
+#![allow(clippy::arithmetic_side_effects)]
 
 use crate::{utils, Result};
 use std::{
+    env,
     io::{self, Write},
     num::NonZeroUsize,
     panic::{catch_unwind, AssertUnwindSafe},
@@ -16,6 +17,10 @@ use std::{
 use structopt::{clap::arg_enum, StructOpt};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+/// Set by nextest while listing tests if it supports its dynamic test discovery protocol.
+const DISCOVERY_PROTOCOL_ENV: &str = "NEXTEST_DISCOVERY_PROTOCOL";
+const DISCOVERY_PROTOCOL_HEADER: &str = "nextest-discovery v1";
+
 #[derive(Debug, StructOpt)]
 #[structopt(about = "Datatest-harness for running data-driven tests")]
 #[allow(dead_code)]
@@ -93,6 +98,7 @@ arg_enum! {
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Format {
     fn default() -> Self {
         Format::Pretty
@@ -113,6 +119,19 @@ pub fn runner(reqs: &[Requirements]) {
     tests.sort_by(|a, b| a.name.cmp(&b.name));
 
     if options.list {
+        // If nextest supports its discovery protocol, list tests in that format so that test names
+        // can contain arbitrary characters.
+        if env::var(DISCOVERY_PROTOCOL_ENV).as_deref() == Ok("1") {
+            println!("{}", DISCOVERY_PROTOCOL_HEADER);
+            for test in &tests {
+                println!(
+                    "{{\"name\": \"{}\"}}",
+                    utils::escape_json_string(&test.name)
+                );
+            }
+            return;
+        }
+
         for test in &tests {
             println!("{}: test", test.name);
         }
@@ -14,7 +14,7 @@ pub fn iterate_directory(path: &Path) -> impl Iterator<Item = PathBuf> {
                 && entry
                     .file_name()
                     .to_str()
-                    .map_or(false, |s| !s.starts_with('.')) // Skip hidden files
+                    .is_some_and(|s| !s.starts_with('.')) // Skip hidden files
         })
         .map(|entry| entry.path().to_path_buf())
 }
@@ -31,3 +31,20 @@ pub fn derive_test_name(root: &Path, path: &Path, test_name: &str) -> String {
     test_name.push_str(&format!("::{}", relative.display()));
     test_name
 }
+
+/// Escapes a string for use within a JSON string literal.
+pub fn escape_json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -6,6 +6,8 @@
 use crate::output::OutputContext;
 use camino::{Utf8Path, Utf8PathBuf};
 use clap::{AppSettings, Args};
+use nextest_metadata::EnvironmentSummary;
+use nextest_runner::env_diff::record_environment;
 use std::path::PathBuf;
 
 /// Options passed down to cargo.
@@ -135,10 +137,29 @@ pub(crate) struct CargoCli<'a> {
     cargo_path: Utf8PathBuf,
     manifest_path: Option<&'a Utf8Path>,
     output: OutputContext,
+    miri: bool,
     command: &'a str,
     args: Vec<&'a str>,
 }
 
+impl CargoOptions {
+    /// Returns the target triple passed in through `--target`, if any.
+    pub(crate) fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Records the parts of the current environment that are likely to affect the results of
+    /// tests built with these options.
+    pub(crate) fn record_environment(&self) -> EnvironmentSummary {
+        record_environment(
+            rustc_version(),
+            &self.features,
+            self.all_features,
+            self.no_default_features,
+        )
+    }
+}
+
 impl<'a> CargoCli<'a> {
     pub(crate) fn new(
         command: &'a str,
@@ -150,11 +171,18 @@ impl<'a> CargoCli<'a> {
             cargo_path,
             manifest_path,
             output,
+            miri: false,
             command,
             args: vec![],
         }
     }
 
+    /// Runs the command through `cargo miri`, e.g. `cargo miri test` rather than `cargo test`.
+    pub(crate) fn set_miri(&mut self) -> &mut Self {
+        self.miri = true;
+        self
+    }
+
     #[allow(dead_code)]
     pub(crate) fn add_arg(&mut self, arg: &'a str) -> &mut Self {
         self.args.push(arg);
@@ -206,6 +234,30 @@ impl<'a> CargoCli<'a> {
         if options.all {
             self.args.push("--all");
         }
+        self.args
+            .extend(options.features.iter().flat_map(|s| ["--features", s]));
+        if options.all_features {
+            self.args.push("--all-features");
+        }
+        if options.no_default_features {
+            self.args.push("--no-default-features");
+        }
+        if options.unit_graph {
+            self.args.push("--unit-graph");
+        }
+        if options.future_incompat_report {
+            self.args.push("--future-incompat-report");
+        }
+        self.add_build_options(options);
+
+        // TODO: other options
+
+        self
+    }
+
+    /// Adds the options that control how artifacts are built, but not which ones are built or the
+    /// features they're built with.
+    pub(crate) fn add_build_options(&mut self, options: &'a CargoOptions) -> &mut Self {
         if options.release {
             self.args.push("--release");
         }
@@ -215,14 +267,6 @@ impl<'a> CargoCli<'a> {
         if let Some(build_jobs) = &options.build_jobs {
             self.args.extend(["--jobs", build_jobs.as_str()]);
         }
-        self.args
-            .extend(options.features.iter().flat_map(|s| ["--features", s]));
-        if options.all_features {
-            self.args.push("--all-features");
-        }
-        if options.no_default_features {
-            self.args.push("--no-default-features");
-        }
         if let Some(target) = &options.target {
             self.args.extend(["--target", target]);
         }
@@ -232,12 +276,6 @@ impl<'a> CargoCli<'a> {
         if options.ignore_rust_version {
             self.args.push("--ignore-rust-version");
         }
-        if options.unit_graph {
-            self.args.push("--unit-graph");
-        }
-        if options.future_incompat_report {
-            self.args.push("--future-incompat-report");
-        }
         if options.frozen {
             self.args.push("--frozen");
         }
@@ -255,21 +293,20 @@ impl<'a> CargoCli<'a> {
                 .iter()
                 .flat_map(|s| ["-Z", s.as_str()]),
         );
-
-        // TODO: other options
-
         self
     }
 
     #[allow(dead_code)]
     pub(crate) fn all_args(&self) -> Vec<&str> {
-        let mut all_args = vec![self.cargo_path.as_str(), self.command];
+        let mut all_args = vec![self.cargo_path.as_str()];
+        all_args.extend(self.command_args());
         all_args.extend_from_slice(&self.args);
         all_args
     }
 
     pub(crate) fn to_expression(&self) -> duct::Expression {
-        let mut initial_args = vec![self.output.color.to_arg(), self.command];
+        let mut initial_args = vec![self.output.color.to_arg()];
+        initial_args.extend(self.command_args());
         if let Some(path) = self.manifest_path {
             initial_args.extend(["--manifest-path", path.as_str()]);
         }
@@ -280,6 +317,119 @@ impl<'a> CargoCli<'a> {
             initial_args.into_iter().chain(self.args.iter().copied()),
         )
     }
+
+    fn command_args(&self) -> impl Iterator<Item = &'a str> {
+        self.miri
+            .then_some("miri")
+            .into_iter()
+            .chain([self.command])
+    }
+}
+
+/// Returns the host triple reported by rustc, or `None` if it couldn't be determined.
+pub(crate) fn host_triple() -> Option<String> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = duct::cmd!(rustc, "-vV")
+        .stdout_capture()
+        .stderr_null()
+        .read()
+        .ok()?;
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_owned())
+}
+
+/// Returns the version reported by `rustc --version`, or `None` if it couldn't be determined.
+pub(crate) fn rustc_version() -> Option<String> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = duct::cmd!(rustc, "--version")
+        .stdout_capture()
+        .stderr_null()
+        .read()
+        .ok()?;
+    Some(output.trim().to_owned())
+}
+
+/// Returns the directory containing the Rust standard library for the given target, or for the
+/// host if `target` is `None`. Returns `None` if the directory couldn't be determined.
+pub(crate) fn rustc_libdir(target: Option<&str>) -> Option<Utf8PathBuf> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let mut args = vec!["--print", "target-libdir"];
+    if let Some(target) = target {
+        args.extend(["--target", target]);
+    }
+    let output = duct::cmd(rustc, args)
+        .stdout_capture()
+        .stderr_null()
+        .read()
+        .ok()?;
+    Some(Utf8PathBuf::from(output.trim()))
+}
+
+/// Returns the root of the workspace containing the given manifest, or the manifest closest to the
+/// current directory if none is given, by reading manifests the same way Cargo does.
+///
+/// This avoids running `cargo locate-project`. Returns `None` if the root couldn't be determined
+/// this way, for example because a manifest couldn't be parsed.
+pub(crate) fn find_workspace_root(manifest_path: Option<&Utf8Path>) -> Option<Utf8PathBuf> {
+    let cwd = Utf8PathBuf::try_from(std::env::current_dir().ok()?).ok()?;
+    let manifest_path = match manifest_path {
+        Some(manifest_path) => cwd.join(manifest_path),
+        None => cwd
+            .ancestors()
+            .map(|dir| dir.join("Cargo.toml"))
+            .find(|manifest_path| manifest_path.is_file())?,
+    };
+    let package_dir = manifest_path.parent()?;
+    let manifest = read_manifest(&manifest_path)?;
+    if manifest.get("workspace").is_some() {
+        return Some(package_dir.to_path_buf());
+    }
+    if manifest
+        .get("package")
+        .and_then(|package| package.get("workspace"))
+        .is_some()
+    {
+        // Cargo normalizes `package.workspace` paths, so leave those to it.
+        return None;
+    }
+
+    for root_dir in package_dir.ancestors().skip(1) {
+        let root_manifest_path = root_dir.join("Cargo.toml");
+        if !root_manifest_path.is_file() {
+            continue;
+        }
+        let root_manifest = read_manifest(&root_manifest_path)?;
+        let workspace = match root_manifest.get("workspace") {
+            Some(workspace) => workspace,
+            None => continue,
+        };
+        let paths = |key: &str| {
+            workspace
+                .get(key)
+                .and_then(|paths| paths.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|path| path.as_str())
+                .map(|path| root_dir.join(path))
+                .collect::<Vec<_>>()
+        };
+        // Packages are excluded from a workspace if they're within an excluded directory, unless
+        // they're listed as a member by their exact path.
+        let excluded = paths("exclude")
+            .iter()
+            .any(|exclude| package_dir.starts_with(exclude))
+            && !paths("members").iter().any(|member| member == package_dir);
+        if !excluded {
+            return Some(root_dir.to_path_buf());
+        }
+    }
+    Some(package_dir.to_path_buf())
+}
+
+fn read_manifest(manifest_path: &Utf8Path) -> Option<toml::Value> {
+    std::fs::read_to_string(manifest_path).ok()?.parse().ok()
 }
 
 fn cargo_path() -> Utf8PathBuf {
@@ -2,25 +2,72 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::{
-    cargo_cli::{CargoCli, CargoOptions},
+    cargo_cli::{
+        find_workspace_root, host_triple, rustc_libdir, rustc_version, CargoCli, CargoOptions,
+    },
     output::{OutputContext, OutputOpts},
     ExpectedError,
 };
 use camino::{Utf8Path, Utf8PathBuf};
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgEnum, Args, Parser, Subcommand};
 use color_eyre::eyre::{Report, Result, WrapErr};
 use guppy::graph::PackageGraph;
+use nextest_metadata::{
+    BuildPlatform, RunEvent, RunInfoSummary, RunSummary, ScheduledRunSummary, TestListSummary,
+    TestReportStatus,
+};
 use nextest_runner::{
-    config::NextestConfig,
+    build_meta::RustBuildMeta,
+    cache::NextestCache,
+    changed::ChangedPackages,
+    config::{NextestConfig, NextestProfile, NextestVersionConfig, NextestVersionEval},
+    coverage::CoverageSelection,
+    custom_reporter::CustomReporter,
+    disk_usage::format_bytes,
+    env_diff::{record_environment, EnvDiff},
+    env_file::EnvFiles,
+    errors::RunStatusError,
+    event_stream::{EventStream, NEXTEST_EVENT_STREAM_ENV},
+    filter_expr::FilterExpr,
+    fingerprint::WorkspaceFingerprint,
+    llvm_coverage::{self, LlvmTools, TestProfile},
+    nesting::{nesting_level, Jobserver},
     partition::PartitionerBuilder,
-    reporter::{StatusLevel, TestOutputDisplay, TestReporterBuilder},
+    property_test::ReplaySeeds,
+    quarantine::Quarantine,
+    renames::TestRenames,
+    report_diff::{ReportDiff, SlowdownThreshold},
+    reporter::{ReporterMode, StatusLevel, TestOutputDisplay, TestReporterBuilder},
+    required_binaries::RequiredBinary,
+    run_report::{RunReport, RunReportRecorder},
+    run_status::{RunStatusPrinter, RunWatcher, StatusServer},
+    run_store::{RunStore, NEXTEST_RUN_INFO_FILE_ENV},
     runner::TestRunnerBuilder,
+    runner_lifecycle::RunnerLifecycleGuard,
+    schedule::{Schedule, ScheduleHistory},
+    selector::Selector,
     signal::SignalHandler,
+    soak::SoakTracker,
+    startup::StartupOverheads,
+    stopwatch::StopwatchStart,
+    target_runner::TargetRunner,
+    test_dependencies::TestDependencies,
     test_filter::{RunIgnored, TestFilterBuilder},
-    test_list::{OutputFormat, RustTestArtifact, TestList},
+    test_list::{
+        BinaryList, DelimitedFormat, EmptyBinaries, ListSettings, OutputFormat, RustTestArtifact,
+        SkippedTestTarget, TestList,
+    },
+    timings::{TestTimings, TimingDatabase, TimingInput},
+    tui::{Tui, TuiAction},
+};
+use semver::Version;
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{Cursor, Write},
+    time::Duration,
 };
-use std::io::Cursor;
 use supports_color::Stream;
+use uuid::Uuid;
 
 /// A new test runner for Rust and Cargo.
 ///
@@ -34,9 +81,13 @@ pub struct CargoNextestApp {
 }
 
 impl CargoNextestApp {
-    pub fn exec(self) -> Result<()> {
+    /// Executes the app.
+    ///
+    /// `inherited_jobserver` is the jobserver inherited from make, Cargo or an outer nextest
+    /// invocation, if any.
+    pub fn exec(self, inherited_jobserver: Option<Jobserver>) -> Result<()> {
         let NextestSubcommand::Nextest(app) = self.subcommand;
-        app.exec()
+        app.exec(inherited_jobserver)
     }
 }
 
@@ -52,6 +103,10 @@ struct AppImpl {
     #[clap(long, global = true, value_name = "PATH")]
     manifest_path: Option<Utf8PathBuf>,
 
+    /// Do not reuse cargo metadata and test lists cached by previous invocations
+    #[clap(long, global = true)]
+    no_cache: bool,
+
     #[clap(flatten)]
     output: OutputOpts,
 
@@ -67,9 +122,51 @@ struct ConfigOpts {
     /// Config file [default: workspace-root/.config/nextest.toml]
     #[clap(long, global = true, value_name = "PATH")]
     pub config_file: Option<Utf8PathBuf>,
+
+    /// Run even if the config requires a newer version of nextest
+    #[clap(long, global = true)]
+    pub override_version_check: bool,
 }
 
 impl ConfigOpts {
+    /// Checks the current version of nextest against the version requirements in the config.
+    ///
+    /// This reads just the `nextest-version` key, so it's done before the rest of the config is
+    /// parsed: a config written for a newer version of nextest may not parse with this one.
+    pub fn check_version(&self, workspace_root: &Utf8Path) -> Result<(), ExpectedError> {
+        let version_config =
+            NextestVersionConfig::from_sources(workspace_root, self.config_file.as_deref())
+                .map_err(ExpectedError::config_parse_error)?;
+        let current =
+            Version::parse(env!("CARGO_PKG_VERSION")).expect("package version is valid semver");
+        match version_config.eval(&current) {
+            NextestVersionEval::Satisfied => {}
+            NextestVersionEval::Error { required, current } => {
+                if self.override_version_check {
+                    log::warn!(
+                        "this repository requires nextest version {}, but the current version is \
+                         {} (overridden with --override-version-check)",
+                        required,
+                        current
+                    );
+                } else {
+                    return Err(ExpectedError::required_version_not_met(required, current));
+                }
+            }
+            NextestVersionEval::Warn {
+                recommended,
+                current,
+            } => {
+                log::warn!(
+                    "this repository recommends nextest version {}, but the current version is {}",
+                    recommended,
+                    current
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// Creates a nextest config with the given options.
     pub fn make_config(&self, workspace_root: &Utf8Path) -> Result<NextestConfig, ExpectedError> {
         NextestConfig::from_sources(workspace_root, self.config_file.as_deref())
@@ -78,6 +175,7 @@ impl ConfigOpts {
 }
 
 #[derive(Debug, Subcommand)]
+#[allow(clippy::large_enum_variant)] // Only one command is parsed, once
 enum Command {
     /// List tests in binary
     List {
@@ -87,6 +185,28 @@ enum Command {
         /// Output format
         #[clap(short = 'T', long, default_value_t, possible_values = OutputFormat::variants(), help_heading = "OUTPUT OPTIONS")]
         format: OutputFormat,
+
+        /// Type of listing: full lists the tests in each binary, while binaries-only lists test
+        /// binaries without running them
+        #[clap(
+            long,
+            arg_enum,
+            default_value_t,
+            value_name = "TYPE",
+            help_heading = "OUTPUT OPTIONS"
+        )]
+        list_type: ListType,
+
+        /// Print one row per test, with its package, binary ID, name, whether it's ignored, whether
+        /// it matches the filter and its platform, instead of the usual output
+        #[clap(
+            long,
+            arg_enum,
+            value_name = "FORMAT",
+            conflicts_with_all = &["format", "list-type"],
+            help_heading = "OUTPUT OPTIONS"
+        )]
+        message_format: Option<MessageFormat>,
     },
     /// Run tests
     Run {
@@ -94,6 +214,71 @@ enum Command {
         #[clap(long, short = 'P')]
         profile: Option<String>,
 
+        /// Write information about the run, including where its summary will be written, to this
+        /// file as it starts ("-" for stdout)
+        #[clap(long, env = NEXTEST_RUN_INFO_FILE_ENV, value_name = "PATH")]
+        run_info_file: Option<Utf8PathBuf>,
+
+        /// Publish events for the run, one JSON object per line, to the Unix domain socket or
+        /// named pipe at this path
+        #[clap(long, env = NEXTEST_EVENT_STREAM_ENV, value_name = "PATH")]
+        event_stream: Option<Utf8PathBuf>,
+
+        /// Only run tests that failed or were flaky in the latest run with this profile
+        #[clap(long, help_heading = "FILTER OPTIONS")]
+        failed: bool,
+
+        /// Like --failed, and also show the output of rerun tests that pass
+        #[clap(long, help_heading = "FILTER OPTIONS")]
+        failed_with_output: bool,
+
+        /// Run tests from this test list, produced by `cargo nextest list --format json`, instead
+        /// of building and listing tests. Filter options are applied to the tests in the list
+        #[clap(long, value_name = "PATH", help_heading = "FILTER OPTIONS")]
+        test_list_file: Option<Utf8PathBuf>,
+
+        /// Let this selector, defined in the config, choose the tests to run and their order. If
+        /// the selector fails, all tests are run
+        #[clap(
+            long,
+            env = "NEXTEST_SELECTOR",
+            value_name = "NAME",
+            help_heading = "FILTER OPTIONS"
+        )]
+        selector: Option<String>,
+
+        /// Only run tests that cover code changed since the commit of this coverage map, and tests
+        /// that aren't in it. If the map is stale, all tests are run
+        #[clap(
+            long,
+            value_name = "PATH",
+            conflicts_with = "selector",
+            help_heading = "FILTER OPTIONS"
+        )]
+        select_by_coverage: Option<Utf8PathBuf>,
+
+        /// Only run the property-based tests that recorded a failing seed in this run report,
+        /// passing them the same seed to reproduce the failure
+        #[clap(
+            long,
+            value_name = "REPORT",
+            conflicts_with_all = &["failed", "failed-with-output"],
+            help_heading = "FILTER OPTIONS"
+        )]
+        replay: Option<Utf8PathBuf>,
+
+        /// Run the tests over and over until this much time has passed (e.g. "8h"), recording the
+        /// duration and peak memory usage of each test, and flagging tests whose memory usage keeps
+        /// growing
+        #[clap(
+            long,
+            value_name = "DURATION",
+            parse(try_from_str = humantime::parse_duration),
+            conflicts_with = "tui",
+            help_heading = "RUNNER OPTIONS"
+        )]
+        soak: Option<Duration>,
+
         /// Run tests serially and do not capture output
         #[clap(
             long,
@@ -112,6 +297,118 @@ enum Command {
         #[clap(flatten)]
         reporter_opts: TestReporterOpts,
     },
+    /// Show the results of the latest run, without running tests again
+    ShowReport {
+        /// Nextest profile the run used
+        #[clap(long, short = 'P')]
+        profile: Option<String>,
+
+        /// Only show tests with these statuses
+        #[clap(long, possible_values = TestReportStatus::variants(), value_name = "STATUS", multiple_occurrences = true)]
+        status: Vec<TestReportStatus>,
+
+        /// Output format
+        #[clap(short = 'T', long, default_value_t, possible_values = OutputFormat::variants())]
+        format: OutputFormat,
+
+        /// Test name filter
+        #[clap(name = "FILTERS")]
+        filter: Vec<String>,
+    },
+    /// Follow the progress of a run that's in progress in another process, until it finishes
+    ///
+    /// Tests that have already finished are shown first.
+    Status {
+        /// Nextest profile the run uses
+        #[clap(long, short = 'P')]
+        profile: Option<String>,
+    },
+    /// Compare the environment recorded in a run summary, such as one from CI, against the local
+    /// environment
+    EnvDiff {
+        /// The summary of the run to compare against, written to `summary.json` in the run
+        /// directory
+        #[clap(value_name = "RUN_SUMMARY")]
+        run_summary: Utf8PathBuf,
+
+        /// Features the local environment would activate
+        #[clap(long)]
+        features: Vec<String>,
+
+        /// Compare against a local environment that activates all available features
+        #[clap(long)]
+        all_features: bool,
+
+        /// Compare against a local environment that doesn't activate the `default` feature
+        #[clap(long)]
+        no_default_features: bool,
+
+        /// Target triple the local environment would build for [default: the host triple]
+        #[clap(long, value_name = "TRIPLE")]
+        target: Option<String>,
+
+        /// Output format
+        #[clap(short = 'T', long, default_value_t, possible_values = OutputFormat::variants())]
+        format: OutputFormat,
+    },
+    /// Measure how long each test binary takes to start up, and report binaries that are slow to
+    /// start
+    ///
+    /// Each binary is run with a filter that matches none of its tests. Since every test is run in
+    /// its own process, a binary's startup time is paid once for each of its tests.
+    MeasureStartup {
+        #[clap(flatten)]
+        build_filter: TestBuildFilter,
+
+        /// Number of times to run each binary. The fastest run is reported
+        #[clap(long, default_value = "3", value_name = "N")]
+        runs: usize,
+
+        /// Report binaries that take longer than this to start
+        #[clap(long, default_value = "250ms", value_name = "DURATION", parse(try_from_str = humantime::parse_duration))]
+        threshold: Duration,
+
+        /// Output format
+        #[clap(short = 'T', long, default_value_t, possible_values = OutputFormat::variants())]
+        format: OutputFormat,
+    },
+    /// Work with the reports written out at the end of each run
+    Report {
+        #[clap(subcommand)]
+        command: ReportCommand,
+    },
+    /// Work with the timing data used to predict how long tests take
+    Timings {
+        #[clap(subcommand)]
+        command: TimingsCommand,
+    },
+    /// Manage the runs kept in the store directory
+    Store {
+        #[clap(subcommand)]
+        command: StoreCommand,
+    },
+    /// Run tests periodically, without a CI system
+    Schedule {
+        #[clap(subcommand)]
+        command: ScheduleCommand,
+    },
+    /// Show a fingerprint of the workspace's sources, lockfile and Rust compiler, for use as a CI
+    /// cache key
+    ShowFingerprint {
+        /// Output format
+        #[clap(short = 'T', long, default_value_t, possible_values = OutputFormat::variants())]
+        format: OutputFormat,
+    },
+    /// Show the fully-resolved config: the store, test groups and every profile, with the
+    /// settings each profile inherits filled in
+    ///
+    /// With `--message-format json`, the output is a `ConfigSummary` from the nextest-metadata
+    /// crate, for tools that audit test settings across repositories.
+    ShowConfig {
+        /// Output format
+        #[clap(short = 'T', long, alias = "message-format", default_value_t, possible_values = OutputFormat::variants())]
+        format: OutputFormat,
+    },
 }
 
 #[derive(Debug, Args)]
@@ -128,22 +425,515 @@ struct TestBuildFilter {
     #[clap(long)]
     partition: Option<PartitionerBuilder>,
 
+    /// Collect the reasons tests are ignored, through libtest's unstable JSON output
+    #[clap(long)]
+    ignore_reasons: bool,
+
+    /// Only include tests in packages changed since this Git revision, along with packages that
+    /// depend on them
+    #[clap(long, value_name = "GIT_REF")]
+    changed_since: Option<String>,
+
+    /// Only include tests matching this filter expression, e.g. 'binary(foo) & test(bar)'. If
+    /// specified more than once, tests matching any of the expressions are included
+    #[clap(long, short = 'E', value_name = "EXPR", multiple_occurrences = true)]
+    filter_expr: Vec<FilterExpr>,
+
+    /// Include tests that don't match the profile's default-filter
+    #[clap(long)]
+    ignore_default_filter: bool,
+
+    /// What to do with test binaries that don't contain any tests. With filter expressions, only
+    /// applies to binaries matched by empty-binary(); other empty binaries are skipped
+    #[clap(long, possible_values = EmptyBinaries::variants(), default_value_t, value_name = "WHAT")]
+    empty_binaries: EmptyBinaries,
+
+    /// Enable the features required by test targets that would otherwise be skipped because of
+    /// their required-features
+    #[clap(long)]
+    enable_required_features: bool,
+
+    /// Build, list and run tests under Miri, through `cargo miri` (requires nightly Rust). Uses the
+    /// default-miri profile unless another profile is specified, and runs half as many tests at once
+    /// by default
+    #[clap(long, conflicts_with = "devices")]
+    miri: bool,
+
+    /// Build tests with LLVM source-based coverage instrumentation, and collect the coverage of
+    /// each test into the run directory
+    #[clap(long, conflicts_with = "miri")]
+    coverage: bool,
+
+    /// Android device to run tests on through an adb target runner, by serial number. If specified
+    /// more than once, tests are spread across the devices
+    #[clap(long = "device", value_name = "SERIAL", multiple_occurrences = true)]
+    devices: Vec<String>,
+
     // TODO: add regex-based filtering in the future?
     /// Test name filter
     #[clap(name = "FILTERS", help_heading = None)]
     filter: Vec<String>,
 }
 
+#[derive(Debug, Subcommand)]
+enum ReportCommand {
+    /// Compare two run reports, listing tests that newly fail, newly pass, newly became flaky or
+    /// got significantly slower
+    ///
+    /// Exits with a failure if any tests newly fail or became flaky, so that it can be used as a
+    /// status check. Tests are matched up by their current names, after applying renames.
+    Diff {
+        /// The report of the old run, the baseline (run-report.json)
+        #[clap(value_name = "OLD")]
+        old: Utf8PathBuf,
+
+        /// The report of the new run (run-report.json)
+        #[clap(value_name = "NEW")]
+        new: Utf8PathBuf,
+
+        /// Report tests whose last attempt took at least this many times as long in the new run
+        #[clap(long, default_value_t = SlowdownThreshold::DEFAULT_RATIO, value_name = "RATIO")]
+        slower_ratio: f64,
+
+        /// Only report tests as slower if they took at least this much longer in the new run
+        #[clap(long, default_value = "100ms", value_name = "DURATION", parse(try_from_str = humantime::parse_duration))]
+        slower_min: Duration,
+
+        /// Output format
+        #[clap(short = 'T', long, alias = "message-format", default_value_t, possible_values = OutputFormat::variants())]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TimingsCommand {
+    /// Merge run reports and timing databases, such as those from the shards of a CI run, into a
+    /// single timing database
+    ///
+    /// Inputs are merged in order. If a test is in several inputs, its duration is an
+    /// exponentially weighted moving average of its durations in each of them. The report of a run
+    /// that's already in the database is skipped, so merges can be repeated.
+    Merge {
+        /// Path to write the timing database to. To update an existing database, also pass it as
+        /// the first input
+        #[clap(long, short = 'o', value_name = "PATH")]
+        output: Utf8PathBuf,
+
+        /// Weight given to the newer duration of a test that's in several inputs, between 0 and 1
+        #[clap(long, default_value_t = TimingDatabase::DEFAULT_WEIGHT, value_name = "WEIGHT", parse(try_from_str = parse_weight))]
+        weight: f64,
+
+        /// Run reports (run-report.json) and timing databases to merge
+        #[clap(value_name = "INPUT", required = true)]
+        inputs: Vec<Utf8PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum StoreCommand {
+    /// Remove old runs from the store directories of all profiles, according to the
+    /// `store.retention` policy in the config
+    Gc,
+}
+
+#[derive(Debug, Subcommand)]
+enum ScheduleCommand {
+    /// Run `cargo nextest run` periodically, appending the result of each run to `history.jsonl` in
+    /// the profile's store directory
+    ///
+    /// Runs start at multiples of the interval after the first one. If a run takes longer than the
+    /// interval, the runs it overlapped with are skipped.
+    Run {
+        /// Nextest profile to use
+        #[clap(long, short = 'P')]
+        profile: Option<String>,
+
+        /// How often to start a run, e.g. "1h"
+        #[clap(long, value_name = "DURATION", parse(try_from_str = parse_interval))]
+        every: Duration,
+
+        /// Stop after this many runs [default: keep running]
+        #[clap(long, value_name = "N")]
+        max_runs: Option<usize>,
+
+        /// Keep running in the background, detached from the terminal, logging to `schedule.log` in
+        /// the profile's store directory
+        #[clap(long)]
+        detach: bool,
+
+        /// Arguments passed to each `cargo nextest run`
+        #[clap(value_name = "RUN_ARGS", last = true)]
+        run_args: Vec<String>,
+    },
+}
+
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(s) {
+        Ok(interval) if !interval.is_zero() => Ok(interval),
+        Ok(_) => Err("the interval must be greater than zero".to_owned()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn parse_weight(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(weight) if (0.0..=1.0).contains(&weight) => Ok(weight),
+        _ => Err(format!("expected a number between 0 and 1, found '{}'", s)),
+    }
+}
+
+/// The type of listing produced by `cargo nextest list`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, ArgEnum)]
+enum ListType {
+    /// List the tests in each test binary.
+    #[default]
+    Full,
+
+    /// Only list test binaries, without running them to find the tests within them.
+    BinariesOnly,
+}
+
+/// A delimited format for `cargo nextest list --message-format`.
+#[derive(Copy, Clone, Debug, PartialEq, ArgEnum)]
+enum MessageFormat {
+    /// Comma-separated values
+    Csv,
+
+    /// Tab-separated values
+    Tsv,
+}
+
+impl MessageFormat {
+    fn to_delimited(self) -> DelimitedFormat {
+        match self {
+            MessageFormat::Csv => DelimitedFormat::Csv,
+            MessageFormat::Tsv => DelimitedFormat::Tsv,
+        }
+    }
+}
+
 impl TestBuildFilter {
-    fn compute<'g>(&self, graph: &'g PackageGraph, output: OutputContext) -> Result<TestList<'g>> {
-        let manifest_path = graph.workspace().root().join("Cargo.toml");
-        let mut cargo_cli = CargoCli::new("test", Some(&manifest_path), output);
+    /// Returns the profile used if none is specified.
+    fn default_profile(&self) -> &'static str {
+        match self.miri {
+            true => NextestConfig::DEFAULT_MIRI_PROFILE,
+            false => NextestConfig::DEFAULT_PROFILE,
+        }
+    }
+
+    /// Skips the tests the profile excludes: those that don't match its default filter, unless
+    /// `--ignore-default-filter` is passed, and those its overrides skip.
+    fn apply_profile_filters(&self, profile: &NextestProfile<'_>, test_list: &mut TestList<'_>) {
+        if !self.ignore_default_filter {
+            if let Some(default_filter) = profile.default_filter() {
+                test_list.apply_default_filter(default_filter);
+            }
+        }
+        test_list.apply_skip_overrides(profile);
+    }
+
+    /// Returns the host triple, and the triple tests are built for: the `--target` triple, or else
+    /// the host triple.
+    fn triples(&self) -> (Option<String>, Option<String>) {
+        let host_triple = host_triple();
+        let target_triple = self
+            .cargo_options
+            .target()
+            .map(|target| target.to_owned())
+            .or_else(|| host_triple.clone());
+        (host_triple, target_triple)
+    }
+
+    fn compute<'g>(
+        &self,
+        graph: &'g PackageGraph,
+        config: &NextestConfig,
+        mut list_settings: ListSettings<'_>,
+        manifest_path: Option<&Utf8Path>,
+        rerun_tests: Option<Vec<(String, String)>>,
+        output: OutputContext,
+    ) -> Result<TestList<'g>> {
+        let test_artifacts = self.compute_artifacts(graph, manifest_path, output)?;
+        let test_filter = self.make_test_filter(graph, rerun_tests)?;
+        list_settings
+            .set_ignore_reasons(self.ignore_reasons)
+            .set_empty_binaries(self.empty_binaries);
+        let mut test_list =
+            TestList::new_with_settings(test_artifacts, &test_filter, &list_settings)
+                .map_err(ExpectedError::test_list_creation_failed)?;
+        add_other_tests(graph, config, &mut test_list, &test_filter)?;
+        for required_binary in config.required_binaries() {
+            let path = self.build_required_binary(graph, required_binary, manifest_path, output)?;
+            test_list.add_required_binary(required_binary, &path);
+        }
+
+        let renamed: Vec<_> = test_list
+            .renamed_tests()
+            .map(|instance| format!("{} {}", instance.bin_info.binary_id, instance.name))
+            .collect();
+        if !renamed.is_empty() {
+            log::warn!(
+                "renamed {} tests whose binaries listed their names more than once: {}",
+                renamed.len(),
+                renamed.join(", "),
+            );
+        }
+        Ok(test_list)
+    }
+
+    /// Loads a test list previously written out by `cargo nextest list`, applying the filter to it.
+    fn load_test_list<'g>(
+        &self,
+        graph: &'g PackageGraph,
+        config: &NextestConfig,
+        list_settings: &ListSettings<'_>,
+        path: &Utf8Path,
+        rerun_tests: Option<Vec<(String, String)>>,
+    ) -> Result<TestList<'g>> {
+        let contents = std::fs::read_to_string(path).map_err(|err| {
+            ExpectedError::setup_error(format!("failed to read test list file '{}'", path), err)
+        })?;
+        let summary = TestListSummary::parse_json(&contents).map_err(|err| {
+            ExpectedError::setup_error(format!("failed to parse test list file '{}'", path), err)
+        })?;
+        let test_filter = self.make_test_filter(graph, rerun_tests)?;
+        let mut test_list = TestList::from_summary(graph, summary, &test_filter, list_settings)
+            .map_err(ExpectedError::test_list_creation_failed)?;
+        // Other tests aren't part of serialized test lists, so they're listed again here.
+        add_other_tests(graph, config, &mut test_list, &test_filter)?;
+        Ok(test_list)
+    }
+
+    /// Returns list settings with the cache, if any, and the runners and Rust library directories
+    /// used to run test binaries built for the host and target platforms.
+    fn make_list_settings<'a>(
+        &self,
+        profile: &NextestProfile<'_>,
+        cache: Option<&'a NextestCache>,
+    ) -> Result<ListSettings<'a>> {
+        let target_triple = self.cargo_options.target();
+        let mut build_meta = RustBuildMeta::default();
+        for (build_platform, target) in [
+            (BuildPlatform::Host, None),
+            (BuildPlatform::Target, target_triple),
+        ] {
+            if let Some(libdir) = rustc_libdir(target) {
+                build_meta.set_libdir(build_platform, libdir);
+            }
+        }
+
+        let cwd = std::env::current_dir()
+            .map_err(|err| ExpectedError::setup_error("failed to get current directory", err))?;
+        let cwd = Utf8PathBuf::try_from(cwd).map_err(|err| {
+            ExpectedError::setup_error("current directory is not valid UTF-8", err)
+        })?;
+        let mut target_runner = TargetRunner::new(
+            profile.target_runner_rules(),
+            profile.workspace_root(),
+            &cwd,
+            host_triple().as_deref(),
+            target_triple,
+            profile.wasm_runtime(),
+        )
+        .map_err(|err| ExpectedError::setup_error("failed to look up target runners", err))?;
+        if self.miri {
+            target_runner.set_miri();
+        } else if !self.devices.is_empty() {
+            target_runner.set_adb_devices(self.devices.clone());
+        }
+        for build_platform in [BuildPlatform::Host, BuildPlatform::Target] {
+            if let Some(runner) = target_runner.for_platform(build_platform) {
+                log::debug!(
+                    "using runner '{}' for {:?} binaries, from {}",
+                    runner.program(),
+                    build_platform,
+                    runner.source()
+                );
+            }
+        }
+
+        let mut list_settings = ListSettings::default();
+        if let Some(cache) = cache {
+            list_settings.set_cache(cache);
+        }
+        list_settings
+            .set_build_meta(build_meta)
+            .set_target_runner(target_runner)
+            .set_missing_cwd(profile.missing_cwd())
+            // Instrumented binaries write a raw profile to their working directory by default, so
+            // discard the profiles written while listing tests. Tests are given their own path.
+            .set_discard_coverage(self.coverage);
+        Ok(list_settings)
+    }
+
+    /// Builds tests and returns the test binaries that the filter could match tests in, without
+    /// running them.
+    fn compute_binary_list<'g>(
+        &self,
+        graph: &'g PackageGraph,
+        manifest_path: Option<&Utf8Path>,
+        output: OutputContext,
+    ) -> Result<BinaryList<'g>> {
+        let test_artifacts = self.compute_artifacts(graph, manifest_path, output)?;
+        let test_filter = self.make_test_filter(graph, None)?;
+        Ok(BinaryList::new(test_artifacts, &test_filter))
+    }
+
+    fn compute_artifacts<'g>(
+        &self,
+        graph: &'g PackageGraph,
+        manifest_path: Option<&Utf8Path>,
+        output: OutputContext,
+    ) -> Result<Vec<RustTestArtifact<'g>>> {
+        let mut messages = self.build_tests(manifest_path, &[], output)?;
+
+        let skipped_targets = SkippedTestTarget::from_messages(graph, Cursor::new(&messages))
+            .map_err(ExpectedError::test_list_creation_failed)?;
+        if !skipped_targets.is_empty() {
+            if self.enable_required_features {
+                // Build again, this time with the missing features enabled. Cargo selects the
+                // same packages as before, so the required features are enabled only for them.
+                let features: BTreeSet<_> = skipped_targets
+                    .iter()
+                    .flat_map(|target| {
+                        target
+                            .missing_features
+                            .iter()
+                            .map(|feature| format!("{}/{}", target.package.name(), feature))
+                    })
+                    .collect();
+                log::debug!("enabling required features: {:?}", features);
+                let features: Vec<_> = features.into_iter().collect();
+                messages = self.build_tests(manifest_path, &features, output)?;
+            } else {
+                let targets: Vec<_> = skipped_targets
+                    .iter()
+                    .map(|target| {
+                        format!(
+                            "{} (requires {})",
+                            target.binary_id,
+                            target.missing_features.join(", ")
+                        )
+                    })
+                    .collect();
+                log::warn!(
+                    "skipped {} test targets whose required features aren't enabled: {} \
+                     (pass in --enable-required-features to build them)",
+                    skipped_targets.len(),
+                    targets.join("; "),
+                );
+            }
+        }
+
+        let test_artifacts = RustTestArtifact::from_messages(graph, Cursor::new(messages))
+            .map_err(ExpectedError::test_list_creation_failed)?;
+        Ok(test_artifacts)
+    }
+
+    fn make_test_filter(
+        &self,
+        graph: &PackageGraph,
+        rerun_tests: Option<Vec<(String, String)>>,
+    ) -> Result<TestFilterBuilder> {
+        let mut test_filter =
+            TestFilterBuilder::new(self.run_ignored, self.partition.clone(), &self.filter);
+        if let Some(rerun_tests) = rerun_tests {
+            test_filter.set_rerun_tests(rerun_tests);
+        }
+        if let Some(git_ref) = &self.changed_since {
+            let changed_packages = ChangedPackages::since(graph, git_ref).map_err(|err| {
+                ExpectedError::setup_error(
+                    format!("failed to determine packages changed since '{}'", git_ref),
+                    err,
+                )
+            })?;
+            log::debug!(
+                "{} packages changed since '{}'",
+                changed_packages.len(),
+                git_ref
+            );
+            test_filter.set_changed_packages(changed_packages);
+        }
+        test_filter.set_exprs(self.filter_expr.iter().cloned());
+        Ok(test_filter)
+    }
+
+    /// Builds a binary that tests need in order to run, and returns the path to it.
+    fn build_required_binary(
+        &self,
+        graph: &PackageGraph,
+        required_binary: &RequiredBinary,
+        manifest_path: Option<&Utf8Path>,
+        output: OutputContext,
+    ) -> Result<Utf8PathBuf> {
+        let mut cargo_cli = CargoCli::new("build", manifest_path, output);
+        cargo_cli.add_args([
+            "--message-format",
+            "json-render-diagnostics",
+            "--package",
+            required_binary.package(),
+            "--bin",
+            required_binary.binary(),
+        ]);
+        cargo_cli.add_build_options(&self.cargo_options);
+
+        let expression = cargo_cli.to_expression();
+        let output = expression
+            .stdout_capture()
+            .unchecked()
+            .run()
+            .wrap_err("failed to build required binary")?;
+        if !output.status.success() {
+            return Err(Report::new(ExpectedError::build_failed(
+                cargo_cli.all_args(),
+                output.status.code(),
+            )));
+        }
+        let path = required_binary
+            .path_from_messages(graph, Cursor::new(output.stdout))
+            .map_err(ExpectedError::test_list_creation_failed)?;
+        log::debug!(
+            "built required binary '{}' for {} at {}",
+            required_binary.binary(),
+            required_binary.env(),
+            path
+        );
+        Ok(path)
+    }
+
+    /// Builds tests with `cargo test --no-run`, enabling the given additional features, and
+    /// returns the JSON messages produced by Cargo.
+    fn build_tests(
+        &self,
+        manifest_path: Option<&Utf8Path>,
+        features: &[String],
+        output: OutputContext,
+    ) -> Result<Vec<u8>> {
+        // Pass through the manifest path exactly as provided, rather than the workspace root's
+        // manifest. Which packages are built without --workspace or --package depends on the
+        // manifest Cargo starts from: within a member of a virtual workspace, that's just the
+        // member, while at the root it's the default members. Selection with --package globs and
+        // --exclude is left to Cargo as well, so the set of test binaries is the same as with
+        // `cargo test`.
+        let mut cargo_cli = CargoCli::new("test", manifest_path, output);
+        if self.miri {
+            cargo_cli.set_miri();
+        }
 
         // Only build tests in the cargo test invocation, do not run them.
         cargo_cli.add_args(["--no-run", "--message-format", "json-render-diagnostics"]);
         cargo_cli.add_options(&self.cargo_options);
+        cargo_cli.add_args(
+            features
+                .iter()
+                .flat_map(|feature| ["--features", feature.as_str()]),
+        );
 
-        let expression = cargo_cli.to_expression();
+        let mut expression = cargo_cli.to_expression();
+        if self.coverage {
+            let (key, value) = llvm_coverage::rustflags_env();
+            expression = expression.env(key, value);
+        }
         let output = expression
             .stdout_capture()
             .unchecked()
@@ -155,12 +945,7 @@ impl TestBuildFilter {
                 output.status.code(),
             )));
         }
-
-        let test_artifacts = RustTestArtifact::from_messages(graph, Cursor::new(output.stdout))?;
-
-        let test_filter =
-            TestFilterBuilder::new(self.run_ignored, self.partition.clone(), &self.filter);
-        TestList::new(test_artifacts, &test_filter).wrap_err("error building test list")
+        Ok(output.stdout)
     }
 }
 
@@ -189,10 +974,46 @@ pub struct TestRunnerOpts {
     /// Run all tests regardless of failure
     #[clap(long, overrides_with = "fail-fast")]
     no_fail_fast: bool,
+
+    /// Share test concurrency limits with nested nextest invocations through a jobserver, or
+    /// participate in one inherited from make or Cargo
+    #[clap(long)]
+    jobserver: bool,
+
+    /// Do not participate in a jobserver inherited from make, Cargo or an outer nextest
+    #[clap(long, conflicts_with = "jobserver")]
+    no_jobserver: bool,
+
+    /// Run Rust tests under this wrapper, failing tests with the errors it finds [default: from
+    /// profile]
+    #[clap(long, arg_enum, value_name = "WRAPPER")]
+    run_wrapper: Option<RunWrapper>,
+
+    /// Stop scheduling tests once the run is predicted to exceed this duration (e.g. "10m"),
+    /// based on timings from the latest run with this profile
+    #[clap(long, value_name = "DURATION", parse(try_from_str = humantime::parse_duration))]
+    max_shard_duration: Option<Duration>,
+
+    /// Timing database or run report to read timings for --max-shard-duration from, instead of
+    /// the latest run with this profile
+    #[clap(long, value_name = "PATH", requires = "max-shard-duration")]
+    timings: Option<Utf8PathBuf>,
+}
+
+/// A wrapper that tests can be run under.
+#[derive(Copy, Clone, Debug, PartialEq, ArgEnum)]
+enum RunWrapper {
+    /// Valgrind's Memcheck, configured through the profile's `valgrind` table.
+    Valgrind,
 }
 
 impl TestRunnerOpts {
-    fn to_builder(&self, no_capture: bool) -> TestRunnerBuilder {
+    fn to_builder(
+        &self,
+        no_capture: bool,
+        miri: bool,
+        inherited_jobserver: Option<Jobserver>,
+    ) -> Result<TestRunnerBuilder> {
         let mut builder = TestRunnerBuilder::default();
         builder.set_no_capture(no_capture);
         if let Some(retries) = self.retries {
@@ -203,11 +1024,46 @@ impl TestRunnerOpts {
         } else if self.fail_fast {
             builder.set_fail_fast(true);
         }
-        if let Some(test_threads) = self.test_threads {
+        // Each test interpreted by Miri takes up far more memory than a native one.
+        let test_threads = self
+            .test_threads
+            .or_else(|| miri.then(|| (num_cpus::get() / 2).max(1)));
+        if let Some(test_threads) = test_threads {
             builder.set_test_threads(test_threads);
         }
+        match self.run_wrapper {
+            Some(RunWrapper::Valgrind) => {
+                builder.set_valgrind(true);
+            }
+            None => {}
+        }
 
-        builder
+        // A jobserver inherited from an outer nextest invocation takes precedence over creating a
+        // new one. A jobserver inherited from make or Cargo is sized for compile jobs, so it's
+        // only participated in if asked for.
+        let jobserver =
+            match inherited_jobserver {
+                _ if self.no_jobserver => None,
+                Some(jobserver) if jobserver.is_nested() || self.jobserver => {
+                    log::debug!("participating in inherited jobserver");
+                    Some(jobserver)
+                }
+                _ if self.jobserver => {
+                    let limit = match no_capture {
+                        true => 1,
+                        false => test_threads.unwrap_or_else(num_cpus::get),
+                    };
+                    Some(Jobserver::new(limit).map_err(|err| {
+                        ExpectedError::setup_error("failed to create jobserver", err)
+                    })?)
+                }
+                _ => None,
+            };
+        if let Some(jobserver) = jobserver {
+            builder.set_jobserver(jobserver);
+        }
+
+        Ok(builder)
     }
 }
 
@@ -232,94 +1088,1217 @@ struct TestReporterOpts {
     )]
     success_output: Option<TestOutputDisplay>,
 
+    /// Print test output exactly as it was captured, without stripping ANSI escapes or
+    /// highlighting assertion failures
+    #[clap(
+        long,
+        conflicts_with = "no-capture",
+        env = "NEXTEST_NO_OUTPUT_PROCESSING"
+    )]
+    no_output_processing: bool,
+
+    /// Only print the last N lines of output for each test at the end of the run
+    #[clap(long, conflicts_with = "no-capture", value_name = "N")]
+    final_output_lines: Option<usize>,
+
     // status_level does not conflict with --no-capture because pass vs skip still makes sense.
     /// Test statuses to output
     #[clap(long, possible_values = StatusLevel::variants(), value_name = "LEVEL")]
     status_level: Option<StatusLevel>,
+
+    /// Test statuses to list again at the end of the run
+    #[clap(long, possible_values = StatusLevel::variants(), value_name = "LEVEL")]
+    final_status_level: Option<StatusLevel>,
+
+    /// Reporter mode [default: github within GitHub Actions, default otherwise]
+    #[clap(long, possible_values = ReporterMode::variants(), value_name = "MODE")]
+    reporter: Option<ReporterMode>,
+
+    /// List the test binaries that weren't run because the filter couldn't match any tests in them
+    #[clap(long)]
+    show_skipped_binaries: bool,
+
+    /// Don't draw a progress bar with the tests that are currently running [default: drawn if
+    /// stderr is a terminal]
+    #[clap(long, env = "NEXTEST_HIDE_PROGRESS_BAR")]
+    hide_progress_bar: bool,
+
+    /// Show an interactive terminal UI with the running and failed tests, from which tests can be
+    /// re-run once the run has finished
+    #[clap(long, conflicts_with = "no-capture")]
+    tui: bool,
 }
 
 impl TestReporterOpts {
     fn to_builder(&self, no_capture: bool) -> TestReporterBuilder {
         let mut builder = TestReporterBuilder::default();
         builder.set_no_capture(no_capture);
+        builder.set_no_output_processing(self.no_output_processing);
         if let Some(failure_output) = self.failure_output {
             builder.set_failure_output(failure_output);
         }
         if let Some(success_output) = self.success_output {
             builder.set_success_output(success_output);
         }
+        if let Some(final_output_lines) = self.final_output_lines {
+            builder.set_final_output_lines(final_output_lines);
+        }
         if let Some(status_level) = self.status_level {
             builder.set_status_level(status_level);
         }
+        if let Some(final_status_level) = self.final_status_level {
+            builder.set_final_status_level(final_status_level);
+        }
+        builder.set_mode(self.reporter.unwrap_or_else(ReporterMode::detect));
+        builder.set_show_skipped_binaries(self.show_skipped_binaries);
+        builder.set_progress_bar(!self.hide_progress_bar && atty::is(atty::Stream::Stderr));
         builder
     }
 }
 
 impl AppImpl {
     /// Execute the command.
-    fn exec(self) -> Result<()> {
+    fn exec(self, inherited_jobserver: Option<Jobserver>) -> Result<()> {
         let output = self.output.init();
 
-        let graph = build_graph(self.manifest_path.as_deref(), output)?;
+        let workspace_root = locate_workspace_root(self.manifest_path.as_deref(), output)?;
+        self.config_opts.check_version(&workspace_root)?;
+        let config = self.config_opts.make_config(&workspace_root)?;
+        let cache = (!self.no_cache).then(|| NextestCache::new(config.store_dir()));
 
         match self.command {
             Command::List {
                 build_filter,
                 format,
+                list_type: ListType::BinariesOnly,
+                ..
+            } => {
+                let graph = build_graph(self.manifest_path.as_deref(), cache.as_ref(), output)?;
+                let mut binary_list = build_filter.compute_binary_list(
+                    &graph,
+                    self.manifest_path.as_deref(),
+                    output,
+                )?;
+                if output.color.should_colorize(Stream::Stdout) {
+                    binary_list.colorize();
+                }
+                let stdout = std::io::stdout();
+                let lock = stdout.lock();
+                binary_list.write(format, lock).map_err(|err| {
+                    ExpectedError::write_output_error("failed to write binary list", err)
+                })?;
+            }
+            Command::List {
+                build_filter,
+                format,
+                list_type: ListType::Full,
+                message_format,
             } => {
-                let mut test_list = build_filter.compute(&graph, output)?;
+                let (host_triple, target_triple) = build_filter.triples();
+                let profile = config
+                    .profile(build_filter.default_profile())
+                    .map_err(ExpectedError::profile_not_found)?
+                    .with_host_triple(host_triple.as_deref())
+                    .with_target_triple(target_triple.as_deref());
+                let graph = build_graph(self.manifest_path.as_deref(), cache.as_ref(), output)?;
+                let list_settings = build_filter.make_list_settings(&profile, cache.as_ref())?;
+                let runner_lifecycles = start_runner_lifecycles(&list_settings)?;
+                let mut test_list = build_filter.compute(
+                    &graph,
+                    &config,
+                    list_settings,
+                    self.manifest_path.as_deref(),
+                    None,
+                    output,
+                )?;
+                build_filter.apply_profile_filters(&profile, &mut test_list);
                 if output.color.should_colorize(Stream::Stdout) {
                     test_list.colorize();
                 }
                 let stdout = std::io::stdout();
                 let lock = stdout.lock();
-                test_list.write(format, lock)?;
+                match message_format {
+                    Some(message_format) => test_list
+                        .write_delimited(message_format.to_delimited(), lock)
+                        .map_err(|err| {
+                            ExpectedError::write_output_error("failed to write test list", err)
+                        })?,
+                    None => test_list.write(format, lock).map_err(|err| {
+                        ExpectedError::write_output_error("failed to write test list", err)
+                    })?,
+                }
+                stop_runner_lifecycles(runner_lifecycles)?;
             }
             Command::Run {
                 ref profile,
+                ref run_info_file,
+                ref event_stream,
+                failed,
+                failed_with_output,
+                ref test_list_file,
+                ref selector,
+                ref select_by_coverage,
+                ref replay,
+                soak,
                 no_capture,
                 ref build_filter,
                 ref runner_opts,
                 ref reporter_opts,
             } => {
-                let config = self.config_opts.make_config(graph.workspace().root())?;
+                let (host_triple, target_triple) = build_filter.triples();
+                // Nested invocations get their own store directories.
                 let profile = config
-                    .profile(profile.as_deref().unwrap_or(NextestConfig::DEFAULT_PROFILE))
-                    .map_err(ExpectedError::profile_not_found)?;
+                    .profile(
+                        profile
+                            .as_deref()
+                            .unwrap_or_else(|| build_filter.default_profile()),
+                    )
+                    .map_err(ExpectedError::profile_not_found)?
+                    .with_nesting_level(nesting_level())
+                    .with_host_triple(host_triple.as_deref())
+                    .with_target_triple(target_triple.as_deref());
                 let store_dir = profile.store_dir();
-                std::fs::create_dir_all(&store_dir)
-                    .wrap_err_with(|| format!("failed to create store dir '{}'", store_dir))?;
+                std::fs::create_dir_all(store_dir).map_err(|err| {
+                    ExpectedError::setup_error(
+                        format!("failed to create store dir '{}'", store_dir),
+                        err,
+                    )
+                })?;
 
-                let test_list = build_filter.compute(&graph, output)?;
+                // Data from previous runs refers to tests by the names they had back then.
+                let renames = TestRenames::load(profile.workspace_root())
+                    .map_err(|err| ExpectedError::setup_error("failed to load renames", err))?;
+                let rerun_tests = if failed || failed_with_output {
+                    Some(failed_tests(
+                        &RunStore::latest_report_path(store_dir),
+                        &renames,
+                    )?)
+                } else {
+                    None
+                };
+                let replay_seeds = replay
+                    .as_deref()
+                    .map(|report_path| replay_seeds(report_path, &renames))
+                    .transpose()?;
+                let rerun_tests = match &replay_seeds {
+                    Some(replay_seeds) => Some(
+                        replay_seeds
+                            .tests()
+                            .map(|(binary_id, name)| (binary_id.to_owned(), name.to_owned()))
+                            .collect(),
+                    ),
+                    None => rerun_tests,
+                };
+                // Read timings before this run replaces the latest report.
+                let max_duration = runner_opts.max_shard_duration.map(|max_duration| {
+                    let timings = load_timings(
+                        runner_opts
+                            .timings
+                            .clone()
+                            .unwrap_or_else(|| RunStore::latest_report_path(store_dir))
+                            .as_path(),
+                        &renames,
+                        profile.default_duration_estimate(),
+                    );
+                    (max_duration, timings)
+                });
 
-                let mut reporter = reporter_opts
-                    .to_builder(no_capture)
-                    .build(&test_list, &profile);
-                if output.color.should_colorize(Stream::Stderr) {
-                    reporter.colorize();
+                let graph = build_graph(self.manifest_path.as_deref(), cache.as_ref(), output)?;
+                let list_settings = build_filter.make_list_settings(&profile, cache.as_ref())?;
+                let runner_lifecycles = start_runner_lifecycles(&list_settings)?;
+                let selector = selector
+                    .as_deref()
+                    .map(|name| Selector::new(&config, name))
+                    .transpose()
+                    .map_err(|err| ExpectedError::setup_error("failed to find selector", err))?;
+                let mut test_list = match test_list_file {
+                    Some(test_list_file) => build_filter.load_test_list(
+                        &graph,
+                        &config,
+                        &list_settings,
+                        test_list_file,
+                        rerun_tests,
+                    )?,
+                    None => build_filter.compute(
+                        &graph,
+                        &config,
+                        list_settings,
+                        self.manifest_path.as_deref(),
+                        rerun_tests,
+                        output,
+                    )?,
+                };
+                build_filter.apply_profile_filters(&profile, &mut test_list);
+                let selection = selector.and_then(|selector| {
+                    match selector.select(&workspace_root, &test_list, env!("CARGO_PKG_VERSION")) {
+                        Ok(selection) => Some(selection),
+                        Err(err) => {
+                            log::warn!("{}, running all tests instead", err);
+                            None
+                        }
+                    }
+                });
+                if let Some(selection) = &selection {
+                    test_list.apply_selection(selection);
+                }
+                if let Some(map_path) = select_by_coverage {
+                    match CoverageSelection::compute(&workspace_root, map_path) {
+                        Ok(selection) => {
+                            log::info!(
+                                "skipping {} tests that don't cover changes since '{}'",
+                                selection.uncovered_count(),
+                                selection.commit(),
+                            );
+                            test_list.apply_coverage_selection(&selection);
+                        }
+                        Err(err) => log::warn!("{}, running all tests instead", err),
+                    }
                 }
 
-                let handler = SignalHandler::new().wrap_err("failed to set up Ctrl-C handler")?;
-                let runner = runner_opts
-                    .to_builder(no_capture)
-                    .build(&test_list, &profile, handler);
-                let stderr = std::io::stderr();
-                let run_stats = runner.try_execute(|event| {
-                    // TODO: consider turning this into a trait, to initialize and carry the lock
-                    // across callback invocations
-                    let lock = stderr.lock();
-                    reporter.report_event(event, lock)
-                })?;
-                if !run_stats.is_success() {
+                let env_files = EnvFiles::load(&profile)
+                    .map_err(|err| ExpectedError::setup_error("failed to load env files", err))?;
+                let dependencies = TestDependencies::new(profile.dependency_rules(), &test_list)
+                    .map_err(|err| {
+                        ExpectedError::setup_error("failed to resolve test dependencies", err)
+                    })?;
+                let quarantine = profile
+                    .quarantine()
+                    .map(|config| Quarantine::load(&workspace_root, config))
+                    .transpose()
+                    .map_err(|err| {
+                        ExpectedError::setup_error("failed to load quarantined tests", err)
+                    })?;
+                let llvm_tools = build_filter.coverage.then(|| {
+                    LlvmTools::find(
+                        profile.coverage().unwrap_or(&Default::default()),
+                        &workspace_root,
+                    )
+                });
+                let mut tui = if reporter_opts.tui {
+                    let mut tui = Tui::new()
+                        .map_err(|err| ExpectedError::setup_error("failed to start TUI", err))?;
+                    if output.color.should_colorize(Stream::Stderr) {
+                        tui.colorize();
+                    }
+                    Some(tui)
+                } else {
+                    None
+                };
+                let mut soak = soak.map(|duration| {
+                    let mut soak = SoakTracker::new(duration);
+                    if output.color.should_colorize(Stream::Stderr) {
+                        soak.colorize();
+                    }
+                    soak
+                });
+                // With the TUI, tests can be re-run with a filter once a run has finished. Each
+                // re-run is a separate run, with its own run store. Soak tests are run the same
+                // way.
+                let mut rerun_filter: Option<String> = None;
+                let run_stats = loop {
+                    let rerun_list;
+                    let test_list = match &rerun_filter {
+                        Some(filter) => {
+                            rerun_list = {
+                                let mut rerun_list = test_list.clone();
+                                rerun_list.apply_string_filter(filter);
+                                rerun_list
+                            };
+                            &rerun_list
+                        }
+                        None => &test_list,
+                    };
+
+                    let mut handler = SignalHandler::new().map_err(|err| {
+                        ExpectedError::setup_error("failed to set up Ctrl-C handler", err)
+                    })?;
+                    if !no_capture && tui.is_none() {
+                        // Tests can't read from standard input in no-capture mode if it's being
+                        // used for keystrokes. The TUI reads keystrokes itself.
+                        handler.listen_for_keystrokes().map_err(|err| {
+                            ExpectedError::setup_error("failed to listen for keystrokes", err)
+                        })?;
+                    }
+                    let mut run_store = RunStore::create(&profile).map_err(|err| {
+                        ExpectedError::setup_error("failed to create run store", err)
+                    })?;
+                    run_store
+                        .set_env_files(&env_files)
+                        .set_nextest_version(env!("CARGO_PKG_VERSION"))
+                        .set_triples(host_triple.clone(), target_triple.clone())
+                        .set_environment(build_filter.cargo_options.record_environment());
+                    if let Some(run_info_file) = run_info_file {
+                        write_run_info(&run_store, run_info_file)?;
+                    }
+                    if let Some(soak) = &mut soak {
+                        soak.start_iteration(run_store.run_id());
+                    }
+                    let mut event_stream = match event_stream {
+                        Some(path) => {
+                            Some(EventStream::connect(&run_store, path).map_err(|err| {
+                                ExpectedError::setup_error("failed to set up event stream", err)
+                            })?)
+                        }
+                        None => None,
+                    };
+                    // Other processes can follow the run with `cargo nextest status`.
+                    let mut status_server = match StatusServer::start(&run_store) {
+                        Ok(status_server) => Some(status_server),
+                        Err(RunStatusError::Unsupported) => None,
+                        Err(err) => {
+                            log::warn!(
+                                "this run can't be followed with `cargo nextest status`: {}",
+                                err
+                            );
+                            None
+                        }
+                    };
+                    let mut custom_reporters = config
+                        .custom_reporters()
+                        .iter()
+                        .map(|(name, reporter_config)| {
+                            CustomReporter::start(
+                                name,
+                                reporter_config,
+                                &workspace_root,
+                                &run_store,
+                                env!("CARGO_PKG_VERSION"),
+                            )
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| {
+                            ExpectedError::setup_error("failed to start custom reporter", err)
+                        })?;
+
+                    // The TUI takes the place of the reporter.
+                    let mut reporter = if tui.is_none() {
+                        let mut reporter_builder = reporter_opts.to_builder(no_capture);
+                        if failed_with_output && reporter_opts.success_output.is_none() {
+                            reporter_builder.set_success_output(TestOutputDisplay::Immediate);
+                        }
+                        let mut reporter = reporter_builder
+                            .set_run_metadata(run_store.metadata().clone())
+                            .build(test_list, &profile);
+                        if output.color.should_colorize(Stream::Stderr) {
+                            reporter.colorize();
+                        }
+                        Some(reporter)
+                    } else {
+                        None
+                    };
+
+                    let mut runner_builder = runner_opts.to_builder(
+                        no_capture,
+                        build_filter.miri,
+                        inherited_jobserver.clone(),
+                    )?;
+                    match &reporter {
+                        Some(reporter) => {
+                            if let Some(progress_interval) = reporter.progress_interval() {
+                                runner_builder.set_progress_interval(progress_interval);
+                            }
+                        }
+                        None => {
+                            runner_builder.set_progress_interval(Tui::REFRESH_INTERVAL);
+                        }
+                    }
+                    if let Some(selection) = &selection {
+                        runner_builder.set_selection(selection.clone());
+                    }
+                    if let Some((max_duration, timings)) = &max_duration {
+                        runner_builder.set_max_duration(*max_duration, timings.clone());
+                    }
+                    if let Some(llvm_tools) = &llvm_tools {
+                        runner_builder.set_llvm_coverage(llvm_tools.clone());
+                    }
+                    if let Some(replay_seeds) = &replay_seeds {
+                        runner_builder.set_replay_seeds(replay_seeds.clone());
+                    }
+                    if !dependencies.is_empty() {
+                        runner_builder.set_dependencies(dependencies.clone());
+                    }
+                    if let Some(quarantine) = &quarantine {
+                        runner_builder.set_quarantine(quarantine.clone());
+                    }
+                    runner_builder.set_track_memory(soak.is_some());
+                    let runner = runner_builder
+                        .set_run_store(run_store.clone())
+                        .set_env_files(env_files.clone())
+                        .build(test_list, &profile, handler);
+
+                    let mut recorder = RunReportRecorder::new(&run_store);
+                    let stderr = std::io::stderr();
+                    let run_stats = runner.try_execute(|event| {
+                        recorder.write_event(&event).map_err(|err| {
+                            ExpectedError::write_output_error("failed to write run report", err)
+                        })?;
+                        if let Some(event_stream) = &mut event_stream {
+                            event_stream.write_event(&event);
+                        }
+                        if let Some(status_server) = &mut status_server {
+                            status_server.write_event(&event);
+                        }
+                        for custom_reporter in &mut custom_reporters {
+                            custom_reporter.write_event(&event);
+                        }
+                        if let Some(soak) = &mut soak {
+                            soak.record_event(&event);
+                        }
+                        if let Some(tui) = &mut tui {
+                            tui.report_event(&event, rerun_filter.as_deref())
+                                .map_err(|err| {
+                                    ExpectedError::write_output_error("failed to draw TUI", err)
+                                })?;
+                        }
+                        if let Some(reporter) = &mut reporter {
+                            // TODO: consider turning this into a trait, to initialize and carry the
+                            // lock across callback invocations
+                            let lock = stderr.lock();
+                            reporter.report_event(event, lock).map_err(|err| {
+                                ExpectedError::write_output_error(
+                                    "failed to write test output",
+                                    err,
+                                )
+                            })?;
+                        }
+                        Ok::<_, ExpectedError>(())
+                    })?;
+                    run_store.write_summary(&run_stats).map_err(|err| {
+                        ExpectedError::write_output_error("failed to write run summary", err)
+                    })?;
+                    if let Some(llvm_tools) = &llvm_tools {
+                        merge_coverage(
+                            llvm_tools,
+                            &run_store,
+                            test_list,
+                            &profile,
+                            &workspace_root,
+                        );
+                    }
+                    for custom_reporter in custom_reporters {
+                        custom_reporter.finish().map_err(|err| {
+                            ExpectedError::write_output_error("custom reporter failed", err)
+                        })?;
+                    }
+                    // Nothing reads from the run directory any more, so old runs can be removed.
+                    let retention = config.store_retention();
+                    if !retention.keeps_everything() {
+                        match retention.apply(store_dir) {
+                            Ok(summary) if summary.removed_runs > 0 => log::info!(
+                                "removed {} old runs, freeing {}",
+                                summary.removed_runs,
+                                format_bytes(summary.removed_bytes)
+                            ),
+                            Ok(_) => {}
+                            Err(err) => log::warn!("failed to remove old runs: {}", err),
+                        }
+                    }
+
+                    if let Some(soak) = &soak {
+                        soak.write(&SoakTracker::summary_path(store_dir))
+                            .map_err(|err| {
+                                ExpectedError::write_output_error(
+                                    "failed to write soak summary",
+                                    err,
+                                )
+                            })?;
+                        if soak.should_continue(&run_stats) {
+                            continue;
+                        }
+                        soak.write_report(std::io::stderr().lock()).map_err(|err| {
+                            ExpectedError::write_output_error("failed to write soak summary", err)
+                        })?;
+                    }
+
+                    let action = match &mut tui {
+                        Some(tui) => tui.wait_for_action().map_err(|err| {
+                            ExpectedError::write_output_error("failed to draw TUI", err)
+                        })?,
+                        None => TuiAction::Quit,
+                    };
+                    match action {
+                        TuiAction::Rerun { filter } => {
+                            rerun_filter = Some(filter).filter(|filter| !filter.is_empty());
+                        }
+                        TuiAction::Quit => break run_stats,
+                    }
+                };
+                // Restore the terminal before anything else is printed out.
+                drop(tui);
+                stop_runner_lifecycles(runner_lifecycles)?;
+                let success = match &soak {
+                    Some(soak) => soak.is_success(),
+                    None => run_stats.is_success(),
+                };
+                if !success {
                     return Err(Report::new(ExpectedError::test_run_failed()));
                 }
             }
+            Command::ShowReport {
+                profile,
+                status,
+                format,
+                filter,
+            } => {
+                let profile = config
+                    .profile(profile.as_deref().unwrap_or(NextestConfig::DEFAULT_PROFILE))
+                    .map_err(ExpectedError::profile_not_found)?
+                    .with_nesting_level(nesting_level());
+                let report_path = RunStore::latest_report_path(profile.store_dir());
+                let mut report = RunReport::read(&report_path).map_err(|err| {
+                    ExpectedError::setup_error(
+                        format!("failed to read run report '{}'", report_path),
+                        err,
+                    )
+                })?;
+                report.filter(&status, &filter);
+                if output.color.should_colorize(Stream::Stdout) {
+                    report.colorize();
+                }
+                let stdout = std::io::stdout();
+                let lock = stdout.lock();
+                report.write(format, lock).map_err(|err| {
+                    ExpectedError::write_output_error("failed to write report", err)
+                })?;
+            }
+            Command::Status { profile } => {
+                let profile = config
+                    .profile(profile.as_deref().unwrap_or(NextestConfig::DEFAULT_PROFILE))
+                    .map_err(ExpectedError::profile_not_found)?
+                    .with_nesting_level(nesting_level());
+                let mut watcher = RunWatcher::attach(profile.store_dir())
+                    .map_err(|err| ExpectedError::setup_error("failed to attach to run", err))?;
+                let mut printer = RunStatusPrinter::new();
+                if output.color.should_colorize(Stream::Stdout) {
+                    printer.colorize();
+                }
+                let stdout = std::io::stdout();
+                while let Some(event) = watcher
+                    .next_event()
+                    .map_err(|err| ExpectedError::setup_error("failed to follow run", err))?
+                {
+                    printer.write_event(&event, stdout.lock()).map_err(|err| {
+                        ExpectedError::write_output_error("failed to write run status", err)
+                    })?;
+                    if let RunEvent::RunFinished { summary, .. } = event {
+                        if !summary.success {
+                            return Err(Report::new(ExpectedError::test_run_failed()));
+                        }
+                        break;
+                    }
+                }
+            }
+            Command::EnvDiff {
+                run_summary,
+                features,
+                all_features,
+                no_default_features,
+                target,
+                format,
+            } => {
+                let contents = std::fs::read_to_string(&run_summary).map_err(|err| {
+                    ExpectedError::setup_error(
+                        format!("failed to read run summary '{}'", run_summary),
+                        err,
+                    )
+                })?;
+                let summary = RunSummary::parse_json(&contents).map_err(|err| {
+                    ExpectedError::setup_error(
+                        format!("failed to parse run summary '{}'", run_summary),
+                        err,
+                    )
+                })?;
+                let host_triple = host_triple();
+                let target_triple = target.or_else(|| host_triple.clone());
+                let local = record_environment(
+                    rustc_version(),
+                    &features,
+                    all_features,
+                    no_default_features,
+                );
+                let mut env_diff = EnvDiff::new(
+                    &summary,
+                    host_triple.as_deref(),
+                    target_triple.as_deref(),
+                    &local,
+                )
+                .map_err(|err| ExpectedError::setup_error("failed to compare environments", err))?;
+                if output.color.should_colorize(Stream::Stdout) {
+                    env_diff.colorize();
+                }
+                let stdout = std::io::stdout();
+                let lock = stdout.lock();
+                env_diff.write(format, lock).map_err(|err| {
+                    ExpectedError::write_output_error(
+                        "failed to write environment differences",
+                        err,
+                    )
+                })?;
+            }
+            Command::MeasureStartup {
+                build_filter,
+                runs,
+                threshold,
+                format,
+            } => {
+                let profile = config
+                    .profile(build_filter.default_profile())
+                    .map_err(ExpectedError::profile_not_found)?;
+                let graph = build_graph(self.manifest_path.as_deref(), cache.as_ref(), output)?;
+                let list_settings = build_filter.make_list_settings(&profile, cache.as_ref())?;
+                let runner_lifecycles = start_runner_lifecycles(&list_settings)?;
+                let test_list = build_filter.compute(
+                    &graph,
+                    &config,
+                    list_settings,
+                    self.manifest_path.as_deref(),
+                    None,
+                    output,
+                )?;
+                let overheads =
+                    StartupOverheads::measure(&test_list, runs, threshold).map_err(|err| {
+                        ExpectedError::setup_error("failed to measure startup overhead", err)
+                    })?;
+                stop_runner_lifecycles(runner_lifecycles)?;
+                let stdout = std::io::stdout();
+                let lock = stdout.lock();
+                overheads.write(format, lock).map_err(|err| {
+                    ExpectedError::write_output_error("failed to write startup overheads", err)
+                })?;
+            }
+            Command::Report {
+                command:
+                    ReportCommand::Diff {
+                        old,
+                        new,
+                        slower_ratio,
+                        slower_min,
+                        format,
+                    },
+            } => {
+                let renames = TestRenames::load(&workspace_root)
+                    .map_err(|err| ExpectedError::setup_error("failed to load renames", err))?;
+                let read_report = |path: &Utf8Path| {
+                    let mut report = RunReport::read(path).map_err(|err| {
+                        ExpectedError::setup_error(
+                            format!("failed to read run report '{}'", path),
+                            err,
+                        )
+                    })?;
+                    report.apply_renames(&renames);
+                    Ok::<_, ExpectedError>(report)
+                };
+                let old = read_report(&old)?;
+                let new = read_report(&new)?;
+                let mut diff = ReportDiff::new(
+                    old.summary(),
+                    new.summary(),
+                    SlowdownThreshold::new(slower_ratio, slower_min),
+                );
+                if output.color.should_colorize(Stream::Stdout) {
+                    diff.colorize();
+                }
+                let stdout = std::io::stdout();
+                let lock = stdout.lock();
+                diff.write(format, lock).map_err(|err| {
+                    ExpectedError::write_output_error("failed to write report diff", err)
+                })?;
+                if diff.has_regressions() {
+                    let summary = diff.summary();
+                    return Err(ExpectedError::tests_regressed(
+                        summary.newly_failing.len(),
+                        summary.newly_flaky.len(),
+                    )
+                    .into());
+                }
+            }
+            Command::Timings {
+                command:
+                    TimingsCommand::Merge {
+                        output: output_path,
+                        weight,
+                        inputs,
+                    },
+            } => {
+                let mut database = TimingDatabase::new();
+                for input in &inputs {
+                    let timings = TimingInput::read(input).map_err(|err| {
+                        ExpectedError::setup_error("failed to read timing data", err)
+                    })?;
+                    if !database.merge(&timings, weight) {
+                        log::info!("skipped {}: its runs were already merged", input);
+                    }
+                }
+                database.write(&output_path).map_err(|err| {
+                    ExpectedError::write_output_error("failed to write timing database", err)
+                })?;
+                log::info!(
+                    "merged timings for {} tests into {}",
+                    database.len(),
+                    output_path
+                );
+            }
+            Command::Store {
+                command: StoreCommand::Gc,
+            } => {
+                let retention = config.store_retention();
+                if retention.keeps_everything() {
+                    log::warn!("store.retention isn't set in the config, so all runs are kept");
+                }
+                let summary = retention
+                    .apply_all(&config.store_dir())
+                    .map_err(|err| ExpectedError::setup_error("failed to remove old runs", err))?;
+                let stdout = std::io::stdout();
+                writeln!(
+                    stdout.lock(),
+                    "removed {} runs ({}), kept {} runs ({})",
+                    summary.removed_runs,
+                    format_bytes(summary.removed_bytes),
+                    summary.kept_runs,
+                    format_bytes(summary.kept_bytes),
+                )
+                .map_err(|err| {
+                    ExpectedError::write_output_error("failed to write to stdout", err)
+                })?;
+            }
+            Command::Schedule {
+                command:
+                    ScheduleCommand::Run {
+                        profile,
+                        every,
+                        max_runs,
+                        detach,
+                        run_args,
+                    },
+            } => {
+                let profile = config
+                    .profile(profile.as_deref().unwrap_or(NextestConfig::DEFAULT_PROFILE))
+                    .map_err(ExpectedError::profile_not_found)?;
+                let store_dir = profile.store_dir();
+                std::fs::create_dir_all(store_dir).map_err(|err| {
+                    ExpectedError::setup_error(
+                        format!("failed to create store dir '{}'", store_dir),
+                        err,
+                    )
+                })?;
+                if detach {
+                    return detach_schedule(store_dir);
+                }
+
+                let mut run_command = vec!["nextest".to_owned()];
+                if let Some(manifest_path) = &self.manifest_path {
+                    run_command.extend(["--manifest-path".to_owned(), manifest_path.to_string()]);
+                }
+                if let Some(config_file) = &self.config_opts.config_file {
+                    run_command.extend(["--config-file".to_owned(), config_file.to_string()]);
+                }
+                run_command.extend(["run".to_owned(), "--profile".to_owned()]);
+                run_command.push(profile.name().to_owned());
+                run_command.extend(run_args);
+
+                let schedule = Schedule::new(every);
+                let history = ScheduleHistory::new(store_dir);
+                let mut all_passed = true;
+                for run in 1.. {
+                    let summary = scheduled_run(&run_command, store_dir)?;
+                    history.append(&summary).map_err(|err| {
+                        ExpectedError::write_output_error("failed to write schedule history", err)
+                    })?;
+                    let run_id = summary
+                        .run
+                        .as_ref()
+                        .map(|run| format!(" (run ID {})", run.metadata.run_id))
+                        .unwrap_or_default();
+                    if summary.exit_code == Some(0) {
+                        log::info!(
+                            "scheduled run {} passed after {:.3}s{}",
+                            run,
+                            summary.time_taken,
+                            run_id
+                        );
+                    } else {
+                        all_passed = false;
+                        log::warn!(
+                            "scheduled run {} failed after {:.3}s{}",
+                            run,
+                            summary.time_taken,
+                            run_id
+                        );
+                    }
+                    if max_runs.is_some_and(|max_runs| run >= max_runs) {
+                        break;
+                    }
+                    let until_next = schedule.until_next();
+                    log::info!(
+                        "next run in {}",
+                        humantime::format_duration(Duration::from_secs(
+                            until_next.as_secs_f64().ceil() as u64
+                        ))
+                    );
+                    std::thread::sleep(until_next);
+                }
+                if !all_passed {
+                    return Err(ExpectedError::test_run_failed().into());
+                }
+            }
+            Command::ShowFingerprint { format } => {
+                let graph = build_graph(self.manifest_path.as_deref(), cache.as_ref(), output)?;
+                let fingerprint = WorkspaceFingerprint::compute(&graph).map_err(|err| {
+                    ExpectedError::setup_error("failed to compute workspace fingerprint", err)
+                })?;
+                let stdout = std::io::stdout();
+                let lock = stdout.lock();
+                fingerprint.write(format, lock).map_err(|err| {
+                    ExpectedError::write_output_error("failed to write fingerprint", err)
+                })?;
+            }
+            Command::ShowConfig { format } => {
+                let stdout = std::io::stdout();
+                let lock = stdout.lock();
+                config.write_summary(format, lock).map_err(|err| {
+                    ExpectedError::write_output_error("failed to write config", err)
+                })?;
+            }
         }
         Ok(())
     }
 }
 
-fn build_graph(manifest_path: Option<&Utf8Path>, output: OutputContext) -> Result<PackageGraph> {
+/// Merges the coverage profiles written by the tests in a run, logging where the merged coverage
+/// was written. Failing to merge coverage doesn't fail the run.
+fn merge_coverage(
+    llvm_tools: &LlvmTools,
+    run_store: &RunStore,
+    test_list: &TestList<'_>,
+    profile: &NextestProfile<'_>,
+    workspace_root: &Utf8Path,
+) {
+    let binaries = test_list
+        .iter()
+        .map(|(path, _)| path)
+        .filter(|path| !path.as_str().is_empty());
+    let lcov = profile.coverage().is_some_and(|coverage| coverage.lcov());
+    let map = profile.coverage().is_some_and(|coverage| coverage.map());
+    match llvm_tools.merge_run(run_store.run_dir(), binaries, lcov) {
+        Ok(Some(coverage)) => {
+            log::info!(
+                "merged coverage from {} tests into {}",
+                coverage.test_profile_count,
+                coverage.profdata
+            );
+            if let Some(lcov) = &coverage.lcov {
+                log::info!("exported coverage to {}", lcov);
+            }
+            if map {
+                match export_coverage_map(llvm_tools, run_store, test_list, workspace_root) {
+                    Ok(map_path) => log::info!("exported per-test coverage map to {}", map_path),
+                    Err(err) => log::warn!("failed to export coverage map: {}", err),
+                }
+            }
+        }
+        Ok(None) => log::warn!("no coverage was collected: no test wrote a coverage profile"),
+        Err(err) => log::warn!("failed to merge coverage: {}", err),
+    }
+}
+
+/// Exports the coverage of each test in the run, using the profiles recorded in its report.
+fn export_coverage_map(
+    llvm_tools: &LlvmTools,
+    run_store: &RunStore,
+    test_list: &TestList<'_>,
+    workspace_root: &Utf8Path,
+) -> Result<Utf8PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let report = RunReport::read(&run_store.report_path())?;
+    let binaries: HashMap<_, _> = test_list
+        .iter()
+        .map(|(path, suite)| (suite.binary_id.as_str(), path))
+        .collect();
+    let tests = report.summary().tests.iter().filter_map(|test| {
+        // The coverage of the last attempt that wrote a profile is used.
+        let profdata = test
+            .attempts
+            .iter()
+            .rev()
+            .find_map(|attempt| attempt.coverage_profile_path.as_deref())?;
+        Some(TestProfile {
+            binary_id: &test.binary_id,
+            name: &test.name,
+            binary: binaries.get(test.binary_id.as_str())?,
+            profdata,
+        })
+    });
+    Ok(llvm_tools.export_map(run_store.run_dir(), workspace_root, tests)?)
+}
+
+/// Starts the environments, such as emulators, that the target runners need for the run.
+fn start_runner_lifecycles(list_settings: &ListSettings<'_>) -> Result<RunnerLifecycleGuard> {
+    list_settings
+        .target_runner()
+        .start_lifecycles()
+        .map_err(|err| {
+            ExpectedError::setup_error("failed to start target runner environments", err).into()
+        })
+}
+
+fn stop_runner_lifecycles(runner_lifecycles: RunnerLifecycleGuard) -> Result<()> {
+    runner_lifecycles.stop().map_err(|err| {
+        ExpectedError::setup_error("failed to stop target runner environments", err).into()
+    })
+}
+
+/// Returns the binary IDs and names of tests that failed or were flaky in the run report at the
+/// given path, renamed to their current names.
+fn failed_tests(report_path: &Utf8Path, renames: &TestRenames) -> Result<Vec<(String, String)>> {
+    let mut report = RunReport::read(report_path).map_err(|err| {
+        ExpectedError::setup_error(
+            format!(
+                "failed to read run report '{}' (--failed requires a previous run with this profile)",
+                report_path
+            ),
+            err,
+        )
+    })?;
+    report.apply_renames(renames);
+    Ok(report
+        .summary()
+        .tests
+        .iter()
+        .filter(|test| {
+            matches!(
+                test.status,
+                TestReportStatus::Fail
+                    | TestReportStatus::ExecFail
+                    | TestReportStatus::DiskQuotaExceeded
+                    | TestReportStatus::Timeout
+                    | TestReportStatus::UnexpectedPass
+                    | TestReportStatus::Flaky
+            )
+        })
+        .map(|test| (test.binary_id.clone(), test.name.clone()))
+        .collect())
+}
+
+/// Reads the proptest seeds recorded in the run report at `report_path`, for tests under their
+/// current names.
+fn replay_seeds(report_path: &Utf8Path, renames: &TestRenames) -> Result<ReplaySeeds> {
+    let mut report = RunReport::read(report_path).map_err(|err| {
+        ExpectedError::setup_error(format!("failed to read run report '{}'", report_path), err)
+    })?;
+    report.apply_renames(renames);
+    let replay_seeds = ReplaySeeds::from_report(report.summary());
+    if replay_seeds.is_empty() {
+        log::warn!(
+            "no property-based test failures with a seed were recorded in '{}'",
+            report_path
+        );
+    }
+    Ok(replay_seeds)
+}
+
+/// Runs `cargo nextest` once with the given arguments for `cargo nextest schedule run`, returning
+/// the result of the run.
+fn scheduled_run(run_command: &[String], store_dir: &Utf8Path) -> Result<ScheduledRunSummary> {
+    let current_exe = std::env::current_exe()
+        .map_err(|err| ExpectedError::setup_error("failed to find the current executable", err))?;
+    // Each run gets its own run info file, so that schedules running side by side with the same
+    // store directory don't read each other's runs.
+    let run_info_path = store_dir.join(format!(
+        "schedule-run-info-{}.json",
+        Uuid::new_v4().hyphenated()
+    ));
+
+    let stopwatch = StopwatchStart::now();
+    let status = std::process::Command::new(current_exe)
+        .args(run_command)
+        .env(NEXTEST_RUN_INFO_FILE_ENV, &run_info_path)
+        .status()
+        .map_err(|err| ExpectedError::setup_error("failed to start cargo nextest run", err))?;
+    let stopwatch_end = stopwatch.end();
+
+    // The run info says where the summary is written, if the run got as far as running tests.
+    let run = std::fs::read_to_string(&run_info_path)
+        .ok()
+        .and_then(|info| RunInfoSummary::parse_json(info).ok())
+        .and_then(|info| std::fs::read_to_string(info.summary_path).ok())
+        .and_then(|summary| RunSummary::parse_json(summary).ok());
+    let _ = std::fs::remove_file(&run_info_path);
+    Ok(ScheduledRunSummary {
+        started_at: humantime::format_rfc3339_seconds(stopwatch_end.start_time).to_string(),
+        time_taken: stopwatch_end.duration.as_secs_f64(),
+        exit_code: status.code(),
+        run,
+    })
+}
+
+/// Starts this `cargo nextest schedule run` again in the background without `--detach`, detached
+/// from the terminal and logging to `schedule.log` in the store directory.
+fn detach_schedule(store_dir: &Utf8Path) -> Result<()> {
+    let current_exe = std::env::current_exe()
+        .map_err(|err| ExpectedError::setup_error("failed to find the current executable", err))?;
+    let log_path = store_dir.join("schedule.log");
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .and_then(|log| Ok((log.try_clone()?, log)))
+        .map_err(|err| {
+            ExpectedError::setup_error(format!("failed to open log file '{}'", log_path), err)
+        })?;
+
+    // Arguments after `--` are passed to `cargo nextest run`, so they're kept as they are.
+    let mut args_done = false;
+    let args: Vec<_> = std::env::args_os()
+        .skip(1)
+        .filter(|arg| {
+            args_done |= arg == "--";
+            args_done || arg != "--detach"
+        })
+        .collect();
+    let mut command = std::process::Command::new(current_exe);
+    command
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(log.0)
+        .stderr(log.1);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        // Start a new session without a controlling terminal, so that neither Ctrl-C in the
+        // terminal nor the terminal being closed (SIGHUP) stops the scheduled runs.
+        //
+        // SAFETY: setsid is async-signal-safe, and the child isn't a process group leader, so it
+        // always succeeds.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    let child = command.spawn().map_err(|err| {
+        ExpectedError::setup_error("failed to start scheduled runs in the background", err)
+    })?;
+    let stdout = std::io::stdout();
+    writeln!(
+        stdout.lock(),
+        "running scheduled runs in the background with PID {}, logging to {}",
+        child.id(),
+        log_path
+    )
+    .map_err(|err| ExpectedError::write_output_error("failed to write to stdout", err))?;
+    Ok(())
+}
+
+/// Adds the suites of tests that aren't written in Rust, as specified in the config, to the test
+/// list.
+fn add_other_tests<'g>(
+    graph: &'g PackageGraph,
+    config: &NextestConfig,
+    test_list: &mut TestList<'g>,
+    test_filter: &TestFilterBuilder,
+) -> Result<(), ExpectedError> {
+    for other_tests in config.other_tests() {
+        other_tests
+            .add_to_test_list(graph, test_list, test_filter)
+            .map_err(ExpectedError::test_list_creation_failed)?;
+    }
+    Ok(())
+}
+
+/// Loads timing data from the run report or timing database at the given path, for tests under
+/// their current names.
+///
+/// Without a previous run, every test is predicted to take the default estimate.
+fn load_timings(path: &Utf8Path, renames: &TestRenames, default_estimate: Duration) -> TestTimings {
+    match TimingInput::read(path) {
+        Ok(TimingInput::Report(mut report)) => {
+            renames.apply_to_report(&mut report);
+            TestTimings::from_report(&report, default_estimate)
+        }
+        Ok(TimingInput::Database(mut database)) => {
+            database.apply_renames(renames);
+            TestTimings::from_database(&database, default_estimate)
+        }
+        Err(err) => {
+            log::warn!(
+                "no timing data available for --max-shard-duration ({}), \
+                 using the default estimate of {} for every test",
+                err,
+                humantime::format_duration(default_estimate)
+            );
+            TestTimings::new(default_estimate)
+        }
+    }
+}
+
+fn write_run_info(run_store: &RunStore, run_info_file: &Utf8Path) -> Result<(), ExpectedError> {
+    let write_err = |err| ExpectedError::write_output_error("failed to write run info", err);
+    if run_info_file == "-" {
+        let stdout = std::io::stdout();
+        let lock = stdout.lock();
+        run_store.write_info(lock).map_err(write_err)?;
+    } else {
+        let f = std::fs::File::create(run_info_file).map_err(|err| {
+            ExpectedError::write_output_error(
+                format!("failed to create run info file '{}'", run_info_file),
+                err,
+            )
+        })?;
+        run_store.write_info(f).map_err(write_err)?;
+    }
+    Ok(())
+}
+
+fn locate_workspace_root(
+    manifest_path: Option<&Utf8Path>,
+    output: OutputContext,
+) -> Result<Utf8PathBuf> {
+    // Reading the manifests directly is much faster than running Cargo, which matters because
+    // this happens on every invocation.
+    if let Some(workspace_root) = find_workspace_root(manifest_path) {
+        return Ok(workspace_root);
+    }
+
+    let mut cargo_cli = CargoCli::new("locate-project", manifest_path, output);
+    cargo_cli.add_args(["--workspace", "--message-format", "plain"]);
+
+    let output = cargo_cli
+        .to_expression()
+        .stdout_capture()
+        .unchecked()
+        .run()
+        .wrap_err("cargo locate-project execution failed")?;
+    if !output.status.success() {
+        return Err(ExpectedError::cargo_metadata_failed().into());
+    }
+
+    let workspace_manifest = String::from_utf8(output.stdout)
+        .wrap_err("cargo locate-project output is invalid UTF-8")?;
+    let workspace_manifest = Utf8Path::new(workspace_manifest.trim_end());
+    Ok(workspace_manifest
+        .parent()
+        .expect("workspace manifest has a parent")
+        .to_path_buf())
+}
+
+fn build_graph(
+    manifest_path: Option<&Utf8Path>,
+    cache: Option<&NextestCache>,
+    output: OutputContext,
+) -> Result<PackageGraph> {
+    if let Some(json) = cache.and_then(|cache| cache.get_metadata()) {
+        match guppy::CargoMetadata::parse_json(&json).and_then(|metadata| metadata.build_graph()) {
+            Ok(graph) => {
+                log::debug!("reusing cached cargo metadata");
+                return Ok(graph);
+            }
+            Err(err) => log::debug!("ignoring invalid cached cargo metadata: {}", err),
+        }
+    }
+
     let mut cargo_cli = CargoCli::new("metadata", manifest_path, output);
     // Construct a package graph with --no-deps since we don't need full dependency
     // information.
@@ -338,5 +2317,9 @@ fn build_graph(manifest_path: Option<&Utf8Path>, output: OutputContext) -> Resul
 
     let json =
         String::from_utf8(output.stdout).wrap_err("cargo metadata output is invalid UTF-8")?;
-    Ok(guppy::CargoMetadata::parse_json(&json)?.build_graph()?)
+    let graph = guppy::CargoMetadata::parse_json(&json)?.build_graph()?;
+    if let Some(cache) = cache {
+        cache.put_metadata(json, &graph);
+    }
+    Ok(graph)
 }
@@ -4,12 +4,15 @@
 use nextest_metadata::NextestExitCode;
 use nextest_runner::errors::{ConfigParseError, ProfileNotFound};
 use owo_colors::{OwoColorize, Stream};
+use semver::Version;
 use std::{
     error::{self, Error},
     fmt,
 };
 
-/// An error occurred in a program that nextest ran, not in nextest itself.
+/// An error occurred in a program that nextest ran, or in an expected way in nextest itself.
+///
+/// Each error maps to one of the exit codes documented in [`NextestExitCode`].
 #[derive(Debug)]
 #[doc(hidden)]
 pub enum ExpectedError {
@@ -20,11 +23,30 @@ pub enum ExpectedError {
     ConfigParseError {
         err: ConfigParseError,
     },
+    RequiredVersionNotMet {
+        required: Version,
+        current: Version,
+    },
     BuildFailed {
         escaped_command: Vec<String>,
         exit_code: Option<i32>,
     },
     TestRunFailed,
+    TestsRegressed {
+        newly_failing: usize,
+        newly_flaky: usize,
+    },
+    SetupError {
+        description: String,
+        err: Box<dyn Error + Send + Sync>,
+    },
+    TestListCreationFailed {
+        err: Box<dyn Error + Send + Sync>,
+    },
+    WriteOutputError {
+        description: String,
+        err: Box<dyn Error + Send + Sync>,
+    },
 }
 
 impl ExpectedError {
@@ -40,6 +62,10 @@ impl ExpectedError {
         Self::ConfigParseError { err }
     }
 
+    pub(crate) fn required_version_not_met(required: Version, current: Version) -> Self {
+        Self::RequiredVersionNotMet { required, current }
+    }
+
     pub(crate) fn build_failed(
         command: impl IntoIterator<Item = impl AsRef<str>>,
         exit_code: Option<i32>,
@@ -57,15 +83,49 @@ impl ExpectedError {
         Self::TestRunFailed
     }
 
+    pub(crate) fn tests_regressed(newly_failing: usize, newly_flaky: usize) -> Self {
+        Self::TestsRegressed {
+            newly_failing,
+            newly_flaky,
+        }
+    }
+
+    pub(crate) fn setup_error(
+        description: impl Into<String>,
+        err: impl Into<Box<dyn Error + Send + Sync>>,
+    ) -> Self {
+        Self::SetupError {
+            description: description.into(),
+            err: err.into(),
+        }
+    }
+
+    pub(crate) fn test_list_creation_failed(err: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self::TestListCreationFailed { err: err.into() }
+    }
+
+    pub(crate) fn write_output_error(
+        description: impl Into<String>,
+        err: impl Into<Box<dyn Error + Send + Sync>>,
+    ) -> Self {
+        Self::WriteOutputError {
+            description: description.into(),
+            err: err.into(),
+        }
+    }
+
     /// Returns the exit code for the process.
     pub fn process_exit_code(&self) -> i32 {
         match self {
             Self::CargoMetadataFailed => NextestExitCode::CARGO_METADATA_FAILED,
-            Self::ProfileNotFound { .. } | Self::ConfigParseError { .. } => {
-                NextestExitCode::SETUP_ERROR
-            }
+            Self::ProfileNotFound { .. }
+            | Self::ConfigParseError { .. }
+            | Self::SetupError { .. } => NextestExitCode::SETUP_ERROR,
+            Self::RequiredVersionNotMet { .. } => NextestExitCode::REQUIRED_VERSION_NOT_MET,
             Self::BuildFailed { .. } => NextestExitCode::BUILD_FAILED,
-            Self::TestRunFailed => NextestExitCode::TEST_RUN_FAILED,
+            Self::TestRunFailed | Self::TestsRegressed { .. } => NextestExitCode::TEST_RUN_FAILED,
+            Self::TestListCreationFailed { .. } => NextestExitCode::TEST_LIST_CREATION_FAILED,
+            Self::WriteOutputError { .. } => NextestExitCode::WRITE_OUTPUT_ERROR,
         }
     }
 
@@ -83,6 +143,15 @@ impl ExpectedError {
                 log::error!("{}", err);
                 err.source()
             }
+            Self::RequiredVersionNotMet { required, current } => {
+                log::error!(
+                    "this repository requires nextest version {}, but the current version is {}\n\
+                     (update nextest, or pass in --override-version-check to run anyway)",
+                    required.if_supports_color(Stream::Stderr, |x| x.bold()),
+                    current.if_supports_color(Stream::Stderr, |x| x.bold()),
+                );
+                None
+            }
             Self::BuildFailed {
                 escaped_command,
                 exit_code,
@@ -111,6 +180,25 @@ impl ExpectedError {
                 log::error!("test run failed");
                 None
             }
+            Self::TestsRegressed {
+                newly_failing,
+                newly_flaky,
+            } => {
+                log::error!(
+                    "tests regressed: {} newly failing, {} newly flaky",
+                    newly_failing.if_supports_color(Stream::Stderr, |x| x.bold()),
+                    newly_flaky.if_supports_color(Stream::Stderr, |x| x.bold()),
+                );
+                None
+            }
+            Self::SetupError { description, err } | Self::WriteOutputError { description, err } => {
+                log::error!("{}", description);
+                Some(err.as_ref() as &(dyn Error + 'static))
+            }
+            Self::TestListCreationFailed { err } => {
+                log::error!("creating test list failed");
+                Some(err.as_ref() as &(dyn Error + 'static))
+            }
         };
 
         while let Some(err) = next_error {
@@ -127,8 +215,14 @@ impl fmt::Display for ExpectedError {
             Self::CargoMetadataFailed => writeln!(f, "cargo metadata failed"),
             Self::ProfileNotFound { .. } => writeln!(f, "profile not found"),
             Self::ConfigParseError { .. } => writeln!(f, "config read error"),
+            Self::RequiredVersionNotMet { .. } => writeln!(f, "required nextest version not met"),
             Self::BuildFailed { .. } => writeln!(f, "build failed"),
             Self::TestRunFailed => writeln!(f, "test run failed"),
+            Self::TestsRegressed { .. } => writeln!(f, "tests regressed"),
+            Self::SetupError { description, .. } | Self::WriteOutputError { description, .. } => {
+                writeln!(f, "{}", description)
+            }
+            Self::TestListCreationFailed { .. } => writeln!(f, "creating test list failed"),
         }
     }
 }
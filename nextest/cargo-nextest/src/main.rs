@@ -4,13 +4,17 @@
 use cargo_nextest::{CargoNextestApp, ExpectedError};
 use clap::Parser;
 use color_eyre::Result;
+use nextest_runner::nesting::Jobserver;
 
 fn main() -> Result<()> {
+    // This must be done before any other file descriptors are opened.
+    let inherited_jobserver = Jobserver::from_env();
+
     color_eyre::install()?;
     let _ = enable_ansi_support::enable_ansi_support();
 
     let opts = CargoNextestApp::parse();
-    match opts.exec() {
+    match opts.exec(inherited_jobserver) {
         Ok(()) => Ok(()),
         Err(err) => {
             let expected_error: ExpectedError = err.downcast()?;
@@ -4,6 +4,7 @@
 use clap::{ArgEnum, Args};
 use env_logger::fmt::Formatter;
 use log::{Level, Record};
+use nextest_runner::nesting::nesting_level;
 use owo_colors::{OwoColorize, Style};
 use std::io::Write;
 use supports_color::Stream;
@@ -28,6 +29,12 @@ impl OutputOpts {
     pub(crate) fn init(self) -> OutputContext {
         let OutputOpts { color } = self;
 
+        // Nested invocations write into the outer invocation's captured output, so don't try to
+        // detect a terminal.
+        let color = match color {
+            Color::Auto if nesting_level() > 0 => Color::Never,
+            other => other,
+        };
         color.init();
 
         OutputContext { color }
@@ -40,20 +47,15 @@ pub(crate) struct OutputContext {
     pub(crate) color: Color,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, ArgEnum)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, ArgEnum)]
 #[must_use]
 pub enum Color {
+    #[default]
     Auto,
     Always,
     Never,
 }
 
-impl Default for Color {
-    fn default() -> Self {
-        Color::Auto
-    }
-}
-
 impl Color {
     fn init(self) {
         match self {
@@ -62,7 +64,8 @@ impl Color {
             Color::Never => owo_colors::set_override(false),
         }
 
-        env_logger::Builder::from_env("NEXTEST_LOG")
+        // Warnings are shown by default.
+        env_logger::Builder::from_env(env_logger::Env::new().filter_or("NEXTEST_LOG", "warn"))
             .format(format_fn)
             .init();
     }
@@ -0,0 +1,143 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Tests for `cargo nextest schedule run`.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::cmd;
+use nextest_metadata::ScheduledRunSummary;
+use std::fs;
+
+fn fixture_root() -> Utf8PathBuf {
+    // one level up from the manifest dir -> into fixtures/virtual-workspace
+    Utf8Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("fixtures/virtual-workspace")
+}
+
+/// Returns the store directory for the given profile, after clearing it out.
+fn clean_store_dir(profile: &str) -> Utf8PathBuf {
+    let store_dir = fixture_root().join("target/nextest").join(profile);
+    let _ = fs::remove_dir_all(&store_dir);
+    store_dir
+}
+
+fn read_history(store_dir: &Utf8Path) -> Vec<ScheduledRunSummary> {
+    fs::read_to_string(store_dir.join("history.jsonl"))
+        .unwrap_or_default()
+        .lines()
+        .map(|line| ScheduledRunSummary::parse_json(line).expect("history line is valid"))
+        .collect()
+}
+
+#[test]
+fn scheduled_run() {
+    let store_dir = clean_store_dir("schedule");
+    let output = cmd!(
+        env!("CARGO_BIN_EXE_cargo-nextest"),
+        "nextest",
+        "schedule",
+        "run",
+        "--profile",
+        "schedule",
+        "--every",
+        "1h",
+        "--max-runs",
+        "1"
+    )
+    .dir(fixture_root())
+    .stdout_capture()
+    .stderr_capture()
+    .unchecked()
+    .run()
+    .expect("cargo nextest schedule run was run");
+    assert!(
+        output.status.success(),
+        "scheduled run passed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let history = read_history(&store_dir);
+    assert_eq!(history.len(), 1, "one run is recorded");
+    assert_eq!(history[0].exit_code, Some(0));
+    let run = history[0].run.as_ref().expect("run summary is recorded");
+    assert_eq!(run.passed, 2, "default members' tests were run");
+
+    let leftover: Vec<_> = fs::read_dir(&store_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().starts_with("schedule-run-info"))
+        .collect();
+    assert!(
+        leftover.is_empty(),
+        "run info files are removed: {:?}",
+        leftover
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn detached_schedule() {
+    use std::time::{Duration, Instant};
+
+    let store_dir = clean_store_dir("schedule-detach");
+    let output = cmd!(
+        env!("CARGO_BIN_EXE_cargo-nextest"),
+        "nextest",
+        "schedule",
+        "run",
+        "--profile",
+        "schedule-detach",
+        "--every",
+        "1h",
+        "--max-runs",
+        "2",
+        "--detach"
+    )
+    .dir(fixture_root())
+    .stdout_capture()
+    .read()
+    .expect("cargo nextest schedule run --detach succeeded");
+    let pid: i32 = output
+        .split_once("PID ")
+        .and_then(|(_, rest)| rest.split_whitespace().next())
+        .and_then(|pid| pid.trim_end_matches(',').parse().ok())
+        .unwrap_or_else(|| panic!("PID is printed: {}", output));
+    // Stop the schedule once the test is done, even if it fails.
+    let _guard = KillOnDrop(pid);
+
+    // The first run is recorded in the background, after which the schedule waits an hour for
+    // the second one.
+    let deadline = Instant::now() + Duration::from_secs(300);
+    while read_history(&store_dir).is_empty() {
+        assert!(Instant::now() < deadline, "scheduled run finished in time");
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    // The schedule runs in its own session, away from the terminal it was started from.
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).expect("schedule is running");
+    // The session ID is the sixth field, after the command name in parentheses.
+    let session: i32 = stat
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().nth(3))
+        .and_then(|session| session.parse().ok())
+        .expect("session ID is valid");
+    assert_eq!(session, pid, "schedule leads its own session");
+
+    assert_eq!(read_history(&store_dir)[0].exit_code, Some(0));
+    assert!(
+        store_dir.join("schedule.log").is_file(),
+        "schedule logs to a file"
+    );
+}
+
+#[cfg(target_os = "linux")]
+struct KillOnDrop(i32);
+
+#[cfg(target_os = "linux")]
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = cmd!("kill", self.0.to_string()).run();
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Tests that `cargo nextest list` selects the same packages as `cargo test` in a virtual
+//! workspace.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::cmd;
+
+fn fixture_root() -> Utf8PathBuf {
+    // one level up from the manifest dir -> into fixtures/virtual-workspace
+    Utf8Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("fixtures/virtual-workspace")
+}
+
+/// Lists tests from `dir` with the given arguments, returning the packages they're in.
+fn list_packages(dir: &Utf8Path, args: &[&str]) -> Vec<String> {
+    let mut list_args = vec!["nextest", "list", "--message-format", "csv"];
+    list_args.extend_from_slice(args);
+    let output = cmd(env!("CARGO_BIN_EXE_cargo-nextest"), list_args)
+        .dir(dir)
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .expect("cargo nextest list was run");
+    assert!(
+        output.status.success(),
+        "cargo nextest list {:?} succeeded: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).expect("output is UTF-8");
+    let mut packages: Vec<_> = stdout
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap_or_default().to_owned())
+        .collect();
+    packages.sort_unstable();
+    packages.dedup();
+    packages
+}
+
+#[test]
+fn default_members() {
+    assert_eq!(
+        list_packages(&fixture_root(), &[]),
+        ["member-a", "member-b"],
+        "only default members are built at the root of a virtual workspace"
+    );
+    assert_eq!(
+        list_packages(&fixture_root().join("member-c"), &[]),
+        ["member-c"],
+        "within a member, only that member is built"
+    );
+}
+
+#[test]
+fn manifest_path() {
+    let root = fixture_root();
+    assert_eq!(
+        list_packages(&root, &["--manifest-path", "member-c/Cargo.toml"]),
+        ["member-c"],
+        "the member's manifest selects just that member"
+    );
+    assert_eq!(
+        list_packages(
+            &root.join("member-c"),
+            &["--manifest-path", "../Cargo.toml"]
+        ),
+        ["member-a", "member-b"],
+        "the root manifest selects the default members"
+    );
+}
+
+#[test]
+fn workspace_and_exclude() {
+    let root = fixture_root();
+    assert_eq!(
+        list_packages(&root, &["--workspace"]),
+        ["member-a", "member-b", "member-c"]
+    );
+    assert_eq!(
+        list_packages(&root, &["--workspace", "--exclude", "member-b"]),
+        ["member-a", "member-c"]
+    );
+    assert_eq!(
+        list_packages(
+            &root.join("member-a"),
+            &["--workspace", "--exclude", "member-a"]
+        ),
+        ["member-b", "member-c"],
+        "--workspace applies from within a member"
+    );
+}
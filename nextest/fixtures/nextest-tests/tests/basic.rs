@@ -61,6 +61,48 @@ fn test_cwd() {
     assert_eq!(runtime_cwd, compile_time_cwd, "current dir matches");
 }
 
+#[test]
+fn test_multiplex_param() {
+    // This variable is set through the multiplex rule in the with-multiplex profile.
+    if let Ok(param) = env::var("NEXTEST_MULTIPLEX_PARAM") {
+        assert!(
+            ["a", "b", "c"].contains(&param.as_str()),
+            "unexpected multiplex param {}",
+            param
+        );
+    }
+}
+
+#[test]
+fn test_env_file() {
+    // This variable is set through the env file loaded in the with-env-files profile.
+    if let Ok(value) = env::var("NEXTEST_ENV_FILE_VAR") {
+        assert_eq!(value, "from env file");
+        assert_eq!(
+            env::var("CARGO_PKG_NAME").as_deref(),
+            Ok("nextest-tests"),
+            "variables set by nextest take precedence over env files"
+        );
+    }
+}
+
+#[test]
+fn test_log_file() {
+    // Nextest sets this variable if it's running tests with a run store.
+    if let Ok(log_file) = env::var("NEXTEST_TEST_LOG_FILE") {
+        std::fs::write(log_file, "this is a log line\n").expect("log file written");
+    }
+}
+
+#[test]
+fn test_runtime_skip() {
+    // Nextest sets this variable if it's running tests with a run store. Creating the file marks
+    // this test as skipped.
+    if let Ok(skip_file) = env::var("NEXTEST_SKIP_FILE") {
+        std::fs::write(skip_file, "required hardware not present\n").expect("skip file written");
+    }
+}
+
 #[test]
 #[ignore]
 fn test_ignored() {}
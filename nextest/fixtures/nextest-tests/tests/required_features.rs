@@ -0,0 +1,2 @@
+#[test]
+fn test_required_features() {}
@@ -0,0 +1,5 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#[test]
+fn test_member_b() {}
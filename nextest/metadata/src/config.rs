@@ -0,0 +1,234 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The fully-resolved nextest configuration for a workspace, output by
+/// `cargo nextest show-config --message-format json`.
+///
+/// The default config is merged with the workspace's config, and each profile's settings are
+/// resolved through the profiles it inherits from. Durations are in seconds and sizes are in bytes.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConfigSummary {
+    /// The root of the workspace the config is for.
+    pub workspace_root: Utf8PathBuf,
+
+    /// Where runs are stored, and for how long.
+    pub store: StoreSummary,
+
+    /// The test groups, keyed by name, with the scripts run before and after their tests.
+    pub test_groups: BTreeMap<String, TestGroupSummary>,
+
+    /// Every profile, keyed by name, including `default`.
+    pub profiles: BTreeMap<String, ProfileSummary>,
+}
+
+impl ConfigSummary {
+    /// Parse a config summary from JSON.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// The `store` section of a [`ConfigSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StoreSummary {
+    /// The absolute directory runs are stored in. Each profile has a subdirectory within it.
+    pub dir: Utf8PathBuf,
+
+    /// The limits on the runs kept in the store. Unset limits are null.
+    pub retention: StoreRetentionSummary,
+}
+
+/// The retention policy for runs in the store, within a [`StoreSummary`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StoreRetentionSummary {
+    /// The maximum number of runs kept for each profile.
+    pub max_runs: Option<usize>,
+
+    /// The maximum total size of the runs kept for each profile, in bytes.
+    pub max_size: Option<u64>,
+
+    /// The maximum age of the runs kept, in seconds.
+    pub max_age: Option<f64>,
+}
+
+/// A test group within a [`ConfigSummary`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestGroupSummary {
+    /// The command run before the first test in the group, as a program followed by its arguments.
+    pub setup: Option<Vec<String>>,
+
+    /// The command run after the last test in the group, as a program followed by its arguments.
+    pub teardown: Option<Vec<String>>,
+
+    /// How long the setup and teardown commands can each take, in seconds.
+    pub timeout: Option<f64>,
+}
+
+/// A profile within a [`ConfigSummary`], with every setting resolved.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfileSummary {
+    /// The absolute directory this profile's runs are stored in.
+    pub store_dir: Utf8PathBuf,
+
+    /// The number of times a failing test is retried.
+    pub retries: usize,
+
+    /// When tests are reported as slow.
+    pub slow_timeout: SlowTimeoutSummary,
+
+    /// The duration assumed for tests with no timing data, in seconds.
+    pub default_duration_estimate: f64,
+
+    /// The statuses shown while the run is in progress, such as `pass`.
+    pub status_level: String,
+
+    /// The statuses shown at the end of the run.
+    pub final_status_level: String,
+
+    /// When the output of failing tests is shown, such as `immediate`.
+    pub failure_output: String,
+
+    /// When the output of passing tests is shown.
+    pub success_output: String,
+
+    /// Whether the run stops at the first failure.
+    pub fail_fast: bool,
+
+    /// The order tests are started in: `binary` or `round-robin`.
+    pub test_order: String,
+
+    /// The filter tests must match to be run, unless `--ignore-default-filter` is passed. Null if
+    /// all tests are run.
+    #[serde(default)]
+    pub default_filter: Option<FilterSummary>,
+
+    /// The env files loaded for tests, in order.
+    pub env_files: Vec<Utf8PathBuf>,
+
+    /// The path the JUnit report is written to, if one is written.
+    pub junit_path: Option<Utf8PathBuf>,
+
+    /// The per-test overrides, in order of precedence: those in the profile come before those in
+    /// the profiles it inherits from. For each setting, the first override that matches a test and
+    /// sets it wins. Only overrides that apply to binaries built for the host or the target are
+    /// included.
+    pub overrides: Vec<OverrideSummary>,
+
+    /// The rules that run matching tests once for each value of an environment variable.
+    pub multiplex: Vec<MultiplexRuleSummary>,
+
+    /// The rules that make matching tests wait for other tests to pass.
+    pub dependencies: Vec<DependencyRuleSummary>,
+}
+
+/// The slow timeout of a [`ProfileSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SlowTimeoutSummary {
+    /// How long a test runs before it's reported as slow, in seconds.
+    pub after: f64,
+
+    /// How often a slow test is reported again after that, in seconds.
+    pub period: Option<f64>,
+}
+
+/// A filter expression in configuration.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FilterSummary {
+    /// The expression as written in the config.
+    pub expr: String,
+
+    /// The expression as nextest parsed it: every matcher has an explicit `=` (exact) or `~`
+    /// (substring) prefix, operators are written as `&`, `|` and `!`, and every `&` and `|` is
+    /// parenthesized.
+    pub compiled: String,
+}
+
+/// An override within a [`ProfileSummary`]. Settings the override doesn't change are null.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OverrideSummary {
+    /// The tests the override applies to.
+    pub filter: FilterSummary,
+
+    /// The number of test slots each matching test takes up.
+    pub threads_required: Option<ThreadsRequiredSummary>,
+
+    /// The test group matching tests are in.
+    pub test_group: Option<String>,
+
+    /// The maximum disk space matching tests can use in their temporary directory, in bytes.
+    pub disk_quota: Option<u64>,
+
+    /// The hard time limit for matching tests.
+    pub time_limit: Option<TimeLimitSummary>,
+
+    /// The result matching tests are expected to have: `pass` or `fail`.
+    #[serde(default)]
+    pub expected_result: Option<String>,
+
+    /// The platforms the override applies to, as a target triple or a `cfg()` expression.
+    #[serde(default)]
+    pub platform: Option<String>,
+
+    /// Whether matching tests are skipped.
+    #[serde(default)]
+    pub skip: Option<bool>,
+}
+
+/// The number of test slots a test takes up, within an [`OverrideSummary`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThreadsRequiredSummary {
+    /// A fixed number of slots, serialized as `{"count": N}`.
+    Count(usize),
+
+    /// As many slots as there are logical CPUs, serialized as `"num-cpus"`.
+    NumCpus,
+}
+
+/// The hard time limit for tests, within an [`OverrideSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TimeLimitSummary {
+    /// How long a test can run before it fails, in seconds.
+    pub limit: f64,
+
+    /// How much longer a test can keep running after that before it's killed, in seconds.
+    pub grace: Option<f64>,
+}
+
+/// A multiplex rule within a [`ProfileSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MultiplexRuleSummary {
+    /// The tests the rule applies to.
+    pub filter: FilterSummary,
+
+    /// The environment variable set for each variant.
+    pub env_var: String,
+
+    /// The values the environment variable is set to, one per variant.
+    pub values: Vec<String>,
+}
+
+/// A dependency rule within a [`ProfileSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DependencyRuleSummary {
+    /// The tests the rule applies to.
+    pub filter: FilterSummary,
+
+    /// The tests that must pass before matching tests are run.
+    pub depends_on: FilterSummary,
+}
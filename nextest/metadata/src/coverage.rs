@@ -0,0 +1,51 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The lines of code each test covered in an instrumented run, used by
+/// `cargo nextest run --select-by-coverage` to only run the tests that cover changed code.
+///
+/// This is written to `coverage-map.json` in the run directory by `cargo nextest run --coverage`,
+/// if the profile's `coverage.map` is true. It can also be produced by tools outside of nextest,
+/// for example from a per-test export of `llvm-cov` data.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CoverageMap {
+    /// The Git commit the instrumented run was made at. Line numbers refer to files at this
+    /// commit.
+    pub commit: String,
+
+    /// The tests in the map, along with the code they covered.
+    pub tests: Vec<TestCoverage>,
+}
+
+impl CoverageMap {
+    /// Parse a coverage map from JSON.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// The code covered by a single test, within a [`CoverageMap`].
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestCoverage {
+    /// The binary ID of the test.
+    pub binary_id: String,
+
+    /// The name of the test.
+    pub name: String,
+
+    /// The files the test covered, relative to the workspace root, mapped to the inclusive ranges
+    /// of lines it covered in each of them, such as `[[10, 24], [40, 41]]`. Lines are numbered from
+    /// 1.
+    pub files: BTreeMap<Utf8PathBuf, Vec<[u32; 2]>>,
+
+    /// The functions the test covered, sorted by name. Names are as they appear in the test
+    /// binary's symbol table, so Rust functions have mangled names.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<String>,
+}
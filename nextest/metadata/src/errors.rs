@@ -55,3 +55,30 @@ impl error::Error for CommandError {
         }
     }
 }
+
+/// Error returned while parsing a [`TestReportStatus`](crate::TestReportStatus) from a string.
+#[derive(Clone, Debug)]
+pub struct TestReportStatusParseError {
+    input: String,
+}
+
+impl TestReportStatusParseError {
+    pub(crate) fn new(input: impl Into<String>) -> Self {
+        Self {
+            input: input.into(),
+        }
+    }
+}
+
+impl fmt::Display for TestReportStatusParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unrecognized value for test status: {}\n(known values: {})",
+            self.input,
+            crate::TestReportStatus::variants().join(", ")
+        )
+    }
+}
+
+impl error::Error for TestReportStatusParseError {}
@@ -0,0 +1,266 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{RunInfoSummary, RunSummary, SkipReasonSummary, TestListSummary, TestReportStatus};
+use serde::{Deserialize, Serialize};
+
+/// An event published while a test run is in progress.
+///
+/// With `--event-stream <PATH>`, `cargo nextest run` connects to the Unix domain socket (or on
+/// Windows, the named pipe) at `PATH` and writes each event to it as a single line of JSON. Every
+/// event has a `type` field naming its kind, such as `"test-started"`.
+///
+/// Events may be added in the future, so consumers should ignore lines they don't recognize.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum RunEvent {
+    /// The run started. This is always the first event.
+    RunStarted {
+        /// Information identifying the run, and where its artifacts are written.
+        run: RunInfoSummary,
+
+        /// The number of tests that will be run.
+        test_count: usize,
+    },
+
+    /// A test started running.
+    TestStarted {
+        /// The test that started.
+        test: TestEventId,
+    },
+
+    /// A test has been running for longer than the slow timeout.
+    TestSlow {
+        /// The test that is slow.
+        test: TestEventId,
+
+        /// The time that has elapsed since the test started, in seconds.
+        elapsed: f64,
+    },
+
+    /// A chunk of the output of an attempt to run a test.
+    ///
+    /// Output is published once the attempt finishes, split up into chunks of at most
+    /// [`RunEvent::MAX_OUTPUT_CHUNK`] bytes. Chunks for an attempt are published in order, before
+    /// the [`TestRetry`](Self::TestRetry) or [`TestFinished`](Self::TestFinished) event for it.
+    TestOutput {
+        /// The test that produced the output.
+        test: TestEventId,
+
+        /// The attempt that produced the output, starting from 1.
+        attempt: usize,
+
+        /// The stream the output was written to.
+        stream: OutputStream,
+
+        /// The output, with invalid UTF-8 replaced.
+        data: String,
+    },
+
+    /// An attempt to run a test failed, and the test is being retried.
+    TestRetry {
+        /// The test being retried.
+        test: TestEventId,
+
+        /// The attempt that failed, starting from 1.
+        attempt: usize,
+
+        /// The result of the attempt that failed.
+        status: TestReportStatus,
+
+        /// The time taken by the attempt, in seconds.
+        time_taken: f64,
+    },
+
+    /// A test finished running, including any retries.
+    TestFinished {
+        /// The test that finished.
+        test: TestEventId,
+
+        /// The overall status of the test.
+        status: TestReportStatus,
+
+        /// Why the test was skipped, if it skipped itself at runtime.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        skip_reason: Option<SkipReasonSummary>,
+
+        /// The number of attempts made to run the test.
+        attempts: usize,
+
+        /// The total time taken by all attempts of the test, in seconds.
+        time_taken: f64,
+    },
+
+    /// A test was skipped without being run.
+    TestSkipped {
+        /// The test that was skipped.
+        test: TestEventId,
+
+        /// Why the test was skipped.
+        skip_reason: SkipReasonSummary,
+    },
+
+    /// The run is being canceled.
+    RunCanceled {
+        /// Why the run is being canceled: `test-failure`, `report-error` or `signal`.
+        reason: String,
+
+        /// The number of tests that are still running.
+        running: usize,
+    },
+
+    /// The run finished. This is always the last event.
+    RunFinished {
+        /// The final summary of the run.
+        summary: RunSummary,
+
+        /// The time taken by the run, in seconds.
+        time_taken: f64,
+    },
+}
+
+impl RunEvent {
+    /// The maximum number of bytes of output in a single [`TestOutput`](Self::TestOutput) event.
+    pub const MAX_OUTPUT_CHUNK: usize = 64 * 1024;
+
+    /// Parse a single line of JSON published by `cargo nextest run --event-stream`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// Identifies a test within a [`RunEvent`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestEventId {
+    /// The binary ID of the test.
+    pub binary_id: String,
+
+    /// The name of the test.
+    pub name: String,
+
+    /// The multiplex variant the test is run with, formatted as `VAR=value`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+/// The stream output was written to, within a [`RunEvent::TestOutput`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputStream {
+    /// Standard output.
+    Stdout,
+
+    /// Standard error.
+    Stderr,
+}
+
+/// The first line nextest writes to the standard input of a custom reporter, before any
+/// [`RunEvent`]s.
+///
+/// The reporter must reply by writing a [`ReporterHandshakeResponse`] as a single line of JSON to
+/// its standard output, choosing one of the protocol versions offered by nextest. If it doesn't,
+/// nextest fails the run before any tests are started.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename = "handshake", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct ReporterHandshake {
+    /// The protocol versions nextest supports, oldest first.
+    pub protocol_versions: Vec<u32>,
+
+    /// The version of nextest.
+    pub nextest_version: String,
+}
+
+impl ReporterHandshake {
+    /// The current version of the custom reporter protocol.
+    ///
+    /// The version is bumped whenever a change is made that existing reporters can't ignore, such
+    /// as a change to the meaning of an existing field. Adding new kinds of [`RunEvent`]s or new
+    /// fields doesn't bump it.
+    pub const PROTOCOL_VERSION: u32 = 1;
+
+    /// Creates a new handshake offering the current protocol version.
+    pub fn new(nextest_version: impl Into<String>) -> Self {
+        Self {
+            protocol_versions: vec![Self::PROTOCOL_VERSION],
+            nextest_version: nextest_version.into(),
+        }
+    }
+
+    /// Parse the handshake line written by nextest.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// The line a custom reporter writes to its standard output in reply to a [`ReporterHandshake`].
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReporterHandshakeResponse {
+    /// The protocol version chosen by the reporter, out of the ones offered by nextest.
+    pub protocol_version: u32,
+}
+
+/// The request nextest writes to the standard input of an external selector, as a single line of
+/// JSON.
+///
+/// The selector must reply by writing a [`SelectorResponse`] as a single line of JSON to its
+/// standard output, and exit.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename = "select", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct SelectorRequest {
+    /// The protocol versions nextest supports, oldest first.
+    pub protocol_versions: Vec<u32>,
+
+    /// The version of nextest.
+    pub nextest_version: String,
+
+    /// The list of tests to choose from. Only tests whose `filter-match` is a match can be chosen.
+    pub test_list: TestListSummary,
+}
+
+impl SelectorRequest {
+    /// The current version of the selector protocol.
+    pub const PROTOCOL_VERSION: u32 = 1;
+
+    /// Creates a new request offering the current protocol version.
+    pub fn new(nextest_version: impl Into<String>, test_list: TestListSummary) -> Self {
+        Self {
+            protocol_versions: vec![Self::PROTOCOL_VERSION],
+            nextest_version: nextest_version.into(),
+            test_list,
+        }
+    }
+
+    /// Parse the request written by nextest.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// The line an external selector writes to its standard output in reply to a
+/// [`SelectorRequest`].
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SelectorResponse {
+    /// The protocol version chosen by the selector, out of the ones offered by nextest.
+    pub protocol_version: u32,
+
+    /// The tests to run, in the order they should be started. Tests that aren't listed are
+    /// skipped, and tests that aren't in the test list are ignored.
+    pub tests: Vec<SelectedTest>,
+}
+
+/// A test chosen by an external selector, within a [`SelectorResponse`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SelectedTest {
+    /// The binary ID of the test.
+    pub binary_id: String,
+
+    /// The name of the test.
+    pub name: String,
+}
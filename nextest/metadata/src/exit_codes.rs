@@ -6,19 +6,38 @@
 /// `cargo nextest` runs may fail for a variety of reasons. This structure documents the exit codes
 /// that may occur in case of expected failures.
 ///
+/// Only [`TEST_RUN_FAILED`](Self::TEST_RUN_FAILED) means that tests were run and at least one of
+/// them failed. Every other code means that nextest itself, or a program it ran on its own behalf
+/// such as Cargo, failed before or after running tests. Wrappers can use this distinction to, for
+/// example, retry a CI job on infrastructure failures but not on test failures.
+///
+/// These values are stable: existing codes won't be changed, although new ones may be added.
+///
 /// Unknown/unexpected failures will always result in exit code 1.
 pub enum NextestExitCode {}
 
 impl NextestExitCode {
+    /// Writing output, for example the test list, the run report, or the run summary, failed.
+    pub const WRITE_OUTPUT_ERROR: i32 = 110;
+
+    /// Creating the list of tests failed: for example, a test binary failed to list its tests or
+    /// produced output nextest couldn't parse.
+    pub const TEST_LIST_CREATION_FAILED: i32 = 104;
+
     /// Running `cargo metadata` produced an error.
     pub const CARGO_METADATA_FAILED: i32 = 102;
 
     /// Building tests produced an error.
     pub const BUILD_FAILED: i32 = 101;
 
-    /// One or more tests failed.
+    /// One or more tests failed, or `cargo nextest report diff` found tests that newly failed or
+    /// became flaky.
     pub const TEST_RUN_FAILED: i32 = 100;
 
-    /// A user issue happened while setting up a nextest invocation.
+    /// A user issue happened while setting up a nextest invocation: for example, a configuration
+    /// file couldn't be parsed, or a previous run needed by the command wasn't found.
     pub const SETUP_ERROR: i32 = 96;
+
+    /// The current version of nextest is older than the version required by the config.
+    pub const REQUIRED_VERSION_NOT_MET: i32 = 92;
 }
@@ -0,0 +1,32 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+/// A fingerprint of a workspace and the toolchain used to build it.
+///
+/// Output by `cargo nextest show-fingerprint --format json`. The fingerprint stays the same
+/// as long as the workspace's sources, its lockfile and the Rust compiler don't change, which makes
+/// it suitable as a cache key for build archives and timing data in CI.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FingerprintSummary {
+    /// The overall fingerprint, combining the components below, as a hex string.
+    pub fingerprint: String,
+
+    /// A hash of the source files in workspace packages, as a hex string.
+    pub sources: String,
+
+    /// A hash of the workspace's `Cargo.lock`, as a hex string, or `None` if it doesn't exist.
+    pub lockfile: Option<String>,
+
+    /// The version of the Rust compiler, as reported by `rustc --version`.
+    pub rustc_version: String,
+}
+
+impl FingerprintSummary {
+    /// Parse JSON output from `cargo nextest show-fingerprint --format json`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
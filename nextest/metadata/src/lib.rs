@@ -5,7 +5,9 @@
 //! [cargo-nextest](https://docs.rs/cargo-nextest).
 //!
 //! Implemented so far:
-//! * ✅ Listing tests
+//! * ✅ Listing tests and test binaries
+//! * ✅ Run information and final run summaries
+//! * ✅ Events published while a run is in progress
 //! * ✅ Semantic exit codes with [`NextestExitCode`]
 //!
 //! # Examples
@@ -25,10 +27,30 @@
 //! ```
 #![warn(missing_docs)]
 
+mod config;
+mod coverage;
 mod errors;
+mod events;
 mod exit_codes;
+mod fingerprint;
+mod report_diff;
+mod run;
+mod schedule;
+mod soak;
+mod startup;
 mod test_list;
+mod timings;
 
+pub use config::*;
+pub use coverage::*;
 pub use errors::*;
+pub use events::*;
 pub use exit_codes::*;
+pub use fingerprint::*;
+pub use report_diff::*;
+pub use run::*;
+pub use schedule::*;
+pub use soak::*;
+pub use startup::*;
 pub use test_list::*;
+pub use timings::*;
@@ -0,0 +1,75 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::TestReportStatus;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The differences between two run reports, output by `cargo nextest report diff`.
+///
+/// Tests are matched up by binary ID, name and variant. Each list is sorted by binary ID, name and
+/// variant.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReportDiffSummary {
+    /// The ID of the old run, the baseline.
+    pub old_run_id: Uuid,
+
+    /// The ID of the new run.
+    pub new_run_id: Uuid,
+
+    /// Tests that failed in the new run, but passed, were skipped or weren't run at all in the old
+    /// one.
+    pub newly_failing: Vec<TestDiffSummary>,
+
+    /// Tests that passed in the new run, and failed in the old one.
+    pub newly_passing: Vec<TestDiffSummary>,
+
+    /// Tests that passed after being retried in the new run, but passed on the first attempt, were
+    /// skipped or weren't run at all in the old one.
+    pub newly_flaky: Vec<TestDiffSummary>,
+
+    /// Tests that passed in both runs, but took significantly longer in the new one.
+    pub slower: Vec<TestDiffSummary>,
+}
+
+impl ReportDiffSummary {
+    /// Parse a report diff from JSON.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+
+    /// Returns true if any tests newly failed or became flaky.
+    pub fn has_regressions(&self) -> bool {
+        !self.newly_failing.is_empty() || !self.newly_flaky.is_empty()
+    }
+}
+
+/// A single test within a [`ReportDiffSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestDiffSummary {
+    /// The binary ID of the test.
+    pub binary_id: String,
+
+    /// The name of the test.
+    pub name: String,
+
+    /// The multiplex variant the test was run with, formatted as `VAR=value`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+
+    /// The status of the test in the old run, or null if it wasn't in the old report.
+    pub old_status: Option<TestReportStatus>,
+
+    /// The status of the test in the new run.
+    pub new_status: TestReportStatus,
+
+    /// The time taken by the last attempt of the test in the old run, in seconds, or null if it
+    /// wasn't run.
+    pub old_time_taken: Option<f64>,
+
+    /// The time taken by the last attempt of the test in the new run, in seconds, or null if it
+    /// wasn't run.
+    pub new_time_taken: Option<f64>,
+}
@@ -0,0 +1,681 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{MismatchReason, TestReportStatusParseError};
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+use uuid::Uuid;
+
+/// Information identifying a test run and the environment it was run in.
+///
+/// Included in [`RunInfoSummary`] and [`RunSummary`], so that CI systems can correlate artifacts
+/// produced by the same run.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunMetadata {
+    /// A unique identifier for this run.
+    pub run_id: Uuid,
+
+    /// The version of nextest used for this run.
+    pub nextest_version: String,
+
+    /// The name of the profile used for this run.
+    pub profile_name: String,
+
+    /// The triple of the host platform, if it could be determined.
+    pub host_triple: Option<String>,
+
+    /// The triple of the platform tests were built for, if it could be determined.
+    pub target_triple: Option<String>,
+
+    /// The command-line arguments nextest was invoked with. Arguments that aren't valid Unicode
+    /// have invalid sequences replaced with U+FFFD.
+    pub invocation: Vec<String>,
+}
+
+/// Information about a test run, made available as soon as the run starts.
+///
+/// `cargo nextest run` writes this out to the file named by the `NEXTEST_RUN_INFO_FILE`
+/// environment variable (or the `--run-info-file` option) before any tests are run. Wrapping
+/// tooling can use it to locate the artifacts of a run, even if nextest is killed midway.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunInfoSummary {
+    /// Information identifying this run.
+    #[serde(flatten)]
+    pub metadata: RunMetadata,
+
+    /// The directory artifacts specific to this run are written to.
+    pub run_dir: Utf8PathBuf,
+
+    /// The path the final [`RunSummary`] will be written to once the run finishes.
+    ///
+    /// This file does not exist until the run is complete.
+    pub summary_path: Utf8PathBuf,
+
+    /// The path the JUnit report will be written to, if JUnit output is configured.
+    pub junit_path: Option<Utf8PathBuf>,
+
+    /// The path the [`RunReportSummary`] will be written to once the run finishes.
+    ///
+    /// This path is shared by all runs with the same profile, and is overwritten by each run.
+    #[serde(default)]
+    pub report_path: Utf8PathBuf,
+
+    /// The dotenv files loaded for this run, in the order they were applied.
+    #[serde(default)]
+    pub env_files: Vec<EnvFileSummary>,
+}
+
+impl RunInfoSummary {
+    /// Parse JSON output written out at the start of `cargo nextest run`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// Where to attach to a run that's in progress, to follow its events.
+///
+/// While `cargo nextest run` is running, it writes this out to `current-run.json` in the profile's
+/// store directory, and removes it once the run finishes. `cargo nextest status` reads it to find
+/// the run, and then connects to the socket to receive the run's [`RunEvent`](crate::RunEvent)s,
+/// one line of JSON each. Events published before the connection are replayed first, except for
+/// [`TestOutput`](crate::RunEvent::TestOutput) events, which aren't published on this socket.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CurrentRunSummary {
+    /// Information identifying the run, and where its artifacts are written.
+    pub run: RunInfoSummary,
+
+    /// The ID of the nextest process performing the run.
+    pub pid: u32,
+
+    /// The Unix domain socket clients can connect to.
+    pub socket_path: Utf8PathBuf,
+}
+
+impl CurrentRunSummary {
+    /// Parse the `current-run.json` file written out by `cargo nextest run`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// A dotenv file whose variables were set for every test in a run.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EnvFileSummary {
+    /// The absolute path to the file.
+    pub path: Utf8PathBuf,
+
+    /// The names of the variables loaded from the file.
+    ///
+    /// Their values aren't recorded, since env files often contain secrets.
+    pub keys: BTreeSet<String>,
+}
+
+/// The final summary of a test run.
+///
+/// Written out to [`RunInfoSummary::summary_path`] at the end of a run.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunSummary {
+    /// Information identifying this run.
+    #[serde(flatten)]
+    pub metadata: RunMetadata,
+
+    /// Whether this run is considered a success.
+    pub success: bool,
+
+    /// The total number of tests that were expected to be run at the beginning.
+    pub initial_run_count: usize,
+
+    /// The total number of tests that were actually run.
+    pub final_run_count: usize,
+
+    /// The number of tests that passed. Includes `flaky`.
+    pub passed: usize,
+
+    /// The number of tests that skipped themselves at runtime. These are not included in `passed`
+    /// or `skipped`.
+    #[serde(default)]
+    pub runtime_skipped: usize,
+
+    /// The number of tests that passed on retry.
+    pub flaky: usize,
+
+    /// The number of tests that failed.
+    pub failed: usize,
+
+    /// The number of tests that encountered an execution failure.
+    pub exec_failed: usize,
+
+    /// The number of tests that wrote more to disk than their disk quota allows.
+    #[serde(default)]
+    pub disk_quota_exceeded: usize,
+
+    /// The number of tests that took longer than their time limit.
+    #[serde(default)]
+    pub timed_out: usize,
+
+    /// The number of quarantined tests that failed. These are not included in `failed`,
+    /// `exec_failed`, `disk_quota_exceeded` or `timed_out`, and don't cause the run to fail.
+    #[serde(default)]
+    pub quarantined: usize,
+
+    /// The number of tests that failed, and were expected to. These are not included in `passed`
+    /// or `failed`.
+    #[serde(default)]
+    pub expected_failures: usize,
+
+    /// The number of tests that passed, but were expected to fail. These are not included in
+    /// `passed`.
+    #[serde(default)]
+    pub unexpected_passes: usize,
+
+    /// The number of tests that were skipped.
+    pub skipped: usize,
+
+    /// The number of tests that weren't run because the maximum duration for the run would have
+    /// been exceeded. These are not included in `skipped`.
+    #[serde(default)]
+    pub overflowed: usize,
+
+    /// The number of tests whose duration was predicted to enforce the maximum duration for the
+    /// run.
+    #[serde(default)]
+    pub timing_predictions: usize,
+
+    /// The number of those predictions that used the default estimate, because there was no timing
+    /// data for the test.
+    #[serde(default)]
+    pub timing_misses: usize,
+
+    /// The number of test binaries that weren't run at all, because the filter couldn't match any
+    /// tests in them. Tests in these binaries aren't included in `skipped`.
+    #[serde(default)]
+    pub skipped_binaries: usize,
+
+    /// The parts of the environment the run was performed in that are likely to affect its
+    /// results. `None` for runs performed by older versions of nextest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentSummary>,
+}
+
+impl RunSummary {
+    /// Parse the JSON summary written out at the end of `cargo nextest run`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// The parts of the environment a run was performed in that are likely to affect its results.
+///
+/// Included in [`RunSummary`], and compared against the local environment by
+/// `cargo nextest env-diff`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EnvironmentSummary {
+    /// The version of the Rust compiler, as reported by `rustc --version`, if it could be
+    /// determined.
+    pub rustc_version: Option<String>,
+
+    /// The features activated through `--features`, split up and sorted.
+    pub features: Vec<String>,
+
+    /// Whether `--all-features` was passed in.
+    pub all_features: bool,
+
+    /// Whether `--no-default-features` was passed in.
+    pub no_default_features: bool,
+
+    /// Environment variables likely to affect the results of tests, such as `RUSTFLAGS` and `TZ`.
+    ///
+    /// Variables whose names suggest that they hold secrets are never recorded.
+    pub env_vars: BTreeMap<String, String>,
+}
+
+/// Differences between the environment a run was performed in and the local environment.
+///
+/// Produced by `cargo nextest env-diff --format json`.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EnvDiffSummary {
+    /// The ID of the run compared against the local environment.
+    pub run_id: Uuid,
+
+    /// The differences found, most likely to affect results first.
+    pub differences: Vec<EnvDifferenceSummary>,
+}
+
+impl EnvDiffSummary {
+    /// Parse JSON output from `cargo nextest env-diff --format json`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// A single difference within an [`EnvDiffSummary`].
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EnvDifferenceSummary {
+    /// What kind of setting differs.
+    pub kind: EnvDifferenceKind,
+
+    /// The name of the setting, such as `target-triple` or the name of an environment variable.
+    pub name: String,
+
+    /// The value recorded for the run, or `None` if it wasn't set.
+    pub recorded: Option<String>,
+
+    /// The value in the local environment, or `None` if it isn't set.
+    pub local: Option<String>,
+}
+
+/// The kind of setting that differs, within an [`EnvDifferenceSummary`].
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EnvDifferenceKind {
+    /// The host or target platform.
+    Platform,
+
+    /// The Rust toolchain.
+    Toolchain,
+
+    /// The Cargo features tests were built with.
+    Features,
+
+    /// An environment variable.
+    EnvVar,
+}
+
+/// A report of the results of every test in a run.
+///
+/// `cargo nextest run` writes this out to `<store-dir>/<profile>/run-report.json` at the end of
+/// each run, overwriting the report for the previous run. Use `cargo nextest show-report` to
+/// display it.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunReportSummary {
+    /// Information identifying this run.
+    #[serde(flatten)]
+    pub metadata: RunMetadata,
+
+    /// Whether this run is considered a success.
+    pub success: bool,
+
+    /// The time taken by the run, in seconds.
+    pub time_taken: f64,
+
+    /// The results of each test, sorted by binary ID and name.
+    pub tests: Vec<TestReportSummary>,
+}
+
+impl RunReportSummary {
+    /// Parse a run report written out by `cargo nextest run`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// The result of a single test within a [`RunReportSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestReportSummary {
+    /// The binary ID of the test.
+    pub binary_id: String,
+
+    /// The name of the test.
+    pub name: String,
+
+    /// The multiplex variant the test was run with, formatted as `VAR=value`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+
+    /// The overall status of the test.
+    pub status: TestReportStatus,
+
+    /// Why the test was skipped, if its status is `skipped`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<SkipReasonSummary>,
+
+    /// Whether the test is quarantined: if it failed, the failure didn't fail the run.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub quarantined: bool,
+
+    /// The total time taken by all attempts of the test, in seconds.
+    pub time_taken: f64,
+
+    /// Each attempt made to run the test, in order. Empty if the test was skipped before running,
+    /// but not if it skipped itself at runtime.
+    pub attempts: Vec<TestAttemptSummary>,
+}
+
+/// A single attempt to run a test within a [`TestReportSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestAttemptSummary {
+    /// The attempt number, starting from 1.
+    pub attempt: usize,
+
+    /// The result of this attempt: one of `pass`, `fail`, `exec-fail` or `skipped`.
+    pub status: TestReportStatus,
+
+    /// The time taken by this attempt, in seconds.
+    pub time_taken: f64,
+
+    /// The file standard output was saved to, if the attempt produced any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_path: Option<Utf8PathBuf>,
+
+    /// The file standard error was saved to, if the attempt produced any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_path: Option<Utf8PathBuf>,
+
+    /// Other files collected for this attempt, such as the test's log file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Utf8PathBuf>,
+
+    /// Why the attempt aborted, such as `SIGSEGV (invalid memory reference)`, if it was killed by
+    /// a signal or crashed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abort_reason: Option<String>,
+
+    /// The core dump or minidump collected for this attempt, if it aborted with core dumps
+    /// enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_dump_path: Option<Utf8PathBuf>,
+
+    /// The summary of the sanitizer report that made this attempt fail, such as
+    /// `AddressSanitizer: heap-use-after-free src/lib.rs:12 in foo`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sanitizer_summary: Option<String>,
+
+    /// The coverage profile written by this attempt, if the run collected coverage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage_profile_path: Option<Utf8PathBuf>,
+
+    /// The failing case found by a property-based testing framework, if this attempt failed
+    /// that way.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub property_test: Option<PropertyTestSummary>,
+
+    /// The peak resident set size of this attempt, in bytes, if it was measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_rss: Option<u64>,
+}
+
+/// A failing case found by a property-based testing framework, within a [`TestAttemptSummary`].
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PropertyTestSummary {
+    /// The framework that found the failing case.
+    pub framework: PropertyTestFramework,
+
+    /// The seed of the random number generator that found the failing case, if it can be
+    /// replayed with `cargo nextest run --replay`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<String>,
+
+    /// The minimal failing input, after shrinking, such as `x = 5`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimal_input: Option<String>,
+
+    /// The line the framework suggests adding to its failure persistence file, such as
+    /// `cc 4a1f... # shrinks to x = 5` for proptest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regression: Option<String>,
+}
+
+/// A property-based testing framework within a [`PropertyTestSummary`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum PropertyTestFramework {
+    /// [proptest](https://docs.rs/proptest).
+    Proptest,
+
+    /// [quickcheck](https://docs.rs/quickcheck).
+    Quickcheck,
+}
+
+impl PropertyTestFramework {
+    /// Returns the name of the framework.
+    pub fn name(self) -> &'static str {
+        match self {
+            PropertyTestFramework::Proptest => "proptest",
+            PropertyTestFramework::Quickcheck => "quickcheck",
+        }
+    }
+}
+
+/// The status of a test or an attempt within a [`RunReportSummary`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestReportStatus {
+    /// The test passed.
+    Pass,
+
+    /// The test passed after being retried. Only used for tests, not attempts.
+    Flaky,
+
+    /// The test failed.
+    Fail,
+
+    /// The test could not be executed.
+    ExecFail,
+
+    /// The test was skipped, either before running or by skipping itself at runtime.
+    Skipped,
+
+    /// The test wrote more to disk than its disk quota allows.
+    DiskQuotaExceeded,
+
+    /// The test took longer than its time limit.
+    Timeout,
+
+    /// The test failed, and was expected to.
+    ExpectedFail,
+
+    /// The test passed, but was expected to fail.
+    UnexpectedPass,
+}
+
+impl TestReportStatus {
+    /// String representations of all known variants.
+    pub fn variants() -> &'static [&'static str] {
+        &[
+            "pass",
+            "flaky",
+            "fail",
+            "exec-fail",
+            "skipped",
+            "disk-quota-exceeded",
+            "timeout",
+            "expected-fail",
+            "unexpected-pass",
+        ]
+    }
+
+    /// Returns true if this status is considered a success.
+    pub fn is_success(self) -> bool {
+        matches!(
+            self,
+            TestReportStatus::Pass
+                | TestReportStatus::Flaky
+                | TestReportStatus::Skipped
+                | TestReportStatus::ExpectedFail
+        )
+    }
+}
+
+impl fmt::Display for TestReportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestReportStatus::Pass => write!(f, "pass"),
+            TestReportStatus::Flaky => write!(f, "flaky"),
+            TestReportStatus::Fail => write!(f, "fail"),
+            TestReportStatus::ExecFail => write!(f, "exec-fail"),
+            TestReportStatus::Skipped => write!(f, "skipped"),
+            TestReportStatus::DiskQuotaExceeded => write!(f, "disk-quota-exceeded"),
+            TestReportStatus::Timeout => write!(f, "timeout"),
+            TestReportStatus::ExpectedFail => write!(f, "expected-fail"),
+            TestReportStatus::UnexpectedPass => write!(f, "unexpected-pass"),
+        }
+    }
+}
+
+impl FromStr for TestReportStatus {
+    type Err = TestReportStatusParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "pass" => TestReportStatus::Pass,
+            "flaky" => TestReportStatus::Flaky,
+            "fail" => TestReportStatus::Fail,
+            "exec-fail" => TestReportStatus::ExecFail,
+            "skipped" => TestReportStatus::Skipped,
+            "disk-quota-exceeded" => TestReportStatus::DiskQuotaExceeded,
+            "timeout" => TestReportStatus::Timeout,
+            "expected-fail" => TestReportStatus::ExpectedFail,
+            "unexpected-pass" => TestReportStatus::UnexpectedPass,
+            other => return Err(TestReportStatusParseError::new(other)),
+        };
+        Ok(val)
+    }
+}
+
+/// Why a test was skipped, in a form suitable for both display and machine consumption.
+///
+/// Used in [`TestReportSummary`], and as the message and type of skipped test cases in JUnit
+/// reports.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SkipReasonSummary {
+    /// What caused the test to be skipped.
+    pub kind: SkipKind,
+
+    /// A human-readable description of why the test was skipped.
+    pub message: String,
+}
+
+impl SkipReasonSummary {
+    /// Creates a skip reason for a test that didn't match the test filter.
+    ///
+    /// `ignore_reason` is the reason specified through `#[ignore = "reason"]`, if known.
+    pub fn from_filter(reason: MismatchReason, ignore_reason: Option<&str>) -> Self {
+        let message = match (reason, ignore_reason) {
+            (MismatchReason::Ignored, Some(ignore_reason)) => format!("ignored: {}", ignore_reason),
+            (MismatchReason::Ignored, None) => "ignored".to_owned(),
+            (reason, _) => format!("test {}", reason),
+        };
+        Self {
+            kind: reason.into(),
+            message,
+        }
+    }
+
+    /// Creates a skip reason for a test that skipped itself at runtime, with the reason the test
+    /// gave, if any.
+    pub fn runtime(reason: Option<&str>) -> Self {
+        let message = match reason.map(str::trim) {
+            Some(reason) if !reason.is_empty() => reason.to_owned(),
+            _ => "skipped at runtime".to_owned(),
+        };
+        Self {
+            kind: SkipKind::Runtime,
+            message,
+        }
+    }
+}
+
+/// What caused a test to be skipped, within a [`SkipReasonSummary`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum SkipKind {
+    /// The test is ignored, and ignored tests weren't run.
+    Ignored,
+
+    /// The test didn't match the provided string filters.
+    String,
+
+    /// The test is in a different partition.
+    Partition,
+
+    /// Only tests from a previous run were rerun, and this test wasn't one of them.
+    Rerun,
+
+    /// The test didn't match the filter expressions specified for the run.
+    Expression,
+
+    /// Only tests in changed packages were run, and this test's package hadn't changed.
+    Unchanged,
+
+    /// The test wasn't run because the maximum duration for the run would have been exceeded.
+    Overflow,
+
+    /// An external selector chose the tests to run, and didn't choose this one.
+    Unselected,
+
+    /// Tests were selected by coverage, and this test didn't cover any code that changed.
+    Uncovered,
+
+    /// An override in the config skipped the test, for example on the platform it was built for.
+    Override,
+
+    /// The test didn't match the profile's default filter.
+    DefaultFilter,
+
+    /// The test skipped itself at runtime.
+    Runtime,
+}
+
+impl SkipKind {
+    /// Returns the string representation of this kind, as used in serialized output.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SkipKind::Ignored => "ignored",
+            SkipKind::String => "string",
+            SkipKind::Partition => "partition",
+            SkipKind::Rerun => "rerun",
+            SkipKind::Expression => "expression",
+            SkipKind::Unchanged => "unchanged",
+            SkipKind::Overflow => "overflow",
+            SkipKind::Unselected => "unselected",
+            SkipKind::Uncovered => "uncovered",
+            SkipKind::Override => "override",
+            SkipKind::DefaultFilter => "default-filter",
+            SkipKind::Runtime => "runtime",
+        }
+    }
+}
+
+impl fmt::Display for SkipKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl From<MismatchReason> for SkipKind {
+    fn from(reason: MismatchReason) -> Self {
+        match reason {
+            MismatchReason::Ignored => SkipKind::Ignored,
+            MismatchReason::String => SkipKind::String,
+            MismatchReason::Partition => SkipKind::Partition,
+            MismatchReason::Rerun => SkipKind::Rerun,
+            MismatchReason::Expression => SkipKind::Expression,
+            MismatchReason::Unchanged => SkipKind::Unchanged,
+            MismatchReason::Overflow => SkipKind::Overflow,
+            MismatchReason::Unselected => SkipKind::Unselected,
+            MismatchReason::Uncovered => SkipKind::Uncovered,
+            MismatchReason::Override => SkipKind::Override,
+            MismatchReason::DefaultFilter => SkipKind::DefaultFilter,
+        }
+    }
+}
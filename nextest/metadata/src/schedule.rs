@@ -0,0 +1,31 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::RunSummary;
+use serde::{Deserialize, Serialize};
+
+/// A run started by `cargo nextest schedule run`, appended as a line of JSON to `history.jsonl` in
+/// the profile's store directory.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScheduledRunSummary {
+    /// The time the run started at, in RFC 3339 format.
+    pub started_at: String,
+
+    /// The time taken by the run, including building and listing tests, in seconds.
+    pub time_taken: f64,
+
+    /// The exit code of `cargo nextest run`, or null if it was killed by a signal.
+    pub exit_code: Option<i32>,
+
+    /// The final summary of the run, or null if it didn't get as far as running tests, for example
+    /// because the build failed.
+    pub run: Option<RunSummary>,
+}
+
+impl ScheduledRunSummary {
+    /// Parse a single line of a schedule history from JSON.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
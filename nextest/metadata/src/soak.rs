@@ -0,0 +1,65 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The results of each iteration of `cargo nextest run --soak`, written to `soak.json` in the
+/// profile's store directory.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SoakSummary {
+    /// Each iteration, in the order they were run.
+    pub iterations: Vec<SoakIterationSummary>,
+
+    /// The tests that were run, sorted by binary ID, name and variant.
+    pub tests: Vec<SoakTestSummary>,
+}
+
+impl SoakSummary {
+    /// Parse a soak summary from JSON.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// A single iteration within a [`SoakSummary`]. Each iteration is a separate run.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SoakIterationSummary {
+    /// The ID of the run.
+    pub run_id: Uuid,
+
+    /// The time taken by the run, in seconds.
+    pub time_taken: f64,
+
+    /// Whether every test in the run passed.
+    pub success: bool,
+}
+
+/// A single test within a [`SoakSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SoakTestSummary {
+    /// The binary ID of the test.
+    pub binary_id: String,
+
+    /// The name of the test.
+    pub name: String,
+
+    /// The multiplex variant the test is run with, formatted as `VAR=value`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+
+    /// The time taken by the last attempt of the test in each iteration, in seconds, or null if it
+    /// wasn't run in that iteration.
+    pub durations: Vec<Option<f64>>,
+
+    /// The peak resident set size of the last attempt of the test in each iteration, in bytes, or
+    /// null if it wasn't run or measured in that iteration.
+    pub peak_rss: Vec<Option<u64>>,
+
+    /// Whether the peak resident set size grew over the iterations without ever shrinking, which
+    /// suggests a leak.
+    pub probable_leak: bool,
+}
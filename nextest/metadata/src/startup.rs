@@ -0,0 +1,46 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+/// How long each test binary takes to start up and exit.
+///
+/// Output by `cargo nextest measure-startup --format json`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StartupSummary {
+    /// The number of times each binary was run. The fastest run is reported.
+    pub runs: usize,
+
+    /// The startup time, in seconds, above which binaries are reported as slow to start.
+    pub threshold: f64,
+
+    /// The binaries that were measured, ordered by the total overhead across their tests, largest
+    /// first.
+    pub binaries: Vec<BinaryStartupSummary>,
+}
+
+impl StartupSummary {
+    /// Parse JSON output from `cargo nextest measure-startup --format json`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// How long a single test binary takes to start up and exit, within a [`StartupSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BinaryStartupSummary {
+    /// The unique identifier of the binary.
+    pub binary_id: String,
+
+    /// The time, in seconds, from spawning the binary with a filter that matches no tests to its
+    /// exit.
+    pub overhead: f64,
+
+    /// The number of tests in the binary. Each of them pays the overhead once.
+    pub test_count: usize,
+
+    /// Whether the overhead is above the threshold.
+    pub slow: bool,
+}
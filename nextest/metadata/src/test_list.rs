@@ -3,7 +3,12 @@
 
 use camino::{Utf8Path, Utf8PathBuf};
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, fmt, path::PathBuf, process::Command};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::PathBuf,
+    process::Command,
+};
 
 use crate::CommandError;
 
@@ -82,7 +87,22 @@ impl ListCommand {
     ///
     ///
     pub fn exec(&self) -> Result<TestListSummary, CommandError> {
+        Self::exec_and_parse(self.cargo_command())
+    }
+
+    /// Executes `cargo nextest list --list-type binaries-only` and parses the output into a
+    /// [`BinaryListSummary`].
+    ///
+    /// Test binaries are built but not run, so this is faster than [`Self::exec`].
+    pub fn exec_binaries_only(&self) -> Result<BinaryListSummary, CommandError> {
         let mut command = self.cargo_command();
+        command.arg("--list-type=binaries-only");
+        Self::exec_and_parse(command)
+    }
+
+    fn exec_and_parse<T: for<'de> Deserialize<'de>>(
+        mut command: Command,
+    ) -> Result<T, CommandError> {
         let output = command.output().map_err(CommandError::Exec)?;
 
         if !output.status.success() {
@@ -108,6 +128,11 @@ pub struct TestListSummary {
     /// A map of Rust test suites to the test binaries within them, keyed by a unique identifier
     /// for each test suite.
     pub rust_suites: BTreeMap<String, RustTestSuiteSummary>,
+
+    /// The unique identifiers of test binaries that weren't listed or run, because the filter
+    /// couldn't match any tests in them.
+    #[serde(default)]
+    pub skipped_binaries: BTreeSet<String>,
 }
 
 impl TestListSummary {
@@ -117,6 +142,71 @@ impl TestListSummary {
     }
 }
 
+/// Root element for a serializable list of test binaries generated by nextest, without the tests
+/// within them.
+///
+/// Produced by `cargo nextest list --list-type binaries-only`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct BinaryListSummary {
+    /// A map of Rust test binaries, keyed by a unique identifier for each binary.
+    pub rust_binaries: BTreeMap<String, RustTestBinarySummary>,
+}
+
+impl BinaryListSummary {
+    /// Parse JSON output from `cargo nextest list --list-type binaries-only --format json`.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// A serializable Rust test binary.
+///
+/// Part of a [`BinaryListSummary`].
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RustTestBinarySummary {
+    /// A unique identifier for this binary.
+    pub binary_id: String,
+
+    /// The name of the test binary within the package.
+    pub binary_name: String,
+
+    /// The unique package ID assigned by Cargo to this binary.
+    ///
+    /// This package ID can be used for lookups in `cargo metadata`.
+    pub package_id: String,
+
+    /// The path to the test binary executable.
+    pub binary_path: Utf8PathBuf,
+
+    /// The platform this binary was built for.
+    pub build_platform: BuildPlatform,
+}
+
+/// The platform a test binary was built for.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildPlatform {
+    /// The target platform, which is the host platform unless cross-compiling.
+    #[default]
+    Target,
+
+    /// The host platform. Tests for procedural macros are always built for the host platform.
+    Host,
+}
+
+impl BuildPlatform {
+    /// Returns the string representation of this platform, as used in serialized output.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BuildPlatform::Target => "target",
+            BuildPlatform::Host => "host",
+        }
+    }
+}
+
 /// A serializable suite of tests within a Rust test binary.
 ///
 /// Part of a [`TestListSummary`].
@@ -137,9 +227,21 @@ pub struct RustTestSuiteSummary {
     /// The path to the test binary executable.
     pub binary_path: Utf8PathBuf,
 
+    /// The platform this binary was built for.
+    ///
+    /// Summaries produced by older versions of nextest don't have this field, so it defaults to
+    /// the target platform.
+    #[serde(default)]
+    pub build_platform: BuildPlatform,
+
     /// The working directory that tests within this package are run in.
     pub cwd: Utf8PathBuf,
 
+    /// Paths to the workspace binaries that tests in this binary need in order to run, keyed by
+    /// the environment variable each path is set as.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub required_binaries: BTreeMap<String, Utf8PathBuf>,
+
     /// Test case names and other information about them.
     pub testcases: BTreeMap<String, RustTestCaseSummary>,
 }
@@ -155,6 +257,22 @@ pub struct RustTestCaseSummary {
     /// Ignored tests, if run, are executed with the `--ignored` argument.
     pub ignored: bool,
 
+    /// The reason this test is ignored, as specified through `#[ignore = "reason"]`.
+    ///
+    /// This is only available if nextest was asked to collect ignore reasons, and the test binary
+    /// supports reporting them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_reason: Option<String>,
+
+    /// The name the test binary listed this test under, if it's different from the name nextest
+    /// identifies the test by.
+    ///
+    /// This happens when a test binary, typically one with a custom harness, lists several tests
+    /// with the same name. Every test after the first is renamed by adding a ` #2`, ` #3` etc
+    /// suffix so that it's tracked separately, and is run by the name it was listed under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listed_name: Option<String>,
+
     /// Whether the test matches the provided test filter.
     ///
     /// Only tests that match the filter are run.
@@ -195,6 +313,50 @@ pub enum MismatchReason {
 
     /// This test is in a different partition.
     Partition,
+
+    /// Only tests from a previous run are being rerun, and this test isn't one of them.
+    Rerun,
+
+    /// This test does not match the provided filter expressions.
+    Expression,
+
+    /// Only tests in changed packages are being run, and this test's package hasn't changed.
+    Unchanged,
+
+    /// This test wasn't run because the maximum duration for the run would have been exceeded.
+    Overflow,
+
+    /// An external selector chose the tests to run, and didn't choose this one.
+    Unselected,
+
+    /// Tests were selected by coverage, and this test doesn't cover any code that changed.
+    Uncovered,
+
+    /// An override in the config skips this test, for example on the platform it's built for.
+    Override,
+
+    /// This test does not match the profile's default filter. Unlike with `Expression`, the test
+    /// can still be run by passing `--ignore-default-filter`.
+    DefaultFilter,
+}
+
+impl MismatchReason {
+    /// Returns the string representation of this reason, as used in serialized output.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MismatchReason::Ignored => "ignored",
+            MismatchReason::String => "string",
+            MismatchReason::Partition => "partition",
+            MismatchReason::Rerun => "rerun",
+            MismatchReason::Expression => "expression",
+            MismatchReason::Unchanged => "unchanged",
+            MismatchReason::Overflow => "overflow",
+            MismatchReason::Unselected => "unselected",
+            MismatchReason::Uncovered => "uncovered",
+            MismatchReason::Override => "override",
+            MismatchReason::DefaultFilter => "default-filter",
+        }
+    }
 }
 
 impl fmt::Display for MismatchReason {
@@ -203,6 +365,18 @@ impl fmt::Display for MismatchReason {
             MismatchReason::Ignored => write!(f, "does not match the run-ignored option"),
             MismatchReason::String => write!(f, "does not match the provided string filters"),
             MismatchReason::Partition => write!(f, "is in a different partition"),
+            MismatchReason::Rerun => write!(f, "is not being rerun from a previous run"),
+            MismatchReason::Expression => {
+                write!(f, "does not match the provided filter expressions")
+            }
+            MismatchReason::Unchanged => write!(f, "is in a package that hasn't changed"),
+            MismatchReason::Overflow => write!(f, "would exceed the maximum duration for the run"),
+            MismatchReason::Unselected => write!(f, "was not chosen by the selector"),
+            MismatchReason::Uncovered => write!(f, "doesn't cover any changed code"),
+            MismatchReason::Override => write!(f, "is skipped by an override in the config"),
+            MismatchReason::DefaultFilter => {
+                write!(f, "does not match the profile's default filter")
+            }
         }
     }
 }
@@ -0,0 +1,50 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+/// The durations of tests, merged from the reports of several runs, such as the shards of a CI
+/// run, by `cargo nextest timings merge`.
+///
+/// It can be used in place of the latest run report for `--max-shard-duration`, with `--timings`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TimingDatabaseSummary {
+    /// The IDs of the runs that have been merged in, sorted. Merging the report of one of these
+    /// runs again has no effect.
+    pub run_ids: BTreeSet<Uuid>,
+
+    /// The tests, sorted by binary ID, name and variant.
+    pub timings: Vec<TestTimingSummary>,
+}
+
+impl TimingDatabaseSummary {
+    /// Parse a timing database from JSON.
+    pub fn parse_json(json: impl AsRef<str>) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json.as_ref())
+    }
+}
+
+/// The duration of a single test, within a [`TimingDatabaseSummary`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestTimingSummary {
+    /// The binary ID of the test.
+    pub binary_id: String,
+
+    /// The name of the test.
+    pub name: String,
+
+    /// The multiplex variant the test is run with, formatted as `VAR=value`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+
+    /// The predicted duration of the test, in seconds: an exponentially weighted moving average of
+    /// the durations it was seen to take.
+    pub duration: f64,
+
+    /// The number of runs the duration is based on.
+    pub samples: usize,
+}
@@ -0,0 +1,364 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Running test binaries on Android devices and emulators through `adb`.
+//!
+//! A `target-runner` rule can specify Android devices instead of a command:
+//!
+//! ```toml
+//! [[profile.default.target-runner]]
+//! platform = "cfg(target_os = \"android\")"
+//! adb = { devices = ["emulator-5554", "R58M123ABC"], dir = "/data/local/tmp/nextest" }
+//! ```
+//!
+//! Before a test binary is first run on a device, it is pushed with `adb push` to
+//! `<dir>/.nextest/bin` on the device, along with any dynamic libraries in the directories it's
+//! linked against, which are pushed to `<dir>/.nextest/lib`. Each test is then run through
+//! `adb shell`, which streams its standard output and standard error back and exits with the exit
+//! code of the test. This requires the shell protocol of Android 7.0 or later.
+//!
+//! `dir` defaults to `/data/local/tmp/nextest`. Paths in the workspace are mapped to the same paths
+//! relative to `dir`, as with [remote hosts](crate::remote_runner): tests are run in the mapped
+//! working directory, and the environment variables nextest sets for the test are passed along with
+//! their paths mapped.
+//!
+//! If more than one device is specified, tests are spread across them: each test is run on the
+//! device for its [global slot](crate::runner::NEXTEST_TEST_GLOBAL_SLOT_ENV), so tests running at
+//! the same time are balanced between devices. Tests are listed on the first device. If no devices
+//! are specified, `adb` picks the device, for example through the `ANDROID_SERIAL` environment
+//! variable. The devices in the config can be overridden with `cargo nextest run --device <serial>`.
+
+use crate::{
+    path_mapper::PathMapper,
+    remote_runner::{dylibs_in, remote_script, run_checked, shell_quote},
+    runner::NEXTEST_TEST_GLOBAL_SLOT_ENV,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::{cmd, Expression};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fmt, io,
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+/// Android devices that test binaries are pushed to and run on through `adb`.
+///
+/// Obtained through [`TargetRunnerRule::adb`](crate::target_runner::TargetRunnerRule::adb).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AdbDevices {
+    #[serde(default)]
+    devices: Vec<String>,
+    #[serde(default = "default_dir")]
+    dir: String,
+    #[serde(default)]
+    adb_args: Vec<String>,
+}
+
+impl AdbDevices {
+    /// The default directory on devices that paths in the workspace are mapped to.
+    pub const DEFAULT_DIR: &'static str = "/data/local/tmp/nextest";
+
+    /// Creates a new set of devices from their serial numbers, as passed to `adb -s`. If no serial
+    /// numbers are given, `adb` picks the device.
+    pub fn new(devices: Vec<String>) -> Self {
+        Self {
+            devices,
+            dir: default_dir(),
+            adb_args: vec![],
+        }
+    }
+
+    /// Sets the serial numbers of the devices to run tests on.
+    pub fn set_devices(&mut self, devices: Vec<String>) -> &mut Self {
+        self.devices = devices;
+        self
+    }
+
+    /// Sets the directory on devices that paths in the workspace are mapped to.
+    pub fn set_dir(&mut self, dir: impl Into<String>) -> &mut Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Sets additional arguments to pass to `adb`, before the device and the subcommand.
+    pub fn set_adb_args(&mut self, adb_args: Vec<String>) -> &mut Self {
+        self.adb_args = adb_args;
+        self
+    }
+
+    /// Returns the serial numbers of the devices to run tests on.
+    pub fn devices(&self) -> &[String] {
+        &self.devices
+    }
+
+    /// Returns the directory on devices that paths in the workspace are mapped to.
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    /// Returns the additional arguments passed to `adb`.
+    pub fn adb_args(&self) -> &[String] {
+        &self.adb_args
+    }
+}
+
+fn default_dir() -> String {
+    AdbDevices::DEFAULT_DIR.to_owned()
+}
+
+/// Runs test binaries on [`AdbDevices`], pushing them and their libraries as needed.
+pub(crate) struct AdbRunner {
+    devices: AdbDevices,
+    path_mapper: PathMapper,
+    // What has been pushed to each device, in the same order as the devices (or a single entry if
+    // adb picks the device).
+    pushed: Vec<Mutex<PushState>>,
+}
+
+// Tracks what has already been pushed to a device.
+#[derive(Debug, Default)]
+struct PushState {
+    binaries: HashSet<Utf8PathBuf>,
+    // Library directories, and whether they have any dynamic libraries in them.
+    lib_dirs: HashMap<Utf8PathBuf, bool>,
+}
+
+impl AdbRunner {
+    pub(crate) fn new(devices: AdbDevices, workspace_root: &Utf8Path) -> Self {
+        let mut path_mapper = PathMapper::new();
+        path_mapper.add(workspace_root, devices.dir.clone());
+        let pushed = (0..devices.devices.len().max(1))
+            .map(|_| Mutex::new(PushState::default()))
+            .collect();
+        Self {
+            devices,
+            path_mapper,
+            pushed,
+        }
+    }
+
+    pub(crate) fn devices(&self) -> &AdbDevices {
+        &self.devices
+    }
+
+    /// Returns a runner for the same directory that runs tests on the given devices instead.
+    pub(crate) fn with_devices(&self, devices: Vec<String>) -> Self {
+        let mut adb_devices = self.devices.clone();
+        adb_devices.set_devices(devices);
+        let pushed = (0..adb_devices.devices.len().max(1))
+            .map(|_| Mutex::new(PushState::default()))
+            .collect();
+        Self {
+            devices: adb_devices,
+            path_mapper: self.path_mapper.clone(),
+            pushed,
+        }
+    }
+
+    /// Returns the `adb` command that runs the given binary on a device.
+    ///
+    /// The device is picked, the binary and the dynamic libraries in `dylib_dirs` are pushed to it,
+    /// and the command run on it is determined right before the command is spawned. This is so that
+    /// the working directory and environment variables set on the expression later are picked up.
+    pub(crate) fn make_command(
+        self: &Arc<Self>,
+        binary_path: &Utf8Path,
+        args: Vec<&str>,
+        dylib_dirs: Vec<Utf8PathBuf>,
+    ) -> Expression {
+        let this = self.clone();
+        let binary_path = binary_path.to_owned();
+        let args: Vec<String> = args.into_iter().map(|arg| arg.to_owned()).collect();
+        cmd("adb", &self.devices.adb_args).before_spawn(move |command| {
+            let device = this.device_index(command.get_envs());
+            let device_binary = this.push_binary(device, &binary_path)?;
+            let device_lib_dir = this.push_libs(device, &dylib_dirs)?;
+            let script = remote_script(
+                &this.path_mapper,
+                &this.devices.dir,
+                command.get_current_dir(),
+                command.get_envs(),
+                &device_binary,
+                &args,
+                device_lib_dir.as_deref(),
+            );
+            command
+                .args(this.device_args(device))
+                .arg("shell")
+                .arg(script);
+            Ok(())
+        })
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    // Picks the device to run a test on from its global slot. Tests are listed without a slot, on
+    // the first device.
+    fn device_index<'a>(
+        &self,
+        envs: impl IntoIterator<Item = (&'a OsStr, Option<&'a OsStr>)>,
+    ) -> usize {
+        let slot = envs
+            .into_iter()
+            .find(|(key, _)| *key == NEXTEST_TEST_GLOBAL_SLOT_ENV)
+            .and_then(|(_, value)| value?.to_str()?.parse::<usize>().ok())
+            .unwrap_or(0);
+        slot % self.pushed.len()
+    }
+
+    fn device_args(&self, device: usize) -> Vec<&str> {
+        match self.devices.devices.get(device) {
+            Some(serial) => vec!["-s", serial.as_str()],
+            None => vec![],
+        }
+    }
+
+    fn device_name(&self, device: usize) -> &str {
+        self.devices
+            .devices
+            .get(device)
+            .map_or("the default device", |serial| serial.as_str())
+    }
+
+    fn bin_dir(&self) -> String {
+        format!("{}/.nextest/bin", self.devices.dir)
+    }
+
+    fn lib_dir(&self) -> String {
+        format!("{}/.nextest/lib", self.devices.dir)
+    }
+
+    fn push_binary(&self, device: usize, binary_path: &Utf8Path) -> io::Result<String> {
+        let file_name = binary_path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("test binary '{}' has no file name", binary_path),
+            )
+        })?;
+        let device_binary = format!("{}/{}", self.bin_dir(), file_name);
+
+        // Hold the lock while pushing, so that tests in the same binary wait for the push rather
+        // than starting one of their own. Pushes to different devices happen in parallel.
+        let mut pushed = self.pushed[device].lock().expect("lock is not poisoned");
+        if !pushed.binaries.contains(binary_path) {
+            self.push_files(device, &[binary_path.to_owned()], &self.bin_dir())?;
+            pushed.binaries.insert(binary_path.to_owned());
+        }
+        Ok(device_binary)
+    }
+
+    // Returns the directory on the device the libraries were pushed to, or None if there are none.
+    fn push_libs(&self, device: usize, dylib_dirs: &[Utf8PathBuf]) -> io::Result<Option<String>> {
+        let mut pushed = self.pushed[device].lock().expect("lock is not poisoned");
+        let mut any_libs = false;
+        for dylib_dir in dylib_dirs {
+            let has_libs = match pushed.lib_dirs.get(dylib_dir) {
+                Some(&has_libs) => has_libs,
+                None => {
+                    let libs = dylibs_in(dylib_dir)?;
+                    if !libs.is_empty() {
+                        self.push_files(device, &libs, &self.lib_dir())?;
+                    }
+                    pushed.lib_dirs.insert(dylib_dir.clone(), !libs.is_empty());
+                    !libs.is_empty()
+                }
+            };
+            any_libs |= has_libs;
+        }
+        Ok(any_libs.then(|| self.lib_dir()))
+    }
+
+    fn push_files(&self, device: usize, files: &[Utf8PathBuf], device_dir: &str) -> io::Result<()> {
+        let mut mkdir = self.adb_command(device);
+        mkdir
+            .arg("shell")
+            .arg(format!("mkdir -p {}", shell_quote(device_dir)));
+        run_checked(mkdir, || {
+            format!("creating '{}' on {}", device_dir, self.device_name(device))
+        })?;
+
+        let mut push = self.adb_command(device);
+        push.arg("push").args(files).arg(format!("{}/", device_dir));
+        run_checked(push, || {
+            format!(
+                "pushing {} file(s) to '{}' on {}",
+                files.len(),
+                device_dir,
+                self.device_name(device)
+            )
+        })
+    }
+
+    fn adb_command(&self, device: usize) -> Command {
+        let mut command = Command::new("adb");
+        command
+            .args(&self.devices.adb_args)
+            .args(self.device_args(device));
+        command
+    }
+}
+
+impl fmt::Debug for AdbRunner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AdbRunner")
+            .field("devices", &self.devices)
+            .field("path_mapper", &self.path_mapper)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for AdbRunner {
+    fn eq(&self, other: &Self) -> bool {
+        self.devices == other.devices && self.path_mapper == other.path_mapper
+    }
+}
+
+impl Eq for AdbRunner {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_devices() {
+        let slot_env = |slot: &'static str| {
+            [
+                (OsStr::new("CARGO_PKG_NAME"), Some(OsStr::new("my-crate"))),
+                (
+                    OsStr::new(NEXTEST_TEST_GLOBAL_SLOT_ENV),
+                    Some(OsStr::new(slot)),
+                ),
+            ]
+        };
+
+        let runner = AdbRunner::new(
+            AdbDevices::new(vec!["emulator-5554".to_owned(), "R58M123ABC".to_owned()]),
+            "/workspace".as_ref(),
+        );
+        assert_eq!(runner.devices().dir(), AdbDevices::DEFAULT_DIR);
+        assert_eq!(runner.device_index(slot_env("0")), 0);
+        assert_eq!(runner.device_index(slot_env("1")), 1);
+        assert_eq!(runner.device_index(slot_env("4")), 0);
+        assert_eq!(
+            runner.device_index([]),
+            0,
+            "tests are listed on the first device"
+        );
+        assert_eq!(runner.device_args(1), ["-s", "R58M123ABC"]);
+
+        let runner = AdbRunner::new(AdbDevices::new(vec![]), "/workspace".as_ref());
+        assert_eq!(runner.device_index(slot_env("3")), 0);
+        assert_eq!(
+            runner.device_args(0),
+            Vec::<&str>::new(),
+            "adb picks the device"
+        );
+        assert_eq!(runner.device_name(0), "the default device");
+    }
+}
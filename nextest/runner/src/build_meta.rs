@@ -0,0 +1,154 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Information about how test binaries were built, used to run them.
+//!
+//! When cross-compiling, Cargo builds tests for procedural macros (along with other host
+//! dependencies) for the host platform, and everything else for the target platform. Each set of
+//! binaries is put in its own output directory, such as `target/debug` for the host and
+//! `target/<triple>/debug` for the target, and may need to load dynamic libraries from that
+//! directory or from the Rust sysroot for its platform.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::BuildPlatform;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    ffi::OsString,
+};
+
+/// The environment variable used to look up dynamic libraries on this platform.
+#[cfg(windows)]
+pub const DYLIB_PATH_ENV: &str = "PATH";
+
+/// The environment variable used to look up dynamic libraries on this platform.
+#[cfg(target_os = "macos")]
+pub const DYLIB_PATH_ENV: &str = "DYLD_FALLBACK_LIBRARY_PATH";
+
+/// The environment variable used to look up dynamic libraries on this platform.
+#[cfg(not(any(windows, target_os = "macos")))]
+pub const DYLIB_PATH_ENV: &str = "LD_LIBRARY_PATH";
+
+/// Information about how test binaries were built, for each [`BuildPlatform`].
+///
+/// Accepted by [`ListSettings::set_build_meta`](crate::test_list::ListSettings::set_build_meta),
+/// which sets the libdirs. Output directories are added by the [`TestList`](crate::test_list::TestList)
+/// from the binaries in it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RustBuildMeta {
+    base_output_directories: BTreeMap<BuildPlatform, BTreeSet<Utf8PathBuf>>,
+    libdirs: BTreeMap<BuildPlatform, Utf8PathBuf>,
+}
+
+impl RustBuildMeta {
+    /// Sets the directory containing the Rust standard library for the given platform, as
+    /// reported by `rustc --print target-libdir`.
+    pub fn set_libdir(
+        &mut self,
+        build_platform: BuildPlatform,
+        libdir: impl Into<Utf8PathBuf>,
+    ) -> &mut Self {
+        self.libdirs.insert(build_platform, libdir.into());
+        self
+    }
+
+    /// Records the output directory of a test binary built for the given platform.
+    ///
+    /// Test binaries are built into the `deps` directory within an output directory.
+    pub(crate) fn add_binary(&mut self, build_platform: BuildPlatform, binary_path: &Utf8Path) {
+        if let Some(base_output_dir) = binary_path.parent().and_then(|deps_dir| deps_dir.parent()) {
+            self.base_output_directories
+                .entry(build_platform)
+                .or_default()
+                .insert(base_output_dir.to_owned());
+        }
+    }
+
+    /// Returns the output directories binaries for the given platform were built into, such as
+    /// `target/debug`.
+    pub fn base_output_directories(
+        &self,
+        build_platform: BuildPlatform,
+    ) -> impl Iterator<Item = &Utf8Path> + '_ {
+        self.base_output_directories
+            .get(&build_platform)
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.as_path())
+    }
+
+    /// Returns the directory containing the Rust standard library for the given platform, if set.
+    pub fn libdir(&self, build_platform: BuildPlatform) -> Option<&Utf8Path> {
+        self.libdirs.get(&build_platform).map(|dir| dir.as_path())
+    }
+
+    /// Returns the directories binaries built for the given platform may load dynamic libraries
+    /// from: the `deps` directories for the platform and its libdir.
+    pub(crate) fn dylib_dirs(&self, build_platform: BuildPlatform) -> Vec<Utf8PathBuf> {
+        self.base_output_directories(build_platform)
+            .map(|dir| dir.join("deps"))
+            .chain(self.libdir(build_platform).map(|dir| dir.to_path_buf()))
+            .collect()
+    }
+
+    /// Returns the value of [`DYLIB_PATH_ENV`] for binaries built for the given platform: the
+    /// `deps` directories for the platform and its libdir, followed by the current value.
+    ///
+    /// Returns `None` if there are no directories to add.
+    pub(crate) fn dylib_path(&self, build_platform: BuildPlatform) -> Option<OsString> {
+        let mut dirs: Vec<_> = self
+            .dylib_dirs(build_platform)
+            .into_iter()
+            .map(|dir| dir.into_std_path_buf())
+            .collect();
+        if dirs.is_empty() {
+            return None;
+        }
+        if let Some(existing) = env::var_os(DYLIB_PATH_ENV) {
+            dirs.extend(env::split_paths(&existing));
+        }
+        // Directories with the separator in them can't be added, so skip setting the variable in
+        // that case.
+        env::join_paths(dirs).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn libdirs_per_platform() {
+        let mut build_meta = RustBuildMeta::default();
+        assert_eq!(build_meta.dylib_path(BuildPlatform::Host), None);
+
+        build_meta.add_binary(
+            BuildPlatform::Target,
+            "/fake/target/aarch64-unknown-linux-gnu/debug/deps/fake-1234".as_ref(),
+        );
+        build_meta
+            .set_libdir(BuildPlatform::Host, "/fake/sysroot/host/lib")
+            .set_libdir(BuildPlatform::Target, "/fake/sysroot/target/lib");
+
+        let split = |platform| {
+            let dylib_path = build_meta.dylib_path(platform).expect("dylib path is set");
+            env::split_paths(&dylib_path)
+                .map(|path| Utf8PathBuf::try_from(path).expect("path is UTF-8"))
+                .collect::<Vec<_>>()
+        };
+        let target = split(BuildPlatform::Target);
+        assert_eq!(
+            &target[..2],
+            [
+                Utf8PathBuf::from("/fake/target/aarch64-unknown-linux-gnu/debug/deps"),
+                Utf8PathBuf::from("/fake/sysroot/target/lib"),
+            ]
+        );
+        let host = split(BuildPlatform::Host);
+        assert_eq!(host[0], "/fake/sysroot/host/lib");
+        assert!(
+            !host.iter().any(|path| path.as_str().contains("aarch64")),
+            "host binaries don't get target directories"
+        );
+    }
+}
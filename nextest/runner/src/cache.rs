@@ -0,0 +1,319 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Caching of results that are expensive to compute but rarely change between invocations.
+//!
+//! Two kinds of results are cached, under `<store-dir>/cache`:
+//!
+//! * The output of `cargo metadata`, which is used to build the package graph. This is reused as
+//!   long as none of the workspace's manifests, its lockfile, the Cargo configuration files that
+//!   apply to it, nor the directories Cargo looks in to discover targets and packages, have
+//!   changed.
+//! * The output of running each test binary with `--list`. This is reused as long as the binary
+//!   hasn't changed, as determined by its size and modification time.
+//!
+//! Caching is best-effort: failures to read or write cache entries are ignored, and the results
+//! are recomputed instead.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use guppy::graph::PackageGraph;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    hash::{Hash, Hasher},
+    time::UNIX_EPOCH,
+};
+use twox_hash::XxHash64;
+
+/// A cache for results reused across nextest invocations.
+#[derive(Clone, Debug)]
+pub struct NextestCache {
+    dir: Utf8PathBuf,
+}
+
+impl NextestCache {
+    /// The name of the cache directory within the store directory.
+    pub const DIR_NAME: &'static str = "cache";
+
+    // Bump this whenever the format of cache entries changes.
+    const VERSION: u32 = 1;
+
+    /// Creates a new cache within the given store directory.
+    ///
+    /// The store directory is typically [`NextestConfig::store_dir`](crate::config::NextestConfig::store_dir).
+    pub fn new(store_dir: impl AsRef<Utf8Path>) -> Self {
+        Self {
+            dir: store_dir.as_ref().join(Self::DIR_NAME),
+        }
+    }
+
+    /// Returns the directory cache entries are written to.
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+
+    /// Returns the cached `cargo metadata` JSON output, if it is still up-to-date.
+    pub fn get_metadata(&self) -> Option<String> {
+        let entry: MetadataEntry = self.read_entry(&self.metadata_path())?;
+        if entry.stamps.iter().all(FileStamp::is_fresh) {
+            Some(entry.json)
+        } else {
+            None
+        }
+    }
+
+    /// Caches `cargo metadata` JSON output, along with the package graph built from it.
+    ///
+    /// The package graph determines the files the output depends on.
+    pub fn put_metadata(&self, json: impl Into<String>, graph: &PackageGraph) {
+        let workspace_root = graph.workspace().root();
+        let package_paths = graph.workspace().iter().flat_map(|package| {
+            let manifest_path = package.manifest_path();
+            let package_dir = manifest_path.parent().expect("manifest path has a parent");
+            // The directories Cargo looks in for automatically discovered targets: adding or
+            // removing files from them changes their modification times. The same goes for the
+            // directory the package is in, which a glob in `workspace.members` may match new
+            // packages in.
+            let dirs = ["src", "src/bin", "tests", "examples", "benches"]
+                .into_iter()
+                .map(move |dir| package_dir.join(dir))
+                .chain(package_dir.parent().map(Utf8Path::to_path_buf));
+            std::iter::once(manifest_path.to_path_buf()).chain(dirs)
+        });
+        // Cargo reads its configuration from `.cargo` directories in the workspace and the
+        // directories above it, as well as from Cargo's home directory.
+        let config_dirs = workspace_root
+            .ancestors()
+            .map(|dir| dir.join(".cargo"))
+            .chain(std::env::var("CARGO_HOME").ok().map(Utf8PathBuf::from));
+        let config_paths = config_dirs
+            .flat_map(|dir| ["config", "config.toml"].map(|file_name| dir.join(file_name)));
+        let mut paths: Vec<_> = package_paths
+            .chain([
+                workspace_root.join("Cargo.toml"),
+                workspace_root.join("Cargo.lock"),
+            ])
+            .chain(config_paths)
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        let stamps = paths.iter().map(|path| FileStamp::new(path)).collect();
+
+        let entry = MetadataEntry {
+            stamps,
+            json: json.into(),
+        };
+        self.write_entry(&self.metadata_path(), &entry);
+    }
+
+    /// Returns the cached `--list` outputs for the given binary, if it hasn't changed since they
+    /// were cached.
+    ///
+    /// `format` distinguishes between the different ways a binary can be listed.
+    pub(crate) fn get_list_output(
+        &self,
+        binary_path: &Utf8Path,
+        format: &str,
+    ) -> Option<(String, String)> {
+        let entry: ListEntry = self.read_entry(&self.list_path(binary_path, format))?;
+        if entry.stamp.path == binary_path && entry.stamp.is_fresh() {
+            Some((entry.non_ignored, entry.ignored))
+        } else {
+            None
+        }
+    }
+
+    /// Caches the `--list` outputs for the given binary.
+    pub(crate) fn put_list_output(
+        &self,
+        binary_path: &Utf8Path,
+        format: &str,
+        non_ignored: &str,
+        ignored: &str,
+    ) {
+        let entry = ListEntry {
+            stamp: FileStamp::new(binary_path),
+            non_ignored: non_ignored.to_owned(),
+            ignored: ignored.to_owned(),
+        };
+        self.write_entry(&self.list_path(binary_path, format), &entry);
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    fn metadata_path(&self) -> Utf8PathBuf {
+        self.dir.join("metadata.json")
+    }
+
+    fn list_path(&self, binary_path: &Utf8Path, format: &str) -> Utf8PathBuf {
+        let mut hasher = XxHash64::default();
+        binary_path.hash(&mut hasher);
+        format.hash(&mut hasher);
+        self.dir
+            .join("list")
+            .join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    fn read_entry<T: DeserializeOwned>(&self, path: &Utf8Path) -> Option<T> {
+        let contents = fs::read(path).ok()?;
+        let entry: VersionedEntry<T> = serde_json::from_slice(&contents).ok()?;
+        (entry.version == Self::VERSION).then_some(entry.entry)
+    }
+
+    fn write_entry<T: Serialize>(&self, path: &Utf8Path, entry: &T) {
+        let entry = VersionedEntry {
+            version: Self::VERSION,
+            entry,
+        };
+        let json = match serde_json::to_vec(&entry) {
+            Ok(json) => json,
+            Err(_) => return,
+        };
+
+        // Write to a temporary file and rename it into place, so that concurrent invocations never
+        // observe partially written entries.
+        let temp_path = Utf8PathBuf::from(format!("{}.{}.tmp", path, std::process::id()));
+        let res = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&temp_path, json))
+            .and_then(|()| fs::rename(&temp_path, path));
+        if res.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct VersionedEntry<T> {
+    version: u32,
+    entry: T,
+}
+
+#[derive(Deserialize, Serialize)]
+struct MetadataEntry {
+    stamps: Vec<FileStamp>,
+    json: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ListEntry {
+    stamp: FileStamp,
+    non_ignored: String,
+    ignored: String,
+}
+
+/// The size and modification time of a file or directory, or `None` if it doesn't exist.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct FileStamp {
+    path: Utf8PathBuf,
+    // (size, seconds, nanoseconds)
+    stamp: Option<(u64, u64, u32)>,
+}
+
+impl FileStamp {
+    fn new(path: &Utf8Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            stamp: Self::compute(path),
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        Self::compute(&self.path) == self.stamp
+    }
+
+    fn compute(path: &Utf8Path) -> Option<(u64, u64, u32)> {
+        let metadata = fs::metadata(path).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some((metadata.len(), mtime.as_secs(), mtime.subsec_nanos()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_freshness() {
+        let workspace_root = tempfile::tempdir().expect("tempdir created");
+        let workspace_root =
+            Utf8Path::from_path(workspace_root.path()).expect("tempdir path is UTF-8");
+        fs::write(
+            workspace_root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .expect("workspace manifest written");
+        let package_dir = workspace_root.join("crates/a");
+        fs::create_dir_all(package_dir.join("src")).expect("package dir created");
+        fs::write(
+            package_dir.join("Cargo.toml"),
+            "[package]\nname = \"a\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .expect("package manifest written");
+        fs::write(package_dir.join("src/lib.rs"), "").expect("lib.rs written");
+        let graph = guppy::MetadataCommand::new()
+            .manifest_path(workspace_root.join("Cargo.toml"))
+            .no_deps()
+            .build_graph()
+            .expect("package graph built");
+
+        let cache = NextestCache::new(workspace_root.join("target/nextest"));
+        let assert_invalidated_by = |description: &str, change: &dyn Fn()| {
+            cache.put_metadata("{}", &graph);
+            assert_eq!(
+                cache.get_metadata().as_deref(),
+                Some("{}"),
+                "metadata cached"
+            );
+            change();
+            assert_eq!(
+                cache.get_metadata(),
+                None,
+                "cache invalidated by {}",
+                description
+            );
+        };
+        assert_invalidated_by("lockfile", &|| {
+            fs::write(workspace_root.join("Cargo.lock"), "").expect("lockfile written");
+        });
+        assert_invalidated_by("Cargo config", &|| {
+            fs::create_dir_all(workspace_root.join(".cargo")).expect(".cargo created");
+            fs::write(workspace_root.join(".cargo/config.toml"), "").expect("config written");
+        });
+        assert_invalidated_by("new package matching a member glob", &|| {
+            fs::create_dir(workspace_root.join("crates/b")).expect("package dir created");
+        });
+    }
+
+    #[test]
+    fn list_output_roundtrip() {
+        let store_dir = tempfile::tempdir().expect("tempdir created");
+        let store_dir = Utf8Path::from_path(store_dir.path()).expect("tempdir path is UTF-8");
+        let cache = NextestCache::new(store_dir);
+
+        let binary_path = store_dir.join("binary");
+        assert_eq!(
+            cache.get_list_output(&binary_path, "terse"),
+            None,
+            "nothing cached"
+        );
+
+        fs::write(&binary_path, "contents").expect("binary written");
+        cache.put_list_output(&binary_path, "terse", "test_a: test\n", "test_b: test\n");
+        assert_eq!(
+            cache.get_list_output(&binary_path, "terse"),
+            Some(("test_a: test\n".to_owned(), "test_b: test\n".to_owned())),
+            "cached output returned"
+        );
+
+        fs::write(&binary_path, "new contents").expect("binary rewritten");
+        assert_eq!(
+            cache.get_list_output(&binary_path, "terse"),
+            None,
+            "cache invalidated on change"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Selecting tests based on which packages have changed since a Git revision.
+//!
+//! Files that differ from the revision are mapped to the workspace packages containing them. Those
+//! packages, along with any workspace packages that depend on them, are considered changed. Tests
+//! in other packages can be filtered out with [`TestFilterBuilder::set_changed_packages`].
+//!
+//! Changed files that aren't inside any workspace package, along with workspace-level files such
+//! as `Cargo.lock`, the workspace's `Cargo.toml`, `.cargo/config.toml` and `rust-toolchain.toml`,
+//! can affect how every package is built. They cause all workspace packages to be considered
+//! changed.
+//!
+//! [`TestFilterBuilder::set_changed_packages`]: crate::test_filter::TestFilterBuilder::set_changed_packages
+
+use crate::errors::ChangedSinceError;
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use duct::cmd;
+use guppy::{graph::PackageGraph, PackageId};
+use std::collections::{HashMap, HashSet};
+
+/// Files at the root of the workspace that affect how every package is built.
+const WORKSPACE_FILES: &[&str] = &[
+    "Cargo.lock",
+    "Cargo.toml",
+    "rust-toolchain",
+    "rust-toolchain.toml",
+];
+
+/// The set of workspace packages that have changed since a Git revision.
+#[derive(Clone, Debug, Default)]
+pub struct ChangedPackages {
+    package_ids: HashSet<PackageId>,
+}
+
+impl ChangedPackages {
+    /// Computes the workspace packages that have changed since the given Git revision.
+    ///
+    /// Both committed and uncommitted changes are considered, including untracked files that
+    /// aren't ignored.
+    pub fn since(graph: &PackageGraph, git_ref: &str) -> Result<Self, ChangedSinceError> {
+        let root = graph.workspace().root();
+        // --relative makes paths relative to the workspace root, and leaves out changes outside
+        // it.
+        let diff = run_git(
+            root,
+            &["diff", "--name-only", "--relative", "-z", git_ref, "--"],
+        )?;
+        let untracked = run_git(root, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+
+        let paths = diff
+            .split('\0')
+            .chain(untracked.split('\0'))
+            .filter(|path| !path.is_empty())
+            .map(Utf8Path::new);
+        Self::from_paths(graph, paths)
+    }
+
+    /// Computes the workspace packages that contain the given paths, relative to the workspace
+    /// root, along with the workspace packages that depend on them. If any of the paths aren't
+    /// within a workspace package, all workspace packages are considered changed.
+    pub fn from_paths<'a>(
+        graph: &PackageGraph,
+        paths: impl IntoIterator<Item = &'a Utf8Path>,
+    ) -> Result<Self, ChangedSinceError> {
+        let workspace = graph.workspace();
+        let root = workspace.root();
+
+        // Package directory -> package ID.
+        let package_dirs: HashMap<&Utf8Path, &PackageId> = workspace
+            .iter()
+            .filter_map(|package| Some((package.manifest_path().parent()?, package.id())))
+            .collect();
+
+        // The package graph is built with --no-deps, so it doesn't know about dependencies between
+        // workspace members. Read them out of the path dependencies in their manifests instead.
+        let workspace_deps = workspace_dependency_paths(root)?;
+        // Package ID -> IDs of workspace packages that depend on it.
+        let mut reverse_deps: HashMap<&PackageId, Vec<&PackageId>> = HashMap::new();
+        for package in workspace.iter() {
+            for dep_dir in path_dependencies(package.manifest_path(), &workspace_deps)? {
+                if let Some(dep_id) = package_dirs.get(dep_dir.as_path()) {
+                    reverse_deps.entry(dep_id).or_default().push(package.id());
+                }
+            }
+        }
+
+        // A path belongs to the package with the closest enclosing directory. Workspace-level files
+        // belong to every package, even if the workspace root is itself a package.
+        let mut to_visit = vec![];
+        for path in paths {
+            let workspace_file =
+                WORKSPACE_FILES.iter().any(|file| path == *file) || path.starts_with(".cargo");
+            match root
+                .join(path)
+                .ancestors()
+                .find_map(|ancestor| package_dirs.get(ancestor).copied())
+                .filter(|_| !workspace_file)
+            {
+                Some(package_id) => to_visit.push(package_id),
+                None => {
+                    return Ok(workspace
+                        .iter()
+                        .map(|package| package.id().clone())
+                        .collect())
+                }
+            }
+        }
+        let mut changed = HashSet::new();
+        while let Some(package_id) = to_visit.pop() {
+            if changed.insert(package_id) {
+                to_visit.extend(reverse_deps.get(package_id).into_iter().flatten());
+            }
+        }
+
+        Ok(changed.into_iter().cloned().collect())
+    }
+
+    /// Returns true if the package with the given ID has changed.
+    pub fn contains(&self, package_id: &PackageId) -> bool {
+        self.package_ids.contains(package_id)
+    }
+
+    /// Returns the number of changed packages.
+    pub fn len(&self) -> usize {
+        self.package_ids.len()
+    }
+
+    /// Returns true if no packages have changed.
+    pub fn is_empty(&self) -> bool {
+        self.package_ids.is_empty()
+    }
+
+    /// Iterates over the IDs of changed packages, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = &PackageId> + '_ {
+        self.package_ids.iter()
+    }
+}
+
+impl FromIterator<PackageId> for ChangedPackages {
+    fn from_iter<I: IntoIterator<Item = PackageId>>(iter: I) -> Self {
+        Self {
+            package_ids: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Returns the directories of the path dependencies declared in `[workspace.dependencies]`, keyed
+/// by name, so that packages that inherit them can be resolved.
+fn workspace_dependency_paths(
+    root: &Utf8Path,
+) -> Result<HashMap<String, Utf8PathBuf>, ChangedSinceError> {
+    let manifest = read_manifest(&root.join("Cargo.toml"))?;
+    let deps = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .and_then(|deps| deps.as_table());
+    Ok(deps
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, dep)| {
+            let path = dep.get("path")?.as_str()?;
+            Some((name.clone(), normalize(&root.join(path))))
+        })
+        .collect())
+}
+
+/// Returns the directories of the path dependencies of the package with the given manifest, of
+/// every kind and for every platform.
+fn path_dependencies(
+    manifest_path: &Utf8Path,
+    workspace_deps: &HashMap<String, Utf8PathBuf>,
+) -> Result<Vec<Utf8PathBuf>, ChangedSinceError> {
+    let manifest = read_manifest(manifest_path)?;
+    let package_dir = manifest_path.parent().expect("manifest path has a parent");
+    let platform_tables = manifest
+        .get("target")
+        .and_then(|targets| targets.as_table())
+        .into_iter()
+        .flat_map(|targets| targets.values());
+    let dep_tables = std::iter::once(&manifest)
+        .chain(platform_tables)
+        .flat_map(|table| {
+            [
+                "dependencies",
+                "dev-dependencies",
+                "dev_dependencies",
+                "build-dependencies",
+                "build_dependencies",
+            ]
+            .into_iter()
+            .filter_map(move |key| table.get(key)?.as_table())
+        });
+    let mut dep_dirs = vec![];
+    for (name, dep) in dep_tables.flatten() {
+        if let Some(path) = dep.get("path").and_then(|path| path.as_str()) {
+            dep_dirs.push(normalize(&package_dir.join(path)));
+        } else if dep
+            .get("workspace")
+            .and_then(|workspace| workspace.as_bool())
+            == Some(true)
+        {
+            dep_dirs.extend(workspace_deps.get(name).cloned());
+        }
+    }
+    Ok(dep_dirs)
+}
+
+fn read_manifest(path: &Utf8Path) -> Result<toml::Value, ChangedSinceError> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|error| ChangedSinceError::ManifestRead {
+            path: path.to_owned(),
+            error,
+        })?;
+    toml::from_str(&contents).map_err(|error| ChangedSinceError::ManifestParse {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+/// Removes `.` and `..` components from a path, without looking at the file system.
+fn normalize(path: &Utf8Path) -> Utf8PathBuf {
+    let mut normalized = Utf8PathBuf::new();
+    for component in path.components() {
+        match component {
+            Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn run_git(dir: &Utf8Path, args: &[&str]) -> Result<String, ChangedSinceError> {
+    cmd("git", args)
+        .dir(dir)
+        .stdout_capture()
+        .read()
+        .map_err(|error| ChangedSinceError::git(format!("git {}", args.join(" ")), dir, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_paths() {
+        let graph = guppy::MetadataCommand::new()
+            .manifest_path(concat!(env!("CARGO_MANIFEST_DIR"), "/../../Cargo.toml"))
+            .no_deps()
+            .build_graph()
+            .expect("package graph built");
+        let names = |changed: &ChangedPackages| {
+            let mut names: Vec<_> = changed
+                .iter()
+                .map(|id| {
+                    graph
+                        .metadata(id)
+                        .expect("changed package is in the graph")
+                        .name()
+                })
+                .collect();
+            names.sort_unstable();
+            names
+        };
+        let changed = |path: &str| {
+            ChangedPackages::from_paths(&graph, [Utf8Path::new(path)])
+                .expect("changed packages computed")
+        };
+
+        assert_eq!(
+            names(&changed("nextest/metadata/src/lib.rs")),
+            ["cargo-nextest", "nextest-metadata", "nextest-runner"],
+            "reverse dependencies of nextest-metadata are changed, transitively"
+        );
+        assert_eq!(
+            names(&changed("nextest/cargo-nextest/Cargo.toml")),
+            ["cargo-nextest"]
+        );
+
+        let all_packages = graph.workspace().iter().count();
+        for path in [
+            "Cargo.lock",
+            "Cargo.toml",
+            ".cargo/config.toml",
+            "README.md",
+        ] {
+            assert_eq!(
+                changed(path).len(),
+                all_packages,
+                "{} is outside packages, so all packages are changed",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(
+            normalize(Utf8Path::new("/ws/nextest/runner/../metadata/./src")),
+            "/ws/nextest/metadata/src"
+        );
+    }
+}
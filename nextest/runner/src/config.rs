@@ -4,13 +4,50 @@
 //! Configuration support for nextest.
 
 use crate::{
-    errors::{ConfigParseError, ProfileNotFound},
+    container::ContainerRule,
+    crash::{CoreDumps, CrashSymbolizer},
+    custom_reporter::{CustomReporterConfig, ReportersConfig},
+    disk_usage::{format_bytes, DiskQuota},
+    errors::{
+        ConfigParseError, MissingCwdError, MultiplexParseError, MultiplexRuleError,
+        ProfileNotFound, ShowConfigError,
+    },
+    fake_time::FakeTimeSettings,
+    filter_expr::{FilterExpr, TestQuery},
+    line_buffering::LineBuffering,
+    llvm_coverage::CoverageSettings,
+    other_tests::OtherTests,
+    output_filter::OutputFilter,
+    output_sanitizer::OutputSanitizer,
+    quarantine::QuarantineConfig,
     reporter::{StatusLevel, TestOutputDisplay},
+    required_binaries::RequiredBinary,
+    retention::RetentionPolicy,
+    selector::SelectorConfig,
+    target_runner::TargetRunnerRule,
+    test_dependencies::DependencyRule,
+    test_groups::TestGroupConfig,
+    test_list::{OutputFormat, TestVariant},
+    valgrind::Valgrind,
+    wasm::WasmRuntime,
 };
-use camino::{Utf8Path, Utf8PathBuf};
-use config::{Config, File, FileFormat};
-use serde::Deserialize;
-use std::{collections::HashMap, time::Duration};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use config::{Config, ConfigError, File, FileFormat};
+use nextest_metadata::{
+    BuildPlatform, ConfigSummary, DependencyRuleSummary, FilterSummary, MultiplexRuleSummary,
+    OverrideSummary, ProfileSummary, SlowTimeoutSummary, StoreRetentionSummary, StoreSummary,
+    TestGroupSummary, ThreadsRequiredSummary, TimeLimitSummary,
+};
+use semver::Version;
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+    time::Duration,
+};
+use target_spec::{Platform, TargetFeatures, TargetSpec};
 
 /// Overall configuration for nextest.
 ///
@@ -44,6 +81,9 @@ impl NextestConfig {
     /// The name of the default profile.
     pub const DEFAULT_PROFILE: &'static str = "default";
 
+    /// The name of the profile used by default when running tests under Miri.
+    pub const DEFAULT_MIRI_PROFILE: &'static str = "default-miri";
+
     /// Reads the nextest config from the given file, or if not specified from `.config/nextest.toml`
     /// in the given directory.
     ///
@@ -55,9 +95,15 @@ impl NextestConfig {
     ) -> Result<Self, ConfigParseError> {
         let workspace_root = workspace_root.into();
         let (config_file, config) = Self::read_from_sources(&workspace_root, config_file)?;
-        let inner = config
+        let inner: NextestConfigImpl = config
             .try_into()
-            .map_err(|err| ConfigParseError::new(config_file, err))?;
+            .map_err(|err| ConfigParseError::new(&config_file, err))?;
+        inner
+            .profiles
+            .check_inheritance()
+            .and_then(|()| inner.profiles.check_output_dirs())
+            .and_then(|()| inner.check_test_groups())
+            .map_err(|message| ConfigParseError::new(config_file, ConfigError::Message(message)))?;
         Ok(Self {
             workspace_root,
             inner,
@@ -74,12 +120,120 @@ impl NextestConfig {
         }
     }
 
+    /// Returns the absolute directory nextest-related files are written to.
+    ///
+    /// Profile-specific storage is written to subdirectories of this directory.
+    pub fn store_dir(&self) -> Utf8PathBuf {
+        self.workspace_root.join(&self.inner.store.dir)
+    }
+
+    /// Returns the limits on the runs kept in the store.
+    ///
+    /// See [`retention`](crate::retention) for more.
+    pub fn store_retention(&self) -> &RetentionPolicy {
+        &self.inner.store.retention
+    }
+
+    /// Returns the suites of tests that aren't written in Rust, in the order they're specified.
+    ///
+    /// See [`other_tests`](crate::other_tests) for more.
+    pub fn other_tests(&self) -> &[OtherTests] {
+        &self.inner.other_tests
+    }
+
+    /// Returns the workspace binaries that tests need in order to run.
+    ///
+    /// See [`required_binaries`](crate::required_binaries) for more.
+    pub fn required_binaries(&self) -> &[RequiredBinary] {
+        &self.inner.required_binaries
+    }
+
+    /// Returns the custom reporters started for each run, keyed by name.
+    ///
+    /// See [`custom_reporter`](crate::custom_reporter) for more.
+    pub fn custom_reporters(&self) -> &BTreeMap<String, CustomReporterConfig> {
+        &self.inner.reporters.custom
+    }
+
+    /// Returns the external selectors that can be used to choose the tests to run, keyed by name.
+    ///
+    /// See [`selector`](crate::selector) for more.
+    pub fn selectors(&self) -> &BTreeMap<String, SelectorConfig> {
+        &self.inner.selectors
+    }
+
     /// Returns the profile with the given name, or an error if a profile was specified but not
     /// found.
     pub fn profile(&self, name: impl AsRef<str>) -> Result<NextestProfile<'_>, ProfileNotFound> {
         self.make_profile(name.as_ref())
     }
 
+    /// Returns the names of all profiles, including the default profile, in sorted order.
+    pub fn profile_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.inner.profiles.all_profiles().collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the fully-resolved config, in the form output by `cargo nextest show-config`.
+    pub fn to_summary(&self) -> ConfigSummary {
+        let retention = self.store_retention();
+        let profiles = self
+            .profile_names()
+            .into_iter()
+            .map(|name| {
+                let profile = self
+                    .make_profile(name)
+                    .expect("profile names are all known");
+                (name.to_owned(), profile.to_summary())
+            })
+            .collect();
+        ConfigSummary {
+            workspace_root: self.workspace_root.clone(),
+            store: StoreSummary {
+                dir: self.store_dir(),
+                retention: StoreRetentionSummary {
+                    max_runs: retention.max_runs(),
+                    max_size: retention.max_size().map(|max_size| max_size.bytes()),
+                    max_age: retention.max_age().map(|max_age| max_age.as_secs_f64()),
+                },
+            },
+            test_groups: self
+                .inner
+                .test_groups
+                .iter()
+                .map(|(name, group)| {
+                    let summary = TestGroupSummary {
+                        setup: group.setup().map(|setup| setup.to_vec()),
+                        teardown: group.teardown().map(|teardown| teardown.to_vec()),
+                        timeout: group.timeout().map(|timeout| timeout.as_secs_f64()),
+                    };
+                    (name.clone(), summary)
+                })
+                .collect(),
+            profiles,
+        }
+    }
+
+    /// Writes out the fully-resolved config in the given format.
+    ///
+    /// The serializable formats write a [`ConfigSummary`].
+    pub fn write_summary(
+        &self,
+        output_format: OutputFormat,
+        writer: impl Write,
+    ) -> Result<(), ShowConfigError> {
+        let summary = self.to_summary();
+        match output_format {
+            OutputFormat::Plain => {
+                write_summary_plain(&summary, writer).map_err(ShowConfigError::Write)
+            }
+            OutputFormat::Serializable(format) => format
+                .to_writer(&summary, writer)
+                .map_err(ShowConfigError::Json),
+        }
+    }
+
     // ---
     // Helper methods
     // ---
@@ -118,102 +272,763 @@ impl NextestConfig {
     }
 
     fn make_profile(&self, name: &str) -> Result<NextestProfile<'_>, ProfileNotFound> {
-        let custom_profile = self.inner.profiles.get(name)?;
+        let custom_profiles = self.inner.profiles.get(name)?;
 
         // The profile was found: construct the NextestProfile.
-        let mut store_dir = self.workspace_root.join(&self.inner.store.dir);
+        let mut store_dir = self.store_dir();
         store_dir.push(name);
 
+        // Overrides are evaluated against the platform nextest was built for, unless told
+        // otherwise.
+        let host_platform = Platform::current().ok();
+        let host_triple = host_platform
+            .as_ref()
+            .map(|platform| platform.triple_str().to_owned())
+            .unwrap_or_default();
+
         Ok(NextestProfile {
+            name: name.to_owned(),
+            workspace_root: &self.workspace_root,
             store_dir,
+            host_triple: host_triple.clone(),
+            host_platform: host_platform.clone(),
+            target_triple: host_triple,
+            target_platform: host_platform,
             default_profile: &self.inner.profiles.default,
-            custom_profile,
+            custom_profiles,
+            test_groups: &self.inner.test_groups,
         })
     }
 }
 
 /// A configuration profile for nextest. Contains most configuration used by the nextest runner.
 ///
+/// Custom profiles inherit settings from the profile named by their `inherits` key, or from the
+/// default profile if `inherits` isn't specified.
+///
 /// Returned by [`NextestConfig::profile`].
 #[derive(Clone, Debug)]
 pub struct NextestProfile<'cfg> {
+    name: String,
+    workspace_root: &'cfg Utf8Path,
     store_dir: Utf8PathBuf,
+    // The platforms the `platform` of overrides is evaluated against, for binaries built for the
+    // host and the target respectively.
+    host_triple: String,
+    host_platform: Option<Platform>,
+    target_triple: String,
+    target_platform: Option<Platform>,
     default_profile: &'cfg DefaultProfileImpl,
-    custom_profile: Option<&'cfg CustomProfileImpl>,
+    // The custom profile followed by the profiles it inherits from, in order.
+    custom_profiles: Vec<&'cfg CustomProfileImpl>,
+    test_groups: &'cfg BTreeMap<String, TestGroupConfig>,
 }
 
 impl<'cfg> NextestProfile<'cfg> {
+    /// Returns the name of this profile.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Returns the absolute profile-specific store directory.
     pub fn store_dir(&self) -> &Utf8Path {
         &self.store_dir
     }
 
+    /// Namespaces the store directory by the given nesting level, so that nested nextest
+    /// invocations don't overwrite the outer invocation's reports.
+    ///
+    /// Has no effect if `nesting_level` is 0. See [`nesting`](crate::nesting) for more.
+    pub fn with_nesting_level(mut self, nesting_level: usize) -> Self {
+        if nesting_level > 0 {
+            self.store_dir.push(format!("nested-{}", nesting_level));
+        }
+        self
+    }
+
+    /// Evaluates the `platform` of overrides for binaries built for the host, such as proc-macro
+    /// tests, against the given host triple rather than the platform nextest was built for.
+    ///
+    /// Has no effect if `host_triple` is `None`. Triples unknown to nextest only match overrides
+    /// whose `platform` is that exact triple.
+    pub fn with_host_triple(mut self, host_triple: Option<&str>) -> Self {
+        if let Some(host_triple) = host_triple {
+            self.host_triple = host_triple.to_owned();
+            self.host_platform =
+                Platform::new(host_triple.to_owned(), TargetFeatures::Unknown).ok();
+        }
+        self
+    }
+
+    /// Evaluates the `platform` of overrides for binaries built for the target against the given
+    /// target triple, rather than the platform nextest was built for.
+    ///
+    /// Has no effect if `target_triple` is `None`. Triples unknown to nextest only match overrides
+    /// whose `platform` is that exact triple.
+    pub fn with_target_triple(mut self, target_triple: Option<&str>) -> Self {
+        if let Some(target_triple) = target_triple {
+            self.target_triple = target_triple.to_owned();
+            self.target_platform =
+                Platform::new(target_triple.to_owned(), TargetFeatures::Unknown).ok();
+        }
+        self
+    }
+
+    /// Returns the triple the `platform` of overrides is evaluated against for binaries built for
+    /// the given platform.
+    pub fn triple_for(&self, build_platform: BuildPlatform) -> &str {
+        match build_platform {
+            BuildPlatform::Host => &self.host_triple,
+            BuildPlatform::Target => &self.target_triple,
+        }
+    }
+
     /// Returns the retry count for this profile.
     pub fn retries(&self) -> usize {
-        self.custom_profile
-            .map(|profile| profile.retries)
-            .flatten()
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.retries)
             .unwrap_or(self.default_profile.retries)
     }
 
-    /// Returns the time after which tests are treated as slow for this profile.
-    pub fn slow_timeout(&self) -> Duration {
-        self.custom_profile
-            .map(|profile| profile.slow_timeout)
-            .flatten()
+    /// Returns when tests are reported as slow for this profile.
+    pub fn slow_timeout(&self) -> SlowTimeout {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.slow_timeout)
             .unwrap_or(self.default_profile.slow_timeout)
     }
 
+    /// Returns the duration predicted for tests without timing data from a previous run, when
+    /// enforcing a maximum duration for the run.
+    pub fn default_duration_estimate(&self) -> Duration {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.default_duration_estimate)
+            .unwrap_or(self.default_profile.default_duration_estimate)
+    }
+
+    /// Returns the WebAssembly runtime used to run tests for WASI targets without a target runner.
+    ///
+    /// See [`wasm`](crate::wasm) for more.
+    pub fn wasm_runtime(&self) -> WasmRuntime {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.wasm_runtime)
+            .unwrap_or(self.default_profile.wasm_runtime)
+    }
+
+    /// Returns what to do if the directory tests in a package are run in doesn't exist, with paths
+    /// made absolute.
+    pub fn missing_cwd(&self) -> MissingCwd {
+        let missing_cwd = self
+            .custom_profiles
+            .iter()
+            .find_map(|profile| profile.missing_cwd.as_ref())
+            .unwrap_or(&self.default_profile.missing_cwd);
+        match missing_cwd {
+            MissingCwd::Fail => MissingCwd::Fail,
+            MissingCwd::Dir(dir) => MissingCwd::Dir(self.workspace_root.join(dir)),
+            MissingCwd::FixturesRoot(root) => {
+                MissingCwd::FixturesRoot(self.workspace_root.join(root))
+            }
+        }
+    }
+
     /// Returns the test status level.
     pub fn status_level(&self) -> StatusLevel {
-        self.custom_profile
-            .map(|profile| profile.status_level)
-            .flatten()
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.status_level)
             .unwrap_or(self.default_profile.status_level)
     }
 
+    /// Returns the level of test statuses listed again at the end of the run.
+    pub fn final_status_level(&self) -> StatusLevel {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.final_status_level)
+            .unwrap_or(self.default_profile.final_status_level)
+    }
+
     /// Returns the failure output config for this profile.
     pub fn failure_output(&self) -> TestOutputDisplay {
-        self.custom_profile
-            .map(|profile| profile.failure_output)
-            .flatten()
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.failure_output)
             .unwrap_or(self.default_profile.failure_output)
     }
 
     /// Returns the failure output config for this profile.
     pub fn success_output(&self) -> TestOutputDisplay {
-        self.custom_profile
-            .map(|profile| profile.success_output)
-            .flatten()
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.success_output)
             .unwrap_or(self.default_profile.success_output)
     }
 
+    /// Returns the number of lines of output printed for each test at the end of the run, if the
+    /// output is cut short.
+    pub fn final_output_lines(&self) -> Option<usize> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.final_output_lines)
+            .or(self.default_profile.final_output_lines)
+    }
+
+    /// Returns the minimum time between the outputs of tests printed with
+    /// [`TestOutputDisplay::Streamed`].
+    pub fn streamed_output_interval(&self) -> Duration {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.streamed_output_interval)
+            .unwrap_or(self.default_profile.streamed_output_interval)
+    }
+
+    /// Returns whether assertion failures in test output are highlighted for this profile.
+    pub fn highlight_diffs(&self) -> bool {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.highlight_diffs)
+            .unwrap_or(self.default_profile.highlight_diffs)
+    }
+
     /// Returns the fail-fast config for this profile.
     pub fn fail_fast(&self) -> bool {
-        self.custom_profile
-            .map(|profile| profile.fail_fast)
-            .flatten()
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.fail_fast)
             .unwrap_or(self.default_profile.fail_fast)
     }
 
+    /// Returns the order in which tests are started for this profile.
+    pub fn test_order(&self) -> TestOrder {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.test_order)
+            .unwrap_or(self.default_profile.test_order)
+    }
+
+    /// Returns the filter that tests must match to be run with this profile, unless
+    /// `--ignore-default-filter` is passed, if any.
+    ///
+    /// This is applied in addition to the filters specified on the command line.
+    pub fn default_filter(&self) -> Option<&'cfg FilterExpr> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.default_filter.as_ref())
+            .or(self.default_profile.default_filter.as_ref())
+    }
+
+    /// Returns the multiplex rules for this profile, in order of precedence.
+    ///
+    /// Rules specified in a custom profile take precedence over rules in the profiles it inherits
+    /// from.
+    pub fn multiplex_rules(&self) -> impl Iterator<Item = &'cfg MultiplexRule> + 'cfg {
+        self.custom_profiles
+            .clone()
+            .into_iter()
+            .flat_map(|profile| profile.multiplex.iter())
+            .chain(self.default_profile.multiplex.iter())
+    }
+
+    /// Returns the rules for the dependencies between tests, from this profile and the profiles it
+    /// inherits from. All of them apply.
+    ///
+    /// See [`test_dependencies`](crate::test_dependencies) for more.
+    pub fn dependency_rules(&self) -> impl Iterator<Item = &'cfg DependencyRule> + 'cfg {
+        self.custom_profiles
+            .clone()
+            .into_iter()
+            .flat_map(|profile| profile.dependencies.iter())
+            .chain(self.default_profile.dependencies.iter())
+    }
+
+    /// Returns the first multiplex rule that matches the given test, if any.
+    pub fn multiplex_for(&self, query: &TestQuery<'_>) -> Option<&'cfg MultiplexRule> {
+        self.settings_for(query).multiplex()
+    }
+
+    /// Returns the overrides for binaries built for the given platform, in order of precedence.
+    ///
+    /// Overrides specified in a custom profile take precedence over overrides in the profiles it
+    /// inherits from. Overrides whose `platform` doesn't match the [host](Self::with_host_triple)
+    /// or [target](Self::with_target_triple) triple, as appropriate, are left out.
+    pub fn overrides(
+        &self,
+        build_platform: BuildPlatform,
+    ) -> impl Iterator<Item = &'cfg ProfileOverride> + 'cfg {
+        let (triple, platform) = match build_platform {
+            BuildPlatform::Host => (self.host_triple.clone(), self.host_platform.clone()),
+            BuildPlatform::Target => (self.target_triple.clone(), self.target_platform.clone()),
+        };
+        self.all_overrides().filter(move |rule| {
+            rule.platform
+                .as_ref()
+                .is_none_or(|spec| spec.matches(&triple, platform.as_ref()))
+        })
+    }
+
+    /// Returns the target runner rules for this profile, in order of precedence.
+    ///
+    /// Rules specified in a custom profile take precedence over rules in the profiles it inherits
+    /// from. See [`target_runner`](crate::target_runner) for more.
+    pub fn target_runner_rules(
+        &self,
+    ) -> impl Iterator<Item = &'cfg TargetRunnerRule> + Clone + 'cfg {
+        self.custom_profiles
+            .clone()
+            .into_iter()
+            .flat_map(|profile| profile.target_runner.iter())
+            .chain(self.default_profile.target_runner.iter())
+    }
+
+    /// Returns the container rules for this profile, in order of precedence.
+    ///
+    /// Rules specified in a custom profile take precedence over rules in the profiles it inherits
+    /// from. See [`container`](crate::container) for more.
+    pub fn container_rules(&self) -> impl Iterator<Item = &'cfg ContainerRule> + 'cfg {
+        self.custom_profiles
+            .clone()
+            .into_iter()
+            .flat_map(|profile| profile.container.iter())
+            .chain(self.default_profile.container.iter())
+    }
+
+    /// Returns the workspace root, which relative paths in the config are relative to.
+    pub fn workspace_root(&self) -> &'cfg Utf8Path {
+        self.workspace_root
+    }
+
+    /// Returns the settings that apply to the given test, resolved from this profile and the
+    /// overrides and rules that match the test.
+    ///
+    /// This is how the test runner resolves settings for each test, so it can be used to explain
+    /// how a test will be run.
+    pub fn settings_for(&self, query: &TestQuery<'_>) -> TestSettings<'cfg> {
+        TestSettings::resolve(
+            self.retries(),
+            self.slow_timeout(),
+            self.overrides(query.platform),
+            self.container_rules(),
+            self.multiplex_rules(),
+            query,
+        )
+    }
+
+    /// Returns the locale and time zone settings forced for the given test, from the first override
+    /// that matches it and specifies `locale`.
+    pub fn locale_for(&self, query: &TestQuery<'_>) -> Option<&'cfg LocaleSettings> {
+        self.settings_for(query).locale()
+    }
+
+    /// Returns the fake time settings for the given test, from the first override that matches it
+    /// and specifies `fake-time`.
+    ///
+    /// See [`fake_time`](crate::fake_time) for more.
+    pub fn fake_time_for(&self, query: &TestQuery<'_>) -> Option<&'cfg FakeTimeSettings> {
+        self.settings_for(query).fake_time()
+    }
+
+    /// Returns the disk quota for the given test, from the first override that matches it and
+    /// specifies `disk-quota`.
+    ///
+    /// See [`disk_usage`](crate::disk_usage) for more.
+    pub fn disk_quota_for(&self, query: &TestQuery<'_>) -> Option<DiskQuota> {
+        self.settings_for(query).disk_quota()
+    }
+
+    /// Returns the time limit for the given test, from the first override that matches it and
+    /// specifies `time-limit`.
+    pub fn time_limit_for(&self, query: &TestQuery<'_>) -> Option<TimeLimit> {
+        self.settings_for(query).time_limit()
+    }
+
+    /// Returns the test group the given test is in, from the first override that matches it and
+    /// specifies `test-group`, or `None` if it isn't in a group.
+    ///
+    /// See [`test_groups`](crate::test_groups) for more.
+    pub fn test_group_for(&self, query: &TestQuery<'_>) -> Option<&'cfg str> {
+        self.settings_for(query).test_group()
+    }
+
+    /// Returns the configs of all test groups, keyed by name.
+    ///
+    /// See [`test_groups`](crate::test_groups) for more.
+    pub fn test_groups(&self) -> &'cfg BTreeMap<String, TestGroupConfig> {
+        self.test_groups
+    }
+
+    /// Returns the absolute paths to the dotenv files loaded for each run, in the order they're
+    /// applied.
+    ///
+    /// See [`env_file`](crate::env_file) for more.
+    pub fn env_files(&self) -> impl Iterator<Item = Utf8PathBuf> + 'cfg {
+        let workspace_root = self.workspace_root;
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.env_files.as_ref())
+            .unwrap_or(&self.default_profile.env_files)
+            .iter()
+            .map(move |path| workspace_root.join(path))
+    }
+
+    /// Returns the command that the output of failed tests is piped through, if any.
+    ///
+    /// See [`output_filter`](crate::output_filter) for more.
+    pub fn output_filter(&self) -> Option<&'cfg OutputFilter> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.output_filter.as_ref())
+            .or(self.default_profile.output_filter.as_ref())
+    }
+
+    /// Returns the options test output is sanitized with, if any.
+    ///
+    /// See [`output_sanitizer`](crate::output_sanitizer) for more.
+    pub fn output_sanitizer(&self) -> Option<&'cfg OutputSanitizer> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.output_sanitizer.as_ref())
+            .or(self.default_profile.output_sanitizer.as_ref())
+    }
+
+    /// Returns the absolute directory the output of each test is written to, if it is.
+    ///
+    /// See [`RunStore::test_output_path`](crate::run_store::RunStore::test_output_path) for more.
+    pub fn output_dir(&self) -> Option<Utf8PathBuf> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.store.output_dir.as_deref())
+            .or(self.default_profile.store.output_dir.as_deref())
+            .map(|output_dir| self.store_dir.join(output_dir))
+    }
+
+    /// Returns the command run to produce a backtrace after a test aborts, if any.
+    ///
+    /// See [`crash`](crate::crash) for more.
+    pub fn crash_symbolizer(&self) -> Option<&'cfg CrashSymbolizer> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.crash_symbolizer.as_ref())
+            .or(self.default_profile.crash_symbolizer.as_ref())
+    }
+
+    /// Returns whether tests can dump core, and where their dumps are collected from, if
+    /// configured.
+    ///
+    /// See [`crash`](crate::crash) for more.
+    pub fn core_dumps(&self) -> Option<&'cfg CoreDumps> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.core_dumps.as_ref())
+            .or(self.default_profile.core_dumps.as_ref())
+    }
+
+    /// Returns how tests are made to line-buffer their standard output, if configured.
+    ///
+    /// See [`line_buffering`](crate::line_buffering) for more.
+    pub fn line_buffering(&self) -> Option<&'cfg LineBuffering> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.line_buffering.as_ref())
+            .or(self.default_profile.line_buffering.as_ref())
+    }
+
+    /// Returns how tests are run under valgrind, if configured.
+    ///
+    /// See [`valgrind`](crate::valgrind) for more.
+    pub fn valgrind(&self) -> Option<&'cfg Valgrind> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.valgrind.as_ref())
+            .or(self.default_profile.valgrind.as_ref())
+    }
+
+    /// Returns the tests whose failures don't fail the run, if configured.
+    ///
+    /// See [`quarantine`](crate::quarantine) for more.
+    pub fn quarantine(&self) -> Option<&'cfg QuarantineConfig> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.quarantine.as_ref())
+            .or(self.default_profile.quarantine.as_ref())
+    }
+
+    /// Returns how coverage is collected for runs with `--coverage`, if configured.
+    ///
+    /// See [`llvm_coverage`](crate::llvm_coverage) for more.
+    pub fn coverage(&self) -> Option<&'cfg CoverageSettings> {
+        self.custom_profiles
+            .iter()
+            .find_map(|profile| profile.coverage.as_ref())
+            .or(self.default_profile.coverage.as_ref())
+    }
+
     /// Returns the JUnit configuration for this profile.
     pub fn junit(&self) -> Option<NextestJunitConfig<'cfg>> {
         let path = self
-            .custom_profile
-            .map(|profile| &profile.junit.path)
-            .unwrap_or(&self.default_profile.junit.path)
-            .as_deref();
+            .custom_profiles
+            .iter()
+            .find_map(|profile| profile.junit.path.as_deref())
+            .or(self.default_profile.junit.path.as_deref());
 
         path.map(|path| {
             let path = self.store_dir.join(path);
             let report_name = self
-                .custom_profile
-                .map(|profile| profile.junit.report_name.as_deref())
-                .flatten()
+                .custom_profiles
+                .iter()
+                .find_map(|profile| profile.junit.report_name.as_deref())
                 .unwrap_or(&self.default_profile.junit.report_name);
-            NextestJunitConfig { path, report_name }
+            let report_skipped = self
+                .custom_profiles
+                .iter()
+                .find_map(|profile| profile.junit.report_skipped)
+                .unwrap_or(self.default_profile.junit.report_skipped);
+            NextestJunitConfig {
+                path,
+                report_name,
+                report_skipped,
+            }
+        })
+    }
+
+    /// Returns the metrics configuration for this profile, or `None` if metrics are neither
+    /// written out nor pushed.
+    pub fn metrics(&self) -> Option<NextestMetricsConfig<'cfg>> {
+        let path = self
+            .custom_profiles
+            .iter()
+            .find_map(|profile| profile.metrics.path.as_deref())
+            .or(self.default_profile.metrics.path.as_deref());
+        let pushgateway = self
+            .custom_profiles
+            .iter()
+            .find_map(|profile| profile.metrics.pushgateway.as_deref())
+            .or(self.default_profile.metrics.pushgateway.as_deref());
+        if path.is_none() && pushgateway.is_none() {
+            return None;
+        }
+
+        let job = self
+            .custom_profiles
+            .iter()
+            .find_map(|profile| profile.metrics.job.as_deref())
+            .unwrap_or(&self.default_profile.metrics.job);
+        Some(NextestMetricsConfig {
+            path: path.map(|path| self.store_dir.join(path)),
+            pushgateway,
+            job,
         })
     }
+
+    /// Returns this profile with every setting resolved, in the form output by
+    /// `cargo nextest show-config`.
+    pub fn to_summary(&self) -> ProfileSummary {
+        let slow_timeout = self.slow_timeout();
+        ProfileSummary {
+            store_dir: self.store_dir.clone(),
+            retries: self.retries(),
+            slow_timeout: SlowTimeoutSummary {
+                after: slow_timeout.after().as_secs_f64(),
+                period: slow_timeout.period().map(|period| period.as_secs_f64()),
+            },
+            default_duration_estimate: self.default_duration_estimate().as_secs_f64(),
+            status_level: self.status_level().to_string(),
+            final_status_level: self.final_status_level().to_string(),
+            failure_output: self.failure_output().to_string(),
+            success_output: self.success_output().to_string(),
+            fail_fast: self.fail_fast(),
+            test_order: match self.test_order() {
+                TestOrder::Binary => "binary".to_owned(),
+                TestOrder::RoundRobin => "round-robin".to_owned(),
+            },
+            default_filter: self.default_filter().map(filter_summary),
+            env_files: self.env_files().collect(),
+            junit_path: self.junit().map(|junit| junit.path().to_owned()),
+            overrides: self
+                .all_overrides()
+                .filter(|rule| {
+                    [BuildPlatform::Host, BuildPlatform::Target]
+                        .into_iter()
+                        .any(|build_platform| self.override_applies(rule, build_platform))
+                })
+                .map(|rule| OverrideSummary {
+                    filter: filter_summary(rule.filter()),
+                    threads_required: rule.threads_required().map(|threads| match threads {
+                        ThreadsRequired::Count(count) => ThreadsRequiredSummary::Count(count),
+                        ThreadsRequired::NumCpus => ThreadsRequiredSummary::NumCpus,
+                    }),
+                    test_group: rule.test_group().map(|group| group.to_owned()),
+                    disk_quota: rule.disk_quota().map(|quota| quota.bytes()),
+                    time_limit: rule.time_limit().map(|time_limit| TimeLimitSummary {
+                        limit: time_limit.limit().as_secs_f64(),
+                        grace: time_limit.grace().map(|grace| grace.as_secs_f64()),
+                    }),
+                    expected_result: rule
+                        .expected_result()
+                        .map(|expected_result| expected_result.as_str().to_owned()),
+                    platform: rule.platform().map(|platform| platform.as_str().to_owned()),
+                    skip: rule.skip(),
+                })
+                .collect(),
+            multiplex: self
+                .multiplex_rules()
+                .map(|rule| MultiplexRuleSummary {
+                    filter: filter_summary(rule.filter()),
+                    env_var: rule.env_var().to_owned(),
+                    values: rule
+                        .variants()
+                        .map(|variant| variant.value.to_owned())
+                        .collect(),
+                })
+                .collect(),
+            dependencies: self
+                .dependency_rules()
+                .map(|rule| DependencyRuleSummary {
+                    filter: filter_summary(rule.filter()),
+                    depends_on: filter_summary(rule.depends_on()),
+                })
+                .collect(),
+        }
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    fn all_overrides(&self) -> impl Iterator<Item = &'cfg ProfileOverride> + 'cfg {
+        self.custom_profiles
+            .clone()
+            .into_iter()
+            .flat_map(|profile| profile.overrides.iter())
+            .chain(self.default_profile.overrides.iter())
+    }
+
+    fn override_applies(&self, rule: &ProfileOverride, build_platform: BuildPlatform) -> bool {
+        let platform = match build_platform {
+            BuildPlatform::Host => self.host_platform.as_ref(),
+            BuildPlatform::Target => self.target_platform.as_ref(),
+        };
+        rule.platform
+            .as_ref()
+            .is_none_or(|spec| spec.matches(self.triple_for(build_platform), platform))
+    }
+}
+
+fn write_summary_plain(summary: &ConfigSummary, mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "store: {}", summary.store.dir)?;
+    let retention = &summary.store.retention;
+    if let Some(max_runs) = retention.max_runs {
+        writeln!(writer, "  max-runs: {}", max_runs)?;
+    }
+    if let Some(max_size) = retention.max_size {
+        writeln!(writer, "  max-size: {}", format_bytes(max_size))?;
+    }
+    if let Some(max_age) = retention.max_age {
+        writeln!(writer, "  max-age: {}s", max_age)?;
+    }
+
+    for (name, group) in &summary.test_groups {
+        writeln!(writer, "test group {}:", name)?;
+        if let Some(setup) = &group.setup {
+            writeln!(writer, "  setup: {}", setup.join(" "))?;
+        }
+        if let Some(teardown) = &group.teardown {
+            writeln!(writer, "  teardown: {}", teardown.join(" "))?;
+        }
+        if let Some(timeout) = group.timeout {
+            writeln!(writer, "  timeout: {}s", timeout)?;
+        }
+    }
+
+    for (name, profile) in &summary.profiles {
+        writeln!(writer, "profile {}:", name)?;
+        writeln!(writer, "  store-dir: {}", profile.store_dir)?;
+        writeln!(writer, "  retries: {}", profile.retries)?;
+        write!(writer, "  slow-timeout: {}s", profile.slow_timeout.after)?;
+        if let Some(period) = profile.slow_timeout.period {
+            write!(writer, " (then every {}s)", period)?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "  status-level: {}", profile.status_level)?;
+        writeln!(
+            writer,
+            "  final-status-level: {}",
+            profile.final_status_level
+        )?;
+        writeln!(writer, "  failure-output: {}", profile.failure_output)?;
+        writeln!(writer, "  success-output: {}", profile.success_output)?;
+        writeln!(writer, "  fail-fast: {}", profile.fail_fast)?;
+        writeln!(writer, "  test-order: {}", profile.test_order)?;
+        if let Some(default_filter) = &profile.default_filter {
+            writeln!(writer, "  default-filter: {}", default_filter.compiled)?;
+        }
+        for env_file in &profile.env_files {
+            writeln!(writer, "  env-file: {}", env_file)?;
+        }
+        if let Some(junit_path) = &profile.junit_path {
+            writeln!(writer, "  junit: {}", junit_path)?;
+        }
+        for rule in &profile.overrides {
+            writeln!(writer, "  override {}:", rule.filter.compiled)?;
+            if let Some(platform) = &rule.platform {
+                writeln!(writer, "    platform: {}", platform)?;
+            }
+            if let Some(skip) = rule.skip {
+                writeln!(writer, "    skip: {}", skip)?;
+            }
+            match rule.threads_required {
+                Some(ThreadsRequiredSummary::Count(count)) => {
+                    writeln!(writer, "    threads-required: {}", count)?
+                }
+                Some(ThreadsRequiredSummary::NumCpus) => {
+                    writeln!(writer, "    threads-required: num-cpus")?
+                }
+                None => {}
+            }
+            if let Some(test_group) = &rule.test_group {
+                writeln!(writer, "    test-group: {}", test_group)?;
+            }
+            if let Some(disk_quota) = rule.disk_quota {
+                writeln!(writer, "    disk-quota: {}", format_bytes(disk_quota))?;
+            }
+            if let Some(time_limit) = &rule.time_limit {
+                write!(writer, "    time-limit: {}s", time_limit.limit)?;
+                if let Some(grace) = time_limit.grace {
+                    write!(writer, " (killed after another {}s)", grace)?;
+                }
+                writeln!(writer)?;
+            }
+            if let Some(expected_result) = &rule.expected_result {
+                writeln!(writer, "    expected-result: {}", expected_result)?;
+            }
+        }
+        for rule in &profile.multiplex {
+            writeln!(
+                writer,
+                "  multiplex {}: {}={}",
+                rule.filter.compiled,
+                rule.env_var,
+                rule.values.join("|")
+            )?;
+        }
+        for rule in &profile.dependencies {
+            writeln!(
+                writer,
+                "  dependency {}: depends on {}",
+                rule.filter.compiled, rule.depends_on.compiled
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn filter_summary(filter: &FilterExpr) -> FilterSummary {
+    FilterSummary {
+        expr: filter.as_str().to_owned(),
+        compiled: filter.compiled(),
+    }
 }
 
 /// JUnit configuration for nextest, returned by a [`NextestProfile`].
@@ -221,6 +1036,7 @@ impl<'cfg> NextestProfile<'cfg> {
 pub struct NextestJunitConfig<'cfg> {
     path: Utf8PathBuf,
     report_name: &'cfg str,
+    report_skipped: bool,
 }
 
 impl<'cfg> NextestJunitConfig<'cfg> {
@@ -233,90 +1049,1400 @@ impl<'cfg> NextestJunitConfig<'cfg> {
     pub fn report_name(&self) -> &'cfg str {
         self.report_name
     }
+
+    /// Returns true if tests skipped by the test filter should be included in the JUnit report.
+    ///
+    /// Tests that skip themselves at runtime are always included.
+    pub fn report_skipped(&self) -> bool {
+        self.report_skipped
+    }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct NextestConfigImpl {
-    store: StoreConfigImpl,
-    #[serde(rename = "profile")]
-    profiles: NextestProfilesImpl,
+/// Configuration for exporting metrics in the Prometheus text format, returned by a
+/// [`NextestProfile`].
+#[derive(Clone, Debug)]
+pub struct NextestMetricsConfig<'cfg> {
+    path: Option<Utf8PathBuf>,
+    pushgateway: Option<&'cfg str>,
+    job: &'cfg str,
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct StoreConfigImpl {
-    dir: Utf8PathBuf,
+impl<'cfg> NextestMetricsConfig<'cfg> {
+    /// Returns the absolute path metrics are written to at the end of a run, if any.
+    pub fn path(&self) -> Option<&Utf8Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the URL of the Prometheus Pushgateway metrics are pushed to at the end of a run, if
+    /// any.
+    pub fn pushgateway(&self) -> Option<&'cfg str> {
+        self.pushgateway
+    }
+
+    /// Returns the job name metrics are pushed under.
+    pub fn job(&self) -> &'cfg str {
+        self.job
+    }
 }
 
+/// A rule that expands a single test into several instances, each run with a different value for
+/// an environment variable.
+///
+/// Specified in the config as:
+///
+/// ```toml
+/// [[profile.default.multiplex]]
+/// filter = "test(seeded_)"
+/// env = "SEED=1|2|3"
+/// ```
+///
+/// Instead of `env`, a rule can specify a matrix of fail points, for the
+/// [`fail`](https://docs.rs/fail) crate. The test is run once for each combination of actions,
+/// with `FAILPOINTS` (or the variable named by `fail-points-env`) set to the combination, such as
+/// `storage::write=return;txn::commit=off`:
+///
+/// ```toml
+/// [[profile.default.multiplex]]
+/// filter = "test(recovery_)"
+/// fail-points = { "storage::write" = ["off", "return"], "txn::commit" = ["off", "1*panic"] }
+/// ```
 #[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "MultiplexRuleImpl")]
+pub struct MultiplexRule {
+    filter: FilterExpr,
+    env: MultiplexEnv,
+}
+
+#[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
-struct NextestProfilesImpl {
-    default: DefaultProfileImpl,
-    #[serde(flatten)]
-    other: HashMap<String, CustomProfileImpl>,
+struct MultiplexRuleImpl {
+    filter: FilterExpr,
+    #[serde(default)]
+    env: Option<MultiplexEnv>,
+    #[serde(default)]
+    fail_points: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(default = "default_fail_points_env")]
+    fail_points_env: String,
 }
 
-impl NextestProfilesImpl {
-    fn get(&self, profile: &str) -> Result<Option<&CustomProfileImpl>, ProfileNotFound> {
-        let custom_profile = match profile {
-            NextestConfig::DEFAULT_PROFILE => None,
-            other => Some(
-                self.other
-                    .get(other)
-                    .ok_or_else(|| ProfileNotFound::new(profile, self.all_profiles()))?,
-            ),
+fn default_fail_points_env() -> String {
+    "FAILPOINTS".to_owned()
+}
+
+impl TryFrom<MultiplexRuleImpl> for MultiplexRule {
+    type Error = MultiplexRuleError;
+
+    fn try_from(rule: MultiplexRuleImpl) -> Result<Self, Self::Error> {
+        let env = match (rule.env, rule.fail_points) {
+            (Some(env), None) => env,
+            (None, Some(fail_points)) if !fail_points.is_empty() => MultiplexEnv {
+                var: rule.fail_points_env,
+                values: fail_point_scenarios(&fail_points)?,
+            },
+            _ => return Err(MultiplexRuleError::EnvOrFailPoints),
         };
-        Ok(custom_profile)
+        Ok(Self {
+            filter: rule.filter,
+            env,
+        })
     }
+}
 
-    fn all_profiles(&self) -> impl Iterator<Item = &str> {
-        self.other
-            .keys()
-            .map(|key| key.as_str())
-            .chain(std::iter::once(NextestConfig::DEFAULT_PROFILE))
+/// Returns every combination of actions in a fail point matrix, in the format the `fail` crate
+/// reads from `FAILPOINTS`.
+fn fail_point_scenarios(
+    fail_points: &BTreeMap<String, Vec<String>>,
+) -> Result<Vec<String>, MultiplexRuleError> {
+    let mut scenarios = vec![String::new()];
+    for (fail_point, actions) in fail_points {
+        if actions.is_empty() {
+            return Err(MultiplexRuleError::NoActions {
+                fail_point: fail_point.clone(),
+            });
+        }
+        scenarios = scenarios
+            .iter()
+            .flat_map(|scenario| {
+                actions.iter().map(move |action| {
+                    let separator = if scenario.is_empty() { "" } else { ";" };
+                    format!("{}{}{}={}", scenario, separator, fail_point, action)
+                })
+            })
+            .collect();
     }
+    Ok(scenarios)
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct DefaultProfileImpl {
-    retries: usize,
-    status_level: StatusLevel,
-    failure_output: TestOutputDisplay,
-    success_output: TestOutputDisplay,
-    fail_fast: bool,
-    #[serde(with = "humantime_serde")]
-    slow_timeout: Duration,
-    junit: DefaultJunitImpl,
+impl MultiplexRule {
+    /// Returns the filter expression that selects tests for this rule.
+    pub fn filter(&self) -> &FilterExpr {
+        &self.filter
+    }
+
+    /// Returns the name of the environment variable set for each variant.
+    pub fn env_var(&self) -> &str {
+        &self.env.var
+    }
+
+    /// Iterates over the variants produced by this rule.
+    pub fn variants(&self) -> impl Iterator<Item = TestVariant<'_>> + '_ {
+        self.env.values.iter().map(move |value| TestVariant {
+            env_var: &self.env.var,
+            value,
+        })
+    }
 }
 
+/// Settings that apply to tests matching a filter expression.
+///
+/// Specified in the config as:
+///
+/// ```toml
+/// [[profile.default.overrides]]
+/// filter = "test(heavy_)"
+/// threads-required = 4
+/// test-group = "db"
+/// locale = true
+/// fake-time = true
+/// disk-quota = "1GiB"
+/// time-limit = { limit = "30s", grace = "10s" }
+/// expected-result = "fail"
+/// ```
+///
+/// An override with a `platform`, either a target triple or a `cfg()` expression, only applies
+/// when tests are built for a matching platform. Setting `skip` skips matching tests altogether:
+///
+/// ```toml
+/// [[profile.default.overrides]]
+/// filter = "test(~uses_epoll)"
+/// platform = "cfg(windows)"
+/// skip = true
+/// ```
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
-struct DefaultJunitImpl {
+pub struct ProfileOverride {
+    filter: FilterExpr,
+    #[serde(default)]
+    platform: Option<PlatformSpec>,
+    #[serde(default)]
+    skip: Option<bool>,
+    #[serde(default)]
+    threads_required: Option<ThreadsRequired>,
+    #[serde(default)]
+    test_group: Option<String>,
+    #[serde(default)]
+    locale: Option<LocaleSettings>,
+    #[serde(default)]
+    fake_time: Option<FakeTimeSettings>,
+    #[serde(default)]
+    disk_quota: Option<DiskQuota>,
+    #[serde(default)]
+    time_limit: Option<TimeLimit>,
+    #[serde(default)]
+    expected_result: Option<ExpectedResult>,
+}
+
+impl ProfileOverride {
+    /// Returns the filter expression that selects tests for this override.
+    pub fn filter(&self) -> &FilterExpr {
+        &self.filter
+    }
+
+    /// Returns the platforms this override applies to, if restricted.
+    pub fn platform(&self) -> Option<&PlatformSpec> {
+        self.platform.as_ref()
+    }
+
+    /// Returns whether matching tests are skipped, if specified.
+    pub fn skip(&self) -> Option<bool> {
+        self.skip
+    }
+
+    /// Returns the number of threads required by matching tests, if specified.
+    pub fn threads_required(&self) -> Option<ThreadsRequired> {
+        self.threads_required
+    }
+
+    /// Returns the test group matching tests are in, if specified.
+    pub fn test_group(&self) -> Option<&str> {
+        self.test_group.as_deref()
+    }
+
+    /// Returns the locale and time zone settings forced for matching tests, if specified.
+    pub fn locale(&self) -> Option<&LocaleSettings> {
+        self.locale.as_ref()
+    }
+
+    /// Returns the fake time settings for matching tests, if specified.
+    pub fn fake_time(&self) -> Option<&FakeTimeSettings> {
+        self.fake_time.as_ref()
+    }
+
+    /// Returns the maximum number of bytes matching tests may write to disk, if specified.
+    pub fn disk_quota(&self) -> Option<DiskQuota> {
+        self.disk_quota
+    }
+
+    /// Returns the time limit for matching tests, if specified.
+    pub fn time_limit(&self) -> Option<TimeLimit> {
+        self.time_limit
+    }
+
+    /// Returns the result matching tests are expected to have, if specified.
+    pub fn expected_result(&self) -> Option<ExpectedResult> {
+        self.expected_result
+    }
+}
+
+/// The settings that apply to a single test.
+///
+/// Each setting that overrides can specify is taken from the first matching override that
+/// specifies it, and the container and multiplex rules are the first ones that match the test.
+///
+/// Returned by [`NextestProfile::settings_for`].
+#[derive(Clone, Debug)]
+pub struct TestSettings<'cfg> {
+    retries: usize,
+    slow_timeout: SlowTimeout,
+    threads_required: ThreadsRequired,
+    test_group: Option<&'cfg str>,
+    locale: Option<&'cfg LocaleSettings>,
+    fake_time: Option<&'cfg FakeTimeSettings>,
+    disk_quota: Option<DiskQuota>,
+    time_limit: Option<TimeLimit>,
+    expected_result: ExpectedResult,
+    skip: bool,
+    container: Option<&'cfg ContainerRule>,
+    multiplex: Option<&'cfg MultiplexRule>,
+}
+
+impl<'cfg> TestSettings<'cfg> {
+    /// Resolves the settings for a test from profile-wide settings, and from overrides and rules
+    /// in order of precedence.
+    pub(crate) fn resolve(
+        retries: usize,
+        slow_timeout: SlowTimeout,
+        overrides: impl IntoIterator<Item = &'cfg ProfileOverride>,
+        container_rules: impl IntoIterator<Item = &'cfg ContainerRule>,
+        multiplex_rules: impl IntoIterator<Item = &'cfg MultiplexRule>,
+        query: &TestQuery<'_>,
+    ) -> Self {
+        let mut settings = Self {
+            retries,
+            slow_timeout,
+            threads_required: ThreadsRequired::default(),
+            test_group: None,
+            locale: None,
+            fake_time: None,
+            disk_quota: None,
+            time_limit: None,
+            expected_result: ExpectedResult::default(),
+            skip: false,
+            container: container_rules
+                .into_iter()
+                .find(|rule| rule.filter().matches_test(query)),
+            multiplex: multiplex_rules
+                .into_iter()
+                .find(|rule| rule.filter.matches_test(query)),
+        };
+        let mut threads_required = None;
+        let mut expected_result = None;
+        let mut skip = None;
+        for rule in overrides
+            .into_iter()
+            .filter(|rule| rule.filter.matches_test(query))
+        {
+            threads_required = threads_required.or(rule.threads_required);
+            settings.test_group = settings.test_group.or(rule.test_group.as_deref());
+            settings.locale = settings.locale.or(rule.locale.as_ref());
+            settings.fake_time = settings.fake_time.or(rule.fake_time.as_ref());
+            settings.disk_quota = settings.disk_quota.or(rule.disk_quota);
+            settings.time_limit = settings.time_limit.or(rule.time_limit);
+            expected_result = expected_result.or(rule.expected_result);
+            skip = skip.or(rule.skip);
+        }
+        settings.threads_required = threads_required.unwrap_or_default();
+        settings.expected_result = expected_result.unwrap_or_default();
+        settings.skip = skip.unwrap_or_default();
+        settings
+    }
+
+    /// Returns the number of times the test is retried if it fails.
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// Returns when the test is reported as slow.
+    pub fn slow_timeout(&self) -> SlowTimeout {
+        self.slow_timeout
+    }
+
+    /// Returns the number of threads the test requires.
+    pub fn threads_required(&self) -> ThreadsRequired {
+        self.threads_required
+    }
+
+    /// Returns the test group the test is in, or `None` if it isn't in a group.
+    pub fn test_group(&self) -> Option<&'cfg str> {
+        self.test_group
+    }
+
+    /// Returns the locale and time zone settings forced for the test, if any.
+    pub fn locale(&self) -> Option<&'cfg LocaleSettings> {
+        self.locale
+    }
+
+    /// Returns the fake time settings for the test, if any.
+    pub fn fake_time(&self) -> Option<&'cfg FakeTimeSettings> {
+        self.fake_time
+    }
+
+    /// Returns the maximum number of bytes the test may write to disk, if any.
+    pub fn disk_quota(&self) -> Option<DiskQuota> {
+        self.disk_quota
+    }
+
+    /// Returns the time limit for the test, if any.
+    pub fn time_limit(&self) -> Option<TimeLimit> {
+        self.time_limit
+    }
+
+    /// Returns the result the test is expected to have.
+    pub fn expected_result(&self) -> ExpectedResult {
+        self.expected_result
+    }
+
+    /// Returns true if the test is skipped because of an override.
+    pub fn skip(&self) -> bool {
+        self.skip
+    }
+
+    /// Returns the rule for the container the test is run in, if any.
+    pub fn container(&self) -> Option<&'cfg ContainerRule> {
+        self.container
+    }
+
+    /// Returns the multiplex rule the test is run once for each variant of, if any.
+    pub fn multiplex(&self) -> Option<&'cfg MultiplexRule> {
+        self.multiplex
+    }
+
+    /// Returns the environment variables set for the test because of these settings.
+    ///
+    /// Variables set by fake time depend on when the run started, and the variable set by a
+    /// multiplex rule depends on the variant, so neither is included here.
+    pub fn env(&self) -> impl Iterator<Item = (&'static str, &'cfg str)> + 'cfg {
+        self.locale.into_iter().flat_map(|locale| locale.vars())
+    }
+}
+
+/// When tests are reported as slow.
+///
+/// Specified in the config as a duration, after which a running test is reported as slow, and
+/// again each time the same duration passes. Alternatively, a table specifies how often a test is
+/// reported again as `period`, or that it's only reported once if `period` is missing:
+///
+/// ```toml
+/// [profile.ci]
+/// slow-timeout = { after = "2m", period = "30s" }
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SlowTimeout {
+    after: Duration,
+    period: Option<Duration>,
+}
+
+impl SlowTimeout {
+    /// Creates a new slow timeout, which reports tests as slow after `after` has passed, and again
+    /// each time `period` passes.
+    pub fn new(after: Duration, period: Option<Duration>) -> Self {
+        Self { after, period }
+    }
+
+    /// Returns the time after which a running test is first reported as slow.
+    pub fn after(&self) -> Duration {
+        self.after
+    }
+
+    /// Returns the time after which a slow test is reported again, if it is.
+    pub fn period(&self) -> Option<Duration> {
+        self.period
+    }
+
+    /// Returns how long to wait before reporting a test as slow, given the number of times it has
+    /// been reported already, or `None` if it isn't reported again.
+    pub(crate) fn next_wait(&self, reported: usize) -> Option<Duration> {
+        match reported {
+            0 => Some(self.after),
+            _ => self.period,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SlowTimeout {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum SlowTimeoutInput {
+            Period(#[serde(with = "humantime_serde")] Duration),
+            #[serde(rename_all = "kebab-case")]
+            Table {
+                #[serde(with = "humantime_serde")]
+                after: Duration,
+                #[serde(default, with = "humantime_serde")]
+                period: Option<Duration>,
+            },
+        }
+
+        match SlowTimeoutInput::deserialize(deserializer)? {
+            SlowTimeoutInput::Period(period) => Ok(Self::new(period, Some(period))),
+            SlowTimeoutInput::Table { after, period } => Ok(Self::new(after, period)),
+        }
+    }
+}
+
+/// The platforms an override applies to: either a target triple, or a `cfg()` expression such as
+/// `cfg(windows)` or `cfg(target_arch = "aarch64")`.
+#[derive(Clone, Debug)]
+pub struct PlatformSpec {
+    spec: TargetSpec,
+    input: String,
+}
+
+impl PlatformSpec {
+    /// Parses a platform specification.
+    pub fn new(input: impl Into<String>) -> Result<Self, target_spec::Error> {
+        let input = input.into();
+        let spec = TargetSpec::new(input.clone())?;
+        Ok(Self { spec, input })
+    }
+
+    /// Returns the specification as written in the config.
+    pub fn as_str(&self) -> &str {
+        &self.input
+    }
+
+    /// Returns true if this specification matches the given target triple. `platform` is the
+    /// triple's platform, if it's known to nextest.
+    pub fn matches(&self, triple: &str, platform: Option<&Platform>) -> bool {
+        if self.input == triple {
+            return true;
+        }
+        platform.is_some_and(|platform| self.spec.eval(platform) == Some(true))
+    }
+}
+
+impl<'de> Deserialize<'de> for PlatformSpec {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let input = String::deserialize(deserializer)?;
+        Self::new(input.clone())
+            .map_err(|error| de::Error::custom(format!("invalid platform '{}': {}", input, error)))
+    }
+}
+
+/// The result a test is expected to have.
+///
+/// Tests that are known to be broken, for example on some platforms, can be expected to fail, so
+/// that they're still run and tracked without failing the run:
+///
+/// ```toml
+/// [[profile.default.overrides]]
+/// filter = "test(=broken_on_arm)"
+/// expected-result = "fail"
+/// ```
+///
+/// If such a test fails, it's reported as [`ExecutionResult::ExpectedFail`] and doesn't fail the
+/// run. If it passes, it's reported as [`ExecutionResult::UnexpectedPass`] and does, so that the
+/// override is removed once the test is fixed. Other failures, such as timeouts and execution
+/// failures, are reported as usual.
+///
+/// [`ExecutionResult::ExpectedFail`]: crate::runner::ExecutionResult::ExpectedFail
+/// [`ExecutionResult::UnexpectedPass`]: crate::runner::ExecutionResult::UnexpectedPass
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExpectedResult {
+    /// The test is expected to pass.
+    ///
+    /// This is the default.
+    #[default]
+    Pass,
+
+    /// The test is expected to fail.
+    Fail,
+}
+
+impl ExpectedResult {
+    /// Returns the string used for this result in the config.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExpectedResult::Pass => "pass",
+            ExpectedResult::Fail => "fail",
+        }
+    }
+}
+
+/// The order in which tests are started.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestOrder {
+    /// Start all the tests in a binary before moving on to the next one.
+    ///
+    /// This is the default.
+    #[default]
+    Binary,
+
+    /// Start one test from each binary in turn, so that tests from across the workspace run early
+    /// on, and the load a single binary's tests put on the system is spread out over the run.
+    RoundRobin,
+}
+
+/// What to do if the directory that tests in a package are run in, the directory containing its
+/// `Cargo.toml`, doesn't exist: for example, if test binaries are run on a machine without the
+/// workspace's sources.
+///
+/// Specified in the config as `"fail"`, or as a table with either `dir` or `fixtures-root`:
+///
+/// ```toml
+/// [profile.ci]
+/// missing-cwd = { fixtures-root = "target/fixtures" }
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum MissingCwd {
+    /// Fail with an error naming the missing directory.
+    ///
+    /// This is the default.
+    #[default]
+    Fail,
+
+    /// Run the tests in this directory instead.
+    Dir(Utf8PathBuf),
+
+    /// Run the tests in the package's directory under this root, which has the same layout as the
+    /// workspace.
+    FixturesRoot(Utf8PathBuf),
+}
+
+impl MissingCwd {
+    /// Returns the directory to run the tests in `binary_id` in, given the directory containing
+    /// their package's `Cargo.toml`.
+    ///
+    /// The substitute directory must exist as well.
+    pub(crate) fn resolve(
+        &self,
+        binary_id: &str,
+        cwd: &Utf8Path,
+        workspace_root: &Utf8Path,
+    ) -> Result<Utf8PathBuf, MissingCwdError> {
+        if cwd.is_dir() {
+            return Ok(cwd.to_owned());
+        }
+        let substitute = match self {
+            MissingCwd::Fail => None,
+            MissingCwd::Dir(dir) => Some(dir.clone()),
+            MissingCwd::FixturesRoot(root) => {
+                // Test binaries belong to workspace packages, so their directories are always
+                // under the workspace root.
+                let package_dir = cwd.strip_prefix(workspace_root).unwrap_or(cwd);
+                Some(root.join(package_dir))
+            }
+        };
+        match substitute {
+            Some(substitute) if substitute.is_dir() => Ok(substitute),
+            substitute => Err(MissingCwdError::new(binary_id, cwd, substitute)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MissingCwd {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum MissingCwdInput {
+            Name(String),
+            #[serde(rename_all = "kebab-case")]
+            Table {
+                #[serde(default)]
+                dir: Option<Utf8PathBuf>,
+                #[serde(default)]
+                fixtures_root: Option<Utf8PathBuf>,
+            },
+        }
+
+        match MissingCwdInput::deserialize(deserializer)? {
+            MissingCwdInput::Name(name) if name == "fail" => Ok(MissingCwd::Fail),
+            MissingCwdInput::Name(name) => Err(de::Error::invalid_value(
+                de::Unexpected::Str(&name),
+                &"\"fail\"",
+            )),
+            MissingCwdInput::Table {
+                dir: Some(dir),
+                fixtures_root: None,
+            } => Ok(MissingCwd::Dir(dir)),
+            MissingCwdInput::Table {
+                dir: None,
+                fixtures_root: Some(fixtures_root),
+            } => Ok(MissingCwd::FixturesRoot(fixtures_root)),
+            MissingCwdInput::Table { .. } => Err(de::Error::custom(
+                "exactly one of \"dir\" and \"fixtures-root\" must be specified",
+            )),
+        }
+    }
+}
+
+/// How long a test may take before it fails with [`ExecutionResult::Timeout`].
+///
+/// Unlike the slow timeout, which only reports tests, a test that takes longer than its time limit
+/// fails even if it passes. Time is measured as wall-clock time.
+///
+/// Specified in the config as a duration, in which case tests that go over the limit are left to
+/// finish. Alternatively, a table specifies a `grace` period after the limit, after which the test
+/// is killed:
+///
+/// ```toml
+/// [[profile.ci.overrides]]
+/// filter = "package(perf-tests)"
+/// time-limit = { limit = "30s", grace = "10s" }
+/// ```
+///
+/// [`ExecutionResult::Timeout`]: crate::runner::ExecutionResult::Timeout
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TimeLimit {
+    limit: Duration,
+    grace: Option<Duration>,
+}
+
+impl TimeLimit {
+    /// Creates a new time limit. Tests are killed once `grace` has passed after the limit, if it's
+    /// specified.
+    pub fn new(limit: Duration, grace: Option<Duration>) -> Self {
+        Self { limit, grace }
+    }
+
+    /// Returns the time after which a test fails.
+    pub fn limit(&self) -> Duration {
+        self.limit
+    }
+
+    /// Returns the time after the limit that a test is allowed to keep running for before it's
+    /// killed, if it is.
+    pub fn grace(&self) -> Option<Duration> {
+        self.grace
+    }
+
+    /// Returns the time after which a test is killed, if it is.
+    pub fn kill_after(&self) -> Option<Duration> {
+        self.grace.map(|grace| self.limit + grace)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeLimit {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum TimeLimitInput {
+            Limit(#[serde(with = "humantime_serde")] Duration),
+            Table {
+                #[serde(with = "humantime_serde")]
+                limit: Duration,
+                #[serde(default, with = "humantime_serde")]
+                grace: Option<Duration>,
+            },
+        }
+
+        match TimeLimitInput::deserialize(deserializer)? {
+            TimeLimitInput::Limit(limit) => Ok(Self::new(limit, None)),
+            TimeLimitInput::Table { limit, grace } => Ok(Self::new(limit, grace)),
+        }
+    }
+}
+
+/// The number of slots a test takes up in the test runner while it runs.
+///
+/// Tests that spawn many threads or processes of their own can require more than one slot, so that
+/// fewer tests are run alongside them.
+///
+/// Specified in the config as either a positive integer or `"num-cpus"`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ThreadsRequired {
+    /// A fixed number of slots.
+    Count(usize),
+
+    /// As many slots as there are logical CPUs.
+    NumCpus,
+}
+
+impl ThreadsRequired {
+    /// Returns the number of slots required, given the number of tests run simultaneously.
+    ///
+    /// The result is always at least 1, and at most `test_threads`, so that a test requiring more
+    /// slots than are available runs on its own rather than never being scheduled.
+    pub fn compute(self, test_threads: usize) -> usize {
+        let required = match self {
+            ThreadsRequired::Count(count) => count,
+            ThreadsRequired::NumCpus => num_cpus::get(),
+        };
+        required.clamp(1, test_threads.max(1))
+    }
+}
+
+impl Default for ThreadsRequired {
+    fn default() -> Self {
+        ThreadsRequired::Count(1)
+    }
+}
+
+impl<'de> Deserialize<'de> for ThreadsRequired {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ThreadsRequiredVisitor;
+
+        impl<'de> de::Visitor<'de> for ThreadsRequiredVisitor {
+            type Value = ThreadsRequired;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a positive integer or \"num-cpus\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                match value {
+                    0 => Err(E::invalid_value(de::Unexpected::Unsigned(value), &self)),
+                    count => Ok(ThreadsRequired::Count(count as usize)),
+                }
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                match u64::try_from(value) {
+                    Ok(value) => self.visit_u64(value),
+                    Err(_) => Err(E::invalid_value(de::Unexpected::Signed(value), &self)),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                match value {
+                    "num-cpus" => Ok(ThreadsRequired::NumCpus),
+                    other => Err(E::invalid_value(de::Unexpected::Str(other), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(ThreadsRequiredVisitor)
+    }
+}
+
+/// Locale and time zone settings forced for tests, so that their results don't depend on the
+/// machine they're run on.
+///
+/// Specified in an override as `true`, which sets `TZ=UTC` and `LC_ALL=C`, as `false`, which leaves
+/// the environment alone, or as a table:
+///
+/// ```toml
+/// [[profile.default.overrides]]
+/// filter = "package(date-utils)"
+/// locale = { tz = "America/New_York", lang = "en_US.UTF-8", lc-all = "en_US.UTF-8" }
+/// ```
+///
+/// Keys missing from the table use the same defaults as `true` for `tz` and `lc-all`, and leave
+/// `LANG` alone. The variables take precedence over the environment nextest is run in and over
+/// [env files](crate::env_file).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LocaleSettings {
+    tz: Option<String>,
+    lang: Option<String>,
+    lc_all: Option<String>,
+}
+
+impl LocaleSettings {
+    /// The time zone set by default.
+    pub const DEFAULT_TZ: &'static str = "UTC";
+
+    /// The locale set by default, through `LC_ALL`.
+    pub const DEFAULT_LC_ALL: &'static str = "C";
+
+    /// Returns the value `TZ` is set to, if any.
+    pub fn tz(&self) -> Option<&str> {
+        self.tz.as_deref()
+    }
+
+    /// Returns the value `LANG` is set to, if any.
+    pub fn lang(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+
+    /// Returns the value `LC_ALL` is set to, if any.
+    pub fn lc_all(&self) -> Option<&str> {
+        self.lc_all.as_deref()
+    }
+
+    /// Iterates over the environment variables set for tests.
+    pub fn vars(&self) -> impl Iterator<Item = (&'static str, &str)> + '_ {
+        [
+            ("TZ", &self.tz),
+            ("LANG", &self.lang),
+            ("LC_ALL", &self.lc_all),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value.as_deref()?)))
+    }
+
+    fn defaults() -> Self {
+        Self {
+            tz: Some(Self::DEFAULT_TZ.to_owned()),
+            lang: None,
+            lc_all: Some(Self::DEFAULT_LC_ALL.to_owned()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LocaleSettings {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct LocaleTableImpl {
+            #[serde(default)]
+            tz: Option<String>,
+            #[serde(default)]
+            lang: Option<String>,
+            #[serde(default)]
+            lc_all: Option<String>,
+        }
+
+        struct LocaleVisitor;
+
+        impl<'de> de::Visitor<'de> for LocaleVisitor {
+            type Value = LocaleSettings;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a boolean or a table with tz, lang and lc-all")
+            }
+
+            fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+                match value {
+                    true => Ok(LocaleSettings::defaults()),
+                    false => Ok(LocaleSettings {
+                        tz: None,
+                        lang: None,
+                        lc_all: None,
+                    }),
+                }
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let table =
+                    LocaleTableImpl::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                let defaults = LocaleSettings::defaults();
+                Ok(LocaleSettings {
+                    tz: table.tz.or(defaults.tz),
+                    lang: table.lang,
+                    lc_all: table.lc_all.or(defaults.lc_all),
+                })
+            }
+        }
+
+        deserializer.deserialize_any(LocaleVisitor)
+    }
+}
+
+/// The versions of nextest required and recommended by a config, through the top-level
+/// `nextest-version` key.
+///
+/// The key is either a version string, which is the required version, or a table with optional
+/// `required` and `recommended` versions:
+///
+/// ```toml
+/// nextest-version = { required = "0.9.20", recommended = "0.9.30" }
+/// ```
+///
+/// This is read on its own through [`Self::from_sources`] before the rest of the config, so that
+/// an older nextest can report that it's too old even if the config uses settings it doesn't
+/// understand.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NextestVersionConfig {
+    required: Option<Version>,
+    recommended: Option<Version>,
+}
+
+impl NextestVersionConfig {
+    /// Reads the `nextest-version` key from the given file, or if not specified from
+    /// `.config/nextest.toml` in the given directory.
+    ///
+    /// Returns a config without any version requirements if the key isn't specified.
+    pub fn from_sources(
+        workspace_root: impl AsRef<Utf8Path>,
+        config_file: Option<&Utf8Path>,
+    ) -> Result<Self, ConfigParseError> {
+        let (config_file, config) =
+            NextestConfig::read_from_sources(workspace_root.as_ref(), config_file)?;
+        match config.get::<NextestVersionConfig>("nextest-version") {
+            Ok(version_config) => Ok(version_config),
+            Err(ConfigError::NotFound(_)) => Ok(Self::default()),
+            Err(err) => Err(ConfigParseError::new(config_file, err)),
+        }
+    }
+
+    /// Returns the minimum version of nextest required by this config, if any.
+    pub fn required(&self) -> Option<&Version> {
+        self.required.as_ref()
+    }
+
+    /// Returns the minimum version of nextest recommended by this config, if any.
+    pub fn recommended(&self) -> Option<&Version> {
+        self.recommended.as_ref()
+    }
+
+    /// Checks the given version of nextest against this config.
+    pub fn eval(&self, current: &Version) -> NextestVersionEval {
+        match (&self.required, &self.recommended) {
+            (Some(required), _) if current < required => NextestVersionEval::Error {
+                required: required.clone(),
+                current: current.clone(),
+            },
+            (_, Some(recommended)) if current < recommended => NextestVersionEval::Warn {
+                recommended: recommended.clone(),
+                current: current.clone(),
+            },
+            _ => NextestVersionEval::Satisfied,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for NextestVersionConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum VersionInput {
+            Required(String),
+            Table {
+                #[serde(default)]
+                required: Option<String>,
+                #[serde(default)]
+                recommended: Option<String>,
+            },
+        }
+
+        let parse = |version: Option<String>| {
+            version
+                .map(|version| Version::parse(&version))
+                .transpose()
+                .map_err(de::Error::custom)
+        };
+        match VersionInput::deserialize(deserializer)? {
+            VersionInput::Required(required) => Ok(Self {
+                required: parse(Some(required))?,
+                recommended: None,
+            }),
+            VersionInput::Table {
+                required,
+                recommended,
+            } => Ok(Self {
+                required: parse(required)?,
+                recommended: parse(recommended)?,
+            }),
+        }
+    }
+}
+
+/// The result of checking the current version of nextest against a [`NextestVersionConfig`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NextestVersionEval {
+    /// The current version satisfies the config.
+    Satisfied,
+
+    /// The current version is older than the required version: nextest should refuse to run.
+    Error {
+        /// The required version.
+        required: Version,
+
+        /// The current version.
+        current: Version,
+    },
+
+    /// The current version is older than the recommended version: nextest should print a warning.
+    Warn {
+        /// The recommended version.
+        recommended: Version,
+
+        /// The current version.
+        current: Version,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct MultiplexEnv {
+    var: String,
+    values: Vec<String>,
+}
+
+impl FromStr for MultiplexEnv {
+    type Err = MultiplexParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (var, values) = s
+            .split_once('=')
+            .ok_or_else(|| MultiplexParseError::new(s))?;
+        let var = var.trim();
+        if var.is_empty() {
+            return Err(MultiplexParseError::new(s));
+        }
+        let values: Vec<_> = values.split('|').map(|value| value.to_owned()).collect();
+        if values.iter().any(|value| value.is_empty()) {
+            return Err(MultiplexParseError::new(s));
+        }
+        Ok(Self {
+            var: var.to_owned(),
+            values,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for MultiplexEnv {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let input = String::deserialize(deserializer)?;
+        input.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct NextestConfigImpl {
+    store: StoreConfigImpl,
+    #[serde(default)]
+    other_tests: Vec<OtherTests>,
+    #[serde(default)]
+    required_binaries: Vec<RequiredBinary>,
+    #[serde(default)]
+    test_groups: BTreeMap<String, TestGroupConfig>,
+    #[serde(default)]
+    reporters: ReportersConfig,
+    #[serde(default)]
+    selectors: BTreeMap<String, SelectorConfig>,
+    #[serde(rename = "profile")]
+    profiles: NextestProfilesImpl,
+}
+
+impl NextestConfigImpl {
+    /// Checks that every test group referred to by an override is defined.
+    fn check_test_groups(&self) -> Result<(), String> {
+        let default_overrides = self
+            .profiles
+            .default
+            .overrides
+            .iter()
+            .map(|rule| (NextestConfig::DEFAULT_PROFILE, rule));
+        let custom_overrides = self.profiles.other.iter().flat_map(|(name, profile)| {
+            profile
+                .overrides
+                .iter()
+                .map(move |rule| (name.as_str(), rule))
+        });
+        for (profile, rule) in default_overrides.chain(custom_overrides) {
+            if let Some(group) = &rule.test_group {
+                if !self.test_groups.contains_key(group) {
+                    return Err(format!(
+                        "profile '{}' has an override with unknown test group '{}'",
+                        profile, group
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct StoreConfigImpl {
+    dir: Utf8PathBuf,
+    #[serde(default)]
+    retention: RetentionPolicy,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct NextestProfilesImpl {
+    default: DefaultProfileImpl,
+    #[serde(flatten)]
+    other: HashMap<String, CustomProfileImpl>,
+}
+
+impl NextestProfilesImpl {
+    /// Returns the given custom profile followed by the profiles it inherits from.
+    ///
+    /// Assumes that [`Self::check_inheritance`] has succeeded.
+    fn get(&self, profile: &str) -> Result<Vec<&CustomProfileImpl>, ProfileNotFound> {
+        let mut custom_profiles = vec![];
+        let mut next = profile;
+        while next != NextestConfig::DEFAULT_PROFILE {
+            let custom_profile = self
+                .other
+                .get(next)
+                .ok_or_else(|| ProfileNotFound::new(next, self.all_profiles()))?;
+            custom_profiles.push(custom_profile);
+            next = custom_profile
+                .inherits
+                .as_deref()
+                .unwrap_or(NextestConfig::DEFAULT_PROFILE);
+        }
+        Ok(custom_profiles)
+    }
+
+    /// Checks that every profile inherits from a known profile, and that there are no cycles.
+    fn check_inheritance(&self) -> Result<(), String> {
+        for (name, profile) in &self.other {
+            let mut seen = vec![name.as_str()];
+            let mut next = profile.inherits.as_deref();
+            while let Some(parent) = next {
+                if parent == NextestConfig::DEFAULT_PROFILE {
+                    break;
+                }
+                if seen.contains(&parent) {
+                    seen.push(parent);
+                    return Err(format!(
+                        "profile inheritance cycle detected: {}",
+                        seen.join(" -> ")
+                    ));
+                }
+                let parent_profile = self.other.get(parent).ok_or_else(|| {
+                    format!(
+                        "profile '{}' inherits from unknown profile '{}'",
+                        seen[seen.len() - 1],
+                        parent
+                    )
+                })?;
+                seen.push(parent);
+                next = parent_profile.inherits.as_deref();
+            }
+        }
+        Ok(())
+    }
+
+    fn check_output_dirs(&self) -> Result<(), String> {
+        let stores = std::iter::once((NextestConfig::DEFAULT_PROFILE, &self.default.store)).chain(
+            self.other
+                .iter()
+                .map(|(name, profile)| (name.as_str(), &profile.store)),
+        );
+        for (name, store) in stores {
+            if let Some(output_dir) = &store.output_dir {
+                check_output_dir(output_dir).map_err(|reason| {
+                    format!(
+                        "profile '{}' has invalid store.output-dir `{}`: {}",
+                        name, output_dir, reason
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn all_profiles(&self) -> impl Iterator<Item = &str> {
+        self.other
+            .keys()
+            .map(|key| key.as_str())
+            .chain(std::iter::once(NextestConfig::DEFAULT_PROFILE))
+    }
+}
+
+/// Checks that an output directory names a dedicated subdirectory of the profile's store
+/// directory, since nextest removes the files it wrote there at the start of each run.
+fn check_output_dir(output_dir: &Utf8Path) -> Result<(), &'static str> {
+    let mut normal_components = 0;
+    for component in output_dir.components() {
+        match component {
+            Utf8Component::Prefix(_) | Utf8Component::RootDir => {
+                return Err("must be a relative path");
+            }
+            Utf8Component::ParentDir => return Err("must not contain `..`"),
+            Utf8Component::CurDir => {}
+            Utf8Component::Normal(name) => {
+                if normal_components == 0 && name == "runs" {
+                    return Err("`runs` is reserved for the directories of runs");
+                }
+                normal_components += 1;
+            }
+        }
+    }
+    if normal_components == 0 {
+        return Err("must name a subdirectory of the profile's store directory");
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DefaultProfileImpl {
+    retries: usize,
+    status_level: StatusLevel,
+    final_status_level: StatusLevel,
+    failure_output: TestOutputDisplay,
+    success_output: TestOutputDisplay,
+    #[serde(with = "humantime_serde")]
+    streamed_output_interval: Duration,
+    #[serde(default)]
+    final_output_lines: Option<usize>,
+    highlight_diffs: bool,
+    fail_fast: bool,
+    test_order: TestOrder,
+    #[serde(default)]
+    default_filter: Option<FilterExpr>,
+    slow_timeout: SlowTimeout,
+    #[serde(with = "humantime_serde")]
+    default_duration_estimate: Duration,
+    wasm_runtime: WasmRuntime,
+    missing_cwd: MissingCwd,
+    #[serde(default)]
+    multiplex: Vec<MultiplexRule>,
+    #[serde(default)]
+    dependencies: Vec<DependencyRule>,
+    #[serde(default)]
+    overrides: Vec<ProfileOverride>,
+    #[serde(default)]
+    target_runner: Vec<TargetRunnerRule>,
+    #[serde(default)]
+    container: Vec<ContainerRule>,
+    #[serde(default)]
+    output_filter: Option<OutputFilter>,
+    #[serde(default)]
+    output_sanitizer: Option<OutputSanitizer>,
+    #[serde(default)]
+    crash_symbolizer: Option<CrashSymbolizer>,
+    #[serde(default)]
+    core_dumps: Option<CoreDumps>,
+    #[serde(default)]
+    line_buffering: Option<LineBuffering>,
+    #[serde(default)]
+    valgrind: Option<Valgrind>,
+    #[serde(default)]
+    coverage: Option<CoverageSettings>,
+    #[serde(default)]
+    quarantine: Option<QuarantineConfig>,
+    #[serde(default)]
+    env_files: Vec<Utf8PathBuf>,
+    #[serde(default)]
+    store: ProfileStoreImpl,
+    junit: DefaultJunitImpl,
+    metrics: DefaultMetricsImpl,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DefaultJunitImpl {
+    #[serde(default)]
+    path: Option<Utf8PathBuf>,
+    report_name: String,
+    report_skipped: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DefaultMetricsImpl {
     #[serde(default)]
     path: Option<Utf8PathBuf>,
-    report_name: String,
+    #[serde(default)]
+    pushgateway: Option<String>,
+    job: String,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct CustomProfileImpl {
+    #[serde(default)]
+    inherits: Option<String>,
     #[serde(default)]
     retries: Option<usize>,
     #[serde(default)]
     status_level: Option<StatusLevel>,
     #[serde(default)]
+    final_status_level: Option<StatusLevel>,
+    #[serde(default)]
     failure_output: Option<TestOutputDisplay>,
     #[serde(default)]
     success_output: Option<TestOutputDisplay>,
+    #[serde(with = "humantime_serde")]
+    #[serde(default)]
+    streamed_output_interval: Option<Duration>,
+    #[serde(default)]
+    final_output_lines: Option<usize>,
+    #[serde(default)]
+    highlight_diffs: Option<bool>,
     #[serde(default)]
     fail_fast: Option<bool>,
+    #[serde(default)]
+    test_order: Option<TestOrder>,
+    #[serde(default)]
+    default_filter: Option<FilterExpr>,
+    #[serde(default)]
+    slow_timeout: Option<SlowTimeout>,
     #[serde(with = "humantime_serde")]
     #[serde(default)]
-    slow_timeout: Option<Duration>,
+    default_duration_estimate: Option<Duration>,
+    #[serde(default)]
+    wasm_runtime: Option<WasmRuntime>,
+    #[serde(default)]
+    missing_cwd: Option<MissingCwd>,
+    #[serde(default)]
+    multiplex: Vec<MultiplexRule>,
+    #[serde(default)]
+    dependencies: Vec<DependencyRule>,
+    #[serde(default)]
+    overrides: Vec<ProfileOverride>,
+    #[serde(default)]
+    target_runner: Vec<TargetRunnerRule>,
+    #[serde(default)]
+    container: Vec<ContainerRule>,
+    #[serde(default)]
+    output_filter: Option<OutputFilter>,
+    #[serde(default)]
+    output_sanitizer: Option<OutputSanitizer>,
+    #[serde(default)]
+    crash_symbolizer: Option<CrashSymbolizer>,
+    #[serde(default)]
+    core_dumps: Option<CoreDumps>,
+    #[serde(default)]
+    line_buffering: Option<LineBuffering>,
+    #[serde(default)]
+    valgrind: Option<Valgrind>,
+    #[serde(default)]
+    coverage: Option<CoverageSettings>,
+    #[serde(default)]
+    quarantine: Option<QuarantineConfig>,
+    #[serde(default)]
+    env_files: Option<Vec<Utf8PathBuf>>,
+    #[serde(default)]
+    store: ProfileStoreImpl,
     #[serde(default)]
     junit: JunitImpl,
+    #[serde(default)]
+    metrics: MetricsImpl,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProfileStoreImpl {
+    #[serde(default)]
+    output_dir: Option<Utf8PathBuf>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -325,11 +2451,24 @@ struct JunitImpl {
     #[serde(default)]
     path: Option<Utf8PathBuf>,
     report_name: Option<String>,
+    report_skipped: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct MetricsImpl {
+    #[serde(default)]
+    path: Option<Utf8PathBuf>,
+    #[serde(default)]
+    pushgateway: Option<String>,
+    #[serde(default)]
+    job: Option<String>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::container::ContainerEngine;
 
     #[test]
     fn default_config_is_valid() {
@@ -338,4 +2477,1265 @@ mod tests {
             .profile(NextestConfig::DEFAULT_PROFILE)
             .expect("default profile should exist");
     }
+
+    #[test]
+    fn multiplex_rules() {
+        let config_contents = r#"
+            [[profile.default.multiplex]]
+            filter = "test(seeded)"
+            env = "SEED=1|2"
+
+            [[profile.ci.multiplex]]
+            filter = "package(my-package) & test(seeded)"
+            env = "SEED=a|b|c"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+
+        let query = TestQuery {
+            binary_id: "my-package",
+            package_name: "my-package",
+            test_name: "tests::seeded_test",
+            platform: BuildPlatform::Target,
+        };
+
+        let default_profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        let rule = default_profile.multiplex_for(&query).expect("rule matches");
+        let values: Vec<_> = rule.variants().map(|variant| variant.value).collect();
+        assert_eq!(values, ["1", "2"]);
+
+        let ci_profile = config.profile("ci").unwrap();
+        let rule = ci_profile.multiplex_for(&query).expect("rule matches");
+        assert_eq!(rule.env_var(), "SEED");
+        let values: Vec<_> = rule.variants().map(|variant| variant.value).collect();
+        assert_eq!(
+            values,
+            ["a", "b", "c"],
+            "custom profile rules take precedence"
+        );
+
+        let other_query = TestQuery {
+            package_name: "other-package",
+            ..query
+        };
+        let rule = ci_profile
+            .multiplex_for(&other_query)
+            .expect("rule matches");
+        assert_eq!(
+            rule.variants().count(),
+            2,
+            "falls back to default profile rules"
+        );
+    }
+
+    #[test]
+    fn fail_point_multiplex() {
+        let config_contents = r#"
+            [[profile.default.multiplex]]
+            filter = "test(recovery)"
+            fail-points = { "txn::commit" = ["off", "1*panic"], "storage::write" = ["off", "return"] }
+
+            [[profile.ci.multiplex]]
+            filter = "test(recovery)"
+            fail-points = { "storage::write" = ["return"] }
+            fail-points-env = "MY_FAILPOINTS"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let query = TestQuery {
+            binary_id: "my-package",
+            package_name: "my-package",
+            test_name: "tests::recovery",
+            platform: BuildPlatform::Target,
+        };
+
+        let default_profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        let rule = default_profile.multiplex_for(&query).expect("rule matches");
+        assert_eq!(rule.env_var(), "FAILPOINTS");
+        let values: Vec<_> = rule.variants().map(|variant| variant.value).collect();
+        assert_eq!(
+            values,
+            [
+                "storage::write=off;txn::commit=off",
+                "storage::write=off;txn::commit=1*panic",
+                "storage::write=return;txn::commit=off",
+                "storage::write=return;txn::commit=1*panic",
+            ]
+        );
+
+        let ci_profile = config.profile("ci").unwrap();
+        let rule = ci_profile.multiplex_for(&query).expect("rule matches");
+        assert_eq!(rule.env_var(), "MY_FAILPOINTS");
+        let values: Vec<_> = rule.variants().map(|variant| variant.value).collect();
+        assert_eq!(values, ["storage::write=return"]);
+
+        for invalid in [
+            r#"filter = "all()""#,
+            r#"filter = "all()"
+            env = "SEED=1|2"
+            fail-points = { "a" = ["off"] }"#,
+            r#"filter = "all()"
+            fail-points = {}"#,
+            r#"filter = "all()"
+            fail-points = { "a" = [] }"#,
+        ] {
+            let config_contents = format!("[[profile.default.multiplex]]\n{}", invalid);
+            config_from_str(&config_contents)
+                .expect_err(&format!("`{}` should be invalid", invalid));
+        }
+    }
+
+    #[test]
+    fn threads_required() {
+        let config_contents = r#"
+            [[profile.default.overrides]]
+            filter = "test(heavy)"
+            threads-required = 4
+
+            [[profile.ci.overrides]]
+            filter = "package(my-package) & test(heavy)"
+            threads-required = "num-cpus"
+
+            [[profile.ci.overrides]]
+            filter = "all()"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+
+        let query = TestQuery {
+            binary_id: "my-package",
+            package_name: "my-package",
+            test_name: "tests::heavy_test",
+            platform: BuildPlatform::Target,
+        };
+        let light_query = TestQuery {
+            test_name: "tests::light_test",
+            ..query
+        };
+
+        let default_profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        assert_eq!(
+            default_profile.settings_for(&query).threads_required(),
+            ThreadsRequired::Count(4)
+        );
+        assert_eq!(
+            default_profile
+                .settings_for(&light_query)
+                .threads_required(),
+            ThreadsRequired::Count(1),
+            "tests without a matching override require 1 thread"
+        );
+
+        let ci_profile = config.profile("ci").unwrap();
+        assert_eq!(
+            ci_profile.settings_for(&query).threads_required(),
+            ThreadsRequired::NumCpus,
+            "custom profile overrides take precedence"
+        );
+        let other_query = TestQuery {
+            package_name: "other-package",
+            ..query
+        };
+        assert_eq!(
+            ci_profile.settings_for(&other_query).threads_required(),
+            ThreadsRequired::Count(4),
+            "overrides without threads-required are skipped"
+        );
+
+        assert_eq!(ThreadsRequired::Count(4).compute(8), 4);
+        assert_eq!(
+            ThreadsRequired::Count(16).compute(8),
+            8,
+            "capped at the number of test threads"
+        );
+
+        for invalid in [
+            "[[profile.default.overrides]]\nfilter = \"all()\"\nthreads-required = 0",
+            "[[profile.default.overrides]]\nfilter = \"all()\"\nthreads-required = \"many\"",
+        ] {
+            config_from_str(invalid).expect_err(&format!("config `{}` should be invalid", invalid));
+        }
+    }
+
+    #[test]
+    fn test_settings() {
+        let config_contents = r#"
+            [test-groups.db]
+
+            [test-groups.other]
+
+            [profile.default]
+            retries = 1
+
+            [[profile.default.overrides]]
+            filter = "test(db_)"
+            test-group = "db"
+            locale = { tz = "America/New_York" }
+
+            [[profile.default.overrides]]
+            filter = "all()"
+            test-group = "other"
+            threads-required = 2
+            time-limit = "30s"
+
+            [[profile.default.container]]
+            filter = "test(db_)"
+            image = "db-test-env:latest"
+
+            [[profile.default.multiplex]]
+            filter = "test(seeded)"
+            env = "SEED=1|2"
+
+            [profile.ci]
+            retries = 3
+            slow-timeout = "2m"
+
+            [[profile.ci.overrides]]
+            filter = "test(db_)"
+            threads-required = 4
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+
+        let query = TestQuery {
+            binary_id: "my-package",
+            package_name: "my-package",
+            test_name: "tests::db_seeded",
+            platform: BuildPlatform::Target,
+        };
+        let other_query = TestQuery {
+            test_name: "tests::other",
+            ..query
+        };
+
+        let default_profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        let settings = default_profile.settings_for(&query);
+        assert_eq!(settings.retries(), 1);
+        assert_eq!(settings.test_group(), Some("db"));
+        assert_eq!(
+            settings.threads_required(),
+            ThreadsRequired::Count(2),
+            "settings are taken from the first override that specifies them"
+        );
+        assert_eq!(
+            settings.time_limit().map(|time_limit| time_limit.limit()),
+            Some(Duration::from_secs(30))
+        );
+        let env: Vec<_> = settings.env().collect();
+        assert_eq!(env, [("TZ", "America/New_York"), ("LC_ALL", "C")]);
+        assert_eq!(
+            settings.container().map(|rule| rule.image()),
+            Some("db-test-env:latest")
+        );
+        assert_eq!(
+            settings.multiplex().map(|rule| rule.env_var()),
+            Some("SEED")
+        );
+
+        let settings = default_profile.settings_for(&other_query);
+        assert_eq!(settings.test_group(), Some("other"));
+        assert_eq!(settings.env().count(), 0);
+        assert!(settings.container().is_none());
+        assert!(settings.multiplex().is_none());
+
+        let ci_profile = config.profile("ci").unwrap();
+        let settings = ci_profile.settings_for(&query);
+        assert_eq!(settings.retries(), 3);
+        assert_eq!(
+            settings.slow_timeout(),
+            SlowTimeout::new(Duration::from_secs(120), Some(Duration::from_secs(120)))
+        );
+        assert_eq!(
+            settings.threads_required(),
+            ThreadsRequired::Count(4),
+            "custom profile overrides take precedence"
+        );
+        assert_eq!(
+            settings.test_group(),
+            Some("db"),
+            "other settings come from inherited overrides"
+        );
+    }
+
+    #[test]
+    fn profile_inheritance() {
+        let config_contents = r#"
+            [profile.default]
+            retries = 1
+
+            [profile.default.junit]
+            path = "junit.xml"
+
+            [profile.ci]
+            retries = 3
+            fail-fast = false
+            test-order = "round-robin"
+            wasm-runtime = "wasmer"
+
+            [profile.ci-slow]
+            inherits = "ci"
+            slow-timeout = "5m"
+            default-duration-estimate = "10s"
+
+            [profile.ci-slow.junit]
+            report-name = "slow-run"
+            report-skipped = false
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+
+        let ci_profile = config.profile("ci").unwrap();
+        assert_eq!(ci_profile.retries(), 3);
+        assert!(!ci_profile.fail_fast());
+        assert_eq!(ci_profile.test_order(), TestOrder::RoundRobin);
+        assert_eq!(ci_profile.wasm_runtime(), WasmRuntime::Wasmer);
+        assert_eq!(
+            ci_profile.default_duration_estimate(),
+            Duration::from_secs(1),
+            "from default config"
+        );
+        let junit = ci_profile
+            .junit()
+            .expect("junit path inherited from default");
+        assert_eq!(junit.path(), "/fake/dir/target/nextest/ci/junit.xml");
+        assert_eq!(junit.report_name(), "nextest-run");
+        assert!(junit.report_skipped(), "report-skipped from default config");
+
+        let slow_profile = config.profile("ci-slow").unwrap();
+        assert_eq!(slow_profile.retries(), 3, "inherited from ci");
+        assert!(!slow_profile.fail_fast(), "inherited from ci");
+        assert_eq!(
+            slow_profile.test_order(),
+            TestOrder::RoundRobin,
+            "inherited from ci"
+        );
+        assert_eq!(
+            slow_profile.wasm_runtime(),
+            WasmRuntime::Wasmer,
+            "inherited from ci"
+        );
+        assert_eq!(
+            slow_profile.slow_timeout(),
+            SlowTimeout::new(Duration::from_secs(300), Some(Duration::from_secs(300)))
+        );
+        assert_eq!(
+            slow_profile.default_duration_estimate(),
+            Duration::from_secs(10)
+        );
+        let junit = slow_profile
+            .junit()
+            .expect("junit path inherited from default");
+        assert_eq!(junit.path(), "/fake/dir/target/nextest/ci-slow/junit.xml");
+        assert_eq!(junit.report_name(), "slow-run");
+        assert!(!junit.report_skipped());
+
+        for invalid in [
+            "[profile.ci]\ninherits = \"missing\"",
+            "[profile.a]\ninherits = \"b\"\n[profile.b]\ninherits = \"a\"",
+        ] {
+            config_from_str(invalid).expect_err(&format!("config `{}` should be invalid", invalid));
+        }
+    }
+
+    #[test]
+    fn output_dir() {
+        let config = config_from_str("[profile.ci.store]\noutput-dir = \"./ci/output\"")
+            .expect("config is valid");
+        let profile = config.profile("ci").unwrap();
+        assert_eq!(
+            profile.output_dir().expect("output dir set"),
+            "/fake/dir/target/nextest/ci/ci/output"
+        );
+
+        for invalid in [
+            "",
+            ".",
+            "/tmp/output",
+            "../output",
+            "output/../..",
+            "runs",
+            "runs/x",
+        ] {
+            let contents = format!("[profile.ci.store]\noutput-dir = \"{}\"", invalid);
+            config_from_str(&contents)
+                .expect_err(&format!("output dir `{}` should be invalid", invalid));
+        }
+    }
+
+    #[test]
+    fn output_filter() {
+        let config_contents = r#"
+            [profile.default.output-filter]
+            command = ["sed", "-e", "s/^noise//"]
+
+            [profile.ci.output-filter]
+            command = ["strip-timestamps"]
+            timeout = "30s"
+
+            [profile.other]
+            retries = 1
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+
+        let default_profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        let filter = default_profile.output_filter().expect("filter specified");
+        assert_eq!(filter.program(), "sed");
+        assert_eq!(filter.args(), ["-e", "s/^noise//"]);
+        assert_eq!(filter.timeout(), OutputFilter::DEFAULT_TIMEOUT);
+
+        let ci_profile = config.profile("ci").unwrap();
+        let filter = ci_profile.output_filter().expect("filter specified");
+        assert_eq!(filter.program(), "strip-timestamps");
+        assert!(filter.args().is_empty());
+        assert_eq!(filter.timeout(), Duration::from_secs(30));
+
+        let other_profile = config.profile("other").unwrap();
+        assert_eq!(
+            other_profile.output_filter().map(|filter| filter.program()),
+            Some("sed"),
+            "falls back to default profile filter"
+        );
+
+        config_from_str("[profile.default.output-filter]\ncommand = []")
+            .expect_err("empty command should be invalid");
+    }
+
+    #[test]
+    fn slow_timeouts() {
+        let slow_timeout = |contents: &str| {
+            config_from_str(contents)
+                .map(|config| config.profile("default").unwrap().slow_timeout())
+        };
+        let secs = Duration::from_secs;
+
+        assert_eq!(
+            slow_timeout("").unwrap(),
+            SlowTimeout::new(secs(60), Some(secs(60))),
+            "default config repeats every 60s"
+        );
+        assert_eq!(
+            slow_timeout("[profile.default]\nslow-timeout = { after = \"2m\", period = \"30s\" }")
+                .unwrap(),
+            SlowTimeout::new(secs(120), Some(secs(30)))
+        );
+        let once = slow_timeout("[profile.default]\nslow-timeout = { after = \"2m\" }").unwrap();
+        assert_eq!(once, SlowTimeout::new(secs(120), None));
+        assert_eq!(once.next_wait(0), Some(secs(120)));
+        assert_eq!(once.next_wait(1), None, "reported only once");
+
+        slow_timeout("[profile.default]\nslow-timeout = { period = \"30s\" }")
+            .expect_err("after is required");
+
+        let miri_timeout = config_from_str("")
+            .unwrap()
+            .profile(NextestConfig::DEFAULT_MIRI_PROFILE)
+            .unwrap()
+            .slow_timeout();
+        assert_eq!(
+            miri_timeout,
+            SlowTimeout::new(secs(300), Some(secs(60))),
+            "tests run under Miri are given longer"
+        );
+    }
+
+    #[test]
+    fn default_filters() {
+        let config_contents = r#"
+            [profile.default]
+            default-filter = "not test(~slow_)"
+
+            [profile.ci]
+            default-filter = "all()"
+
+            [profile.local]
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let default_filter = |profile: &str| {
+            config
+                .profile(profile)
+                .unwrap()
+                .default_filter()
+                .map(|filter| filter.as_str().to_owned())
+        };
+
+        assert_eq!(
+            default_filter("default").as_deref(),
+            Some("not test(~slow_)")
+        );
+        assert_eq!(default_filter("ci").as_deref(), Some("all()"));
+        assert_eq!(
+            default_filter("local").as_deref(),
+            Some("not test(~slow_)"),
+            "inherited from the default profile"
+        );
+        assert_eq!(
+            config_from_str("")
+                .unwrap()
+                .profile("default")
+                .unwrap()
+                .default_filter()
+                .map(|filter| filter.as_str()),
+            None,
+            "all tests are run by default"
+        );
+    }
+
+    #[test]
+    fn time_limits() {
+        let time_limit = |time_limit: &str| {
+            let contents = format!(
+                "[[profile.default.overrides]]\nfilter = \"all()\"\ntime-limit = {}",
+                time_limit
+            );
+            config_from_str(&contents).map(|config| {
+                config
+                    .profile("default")
+                    .unwrap()
+                    .overrides(BuildPlatform::Target)
+                    .find_map(|rule| rule.time_limit())
+            })
+        };
+        let secs = Duration::from_secs;
+
+        let limit = time_limit("\"30s\"").unwrap().unwrap();
+        assert_eq!(limit, TimeLimit::new(secs(30), None));
+        assert_eq!(
+            limit.kill_after(),
+            None,
+            "not killed without a grace period"
+        );
+        let limit = time_limit("{ limit = \"30s\", grace = \"10s\" }")
+            .unwrap()
+            .unwrap();
+        assert_eq!(limit.kill_after(), Some(secs(40)));
+        time_limit("{ grace = \"10s\" }").expect_err("limit is required");
+    }
+
+    #[test]
+    fn expected_results() {
+        let config_contents = r#"
+            [[profile.default.overrides]]
+            filter = "test(=tests::fixed)"
+            expected-result = "pass"
+
+            [[profile.default.overrides]]
+            filter = "test(~tests::)"
+            expected-result = "fail"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        let expected_result = |test_name| {
+            profile
+                .settings_for(&TestQuery {
+                    binary_id: "my-package",
+                    package_name: "my-package",
+                    test_name,
+                    platform: BuildPlatform::Target,
+                })
+                .expected_result()
+        };
+
+        assert_eq!(expected_result("tests::broken"), ExpectedResult::Fail);
+        assert_eq!(
+            expected_result("tests::fixed"),
+            ExpectedResult::Pass,
+            "first matching override wins"
+        );
+        assert_eq!(
+            expected_result("other"),
+            ExpectedResult::Pass,
+            "tests are expected to pass by default"
+        );
+
+        config_from_str(
+            "[[profile.default.overrides]]\nfilter = \"all()\"\nexpected-result = \"flaky\"",
+        )
+        .expect_err("unknown expected result");
+    }
+
+    #[test]
+    fn platform_overrides() {
+        let config_contents = r#"
+            [[profile.default.overrides]]
+            filter = "test(~uses_epoll)"
+            platform = "cfg(windows)"
+            skip = true
+
+            [[profile.default.overrides]]
+            filter = "test(~uses_epoll)"
+            platform = "x86_64-unknown-linux-gnu"
+            threads-required = 2
+
+            [[profile.default.overrides]]
+            filter = "test(=tests::slow)"
+            skip = true
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let query = |test_name| TestQuery {
+            binary_id: "my-package",
+            package_name: "my-package",
+            test_name,
+            platform: BuildPlatform::Target,
+        };
+
+        let windows = config
+            .profile(NextestConfig::DEFAULT_PROFILE)
+            .unwrap()
+            .with_target_triple(Some("x86_64-pc-windows-msvc"));
+        assert_eq!(
+            windows.triple_for(BuildPlatform::Target),
+            "x86_64-pc-windows-msvc"
+        );
+        assert!(windows
+            .settings_for(&query("tests::uses_epoll_wait"))
+            .skip());
+        assert_eq!(
+            windows
+                .settings_for(&query("tests::uses_epoll_wait"))
+                .threads_required(),
+            ThreadsRequired::Count(1)
+        );
+        assert!(windows.settings_for(&query("tests::slow")).skip());
+        assert!(!windows.settings_for(&query("tests::other")).skip());
+
+        let linux = config
+            .profile(NextestConfig::DEFAULT_PROFILE)
+            .unwrap()
+            .with_target_triple(Some("x86_64-unknown-linux-gnu"));
+        let settings = linux.settings_for(&query("tests::uses_epoll_wait"));
+        assert!(!settings.skip(), "cfg(windows) doesn't match linux");
+        assert_eq!(
+            settings.threads_required(),
+            ThreadsRequired::Count(2),
+            "exact triples match"
+        );
+        assert_eq!(linux.overrides(BuildPlatform::Target).count(), 2);
+
+        // Binaries built for the host, such as proc-macro tests, are evaluated against the host
+        // triple when cross-compiling.
+        let cross = config
+            .profile(NextestConfig::DEFAULT_PROFILE)
+            .unwrap()
+            .with_host_triple(Some("x86_64-unknown-linux-gnu"))
+            .with_target_triple(Some("x86_64-pc-windows-msvc"));
+        let host_query = TestQuery {
+            platform: BuildPlatform::Host,
+            ..query("tests::uses_epoll_wait")
+        };
+        assert!(cross.settings_for(&query("tests::uses_epoll_wait")).skip());
+        let settings = cross.settings_for(&host_query);
+        assert!(!settings.skip(), "host binaries aren't built for windows");
+        assert_eq!(settings.threads_required(), ThreadsRequired::Count(2));
+
+        let custom = config
+            .profile(NextestConfig::DEFAULT_PROFILE)
+            .unwrap()
+            .with_target_triple(Some("my-custom-target"));
+        assert!(
+            !custom.settings_for(&query("tests::uses_epoll_wait")).skip(),
+            "unknown triples don't match cfg() expressions"
+        );
+
+        config_from_str(
+            "[[profile.default.overrides]]\nfilter = \"all()\"\nplatform = \"cfg(\"\nskip = true",
+        )
+        .expect_err("invalid platform");
+    }
+
+    #[test]
+    fn missing_cwd() {
+        let missing_cwd = |contents: &str| {
+            config_from_str(contents).map(|config| config.profile("default").unwrap().missing_cwd())
+        };
+
+        assert_eq!(missing_cwd("").unwrap(), MissingCwd::Fail);
+        assert_eq!(
+            missing_cwd("[profile.default]\nmissing-cwd = { dir = \"fixtures\" }").unwrap(),
+            MissingCwd::Dir("/fake/dir/fixtures".into()),
+            "relative to the workspace root"
+        );
+        assert_eq!(
+            missing_cwd("[profile.default]\nmissing-cwd = { fixtures-root = \"/extracted\" }")
+                .unwrap(),
+            MissingCwd::FixturesRoot("/extracted".into())
+        );
+        missing_cwd("[profile.default]\nmissing-cwd = \"skip\"").expect_err("unknown value");
+        missing_cwd("[profile.default]\nmissing-cwd = { dir = \"a\", fixtures-root = \"b\" }")
+            .expect_err("both keys specified");
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+        std::fs::create_dir_all(root.join("fixtures/crates/foo")).unwrap();
+        let workspace_root = root.join("workspace");
+        let cwd = workspace_root.join("crates/foo");
+
+        let err = MissingCwd::Fail
+            .resolve("foo", &cwd, &workspace_root)
+            .expect_err("cwd doesn't exist");
+        assert!(err.to_string().contains("set missing-cwd"), "{}", err);
+        assert_eq!(
+            MissingCwd::Dir(root.to_owned())
+                .resolve("foo", &cwd, &workspace_root)
+                .unwrap(),
+            root
+        );
+        assert_eq!(
+            MissingCwd::FixturesRoot(root.join("fixtures"))
+                .resolve("foo", &cwd, &workspace_root)
+                .unwrap(),
+            root.join("fixtures/crates/foo")
+        );
+        MissingCwd::Dir(root.join("missing"))
+            .resolve("foo", &cwd, &workspace_root)
+            .expect_err("substitute doesn't exist either");
+        assert_eq!(
+            MissingCwd::Fail
+                .resolve("foo", &root.join("fixtures"), &workspace_root)
+                .unwrap(),
+            root.join("fixtures"),
+            "existing directories are used as is"
+        );
+    }
+
+    #[test]
+    fn invalid_multiplex() {
+        for env in ["SEED", "=1|2", "SEED=", "SEED=1||2", "SEED=1|"] {
+            env.parse::<MultiplexEnv>()
+                .expect_err(&format!("`{}` should be invalid", env));
+        }
+        let env: MultiplexEnv = "SEED=1|2".parse().unwrap();
+        assert_eq!(
+            env,
+            MultiplexEnv {
+                var: "SEED".to_owned(),
+                values: vec!["1".to_owned(), "2".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn nextest_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = Utf8PathBuf::try_from(dir.path().join("nextest.toml")).unwrap();
+        let version_config = |contents: &str| {
+            std::fs::write(&config_path, contents).unwrap();
+            NextestVersionConfig::from_sources("/fake/dir", Some(&config_path))
+        };
+        let version = |s: &str| Version::parse(s).unwrap();
+
+        let config = version_config("").expect("no version is valid");
+        assert_eq!(config, NextestVersionConfig::default());
+        assert_eq!(
+            config.eval(&version("0.1.0")),
+            NextestVersionEval::Satisfied
+        );
+
+        let config = version_config(r#"nextest-version = "0.9.50""#).expect("valid version");
+        assert_eq!(config.required(), Some(&version("0.9.50")));
+        assert_eq!(config.recommended(), None);
+        assert_eq!(
+            config.eval(&version("0.9.49")),
+            NextestVersionEval::Error {
+                required: version("0.9.50"),
+                current: version("0.9.49"),
+            }
+        );
+        assert_eq!(
+            config.eval(&version("0.9.50")),
+            NextestVersionEval::Satisfied
+        );
+
+        // Settings this version of nextest doesn't know about don't get in the way of reading the
+        // version.
+        let config = version_config(
+            r#"
+            nextest-version = { required = "0.9.20", recommended = "0.9.30" }
+            future-setting = { enabled = true }
+
+            [profile.default]
+            retries = "not a number"
+            "#,
+        )
+        .expect("valid version table");
+        assert_eq!(
+            config.eval(&version("0.9.25")),
+            NextestVersionEval::Warn {
+                recommended: version("0.9.30"),
+                current: version("0.9.25"),
+            }
+        );
+        assert_eq!(
+            config.eval(&version("0.9.10")),
+            NextestVersionEval::Error {
+                required: version("0.9.20"),
+                current: version("0.9.10"),
+            },
+            "required version takes precedence"
+        );
+
+        version_config(r#"nextest-version = "not-a-version""#)
+            .expect_err("invalid version is an error");
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let config_contents = r#"
+            nextest-version = "0.1.0"
+            future-setting = 42
+
+            [profile.default]
+            future-profile-setting = "foo"
+
+            [profile.ci]
+            retries = 2
+            future-profile-setting = ["bar"]
+        "#;
+        let config = config_from_str(config_contents).expect("unknown keys are ignored");
+        assert_eq!(config.profile("ci").unwrap().retries(), 2);
+    }
+
+    #[test]
+    fn target_runner_rules() {
+        let config_contents = r#"
+            [[profile.default.target-runner]]
+            platform = "cfg(target_arch = \"aarch64\")"
+            command = ["qemu-aarch64"]
+
+            [[profile.ci.target-runner]]
+            platform = "aarch64-unknown-linux-gnu"
+            command = ["ci-runner"]
+            list-command = []
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let platforms = |profile: &str| {
+            config
+                .profile(profile)
+                .unwrap()
+                .target_runner_rules()
+                .map(|rule| rule.platform().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(platforms("default"), ["cfg(target_arch = \"aarch64\")"]);
+        assert_eq!(
+            platforms("ci"),
+            [
+                "aarch64-unknown-linux-gnu",
+                "cfg(target_arch = \"aarch64\")"
+            ],
+            "rules in custom profiles take precedence"
+        );
+
+        config_from_str(
+            r#"
+            [[profile.default.target-runner]]
+            platform = "cfg(not-valid"
+            command = ["runner"]
+        "#,
+        )
+        .expect_err("invalid platform is an error");
+
+        let config = config_from_str(
+            r#"
+            [[profile.default.target-runner]]
+            platform = "aarch64-unknown-linux-gnu"
+            remote = { host = "ci@arm-board", dir = "/tmp/nextest", ssh-args = ["-p", "2222"] }
+        "#,
+        )
+        .expect("remote runner is valid");
+        let profile = config.profile("default").unwrap();
+        let rule = profile
+            .target_runner_rules()
+            .next()
+            .expect("rule is present");
+        let remote = rule.remote().expect("rule has a remote host");
+        assert_eq!(remote.host(), "ci@arm-board");
+        assert_eq!(remote.dir(), "/tmp/nextest");
+        assert_eq!(remote.ssh_args(), ["-p", "2222"]);
+        assert!(remote.scp_args().is_empty());
+        assert!(rule.command().is_empty());
+
+        config_from_str(
+            r#"
+            [[profile.default.target-runner]]
+            platform = "aarch64-unknown-linux-gnu"
+            command = ["runner"]
+            remote = { host = "ci@arm-board", dir = "/tmp/nextest" }
+        "#,
+        )
+        .expect_err("command and remote host are mutually exclusive");
+    }
+
+    #[test]
+    fn container_rules() {
+        let config_contents = r#"
+            [[profile.default.container]]
+            filter = "package(db-tests)"
+            image = "db-test-env:latest"
+
+            [[profile.ci.container]]
+            filter = "all()"
+            image = "ci-env:latest"
+            engine = "podman"
+            network = "none"
+            memory = "2g"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let images = |profile: &str| {
+            config
+                .profile(profile)
+                .unwrap()
+                .container_rules()
+                .map(|rule| rule.image().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(images("default"), ["db-test-env:latest"]);
+        assert_eq!(
+            images("ci"),
+            ["ci-env:latest", "db-test-env:latest"],
+            "rules in custom profiles take precedence"
+        );
+
+        let ci_profile = config.profile("ci").unwrap();
+        let rule = ci_profile.container_rules().next().unwrap();
+        assert_eq!(rule.engine(), ContainerEngine::Podman);
+        assert_eq!(rule.network(), Some("none"));
+        assert_eq!(rule.memory(), Some("2g"));
+        assert_eq!(rule.cpus(), None);
+
+        config_from_str(
+            r#"
+            [[profile.default.container]]
+            filter = "all()"
+            image = "env:latest"
+            engine = "lxc"
+        "#,
+        )
+        .expect_err("unknown engine is an error");
+    }
+
+    #[test]
+    fn other_tests() {
+        let config_contents = r#"
+            [[other-tests]]
+            name = "python"
+            package = "fake-package"
+            command = "python3"
+            args = ["-m", "pytest"]
+            list-command = ["python3", "-m", "pytest", "--collect-only", "-q"]
+
+            [[other-tests]]
+            name = "lint"
+            package = "fake-package"
+            command = "./lint.sh"
+            cwd = "scripts"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let other_tests = config.other_tests();
+        assert_eq!(other_tests.len(), 2);
+        assert_eq!(other_tests[0].args(), ["-m", "pytest"]);
+        assert_eq!(other_tests[0].list_command().map(|c| c.len()), Some(5));
+        assert_eq!(other_tests[0].cwd(), None);
+        assert_eq!(other_tests[1].command(), "./lint.sh");
+        assert_eq!(other_tests[1].cwd(), Some("scripts".as_ref()));
+        assert_eq!(other_tests[1].list_command(), None);
+
+        config_from_str(
+            r#"
+            [[other-tests]]
+            name = "missing-command"
+            package = "fake-package"
+        "#,
+        )
+        .expect_err("command is required");
+    }
+
+    #[test]
+    fn required_binaries() {
+        let config_contents = r#"
+            [[required-binaries]]
+            filter = "package(my-client)"
+            package = "my-server"
+            binary = "my-server"
+            env = "MY_SERVER_BIN"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let required_binaries = config.required_binaries();
+        assert_eq!(required_binaries.len(), 1);
+        assert_eq!(required_binaries[0].package(), "my-server");
+        assert_eq!(required_binaries[0].binary(), "my-server");
+        assert_eq!(required_binaries[0].env(), "MY_SERVER_BIN");
+
+        config_from_str(
+            r#"
+            [[required-binaries]]
+            filter = "package("
+            package = "my-server"
+            binary = "my-server"
+            env = "MY_SERVER_BIN"
+        "#,
+        )
+        .expect_err("filter is invalid");
+    }
+
+    #[test]
+    fn locale() {
+        let config_contents = r#"
+            [[profile.default.overrides]]
+            filter = "package(date-utils)"
+            locale = { tz = "America/New_York", lang = "en_US.UTF-8" }
+
+            [[profile.default.overrides]]
+            filter = "all()"
+            locale = true
+
+            [[profile.ci.overrides]]
+            filter = "test(native_locale)"
+            locale = false
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+
+        let query = TestQuery {
+            binary_id: "date-utils",
+            package_name: "date-utils",
+            test_name: "tests::native_locale",
+            platform: BuildPlatform::Target,
+        };
+        let other_query = TestQuery {
+            binary_id: "my-package",
+            package_name: "my-package",
+            test_name: "tests::native_locale",
+            platform: BuildPlatform::Target,
+        };
+
+        let default_profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        let locale = default_profile
+            .locale_for(&query)
+            .expect("override matches");
+        assert_eq!(
+            locale.vars().collect::<Vec<_>>(),
+            [
+                ("TZ", "America/New_York"),
+                ("LANG", "en_US.UTF-8"),
+                ("LC_ALL", "C")
+            ],
+            "missing keys use the defaults"
+        );
+        let locale = default_profile
+            .locale_for(&other_query)
+            .expect("override matches");
+        assert_eq!(locale.tz(), Some("UTC"));
+        assert_eq!(locale.lang(), None);
+        assert_eq!(locale.lc_all(), Some("C"));
+
+        let ci_profile = config.profile("ci").unwrap();
+        let locale = ci_profile.locale_for(&query).expect("override matches");
+        assert_eq!(
+            locale.vars().count(),
+            0,
+            "custom profile overrides take precedence"
+        );
+
+        config_from_str(
+            r#"
+            [[profile.default.overrides]]
+            filter = "all()"
+            locale = "UTC"
+            "#,
+        )
+        .expect_err("locale must be a boolean or a table");
+    }
+
+    #[test]
+    fn store_retention() {
+        let config = config_from_str("").expect("config is valid");
+        assert!(config.store_retention().keeps_everything());
+
+        let config_contents = r#"
+            [store.retention]
+            max-runs = 10
+            max-size = "1GiB"
+            max-age = "7d"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let retention = config.store_retention();
+        assert_eq!(retention.max_runs(), Some(10));
+        assert_eq!(retention.max_size(), Some(DiskQuota::new(1 << 30)));
+        assert_eq!(
+            retention.max_age(),
+            Some(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            config.store_dir(),
+            config_from_str("").unwrap().store_dir(),
+            "the store directory still defaults to target/nextest"
+        );
+    }
+
+    #[test]
+    fn test_groups() {
+        let config_contents = r#"
+            [test-groups.db]
+            setup = ["scripts/start-db.sh", "--port", "5432"]
+            teardown = ["scripts/stop-db.sh"]
+            timeout = "2m"
+
+            [test-groups.serial]
+
+            [[profile.default.overrides]]
+            filter = "package(db-tests)"
+            test-group = "db"
+
+            [[profile.ci.overrides]]
+            filter = "test(serial_)"
+            test-group = "serial"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+
+        let query = TestQuery {
+            binary_id: "db-tests",
+            package_name: "db-tests",
+            test_name: "tests::serial_insert",
+            platform: BuildPlatform::Target,
+        };
+        let other_query = TestQuery {
+            binary_id: "my-package",
+            package_name: "my-package",
+            test_name: "tests::insert",
+            platform: BuildPlatform::Target,
+        };
+
+        let default_profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        assert_eq!(default_profile.test_group_for(&query), Some("db"));
+        assert_eq!(default_profile.test_group_for(&other_query), None);
+        let db = &default_profile.test_groups()["db"];
+        assert_eq!(
+            db.setup(),
+            Some(
+                &[
+                    "scripts/start-db.sh".to_owned(),
+                    "--port".to_owned(),
+                    "5432".to_owned()
+                ][..]
+            )
+        );
+        assert_eq!(db.teardown(), Some(&["scripts/stop-db.sh".to_owned()][..]));
+        assert_eq!(db.timeout(), Some(Duration::from_secs(120)));
+        assert_eq!(default_profile.test_groups()["serial"].setup(), None);
+        assert_eq!(default_profile.test_groups()["serial"].timeout(), None);
+
+        let ci_profile = config.profile("ci").unwrap();
+        assert_eq!(
+            ci_profile.test_group_for(&query),
+            Some("serial"),
+            "custom profile overrides take precedence"
+        );
+
+        let err = config_from_str(
+            r#"
+            [[profile.ci.overrides]]
+            filter = "all()"
+            test-group = "missing"
+            "#,
+        )
+        .expect_err("test group is unknown");
+        let source = std::error::Error::source(&err).expect("error has a source");
+        assert_eq!(
+            source.to_string(),
+            "profile 'ci' has an override with unknown test group 'missing'"
+        );
+    }
+
+    fn config_from_str(contents: &str) -> Result<NextestConfig, ConfigParseError> {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = Utf8PathBuf::try_from(dir.path().join("nextest.toml")).unwrap();
+        std::fs::write(&config_path, contents).unwrap();
+        NextestConfig::from_sources("/fake/dir", Some(&config_path))
+    }
+
+    #[test]
+    fn config_summary() {
+        let config_contents = r#"
+            [store.retention]
+            max-runs = 5
+
+            [test-groups.db]
+            setup = ["start-db"]
+            timeout = "30s"
+
+            [[profile.default.overrides]]
+            filter = "package(db-tests)"
+            test-group = "db"
+
+            [profile.ci]
+            retries = 2
+            fail-fast = false
+
+            [[profile.ci.overrides]]
+            filter = "test(heavy_) or binary(bench)"
+            threads-required = "num-cpus"
+            time-limit = { limit = "1m", grace = "5s" }
+
+            [[profile.ci.multiplex]]
+            filter = "test(seeded)"
+            env = "SEED=1|2"
+
+            [[profile.ci.dependencies]]
+            filter = "test(=api::smoke)"
+            depends-on = "test(migrations)"
+        "#;
+        let config = config_from_str(config_contents).expect("config is valid");
+        let summary = config.to_summary();
+        assert_eq!(summary.workspace_root, "/fake/dir");
+        assert_eq!(summary.store.dir, "/fake/dir/target/nextest");
+        assert_eq!(summary.store.retention.max_runs, Some(5));
+        assert_eq!(summary.store.retention.max_size, None);
+        assert_eq!(
+            summary.test_groups["db"],
+            TestGroupSummary {
+                setup: Some(vec!["start-db".to_owned()]),
+                teardown: None,
+                timeout: Some(30.0),
+            }
+        );
+        assert_eq!(
+            summary.profiles.keys().collect::<Vec<_>>(),
+            ["ci", "default", "default-miri"]
+        );
+
+        let default = &summary.profiles["default"];
+        assert_eq!(default.store_dir, "/fake/dir/target/nextest/default");
+        assert_eq!(default.retries, 0);
+        assert!(default.fail_fast);
+        assert_eq!(default.overrides.len(), 1);
+        assert!(default.multiplex.is_empty());
+
+        let ci = &summary.profiles["ci"];
+        assert_eq!(ci.retries, 2);
+        assert!(!ci.fail_fast);
+        assert_eq!(ci.test_order, "binary");
+        let overrides: Vec<_> = ci
+            .overrides
+            .iter()
+            .map(|rule| rule.filter.compiled.as_str())
+            .collect();
+        assert_eq!(
+            overrides,
+            ["(test(~heavy_) | binary(=bench))", "package(=db-tests)"],
+            "the profile's own overrides come first"
+        );
+        assert_eq!(
+            ci.overrides[0].threads_required,
+            Some(ThreadsRequiredSummary::NumCpus)
+        );
+        assert_eq!(
+            ci.overrides[0].time_limit,
+            Some(TimeLimitSummary {
+                limit: 60.0,
+                grace: Some(5.0),
+            })
+        );
+        assert_eq!(ci.overrides[1].test_group.as_deref(), Some("db"));
+        assert_eq!(ci.multiplex[0].env_var, "SEED");
+        assert_eq!(ci.multiplex[0].values, ["1", "2"]);
+        assert_eq!(ci.dependencies[0].filter.expr, "test(=api::smoke)");
+        assert_eq!(ci.dependencies[0].depends_on.compiled, "test(~migrations)");
+
+        let json = serde_json::to_string(&summary).unwrap();
+        assert_eq!(ConfigSummary::parse_json(json).unwrap(), summary);
+    }
 }
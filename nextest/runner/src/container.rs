@@ -0,0 +1,318 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Running tests inside containers.
+//!
+//! Tests can be run inside a Docker or Podman image, for example to give them a database server or
+//! a different set of system libraries, through rules in the nextest profile:
+//!
+//! ```toml
+//! [[profile.default.container]]
+//! filter = "package(db-tests)"
+//! image = "ghcr.io/example/db-test-env:latest"
+//! # Optional: "docker" (the default) or "podman".
+//! engine = "podman"
+//! # Optional: the network mode, passed in as --network.
+//! network = "none"
+//! # Optional: resource limits, passed in as --memory and --cpus.
+//! memory = "2g"
+//! cpus = "1.5"
+//! ```
+//!
+//! The first rule whose filter matches a test is used. Rules in a profile take precedence over the
+//! ones in the profiles it inherits from.
+//!
+//! Each matching test is run in a new container, which is removed once the test exits. The
+//! workspace is bind-mounted at `/workspace`, and the directories test binaries were built in at
+//! `/nextest/target/<n>` if they aren't in the workspace. The working directory of the test and the
+//! environment variables nextest sets for it are mapped to the paths inside the container.
+//!
+//! Tests are still listed on this machine, and target runners aren't used for tests run inside
+//! containers. Containers only apply to Rust tests, not to [other tests](crate::other_tests).
+
+use crate::{build_meta::RustBuildMeta, filter_expr::FilterExpr, path_mapper::PathMapper};
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::{cmd, Expression};
+use nextest_metadata::BuildPlatform;
+use serde::Deserialize;
+use std::{ffi::OsStr, path::Path};
+
+/// The directory the workspace is mounted at inside containers.
+const CONTAINER_WORKSPACE_DIR: &str = "/workspace";
+
+/// The directory build output directories outside the workspace are mounted under.
+const CONTAINER_TARGET_DIR: &str = "/nextest/target";
+
+/// A rule that runs the tests matching a filter expression inside a container.
+///
+/// Obtained through
+/// [`NextestProfile::container_rules`](crate::config::NextestProfile::container_rules).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ContainerRule {
+    filter: FilterExpr,
+    image: String,
+    #[serde(default)]
+    engine: ContainerEngine,
+    #[serde(default)]
+    network: Option<String>,
+    #[serde(default)]
+    memory: Option<String>,
+    #[serde(default)]
+    cpus: Option<String>,
+}
+
+impl ContainerRule {
+    /// Returns the filter expression that selects tests for this rule.
+    pub fn filter(&self) -> &FilterExpr {
+        &self.filter
+    }
+
+    /// Returns the image tests are run in.
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    /// Returns the container engine used to run tests.
+    pub fn engine(&self) -> ContainerEngine {
+        self.engine
+    }
+
+    /// Returns the network mode of the container, if specified.
+    pub fn network(&self) -> Option<&str> {
+        self.network.as_deref()
+    }
+
+    /// Returns the memory limit of the container, if specified.
+    pub fn memory(&self) -> Option<&str> {
+        self.memory.as_deref()
+    }
+
+    /// Returns the number of CPUs the container can use, if specified.
+    pub fn cpus(&self) -> Option<&str> {
+        self.cpus.as_deref()
+    }
+
+    /// Returns the command that runs the given test binary inside a container.
+    ///
+    /// The working directory and environment variables of the test are determined right before the
+    /// command is spawned, so that the ones set on the expression later are picked up.
+    pub(crate) fn make_command(
+        &self,
+        binary_path: &Utf8Path,
+        build_platform: BuildPlatform,
+        args: Vec<&str>,
+        workspace_root: &Utf8Path,
+        build_meta: &RustBuildMeta,
+    ) -> Expression {
+        let mut path_mapper = PathMapper::new();
+        let mut mounts = vec![(
+            workspace_root.to_owned(),
+            CONTAINER_WORKSPACE_DIR.to_owned(),
+        )];
+        path_mapper.add(workspace_root, CONTAINER_WORKSPACE_DIR);
+        for (idx, dir) in build_meta
+            .base_output_directories(build_platform)
+            .enumerate()
+        {
+            if path_mapper.map_path(dir.as_str()).is_none() {
+                let mapped = format!("{}/{}", CONTAINER_TARGET_DIR, idx);
+                path_mapper.add(dir, mapped.clone());
+                mounts.push((dir.to_owned(), mapped));
+            }
+        }
+
+        // Binaries are normally in one of the mounted directories. Mount the binary on its own
+        // otherwise.
+        let container_binary = match path_mapper.map_path(binary_path.as_str()) {
+            Some(container_binary) => container_binary,
+            None => {
+                let container_binary = format!(
+                    "{}/bin/{}",
+                    CONTAINER_TARGET_DIR,
+                    binary_path.file_name().unwrap_or("test-binary")
+                );
+                mounts.push((binary_path.to_owned(), container_binary.clone()));
+                container_binary
+            }
+        };
+        let dylib_path = build_meta
+            .dylib_dirs(build_platform)
+            .iter()
+            .filter_map(|dir| path_mapper.map_path(dir.as_str()))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        let engine_args = self.engine_args(&mounts);
+        let args: Vec<String> = args.into_iter().map(|arg| arg.to_owned()).collect();
+        let image = self.image.clone();
+        cmd(self.engine.program(), engine_args).before_spawn(move |command| {
+            let run_args = run_args(
+                &image,
+                &path_mapper,
+                command.get_current_dir(),
+                command.get_envs(),
+                &dylib_path,
+                &container_binary,
+                &args,
+            );
+            command.args(run_args);
+            Ok(())
+        })
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    // Returns the arguments to the engine that don't depend on the test.
+    fn engine_args(&self, mounts: &[(Utf8PathBuf, String)]) -> Vec<String> {
+        let mut engine_args = vec!["run".to_owned(), "--rm".to_owned()];
+        for (local, mapped) in mounts {
+            engine_args.extend(["--volume".to_owned(), format!("{}:{}", local, mapped)]);
+        }
+        let options = [
+            ("--network", &self.network),
+            ("--memory", &self.memory),
+            ("--cpus", &self.cpus),
+        ];
+        for (flag, value) in options {
+            if let Some(value) = value {
+                engine_args.extend([flag.to_owned(), value.clone()]);
+            }
+        }
+        engine_args
+    }
+}
+
+// Returns the arguments to the engine that depend on the test: its working directory and
+// environment, followed by the image and the command to run inside it.
+fn run_args<'a>(
+    image: &str,
+    path_mapper: &PathMapper,
+    cwd: Option<&Path>,
+    envs: impl IntoIterator<Item = (&'a OsStr, Option<&'a OsStr>)>,
+    dylib_path: &str,
+    container_binary: &str,
+    args: &[String],
+) -> Vec<String> {
+    let mut run_args = vec![];
+    if let Some(cwd) = cwd {
+        run_args.extend([
+            "--workdir".to_owned(),
+            path_mapper.map_value(&cwd.to_string_lossy()),
+        ]);
+    }
+    for (key, value) in path_mapper.mapped_env(envs) {
+        run_args.extend(["--env".to_owned(), format!("{}={}", key, value)]);
+    }
+    if !dylib_path.is_empty() {
+        run_args.extend([
+            "--env".to_owned(),
+            format!("LD_LIBRARY_PATH={}", dylib_path),
+        ]);
+    }
+    run_args.push(image.to_owned());
+    run_args.push(container_binary.to_owned());
+    run_args.extend(args.iter().cloned());
+    run_args
+}
+
+/// The program used to run containers.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContainerEngine {
+    /// Docker.
+    #[default]
+    Docker,
+
+    /// Podman.
+    Podman,
+}
+
+impl ContainerEngine {
+    /// Returns the name of the program for this engine.
+    pub fn program(self) -> &'static str {
+        match self {
+            ContainerEngine::Docker => "docker",
+            ContainerEngine::Podman => "podman",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_args() {
+        let rule: ContainerRule = toml::from_str(
+            r#"
+                filter = "all()"
+                image = "test-env:latest"
+                network = "none"
+                cpus = "2"
+            "#,
+        )
+        .expect("rule is valid");
+        assert_eq!(rule.engine(), ContainerEngine::Docker);
+
+        let mounts = [
+            (
+                Utf8PathBuf::from("/workspace-root"),
+                CONTAINER_WORKSPACE_DIR.to_owned(),
+            ),
+            (
+                Utf8PathBuf::from("/shared-target/debug"),
+                format!("{}/0", CONTAINER_TARGET_DIR),
+            ),
+        ];
+        assert_eq!(
+            rule.engine_args(&mounts),
+            [
+                "run",
+                "--rm",
+                "--volume",
+                "/workspace-root:/workspace",
+                "--volume",
+                "/shared-target/debug:/nextest/target/0",
+                "--network",
+                "none",
+                "--cpus",
+                "2",
+            ]
+        );
+
+        let mut path_mapper = PathMapper::new();
+        for (local, mapped) in &mounts {
+            path_mapper.add(local, mapped.clone());
+        }
+        let envs = [(
+            OsStr::new("CARGO_MANIFEST_DIR"),
+            Some(OsStr::new("/workspace-root/my-crate")),
+        )];
+        assert_eq!(
+            run_args(
+                rule.image(),
+                &path_mapper,
+                Some(Path::new("/workspace-root/my-crate")),
+                envs,
+                "/nextest/target/0/deps",
+                "/nextest/target/0/deps/my_test-1234",
+                &["--exact".to_owned(), "tests::foo".to_owned()],
+            ),
+            [
+                "--workdir",
+                "/workspace/my-crate",
+                "--env",
+                "CARGO_MANIFEST_DIR=/workspace/my-crate",
+                "--env",
+                "LD_LIBRARY_PATH=/nextest/target/0/deps",
+                "test-env:latest",
+                "/nextest/target/0/deps/my_test-1234",
+                "--exact",
+                "tests::foo",
+            ]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Selecting tests based on the code they cover and the lines that have changed.
+//!
+//! With `cargo nextest run --select-by-coverage <map>`, only tests that cover code changed since an
+//! earlier instrumented run are run. The [`CoverageMap`] lists, for each test, the lines it covered
+//! in that run, along with the Git commit the run was made at. It's written out by
+//! `cargo nextest run --coverage` (see [`llvm_coverage`](crate::llvm_coverage)), or can be produced
+//! outside of nextest, for example from per-test `llvm-cov` exports.
+//!
+//! Lines that differ from the map's commit, including uncommitted changes, are read out of
+//! `git diff`. A test is run if it covered any of them, or if it isn't in the map at all (for
+//! example, because it was added since). Other tests are skipped with
+//! [`MismatchReason::Uncovered`](nextest_metadata::MismatchReason::Uncovered).
+//!
+//! Coverage only says which lines a test ran, so the selection is an approximation. It isn't used,
+//! and all tests are run instead, if:
+//! * the map's commit isn't an ancestor of `HEAD`, so its line numbers can't be matched up with the
+//!   changes, or
+//! * a file that affects how tests are built changed: a `Cargo.toml`, `Cargo.lock`, `build.rs`,
+//!   toolchain file, or anything in `.cargo`.
+
+use crate::errors::CoverageSelectionError;
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::cmd;
+use nextest_metadata::CoverageMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// The tests in a coverage map that don't cover any changed code.
+#[derive(Clone, Debug, Default)]
+pub struct CoverageSelection {
+    commit: String,
+    // Binary ID -> names of tests that don't cover any changed line.
+    uncovered: HashMap<String, HashSet<String>>,
+    uncovered_count: usize,
+}
+
+impl CoverageSelection {
+    /// Reads the coverage map at `map_path`, and matches it up with the changes made in the
+    /// workspace since the map's commit.
+    pub fn compute(
+        workspace_root: &Utf8Path,
+        map_path: &Utf8Path,
+    ) -> Result<Self, CoverageSelectionError> {
+        let json =
+            std::fs::read_to_string(map_path).map_err(|error| CoverageSelectionError::Read {
+                path: map_path.to_owned(),
+                error,
+            })?;
+        let map =
+            CoverageMap::parse_json(&json).map_err(|error| CoverageSelectionError::Parse {
+                path: map_path.to_owned(),
+                error,
+            })?;
+
+        let args = ["merge-base", "--is-ancestor", map.commit.as_str(), "HEAD"];
+        let output = cmd("git", args)
+            .dir(workspace_root)
+            .stdout_null()
+            .stderr_null()
+            .unchecked()
+            .run()
+            .map_err(|error| CoverageSelectionError::Git {
+                command: format!("git {}", args.join(" ")),
+                error,
+            })?;
+        if !output.status.success() {
+            return Err(CoverageSelectionError::Stale { commit: map.commit });
+        }
+
+        let diff = run_git(
+            workspace_root,
+            &[
+                "diff",
+                "--unified=0",
+                "--relative",
+                "--no-renames",
+                "--no-color",
+                "--no-ext-diff",
+                "--src-prefix=a/",
+                "--dst-prefix=b/",
+                &map.commit,
+                "--",
+            ],
+        )?;
+        let untracked = run_git(
+            workspace_root,
+            &["ls-files", "--others", "--exclude-standard", "-z"],
+        )?;
+        let changes = ChangedLines::parse_diff(&diff);
+        let untracked_paths = untracked
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(Utf8Path::new);
+        if let Some(path) = changes
+            .paths()
+            .chain(untracked_paths)
+            .find(|path| is_build_input(path))
+        {
+            return Err(CoverageSelectionError::BuildChanged {
+                path: path.to_owned(),
+            });
+        }
+
+        Ok(Self::new(&map, &changes))
+    }
+
+    /// Matches up a coverage map with the lines that changed since its commit.
+    pub fn new(map: &CoverageMap, changes: &ChangedLines) -> Self {
+        let mut uncovered: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut uncovered_count = 0;
+        for test in &map.tests {
+            let covers_change = test.files.iter().any(|(path, ranges)| {
+                ranges
+                    .iter()
+                    .any(|&[start, end]| changes.intersects(path, start, end))
+            });
+            if !covers_change
+                && uncovered
+                    .entry(test.binary_id.clone())
+                    .or_default()
+                    .insert(test.name.clone())
+            {
+                uncovered_count += 1;
+            }
+        }
+        Self {
+            commit: map.commit.clone(),
+            uncovered,
+            uncovered_count,
+        }
+    }
+
+    /// Returns the commit the coverage map was made at.
+    pub fn commit(&self) -> &str {
+        &self.commit
+    }
+
+    /// Returns the number of tests in the map that don't cover any changed code.
+    pub fn uncovered_count(&self) -> usize {
+        self.uncovered_count
+    }
+
+    /// Returns true if the given test is in the map, and doesn't cover any changed code.
+    pub fn is_uncovered(&self, binary_id: &str, name: &str) -> bool {
+        self.uncovered
+            .get(binary_id)
+            .is_some_and(|names| names.contains(name))
+    }
+}
+
+/// The lines of files that changed, numbered as they were before the change.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ChangedLines {
+    // Path -> inclusive ranges of changed lines.
+    files: BTreeMap<Utf8PathBuf, Vec<(u32, u32)>>,
+    // Every path that changed, before or after the change.
+    paths: BTreeSet<Utf8PathBuf>,
+}
+
+impl ChangedLines {
+    /// Parses the output of `git diff --unified=0 --src-prefix=a/ --dst-prefix=b/`.
+    pub fn parse_diff(diff: &str) -> Self {
+        let mut changes = Self::default();
+        let mut old_path: Option<Utf8PathBuf> = None;
+        let mut in_header = false;
+        for line in diff.lines() {
+            if line.starts_with("diff --git ") {
+                in_header = true;
+                old_path = None;
+            } else if in_header {
+                if let Some(path) = line.strip_prefix("--- ") {
+                    old_path = changes.add_path(path, "a/");
+                } else if let Some(path) = line.strip_prefix("+++ ") {
+                    changes.add_path(path, "b/");
+                } else if let Some(paths) = line
+                    .strip_prefix("Binary files ")
+                    .and_then(|paths| paths.strip_suffix(" differ"))
+                {
+                    // Binary files have no line numbers, so every line is considered changed.
+                    if let Some((old, new)) = paths.split_once(" and ") {
+                        if let Some(old_path) = changes.add_path(old, "a/") {
+                            changes
+                                .files
+                                .entry(old_path)
+                                .or_default()
+                                .push((1, u32::MAX));
+                        }
+                        changes.add_path(new, "b/");
+                    }
+                } else if line.starts_with("@@ ") {
+                    in_header = false;
+                }
+            }
+
+            if !in_header && line.starts_with("@@ ") {
+                if let (Some(path), Some(range)) = (&old_path, parse_hunk_header(line)) {
+                    changes.files.entry(path.clone()).or_default().push(range);
+                }
+            }
+        }
+        changes
+    }
+
+    /// Returns every path that changed, numbered as before or after the change.
+    pub fn paths(&self) -> impl Iterator<Item = &Utf8Path> + '_ {
+        self.paths.iter().map(|path| path.as_path())
+    }
+
+    /// Returns true if any line from `start` to `end` inclusive in the given file changed.
+    pub fn intersects(&self, path: &Utf8Path, start: u32, end: u32) -> bool {
+        self.files.get(path).is_some_and(|ranges| {
+            ranges
+                .iter()
+                .any(|&(changed_start, changed_end)| changed_start <= end && start <= changed_end)
+        })
+    }
+
+    /// Records a path from a header line, returning it without its prefix. New and deleted files
+    /// are named `/dev/null` on the other side.
+    fn add_path(&mut self, path: &str, prefix: &str) -> Option<Utf8PathBuf> {
+        let path = Utf8PathBuf::from(path.strip_prefix(prefix)?);
+        self.paths.insert(path.clone());
+        Some(path)
+    }
+}
+
+/// Parses the old side of a hunk header such as `@@ -12,3 +12,4 @@` into the inclusive range of
+/// lines that changed.
+fn parse_hunk_header(line: &str) -> Option<(u32, u32)> {
+    let old = line.strip_prefix("@@ -")?.split(' ').next()?;
+    let (start, count) = match old.split_once(',') {
+        Some((start, count)) => (start.parse::<u32>().ok()?, count.parse::<u32>().ok()?),
+        None => (old.parse::<u32>().ok()?, 1),
+    };
+    match count {
+        // Lines were only added, after `start`: count the lines on either side as changed.
+        0 => Some((start, start + 1)),
+        _ => Some((start, start + count - 1)),
+    }
+}
+
+/// Returns true if changes to the file at this path, relative to the workspace root, can change
+/// how tests are built.
+fn is_build_input(path: &Utf8Path) -> bool {
+    matches!(
+        path.file_name(),
+        Some("Cargo.toml" | "Cargo.lock" | "build.rs" | "rust-toolchain" | "rust-toolchain.toml")
+    ) || path.starts_with(".cargo")
+}
+
+fn run_git(dir: &Utf8Path, args: &[&str]) -> Result<String, CoverageSelectionError> {
+    cmd("git", args)
+        .dir(dir)
+        .stdout_capture()
+        .read()
+        .map_err(|error| CoverageSelectionError::Git {
+            command: format!("git {}", args.join(" ")),
+            error,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nextest_metadata::TestCoverage;
+
+    #[test]
+    fn select_by_coverage() {
+        let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -10,2 +10,3 @@ fn foo() {
+-    let a = 1;
+--- a/not-a-header
++    let a = 2;
++    let b = 3;
+@@ -40,0 +42 @@ fn bar() {
++    baz();
+diff --git a/src/old.rs b/src/old.rs
+deleted file mode 100644
+--- a/src/old.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-fn old() {}
+diff --git a/data.bin b/data.bin
+Binary files a/data.bin and b/data.bin differ
+";
+        let changes = ChangedLines::parse_diff(diff);
+        let paths: Vec<_> = changes.paths().map(|path| path.as_str()).collect();
+        assert_eq!(paths, ["data.bin", "src/lib.rs", "src/old.rs"]);
+        assert!(changes.intersects("src/lib.rs".into(), 5, 10));
+        assert!(changes.intersects("src/lib.rs".into(), 11, 11));
+        assert!(!changes.intersects("src/lib.rs".into(), 12, 39));
+        assert!(
+            changes.intersects("src/lib.rs".into(), 41, 60),
+            "line added after 40"
+        );
+        assert!(changes.intersects("src/old.rs".into(), 1, 1));
+        assert!(changes.intersects("data.bin".into(), 1000, 1000));
+        assert!(!changes.intersects("src/other.rs".into(), 1, 100));
+
+        let test = |name: &str, files: &[(&str, [u32; 2])]| TestCoverage {
+            binary_id: "my-crate".to_owned(),
+            name: name.to_owned(),
+            files: files
+                .iter()
+                .map(|(path, range)| (Utf8PathBuf::from(*path), vec![*range]))
+                .collect(),
+            functions: vec![],
+        };
+        let map = CoverageMap {
+            commit: "abc123".to_owned(),
+            tests: vec![
+                test("covers_foo", &[("src/lib.rs", [8, 12])]),
+                test("covers_other", &[("src/other.rs", [1, 50])]),
+                test(
+                    "covers_old",
+                    &[("src/lib.rs", [1, 2]), ("src/old.rs", [1, 1])],
+                ),
+            ],
+        };
+        let selection = CoverageSelection::new(&map, &changes);
+        assert_eq!(selection.commit(), "abc123");
+        assert_eq!(selection.uncovered_count(), 1);
+        assert!(selection.is_uncovered("my-crate", "covers_other"));
+        assert!(!selection.is_uncovered("my-crate", "covers_foo"));
+        assert!(!selection.is_uncovered("my-crate", "covers_old"));
+        assert!(
+            !selection.is_uncovered("my-crate", "new_test"),
+            "tests missing from the map are run"
+        );
+
+        assert!(is_build_input("Cargo.lock".into()));
+        assert!(is_build_input("crates/foo/build.rs".into()));
+        assert!(is_build_input(".cargo/config.toml".into()));
+        assert!(!is_build_input("src/lib.rs".into()));
+    }
+}
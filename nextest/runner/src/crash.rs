@@ -0,0 +1,474 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Decoding why tests crashed, and symbolizing their backtraces.
+//!
+//! A test that is killed by a signal on Unix, or that exits with an NT status code such as
+//! `STATUS_ACCESS_VIOLATION` on Windows, usually doesn't get to print anything useful about why.
+//! Nextest decodes the exit status into an [`AbortStatus`], and notes it at the end of the test's
+//! standard error, for example:
+//!
+//! ```text
+//! nextest: test aborted with SIGSEGV (invalid memory reference)
+//! ```
+//!
+//! # Symbolizers
+//!
+//! A profile can also configure a symbolizer: a command that is run after a test aborts, to produce
+//! a backtrace from a core dump or minidump with a tool like `llvm-symbolizer`, `gdb` or `cdb`.
+//!
+//! ```toml
+//! [profile.ci.crash-symbolizer]
+//! command = ["scripts/symbolize.sh"]
+//! core-pattern = "/tmp/cores/core.{pid}"
+//! ```
+//!
+//! The command is run in the workspace root with these environment variables set:
+//!
+//! * [`NEXTEST_CRASH_BINARY_ENV`]: the path to the test binary.
+//! * [`NEXTEST_CRASH_PID_ENV`]: the process ID of the test, if known.
+//! * [`NEXTEST_CRASH_REASON_ENV`]: the description of the abort status, as above.
+//! * [`NEXTEST_CRASH_CORE_ENV`]: the path to the core dump or minidump, if `core-pattern` is
+//!   specified and a file exists at it. `{pid}` in the pattern is replaced with the process ID of
+//!   the test, and relative paths are relative to the workspace root.
+//!
+//! Its standard output and standard error are saved to the run directory and attached to the
+//! test's result, so they're displayed along with the test's output and referenced from reports.
+//!
+//! # Core dumps
+//!
+//! Core dumps are usually disabled through a core file size limit of 0. A profile can enable them
+//! for tests, and collect the dumps of tests that abort into the run directory:
+//!
+//! ```toml
+//! [profile.ci.core-dumps]
+//! enable = true
+//! pattern = "core.{pid}"
+//! ```
+//!
+//! On Unix, tests are run with their core file size limit (`RLIMIT_CORE`) raised to the hard
+//! limit. Where the dump is written is up to the system: `pattern` is the path it's expected at,
+//! relative to the working directory of the test, with `{pid}` replaced with the process ID of the
+//! test. The default, `core.{pid}`, matches Linux systems with `kernel.core_uses_pid = 1` and
+//! `kernel.core_pattern = core`. On Windows, minidumps are written by Windows Error Reporting once a
+//! `LocalDumps` registry key has been set up, so `pattern` should point to the dump folder, for
+//! example `C:/dumps/my_tests.exe.{pid}.dmp`.
+//!
+//! A dump found at `pattern` after a test aborts is moved into the run directory, noted in the
+//! test's standard error and in the run report, and passed to the symbolizer if one is configured.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::{cmd, Expression};
+use serde::Deserialize;
+use std::{fmt, fs, process::ExitStatus};
+
+/// The environment variable set to the path of the test binary when running a symbolizer.
+pub const NEXTEST_CRASH_BINARY_ENV: &str = "NEXTEST_CRASH_BINARY";
+
+/// The environment variable set to the process ID of the test when running a symbolizer.
+pub const NEXTEST_CRASH_PID_ENV: &str = "NEXTEST_CRASH_PID";
+
+/// The environment variable set to the description of why the test aborted when running a
+/// symbolizer.
+pub const NEXTEST_CRASH_REASON_ENV: &str = "NEXTEST_CRASH_REASON";
+
+/// The environment variable set to the path of the core dump or minidump of the test when running a
+/// symbolizer, if there is one.
+pub const NEXTEST_CRASH_CORE_ENV: &str = "NEXTEST_CRASH_CORE";
+
+/// Why a test process aborted rather than exiting normally.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AbortStatus {
+    /// The process was killed by a Unix signal.
+    UnixSignal {
+        /// The signal number.
+        signal: i32,
+
+        /// Whether the process dumped core.
+        core_dumped: bool,
+    },
+
+    /// The process exited with an NT status code that indicates a crash on Windows.
+    WindowsNtStatus(u32),
+}
+
+impl AbortStatus {
+    /// Returns the abort status of a process that exited with the given status, or `None` if it
+    /// exited normally.
+    pub fn from_exit_status(exit_status: ExitStatus) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            exit_status.signal().map(|signal| AbortStatus::UnixSignal {
+                signal,
+                core_dumped: exit_status.core_dumped(),
+            })
+        }
+        #[cfg(windows)]
+        {
+            exit_status
+                .code()
+                .map(|code| code as u32)
+                .filter(|&code| nt_status_name(code).is_some() || code & 0xc000_0000 == 0xc000_0000)
+                .map(AbortStatus::WindowsNtStatus)
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = exit_status;
+            None
+        }
+    }
+}
+
+impl fmt::Display for AbortStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            AbortStatus::UnixSignal {
+                signal,
+                core_dumped,
+            } => {
+                match signal_name(signal) {
+                    Some((name, description)) => write!(f, "{} ({})", name, description)?,
+                    None => write!(f, "signal {}", signal)?,
+                }
+                if core_dumped {
+                    write!(f, ", core dumped")?;
+                }
+                Ok(())
+            }
+            AbortStatus::WindowsNtStatus(code) => match nt_status_name(code) {
+                Some((name, description)) => {
+                    write!(f, "{} ({}, {:#010x})", name, description, code)
+                }
+                None => write!(f, "NT status {:#010x}", code),
+            },
+        }
+    }
+}
+
+/// Returns the name and a description of common signals that tests abort with.
+fn signal_name(signal: i32) -> Option<(&'static str, &'static str)> {
+    #[cfg(unix)]
+    {
+        let name = match signal {
+            libc::SIGHUP => ("SIGHUP", "hangup"),
+            libc::SIGINT => ("SIGINT", "interrupted"),
+            libc::SIGQUIT => ("SIGQUIT", "quit"),
+            libc::SIGILL => ("SIGILL", "illegal instruction"),
+            libc::SIGTRAP => ("SIGTRAP", "trace or breakpoint trap"),
+            libc::SIGABRT => ("SIGABRT", "aborted"),
+            libc::SIGBUS => ("SIGBUS", "bus error, such as a misaligned memory access"),
+            libc::SIGFPE => ("SIGFPE", "arithmetic exception"),
+            libc::SIGKILL => ("SIGKILL", "killed"),
+            libc::SIGSEGV => ("SIGSEGV", "invalid memory reference"),
+            libc::SIGPIPE => ("SIGPIPE", "broken pipe"),
+            libc::SIGALRM => ("SIGALRM", "alarm clock"),
+            libc::SIGTERM => ("SIGTERM", "terminated"),
+            libc::SIGSYS => ("SIGSYS", "bad system call"),
+            libc::SIGXCPU => ("SIGXCPU", "CPU time limit exceeded"),
+            libc::SIGXFSZ => ("SIGXFSZ", "file size limit exceeded"),
+            _ => return None,
+        };
+        Some(name)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal;
+        None
+    }
+}
+
+/// Returns the name and a description of common NT status codes that tests crash with.
+fn nt_status_name(code: u32) -> Option<(&'static str, &'static str)> {
+    let name = match code {
+        0x8000_0003 => ("STATUS_BREAKPOINT", "breakpoint"),
+        0xc000_0005 => ("STATUS_ACCESS_VIOLATION", "invalid memory reference"),
+        0xc000_001d => ("STATUS_ILLEGAL_INSTRUCTION", "illegal instruction"),
+        0xc000_0094 => ("STATUS_INTEGER_DIVIDE_BY_ZERO", "integer division by zero"),
+        0xc000_00fd => ("STATUS_STACK_OVERFLOW", "stack overflow"),
+        0xc000_0135 => ("STATUS_DLL_NOT_FOUND", "a required DLL was not found"),
+        0xc000_0139 => (
+            "STATUS_ENTRYPOINT_NOT_FOUND",
+            "a required DLL entry point was not found",
+        ),
+        0xc000_0409 => (
+            "STATUS_STACK_BUFFER_OVERRUN",
+            "fail-fast exception, such as an abort",
+        ),
+        0xc000_0374 => ("STATUS_HEAP_CORRUPTION", "heap corruption"),
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Runs the symbolizer for a test that aborted, returning what it printed, or a note about why it
+/// couldn't be run.
+pub(crate) fn run_symbolizer(
+    symbolizer: &CrashSymbolizer,
+    workspace_root: &Utf8Path,
+    binary_path: &Utf8Path,
+    pid: Option<u32>,
+    abort_status: AbortStatus,
+    core_dump: Option<&Utf8Path>,
+) -> Vec<u8> {
+    let (program, args) = match symbolizer.command().split_first() {
+        Some(command) => command,
+        None => return vec![],
+    };
+    let mut expression = cmd(program, args)
+        .dir(workspace_root)
+        .env(NEXTEST_CRASH_BINARY_ENV, binary_path)
+        .env(NEXTEST_CRASH_REASON_ENV, abort_status.to_string())
+        .stdin_null()
+        .stderr_to_stdout()
+        .stdout_capture()
+        .unchecked();
+    if let Some(pid) = pid {
+        expression = expression.env(NEXTEST_CRASH_PID_ENV, pid.to_string());
+    }
+    // Prefer a dump that was already collected.
+    let core_path = match core_dump {
+        Some(core_dump) => Some(core_dump.to_path_buf()),
+        None => symbolizer
+            .core_path(workspace_root, pid)
+            .filter(|core_path| core_path.exists()),
+    };
+    if let Some(core_path) = core_path {
+        expression = expression.env(NEXTEST_CRASH_CORE_ENV, core_path);
+    }
+
+    match expression.run() {
+        Ok(output) if output.status.success() => output.stdout,
+        Ok(output) => {
+            let mut stdout = output.stdout;
+            stdout.extend_from_slice(
+                format!("\n(nextest: '{}' exited with {})\n", program, output.status).as_bytes(),
+            );
+            stdout
+        }
+        Err(error) => format!("(nextest: failed to run '{}': {})\n", program, error).into_bytes(),
+    }
+}
+
+/// A command run after a test aborts to produce a backtrace.
+///
+/// Obtained through
+/// [`NextestProfile::crash_symbolizer`](crate::config::NextestProfile::crash_symbolizer).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CrashSymbolizer {
+    command: Vec<String>,
+    #[serde(default)]
+    core_pattern: Option<String>,
+}
+
+impl CrashSymbolizer {
+    /// Creates a new symbolizer that runs the given command, with the core dump or minidump at
+    /// `core_pattern` if specified.
+    pub fn new(command: Vec<String>, core_pattern: Option<String>) -> Self {
+        Self {
+            command,
+            core_pattern,
+        }
+    }
+
+    /// Returns the command that is run, as a program followed by its arguments.
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    /// Returns the path to core dumps or minidumps, with `{pid}` standing for the process ID of
+    /// the test, if specified.
+    pub fn core_pattern(&self) -> Option<&str> {
+        self.core_pattern.as_deref()
+    }
+
+    /// Returns the path to the core dump or minidump of the process with the given ID, if
+    /// `core-pattern` is specified.
+    pub fn core_path(&self, workspace_root: &Utf8Path, pid: Option<u32>) -> Option<Utf8PathBuf> {
+        expand_pattern(self.core_pattern()?, workspace_root, pid)
+    }
+}
+
+/// Whether tests can dump core, and where their dumps are collected from.
+///
+/// Obtained through [`NextestProfile::core_dumps`](crate::config::NextestProfile::core_dumps).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CoreDumps {
+    enable: bool,
+    #[serde(default = "default_core_pattern")]
+    pattern: String,
+}
+
+fn default_core_pattern() -> String {
+    "core.{pid}".to_owned()
+}
+
+impl CoreDumps {
+    /// Returns true if tests can dump core.
+    pub fn enable(&self) -> bool {
+        self.enable
+    }
+
+    /// Returns the path dumps are expected at, relative to the working directory of the test, with
+    /// `{pid}` standing for the process ID of the test.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Lets the process run by the expression dump core, if enabled.
+    pub(crate) fn apply(&self, expression: Expression) -> Expression {
+        if !self.enable {
+            return expression;
+        }
+        #[cfg(unix)]
+        {
+            expression.before_spawn(|command| {
+                use std::os::unix::process::CommandExt;
+                // SAFETY: setrlimit and getrlimit are async-signal-safe, and nothing is allocated.
+                unsafe {
+                    command.pre_exec(|| {
+                        let mut limit = libc::rlimit {
+                            rlim_cur: 0,
+                            rlim_max: 0,
+                        };
+                        if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) == 0 {
+                            limit.rlim_cur = limit.rlim_max;
+                            libc::setrlimit(libc::RLIMIT_CORE, &limit);
+                        }
+                        Ok(())
+                    });
+                }
+                Ok(())
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            expression
+        }
+    }
+
+    /// Moves the dump of the process with the given ID, run in `cwd`, to `dest`. Returns false if
+    /// there's no dump to collect.
+    pub(crate) fn collect(&self, cwd: &Utf8Path, pid: Option<u32>, dest: &Utf8Path) -> bool {
+        if !self.enable {
+            return false;
+        }
+        let Some(path) = expand_pattern(&self.pattern, cwd, pid).filter(|path| path.is_file())
+        else {
+            return false;
+        };
+        // Renaming fails across file systems, in which case the dump is copied instead.
+        fs::rename(&path, dest).is_ok()
+            || (fs::copy(&path, dest).is_ok() && fs::remove_file(&path).is_ok())
+    }
+}
+
+/// Replaces `{pid}` in the pattern with the process ID, and makes the path absolute. Returns `None`
+/// if the pattern needs the process ID but it isn't known.
+fn expand_pattern(pattern: &str, dir: &Utf8Path, pid: Option<u32>) -> Option<Utf8PathBuf> {
+    let path = match pid {
+        Some(pid) => pattern.replace("{pid}", &pid.to_string()),
+        None if pattern.contains("{pid}") => return None,
+        None => pattern.to_owned(),
+    };
+    Some(dir.join(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use duct::cmd;
+
+    #[test]
+    fn describe_abort_status() {
+        assert_eq!(
+            AbortStatus::WindowsNtStatus(0xc000_0005).to_string(),
+            "STATUS_ACCESS_VIOLATION (invalid memory reference, 0xc0000005)"
+        );
+        assert_eq!(
+            AbortStatus::WindowsNtStatus(0xc000_1234).to_string(),
+            "NT status 0xc0001234"
+        );
+        #[cfg(unix)]
+        {
+            assert_eq!(
+                AbortStatus::UnixSignal {
+                    signal: libc::SIGSEGV,
+                    core_dumped: true,
+                }
+                .to_string(),
+                "SIGSEGV (invalid memory reference), core dumped"
+            );
+            assert_eq!(
+                AbortStatus::UnixSignal {
+                    signal: 1000,
+                    core_dumped: false,
+                }
+                .to_string(),
+                "signal 1000"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn core_dumps() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let core_dumps = CoreDumps {
+            enable: true,
+            pattern: default_core_pattern(),
+        };
+
+        let limits = core_dumps
+            .apply(cmd!("sh", "-c", "echo $(ulimit -c) $(ulimit -Hc)"))
+            .read()
+            .unwrap();
+        let (soft, hard) = limits.split_once(' ').unwrap();
+        assert_eq!(soft, hard, "soft limit raised to the hard limit");
+
+        let dest = root.join("collected.core");
+        assert!(!core_dumps.collect(&root, Some(42), &dest), "no dump yet");
+        fs::write(root.join("core.42"), "dump").unwrap();
+        assert!(!core_dumps.collect(&root, None, &dest), "pid unknown");
+        assert!(core_dumps.collect(&root, Some(42), &dest));
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "dump");
+        assert!(!root.join("core.42").exists(), "dump moved");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symbolizer() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        std::fs::write(root.join("core.42"), "").unwrap();
+        let symbolizer = CrashSymbolizer::new(
+            vec![
+                "sh".to_owned(),
+                "-c".to_owned(),
+                "echo \"$NEXTEST_CRASH_PID $NEXTEST_CRASH_REASON\"; basename \"$NEXTEST_CRASH_CORE\""
+                    .to_owned(),
+            ],
+            Some("core.{pid}".to_owned()),
+        );
+        let abort_status = AbortStatus::UnixSignal {
+            signal: libc::SIGABRT,
+            core_dumped: false,
+        };
+        let output = run_symbolizer(
+            &symbolizer,
+            &root,
+            Utf8Path::new("test-binary"),
+            Some(42),
+            abort_status,
+            None,
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "42 SIGABRT (aborted)\ncore.42\n"
+        );
+        assert_eq!(symbolizer.core_path(&root, None), None, "pid unknown");
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Custom reporters: external processes that consume the events of a run.
+//!
+//! Organizations can write their own reporters, for example to upload results to an in-house
+//! dashboard, without forking nextest. Reporters are defined at the top level of the config:
+//!
+//! ```toml
+//! [reporters.custom.dashboard]
+//! command = ["dashboard-reporter", "--url", "https://dashboard.example.com"]
+//! ```
+//!
+//! Each reporter is started in the workspace root at the beginning of `cargo nextest run`, and
+//! talks to nextest over its standard input and output:
+//!
+//! 1. Nextest writes a [`ReporterHandshake`] as a single line of JSON to the reporter's standard
+//!    input. It lists the protocol versions nextest supports.
+//! 2. The reporter replies with a [`ReporterHandshakeResponse`] on a single line of its standard
+//!    output, choosing one of those versions. If it doesn't reply within 30 seconds, or picks a
+//!    version nextest doesn't support, the run fails before any tests are started.
+//! 3. Nextest writes each event of the run to the reporter's standard input as a line of JSON, in
+//!    the same format as the [event stream](crate::event_stream). The schema is documented by
+//!    [`RunEvent`](nextest_metadata::RunEvent) in the `nextest-metadata` crate.
+//! 4. Once the run finishes, nextest closes the reporter's standard input and waits for it to
+//!    exit. If it exits with a non-zero status, the run fails.
+//!
+//! Anything the reporter writes to its standard output after the handshake, and everything it
+//! writes to its standard error, is passed through to nextest's standard error.
+//!
+//! If the reporter exits early, nextest stops writing events to it but carries on with the run.
+
+use crate::{
+    errors::CustomReporterError, event_stream::to_run_events, reporter::TestEvent,
+    run_store::RunStore,
+};
+use camino::Utf8Path;
+use nextest_metadata::{ReporterHandshake, ReporterHandshakeResponse};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// How long a reporter has to reply to the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configuration for reporters, under `[reporters]`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ReportersConfig {
+    #[serde(default)]
+    pub(crate) custom: BTreeMap<String, CustomReporterConfig>,
+}
+
+/// The configuration for a custom reporter.
+///
+/// Obtained through
+/// [`NextestConfig::custom_reporters`](crate::config::NextestConfig::custom_reporters).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CustomReporterConfig {
+    command: Vec<String>,
+}
+
+impl CustomReporterConfig {
+    /// Returns the command the reporter is started with.
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+}
+
+/// A running custom reporter.
+pub struct CustomReporter<'a> {
+    name: String,
+    run_store: &'a RunStore,
+    child: Child,
+    // None once the reporter has stopped reading events.
+    stdin: Option<BufWriter<ChildStdin>>,
+    stdout_thread: Option<JoinHandle<()>>,
+}
+
+impl<'a> CustomReporter<'a> {
+    /// Starts the custom reporter `name` in `workspace_root`, and performs the handshake with it.
+    pub fn start(
+        name: &str,
+        config: &CustomReporterConfig,
+        workspace_root: &Utf8Path,
+        run_store: &'a RunStore,
+        nextest_version: &str,
+    ) -> Result<Self, CustomReporterError> {
+        let (program, args) =
+            config
+                .command
+                .split_first()
+                .ok_or_else(|| CustomReporterError::EmptyCommand {
+                    name: name.to_owned(),
+                })?;
+        let mut child = Command::new(program)
+            .args(args)
+            .current_dir(workspace_root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| CustomReporterError::Spawn {
+                name: name.to_owned(),
+                error,
+            })?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let mut reporter = Self {
+            name: name.to_owned(),
+            run_store,
+            child,
+            stdin: Some(BufWriter::new(stdin)),
+            stdout_thread: None,
+        };
+        if let Err(err) = reporter.handshake(stdout, nextest_version) {
+            let _ = reporter.child.kill();
+            let _ = reporter.child.wait();
+            return Err(err);
+        }
+        Ok(reporter)
+    }
+
+    fn handshake(
+        &mut self,
+        stdout: impl io::Read + Send + 'static,
+        nextest_version: &str,
+    ) -> Result<(), CustomReporterError> {
+        let handshake = ReporterHandshake::new(nextest_version);
+        let handshake_err = |reason: String| CustomReporterError::Handshake {
+            name: self.name.clone(),
+            reason,
+        };
+
+        let stdin = self
+            .stdin
+            .as_mut()
+            .expect("stdin is open during the handshake");
+        serde_json::to_writer(&mut *stdin, &handshake)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(stdin))
+            .and_then(|()| stdin.flush())
+            .map_err(|error| handshake_err(format!("failed to write handshake: {}", error)))?;
+
+        // Read the response on a separate thread so that it can time out. Once the handshake is
+        // done, the thread passes the rest of the reporter's output through to stderr.
+        let (sender, receiver) = mpsc::channel();
+        self.stdout_thread = Some(thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            let mut line = String::new();
+            let result = stdout.read_line(&mut line).map(|_| line);
+            let done = result.is_err();
+            let _ = sender.send(result);
+            if !done {
+                let _ = io::copy(&mut stdout, &mut io::stderr());
+            }
+        }));
+
+        let line = match receiver.recv_timeout(HANDSHAKE_TIMEOUT) {
+            Ok(Ok(line)) => line,
+            Ok(Err(error)) => {
+                return Err(handshake_err(format!(
+                    "failed to read handshake response: {}",
+                    error
+                )))
+            }
+            Err(_) => {
+                return Err(handshake_err(format!(
+                    "no response after {} seconds",
+                    HANDSHAKE_TIMEOUT.as_secs()
+                )))
+            }
+        };
+        if line.is_empty() {
+            return Err(handshake_err(
+                "reporter exited without responding".to_owned(),
+            ));
+        }
+        let response: ReporterHandshakeResponse = serde_json::from_str(&line).map_err(|error| {
+            handshake_err(format!("invalid response {:?}: {}", line.trim_end(), error))
+        })?;
+        if !handshake
+            .protocol_versions
+            .contains(&response.protocol_version)
+        {
+            return Err(handshake_err(format!(
+                "reporter chose protocol version {}, but nextest only supports {:?}",
+                response.protocol_version, handshake.protocol_versions
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the name of this reporter.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sends a test event to the reporter, as zero or more
+    /// [`RunEvent`](nextest_metadata::RunEvent)s.
+    ///
+    /// Errors are not returned: if the reporter has stopped reading events, this and later events
+    /// are dropped, and [`finish`](Self::finish) reports how the reporter exited.
+    pub fn write_event(&mut self, event: &TestEvent<'_>) {
+        let stdin = match &mut self.stdin {
+            Some(stdin) => stdin,
+            None => return,
+        };
+        let result = to_run_events(self.run_store, event)
+            .iter()
+            .try_for_each(|run_event| {
+                serde_json::to_writer(&mut *stdin, run_event)?;
+                writeln!(stdin)
+            })
+            .and_then(|()| stdin.flush());
+        if result.is_err() {
+            self.stdin = None;
+        }
+    }
+
+    /// Closes the reporter's standard input, and waits for it to exit.
+    ///
+    /// Returns an error if the reporter exited with a non-zero status.
+    pub fn finish(mut self) -> Result<(), CustomReporterError> {
+        if let Some(mut stdin) = self.stdin.take() {
+            let _ = stdin.flush();
+        }
+        let status = self
+            .child
+            .wait()
+            .map_err(|error| CustomReporterError::Wait {
+                name: self.name.clone(),
+                error,
+            })?;
+        if let Some(stdout_thread) = self.stdout_thread.take() {
+            let _ = stdout_thread.join();
+        }
+        if status.success() {
+            Ok(())
+        } else {
+            Err(CustomReporterError::Exited {
+                name: self.name,
+                status,
+            })
+        }
+    }
+}
+
+impl<'a> fmt::Debug for CustomReporter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CustomReporter")
+            .field("name", &self.name)
+            .field("run_store", &self.run_store)
+            .field("pid", &self.child.id())
+            .field("reading_events", &self.stdin.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Accounting for how much each test writes to disk.
+//!
+//! Each test is run with [`NEXTEST_TEST_TMPDIR_ENV`](crate::run_store::NEXTEST_TEST_TMPDIR_ENV)
+//! set to an empty directory of its own. Once the test finishes, nextest adds up the sizes of the
+//! files in it and of the test's [log file](crate::run_store::NEXTEST_TEST_LOG_FILE_ENV), and
+//! removes the directory. The tests that wrote the most are listed at the end of the run.
+//!
+//! A quota can be set for tests through overrides, either as a number of bytes or as a string
+//! with a unit:
+//!
+//! ```toml
+//! [[profile.default.overrides]]
+//! filter = "package(image-processing)"
+//! disk-quota = "500MiB"
+//! ```
+//!
+//! Tests that write more than their quota fail with a distinct status, even if they would
+//! otherwise have passed. The quota is checked once the test has finished.
+
+use camino::Utf8Path;
+use serde::{de, Deserialize, Deserializer};
+use std::{fmt, fs, path::Path};
+
+/// The maximum number of bytes a test may write to disk.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct DiskQuota(u64);
+
+impl DiskQuota {
+    /// Creates a new quota of the given number of bytes.
+    pub fn new(bytes: u64) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the quota in bytes.
+    pub fn bytes(self) -> u64 {
+        self.0
+    }
+
+    // Parses a size such as "512", "100KB" or "1.5GiB". Units with an "i" are powers of 1024, and
+    // the others are powers of 1000.
+    fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let split = input
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(input.len());
+        let (number, unit) = input.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1000 * 1000,
+            "g" | "gb" => 1000 * 1000 * 1000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            _ => return None,
+        };
+        Some(Self((number * multiplier as f64) as u64))
+    }
+}
+
+impl fmt::Display for DiskQuota {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_bytes(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for DiskQuota {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DiskQuotaVisitor;
+
+        impl<'de> de::Visitor<'de> for DiskQuotaVisitor {
+            type Value = DiskQuota;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a number of bytes or a size such as \"500MiB\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(DiskQuota(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                match u64::try_from(value) {
+                    Ok(value) => self.visit_u64(value),
+                    Err(_) => Err(E::invalid_value(de::Unexpected::Signed(value), &self)),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                DiskQuota::parse(value)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_any(DiskQuotaVisitor)
+    }
+}
+
+/// Formats a number of bytes for display, such as `1.5 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next_unit in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+/// Returns the total size of the files under `dir`, not following symlinks. Files that can't be
+/// read are ignored.
+pub(crate) fn dir_size(dir: &Utf8Path) -> u64 {
+    dir_size_impl(dir.as_std_path())
+}
+
+fn dir_size_impl(dir: &Path) -> u64 {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size_impl(&entry.path()),
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotas_and_sizes() {
+        assert_eq!(DiskQuota::parse("512"), Some(DiskQuota(512)));
+        assert_eq!(DiskQuota::parse("100KB"), Some(DiskQuota(100_000)));
+        assert_eq!(DiskQuota::parse("1.5 GiB"), Some(DiskQuota(3 << 29)));
+        assert_eq!(DiskQuota::parse("10mib"), Some(DiskQuota(10 << 20)));
+        assert_eq!(DiskQuota::parse("10 parsecs"), None);
+        assert_eq!(DiskQuota::parse("MB"), None);
+
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(10 << 20), "10.0 MiB");
+
+        let dir = tempfile::tempdir().expect("created temp dir");
+        let dir = Utf8Path::from_path(dir.path()).expect("temp dir is valid UTF-8");
+        fs::create_dir_all(dir.join("nested")).expect("created nested dir");
+        fs::write(dir.join("a"), [0; 100]).expect("wrote file");
+        fs::write(dir.join("nested/b"), [0; 23]).expect("wrote file");
+        assert_eq!(dir_size(dir), 123);
+        assert_eq!(dir_size(&dir.join("missing")), 0);
+    }
+}
@@ -0,0 +1,404 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Comparing the environment a run was performed in against the local environment.
+//!
+//! The final summary of each run records the parts of its environment that are likely to affect
+//! the results of tests: the Rust toolchain, the features tests were built with, and environment
+//! variables such as `RUSTFLAGS`, `TZ` and `CARGO_*`. Variables whose names suggest that they hold
+//! secrets, such as `CARGO_REGISTRY_TOKEN`, are never recorded.
+//!
+//! `cargo nextest env-diff <run-summary>` compares the environment recorded in a summary, for
+//! example one downloaded from CI, against the local environment, to help track down tests that
+//! only fail in one of them.
+
+use crate::{errors::EnvDiffError, test_list::OutputFormat};
+use nextest_metadata::{
+    EnvDiffSummary, EnvDifferenceKind, EnvDifferenceSummary, EnvironmentSummary, RunSummary,
+};
+use owo_colors::{OwoColorize, Style};
+use std::{collections::BTreeSet, env, io, io::Write};
+
+// Environment variables with these prefixes, or with these names, are recorded.
+const RECORDED_VAR_PREFIXES: &[&str] = &["CARGO_", "LC_", "NEXTEST_", "RUST"];
+const RECORDED_VARS: &[&str] = &["CI", "LANG", "TZ"];
+
+// Environment variables whose names contain any of these are never recorded.
+const SECRET_VAR_PATTERNS: &[&str] = &["CREDENTIAL", "KEY", "PASSWORD", "SECRET", "TOKEN"];
+
+/// Records the parts of the current environment that are likely to affect the results of tests.
+///
+/// `rustc_version` is the output of `rustc --version`, and the feature options are the ones passed
+/// in to Cargo.
+pub fn record_environment(
+    rustc_version: Option<String>,
+    features: &[String],
+    all_features: bool,
+    no_default_features: bool,
+) -> EnvironmentSummary {
+    let mut features: Vec<_> = features
+        .iter()
+        .flat_map(|features| features.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|feature| !feature.is_empty())
+        .map(|feature| feature.to_owned())
+        .collect();
+    features.sort_unstable();
+    features.dedup();
+
+    EnvironmentSummary {
+        rustc_version: rustc_version.map(|version| version.trim().to_owned()),
+        features,
+        all_features,
+        no_default_features,
+        env_vars: env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| is_recorded_var(name))
+            .collect(),
+    }
+}
+
+fn is_recorded_var(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    if SECRET_VAR_PATTERNS
+        .iter()
+        .any(|pattern| upper.contains(pattern))
+    {
+        return false;
+    }
+    RECORDED_VAR_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+        || RECORDED_VARS.contains(&name)
+}
+
+/// Differences between the environment a run was performed in and the local environment.
+#[derive(Clone, Debug)]
+pub struct EnvDiff {
+    summary: EnvDiffSummary,
+    identical_vars: usize,
+    styles: Box<Styles>,
+}
+
+impl EnvDiff {
+    /// Compares the environment recorded in `recorded` against the local one.
+    ///
+    /// Returns an error if the summary was written by a version of nextest that didn't record the
+    /// environment.
+    pub fn new(
+        recorded: &RunSummary,
+        local_host_triple: Option<&str>,
+        local_target_triple: Option<&str>,
+        local: &EnvironmentSummary,
+    ) -> Result<Self, EnvDiffError> {
+        let recorded_env = recorded
+            .environment
+            .as_ref()
+            .ok_or(EnvDiffError::NotRecorded {
+                run_id: recorded.metadata.run_id,
+            })?;
+
+        let mut differences = vec![];
+        let mut compare = |kind, name: &str, recorded: Option<String>, local: Option<String>| {
+            if recorded != local {
+                differences.push(EnvDifferenceSummary {
+                    kind,
+                    name: name.to_owned(),
+                    recorded,
+                    local,
+                });
+            }
+        };
+
+        compare(
+            EnvDifferenceKind::Platform,
+            "host-triple",
+            recorded.metadata.host_triple.clone(),
+            local_host_triple.map(|triple| triple.to_owned()),
+        );
+        compare(
+            EnvDifferenceKind::Platform,
+            "target-triple",
+            recorded.metadata.target_triple.clone(),
+            local_target_triple.map(|triple| triple.to_owned()),
+        );
+        compare(
+            EnvDifferenceKind::Toolchain,
+            "rustc",
+            recorded_env.rustc_version.clone(),
+            local.rustc_version.clone(),
+        );
+        let features =
+            |env: &EnvironmentSummary| (!env.features.is_empty()).then(|| env.features.join(","));
+        compare(
+            EnvDifferenceKind::Features,
+            "features",
+            features(recorded_env),
+            features(local),
+        );
+        compare(
+            EnvDifferenceKind::Features,
+            "all-features",
+            Some(recorded_env.all_features.to_string()),
+            Some(local.all_features.to_string()),
+        );
+        compare(
+            EnvDifferenceKind::Features,
+            "no-default-features",
+            Some(recorded_env.no_default_features.to_string()),
+            Some(local.no_default_features.to_string()),
+        );
+
+        let names: BTreeSet<_> = recorded_env
+            .env_vars
+            .keys()
+            .chain(local.env_vars.keys())
+            .collect();
+        let mut identical_vars = 0;
+        for name in names {
+            let recorded = recorded_env.env_vars.get(name).cloned();
+            let local = local.env_vars.get(name).cloned();
+            if recorded == local {
+                identical_vars += 1;
+            } else {
+                compare(EnvDifferenceKind::EnvVar, name, recorded, local);
+            }
+        }
+
+        Ok(Self {
+            summary: EnvDiffSummary {
+                run_id: recorded.metadata.run_id,
+                differences,
+            },
+            identical_vars,
+            styles: Box::new(Styles::default()),
+        })
+    }
+
+    /// Returns a serializable summary of the differences.
+    pub fn summary(&self) -> &EnvDiffSummary {
+        &self.summary
+    }
+
+    /// Colorizes output.
+    pub fn colorize(&mut self) {
+        self.styles.colorize();
+    }
+
+    /// Writes out the differences in the given format.
+    pub fn write(
+        &self,
+        output_format: OutputFormat,
+        writer: impl Write,
+    ) -> Result<(), EnvDiffError> {
+        match output_format {
+            OutputFormat::Plain => self.write_plain(writer).map_err(EnvDiffError::Write),
+            OutputFormat::Serializable(format) => format
+                .to_writer(&self.summary, writer)
+                .map_err(EnvDiffError::Json),
+        }
+    }
+
+    fn write_plain(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{:>12} {} (recorded) against the local environment",
+            "Comparing".style(self.styles.count),
+            self.summary.run_id,
+        )?;
+
+        for difference in &self.summary.differences {
+            let (kind_str, kind_style) = match difference.kind {
+                EnvDifferenceKind::Platform => ("PLATFORM", self.styles.fail),
+                EnvDifferenceKind::Toolchain => ("TOOLCHAIN", self.styles.fail),
+                EnvDifferenceKind::Features => ("FEATURES", self.styles.fail),
+                EnvDifferenceKind::EnvVar => ("ENV VAR", self.styles.skip),
+            };
+            write!(
+                writer,
+                "{:>12} {}: ",
+                kind_str.style(kind_style),
+                difference.name.style(self.styles.name),
+            )?;
+            self.write_value(difference.recorded.as_deref(), &mut writer)?;
+            write!(writer, " (recorded), ")?;
+            self.write_value(difference.local.as_deref(), &mut writer)?;
+            writeln!(writer, " (local)")?;
+        }
+
+        let count = self.summary.differences.len();
+        writeln!(
+            writer,
+            "{:>12} {} {}, {} environment {} identical",
+            "Summary".style(if count > 0 {
+                self.styles.fail
+            } else {
+                self.styles.pass
+            }),
+            count.style(self.styles.count),
+            if count == 1 {
+                "difference"
+            } else {
+                "differences"
+            },
+            self.identical_vars.style(self.styles.count),
+            if self.identical_vars == 1 {
+                "variable"
+            } else {
+                "variables"
+            },
+        )
+    }
+
+    fn write_value(&self, value: Option<&str>, mut writer: impl Write) -> io::Result<()> {
+        match value {
+            Some(value) => write!(writer, "{:?}", value),
+            None => write!(writer, "{}", "not set".style(self.styles.skip)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Styles {
+    count: Style,
+    pass: Style,
+    fail: Style,
+    skip: Style,
+    name: Style,
+}
+
+impl Styles {
+    fn colorize(&mut self) {
+        self.count = Style::new().bold();
+        self.pass = Style::new().green().bold();
+        self.fail = Style::new().red().bold();
+        self.skip = Style::new().yellow().bold();
+        self.name = Style::new().blue().bold();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::btreemap;
+    use nextest_metadata::RunMetadata;
+
+    #[test]
+    fn diff_environments() {
+        assert!(is_recorded_var("RUSTFLAGS"));
+        assert!(is_recorded_var("CARGO_TARGET_DIR"));
+        assert!(is_recorded_var("TZ"));
+        assert!(!is_recorded_var("CARGO_REGISTRY_TOKEN"));
+        assert!(!is_recorded_var("NEXTEST_API_KEY"));
+        assert!(!is_recorded_var("HOME"));
+
+        let recorded_env = EnvironmentSummary {
+            rustc_version: Some("rustc 1.60.0".to_owned()),
+            features: vec!["a".to_owned()],
+            all_features: false,
+            no_default_features: false,
+            env_vars: btreemap! {
+                "CI".to_owned() => "true".to_owned(),
+                "RUSTFLAGS".to_owned() => "-Dwarnings".to_owned(),
+                "TZ".to_owned() => "UTC".to_owned(),
+            },
+        };
+        let summary = RunSummary {
+            metadata: RunMetadata {
+                run_id: Default::default(),
+                nextest_version: "0.1.0".to_owned(),
+                profile_name: "ci".to_owned(),
+                host_triple: Some("x86_64-unknown-linux-gnu".to_owned()),
+                target_triple: Some("x86_64-unknown-linux-gnu".to_owned()),
+                invocation: vec![],
+            },
+            success: false,
+            initial_run_count: 0,
+            final_run_count: 0,
+            passed: 0,
+            runtime_skipped: 0,
+            flaky: 0,
+            failed: 0,
+            exec_failed: 0,
+            disk_quota_exceeded: 0,
+            timed_out: 0,
+            quarantined: 0,
+            expected_failures: 0,
+            unexpected_passes: 0,
+            skipped: 0,
+            overflowed: 0,
+            timing_predictions: 0,
+            timing_misses: 0,
+            skipped_binaries: 0,
+            environment: Some(recorded_env.clone()),
+        };
+
+        let mut local_env = record_environment(
+            Some("rustc 1.60.0\n".to_owned()),
+            &["a".to_owned()],
+            false,
+            false,
+        );
+        local_env.env_vars = recorded_env.env_vars.clone();
+        let diff = EnvDiff::new(
+            &summary,
+            Some("x86_64-unknown-linux-gnu"),
+            Some("x86_64-unknown-linux-gnu"),
+            &local_env,
+        )
+        .expect("environment recorded");
+        assert_eq!(diff.summary().differences, []);
+        assert_eq!(diff.identical_vars, 3);
+
+        local_env.features = vec![];
+        local_env.env_vars.remove("CI");
+        local_env
+            .env_vars
+            .insert("TZ".to_owned(), "Europe/Paris".to_owned());
+        let diff = EnvDiff::new(
+            &summary,
+            Some("x86_64-unknown-linux-gnu"),
+            Some("aarch64-unknown-linux-gnu"),
+            &local_env,
+        )
+        .expect("environment recorded");
+        let differences: Vec<_> = diff
+            .summary()
+            .differences
+            .iter()
+            .map(|difference| {
+                (
+                    difference.kind,
+                    difference.name.as_str(),
+                    difference.recorded.as_deref(),
+                    difference.local.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            differences,
+            [
+                (
+                    EnvDifferenceKind::Platform,
+                    "target-triple",
+                    Some("x86_64-unknown-linux-gnu"),
+                    Some("aarch64-unknown-linux-gnu"),
+                ),
+                (EnvDifferenceKind::Features, "features", Some("a"), None),
+                (EnvDifferenceKind::EnvVar, "CI", Some("true"), None),
+                (
+                    EnvDifferenceKind::EnvVar,
+                    "TZ",
+                    Some("UTC"),
+                    Some("Europe/Paris")
+                ),
+            ]
+        );
+        assert_eq!(diff.identical_vars, 1);
+
+        let summary = RunSummary {
+            environment: None,
+            ..summary
+        };
+        EnvDiff::new(&summary, None, None, &local_env).expect_err("environment not recorded");
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Loading environment variables for tests from dotenv files.
+//!
+//! A profile can name one or more dotenv files, relative to the workspace root:
+//!
+//! ```toml
+//! [profile.default]
+//! env-files = [".env", ".env.test"]
+//! ```
+//!
+//! The files are read once, at the start of the run, and the variables in them are set for every
+//! test process. Files later in the list take precedence over earlier ones, and variables from
+//! these files take precedence over variables inherited from the environment nextest is run in.
+//! The paths of the files and the names of the variables in them are recorded in the run's
+//! [info summary](crate::run_store::RunStore::write_info). Values aren't recorded, since they may
+//! be secrets.
+//!
+//! Each line of a dotenv file is one of:
+//!
+//! * blank, or a comment starting with `#`
+//! * `KEY=value`, optionally prefixed with `export `. Unquoted values are trimmed, and anything
+//!   after a ` #` is treated as a comment.
+//! * `KEY='value'`: the value is used literally.
+//! * `KEY="value"`: the escapes `\n`, `\t`, `\"` and `\\` are supported.
+//!
+//! Variable references like `${OTHER}` are not expanded.
+
+use crate::{config::NextestProfile, errors::EnvFileError};
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::EnvFileSummary;
+use std::fs;
+
+/// Environment variables loaded from the dotenv files configured for a profile.
+#[derive(Clone, Debug, Default)]
+pub struct EnvFiles {
+    files: Vec<EnvFile>,
+}
+
+#[derive(Clone, Debug)]
+struct EnvFile {
+    path: Utf8PathBuf,
+    vars: Vec<(String, String)>,
+}
+
+impl EnvFiles {
+    /// Reads and parses the dotenv files configured for the given profile.
+    pub fn load(profile: &NextestProfile<'_>) -> Result<Self, EnvFileError> {
+        let files = profile
+            .env_files()
+            .map(|path| {
+                let contents = fs::read_to_string(&path).map_err(|error| EnvFileError::Read {
+                    path: path.clone(),
+                    error,
+                })?;
+                let vars =
+                    parse_env_file(&contents).map_err(|(line, message)| EnvFileError::Parse {
+                        path: path.clone(),
+                        line,
+                        message,
+                    })?;
+                Ok(EnvFile { path, vars })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { files })
+    }
+
+    /// Returns the paths of the files that were loaded, in order.
+    pub fn paths(&self) -> impl Iterator<Item = &Utf8Path> + '_ {
+        self.files.iter().map(|file| file.path.as_path())
+    }
+
+    /// Iterates over all variables that were loaded, in the order they should be set.
+    ///
+    /// A variable may be returned more than once; later values take precedence.
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.files.iter().flat_map(|file| {
+            file.vars
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+        })
+    }
+
+    /// Returns a serializable summary of the files that were loaded, without the values of the
+    /// variables in them.
+    pub fn summaries(&self) -> Vec<EnvFileSummary> {
+        self.files
+            .iter()
+            .map(|file| EnvFileSummary {
+                path: file.path.clone(),
+                keys: file.vars.iter().map(|(key, _)| key.clone()).collect(),
+            })
+            .collect()
+    }
+}
+
+/// Parses the contents of a dotenv file, returning the 1-based line number and a message on error.
+pub(crate) fn parse_env_file(contents: &str) -> Result<Vec<(String, String)>, (usize, String)> {
+    let mut vars = vec![];
+    for (idx, line) in contents.lines().enumerate() {
+        let line_number = idx + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| (line_number, "expected KEY=value".to_owned()))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err((line_number, format!("invalid variable name '{}'", key)));
+        }
+        let value = parse_value(value.trim()).map_err(|message| (line_number, message))?;
+        vars.push((key.to_owned(), value));
+    }
+    Ok(vars)
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    if let Some(rest) = value.strip_prefix('\'') {
+        let end = rest
+            .find('\'')
+            .ok_or_else(|| "unterminated single-quoted value".to_owned())?;
+        return Ok(rest[..end].to_owned());
+    }
+
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Ok(out),
+                '\\' => match chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(c @ ('"' | '\\')) => out.push(c),
+                    Some(c) => {
+                        out.push('\\');
+                        out.push(c);
+                    }
+                    None => break,
+                },
+                c => out.push(c),
+            }
+        }
+        return Err("unterminated double-quoted value".to_owned());
+    }
+
+    let value = match value.find(" #") {
+        Some(idx) => &value[..idx],
+        None => value,
+    };
+    Ok(value.trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let contents = r#"
+            # A comment.
+            PLAIN=value
+            export EXPORTED = spaced value  # trailing comment
+            SINGLE='literal \n $value'
+            DOUBLE="line one\nline \"two\""
+            EMPTY=
+        "#;
+        let vars = parse_env_file(contents).expect("valid env file");
+        assert_eq!(
+            vars,
+            [
+                ("PLAIN", "value"),
+                ("EXPORTED", "spaced value"),
+                ("SINGLE", "literal \\n $value"),
+                ("DOUBLE", "line one\nline \"two\""),
+                ("EMPTY", ""),
+            ]
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        );
+
+        for (invalid, line) in [
+            ("FOO=bar\nno equals sign", 2),
+            ("BAD KEY=value", 1),
+            ("A=1\nB=2\nC=\"unterminated", 3),
+            ("=value", 1),
+            ("DOTTED.KEY=value", 1),
+        ] {
+            let (err_line, _) =
+                parse_env_file(invalid).expect_err(&format!("`{}` should be invalid", invalid));
+            assert_eq!(err_line, line, "error line for `{}`", invalid);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Execution environments: where and how test binaries are listed and run.
+//!
+//! Each [`PlatformRunner`](crate::target_runner::PlatformRunner) runs test binaries in an
+//! [`ExecutionEnvironment`]. These are:
+//!
+//! * [`Local`]: binaries are run directly, if no runner is configured.
+//! * [`WrapperCommand`]: binaries are passed to a wrapper command such as an emulator, and
+//!   [`WasmRunner`] for WebAssembly runtimes, which are passed the environment of the test
+//!   explicitly.
+//! * [`RemoteRunner`]: binaries are copied to a remote host and run on it over SSH.
+//! * Devices: [`AdbRunner`] pushes binaries to `adb` devices and runs each test on one of them,
+//!   while [`ProbeRunner`] flashes binaries to a probe-attached device and runs them as a whole.
+//!
+//! The rest of nextest only goes through this trait to list and run tests, so a backend for another
+//! OS, such as one that runs tests on a Fuchsia device through `ffx test`, is added by implementing
+//! [`ExecutionEnvironment`] and letting `target-runner` rules configure it (see
+//! [`target_runner`](crate::target_runner)).
+
+use crate::{
+    adb_runner::AdbRunner,
+    build_meta::{RustBuildMeta, DYLIB_PATH_ENV},
+    errors::ParseTestListError,
+    probe_runner::ProbeRunner,
+    remote_runner::RemoteRunner,
+    wasm::{self, WasmRuntime},
+};
+use camino::Utf8Path;
+use duct::{cmd, Expression};
+use nextest_metadata::BuildPlatform;
+use std::{any::Any, fmt, io, path::Path, sync::Arc, time::Duration};
+
+/// Where and how test binaries for a platform are listed and run.
+pub(crate) trait ExecutionEnvironment: fmt::Debug + Send + Sync + 'static {
+    /// Lists the tests in the binary without running it with `--list`, in the
+    /// [discovery protocol](crate::test_list#dynamic-test-discovery) format.
+    ///
+    /// Returns `None` if the binary should be listed by running the command returned by
+    /// [`make_command`](Self::make_command) instead, which is the default.
+    fn list_tests(
+        self: Arc<Self>,
+        binary_path: &Utf8Path,
+    ) -> Result<Option<String>, ParseTestListError> {
+        let _ = binary_path;
+        Ok(None)
+    }
+
+    /// Returns the command that runs a test binary with the given arguments.
+    ///
+    /// The working directory and environment variables of the test are set on the returned
+    /// expression afterwards. Environments that need them to build the command should read them
+    /// right before the command is spawned, with [`Expression::before_spawn`].
+    fn make_command(self: Arc<Self>, binary: BinaryCommand<'_>) -> Expression;
+
+    /// Starts running all the tests in a binary at once, for environments that can't run tests
+    /// one at a time, or returns the run that was already started for the attempt.
+    ///
+    /// Returns `None` if each test is run in its own process through
+    /// [`make_command`](Self::make_command) instead, which is the default.
+    fn start_binary_run(
+        self: Arc<Self>,
+        binary_path: &Utf8Path,
+        attempt: usize,
+    ) -> Option<Arc<dyn BinaryRun>> {
+        let _ = (binary_path, attempt);
+        None
+    }
+
+    /// Returns self as [`Any`], to compare environments and to look up their configuration.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Returns true if the other environment is the same kind of environment, with the same
+    /// configuration.
+    fn eq_dyn(&self, other: &dyn ExecutionEnvironment) -> bool;
+}
+
+/// A test binary to run, with the arguments to pass to it.
+#[derive(Clone, Debug)]
+pub(crate) struct BinaryCommand<'a> {
+    pub(crate) binary_path: &'a Utf8Path,
+    pub(crate) args: Vec<&'a str>,
+    pub(crate) build_platform: BuildPlatform,
+    pub(crate) build_meta: &'a RustBuildMeta,
+}
+
+/// A run of all the tests in a binary, started through
+/// [`ExecutionEnvironment::start_binary_run`]. Tests in the binary wait for the run, then take
+/// their own results out of it.
+pub(crate) trait BinaryRun: Send + Sync {
+    /// Waits for the run to finish for up to `timeout`, or until it finishes if `timeout` is
+    /// `None`. Returns false if it's still going.
+    fn wait_timeout(&self, timeout: Option<Duration>) -> bool;
+
+    /// Returns the result of the test with the given name, once the run has finished.
+    fn test_result(&self, name: &str) -> io::Result<BinaryTestResult>;
+}
+
+/// The result of a single test, taken out of a [`BinaryRun`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct BinaryTestResult {
+    pub(crate) outcome: BinaryTestOutcome,
+    pub(crate) stdout: Vec<u8>,
+    pub(crate) stderr: Vec<u8>,
+    pub(crate) duration: Duration,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum BinaryTestOutcome {
+    Pass,
+    Fail,
+    Skip(String),
+}
+
+/// Implements [`ExecutionEnvironment::eq_dyn`] for environments that implement [`PartialEq`].
+pub(crate) fn eq_dyn<T: ExecutionEnvironment + PartialEq>(
+    this: &T,
+    other: &dyn ExecutionEnvironment,
+) -> bool {
+    other
+        .as_any()
+        .downcast_ref::<T>()
+        .is_some_and(|other| this == other)
+}
+
+/// Runs test binaries directly, with the library path set up for them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Local;
+
+impl ExecutionEnvironment for Local {
+    fn make_command(self: Arc<Self>, binary: BinaryCommand<'_>) -> Expression {
+        let cmd = cmd(AsRef::<Path>::as_ref(binary.binary_path), binary.args);
+        with_dylib_path(cmd, binary.build_meta, binary.build_platform)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_dyn(&self, other: &dyn ExecutionEnvironment) -> bool {
+        eq_dyn(self, other)
+    }
+}
+
+/// Runs test binaries by passing them to a wrapper command, such as an emulator, after its
+/// arguments.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct WrapperCommand {
+    pub(crate) program: String,
+    pub(crate) args: Vec<String>,
+}
+
+impl ExecutionEnvironment for WrapperCommand {
+    fn make_command(self: Arc<Self>, binary: BinaryCommand<'_>) -> Expression {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.as_str())
+            .chain(std::iter::once(binary.binary_path.as_str()))
+            .chain(binary.args.iter().copied());
+        // As with synthetic tests, pass the program as a string so that duct looks up bare names
+        // in PATH.
+        let cmd = cmd(self.program.as_str(), args);
+        with_dylib_path(cmd, binary.build_meta, binary.build_platform)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_dyn(&self, other: &dyn ExecutionEnvironment) -> bool {
+        eq_dyn(self, other)
+    }
+}
+
+/// Runs WebAssembly test binaries through a runtime: see [`wasm`](crate::wasm).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct WasmRunner {
+    pub(crate) runtime: WasmRuntime,
+    pub(crate) program: String,
+    pub(crate) args: Vec<String>,
+}
+
+impl ExecutionEnvironment for WasmRunner {
+    fn list_tests(
+        self: Arc<Self>,
+        binary_path: &Utf8Path,
+    ) -> Result<Option<String>, ParseTestListError> {
+        // Components that export their tests are listed without being run.
+        wasm::list_component_tests(binary_path, self.runtime).map_err(|error| {
+            ParseTestListError::WasmComponent {
+                binary_path: binary_path.to_owned(),
+                error,
+            }
+        })
+    }
+
+    fn make_command(self: Arc<Self>, binary: BinaryCommand<'_>) -> Expression {
+        // WebAssembly runtimes are passed the environment and directories of the test explicitly,
+        // so the library path doesn't apply.
+        wasm::make_command(&self.program, &self.args, binary.binary_path, binary.args)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_dyn(&self, other: &dyn ExecutionEnvironment) -> bool {
+        eq_dyn(self, other)
+    }
+}
+
+impl ExecutionEnvironment for RemoteRunner {
+    fn make_command(self: Arc<Self>, binary: BinaryCommand<'_>) -> Expression {
+        // Remote runners set up the library path on the remote host themselves.
+        let dylib_dirs = binary.build_meta.dylib_dirs(binary.build_platform);
+        RemoteRunner::make_command(&self, binary.binary_path, binary.args, dylib_dirs)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_dyn(&self, other: &dyn ExecutionEnvironment) -> bool {
+        eq_dyn(self, other)
+    }
+}
+
+impl ExecutionEnvironment for AdbRunner {
+    fn make_command(self: Arc<Self>, binary: BinaryCommand<'_>) -> Expression {
+        // As do runners for adb devices, on the device the test is run on.
+        let dylib_dirs = binary.build_meta.dylib_dirs(binary.build_platform);
+        AdbRunner::make_command(&self, binary.binary_path, binary.args, dylib_dirs)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_dyn(&self, other: &dyn ExecutionEnvironment) -> bool {
+        eq_dyn(self, other)
+    }
+}
+
+impl ExecutionEnvironment for ProbeRunner {
+    fn list_tests(
+        self: Arc<Self>,
+        binary_path: &Utf8Path,
+    ) -> Result<Option<String>, ParseTestListError> {
+        // Binaries for probe-attached devices are listed by running all their tests.
+        let output = ProbeRunner::list_tests(&self, binary_path).map_err(|error| {
+            ParseTestListError::command(format!("'probe-rs run {}'", binary_path), error)
+        })?;
+        Ok(Some(output))
+    }
+
+    fn make_command(self: Arc<Self>, binary: BinaryCommand<'_>) -> Expression {
+        // Probe-attached devices can only run the binary as a whole, so the arguments don't apply.
+        let mut probe_args = self.device().run_args();
+        probe_args.push(binary.binary_path.to_string());
+        cmd("probe-rs", probe_args)
+    }
+
+    fn start_binary_run(
+        self: Arc<Self>,
+        binary_path: &Utf8Path,
+        attempt: usize,
+    ) -> Option<Arc<dyn BinaryRun>> {
+        Some(self.start_run(binary_path, attempt))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_dyn(&self, other: &dyn ExecutionEnvironment) -> bool {
+        eq_dyn(self, other)
+    }
+}
+
+fn with_dylib_path(
+    cmd: Expression,
+    build_meta: &RustBuildMeta,
+    build_platform: BuildPlatform,
+) -> Expression {
+    match build_meta.dylib_path(build_platform) {
+        Some(dylib_path) => cmd.env(DYLIB_PATH_ENV, dylib_path),
+        None => cmd,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_environments() {
+        let wrapper = WrapperCommand {
+            program: "wasmtime".to_owned(),
+            args: vec![],
+        };
+        let wasm = WasmRunner {
+            runtime: WasmRuntime::Wasmtime,
+            program: "wasmtime".to_owned(),
+            args: vec![],
+        };
+        assert!(wrapper.eq_dyn(&wrapper.clone()), "same configuration");
+        assert!(
+            !wrapper.eq_dyn(&WrapperCommand {
+                program: "qemu-aarch64".to_owned(),
+                args: vec![],
+            }),
+            "different program"
+        );
+        assert!(
+            !wrapper.eq_dyn(&wasm),
+            "same program, different kinds of environment"
+        );
+        assert!(!Local.eq_dyn(&wrapper), "local and wrapper command");
+    }
+}
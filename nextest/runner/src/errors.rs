@@ -4,13 +4,14 @@
 //! Errors produced by nextest.
 
 use crate::{
-    reporter::{StatusLevel, TestOutputDisplay},
+    reporter::{ReporterMode, StatusLevel, TestOutputDisplay},
     test_filter::RunIgnored,
-    test_list::OutputFormat,
+    test_list::{EmptyBinaries, OutputFormat},
 };
 use camino::Utf8PathBuf;
 use config::ConfigError;
-use std::{borrow::Cow, error, fmt};
+use std::{borrow::Cow, error, fmt, process::ExitStatus, time::Duration};
+use uuid::Uuid;
 
 /// An error that occurred while parsing the config.
 #[derive(Debug)]
@@ -80,6 +81,341 @@ impl fmt::Display for ProfileNotFound {
 
 impl error::Error for ProfileNotFound {}
 
+/// An error which indicates that the directory tests in a binary are run in doesn't exist, and
+/// that no existing directory was configured to run them in instead.
+///
+/// See [`MissingCwd`](crate::config::MissingCwd).
+#[derive(Clone, Debug)]
+pub struct MissingCwdError {
+    binary_id: String,
+    cwd: Utf8PathBuf,
+    substitute: Option<Utf8PathBuf>,
+}
+
+impl MissingCwdError {
+    pub(crate) fn new(
+        binary_id: impl Into<String>,
+        cwd: impl Into<Utf8PathBuf>,
+        substitute: Option<Utf8PathBuf>,
+    ) -> Self {
+        Self {
+            binary_id: binary_id.into(),
+            cwd: cwd.into(),
+            substitute,
+        }
+    }
+}
+
+impl fmt::Display for MissingCwdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "directory to run tests in '{}' in doesn't exist: {}",
+            self.binary_id, self.cwd
+        )?;
+        match &self.substitute {
+            Some(substitute) => write!(
+                f,
+                " (and neither does the directory configured through missing-cwd: {})",
+                substitute
+            ),
+            None => write!(
+                f,
+                " (set missing-cwd in the profile to run them in another directory)"
+            ),
+        }
+    }
+}
+
+impl error::Error for MissingCwdError {}
+
+/// An error that occurs while parsing a [`FilterExpr`](crate::filter_expr::FilterExpr).
+#[derive(Clone, Debug)]
+pub struct FilterExprParseError {
+    input: String,
+    position: usize,
+    message: String,
+}
+
+impl FilterExprParseError {
+    pub(crate) fn new(
+        input: impl Into<String>,
+        position: usize,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            input: input.into(),
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FilterExprParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid filter expression `{}` at position {}: {}",
+            self.input, self.position, self.message
+        )
+    }
+}
+
+impl error::Error for FilterExprParseError {}
+
+/// An error that occurs while parsing a multiplex specification such as `PARAM=a|b|c`.
+#[derive(Clone, Debug)]
+pub struct MultiplexParseError {
+    input: String,
+}
+
+impl MultiplexParseError {
+    pub(crate) fn new(input: impl Into<String>) -> Self {
+        Self {
+            input: input.into(),
+        }
+    }
+}
+
+impl fmt::Display for MultiplexParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid multiplex env `{}`: expected the format VAR=value1|value2|... with non-empty values",
+            self.input
+        )
+    }
+}
+
+impl error::Error for MultiplexParseError {}
+
+/// An error that occurs while reading a [`MultiplexRule`](crate::config::MultiplexRule) from the
+/// config.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum MultiplexRuleError {
+    /// Neither or both of `env` and `fail-points` were specified.
+    EnvOrFailPoints,
+
+    /// A fail point in the `fail-points` matrix has no actions.
+    NoActions {
+        /// The name of the fail point.
+        fail_point: String,
+    },
+}
+
+impl fmt::Display for MultiplexRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultiplexRuleError::EnvOrFailPoints => {
+                write!(
+                    f,
+                    "multiplex rule must specify exactly one of `env` and `fail-points`"
+                )
+            }
+            MultiplexRuleError::NoActions { fail_point } => {
+                write!(
+                    f,
+                    "fail point `{}` has no actions to multiplex over",
+                    fail_point
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for MultiplexRuleError {}
+
+/// An error that occurs while parsing a [`TargetRunnerRule`](crate::target_runner::TargetRunnerRule).
+#[derive(Debug)]
+pub struct TargetRunnerRuleParseError {
+    platform: String,
+    kind: TargetRunnerRuleParseErrorKind,
+}
+
+#[derive(Debug)]
+enum TargetRunnerRuleParseErrorKind {
+    Spec(target_spec::Error),
+    EmptyCommand,
+    MultipleRunners,
+}
+
+impl TargetRunnerRuleParseError {
+    pub(crate) fn new(platform: impl Into<String>, spec_error: target_spec::Error) -> Self {
+        Self {
+            platform: platform.into(),
+            kind: TargetRunnerRuleParseErrorKind::Spec(spec_error),
+        }
+    }
+
+    pub(crate) fn empty_command(platform: impl Into<String>) -> Self {
+        Self {
+            platform: platform.into(),
+            kind: TargetRunnerRuleParseErrorKind::EmptyCommand,
+        }
+    }
+
+    pub(crate) fn multiple_runners(platform: impl Into<String>) -> Self {
+        Self {
+            platform: platform.into(),
+            kind: TargetRunnerRuleParseErrorKind::MultipleRunners,
+        }
+    }
+}
+
+impl fmt::Display for TargetRunnerRuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            TargetRunnerRuleParseErrorKind::Spec(spec_error) => write!(
+                f,
+                "invalid target runner platform `{}`: {}",
+                self.platform, spec_error
+            ),
+            TargetRunnerRuleParseErrorKind::EmptyCommand => write!(
+                f,
+                "target runner for platform `{}` has an empty command",
+                self.platform
+            ),
+            TargetRunnerRuleParseErrorKind::MultipleRunners => write!(
+                f,
+                "target runner for platform `{}` specifies more than one of a command, a remote \
+                 host, adb devices and a probe",
+                self.platform
+            ),
+        }
+    }
+}
+
+impl error::Error for TargetRunnerRuleParseError {}
+
+/// An error that occurs while looking up the runners for test binaries.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TargetRunnerError {
+    /// A Cargo config file couldn't be read.
+    CargoConfigRead {
+        /// The path to the config file.
+        path: Utf8PathBuf,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// A Cargo config file couldn't be parsed.
+    CargoConfigParse {
+        /// The path to the config file.
+        path: Utf8PathBuf,
+
+        /// The underlying error.
+        error: toml::de::Error,
+    },
+
+    /// The runner in a Cargo config file is neither a string nor an array of strings, or is empty.
+    InvalidCargoRunner {
+        /// The path to the config file.
+        path: Utf8PathBuf,
+
+        /// The triple the runner was looked up for.
+        triple: String,
+    },
+}
+
+impl fmt::Display for TargetRunnerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TargetRunnerError::CargoConfigRead { path, .. } => {
+                write!(f, "failed to read Cargo config '{}'", path)
+            }
+            TargetRunnerError::CargoConfigParse { path, .. } => {
+                write!(f, "failed to parse Cargo config '{}'", path)
+            }
+            TargetRunnerError::InvalidCargoRunner { path, triple } => {
+                write!(
+                    f,
+                    "invalid runner for '{}' in Cargo config '{}': expected a non-empty string or \
+                     array of strings",
+                    triple, path
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for TargetRunnerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TargetRunnerError::CargoConfigRead { error, .. } => Some(error),
+            TargetRunnerError::CargoConfigParse { error, .. } => Some(error),
+            TargetRunnerError::InvalidCargoRunner { .. } => None,
+        }
+    }
+}
+
+/// An error that occurs while starting or stopping the environment a target runner needs.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RunnerLifecycleError {
+    /// A start, readiness or stop command couldn't be run.
+    Exec {
+        /// The command, with its arguments separated by spaces.
+        command: String,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// A start or stop command exited unsuccessfully.
+    Failed {
+        /// The command, with its arguments separated by spaces.
+        command: String,
+
+        /// The exit status of the command.
+        status: ExitStatus,
+    },
+
+    /// The readiness probe didn't succeed within the timeout.
+    NotReady {
+        /// The readiness probe, with its arguments separated by spaces.
+        command: String,
+
+        /// How long nextest waited for the probe to succeed.
+        timeout: Duration,
+    },
+}
+
+impl fmt::Display for RunnerLifecycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunnerLifecycleError::Exec { command, .. } => {
+                write!(f, "failed to run runner lifecycle command `{}`", command)
+            }
+            RunnerLifecycleError::Failed { command, status } => {
+                write!(
+                    f,
+                    "runner lifecycle command `{}` failed with {}",
+                    command, status
+                )
+            }
+            RunnerLifecycleError::NotReady { command, timeout } => {
+                write!(
+                    f,
+                    "readiness probe `{}` didn't succeed within {:?}",
+                    command, timeout
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for RunnerLifecycleError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RunnerLifecycleError::Exec { error, .. } => Some(error),
+            RunnerLifecycleError::Failed { .. } | RunnerLifecycleError::NotReady { .. } => None,
+        }
+    }
+}
+
 /// Error returned while parsing a [`TestOutputDisplay`] value from a string.
 #[derive(Clone, Debug)]
 pub struct TestOutputDisplayParseError {
@@ -134,6 +470,33 @@ impl fmt::Display for StatusLevelParseError {
 
 impl error::Error for StatusLevelParseError {}
 
+/// Error returned while parsing a [`ReporterMode`] value from a string.
+#[derive(Clone, Debug)]
+pub struct ReporterModeParseError {
+    input: String,
+}
+
+impl ReporterModeParseError {
+    pub(crate) fn new(input: impl Into<String>) -> Self {
+        Self {
+            input: input.into(),
+        }
+    }
+}
+
+impl fmt::Display for ReporterModeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unrecognized value for reporter: {}\n(known values: {})",
+            self.input,
+            ReporterMode::variants().join(", ")
+        )
+    }
+}
+
+impl error::Error for ReporterModeParseError {}
+
 /// An error that occurs while parsing an [`OutputFormat`] value from a string.
 #[derive(Clone, Debug)]
 pub struct OutputFormatParseError {
@@ -188,6 +551,33 @@ impl fmt::Display for RunIgnoredParseError {
 
 impl error::Error for RunIgnoredParseError {}
 
+/// An error that occurs while parsing an [`EmptyBinaries`] value from a string.
+#[derive(Clone, Debug)]
+pub struct EmptyBinariesParseError {
+    input: String,
+}
+
+impl EmptyBinariesParseError {
+    pub(crate) fn new(input: impl Into<String>) -> Self {
+        Self {
+            input: input.into(),
+        }
+    }
+}
+
+impl fmt::Display for EmptyBinariesParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unrecognized value for empty-binaries: {}\n(known values: {})",
+            self.input,
+            EmptyBinaries::variants().join(", ")
+        )
+    }
+}
+
+impl error::Error for EmptyBinariesParseError {}
+
 /// An error that occurs while parsing a
 /// [`PartitionerBuilder`](crate::partition::PartitionerBuilder) input.
 #[derive(Clone, Debug)]
@@ -258,49 +648,290 @@ impl error::Error for FromMessagesError {
     }
 }
 
-/// An error that occurs while parsing test list output.
+/// An error that occurs in [`TestList::from_summary`](crate::test_list::TestList::from_summary).
 #[derive(Debug)]
 #[non_exhaustive]
-pub enum ParseTestListError {
-    /// Running a command to gather the list of tests failed.
-    Command {
-        /// The command that was run.
-        command: Cow<'static, str>,
-
-        /// The underlying error.
-        error: std::io::Error,
-    },
+pub enum FromSummaryError {
+    /// A package in the summary couldn't be found in the package graph.
+    PackageGraph(guppy::Error),
 
-    /// An error occurred while parsing a line in the test output.
-    ParseLine {
-        /// A descriptive message.
-        message: Cow<'static, str>,
+    /// A test binary in the summary doesn't exist.
+    MissingBinary {
+        /// The unique identifier of the binary.
+        binary_id: String,
 
-        /// The full output.
-        full_output: String,
+        /// The path to the binary, as specified in the summary.
+        binary_path: Utf8PathBuf,
     },
+
+    /// The directory tests in a binary are run in doesn't exist.
+    MissingCwd(MissingCwdError),
 }
 
-impl ParseTestListError {
-    pub(crate) fn command(command: impl Into<Cow<'static, str>>, error: std::io::Error) -> Self {
-        ParseTestListError::Command {
-            command: command.into(),
-            error,
+impl fmt::Display for FromSummaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FromSummaryError::PackageGraph(_) => {
+                write!(f, "error querying package graph")
+            }
+            FromSummaryError::MissingBinary {
+                binary_id,
+                binary_path,
+            } => {
+                write!(
+                    f,
+                    "test binary for '{}' not found at {}",
+                    binary_id, binary_path
+                )
+            }
+            FromSummaryError::MissingCwd(_) => {
+                write!(f, "directory to run tests in not found")
+            }
         }
     }
+}
 
-    pub(crate) fn parse_line(
-        message: impl Into<Cow<'static, str>>,
-        full_output: impl Into<String>,
-    ) -> Self {
-        ParseTestListError::ParseLine {
-            message: message.into(),
-            full_output: full_output.into(),
+impl error::Error for FromSummaryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            FromSummaryError::PackageGraph(error) => Some(error),
+            FromSummaryError::MissingBinary { .. } => None,
+            FromSummaryError::MissingCwd(error) => Some(error),
         }
     }
 }
 
-impl fmt::Display for ParseTestListError {
+/// An error that occurs in
+/// [`TestList::add_synthetic_tests`](crate::test_list::TestList::add_synthetic_tests).
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum SyntheticTestError {
+    /// The test list already has a suite with this binary ID.
+    DuplicateSuite {
+        /// The binary ID of the suite.
+        binary_id: String,
+    },
+
+    /// Several synthetic tests in the suite have the same name.
+    DuplicateTest {
+        /// The binary ID of the suite.
+        binary_id: String,
+
+        /// The name of the test.
+        name: String,
+    },
+}
+
+impl fmt::Display for SyntheticTestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyntheticTestError::DuplicateSuite { binary_id } => {
+                write!(
+                    f,
+                    "test list already has a suite with binary ID '{}'",
+                    binary_id
+                )
+            }
+            SyntheticTestError::DuplicateTest { binary_id, name } => {
+                write!(
+                    f,
+                    "synthetic suite '{}' has more than one test named '{}'",
+                    binary_id, name
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for SyntheticTestError {}
+
+/// An error that occurs while finding the path to a
+/// [required binary](crate::required_binaries::RequiredBinary) after building it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RequiredBinaryError {
+    /// An error occurred while reading Cargo's JSON messages.
+    ReadMessages(std::io::Error),
+
+    /// An error occurred while querying the package graph.
+    PackageGraph(guppy::Error),
+
+    /// Cargo didn't report building the binary.
+    NotBuilt {
+        /// The name of the package.
+        package: String,
+
+        /// The name of the binary.
+        binary: String,
+    },
+}
+
+impl fmt::Display for RequiredBinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequiredBinaryError::ReadMessages(_) => {
+                write!(f, "error reading Cargo JSON messages")
+            }
+            RequiredBinaryError::PackageGraph(_) => {
+                write!(f, "error querying package graph")
+            }
+            RequiredBinaryError::NotBuilt { package, binary } => {
+                write!(
+                    f,
+                    "required binary '{}' in package '{}' wasn't built",
+                    binary, package
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for RequiredBinaryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RequiredBinaryError::ReadMessages(error) => Some(error),
+            RequiredBinaryError::PackageGraph(error) => Some(error),
+            RequiredBinaryError::NotBuilt { .. } => None,
+        }
+    }
+}
+
+/// An error that occurs while adding [other tests](crate::other_tests) from the config to a test
+/// list.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OtherTestsError {
+    /// The package the suite belongs to isn't in the workspace.
+    UnknownPackage {
+        /// The name of the suite.
+        suite: String,
+
+        /// The name of the package.
+        package: String,
+    },
+
+    /// The list command is empty.
+    EmptyListCommand {
+        /// The name of the suite.
+        suite: String,
+    },
+
+    /// Running the list command failed.
+    ListCommand {
+        /// The name of the suite.
+        suite: String,
+
+        /// The command that was run.
+        command: String,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// The tests couldn't be added to the test list.
+    Synthetic(SyntheticTestError),
+}
+
+impl fmt::Display for OtherTestsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OtherTestsError::UnknownPackage { suite, package } => {
+                write!(
+                    f,
+                    "other tests '{}': package '{}' isn't in the workspace",
+                    suite, package
+                )
+            }
+            OtherTestsError::EmptyListCommand { suite } => {
+                write!(f, "other tests '{}': list-command is empty", suite)
+            }
+            OtherTestsError::ListCommand { suite, command, .. } => {
+                write!(
+                    f,
+                    "other tests '{}': running list command '{}' failed",
+                    suite, command
+                )
+            }
+            OtherTestsError::Synthetic(_) => {
+                write!(f, "failed to add other tests to the test list")
+            }
+        }
+    }
+}
+
+impl error::Error for OtherTestsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            OtherTestsError::ListCommand { error, .. } => Some(error),
+            OtherTestsError::Synthetic(error) => Some(error),
+            OtherTestsError::UnknownPackage { .. } | OtherTestsError::EmptyListCommand { .. } => {
+                None
+            }
+        }
+    }
+}
+
+/// An error that occurs while parsing test list output.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseTestListError {
+    /// Running a command to gather the list of tests failed.
+    Command {
+        /// The command that was run.
+        command: Cow<'static, str>,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// An error occurred while parsing a line in the test output.
+    ParseLine {
+        /// A descriptive message.
+        message: Cow<'static, str>,
+
+        /// The full output.
+        full_output: String,
+    },
+
+    /// The test functions exported by a WebAssembly component couldn't be read.
+    WasmComponent {
+        /// The path to the component.
+        binary_path: Utf8PathBuf,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// Some test binaries didn't contain any tests, and empty binaries were configured to fail.
+    EmptyBinaries {
+        /// The unique identifiers of the empty binaries.
+        binary_ids: Vec<String>,
+    },
+
+    /// The directory tests in a binary are run in doesn't exist.
+    MissingCwd(MissingCwdError),
+}
+
+impl ParseTestListError {
+    pub(crate) fn command(command: impl Into<Cow<'static, str>>, error: std::io::Error) -> Self {
+        ParseTestListError::Command {
+            command: command.into(),
+            error,
+        }
+    }
+
+    pub(crate) fn parse_line(
+        message: impl Into<Cow<'static, str>>,
+        full_output: impl Into<String>,
+    ) -> Self {
+        ParseTestListError::ParseLine {
+            message: message.into(),
+            full_output: full_output.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseTestListError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ParseTestListError::Command { command, .. } => {
@@ -312,6 +943,24 @@ impl fmt::Display for ParseTestListError {
             } => {
                 write!(f, "{}\nfull output:\n{}", message, full_output)
             }
+            ParseTestListError::WasmComponent { binary_path, .. } => {
+                write!(
+                    f,
+                    "failed to read test exports from WebAssembly component '{}'",
+                    binary_path
+                )
+            }
+            ParseTestListError::EmptyBinaries { binary_ids } => {
+                write!(
+                    f,
+                    "{} test binaries don't contain any tests: {}",
+                    binary_ids.len(),
+                    binary_ids.join(", ")
+                )
+            }
+            ParseTestListError::MissingCwd(_) => {
+                write!(f, "directory to run tests in not found")
+            }
         }
     }
 }
@@ -319,8 +968,10 @@ impl fmt::Display for ParseTestListError {
 impl error::Error for ParseTestListError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
-            ParseTestListError::Command { error, .. } => Some(error),
-            ParseTestListError::ParseLine { .. } => None,
+            ParseTestListError::Command { error, .. }
+            | ParseTestListError::WasmComponent { error, .. } => Some(error),
+            ParseTestListError::MissingCwd(error) => Some(error),
+            ParseTestListError::ParseLine { .. } | ParseTestListError::EmptyBinaries { .. } => None,
         }
     }
 }
@@ -382,6 +1033,15 @@ pub enum WriteEventError {
         /// The underlying error.
         error: JunitError,
     },
+
+    /// An error occurred while pushing metrics to a Prometheus Pushgateway.
+    Pushgateway {
+        /// The URL of the Pushgateway.
+        url: String,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
 }
 
 impl fmt::Display for WriteEventError {
@@ -396,6 +1056,9 @@ impl fmt::Display for WriteEventError {
             WriteEventError::Junit { file, .. } => {
                 write!(f, "error writing JUnit output to {}", file)
             }
+            WriteEventError::Pushgateway { url, .. } => {
+                write!(f, "error pushing metrics to {}", url)
+            }
         }
     }
 }
@@ -406,30 +1069,1092 @@ impl error::Error for WriteEventError {
             WriteEventError::Io(error) => Some(error),
             WriteEventError::Fs { error, .. } => Some(error),
             WriteEventError::Junit { error, .. } => Some(error),
+            WriteEventError::Pushgateway { error, .. } => Some(error),
         }
     }
 }
 
-/// An error that occurred while producing JUnit XML.
+/// An error that occurs while writing out information about a run to its store.
 #[derive(Debug)]
-pub struct JunitError {
-    err: quick_junit::Error,
+#[non_exhaustive]
+pub enum RunStoreError {
+    /// An error occurred while operating on the file system.
+    Fs {
+        /// The path being operated on.
+        path: Utf8PathBuf,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
+
+    /// An error occurred while writing to the provided output.
+    Io(std::io::Error),
+
+    /// An error occurred while serializing JSON.
+    Json(serde_json::Error),
 }
 
-impl JunitError {
-    pub(crate) fn new(err: quick_junit::Error) -> Self {
-        Self { err }
+impl fmt::Display for RunStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunStoreError::Fs { path, .. } => {
+                write!(f, "error operating on path {}", path)
+            }
+            RunStoreError::Io(_) => {
+                write!(f, "error writing run information to output")
+            }
+            RunStoreError::Json(_) => {
+                write!(f, "error serializing run information to JSON")
+            }
+        }
     }
 }
 
-impl fmt::Display for JunitError {
-    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
-        Ok(())
+impl error::Error for RunStoreError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RunStoreError::Fs { error, .. } => Some(error),
+            RunStoreError::Io(error) => Some(error),
+            RunStoreError::Json(error) => Some(error),
+        }
     }
 }
 
-impl error::Error for JunitError {
+/// An error that occurs while serving the status of a run in progress, or attaching to one.
+///
+/// See [`run_status`](crate::run_status) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RunStatusError {
+    /// Serving the status of runs isn't supported on this platform.
+    Unsupported,
+
+    /// The socket clients connect to couldn't be created.
+    Bind {
+        /// The path of the socket.
+        path: Utf8PathBuf,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
+
+    /// The run couldn't be advertised in the store directory.
+    Advertise(RunStoreError),
+
+    /// No run is in progress with the profile.
+    NotRunning {
+        /// The path a run in progress would be advertised at.
+        path: Utf8PathBuf,
+    },
+
+    /// The file advertising the run in progress couldn't be parsed.
+    Parse {
+        /// The path of the file.
+        path: Utf8PathBuf,
+
+        /// The underlying JSON error.
+        error: serde_json::Error,
+    },
+
+    /// An error occurred while reading events from the run.
+    Read(std::io::Error),
+}
+
+impl fmt::Display for RunStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunStatusError::Unsupported => {
+                write!(f, "serving the status of runs is only supported on Unix")
+            }
+            RunStatusError::Bind { path, .. } => {
+                write!(f, "failed to create status socket at {}", path)
+            }
+            RunStatusError::Advertise(_) => write!(f, "failed to advertise run in store directory"),
+            RunStatusError::NotRunning { path } => {
+                write!(f, "no run is in progress (looked for {})", path)
+            }
+            RunStatusError::Parse { path, .. } => {
+                write!(f, "failed to parse current run at {}", path)
+            }
+            RunStatusError::Read(_) => write!(f, "failed to read events from run"),
+        }
+    }
+}
+
+impl error::Error for RunStatusError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        Some(&self.err)
+        match self {
+            RunStatusError::Unsupported | RunStatusError::NotRunning { .. } => None,
+            RunStatusError::Bind { error, .. } => Some(error),
+            RunStatusError::Advertise(error) => Some(error),
+            RunStatusError::Parse { error, .. } => Some(error),
+            RunStatusError::Read(error) => Some(error),
+        }
+    }
+}
+
+/// An error that occurs while connecting to the endpoint events are published to.
+///
+/// See [`event_stream`](crate::event_stream) for more.
+#[derive(Debug)]
+pub struct EventStreamError {
+    path: Utf8PathBuf,
+    error: std::io::Error,
+}
+
+impl EventStreamError {
+    pub(crate) fn new(path: impl Into<Utf8PathBuf>, error: std::io::Error) -> Self {
+        Self {
+            path: path.into(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for EventStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to connect to event stream at {}", self.path)
+    }
+}
+
+impl error::Error for EventStreamError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// An error that occurs while running a custom reporter.
+///
+/// See [`custom_reporter`](crate::custom_reporter) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CustomReporterError {
+    /// The reporter's command is empty.
+    EmptyCommand {
+        /// The name of the reporter.
+        name: String,
+    },
+
+    /// The reporter couldn't be started.
+    Spawn {
+        /// The name of the reporter.
+        name: String,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// The handshake with the reporter failed.
+    Handshake {
+        /// The name of the reporter.
+        name: String,
+
+        /// Why the handshake failed.
+        reason: String,
+    },
+
+    /// An error occurred while waiting for the reporter to exit.
+    Wait {
+        /// The name of the reporter.
+        name: String,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// The reporter exited with a non-zero status.
+    Exited {
+        /// The name of the reporter.
+        name: String,
+
+        /// The status the reporter exited with.
+        status: ExitStatus,
+    },
+}
+
+impl fmt::Display for CustomReporterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CustomReporterError::EmptyCommand { name } => {
+                write!(f, "command for custom reporter '{}' is empty", name)
+            }
+            CustomReporterError::Spawn { name, .. } => {
+                write!(f, "failed to start custom reporter '{}'", name)
+            }
+            CustomReporterError::Handshake { name, reason } => {
+                write!(
+                    f,
+                    "handshake with custom reporter '{}' failed: {}",
+                    name, reason
+                )
+            }
+            CustomReporterError::Wait { name, .. } => {
+                write!(f, "failed to wait for custom reporter '{}' to exit", name)
+            }
+            CustomReporterError::Exited { name, status } => {
+                write!(f, "custom reporter '{}' failed ({})", name, status)
+            }
+        }
+    }
+}
+
+impl error::Error for CustomReporterError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CustomReporterError::Spawn { error, .. } | CustomReporterError::Wait { error, .. } => {
+                Some(error)
+            }
+            CustomReporterError::EmptyCommand { .. }
+            | CustomReporterError::Handshake { .. }
+            | CustomReporterError::Exited { .. } => None,
+        }
+    }
+}
+
+/// An error that occurs while running an external selector.
+///
+/// See [`selector`](crate::selector) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SelectorError {
+    /// No selector with the given name is defined.
+    NotFound {
+        /// The name of the selector.
+        name: String,
+    },
+
+    /// The selector's command is empty.
+    EmptyCommand {
+        /// The name of the selector.
+        name: String,
+    },
+
+    /// The selector couldn't be started.
+    Spawn {
+        /// The name of the selector.
+        name: String,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// An error occurred while communicating with the selector.
+    Io {
+        /// The name of the selector.
+        name: String,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// The selector didn't exit within its timeout.
+    TimedOut {
+        /// The name of the selector.
+        name: String,
+
+        /// The timeout.
+        timeout: Duration,
+    },
+
+    /// The selector exited with a non-zero status.
+    Exited {
+        /// The name of the selector.
+        name: String,
+
+        /// The status the selector exited with.
+        status: ExitStatus,
+    },
+
+    /// The selector's response couldn't be understood.
+    InvalidResponse {
+        /// The name of the selector.
+        name: String,
+
+        /// Why the response is invalid.
+        reason: String,
+    },
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SelectorError::NotFound { name } => {
+                write!(f, "selector '{}' is not defined in the config", name)
+            }
+            SelectorError::EmptyCommand { name } => {
+                write!(f, "command for selector '{}' is empty", name)
+            }
+            SelectorError::Spawn { name, .. } => {
+                write!(f, "failed to start selector '{}'", name)
+            }
+            SelectorError::Io { name, .. } => {
+                write!(f, "error communicating with selector '{}'", name)
+            }
+            SelectorError::TimedOut { name, timeout } => {
+                write!(f, "selector '{}' didn't respond within {:?}", name, timeout)
+            }
+            SelectorError::Exited { name, status } => {
+                write!(f, "selector '{}' failed ({})", name, status)
+            }
+            SelectorError::InvalidResponse { name, reason } => {
+                write!(
+                    f,
+                    "selector '{}' sent an invalid response: {}",
+                    name, reason
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for SelectorError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SelectorError::Spawn { error, .. } | SelectorError::Io { error, .. } => Some(error),
+            SelectorError::NotFound { .. }
+            | SelectorError::EmptyCommand { .. }
+            | SelectorError::TimedOut { .. }
+            | SelectorError::Exited { .. }
+            | SelectorError::InvalidResponse { .. } => None,
+        }
+    }
+}
+
+/// An error that occurs while selecting tests by coverage.
+///
+/// See [`coverage`](crate::coverage) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CoverageSelectionError {
+    /// The coverage map couldn't be read.
+    Read {
+        /// The path to the coverage map.
+        path: Utf8PathBuf,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// The coverage map isn't valid JSON in the expected format.
+    Parse {
+        /// The path to the coverage map.
+        path: Utf8PathBuf,
+
+        /// The underlying error.
+        error: serde_json::Error,
+    },
+
+    /// Running Git failed.
+    Git {
+        /// The command that was run.
+        command: String,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// The commit the coverage map was made at isn't an ancestor of `HEAD`, so line numbers in it
+    /// can't be matched up with the changes.
+    Stale {
+        /// The commit the coverage map was made at.
+        commit: String,
+    },
+
+    /// A file that affects how tests are built changed, so any test may behave differently.
+    BuildChanged {
+        /// The file that changed, relative to the workspace root.
+        path: Utf8PathBuf,
+    },
+}
+
+impl fmt::Display for CoverageSelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoverageSelectionError::Read { path, .. } => {
+                write!(f, "failed to read coverage map at {}", path)
+            }
+            CoverageSelectionError::Parse { path, .. } => {
+                write!(f, "failed to parse coverage map at {}", path)
+            }
+            CoverageSelectionError::Git { command, .. } => {
+                write!(f, "running '{}' failed", command)
+            }
+            CoverageSelectionError::Stale { commit } => write!(
+                f,
+                "coverage map is stale: commit '{}' isn't an ancestor of HEAD",
+                commit
+            ),
+            CoverageSelectionError::BuildChanged { path } => write!(
+                f,
+                "coverage map is stale: build input '{}' has changed",
+                path
+            ),
+        }
+    }
+}
+
+impl error::Error for CoverageSelectionError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CoverageSelectionError::Read { error, .. }
+            | CoverageSelectionError::Git { error, .. } => Some(error),
+            CoverageSelectionError::Parse { error, .. } => Some(error),
+            CoverageSelectionError::Stale { .. } | CoverageSelectionError::BuildChanged { .. } => {
+                None
+            }
+        }
+    }
+}
+
+/// An error that occurs while merging or exporting the coverage collected for a run.
+///
+/// See [`llvm_coverage`](crate::llvm_coverage) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LlvmCoverageError {
+    /// A file couldn't be written.
+    Write {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// An LLVM tool couldn't be run.
+    Exec {
+        /// The command that was run.
+        command: String,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// An LLVM tool exited with a failure.
+    Failed {
+        /// The command that was run.
+        command: String,
+
+        /// The standard error of the tool.
+        stderr: String,
+    },
+}
+
+impl fmt::Display for LlvmCoverageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LlvmCoverageError::Write { path, .. } => write!(f, "failed to write {}", path),
+            LlvmCoverageError::Exec { command, .. } => {
+                write!(f, "failed to run '{}'", command)
+            }
+            LlvmCoverageError::Failed { command, stderr } => {
+                write!(f, "'{}' failed", command)?;
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for LlvmCoverageError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            LlvmCoverageError::Write { error, .. } | LlvmCoverageError::Exec { error, .. } => {
+                Some(error)
+            }
+            LlvmCoverageError::Failed { .. } => None,
+        }
+    }
+}
+
+/// An error that occurs while setting up the terminal UI.
+///
+/// See [`tui`](crate::tui) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TuiError {
+    /// Standard input or standard error isn't a terminal.
+    NotATerminal,
+
+    /// The terminal UI isn't supported on this platform.
+    Unsupported,
+
+    /// The terminal couldn't be set up.
+    Terminal(std::io::Error),
+}
+
+impl fmt::Display for TuiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TuiError::NotATerminal => {
+                write!(f, "standard input and standard error must be terminals")
+            }
+            TuiError::Unsupported => write!(f, "the TUI is only supported on Unix platforms"),
+            TuiError::Terminal(_) => write!(f, "failed to set up the terminal"),
+        }
+    }
+}
+
+impl error::Error for TuiError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TuiError::Terminal(error) => Some(error),
+            TuiError::NotATerminal | TuiError::Unsupported => None,
+        }
+    }
+}
+
+/// An error that occurs while computing the packages that have changed since a Git revision.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ChangedSinceError {
+    /// Running Git failed.
+    Git {
+        /// The command that was run.
+        command: String,
+
+        /// The directory the command was run in.
+        dir: Utf8PathBuf,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// A workspace package's manifest couldn't be read.
+    ManifestRead {
+        /// The path to the manifest.
+        path: Utf8PathBuf,
+
+        /// The underlying error.
+        error: std::io::Error,
+    },
+
+    /// A workspace package's manifest couldn't be parsed.
+    ManifestParse {
+        /// The path to the manifest.
+        path: Utf8PathBuf,
+
+        /// The underlying error.
+        error: toml::de::Error,
+    },
+}
+
+impl ChangedSinceError {
+    pub(crate) fn git(
+        command: impl Into<String>,
+        dir: impl Into<Utf8PathBuf>,
+        error: std::io::Error,
+    ) -> Self {
+        ChangedSinceError::Git {
+            command: command.into(),
+            dir: dir.into(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for ChangedSinceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChangedSinceError::Git { command, dir, .. } => {
+                write!(f, "running '{}' in {} failed", command, dir)
+            }
+            ChangedSinceError::ManifestRead { path, .. } => {
+                write!(f, "failed to read manifest '{}'", path)
+            }
+            ChangedSinceError::ManifestParse { path, .. } => {
+                write!(f, "failed to parse manifest '{}'", path)
+            }
+        }
+    }
+}
+
+impl error::Error for ChangedSinceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ChangedSinceError::Git { error, .. } => Some(error),
+            ChangedSinceError::ManifestRead { error, .. } => Some(error),
+            ChangedSinceError::ManifestParse { error, .. } => Some(error),
+        }
+    }
+}
+
+/// An error that occurs while comparing the environment of a run against the local environment.
+///
+/// See [`env_diff`](crate::env_diff) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EnvDiffError {
+    /// The run summary was written by a version of nextest that didn't record the environment.
+    NotRecorded {
+        /// The ID of the run.
+        run_id: Uuid,
+    },
+
+    /// An error occurred while writing to the provided output.
+    Write(std::io::Error),
+
+    /// An error occurred while serializing JSON.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for EnvDiffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvDiffError::NotRecorded { run_id } => write!(
+                f,
+                "the summary of run {} doesn't record its environment \
+                 (it was written by an older version of nextest)",
+                run_id
+            ),
+            EnvDiffError::Write(_) => write!(f, "error writing environment differences to output"),
+            EnvDiffError::Json(_) => {
+                write!(f, "error serializing environment differences to JSON")
+            }
+        }
+    }
+}
+
+impl error::Error for EnvDiffError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            EnvDiffError::NotRecorded { .. } => None,
+            EnvDiffError::Write(error) => Some(error),
+            EnvDiffError::Json(error) => Some(error),
+        }
+    }
+}
+
+/// An error that occurs while computing a [`WorkspaceFingerprint`](crate::fingerprint::WorkspaceFingerprint).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FingerprintError {
+    /// An error occurred while reading a file or directory in the workspace.
+    Fs {
+        /// The path being read.
+        path: Utf8PathBuf,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
+
+    /// An error occurred while running `rustc -vV`.
+    Rustc(std::io::Error),
+
+    /// An error occurred while writing to the provided output.
+    Write(std::io::Error),
+
+    /// An error occurred while serializing JSON.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for FingerprintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FingerprintError::Fs { path, .. } => write!(f, "error reading {}", path),
+            FingerprintError::Rustc(_) => write!(f, "error running 'rustc -vV'"),
+            FingerprintError::Write(_) => write!(f, "error writing fingerprint to output"),
+            FingerprintError::Json(_) => write!(f, "error serializing fingerprint to JSON"),
+        }
+    }
+}
+
+impl error::Error for FingerprintError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            FingerprintError::Fs { error, .. } => Some(error),
+            FingerprintError::Rustc(error) => Some(error),
+            FingerprintError::Write(error) => Some(error),
+            FingerprintError::Json(error) => Some(error),
+        }
+    }
+}
+
+/// An error that occurs while measuring the startup overhead of test binaries.
+///
+/// See [`startup`](crate::startup) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StartupError {
+    /// An error occurred while running a test binary.
+    Exec {
+        /// The binary that was being run.
+        binary_id: String,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
+
+    /// An error occurred while writing to the provided output.
+    Write(std::io::Error),
+
+    /// An error occurred while serializing JSON.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StartupError::Exec { binary_id, .. } => {
+                write!(f, "error running test binary '{}'", binary_id)
+            }
+            StartupError::Write(_) => write!(f, "error writing startup overheads to output"),
+            StartupError::Json(_) => write!(f, "error serializing startup overheads to JSON"),
+        }
+    }
+}
+
+impl error::Error for StartupError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            StartupError::Exec { error, .. } => Some(error),
+            StartupError::Write(error) => Some(error),
+            StartupError::Json(error) => Some(error),
+        }
+    }
+}
+
+/// An error that occurred while loading a dotenv file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EnvFileError {
+    /// The file could not be read.
+    Read {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
+
+    /// The file could not be parsed.
+    Parse {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The 1-based line number the error occurred on.
+        line: usize,
+
+        /// A description of the error.
+        message: String,
+    },
+}
+
+impl fmt::Display for EnvFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvFileError::Read { path, .. } => write!(f, "error reading env file {}", path),
+            EnvFileError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "error parsing env file {}:{}: {}", path, line, message),
+        }
+    }
+}
+
+impl error::Error for EnvFileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            EnvFileError::Read { error, .. } => Some(error),
+            EnvFileError::Parse { .. } => None,
+        }
+    }
+}
+
+/// An error that occurred while reading or writing a timing database.
+///
+/// See [`TimingDatabase`](crate::timings::TimingDatabase) for more.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TimingDatabaseError {
+    /// A timing database or run report could not be read.
+    Read {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
+
+    /// A file was neither a timing database nor a run report.
+    Parse {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The error from parsing the file as a run report.
+        error: serde_json::Error,
+    },
+
+    /// The timing database could not be written out.
+    Write(RunStoreError),
+}
+
+impl fmt::Display for TimingDatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimingDatabaseError::Read { path, .. } => write!(f, "error reading {}", path),
+            TimingDatabaseError::Parse { path, .. } => {
+                write!(f, "{} is neither a timing database nor a run report", path)
+            }
+            TimingDatabaseError::Write(_) => write!(f, "error writing timing database"),
+        }
+    }
+}
+
+impl error::Error for TimingDatabaseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TimingDatabaseError::Read { error, .. } => Some(error),
+            TimingDatabaseError::Parse { error, .. } => Some(error),
+            TimingDatabaseError::Write(error) => Some(error),
+        }
+    }
+}
+
+/// An error that occurred while resolving the dependencies between tests.
+///
+/// See [`test_dependencies`](crate::test_dependencies) for more.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum TestDependencyError {
+    /// Tests depend on each other in a cycle.
+    Cycle {
+        /// The tests in the cycle, each depending on the next, with the first test repeated at
+        /// the end.
+        tests: Vec<String>,
+    },
+}
+
+impl fmt::Display for TestDependencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestDependencyError::Cycle { tests } => {
+                write!(
+                    f,
+                    "tests depend on each other in a cycle: {}",
+                    tests.join(" -> ")
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for TestDependencyError {}
+
+/// An error that occurred while reading the [renames file](crate::renames).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RenamesError {
+    /// The file could not be read.
+    Read {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
+
+    /// The file could not be parsed.
+    Parse {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The underlying TOML error.
+        error: toml::de::Error,
+    },
+
+    /// A rename in the file is invalid.
+    Invalid {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The ID the rename is from.
+        from: String,
+
+        /// The ID the rename is to.
+        to: String,
+
+        /// A description of the error.
+        message: String,
+    },
+}
+
+impl fmt::Display for RenamesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenamesError::Read { path, .. } => write!(f, "error reading renames file {}", path),
+            RenamesError::Parse { path, .. } => write!(f, "error parsing renames file {}", path),
+            RenamesError::Invalid {
+                path,
+                from,
+                to,
+                message,
+            } => write!(
+                f,
+                "invalid rename from '{}' to '{}' in {}: {}",
+                from, to, path, message
+            ),
+        }
+    }
+}
+
+impl error::Error for RenamesError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RenamesError::Read { error, .. } => Some(error),
+            RenamesError::Parse { error, .. } => Some(error),
+            RenamesError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// An error that occurred while running an output filter over the output of a test.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OutputFilterError {
+    /// The filter command could not be started.
+    Spawn(std::io::Error),
+
+    /// An error occurred while waiting for the filter command to finish.
+    Wait(std::io::Error),
+
+    /// The filter command didn't finish within the given timeout, and was killed.
+    Timeout(Duration),
+
+    /// The filter command exited with a failure status.
+    Failed(ExitStatus),
+}
+
+impl fmt::Display for OutputFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputFilterError::Spawn(error) => {
+                write!(f, "error starting output filter: {}", error)
+            }
+            OutputFilterError::Wait(error) => {
+                write!(f, "error waiting for output filter: {}", error)
+            }
+            OutputFilterError::Timeout(timeout) => {
+                write!(f, "output filter timed out after {:?}", timeout)
+            }
+            OutputFilterError::Failed(status) => {
+                write!(f, "output filter failed with {}", status)
+            }
+        }
+    }
+}
+
+impl error::Error for OutputFilterError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            OutputFilterError::Spawn(error) | OutputFilterError::Wait(error) => Some(error),
+            OutputFilterError::Timeout(_) | OutputFilterError::Failed(_) => None,
+        }
+    }
+}
+
+/// An error that occurred while producing JUnit XML.
+#[derive(Debug)]
+pub struct JunitError {
+    err: quick_junit::Error,
+}
+
+impl JunitError {
+    pub(crate) fn new(err: quick_junit::Error) -> Self {
+        Self { err }
+    }
+}
+
+impl fmt::Display for JunitError {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl error::Error for JunitError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.err)
+    }
+}
+
+/// An error that occurs while writing out the resolved config.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ShowConfigError {
+    /// An error occurred while writing to the provided output.
+    Write(std::io::Error),
+
+    /// An error occurred while serializing JSON.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for ShowConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShowConfigError::Write(_) => write!(f, "error writing config to output"),
+            ShowConfigError::Json(_) => write!(f, "error serializing config to JSON"),
+        }
+    }
+}
+
+impl error::Error for ShowConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ShowConfigError::Write(error) => Some(error),
+            ShowConfigError::Json(error) => Some(error),
+        }
+    }
+}
+
+/// An error that occurred while reading the file of [quarantined](crate::quarantine) tests.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum QuarantineError {
+    /// The file could not be read.
+    Read {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The underlying IO error.
+        error: std::io::Error,
+    },
+
+    /// A line in the file isn't a valid test ID.
+    Invalid {
+        /// The path to the file.
+        path: Utf8PathBuf,
+
+        /// The line number, starting from 1.
+        line_number: usize,
+
+        /// A description of the error.
+        message: String,
+    },
+}
+
+impl fmt::Display for QuarantineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuarantineError::Read { path, .. } => {
+                write!(f, "error reading quarantine file {}", path)
+            }
+            QuarantineError::Invalid {
+                path,
+                line_number,
+                message,
+            } => write!(f, "{}:{}: {}", path, line_number, message),
+        }
+    }
+}
+
+impl error::Error for QuarantineError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            QuarantineError::Read { error, .. } => Some(error),
+            QuarantineError::Invalid { .. } => None,
+        }
     }
 }
@@ -0,0 +1,240 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Publishing events over a local IPC channel while a run is in progress.
+//!
+//! Tools such as dashboards and IDE plugins can display the live progress of a run without parsing
+//! nextest's human-readable output. Such a tool listens on a Unix domain socket (or on Windows, a
+//! named pipe), and nextest is pointed at it with `--event-stream <PATH>` or the
+//! [`NEXTEST_EVENT_STREAM_ENV`] environment variable:
+//!
+//! ```sh
+//! cargo nextest run --event-stream /tmp/dashboard.sock
+//! ```
+//!
+//! Nextest connects to the endpoint as the run starts, and writes each event to it as a single line
+//! of JSON. The schema is documented by [`RunEvent`] in the `nextest-metadata` crate.
+//!
+//! If the tool disconnects midway, nextest stops publishing events but carries on with the run.
+
+use crate::{
+    errors::EventStreamError,
+    reporter::{CancelReason, TestEvent},
+    run_report::{attempt_status, test_status},
+    run_store::RunStore,
+    runner::ExecuteStatus,
+    test_list::TestInstance,
+};
+use camino::Utf8Path;
+use nextest_metadata::{OutputStream, RunEvent, SkipReasonSummary, TestEventId};
+use std::{
+    fmt,
+    io::{self, BufWriter, Write},
+};
+
+/// The environment variable naming the socket or named pipe to publish events to, equivalent to
+/// `--event-stream`.
+pub const NEXTEST_EVENT_STREAM_ENV: &str = "NEXTEST_EVENT_STREAM";
+
+/// Publishes the events of a run to a Unix domain socket or a named pipe.
+pub struct EventStream<'a> {
+    run_store: &'a RunStore,
+    // None once the other end has disconnected.
+    writer: Option<BufWriter<Box<dyn Write + Send>>>,
+}
+
+impl<'a> EventStream<'a> {
+    /// Connects to the Unix domain socket or named pipe at `path`.
+    pub fn connect(run_store: &'a RunStore, path: &Utf8Path) -> Result<Self, EventStreamError> {
+        let writer = connect(path).map_err(|error| EventStreamError::new(path, error))?;
+        Ok(Self {
+            run_store,
+            writer: Some(BufWriter::new(writer)),
+        })
+    }
+
+    /// Publishes a test event, as zero or more [`RunEvent`]s.
+    ///
+    /// Errors are not returned: if the other end has disconnected, this and later events are
+    /// dropped.
+    pub fn write_event(&mut self, event: &TestEvent<'_>) {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+        let result = to_run_events(self.run_store, event)
+            .iter()
+            .try_for_each(|run_event| {
+                serde_json::to_writer(&mut *writer, run_event)?;
+                writeln!(writer)
+            })
+            .and_then(|()| writer.flush());
+        if result.is_err() {
+            self.writer = None;
+        }
+    }
+}
+
+impl<'a> fmt::Debug for EventStream<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventStream")
+            .field("run_store", &self.run_store)
+            .field("connected", &self.writer.is_some())
+            .finish()
+    }
+}
+
+#[cfg(unix)]
+fn connect(path: &Utf8Path) -> io::Result<Box<dyn Write + Send>> {
+    Ok(Box::new(std::os::unix::net::UnixStream::connect(path)?))
+}
+
+#[cfg(windows)]
+fn connect(path: &Utf8Path) -> io::Result<Box<dyn Write + Send>> {
+    // The client end of a named pipe is opened like a file.
+    let pipe = std::fs::OpenOptions::new().write(true).open(path)?;
+    Ok(Box::new(pipe))
+}
+
+pub(crate) fn to_run_events(run_store: &RunStore, event: &TestEvent<'_>) -> Vec<RunEvent> {
+    match event {
+        TestEvent::RunStarted { run_count, .. } => vec![RunEvent::RunStarted {
+            run: run_store.info_summary(),
+            test_count: *run_count,
+        }],
+        TestEvent::TestStarted { test_instance } => vec![RunEvent::TestStarted {
+            test: test_id(test_instance),
+        }],
+        TestEvent::TestSlow {
+            test_instance,
+            elapsed,
+        } => vec![RunEvent::TestSlow {
+            test: test_id(test_instance),
+            elapsed: elapsed.as_secs_f64(),
+        }],
+        TestEvent::TestRetry {
+            test_instance,
+            run_status,
+        } => {
+            let mut run_events = output_events(test_instance, run_status);
+            run_events.push(RunEvent::TestRetry {
+                test: test_id(test_instance),
+                attempt: run_status.attempt,
+                status: attempt_status(run_status.result),
+                time_taken: run_status.time_taken.as_secs_f64(),
+            });
+            run_events
+        }
+        TestEvent::TestFinished {
+            test_instance,
+            run_statuses,
+        } => {
+            // The output of earlier attempts was published along with their retry events.
+            let mut run_events = output_events(test_instance, run_statuses.last_status());
+            let (status, skip_reason) = test_status(run_statuses);
+            run_events.push(RunEvent::TestFinished {
+                test: test_id(test_instance),
+                status,
+                skip_reason,
+                attempts: run_statuses.len(),
+                time_taken: run_statuses
+                    .iter()
+                    .map(|run_status| run_status.time_taken.as_secs_f64())
+                    .sum(),
+            });
+            run_events
+        }
+        TestEvent::TestSkipped {
+            test_instance,
+            reason,
+        } => vec![RunEvent::TestSkipped {
+            test: test_id(test_instance),
+            skip_reason: SkipReasonSummary::from_filter(
+                *reason,
+                test_instance.test_info.ignore_reason.as_deref(),
+            ),
+        }],
+        TestEvent::RunBeginCancel { running, reason } => vec![RunEvent::RunCanceled {
+            reason: match reason {
+                CancelReason::TestFailure => "test-failure",
+                CancelReason::ReportError => "report-error",
+                CancelReason::Signal => "signal",
+            }
+            .to_owned(),
+            running: *running,
+        }],
+        TestEvent::RunFinished {
+            elapsed, run_stats, ..
+        } => vec![RunEvent::RunFinished {
+            summary: run_store.summary(run_stats),
+            time_taken: elapsed.as_secs_f64(),
+        }],
+        TestEvent::TestGroupHookFailed { .. }
+        | TestEvent::RunSnapshot { .. }
+        | TestEvent::RunProgress { .. } => vec![],
+    }
+}
+
+fn test_id(test_instance: &TestInstance<'_>) -> TestEventId {
+    TestEventId {
+        binary_id: test_instance.bin_info.binary_id.clone(),
+        name: test_instance.name.to_owned(),
+        variant: test_instance.variant.map(|variant| variant.to_string()),
+    }
+}
+
+fn output_events(test_instance: &TestInstance<'_>, run_status: &ExecuteStatus) -> Vec<RunEvent> {
+    [
+        (OutputStream::Stdout, run_status.stdout()),
+        (OutputStream::Stderr, run_status.stderr()),
+    ]
+    .into_iter()
+    .flat_map(|(stream, output)| {
+        let output = String::from_utf8_lossy(output);
+        output_chunks(&output)
+            .map(|data| RunEvent::TestOutput {
+                test: test_id(test_instance),
+                attempt: run_status.attempt,
+                stream,
+                data: data.to_owned(),
+            })
+            .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
+/// Splits output into chunks of at most [`RunEvent::MAX_OUTPUT_CHUNK`] bytes, without splitting up
+/// any characters.
+fn output_chunks(mut output: &str) -> impl Iterator<Item = &str> {
+    std::iter::from_fn(move || {
+        if output.is_empty() {
+            return None;
+        }
+        let mut end = output.len().min(RunEvent::MAX_OUTPUT_CHUNK);
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, rest) = output.split_at(end);
+        output = rest;
+        Some(chunk)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_output() {
+        assert_eq!(output_chunks("").count(), 0);
+        assert_eq!(output_chunks("hello").collect::<Vec<_>>(), ["hello"]);
+
+        // A multi-byte character straddling the limit is moved to the next chunk.
+        let output = format!("{}é{}", "a".repeat(RunEvent::MAX_OUTPUT_CHUNK - 1), "b");
+        let chunks: Vec<_> = output_chunks(&output).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), RunEvent::MAX_OUTPUT_CHUNK - 1);
+        assert_eq!(chunks[1], "éb");
+        assert_eq!(chunks.concat(), output);
+    }
+}
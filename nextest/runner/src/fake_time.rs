@@ -0,0 +1,244 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Making time-sensitive tests reproducible by giving them a fixed notion of the current time.
+//!
+//! Fake time is opted into through overrides:
+//!
+//! ```toml
+//! [[profile.default.overrides]]
+//! filter = "package(scheduler)"
+//! fake-time = true
+//!
+//! [[profile.default.overrides]]
+//! filter = "package(legacy-billing)"
+//! # Either a number of seconds since the Unix epoch or an RFC 3339 timestamp.
+//! fake-time = { epoch = "2020-02-29T12:00:00Z", preload = "/usr/lib/x86_64-linux-gnu/faketime/libfaketime.so.1" }
+//! ```
+//!
+//! Matching tests see the `NEXTEST_FAKE_EPOCH` environment variable, set to a number of seconds
+//! since the Unix epoch. Tests (or the clock abstraction they use) can read it to pin the current
+//! time. Unless `epoch` is specified, the value is the time the run started at, which is the same
+//! for every test in the run. To reproduce a run, for example one that failed in CI, set
+//! `NEXTEST_FAKE_EPOCH` to the same value in the environment nextest is run in.
+//!
+//! If `preload` is specified, it's the path to a [libfaketime](https://github.com/wolfcw/libfaketime)
+//! style library that is preloaded into matching tests through `LD_PRELOAD` (or
+//! `DYLD_INSERT_LIBRARIES` on macOS), with `FAKETIME` and `FAKETIME_FMT` set so that the clock
+//! starts at the epoch and runs from there. Relative paths are relative to the workspace root.
+//!
+//! `fake-time = false` disables fake time for tests that an earlier override would otherwise
+//! enable it for.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    env, fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The environment variable set to the fake current time of a test, in seconds since the Unix
+/// epoch.
+///
+/// If this is set in the environment nextest is run in, it's used as the epoch of the run.
+pub const NEXTEST_FAKE_EPOCH_ENV: &str = "NEXTEST_FAKE_EPOCH";
+
+/// Fake time settings for tests, specified through the `fake-time` key of an override.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FakeTimeSettings {
+    enabled: bool,
+    epoch: Option<i64>,
+    preload: Option<Utf8PathBuf>,
+}
+
+impl FakeTimeSettings {
+    /// Returns true if fake time is enabled for matching tests.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the fixed epoch for matching tests in seconds since the Unix epoch, if specified.
+    /// Otherwise, the epoch of the run is used.
+    pub fn epoch(&self) -> Option<i64> {
+        self.epoch
+    }
+
+    /// Returns the path to the library preloaded into matching tests, if any.
+    pub fn preload(&self) -> Option<&Utf8Path> {
+        self.preload.as_deref()
+    }
+
+    /// Returns the environment variables set for matching tests, given the epoch of the run.
+    ///
+    /// Relative preload paths are resolved against `workspace_root`.
+    pub fn vars(&self, run_epoch: i64, workspace_root: &Utf8Path) -> Vec<(&'static str, String)> {
+        if !self.enabled {
+            return vec![];
+        }
+        let epoch = self.epoch.unwrap_or(run_epoch);
+        let mut vars = vec![(NEXTEST_FAKE_EPOCH_ENV, epoch.to_string())];
+        if let Some(preload) = &self.preload {
+            let preload = workspace_root.join(preload);
+            let preload_var = if cfg!(target_os = "macos") {
+                vars.push(("DYLD_FORCE_FLAT_NAMESPACE", "1".to_owned()));
+                "DYLD_INSERT_LIBRARIES"
+            } else {
+                "LD_PRELOAD"
+            };
+            // Keep libraries that are already being preloaded.
+            let value = match env::var(preload_var) {
+                Ok(existing) if !existing.is_empty() => format!("{}:{}", preload, existing),
+                _ => preload.into_string(),
+            };
+            vars.push((preload_var, value));
+            vars.push(("FAKETIME", format!("@{}", epoch)));
+            vars.push(("FAKETIME_FMT", "%s".to_owned()));
+        }
+        vars
+    }
+}
+
+/// Returns the epoch of a run: the value of `NEXTEST_FAKE_EPOCH` in the environment nextest is run
+/// in, if valid, or the current time otherwise.
+pub fn run_epoch() -> i64 {
+    env::var(NEXTEST_FAKE_EPOCH_ENV)
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .unwrap_or_else(|| match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch.as_secs() as i64,
+            Err(before_epoch) => -(before_epoch.duration().as_secs() as i64),
+        })
+}
+
+impl<'de> Deserialize<'de> for FakeTimeSettings {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct FakeTimeTableImpl {
+            #[serde(default)]
+            epoch: Option<EpochImpl>,
+            #[serde(default)]
+            preload: Option<Utf8PathBuf>,
+        }
+
+        struct FakeTimeVisitor;
+
+        impl<'de> de::Visitor<'de> for FakeTimeVisitor {
+            type Value = FakeTimeSettings;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a boolean or a table with epoch and preload")
+            }
+
+            fn visit_bool<E: de::Error>(self, enabled: bool) -> Result<Self::Value, E> {
+                Ok(FakeTimeSettings {
+                    enabled,
+                    epoch: None,
+                    preload: None,
+                })
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let table =
+                    FakeTimeTableImpl::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(FakeTimeSettings {
+                    enabled: true,
+                    epoch: table.epoch.map(|epoch| epoch.0),
+                    preload: table.preload,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(FakeTimeVisitor)
+    }
+}
+
+// An epoch specified as seconds since the Unix epoch or as an RFC 3339 timestamp.
+struct EpochImpl(i64);
+
+impl<'de> Deserialize<'de> for EpochImpl {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EpochVisitor;
+
+        impl<'de> de::Visitor<'de> for EpochVisitor {
+            type Value = EpochImpl;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "a number of seconds since the Unix epoch or an RFC 3339 timestamp"
+                )
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                Ok(EpochImpl(value))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                i64::try_from(value)
+                    .map(EpochImpl)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                chrono::DateTime::parse_from_rfc3339(value)
+                    .map(|timestamp| EpochImpl(timestamp.timestamp()))
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_any(EpochVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fake_time_vars() {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Override {
+            fake_time: FakeTimeSettings,
+        }
+        let parse = |contents: &str| {
+            toml::from_str::<Override>(contents)
+                .map(|rule| rule.fake_time)
+                .map_err(|err| err.to_string())
+        };
+        let workspace_root = Utf8Path::new("/workspace");
+
+        let settings = parse("fake-time = true").expect("settings are valid");
+        assert_eq!(
+            settings.vars(1_600_000_000, workspace_root),
+            [(NEXTEST_FAKE_EPOCH_ENV, "1600000000".to_owned())]
+        );
+
+        let settings = parse("fake-time = false").expect("settings are valid");
+        assert!(!settings.enabled());
+        assert_eq!(settings.vars(1_600_000_000, workspace_root), []);
+
+        let settings =
+            parse(r#"fake-time = { epoch = "2020-02-29T12:00:00Z" }"#).expect("settings are valid");
+        assert_eq!(settings.epoch(), Some(1_582_977_600));
+        let settings = parse("fake-time = { epoch = 86400, preload = \"tools/libfaketime.so\" }")
+            .expect("settings are valid");
+        let vars = settings.vars(1_600_000_000, workspace_root);
+        assert_eq!(vars[0], (NEXTEST_FAKE_EPOCH_ENV, "86400".to_owned()));
+        assert!(vars.contains(&("FAKETIME", "@86400".to_owned())));
+        assert!(vars.contains(&("FAKETIME_FMT", "%s".to_owned())));
+        assert!(vars.iter().any(|(key, value)| {
+            ["LD_PRELOAD", "DYLD_INSERT_LIBRARIES"].contains(key)
+                && value.starts_with("/workspace/tools/libfaketime.so")
+        }));
+
+        parse(r#"fake-time = { epoch = "yesterday" }"#).expect_err("epoch is invalid");
+    }
+}
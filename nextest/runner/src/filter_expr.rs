@@ -0,0 +1,584 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Filter expressions, used to select tests in nextest configuration and on the command line.
+//!
+//! A filter expression is made up of *predicates* combined with boolean operators. The supported
+//! predicates are:
+//!
+//! * `all()`: matches every test.
+//! * `none()`: matches no tests.
+//! * `test(matcher)`: matches the name of the test. Defaults to a substring match.
+//! * `package(matcher)`: matches the name of the package the test is in. Defaults to an exact
+//!   match.
+//! * `binary(matcher)`: matches the binary ID of the test. Defaults to an exact match.
+//! * `empty-binary()`: matches test binaries that don't contain any tests. Since there are no
+//!   tests in such binaries, this doesn't match any tests: it's used to decide which empty
+//!   binaries are shown in test lists. While evaluating an expression against an empty binary,
+//!   `test()` predicates don't match.
+//!
+//! A matcher can be prefixed with `=` for an exact match or `~` for a substring match.
+//!
+//! Predicates can be combined with `|` (or `or`), `&` (or `and`) and `!` (or `not`), and grouped
+//! with parentheses. `&` binds more tightly than `|`.
+//!
+//! For example, `package(my-crate) & test(~integration_)` matches all tests in `my-crate` whose
+//! names contain `integration_`.
+//!
+//! Expressions can also be evaluated against a test binary before its tests are known, to find out
+//! whether any of them could match: see [`FilterExpr::could_match_binary`]. Binaries that can't
+//! match don't need to be listed at all.
+
+use crate::errors::FilterExprParseError;
+use guppy::PackageId;
+use nextest_metadata::BuildPlatform;
+use serde::{Deserialize, Deserializer};
+use std::{fmt, str::FromStr};
+
+/// A parsed filter expression.
+///
+/// Created through the [`FromStr`] implementation, or by deserializing a string.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FilterExpr {
+    input: String,
+    expr: ExprNode,
+}
+
+impl FilterExpr {
+    /// Returns the input string this expression was parsed from.
+    pub fn as_str(&self) -> &str {
+        &self.input
+    }
+
+    /// Returns this expression as it was parsed, in a normalized form: every matcher has an
+    /// explicit `=` or `~` prefix, operators are written as `&`, `|` and `!`, and every `&` and `|`
+    /// is parenthesized. For example, `package(foo) and test(bar) or none()` becomes
+    /// `((package(=foo) & test(~bar)) | none())`.
+    ///
+    /// The result parses back to an equivalent expression.
+    pub fn compiled(&self) -> String {
+        self.expr.to_string()
+    }
+
+    /// Returns true if the given test matches this expression.
+    pub fn matches_test(&self, query: &TestQuery<'_>) -> bool {
+        self.expr.matches_test(query)
+    }
+
+    /// Returns false if no test in the given binary can match this expression, and true if some
+    /// might.
+    pub fn could_match_binary(&self, query: &BinaryQuery<'_>) -> bool {
+        self.expr.matches_binary(query).unwrap_or(true)
+    }
+
+    /// Returns true if the given binary, which doesn't contain any tests, matches this expression.
+    pub fn matches_empty_binary(&self, query: &BinaryQuery<'_>) -> bool {
+        self.expr.matches_empty_binary(query)
+    }
+}
+
+impl FromStr for FilterExpr {
+    type Err = FilterExprParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { input, pos: 0 };
+        let expr = parser.parse_or()?;
+        parser.skip_whitespace();
+        if parser.pos < input.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+        Ok(Self {
+            input: input.to_owned(),
+            expr,
+        })
+    }
+}
+
+impl fmt::Display for FilterExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.input)
+    }
+}
+
+impl<'de> Deserialize<'de> for FilterExpr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let input = String::deserialize(deserializer)?;
+        input.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The information about a test that a [`FilterExpr`] is evaluated against.
+#[derive(Copy, Clone, Debug)]
+pub struct TestQuery<'a> {
+    /// The binary ID of the test.
+    pub binary_id: &'a str,
+
+    /// The name of the package the test is in.
+    pub package_name: &'a str,
+
+    /// The name of the test.
+    pub test_name: &'a str,
+
+    /// The platform the test's binary was built for.
+    pub platform: BuildPlatform,
+}
+
+/// The information about a test binary that a [`FilterExpr`] is evaluated against, before the
+/// tests in it are known.
+#[derive(Copy, Clone, Debug)]
+pub struct BinaryQuery<'a> {
+    /// The ID of the package the binary is in.
+    pub package_id: &'a PackageId,
+
+    /// The name of the package the binary is in.
+    pub package_name: &'a str,
+
+    /// The binary ID.
+    pub binary_id: &'a str,
+
+    /// The platform the binary was built for.
+    pub platform: BuildPlatform,
+}
+
+impl<'a> BinaryQuery<'a> {
+    /// Returns the query for the test with the given name in this binary.
+    pub fn to_test_query(&self, test_name: &'a str) -> TestQuery<'a> {
+        TestQuery {
+            binary_id: self.binary_id,
+            package_name: self.package_name,
+            test_name,
+            platform: self.platform,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum ExprNode {
+    All,
+    None,
+    Test(NameMatcher),
+    Package(NameMatcher),
+    Binary(NameMatcher),
+    EmptyBinary,
+    Not(Box<ExprNode>),
+    And(Box<ExprNode>, Box<ExprNode>),
+    Or(Box<ExprNode>, Box<ExprNode>),
+}
+
+impl ExprNode {
+    fn matches_test(&self, query: &TestQuery<'_>) -> bool {
+        match self {
+            ExprNode::All => true,
+            ExprNode::None => false,
+            ExprNode::Test(matcher) => matcher.is_match(query.test_name),
+            ExprNode::Package(matcher) => matcher.is_match(query.package_name),
+            ExprNode::Binary(matcher) => matcher.is_match(query.binary_id),
+            // A binary with a test in it isn't empty.
+            ExprNode::EmptyBinary => false,
+            ExprNode::Not(expr) => !expr.matches_test(query),
+            ExprNode::And(a, b) => a.matches_test(query) && b.matches_test(query),
+            ExprNode::Or(a, b) => a.matches_test(query) || b.matches_test(query),
+        }
+    }
+
+    /// Evaluates this expression for a binary, with `None` meaning that the result depends on the
+    /// test.
+    fn matches_binary(&self, query: &BinaryQuery<'_>) -> Option<bool> {
+        match self {
+            ExprNode::All => Some(true),
+            ExprNode::None => Some(false),
+            ExprNode::Test(_) => None,
+            ExprNode::Package(matcher) => Some(matcher.is_match(query.package_name)),
+            ExprNode::Binary(matcher) => Some(matcher.is_match(query.binary_id)),
+            // Whether a binary is empty isn't known until its tests are listed.
+            ExprNode::EmptyBinary => None,
+            ExprNode::Not(expr) => expr.matches_binary(query).map(|matches| !matches),
+            ExprNode::And(a, b) => match (a.matches_binary(query), b.matches_binary(query)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            ExprNode::Or(a, b) => match (a.matches_binary(query), b.matches_binary(query)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+        }
+    }
+
+    fn matches_empty_binary(&self, query: &BinaryQuery<'_>) -> bool {
+        match self {
+            ExprNode::All => true,
+            ExprNode::None => false,
+            // There are no tests for the name to match.
+            ExprNode::Test(_) => false,
+            ExprNode::Package(matcher) => matcher.is_match(query.package_name),
+            ExprNode::Binary(matcher) => matcher.is_match(query.binary_id),
+            ExprNode::EmptyBinary => true,
+            ExprNode::Not(expr) => !expr.matches_empty_binary(query),
+            ExprNode::And(a, b) => a.matches_empty_binary(query) && b.matches_empty_binary(query),
+            ExprNode::Or(a, b) => a.matches_empty_binary(query) || b.matches_empty_binary(query),
+        }
+    }
+}
+
+impl fmt::Display for ExprNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprNode::All => write!(f, "all()"),
+            ExprNode::None => write!(f, "none()"),
+            ExprNode::Test(matcher) => write!(f, "test({})", matcher),
+            ExprNode::Package(matcher) => write!(f, "package({})", matcher),
+            ExprNode::Binary(matcher) => write!(f, "binary({})", matcher),
+            ExprNode::EmptyBinary => write!(f, "empty-binary()"),
+            ExprNode::Not(expr) => write!(f, "!{}", expr),
+            ExprNode::And(a, b) => write!(f, "({} & {})", a, b),
+            ExprNode::Or(a, b) => write!(f, "({} | {})", a, b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum NameMatcher {
+    Equal(String),
+    Contains(String),
+}
+
+impl NameMatcher {
+    fn is_match(&self, input: &str) -> bool {
+        match self {
+            NameMatcher::Equal(value) => input == value,
+            NameMatcher::Contains(value) => input.contains(value.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for NameMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (prefix, value) = match self {
+            NameMatcher::Equal(value) => ('=', value),
+            NameMatcher::Contains(value) => ('~', value),
+        };
+        write!(f, "{}", prefix)?;
+        for c in value.chars() {
+            if c == '\\' || c == ')' {
+                write!(f, "\\")?;
+            }
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum DefaultMatcher {
+    Equal,
+    Contains,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_or(&mut self) -> Result<ExprNode, FilterExprParseError> {
+        let mut expr = self.parse_and()?;
+        while self.eat_operator("|", "or") {
+            let rhs = self.parse_and()?;
+            expr = ExprNode::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<ExprNode, FilterExprParseError> {
+        let mut expr = self.parse_not()?;
+        while self.eat_operator("&", "and") {
+            let rhs = self.parse_not()?;
+            expr = ExprNode::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<ExprNode, FilterExprParseError> {
+        if self.eat_operator("!", "not") {
+            let expr = self.parse_not()?;
+            return Ok(ExprNode::Not(Box::new(expr)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<ExprNode, FilterExprParseError> {
+        self.skip_whitespace();
+        if self.rest().starts_with('(') {
+            self.pos += 1;
+            let expr = self.parse_or()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with(')') {
+                return Err(self.error("expected `)`"));
+            }
+            self.pos += 1;
+            return Ok(expr);
+        }
+
+        let start = self.pos;
+        let name = self.parse_ident();
+        if name.is_empty() {
+            return Err(self.error("expected a predicate such as `test(...)`"));
+        }
+        if !self.rest().starts_with('(') {
+            self.pos = start;
+            return Err(self.error(format!("expected `(` after `{}`", name)));
+        }
+        self.pos += 1;
+
+        let expr = match name {
+            "all" => {
+                self.expect_close()?;
+                ExprNode::All
+            }
+            "none" => {
+                self.expect_close()?;
+                ExprNode::None
+            }
+            "empty-binary" => {
+                self.expect_close()?;
+                ExprNode::EmptyBinary
+            }
+            "test" => ExprNode::Test(self.parse_matcher(DefaultMatcher::Contains)?),
+            "package" => ExprNode::Package(self.parse_matcher(DefaultMatcher::Equal)?),
+            "binary" => ExprNode::Binary(self.parse_matcher(DefaultMatcher::Equal)?),
+            other => {
+                self.pos = start;
+                return Err(self.error(format!("unknown predicate `{}`", other)));
+            }
+        };
+        Ok(expr)
+    }
+
+    fn parse_matcher(
+        &mut self,
+        default: DefaultMatcher,
+    ) -> Result<NameMatcher, FilterExprParseError> {
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, c)) => value.push(c),
+                    None => return Err(self.error("unterminated escape sequence")),
+                },
+                Some((idx, ')')) => break idx,
+                Some((_, c)) => value.push(c),
+                None => return Err(self.error("expected `)`")),
+            }
+        };
+        // Skip past the closing parenthesis.
+        self.pos += end + 1;
+
+        let value = value.trim();
+        let matcher = if let Some(value) = value.strip_prefix('=') {
+            NameMatcher::Equal(value.to_owned())
+        } else if let Some(value) = value.strip_prefix('~') {
+            NameMatcher::Contains(value.to_owned())
+        } else {
+            match default {
+                DefaultMatcher::Equal => NameMatcher::Equal(value.to_owned()),
+                DefaultMatcher::Contains => NameMatcher::Contains(value.to_owned()),
+            }
+        };
+        Ok(matcher)
+    }
+
+    fn expect_close(&mut self) -> Result<(), FilterExprParseError> {
+        self.skip_whitespace();
+        if !self.rest().starts_with(')') {
+            return Err(self.error("expected `)`"));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn eat_operator(&mut self, symbol: &str, word: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(symbol) {
+            self.pos += symbol.len();
+            return true;
+        }
+        let start = self.pos;
+        if self.parse_ident() == word {
+            return true;
+        }
+        self.pos = start;
+        false
+    }
+
+    fn parse_ident(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn error(&self, message: impl Into<String>) -> FilterExprParseError {
+        FilterExprParseError::new(self.input, self.pos, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query<'a>(binary_id: &'a str, test_name: &'a str) -> TestQuery<'a> {
+        TestQuery {
+            binary_id,
+            package_name: "my-package",
+            test_name,
+            platform: BuildPlatform::Target,
+        }
+    }
+
+    #[test]
+    fn parse_and_match() {
+        let expr: FilterExpr = "test(foo)".parse().unwrap();
+        assert!(expr.matches_test(&query("my-package", "tests::foo_bar")));
+        assert!(!expr.matches_test(&query("my-package", "tests::bar")));
+
+        let expr: FilterExpr = "test(=tests::foo)".parse().unwrap();
+        assert!(expr.matches_test(&query("my-package", "tests::foo")));
+        assert!(!expr.matches_test(&query("my-package", "tests::foo_bar")));
+
+        let expr: FilterExpr = "package(my-package) & !binary(my-package::other)"
+            .parse()
+            .unwrap();
+        assert!(expr.matches_test(&query("my-package", "tests::foo")));
+        assert!(!expr.matches_test(&query("my-package::other", "tests::foo")));
+
+        let expr: FilterExpr = "none() or (test(~a) and not test(b))".parse().unwrap();
+        assert!(expr.matches_test(&query("my-package", "a")));
+        assert!(!expr.matches_test(&query("my-package", "ab")));
+
+        let expr: FilterExpr = "all() & test(\\))".parse().unwrap();
+        assert!(expr.matches_test(&query("my-package", "a)b")));
+    }
+
+    #[test]
+    fn could_match_binary() {
+        let package_id = PackageId::new("my-package-id");
+        let binary = |binary_id| BinaryQuery {
+            package_id: &package_id,
+            package_name: "my-package",
+            binary_id,
+            platform: BuildPlatform::Target,
+        };
+
+        for (input, expected) in [
+            ("test(foo)", true),
+            ("package(my-package)", true),
+            ("package(other)", false),
+            ("package(other) & test(foo)", false),
+            ("package(other) | test(foo)", true),
+            ("!(binary(my-package) & test(foo))", true),
+            ("!binary(my-package)", false),
+            ("binary(other) | none()", false),
+        ] {
+            let expr: FilterExpr = input.parse().unwrap();
+            assert_eq!(
+                expr.could_match_binary(&binary("my-package")),
+                expected,
+                "for `{}`",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn empty_binary() {
+        let package_id = PackageId::new("my-package-id");
+        let binary = BinaryQuery {
+            package_id: &package_id,
+            package_name: "my-package",
+            binary_id: "my-package::empty",
+            platform: BuildPlatform::Target,
+        };
+
+        let expr: FilterExpr = "empty-binary()".parse().unwrap();
+        assert!(!expr.matches_test(&query("my-package::empty", "tests::foo")));
+        assert!(
+            expr.could_match_binary(&binary),
+            "emptiness isn't known yet"
+        );
+        assert!(expr.matches_empty_binary(&binary));
+
+        for (input, expected) in [
+            ("all()", true),
+            ("test(foo)", false),
+            ("!test(foo)", true),
+            ("empty-binary() & package(my-package)", true),
+            ("empty-binary() & binary(other)", false),
+            ("!empty-binary()", false),
+        ] {
+            let expr: FilterExpr = input.parse().unwrap();
+            assert_eq!(
+                expr.matches_empty_binary(&binary),
+                expected,
+                "for `{}`",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for input in [
+            "",
+            "test(foo",
+            "unknown(foo)",
+            "test(foo) |",
+            "(test(foo)",
+            "test(foo) test(bar)",
+            "all(foo)",
+            "empty-binary(foo)",
+        ] {
+            input
+                .parse::<FilterExpr>()
+                .expect_err(&format!("`{}` should fail to parse", input));
+        }
+    }
+
+    #[test]
+    fn compiled_form() {
+        for (input, expected) in [
+            ("test(foo)", "test(~foo)"),
+            (
+                "package(foo) and test(bar) or none()",
+                "((package(=foo) & test(~bar)) | none())",
+            ),
+            (
+                "not binary(~a) & (all() | empty-binary())",
+                "(!binary(~a) & (all() | empty-binary()))",
+            ),
+            (r"test(=f\)oo)", r"test(=f\)oo)"),
+        ] {
+            let expr: FilterExpr = input.parse().unwrap();
+            assert_eq!(expr.compiled(), expected, "for `{}`", input);
+            let reparsed: FilterExpr = expr.compiled().parse().unwrap();
+            assert_eq!(reparsed.expr, expr.expr, "for `{}`", input);
+        }
+    }
+}
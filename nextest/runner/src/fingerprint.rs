@@ -0,0 +1,190 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Stable fingerprints of a workspace, for use as cache keys in CI.
+//!
+//! A [`WorkspaceFingerprint`] combines:
+//!
+//! * a hash of the source files in every workspace package, skipping hidden files and directories
+//!   as well as the `target` directory at the workspace root and symbolic links;
+//! * a hash of the workspace's `Cargo.lock`, if any;
+//! * the verbose version information reported by `rustc -vV`, which includes the host triple.
+//!
+//! The hashes are computed over file contents and paths relative to the workspace root, so the
+//! fingerprint is the same for checkouts of the same sources in different locations. Modification
+//! times are ignored.
+
+use crate::{errors::FingerprintError, test_list::OutputFormat};
+use camino::{Utf8Path, Utf8PathBuf};
+use guppy::graph::PackageGraph;
+use nextest_metadata::FingerprintSummary;
+use std::{collections::BTreeSet, fs, hash::Hasher, io, io::Write};
+use twox_hash::XxHash64;
+
+/// A fingerprint of a workspace and the toolchain used to build it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WorkspaceFingerprint {
+    sources: u64,
+    lockfile: Option<u64>,
+    rustc_version: String,
+}
+
+impl WorkspaceFingerprint {
+    /// Computes the fingerprint of the workspace in the given package graph.
+    ///
+    /// The Rust compiler is the one named by the `RUSTC` environment variable, or `rustc` if it
+    /// isn't set.
+    pub fn compute(graph: &PackageGraph) -> Result<Self, FingerprintError> {
+        let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+        let rustc_version = duct::cmd!(rustc, "-vV")
+            .stdout_capture()
+            .read()
+            .map_err(FingerprintError::Rustc)?;
+        Self::compute_with_rustc_version(graph, rustc_version)
+    }
+
+    /// Computes the fingerprint of the workspace in the given package graph, with the given output
+    /// of `rustc -vV`.
+    pub fn compute_with_rustc_version(
+        graph: &PackageGraph,
+        rustc_version: impl Into<String>,
+    ) -> Result<Self, FingerprintError> {
+        let workspace = graph.workspace();
+        let root = workspace.root();
+
+        // Collect paths first so that the hash doesn't depend on the order packages or directory
+        // entries are visited in.
+        let mut paths = BTreeSet::new();
+        for package in workspace.iter() {
+            let package_dir = package
+                .manifest_path()
+                .parent()
+                .expect("manifest path has a parent");
+            collect_files(root, package_dir, &mut paths)?;
+        }
+
+        let mut hasher = XxHash64::default();
+        for rel_path in &paths {
+            // Use forward slashes so that the hash is the same on every platform.
+            hash_bytes(&mut hasher, rel_path.as_str().replace('\\', "/").as_bytes());
+            hash_bytes(&mut hasher, &read_file(&root.join(rel_path))?);
+        }
+        let sources = hasher.finish();
+
+        let lockfile_path = root.join("Cargo.lock");
+        let lockfile = match fs::read(&lockfile_path) {
+            Ok(contents) => {
+                let mut hasher = XxHash64::default();
+                hash_bytes(&mut hasher, &contents);
+                Some(hasher.finish())
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => {
+                return Err(FingerprintError::Fs {
+                    path: lockfile_path,
+                    error,
+                })
+            }
+        };
+
+        Ok(Self {
+            sources,
+            lockfile,
+            rustc_version: rustc_version.into().trim().to_owned(),
+        })
+    }
+
+    /// Returns the overall fingerprint, combining all of its components.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = XxHash64::default();
+        hasher.write_u64(self.sources);
+        match self.lockfile {
+            Some(lockfile) => {
+                hasher.write_u8(1);
+                hasher.write_u64(lockfile);
+            }
+            None => hasher.write_u8(0),
+        }
+        hash_bytes(&mut hasher, self.rustc_version.as_bytes());
+        hasher.finish()
+    }
+
+    /// Returns a serializable summary of this fingerprint.
+    pub fn to_summary(&self) -> FingerprintSummary {
+        FingerprintSummary {
+            fingerprint: format!("{:016x}", self.fingerprint()),
+            sources: format!("{:016x}", self.sources),
+            lockfile: self.lockfile.map(|lockfile| format!("{:016x}", lockfile)),
+            rustc_version: self
+                .rustc_version
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+
+    /// Writes out this fingerprint in the given format.
+    ///
+    /// The plain format is just the overall fingerprint, followed by a newline.
+    pub fn write(
+        &self,
+        output_format: OutputFormat,
+        mut writer: impl Write,
+    ) -> Result<(), FingerprintError> {
+        match output_format {
+            OutputFormat::Plain => {
+                writeln!(writer, "{:016x}", self.fingerprint()).map_err(FingerprintError::Write)
+            }
+            OutputFormat::Serializable(format) => format
+                .to_writer(&self.to_summary(), writer)
+                .map_err(FingerprintError::Json),
+        }
+    }
+}
+
+/// Adds the paths of all files within `dir` to `paths`, relative to `root`.
+fn collect_files(
+    root: &Utf8Path,
+    dir: &Utf8Path,
+    paths: &mut BTreeSet<Utf8PathBuf>,
+) -> Result<(), FingerprintError> {
+    let fs_err = |error| FingerprintError::Fs {
+        path: dir.to_owned(),
+        error,
+    };
+    for entry in dir.read_dir().map_err(fs_err)? {
+        let entry = entry.map_err(fs_err)?;
+        // Files with non-UTF-8 paths can't be part of a Cargo package's sources anyway.
+        let path = match Utf8PathBuf::try_from(entry.path()) {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+        let is_hidden = path.file_name().is_some_and(|name| name.starts_with('.'));
+        if is_hidden || path == root.join("target") {
+            continue;
+        }
+        let file_type = entry.file_type().map_err(fs_err)?;
+        if file_type.is_dir() {
+            collect_files(root, &path, paths)?;
+        } else if file_type.is_file() {
+            if let Ok(rel_path) = path.strip_prefix(root) {
+                paths.insert(rel_path.to_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_file(path: &Utf8Path) -> Result<Vec<u8>, FingerprintError> {
+    fs::read(path).map_err(|error| FingerprintError::Fs {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+// Prefixes the bytes with their length, so that adjacent inputs can't run into each other.
+fn hash_bytes(hasher: &mut XxHash64, bytes: &[u8]) {
+    hasher.write_u64(bytes.len() as u64);
+    hasher.write(bytes);
+}
@@ -16,3 +16,64 @@ pub(crate) fn write_test_name(name: &str, style: Style, mut writer: impl Write)
 
     Ok(())
 }
+
+/// Writes out styled segments, truncated so that the line doesn't wrap. A line that wraps would
+/// take up more than one line on screen, and would then not be cleared properly.
+pub(crate) fn write_truncated(
+    segments: &[(String, Style)],
+    width: usize,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    // Leave the last column free: some terminals wrap as soon as it's written to.
+    let mut remaining = width.saturating_sub(1);
+    for (text, style) in segments {
+        let len = text.chars().count();
+        if len <= remaining {
+            write!(writer, "{}", text.style(*style))?;
+            remaining -= len;
+        } else {
+            let truncated: String = text.chars().take(remaining).collect();
+            write!(writer, "{}", truncated.style(*style))?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+// The size to assume if it can't be determined from the terminal.
+const DEFAULT_WIDTH: usize = 80;
+const DEFAULT_HEIGHT: usize = 24;
+
+/// Returns the width of the terminal standard error is connected to.
+pub(crate) fn terminal_width() -> usize {
+    terminal_size().0
+}
+
+/// Returns the width and height of the terminal standard error is connected to.
+pub(crate) fn terminal_size() -> (usize, usize) {
+    let size = terminal_size_impl();
+    let from_env = |var: &str| std::env::var(var).ok()?.parse().ok();
+    (
+        size.map(|(width, _)| width)
+            .or_else(|| from_env("COLUMNS"))
+            .unwrap_or(DEFAULT_WIDTH),
+        size.map(|(_, height)| height)
+            .or_else(|| from_env("LINES"))
+            .unwrap_or(DEFAULT_HEIGHT),
+    )
+}
+
+#[cfg(unix)]
+fn terminal_size_impl() -> Option<(usize, usize)> {
+    // SAFETY: winsize is a plain C struct, for which all zeroes is a valid value.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ only writes to the winsize struct passed in.
+    let ret = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) };
+    (ret == 0 && size.ws_col > 0 && size.ws_row > 0)
+        .then_some((size.ws_col as usize, size.ws_row as usize))
+}
+
+#[cfg(not(unix))]
+fn terminal_size_impl() -> Option<(usize, usize)> {
+    None
+}
@@ -15,7 +15,8 @@
 //! 2. The messages generated by Cargo are processed into a list of [`test_list::RustTestArtifact`]
 //!    instances.
 //! 3. Separately, a [`test_filter::TestFilter`] is created based on text filters, along with the
-//!    run-ignored and partitioning filters if provided.
+//!    run-ignored and partitioning filters if provided. If only tests in packages that have changed
+//!    should be run, the filter is also given a [`changed::ChangedPackages`].
 //! 4. The list of test binaries and test filter are combined. Each binary is run with `--list` to
 //!    grab the list of tests, the given filters are applied to it, and everything is put together
 //!    to create a [`test_list::TestList`].
@@ -40,13 +41,61 @@
 //! 6. The test reporter sees events and prints them to stderr (and aggregates them if necessary
 //!    based on configs).
 
+pub mod adb_runner;
+pub mod build_meta;
+pub mod cache;
+pub mod changed;
 pub mod config;
+pub mod container;
+pub mod coverage;
+pub mod crash;
+pub mod custom_reporter;
+pub mod disk_usage;
+pub mod env_diff;
+pub mod env_file;
+mod environment;
 pub mod errors;
+pub mod event_stream;
+pub mod fake_time;
+pub mod filter_expr;
+pub mod fingerprint;
 mod helpers;
+pub mod line_buffering;
+pub mod llvm_coverage;
+pub mod nesting;
+pub mod other_tests;
+pub mod output_filter;
+pub mod output_sanitizer;
 pub mod partition;
+mod path_mapper;
+pub mod probe_runner;
+pub mod property_test;
+pub mod quarantine;
+pub mod remote_runner;
+pub mod renames;
+pub mod report_diff;
 pub mod reporter;
+pub mod required_binaries;
+pub mod retention;
+pub mod run_report;
+pub mod run_status;
+pub mod run_store;
 pub mod runner;
+pub mod runner_lifecycle;
+pub mod sanitizer_report;
+pub mod schedule;
+pub mod selector;
+mod semaphore;
 pub mod signal;
-mod stopwatch;
+pub mod soak;
+pub mod startup;
+pub mod stopwatch;
+pub mod target_runner;
+pub mod test_dependencies;
 pub mod test_filter;
+pub mod test_groups;
 pub mod test_list;
+pub mod timings;
+pub mod tui;
+pub mod valgrind;
+pub mod wasm;
@@ -0,0 +1,127 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Making tests line-buffer their standard output.
+//!
+//! Nextest reads the output of tests through pipes, and many runtimes fully buffer standard output
+//! when it isn't a terminal. Output printed by such tests shows up in large chunks, or only once
+//! they exit, which garbles the interleaved output of tests run with `--no-capture` and delays
+//! output that's streamed while tests run. (Rust's standard output is always line-buffered, so
+//! this mostly matters for tests that aren't written in Rust, and for subprocesses run by tests.)
+//!
+//! ```toml
+//! [profile.default.line-buffering]
+//! env = true
+//! preload = "/usr/libexec/coreutils/libstdbuf.so"
+//! ```
+//!
+//! * `env` (the default) sets environment variables that make common runtimes flush standard output
+//!   after each line, such as `PYTHONUNBUFFERED`.
+//! * `preload` is a library preloaded into tests on Unix that sets up line buffering for C stdio,
+//!   the way `stdbuf -oL` does. This is typically coreutils' `libstdbuf.so`, whose location varies
+//!   between systems. It's appended to `LD_PRELOAD`, or `DYLD_INSERT_LIBRARIES` on macOS.
+//!
+//! Environment variables from [env files](crate::env_file) take precedence over these.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::Expression;
+use serde::Deserialize;
+
+/// Environment variables set for tests to line-buffer their standard output.
+pub const LINE_BUFFERING_ENV: &[(&str, &str)] = &[("PYTHONUNBUFFERED", "1")];
+
+/// Makes tests line-buffer their standard output, even when it's piped.
+///
+/// Obtained through
+/// [`NextestProfile::line_buffering`](crate::config::NextestProfile::line_buffering).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LineBuffering {
+    #[serde(default = "default_env")]
+    env: bool,
+    #[serde(default)]
+    preload: Option<Utf8PathBuf>,
+}
+
+fn default_env() -> bool {
+    true
+}
+
+impl LineBuffering {
+    /// Returns true if [`LINE_BUFFERING_ENV`] is set for tests.
+    pub fn env(&self) -> bool {
+        self.env
+    }
+
+    /// Returns the library preloaded into tests to line-buffer C stdio, if any.
+    pub fn preload(&self) -> Option<&Utf8Path> {
+        self.preload.as_deref()
+    }
+
+    /// Returns the environment variables to set for tests.
+    pub(crate) fn vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars: Vec<_> = match self.env {
+            true => LINE_BUFFERING_ENV
+                .iter()
+                .map(|(key, value)| (*key, (*value).to_owned()))
+                .collect(),
+            false => vec![],
+        };
+        if let (Some(preload), true) = (&self.preload, cfg!(unix)) {
+            let preload_env = if cfg!(target_os = "macos") {
+                "DYLD_INSERT_LIBRARIES"
+            } else {
+                "LD_PRELOAD"
+            };
+            let preload = match std::env::var(preload_env) {
+                Ok(existing) if !existing.is_empty() => format!("{}:{}", existing, preload),
+                _ => preload.to_string(),
+            };
+            vars.push((preload_env, preload));
+            // Read by libstdbuf: "L" line-buffers the stream.
+            vars.push(("_STDBUF_O", "L".to_owned()));
+        }
+        vars
+    }
+
+    /// Sets the environment variables for the process run by the expression.
+    pub(crate) fn apply(&self, expression: Expression) -> Expression {
+        self.vars()
+            .into_iter()
+            .fold(expression, |expression, (key, value)| {
+                expression.env(key, value)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_buffering_vars() {
+        let line_buffering: LineBuffering = toml::from_str("").unwrap();
+        assert!(line_buffering.env(), "env is on by default");
+        assert_eq!(
+            line_buffering.vars(),
+            [("PYTHONUNBUFFERED", "1".to_owned())]
+        );
+
+        let line_buffering: LineBuffering =
+            toml::from_str("env = false\npreload = \"/lib/libstdbuf.so\"").unwrap();
+        let vars = line_buffering.vars();
+        if cfg!(unix) {
+            assert!(vars.contains(&("_STDBUF_O", "L".to_owned())), "{:?}", vars);
+            assert!(
+                vars.iter()
+                    .any(|(_, value)| value.ends_with("/lib/libstdbuf.so")),
+                "{:?}",
+                vars
+            );
+        } else {
+            assert!(vars.is_empty(), "preload is only supported on Unix");
+        }
+
+        toml::from_str::<LineBuffering>("stderr = true").expect_err("unknown fields are rejected");
+    }
+}
@@ -0,0 +1,525 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Collecting LLVM source-based code coverage for each test.
+//!
+//! With `cargo nextest run --coverage`, tests are built with `-C instrument-coverage`, and each
+//! test writes its raw coverage profiles to a directory of its own in the run directory, through
+//! `LLVM_PROFILE_FILE`. Once a test finishes, its raw profiles are merged into an indexed profile at
+//! `coverage/<binary-id>/<test-name>.<attempt>.profdata`, whose path is recorded in the run report.
+//! This attributes coverage to individual tests.
+//!
+//! Once the run finishes, the profiles of all tests are merged into `coverage.profdata` in the run
+//! directory, and optionally exported in the LCOV format to `coverage.lcov`:
+//!
+//! ```toml
+//! [profile.default.coverage]
+//! lcov = true
+//! map = true
+//! llvm-tools-dir = "/usr/lib/llvm-18/bin"
+//! ```
+//!
+//! * `lcov` exports the merged coverage to `coverage.lcov`.
+//! * `map` exports the files, lines and functions each test covered to `coverage-map.json`, as a
+//!   [`CoverageMap`]. Test-impact analysis tools can use it to find the tests affected by a diff,
+//!   and it can be passed to `--select-by-coverage` in later runs. Only files within the workspace
+//!   are included.
+//! * `llvm-tools-dir` is the directory `llvm-profdata` and `llvm-cov` are in, relative to the
+//!   workspace root. It defaults to the directory the `llvm-tools` rustup component installs them
+//!   to. Tools that aren't found there are looked up in `PATH`.
+//!
+//! The instrumentation flag is added to `RUSTFLAGS`, which replaces any `rustflags` set in Cargo
+//! config files. Like with valgrind, coverage is only collected for tests that are run locally.
+
+use crate::errors::LlvmCoverageError;
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::cmd;
+use nextest_metadata::{CoverageMap, TestCoverage};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+};
+
+/// The flag that makes rustc instrument binaries for source-based coverage.
+pub const INSTRUMENT_COVERAGE_FLAG: &str = "-Cinstrument-coverage";
+
+/// The environment variable that sets where instrumented binaries write their raw profiles.
+pub const LLVM_PROFILE_FILE_ENV: &str = "LLVM_PROFILE_FILE";
+
+/// How coverage is collected for a profile.
+///
+/// Obtained through [`NextestProfile::coverage`](crate::config::NextestProfile::coverage).
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CoverageSettings {
+    #[serde(default)]
+    lcov: bool,
+    #[serde(default)]
+    map: bool,
+    #[serde(default)]
+    llvm_tools_dir: Option<Utf8PathBuf>,
+}
+
+impl CoverageSettings {
+    /// Returns true if the merged coverage is exported in the LCOV format.
+    pub fn lcov(&self) -> bool {
+        self.lcov
+    }
+
+    /// Returns true if the coverage of each test is exported as a [`CoverageMap`].
+    pub fn map(&self) -> bool {
+        self.map
+    }
+
+    /// Returns the directory LLVM tools are looked up in, relative to the workspace root, if
+    /// specified.
+    pub fn llvm_tools_dir(&self) -> Option<&Utf8Path> {
+        self.llvm_tools_dir.as_deref()
+    }
+}
+
+/// Returns the environment variable to set for Cargo so that tests are built with coverage
+/// instrumentation, along with its value. Flags already set in the environment are kept.
+pub fn rustflags_env() -> (&'static str, String) {
+    instrumented_rustflags(
+        env::var("CARGO_ENCODED_RUSTFLAGS").ok().as_deref(),
+        env::var("RUSTFLAGS").ok().as_deref(),
+    )
+}
+
+fn instrumented_rustflags(
+    encoded_rustflags: Option<&str>,
+    rustflags: Option<&str>,
+) -> (&'static str, String) {
+    // Cargo prefers CARGO_ENCODED_RUSTFLAGS, whose flags are separated by 0x1f, over RUSTFLAGS.
+    match (encoded_rustflags, rustflags) {
+        (Some(flags), _) if !flags.is_empty() => (
+            "CARGO_ENCODED_RUSTFLAGS",
+            format!("{}\x1f{}", flags, INSTRUMENT_COVERAGE_FLAG),
+        ),
+        (_, Some(flags)) if !flags.trim().is_empty() => (
+            "RUSTFLAGS",
+            format!("{} {}", flags.trim(), INSTRUMENT_COVERAGE_FLAG),
+        ),
+        _ => ("RUSTFLAGS", INSTRUMENT_COVERAGE_FLAG.to_owned()),
+    }
+}
+
+/// The LLVM tools used to merge and export coverage profiles.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LlvmTools {
+    llvm_profdata: Utf8PathBuf,
+    llvm_cov: Utf8PathBuf,
+}
+
+impl LlvmTools {
+    /// Looks up the tools in the directory specified by `settings`, or in the directory of the
+    /// `llvm-tools` rustup component, falling back to `PATH`.
+    pub fn find(settings: &CoverageSettings, workspace_root: &Utf8Path) -> Self {
+        let dir = match &settings.llvm_tools_dir {
+            Some(dir) => Some(workspace_root.join(dir)),
+            None => rustup_tools_dir(),
+        };
+        let tool = |name: &str| {
+            let file_name = format!("{}{}", name, env::consts::EXE_SUFFIX);
+            dir.as_ref()
+                .map(|dir| dir.join(&file_name))
+                .filter(|path| path.is_file())
+                .unwrap_or_else(|| Utf8PathBuf::from(file_name))
+        };
+        Self {
+            llvm_profdata: tool("llvm-profdata"),
+            llvm_cov: tool("llvm-cov"),
+        }
+    }
+
+    /// Returns the path to `llvm-profdata`.
+    pub fn llvm_profdata(&self) -> &Utf8Path {
+        &self.llvm_profdata
+    }
+
+    /// Returns the path to `llvm-cov`.
+    pub fn llvm_cov(&self) -> &Utf8Path {
+        &self.llvm_cov
+    }
+
+    /// Merges the raw profiles a test wrote to `raw_dir` into an indexed profile at
+    /// `profdata_path`, and removes `raw_dir`. Returns false if the test didn't write any profiles,
+    /// or they couldn't be merged.
+    pub(crate) fn merge_test_profiles(&self, raw_dir: &Utf8Path, profdata_path: &Utf8Path) -> bool {
+        let raw_profiles = files_with_extension(raw_dir, "profraw");
+        let merged = !raw_profiles.is_empty()
+            && cmd(
+                self.llvm_profdata.as_str(),
+                ["merge", "-sparse", "-o", profdata_path.as_str()]
+                    .into_iter()
+                    .chain(raw_profiles.iter().map(|path| path.as_str())),
+            )
+            .stdout_null()
+            .stderr_null()
+            .unchecked()
+            .run()
+            .is_ok_and(|output| output.status.success());
+        let _ = fs::remove_dir_all(raw_dir);
+        merged
+    }
+
+    /// Merges the profiles of every test in the run into `coverage.profdata` in the run directory,
+    /// and exports them to `coverage.lcov` if `lcov` is true. `binaries` are the instrumented
+    /// binaries the profiles were written by.
+    ///
+    /// Returns `None` if no test wrote a profile.
+    pub fn merge_run<'a>(
+        &self,
+        run_dir: &Utf8Path,
+        binaries: impl IntoIterator<Item = &'a Utf8Path>,
+        lcov: bool,
+    ) -> Result<Option<RunCoverage>, LlvmCoverageError> {
+        let mut test_profiles = vec![];
+        let coverage_dir = run_dir.join("coverage");
+        if let Ok(entries) = fs::read_dir(&coverage_dir) {
+            for entry in entries.flatten() {
+                if let Ok(dir) = Utf8PathBuf::try_from(entry.path()) {
+                    test_profiles.extend(files_with_extension(&dir, "profdata"));
+                }
+            }
+        }
+        if test_profiles.is_empty() {
+            return Ok(None);
+        }
+        test_profiles.sort();
+
+        // The list of inputs can be too long for a command line, so it's passed in through a file.
+        let inputs_path = coverage_dir.join("inputs.txt");
+        let inputs: String = test_profiles
+            .iter()
+            .map(|path| format!("{}\n", path))
+            .collect();
+        fs::write(&inputs_path, inputs).map_err(|error| LlvmCoverageError::Write {
+            path: inputs_path.clone(),
+            error,
+        })?;
+        let profdata = run_dir.join("coverage.profdata");
+        run_tool(
+            &self.llvm_profdata,
+            &[
+                "merge",
+                "-sparse",
+                "-f",
+                inputs_path.as_str(),
+                "-o",
+                profdata.as_str(),
+            ],
+        )?;
+
+        let lcov = if lcov {
+            let instr_profile = format!("-instr-profile={}", profdata);
+            let mut args = vec!["export", "-format=lcov", instr_profile.as_str()];
+            for (i, binary) in binaries.into_iter().enumerate() {
+                if i > 0 {
+                    args.push("-object");
+                }
+                args.push(binary.as_str());
+            }
+            let output = run_tool(&self.llvm_cov, &args)?;
+            let lcov_path = run_dir.join("coverage.lcov");
+            fs::write(&lcov_path, output).map_err(|error| LlvmCoverageError::Write {
+                path: lcov_path.clone(),
+                error,
+            })?;
+            Some(lcov_path)
+        } else {
+            None
+        };
+
+        Ok(Some(RunCoverage {
+            test_profile_count: test_profiles.len(),
+            profdata,
+            lcov,
+        }))
+    }
+
+    /// Exports the files, lines and functions each test covered to `coverage-map.json` in the run
+    /// directory, returning its path.
+    ///
+    /// Paths in the map are relative to `workspace_root`, and the map records the commit checked
+    /// out there. Tests that were run several times, such as multiplexed tests, are merged.
+    pub fn export_map<'a>(
+        &self,
+        run_dir: &Utf8Path,
+        workspace_root: &Utf8Path,
+        tests: impl IntoIterator<Item = TestProfile<'a>>,
+    ) -> Result<Utf8PathBuf, LlvmCoverageError> {
+        let commit = run_tool(
+            Utf8Path::new("git"),
+            &["-C", workspace_root.as_str(), "rev-parse", "HEAD"],
+        )?;
+
+        let mut coverage: BTreeMap<(&str, &str), LcovCoverage> = BTreeMap::new();
+        for test in tests {
+            let instr_profile = format!("-instr-profile={}", test.profdata);
+            let lcov = run_tool(
+                &self.llvm_cov,
+                &[
+                    "export",
+                    "-format=lcov",
+                    instr_profile.as_str(),
+                    test.binary.as_str(),
+                ],
+            )?;
+            coverage
+                .entry((test.binary_id, test.name))
+                .or_default()
+                .add(&String::from_utf8_lossy(&lcov), workspace_root);
+        }
+
+        let map = CoverageMap {
+            commit: String::from_utf8_lossy(&commit).trim().to_owned(),
+            tests: coverage
+                .into_iter()
+                .map(|((binary_id, name), coverage)| coverage.into_test(binary_id, name))
+                .collect(),
+        };
+        let map_path = run_dir.join("coverage-map.json");
+        let json = serde_json::to_vec_pretty(&map).expect("coverage map is serializable");
+        fs::write(&map_path, json).map_err(|error| LlvmCoverageError::Write {
+            path: map_path.clone(),
+            error,
+        })?;
+        Ok(map_path)
+    }
+}
+
+/// The lines and functions covered according to one or more LCOV exports.
+#[derive(Debug, Default)]
+struct LcovCoverage {
+    // Path relative to the workspace root -> line -> whether it was covered.
+    lines: BTreeMap<Utf8PathBuf, BTreeMap<u32, bool>>,
+    functions: BTreeSet<String>,
+}
+
+impl LcovCoverage {
+    /// Adds the coverage in an LCOV export, skipping files outside of `workspace_root`.
+    fn add(&mut self, lcov: &str, workspace_root: &Utf8Path) {
+        let mut file = None;
+        for line in lcov.lines() {
+            if let Some(path) = line.strip_prefix("SF:") {
+                file = Utf8Path::new(path)
+                    .strip_prefix(workspace_root)
+                    .ok()
+                    .map(|path| self.lines.entry(path.to_owned()).or_default());
+            } else if line == "end_of_record" {
+                file = None;
+            } else if let (Some(file), Some(data)) = (&mut file, line.strip_prefix("DA:")) {
+                // DA:<line>,<count>[,<checksum>]
+                let mut fields = data.split(',');
+                if let (Some(Ok(line)), Some(Ok(count))) = (
+                    fields.next().map(str::parse::<u32>),
+                    fields.next().map(str::parse::<u64>),
+                ) {
+                    *file.entry(line).or_default() |= count > 0;
+                }
+            } else if let (Some(_), Some(data)) = (&file, line.strip_prefix("FNDA:")) {
+                // FNDA:<count>,<name>
+                if let Some((count, name)) = data.split_once(',') {
+                    if count.parse::<u64>().is_ok_and(|count| count > 0) {
+                        self.functions.insert(name.to_owned());
+                    }
+                }
+            }
+        }
+    }
+
+    fn into_test(self, binary_id: &str, name: &str) -> TestCoverage {
+        let files = self
+            .lines
+            .into_iter()
+            .filter_map(|(path, lines)| {
+                // Covered lines are joined up into ranges, across lines without any code.
+                let mut ranges: Vec<[u32; 2]> = vec![];
+                let mut in_range = false;
+                for (line, covered) in lines {
+                    match (covered, in_range, ranges.last_mut()) {
+                        (true, true, Some(range)) => range[1] = line,
+                        (true, _, _) => ranges.push([line, line]),
+                        (false, _, _) => {}
+                    }
+                    in_range = covered;
+                }
+                (!ranges.is_empty()).then_some((path, ranges))
+            })
+            .collect();
+        TestCoverage {
+            binary_id: binary_id.to_owned(),
+            name: name.to_owned(),
+            files,
+            functions: self.functions.into_iter().collect(),
+        }
+    }
+}
+
+/// The coverage profile of a test, exported into a [`CoverageMap`] by [`LlvmTools::export_map`].
+#[derive(Clone, Copy, Debug)]
+pub struct TestProfile<'a> {
+    /// The binary ID of the test.
+    pub binary_id: &'a str,
+
+    /// The name of the test.
+    pub name: &'a str,
+
+    /// The instrumented binary the test was run from.
+    pub binary: &'a Utf8Path,
+
+    /// The indexed profile the test's coverage was merged into.
+    pub profdata: &'a Utf8Path,
+}
+
+/// The coverage collected for a run, returned by [`LlvmTools::merge_run`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RunCoverage {
+    /// The number of per-test profiles that were merged.
+    pub test_profile_count: usize,
+
+    /// The path to the merged profile.
+    pub profdata: Utf8PathBuf,
+
+    /// The path to the LCOV export, if one was written.
+    pub lcov: Option<Utf8PathBuf>,
+}
+
+/// Returns the path raw profiles are written to for a test whose profiles go in `raw_dir`. `%p` and
+/// `%m` make sure that processes spawned by the test, and other binaries, don't overwrite them.
+pub(crate) fn profile_file_pattern(raw_dir: &Utf8Path) -> Utf8PathBuf {
+    raw_dir.join("%p-%m.profraw")
+}
+
+// The rustup llvm-tools component installs tools next to the host's Rust libraries.
+fn rustup_tools_dir() -> Option<Utf8PathBuf> {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let libdir = cmd!(rustc, "--print", "target-libdir")
+        .stdout_capture()
+        .stderr_null()
+        .read()
+        .ok()?;
+    Some(Utf8Path::new(libdir.trim()).parent()?.join("bin"))
+}
+
+fn files_with_extension(dir: &Utf8Path, extension: &str) -> Vec<Utf8PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| Utf8PathBuf::try_from(entry.path()).ok())
+        .filter(|path| path.extension() == Some(extension))
+        .collect()
+}
+
+fn run_tool(program: &Utf8Path, args: &[&str]) -> Result<Vec<u8>, LlvmCoverageError> {
+    let command = format!("{} {}", program, args.join(" "));
+    let output = cmd(program.as_str(), args)
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .map_err(|error| LlvmCoverageError::Exec {
+            command: command.clone(),
+            error,
+        })?;
+    if !output.status.success() {
+        return Err(LlvmCoverageError::Failed {
+            command,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_settings() {
+        assert_eq!(
+            instrumented_rustflags(None, None),
+            ("RUSTFLAGS", "-Cinstrument-coverage".to_owned())
+        );
+        assert_eq!(
+            instrumented_rustflags(None, Some(" -Dwarnings ")),
+            ("RUSTFLAGS", "-Dwarnings -Cinstrument-coverage".to_owned())
+        );
+        assert_eq!(
+            instrumented_rustflags(Some("-Dwarnings\x1f--cfg=foo"), Some("-Copt-level=1")),
+            (
+                "CARGO_ENCODED_RUSTFLAGS",
+                "-Dwarnings\x1f--cfg=foo\x1f-Cinstrument-coverage".to_owned()
+            ),
+            "encoded flags take precedence"
+        );
+        assert_eq!(
+            instrumented_rustflags(Some(""), Some("-Dwarnings")).0,
+            "RUSTFLAGS"
+        );
+
+        let settings: CoverageSettings =
+            toml::from_str("lcov = true\nllvm-tools-dir = \"tools\"").unwrap();
+        assert!(settings.lcov());
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let profdata = format!("llvm-profdata{}", env::consts::EXE_SUFFIX);
+        fs::create_dir(root.join("tools")).unwrap();
+        fs::write(root.join("tools").join(&profdata), "").unwrap();
+        let tools = LlvmTools::find(&settings, &root);
+        assert_eq!(tools.llvm_profdata(), root.join("tools").join(&profdata));
+        assert_eq!(
+            tools.llvm_cov(),
+            format!("llvm-cov{}", env::consts::EXE_SUFFIX),
+            "missing tools are looked up in PATH"
+        );
+
+        assert_eq!(
+            profile_file_pattern(Utf8Path::new("run/coverage/my-crate/test.1.profraw")),
+            "run/coverage/my-crate/test.1.profraw/%p-%m.profraw"
+        );
+        toml::from_str::<CoverageSettings>("html = true").expect_err("unknown fields are rejected");
+    }
+
+    #[test]
+    fn lcov_coverage_map() {
+        let lcov = "\
+SF:/ws/src/lib.rs
+FN:3,_RNvCs1_5crate3foo
+FN:20,_RNvCs1_5crate3bar
+FNDA:1,_RNvCs1_5crate3foo
+FNDA:0,_RNvCs1_5crate3bar
+DA:3,1
+DA:4,1
+DA:6,2
+DA:20,0
+DA:21,0
+DA:30,5
+end_of_record
+SF:/home/user/.cargo/registry/src/dep/lib.rs
+FNDA:4,_RNvCs2_3dep3baz
+DA:1,4
+end_of_record
+";
+        let mut coverage = LcovCoverage::default();
+        coverage.add(lcov, Utf8Path::new("/ws"));
+        // Another run of the same test, with a different variant.
+        coverage.add(
+            "SF:/ws/src/lib.rs\nDA:20,0\nDA:21,3\nend_of_record\nSF:/ws/src/other.rs\nDA:1,0\nend_of_record\n",
+            Utf8Path::new("/ws"),
+        );
+        let test = coverage.into_test("my-crate", "tests::foo");
+        assert_eq!(test.binary_id, "my-crate");
+        assert_eq!(
+            test.files.into_iter().collect::<Vec<_>>(),
+            [(Utf8PathBuf::from("src/lib.rs"), vec![[3, 6], [21, 30]])],
+            "uncovered files and files outside the workspace are left out"
+        );
+        assert_eq!(test.functions, ["_RNvCs1_5crate3foo"]);
+    }
+}
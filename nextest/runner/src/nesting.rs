@@ -0,0 +1,146 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Support for nested nextest invocations.
+//!
+//! Some test suites invoke `cargo nextest` from within their own tests. Nextest sets
+//! [`NEXTEST_LEVEL_ENV`] in the environment of every test it runs, so that nested invocations can
+//! detect that they're nested and behave accordingly.
+//!
+//! Nested invocations can also share a [`Jobserver`] with the outermost invocation, so that the
+//! total number of tests running at any time is bounded. The same mechanism lets nextest
+//! participate in a jobserver set up by GNU make or Cargo, if asked to.
+
+use std::{
+    env, io,
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// The environment variable nextest sets to the nesting level of the tests it runs.
+///
+/// Tests run by a top-level nextest invocation see `1`, tests run by a nextest invocation within
+/// those tests see `2`, and so on.
+pub const NEXTEST_LEVEL_ENV: &str = "NEXTEST_LEVEL";
+
+/// Returns the nesting level of the current process.
+///
+/// This is 0 if the current process is not being run by nextest.
+pub fn nesting_level() -> usize {
+    env::var(NEXTEST_LEVEL_ENV)
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(0)
+}
+
+/// A jobserver that limits the number of tests running at the same time across nested nextest
+/// invocations.
+///
+/// This uses the same protocol as GNU make and Cargo. Every process connected to the jobserver
+/// owns an implicit token, and acquires further tokens from the jobserver to run more tests
+/// concurrently.
+#[derive(Clone, Debug)]
+pub struct Jobserver {
+    client: jobserver::Client,
+    implicit_token_used: Arc<AtomicBool>,
+    nested: bool,
+}
+
+impl Jobserver {
+    /// Creates a new jobserver that allows `limit` tests to run concurrently.
+    pub fn new(limit: usize) -> io::Result<Self> {
+        // One of the tokens is implicitly owned by this process.
+        let client = jobserver::Client::new(limit.saturating_sub(1))?;
+        Ok(Self::from_client(client, false))
+    }
+
+    /// Connects to a jobserver inherited from the environment, if one is available.
+    ///
+    /// The jobserver may have been set up by an outer nextest invocation, or by GNU make or Cargo.
+    /// Use [`is_nested`](Self::is_nested) to tell the two apart: a jobserver set up by make or
+    /// Cargo is sized for compile jobs rather than tests, so it should only be participated in if
+    /// explicitly requested.
+    ///
+    /// This should be called early in `main`, before any other file descriptors are opened.
+    pub fn from_env() -> Option<Self> {
+        // SAFETY: the file descriptors named in the environment are only valid if this is called
+        // before any other file descriptors are opened, as documented above.
+        let client = unsafe { jobserver::Client::from_env()? };
+        Some(Self::from_client(client, nesting_level() > 0))
+    }
+
+    /// Returns true if this jobserver was inherited from within a test run by nextest.
+    ///
+    /// Such a jobserver was most likely set up by an outer nextest invocation.
+    pub fn is_nested(&self) -> bool {
+        self.nested
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    fn from_client(client: jobserver::Client, nested: bool) -> Self {
+        Self {
+            client,
+            implicit_token_used: Arc::new(AtomicBool::new(false)),
+            nested,
+        }
+    }
+
+    /// Acquires a token, blocking until one is available.
+    pub(crate) fn acquire(&self) -> io::Result<JobserverToken> {
+        if self
+            .implicit_token_used
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            return Ok(JobserverToken::Implicit(self.implicit_token_used.clone()));
+        }
+        Ok(JobserverToken::Acquired(self.client.acquire()?))
+    }
+
+    /// Configures a command so that the process it spawns inherits this jobserver.
+    pub(crate) fn configure(&self, cmd: &mut Command) {
+        self.client.configure(cmd);
+    }
+}
+
+/// A token acquired from a [`Jobserver`], released on drop.
+#[derive(Debug)]
+pub(crate) enum JobserverToken {
+    Implicit(Arc<AtomicBool>),
+    // This is only held to be released on drop.
+    #[allow(dead_code)]
+    Acquired(jobserver::Acquired),
+}
+
+impl Drop for JobserverToken {
+    fn drop(&mut self) {
+        if let JobserverToken::Implicit(used) = self {
+            used.store(false, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn implicit_token() {
+        let jobserver = Jobserver::new(2).expect("jobserver created");
+        assert!(!jobserver.is_nested(), "new jobservers aren't nested");
+        let first = jobserver.acquire().expect("implicit token acquired");
+        assert!(matches!(first, JobserverToken::Implicit(_)));
+        let second = jobserver.acquire().expect("second token acquired");
+        assert!(matches!(second, JobserverToken::Acquired(_)));
+
+        drop(first);
+        let third = jobserver.acquire().expect("implicit token reacquired");
+        assert!(matches!(third, JobserverToken::Implicit(_)));
+    }
+}
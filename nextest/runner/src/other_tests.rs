@@ -0,0 +1,203 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Test suites that aren't written in Rust, registered through the nextest config.
+//!
+//! Repositories often have tests written in other languages, such as Python or shell scripts,
+//! alongside their Rust tests. These can be run and reported as part of the same nextest run by
+//! adding them to `.config/nextest.toml`:
+//!
+//! ```toml
+//! [[other-tests]]
+//! name = "python"
+//! package = "my-package"
+//! command = "python3"
+//! args = ["-m", "pytest", "-q"]
+//! cwd = "python"
+//! list-command = ["python3", "-m", "pytest", "--collect-only", "-q"]
+//! ```
+//!
+//! Each entry becomes a suite of [synthetic tests](crate::test_list::SyntheticTest) with the binary
+//! ID `<package>::<name>`. A test passes if its command exits with code 0.
+//!
+//! * Without `list-command`, the suite consists of a single test with the same name as the
+//!   suite, which runs `command` with `args`.
+//! * With `list-command`, the command is run while building the test list, and each non-empty line
+//!   it prints out is the name of a test. Each test runs `command` with `args`, followed by the
+//!   name of the test.
+//!
+//! `cwd` is relative to the workspace root, and defaults to the directory of the package. Both
+//! commands are run in it.
+
+use crate::{
+    errors::OtherTestsError,
+    test_filter::TestFilterBuilder,
+    test_list::{SyntheticTest, SyntheticTestBuilder, TestList},
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use guppy::graph::PackageGraph;
+use serde::Deserialize;
+
+/// A suite of tests that aren't written in Rust, run through an arbitrary command.
+///
+/// Obtained through [`NextestConfig::other_tests`](crate::config::NextestConfig::other_tests).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct OtherTests {
+    name: String,
+    package: String,
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    cwd: Option<Utf8PathBuf>,
+    #[serde(default)]
+    list_command: Option<Vec<String>>,
+}
+
+impl OtherTests {
+    /// Returns the name of this suite.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name of the workspace package this suite belongs to.
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    /// Returns the program run for each test.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Returns the arguments passed to the program.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns the working directory for this suite, relative to the workspace root, if specified.
+    pub fn cwd(&self) -> Option<&Utf8Path> {
+        self.cwd.as_deref()
+    }
+
+    /// Returns the command used to list the tests in this suite, if specified.
+    pub fn list_command(&self) -> Option<&[String]> {
+        self.list_command.as_deref()
+    }
+
+    /// Lists the tests in this suite and adds them to the test list, applying the filter to them.
+    pub fn add_to_test_list<'g>(
+        &self,
+        graph: &'g PackageGraph,
+        test_list: &mut TestList<'g>,
+        filter: &TestFilterBuilder,
+    ) -> Result<(), OtherTestsError> {
+        let package = graph
+            .workspace()
+            .member_by_name(&self.package)
+            .map_err(|_| OtherTestsError::UnknownPackage {
+                suite: self.name.clone(),
+                package: self.package.clone(),
+            })?;
+        let cwd = match &self.cwd {
+            Some(cwd) => graph.workspace().root().join(cwd),
+            None => package
+                .manifest_path()
+                .parent()
+                .expect("manifest path has a parent")
+                .to_path_buf(),
+        };
+
+        let tests = self.make_tests(&cwd)?;
+        test_list
+            .add_synthetic_tests(package, &self.name, tests, filter)
+            .map_err(OtherTestsError::Synthetic)
+    }
+
+    fn make_tests(&self, cwd: &Utf8Path) -> Result<Vec<SyntheticTest>, OtherTestsError> {
+        let list_command = match &self.list_command {
+            Some(list_command) => list_command,
+            None => {
+                let test = SyntheticTestBuilder::new(&self.name, &self.command)
+                    .add_args(&self.args)
+                    .set_cwd(cwd)
+                    .build();
+                return Ok(vec![test]);
+            }
+        };
+
+        let (program, args) =
+            list_command
+                .split_first()
+                .ok_or_else(|| OtherTestsError::EmptyListCommand {
+                    suite: self.name.clone(),
+                })?;
+        let output = duct::cmd(program, args).dir(cwd).read().map_err(|error| {
+            OtherTestsError::ListCommand {
+                suite: self.name.clone(),
+                command: list_command.join(" "),
+                error,
+            }
+        })?;
+
+        let tests = output
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|name| {
+                SyntheticTestBuilder::new(name, &self.command)
+                    .add_args(&self.args)
+                    .add_arg(name)
+                    .set_cwd(cwd)
+                    .build()
+            })
+            .collect();
+        Ok(tests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn make_tests() {
+        let other_tests = OtherTests {
+            name: "shell".to_owned(),
+            package: "fake-package".to_owned(),
+            command: "sh".to_owned(),
+            args: vec!["run-test.sh".to_owned()],
+            cwd: None,
+            list_command: None,
+        };
+        let tests = other_tests
+            .make_tests("/fake/cwd".as_ref())
+            .expect("no list command to run");
+        assert_eq!(tests.len(), 1);
+        assert_eq!(tests[0].name(), "shell");
+        assert_eq!(tests[0].args(), ["run-test.sh"]);
+        assert_eq!(tests[0].cwd(), Some("/fake/cwd".as_ref()));
+
+        let cwd = Utf8PathBuf::try_from(std::env::current_dir().expect("current dir exists"))
+            .expect("current dir is UTF-8");
+        let other_tests = OtherTests {
+            list_command: Some(vec!["echo".to_owned(), "first\n\n  second  ".to_owned()]),
+            ..other_tests
+        };
+        let tests = other_tests.make_tests(&cwd).expect("list command succeeds");
+        let names: Vec<_> = tests.iter().map(|test| test.name()).collect();
+        assert_eq!(names, ["first", "second"]);
+        assert_eq!(tests[1].args(), ["run-test.sh", "second"]);
+
+        let other_tests = OtherTests {
+            list_command: Some(vec![]),
+            ..other_tests
+        };
+        assert!(matches!(
+            other_tests.make_tests(&cwd),
+            Err(OtherTestsError::EmptyListCommand { .. })
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! User-provided postprocessors for test output.
+//!
+//! An output filter is a command that the captured standard output and standard error of each
+//! failed test are piped through before being displayed or reported. This can be used to strip
+//! timestamps, remove noisy log lines, or otherwise make failures easier to read.
+//!
+//! Output filters are specified per-profile:
+//!
+//! ```toml
+//! [profile.default.output-filter]
+//! command = ["sed", "-e", "s/^[0-9:.T-]* //"]
+//! timeout = "10s"
+//! ```
+//!
+//! The filter is run once per stream, with the output passed in through standard input. The
+//! filtered output is read from the command's standard output. The following environment
+//! variables are set for the command:
+//!
+//! * `NEXTEST_TEST_NAME`: the name of the test.
+//! * `NEXTEST_BINARY_ID`: the binary ID of the test.
+//! * `NEXTEST_OUTPUT_STREAM`: either `stdout` or `stderr`.
+//!
+//! If the filter can't be started, exits with a non-zero status or doesn't finish within its
+//! timeout, the unfiltered output is used instead and a note is added to standard error.
+
+use crate::{errors::OutputFilterError, stopwatch::ClockInstant, test_list::TestInstance};
+use serde::{Deserialize, Deserializer};
+use std::{fmt, time::Duration};
+
+/// A command that test output is piped through before being displayed.
+///
+/// Obtained through [`NextestProfile::output_filter`](crate::config::NextestProfile::output_filter).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutputFilter {
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl OutputFilter {
+    /// The timeout used if one isn't specified.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Returns the program that is run.
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// Returns the arguments passed to the program.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns the amount of time the filter is allowed to run for.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Filters the given output of a test, falling back to the unfiltered output on failure.
+    pub(crate) fn filter_or_fallback(
+        &self,
+        test_instance: &TestInstance<'_>,
+        stdout: Vec<u8>,
+        mut stderr: Vec<u8>,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let mut errors = vec![];
+        let mut filter_stream =
+            |stream: OutputStream, input: Vec<u8>| match self.filter(test_instance, stream, &input)
+            {
+                Ok(output) => output,
+                Err(err) => {
+                    errors.push((stream, err));
+                    input
+                }
+            };
+        let stdout = filter_stream(OutputStream::Stdout, stdout);
+        stderr = filter_stream(OutputStream::Stderr, stderr);
+
+        for (stream, err) in errors {
+            if !stderr.is_empty() && !stderr.ends_with(b"\n") {
+                stderr.push(b'\n');
+            }
+            stderr.extend_from_slice(
+                format!(
+                    "(nextest: output filter failed for {}, showing unfiltered output: {})\n",
+                    stream, err
+                )
+                .as_bytes(),
+            );
+        }
+
+        (stdout, stderr)
+    }
+
+    /// Runs the filter over a single stream of output.
+    pub fn filter(
+        &self,
+        test_instance: &TestInstance<'_>,
+        stream: OutputStream,
+        input: &[u8],
+    ) -> Result<Vec<u8>, OutputFilterError> {
+        let handle = duct::cmd(&self.program, &self.args)
+            .env("NEXTEST_TEST_NAME", test_instance.name)
+            .env(
+                "NEXTEST_BINARY_ID",
+                test_instance.bin_info.binary_id.as_str(),
+            )
+            .env("NEXTEST_OUTPUT_STREAM", stream.as_str())
+            .stdin_bytes(input)
+            .stdout_capture()
+            .stderr_null()
+            .unchecked()
+            .start()
+            .map_err(OutputFilterError::Spawn)?;
+
+        let deadline = ClockInstant::now() + self.timeout;
+        loop {
+            if handle
+                .try_wait()
+                .map_err(OutputFilterError::Wait)?
+                .is_some()
+            {
+                break;
+            }
+            if ClockInstant::now() >= deadline {
+                let _ = handle.kill();
+                return Err(OutputFilterError::Timeout(self.timeout));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let output = handle.into_output().map_err(OutputFilterError::Wait)?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(OutputFilterError::Failed(output.status))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputFilter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct OutputFilterImpl {
+            command: Vec<String>,
+            #[serde(with = "humantime_serde")]
+            #[serde(default)]
+            timeout: Option<Duration>,
+        }
+
+        let OutputFilterImpl { command, timeout } = OutputFilterImpl::deserialize(deserializer)?;
+        let mut command = command.into_iter();
+        let program = command
+            .next()
+            .ok_or_else(|| serde::de::Error::custom("output filter command must not be empty"))?;
+        Ok(Self {
+            program,
+            args: command.collect(),
+            timeout: timeout.unwrap_or(Self::DEFAULT_TIMEOUT),
+        })
+    }
+}
+
+/// A stream of output produced by a test.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OutputStream {
+    /// Standard output.
+    Stdout,
+
+    /// Standard error.
+    Stderr,
+}
+
+impl OutputStream {
+    /// Returns the name of this stream.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+impl fmt::Display for OutputStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
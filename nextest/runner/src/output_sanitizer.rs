@@ -0,0 +1,227 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Sanitization of captured test output, to keep CI logs readable and small.
+//!
+//! Tests can print color codes meant for a terminal, megabytes of logs, or binary data. An output
+//! sanitizer cleans up the captured standard output and standard error of every test before it's
+//! displayed, stored or written to reports:
+//!
+//! ```toml
+//! [profile.ci.output-sanitizer]
+//! strip-ansi = true
+//! max-bytes = "1MiB"
+//! keep = "head-tail"
+//! replace-invalid-utf8 = true
+//! ```
+//!
+//! * `strip-ansi` removes ANSI escape sequences.
+//! * `max-bytes` truncates each stream to about this size, either a number of bytes or a string
+//!   such as `"500KiB"`. A line noting how much was left out is put where output was removed.
+//! * `keep` is the part of truncated output that is kept: `"head"`, `"tail"`, or `"head-tail"`
+//!   (the default) for half of each.
+//! * `replace-invalid-utf8` replaces bytes that aren't valid UTF-8 with U+FFFD.
+//!
+//! All options are off by default. Output is stripped of ANSI escapes first, then truncated, then
+//! has invalid UTF-8 replaced. Truncation doesn't split UTF-8 characters.
+
+use crate::disk_usage::{format_bytes, DiskQuota};
+use serde::{Deserialize, Deserializer};
+
+/// Cleans up the captured output of tests.
+///
+/// Obtained through
+/// [`NextestProfile::output_sanitizer`](crate::config::NextestProfile::output_sanitizer).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OutputSanitizer {
+    strip_ansi: bool,
+    max_bytes: Option<usize>,
+    keep: TruncateKeep,
+    replace_invalid_utf8: bool,
+}
+
+impl OutputSanitizer {
+    /// Returns true if ANSI escapes are stripped.
+    pub fn strip_ansi(&self) -> bool {
+        self.strip_ansi
+    }
+
+    /// Returns the size output is truncated to, if it is.
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+
+    /// Returns the part of truncated output that is kept.
+    pub fn keep(&self) -> TruncateKeep {
+        self.keep
+    }
+
+    /// Returns true if invalid UTF-8 is replaced.
+    pub fn replace_invalid_utf8(&self) -> bool {
+        self.replace_invalid_utf8
+    }
+
+    /// Sanitizes a single stream of output.
+    pub fn sanitize(&self, mut output: Vec<u8>) -> Vec<u8> {
+        if self.strip_ansi {
+            output = strip_ansi_escapes::strip(&output).unwrap_or(output);
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            output = truncate(output, max_bytes, self.keep);
+        }
+        if self.replace_invalid_utf8 {
+            output = match String::from_utf8(output) {
+                Ok(output) => output.into_bytes(),
+                Err(err) => String::from_utf8_lossy(err.as_bytes())
+                    .into_owned()
+                    .into_bytes(),
+            };
+        }
+        output
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputSanitizer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case", deny_unknown_fields)]
+        struct OutputSanitizerImpl {
+            #[serde(default)]
+            strip_ansi: bool,
+            // Sizes are specified the same way as disk quotas.
+            #[serde(default)]
+            max_bytes: Option<DiskQuota>,
+            #[serde(default)]
+            keep: TruncateKeep,
+            #[serde(default)]
+            replace_invalid_utf8: bool,
+        }
+
+        let sanitizer = OutputSanitizerImpl::deserialize(deserializer)?;
+        Ok(Self {
+            strip_ansi: sanitizer.strip_ansi,
+            max_bytes: sanitizer
+                .max_bytes
+                .map(|max_bytes| usize::try_from(max_bytes.bytes()).unwrap_or(usize::MAX)),
+            keep: sanitizer.keep,
+            replace_invalid_utf8: sanitizer.replace_invalid_utf8,
+        })
+    }
+}
+
+/// The part of truncated output that is kept.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TruncateKeep {
+    /// The start of the output.
+    Head,
+
+    /// The end of the output.
+    Tail,
+
+    /// Half of the start and half of the end of the output.
+    ///
+    /// This is the default.
+    #[default]
+    HeadTail,
+}
+
+fn truncate(output: Vec<u8>, max_bytes: usize, keep: TruncateKeep) -> Vec<u8> {
+    if output.len() <= max_bytes {
+        return output;
+    }
+    let (head_len, tail_len) = match keep {
+        TruncateKeep::Head => (max_bytes, 0),
+        TruncateKeep::Tail => (0, max_bytes),
+        TruncateKeep::HeadTail => (max_bytes / 2, max_bytes - max_bytes / 2),
+    };
+
+    // Move the cuts so that they don't split UTF-8 characters, by skipping continuation bytes.
+    let is_continuation = |idx: usize| output.get(idx).is_some_and(|b| b & 0xc0 == 0x80);
+    let mut head_end = head_len;
+    while head_end > 0 && is_continuation(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = output.len() - tail_len;
+    while tail_start < output.len() && is_continuation(tail_start) {
+        tail_start += 1;
+    }
+
+    let mut truncated = Vec::with_capacity(max_bytes + 64);
+    truncated.extend_from_slice(&output[..head_end]);
+    if !truncated.is_empty() && !truncated.ends_with(b"\n") {
+        truncated.push(b'\n');
+    }
+    truncated.extend_from_slice(
+        format!(
+            "(nextest: {} of output truncated)\n",
+            format_bytes((tail_start - head_end) as u64)
+        )
+        .as_bytes(),
+    );
+    truncated.extend_from_slice(&output[tail_start..]);
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_output() {
+        let sanitizer = OutputSanitizer {
+            strip_ansi: true,
+            max_bytes: Some(8),
+            keep: TruncateKeep::HeadTail,
+            replace_invalid_utf8: true,
+        };
+        assert_eq!(
+            sanitizer.sanitize(b"\x1b[31mred\x1b[0m\n".to_vec()),
+            b"red\n",
+            "escapes are stripped before truncating"
+        );
+        assert_eq!(
+            String::from_utf8(sanitizer.sanitize(b"0123\n456789abc\n".to_vec())).unwrap(),
+            "0123\n(nextest: 7 B of output truncated)\nabc\n",
+        );
+        let replace_only = OutputSanitizer {
+            replace_invalid_utf8: true,
+            ..OutputSanitizer::default()
+        };
+        assert_eq!(
+            replace_only.sanitize(b"ok \xff\n".to_vec()),
+            "ok \u{fffd}\n".as_bytes(),
+            "invalid UTF-8 is replaced"
+        );
+        assert_eq!(
+            OutputSanitizer::default().sanitize(b"\x1b[31m\xff".to_vec()),
+            b"\x1b[31m\xff",
+            "nothing is done by default"
+        );
+    }
+
+    #[test]
+    fn truncate_keep() {
+        let output = "αβγδεζ".as_bytes().to_vec();
+        let truncated =
+            |max_bytes, keep| String::from_utf8(truncate(output.clone(), max_bytes, keep));
+
+        assert_eq!(
+            truncated(5, TruncateKeep::Head).unwrap(),
+            "αβ\n(nextest: 8 B of output truncated)\n",
+            "cut before a character that doesn't fit"
+        );
+        assert_eq!(
+            truncated(5, TruncateKeep::Tail).unwrap(),
+            "(nextest: 8 B of output truncated)\nεζ",
+        );
+        assert_eq!(
+            truncated(6, TruncateKeep::HeadTail).unwrap(),
+            "α\n(nextest: 8 B of output truncated)\nζ",
+        );
+        assert_eq!(truncated(12, TruncateKeep::Head).unwrap(), "αβγδεζ");
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Mapping paths on this machine to the paths they're available at where tests are run, such as on
+//! a remote host or inside a container.
+
+use camino::Utf8PathBuf;
+use std::{env, ffi::OsStr};
+
+/// Maps directories on this machine to the paths they're available at elsewhere.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct PathMapper {
+    // Sorted so that longer (more specific) local directories come first.
+    mappings: Vec<(Utf8PathBuf, String)>,
+}
+
+impl PathMapper {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps `local` and everything under it to `mapped`.
+    pub(crate) fn add(
+        &mut self,
+        local: impl Into<Utf8PathBuf>,
+        mapped: impl Into<String>,
+    ) -> &mut Self {
+        self.mappings.push((local.into(), mapped.into()));
+        self.mappings
+            .sort_by_key(|(local, _)| std::cmp::Reverse(local.as_str().len()));
+        self
+    }
+
+    /// Maps a path, returning `None` if it isn't under any of the mapped directories.
+    pub(crate) fn map_path(&self, path: &str) -> Option<String> {
+        self.mappings.iter().find_map(|(local, mapped)| {
+            let rest = path.strip_prefix(local.as_str())?;
+            (rest.is_empty() || rest.starts_with('/')).then(|| format!("{}{}", mapped, rest))
+        })
+    }
+
+    /// Maps a value that may be a path, returning it unchanged if it isn't under any of the mapped
+    /// directories.
+    pub(crate) fn map_value(&self, value: &str) -> String {
+        self.map_path(value).unwrap_or_else(|| value.to_owned())
+    }
+
+    /// Returns the environment variables set on a command that aren't inherited from the current
+    /// process (such as `PATH` and `HOME`), with their values mapped, sorted by name.
+    pub(crate) fn mapped_env<'a>(
+        &self,
+        envs: impl IntoIterator<Item = (&'a OsStr, Option<&'a OsStr>)>,
+    ) -> Vec<(String, String)> {
+        let mut mapped: Vec<_> = envs
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let value = value?;
+                if env::var_os(key).as_deref() == Some(value) {
+                    return None;
+                }
+                Some((
+                    key.to_string_lossy().into_owned(),
+                    self.map_value(&value.to_string_lossy()),
+                ))
+            })
+            .collect();
+        mapped.sort_unstable();
+        mapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_paths() {
+        let mut mapper = PathMapper::new();
+        mapper
+            .add("/workspace", "/mnt/workspace")
+            .add("/workspace/target/debug", "/mnt/target");
+
+        assert_eq!(
+            mapper.map_path("/workspace/my-crate").as_deref(),
+            Some("/mnt/workspace/my-crate")
+        );
+        assert_eq!(
+            mapper.map_path("/workspace/target/debug/deps").as_deref(),
+            Some("/mnt/target/deps"),
+            "the most specific mapping is used"
+        );
+        assert_eq!(
+            mapper.map_path("/workspace").as_deref(),
+            Some("/mnt/workspace")
+        );
+        assert_eq!(mapper.map_path("/workspace-other"), None);
+        assert_eq!(mapper.map_value("not a path"), "not a path");
+
+        let path = env::var_os("PATH").unwrap_or_default();
+        let envs = [
+            (OsStr::new("OUT_DIR"), Some(OsStr::new("/workspace/out"))),
+            (OsStr::new("NEXTEST_VALUE"), Some(OsStr::new("value"))),
+            (OsStr::new("PATH"), Some(path.as_os_str())),
+            (OsStr::new("REMOVED"), None),
+        ];
+        assert_eq!(
+            mapper.mapped_env(envs),
+            [
+                ("NEXTEST_VALUE".to_owned(), "value".to_owned()),
+                ("OUT_DIR".to_owned(), "/mnt/workspace/out".to_owned()),
+            ],
+            "inherited variables aren't included"
+        );
+    }
+}
@@ -0,0 +1,624 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Running test binaries on embedded devices attached through a debug probe, with `probe-rs`.
+//!
+//! A `target-runner` rule can specify the chip on a probe-attached device instead of a command:
+//!
+//! ```toml
+//! [[profile.default.target-runner]]
+//! platform = "thumbv7em-none-eabihf"
+//! probe = { chip = "nRF52840_xxAA", probe = "1366:1015", probe-rs-args = ["--connect-under-reset"] }
+//! ```
+//!
+//! Test binaries are expected to use an on-target harness such as
+//! [defmt-test](https://crates.io/crates/defmt-test), which runs each test in turn and reports its
+//! progress over RTT as lines like ``(1/3) running `it_works`...``, followed by `all tests passed!`
+//! once every test has passed. A test that fails stops the binary. Each binary is flashed and run
+//! with `probe-rs run --chip <chip> [--probe <probe>] <probe-rs args> <binary>`, which decodes the
+//! defmt output.
+//!
+//! A device can't run a single test out of a binary, so the binary is run as a whole and its output
+//! is split into a result for each test:
+//!
+//! * Tests are listed by running the binary. That run is reused for the first attempt of each test.
+//! * A test passes if the harness went on to the next test, or reported that all tests passed. Its
+//!   output is what the device printed from the time it started until the next test started.
+//! * Tests after a failing test aren't run, and fail. Tests that weren't reached while listing are
+//!   named after their position, such as `#3`.
+//! * Tests the harness reports as ``(2/3) ignoring `name`...`` are listed as ignored.
+//! * Retried tests run the binary again, once per attempt: tests that are retried at the same time
+//!   share the run.
+//!
+//! Only one binary is run at a time, since the probe can't be shared. A run is stopped after
+//! `timeout` (5 minutes by default), failing the tests that didn't finish. Tests waiting for a run
+//! are reported as slow as configured by the profile's `slow-timeout`, as with other tests.
+
+use crate::{
+    environment::{BinaryRun, BinaryTestOutcome, BinaryTestResult},
+    stopwatch::ClockInstant,
+    test_list::DISCOVERY_PROTOCOL_HEADER,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use duct::cmd;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// An embedded device attached through a debug probe, that test binaries are flashed to and run on
+/// with `probe-rs`.
+///
+/// Obtained through [`TargetRunnerRule::probe`](crate::target_runner::TargetRunnerRule::probe).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProbeDevice {
+    chip: String,
+    #[serde(default)]
+    probe: Option<String>,
+    #[serde(default)]
+    probe_rs_args: Vec<String>,
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    timeout: Duration,
+}
+
+impl ProbeDevice {
+    /// The default amount of time a binary is allowed to run on the device for.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+    /// Creates a new device with the given chip, as passed to `probe-rs run --chip`.
+    pub fn new(chip: impl Into<String>) -> Self {
+        Self {
+            chip: chip.into(),
+            probe: None,
+            probe_rs_args: vec![],
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the probe to use, as passed to `probe-rs run --probe`. If this isn't set, `probe-rs`
+    /// picks the probe.
+    pub fn set_probe(&mut self, probe: impl Into<String>) -> &mut Self {
+        self.probe = Some(probe.into());
+        self
+    }
+
+    /// Sets additional arguments to pass to `probe-rs run`, before the binary.
+    pub fn set_probe_rs_args(&mut self, probe_rs_args: Vec<String>) -> &mut Self {
+        self.probe_rs_args = probe_rs_args;
+        self
+    }
+
+    /// Sets the amount of time a binary is allowed to run on the device for.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the chip on the device.
+    pub fn chip(&self) -> &str {
+        &self.chip
+    }
+
+    /// Returns the probe to use, if specified.
+    pub fn probe(&self) -> Option<&str> {
+        self.probe.as_deref()
+    }
+
+    /// Returns the additional arguments passed to `probe-rs run`.
+    pub fn probe_rs_args(&self) -> &[String] {
+        &self.probe_rs_args
+    }
+
+    /// Returns the amount of time a binary is allowed to run on the device for.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns the arguments to `probe-rs` that flash and run a binary, before the binary itself.
+    pub(crate) fn run_args(&self) -> Vec<String> {
+        let mut args = vec!["run".to_owned(), "--chip".to_owned(), self.chip.clone()];
+        if let Some(probe) = &self.probe {
+            args.extend(["--probe".to_owned(), probe.clone()]);
+        }
+        args.extend(self.probe_rs_args.iter().cloned());
+        args
+    }
+}
+
+fn default_timeout() -> Duration {
+    ProbeDevice::DEFAULT_TIMEOUT
+}
+
+/// Runs test binaries on a [`ProbeDevice`], sharing each run between the tests in the binary.
+pub(crate) struct ProbeRunner {
+    device: ProbeDevice,
+    // Held while a binary is running, since the probe can only be used by one run at a time.
+    probe_lock: Mutex<()>,
+    // Runs of each binary, by attempt.
+    runs: Mutex<HashMap<(Utf8PathBuf, usize), Arc<DeviceRun>>>,
+}
+
+impl ProbeRunner {
+    pub(crate) fn new(device: ProbeDevice) -> Self {
+        Self {
+            device,
+            probe_lock: Mutex::new(()),
+            runs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn device(&self) -> &ProbeDevice {
+        &self.device
+    }
+
+    /// Runs the binary on the device, and returns the tests in it in the
+    /// [discovery protocol](crate::test_list#dynamic-test-discovery) format.
+    ///
+    /// The run is reused for the first attempt of each test.
+    pub(crate) fn list_tests(self: &Arc<Self>, binary_path: &Utf8Path) -> io::Result<String> {
+        let run = self.start_run(binary_path, 1);
+        run.wait()
+            .as_ref()
+            .map_err(|error| error.to_io())?
+            .to_list()
+    }
+
+    /// Returns the run of the binary for the given attempt, starting it if it hasn't been started
+    /// yet.
+    pub(crate) fn start_run(
+        self: &Arc<Self>,
+        binary_path: &Utf8Path,
+        attempt: usize,
+    ) -> Arc<DeviceRun> {
+        let mut runs = self.runs.lock().expect("lock is not poisoned");
+        if let Some(run) = runs.get(&(binary_path.to_owned(), attempt)) {
+            return run.clone();
+        }
+
+        // The sender is dropped once the output has been set, which wakes up the tests waiting for
+        // it.
+        let (sender, receiver) = crossbeam_channel::bounded::<()>(0);
+        let run = Arc::new(DeviceRun {
+            output: OnceCell::new(),
+            done: receiver,
+        });
+        runs.insert((binary_path.to_owned(), attempt), run.clone());
+
+        let this = self.clone();
+        let binary_path = binary_path.to_owned();
+        let thread_run = run.clone();
+        let spawned = thread::Builder::new()
+            .name("nextest-probe-run".to_owned())
+            .spawn(move || {
+                let output = this.run_on_device(&binary_path).map_err(RunError::new);
+                let _ = thread_run.output.set(output);
+                drop(sender);
+            });
+        if let Err(error) = spawned {
+            let _ = run.output.set(Err(RunError::new(error)));
+        }
+        run
+    }
+
+    fn run_on_device(&self, binary_path: &Utf8Path) -> io::Result<HarnessOutput> {
+        let _probe = self.probe_lock.lock().expect("lock is not poisoned");
+        let mut args = self.device.run_args();
+        args.push(binary_path.to_string());
+        let handle = cmd("probe-rs", args)
+            .stdin_null()
+            .stderr_capture()
+            .unchecked()
+            .reader()?;
+
+        let start = ClockInstant::now();
+        let mut parser = HarnessParser::default();
+        let (done_sender, done_receiver) = crossbeam_channel::bounded::<()>(0);
+        let timed_out = thread::scope(|s| {
+            // Stop the run if it takes too long.
+            let watchdog = s.spawn(|| match done_receiver.recv_timeout(self.device.timeout) {
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = handle.kill();
+                    true
+                }
+                _ => false,
+            });
+
+            let mut reader = BufReader::new(&handle);
+            let mut line = Vec::new();
+            let read_result = loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) => break Ok(()),
+                    Ok(_) => parser.push_line(&line, start.elapsed()),
+                    Err(error) => break Err(error),
+                }
+            };
+            drop(done_sender);
+            let timed_out = watchdog.join().expect("watchdog thread doesn't panic");
+            // Reads fail once the run is stopped.
+            if !timed_out {
+                read_result?;
+            }
+            Ok::<_, io::Error>(timed_out)
+        })?;
+
+        let output = handle
+            .try_wait()?
+            .ok_or_else(|| io::Error::other("probe-rs is still running"))?;
+        Ok(parser.finish(
+            output.status.success(),
+            timed_out,
+            output.stderr.clone(),
+            start.elapsed(),
+        ))
+    }
+}
+
+impl fmt::Debug for ProbeRunner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProbeRunner")
+            .field("device", &self.device)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for ProbeRunner {
+    fn eq(&self, other: &Self) -> bool {
+        self.device == other.device
+    }
+}
+
+impl Eq for ProbeRunner {}
+
+/// A run of a binary on the device, which tests in the binary wait for.
+pub(crate) struct DeviceRun {
+    output: OnceCell<Result<HarnessOutput, RunError>>,
+    done: Receiver<()>,
+}
+
+impl DeviceRun {
+    /// Waits for the run to finish.
+    pub(crate) fn wait(&self) -> &Result<HarnessOutput, RunError> {
+        let _ = self.done.recv();
+        self.finished()
+    }
+
+    fn finished(&self) -> &Result<HarnessOutput, RunError> {
+        // The output is set before the sender is dropped, unless the thread doing the run
+        // panicked.
+        self.output.get_or_init(|| {
+            Err(RunError(
+                "the thread running the binary panicked".to_owned(),
+            ))
+        })
+    }
+}
+
+impl BinaryRun for DeviceRun {
+    fn wait_timeout(&self, timeout: Option<Duration>) -> bool {
+        match timeout {
+            Some(timeout) => !matches!(
+                self.done.recv_timeout(timeout),
+                Err(RecvTimeoutError::Timeout)
+            ),
+            None => {
+                let _ = self.done.recv();
+                true
+            }
+        }
+    }
+
+    fn test_result(&self, name: &str) -> io::Result<BinaryTestResult> {
+        let output = self.wait().as_ref().map_err(|error| error.to_io())?;
+        Ok(output.test_result(name))
+    }
+}
+
+/// An error that occurred while running a binary on the device, shared between the tests waiting
+/// for it.
+#[derive(Clone, Debug)]
+pub(crate) struct RunError(String);
+
+impl RunError {
+    fn new(error: io::Error) -> Self {
+        Self(format!("running probe-rs failed: {}", error))
+    }
+
+    pub(crate) fn to_io(&self) -> io::Error {
+        io::Error::other(self.0.clone())
+    }
+}
+
+/// The output of a run of a binary, parsed into the tests the harness ran.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HarnessOutput {
+    // The number of tests the harness said there are.
+    total: usize,
+    tests: Vec<HarnessTest>,
+    all_passed: bool,
+    success: bool,
+    timed_out: bool,
+    stderr: Vec<u8>,
+    elapsed: Duration,
+}
+
+#[derive(Clone, Debug)]
+struct HarnessTest {
+    // The position of the test, starting from 1.
+    index: usize,
+    name: String,
+    ignored: bool,
+    started: Duration,
+    output: Vec<u8>,
+}
+
+impl HarnessOutput {
+    fn to_list(&self) -> io::Result<String> {
+        if self.tests.is_empty() && !(self.all_passed && self.success) {
+            return Err(io::Error::other(format!(
+                "the binary didn't report running any tests{} (does it use a harness such \
+                     as defmt-test?)\n{}",
+                if self.timed_out {
+                    " before it timed out"
+                } else {
+                    ""
+                },
+                String::from_utf8_lossy(&self.stderr).trim_end(),
+            )));
+        }
+
+        let mut list = format!("{}\n", DISCOVERY_PROTOCOL_HEADER);
+        for test in &self.tests {
+            let test = serde_json::json!({ "name": test.name, "ignored": test.ignored });
+            list.push_str(&test.to_string());
+            list.push('\n');
+        }
+        let reached = self.tests.last().map_or(0, |test| test.index);
+        for index in reached + 1..=self.total {
+            let test = serde_json::json!({ "name": format!("#{}", index) });
+            list.push_str(&test.to_string());
+            list.push('\n');
+        }
+        Ok(list)
+    }
+
+    /// Returns the result of the test with the given name: either its name as reported by the
+    /// harness, or its position for tests that weren't reached while listing.
+    pub(crate) fn test_result(&self, name: &str) -> BinaryTestResult {
+        let index = name.strip_prefix('#').and_then(|index| index.parse().ok());
+        let position = self
+            .tests
+            .iter()
+            .position(|test| test.name == name || Some(test.index) == index);
+        let position = match position {
+            Some(position) => position,
+            None => {
+                let mut stderr = Vec::new();
+                let _ = write!(
+                    stderr,
+                    "nextest: this test wasn't run, since the binary stopped before reaching it"
+                );
+                if let Some(last) = self.tests.last() {
+                    let _ = write!(stderr, " (after `{}` failed)", last.name);
+                }
+                let _ = writeln!(stderr);
+                return BinaryTestResult {
+                    outcome: BinaryTestOutcome::Fail,
+                    stdout: vec![],
+                    stderr,
+                    duration: Duration::ZERO,
+                };
+            }
+        };
+
+        let test = &self.tests[position];
+        let next = self.tests.get(position + 1);
+        let duration = next
+            .map_or(self.elapsed, |next| next.started)
+            .saturating_sub(test.started);
+        let passed = next.is_some() || (self.all_passed && self.success);
+        let (outcome, stderr) = if test.ignored {
+            (
+                BinaryTestOutcome::Skip("ignored by the test harness".to_owned()),
+                vec![],
+            )
+        } else if passed {
+            (BinaryTestOutcome::Pass, vec![])
+        } else {
+            // The run stopped during this test, so what probe-rs printed out is relevant to it.
+            let mut stderr = self.stderr.clone();
+            if self.timed_out {
+                let _ = writeln!(
+                    stderr,
+                    "nextest: the binary was stopped after running on the device for {:?}",
+                    self.elapsed
+                );
+            }
+            (BinaryTestOutcome::Fail, stderr)
+        };
+        BinaryTestResult {
+            outcome,
+            stdout: test.output.clone(),
+            stderr,
+            duration,
+        }
+    }
+}
+
+/// Splits the output of a run into tests, line by line.
+#[derive(Debug, Default)]
+struct HarnessParser {
+    output: HarnessOutput,
+}
+
+impl HarnessParser {
+    fn push_line(&mut self, line: &[u8], elapsed: Duration) {
+        let stripped = strip_ansi_escapes::strip(line).unwrap_or_else(|_| line.to_vec());
+        let text = String::from_utf8_lossy(&stripped);
+        if let Some((index, total, name, ignored)) = parse_progress(&text) {
+            self.output.total = total;
+            self.output.tests.push(HarnessTest {
+                index,
+                name: name.to_owned(),
+                ignored,
+                started: elapsed,
+                output: vec![],
+            });
+        } else if text.contains("all tests passed!") {
+            self.output.all_passed = true;
+        }
+
+        // Output before the first test, such as from flashing the binary, isn't part of any test.
+        if let Some(test) = self.output.tests.last_mut() {
+            test.output.extend_from_slice(line);
+        }
+    }
+
+    fn finish(
+        self,
+        success: bool,
+        timed_out: bool,
+        stderr: Vec<u8>,
+        elapsed: Duration,
+    ) -> HarnessOutput {
+        HarnessOutput {
+            success,
+            timed_out,
+            stderr,
+            elapsed,
+            ..self.output
+        }
+    }
+}
+
+// Parses a line of the form "(1/3) running `name`...", possibly with a log level or other prefix
+// before it, into the position, the total, the name and whether the test is ignored.
+fn parse_progress(line: &str) -> Option<(usize, usize, &str, bool)> {
+    let (before, ignored, rest) = match line.find(") running `") {
+        Some(idx) => (&line[..idx], false, &line[idx + ") running `".len()..]),
+        None => {
+            let idx = line.find(") ignoring `")?;
+            (&line[..idx], true, &line[idx + ") ignoring `".len()..])
+        }
+    };
+    let (index, total) = before[before.rfind('(')? + 1..].split_once('/')?;
+    let name = &rest[..rest.find('`')?];
+    Some((index.parse().ok()?, total.parse().ok()?, name, ignored))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(lines: &[(&str, u64)], success: bool) -> HarnessOutput {
+        let mut parser = HarnessParser::default();
+        for (line, elapsed_ms) in lines {
+            parser.push_line(
+                format!("{}\n", line).as_bytes(),
+                Duration::from_millis(*elapsed_ms),
+            );
+        }
+        parser.finish(
+            success,
+            false,
+            b"Error: the chip panicked\n".to_vec(),
+            Duration::from_millis(1000),
+        )
+    }
+
+    #[test]
+    fn split_harness_output() {
+        assert_eq!(
+            parse_progress("INFO  (2/13) running `it_works`..."),
+            Some((2, 13, "it_works", false))
+        );
+        assert_eq!(
+            parse_progress("(1/2) ignoring `slow`..."),
+            Some((1, 2, "slow", true))
+        );
+        assert_eq!(parse_progress("running `it_works`..."), None);
+        assert_eq!(parse_progress("(a/2) running `it_works`..."), None);
+
+        let passed = parse(
+            &[
+                ("      Erasing ✔ [00:00:01]", 0),
+                ("\x1b[32m(1/3) running `first`...\x1b[0m", 100),
+                ("└─ tests::__defmt_test_entry @ tests/basic.rs:10", 110),
+                ("(2/3) ignoring `second`...", 300),
+                ("(3/3) running `third`...", 300),
+                ("all tests passed!", 900),
+            ],
+            true,
+        );
+        let list = passed.to_list().expect("tests were reported");
+        let mut lines = list.lines();
+        assert_eq!(lines.next(), Some(DISCOVERY_PROTOCOL_HEADER));
+        assert_eq!(
+            lines.collect::<Vec<_>>(),
+            [
+                r#"{"ignored":false,"name":"first"}"#,
+                r#"{"ignored":true,"name":"second"}"#,
+                r#"{"ignored":false,"name":"third"}"#,
+            ]
+        );
+
+        let first = passed.test_result("first");
+        assert_eq!(first.outcome, BinaryTestOutcome::Pass);
+        assert_eq!(first.duration, Duration::from_millis(200));
+        assert!(
+            String::from_utf8_lossy(&first.stdout).ends_with("tests/basic.rs:10\n"),
+            "output is split between tests"
+        );
+        assert!(first.stderr.is_empty());
+        assert!(matches!(
+            passed.test_result("second").outcome,
+            BinaryTestOutcome::Skip(_)
+        ));
+        let third = passed.test_result("third");
+        assert_eq!(third.outcome, BinaryTestOutcome::Pass);
+        assert_eq!(third.duration, Duration::from_millis(700));
+
+        // The second test panics, so the third one isn't reached.
+        let failed = parse(
+            &[
+                ("(1/3) running `first`...", 0),
+                ("(2/3) running `second`...", 100),
+                ("ERROR panicked at 'assertion failed'", 150),
+            ],
+            false,
+        );
+        let list = failed.to_list().expect("tests were reported");
+        assert!(list.ends_with("{\"name\":\"#3\"}\n"));
+        assert_eq!(failed.test_result("first").outcome, BinaryTestOutcome::Pass);
+        let second = failed.test_result("second");
+        assert_eq!(second.outcome, BinaryTestOutcome::Fail);
+        assert_eq!(second.stderr, b"Error: the chip panicked\n");
+        let third = failed.test_result("#3");
+        assert_eq!(third.outcome, BinaryTestOutcome::Fail);
+        assert!(String::from_utf8_lossy(&third.stderr).contains("after `second` failed"));
+
+        // On a later attempt, the third test is reached, and found by its position.
+        let retried = parse(
+            &[
+                ("(1/3) running `first`...", 0),
+                ("(2/3) running `second`...", 100),
+                ("(3/3) running `third`...", 200),
+                ("all tests passed!", 300),
+            ],
+            true,
+        );
+        assert_eq!(retried.test_result("#3").outcome, BinaryTestOutcome::Pass);
+
+        // Binaries that don't use a harness can't be listed.
+        let no_harness = parse(&[("Hello, world!", 0)], false);
+        assert!(no_harness.to_list().is_err());
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Capturing and replaying the failing cases of property-based tests.
+//!
+//! Property-based testing frameworks generate inputs at random, so a failure seen in one run might
+//! not show up in the next. To make such failures reproducible, nextest chooses the seed of
+//! [proptest](https://docs.rs/proptest)'s random number generator for each attempt, passing it in
+//! `PROPTEST_RNG_SEED`, unless it's already set in the environment or by an env file.
+//!
+//! When a test fails, its output is searched for the failure messages of proptest and
+//! [quickcheck](https://docs.rs/quickcheck). The failing case is stored in the run report: the
+//! seed, for proptest, along with the minimal failing input after shrinking, and the line the
+//! framework suggests adding to its failure persistence file.
+//!
+//! `cargo nextest run --replay <report>` then runs the tests with a recorded seed, passing them the
+//! same seed again, so that they generate the same inputs as in the failing run. Quickcheck can't be
+//! seeded this way, so its failures are recorded but not replayed.
+//!
+//! Proptest reads `PROPTEST_RNG_SEED` from version 1.2 onwards.
+
+use nextest_metadata::{PropertyTestFramework, PropertyTestSummary, RunReportSummary};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The environment variable proptest reads the seed of its random number generator from.
+pub const PROPTEST_RNG_SEED_ENV: &str = "PROPTEST_RNG_SEED";
+
+/// A failing case found by a property-based testing framework, detected in the output of a test.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PropertyTestFailure {
+    /// The framework that found the failing case.
+    pub framework: PropertyTestFramework,
+
+    /// The seed the test was run with, if the framework can be seeded.
+    pub seed: Option<String>,
+
+    /// The minimal failing input, after shrinking, such as `x = 5`.
+    pub minimal_input: Option<String>,
+
+    /// The line the framework suggests adding to its failure persistence file.
+    pub regression: Option<String>,
+}
+
+impl PropertyTestFailure {
+    /// Looks for the failure message of a property-based testing framework in the output of a test
+    /// that was run with the given proptest seed.
+    pub fn detect(stdout: &[u8], stderr: &[u8], seed: Option<&str>) -> Option<Self> {
+        // Panic messages go to standard error, but the test harness might print them to standard
+        // output.
+        let output = format!(
+            "{}\n{}",
+            String::from_utf8_lossy(stderr),
+            String::from_utf8_lossy(stdout)
+        );
+
+        let mut proptest_input = None;
+        let mut regression = None;
+        let mut quickcheck_input = None;
+        for line in output.lines() {
+            let line = line.trim();
+            if proptest_input.is_none() {
+                if let Some((_, input)) = line.split_once("minimal failing input: ") {
+                    proptest_input = Some(input.trim_end_matches('\'').trim().to_owned());
+                }
+            }
+            if regression.is_none() && is_regression_line(line) {
+                regression = Some(line.to_owned());
+            }
+            if quickcheck_input.is_none() && line.contains("[quickcheck] TEST FAILED") {
+                quickcheck_input = Some(
+                    line.split_once("Arguments: ")
+                        .map(|(_, input)| input.trim_end_matches('\'').trim().to_owned()),
+                );
+            }
+        }
+
+        if proptest_input.is_some() || regression.is_some() {
+            Some(Self {
+                framework: PropertyTestFramework::Proptest,
+                seed: seed.map(|seed| seed.to_owned()),
+                minimal_input: proptest_input,
+                regression,
+            })
+        } else {
+            quickcheck_input.map(|minimal_input| Self {
+                framework: PropertyTestFramework::Quickcheck,
+                seed: None,
+                minimal_input,
+                regression: None,
+            })
+        }
+    }
+
+    /// Returns the summary of this failure stored in run reports.
+    pub fn to_summary(&self) -> PropertyTestSummary {
+        PropertyTestSummary {
+            framework: self.framework,
+            seed: self.seed.clone(),
+            minimal_input: self.minimal_input.clone(),
+            regression: self.regression.clone(),
+        }
+    }
+}
+
+/// Returns true if this is a line proptest prints for its failure persistence file, such as
+/// `cc 4a1f0e # shrinks to x = 5`.
+fn is_regression_line(line: &str) -> bool {
+    line.strip_prefix("cc ")
+        .and_then(|rest| rest.split(' ').next())
+        .is_some_and(|hash| !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// The proptest seeds recorded in a run report, to replay the failing cases they found.
+#[derive(Clone, Debug, Default)]
+pub struct ReplaySeeds {
+    // Binary ID -> test name -> seed.
+    seeds: HashMap<String, HashMap<String, String>>,
+}
+
+impl ReplaySeeds {
+    /// Collects the seeds of the failing cases in a run report, taken from the last attempt of each
+    /// test that recorded one.
+    pub fn from_report(summary: &RunReportSummary) -> Self {
+        let mut seeds: HashMap<String, HashMap<String, String>> = HashMap::new();
+        for test in &summary.tests {
+            let seed = test.attempts.iter().rev().find_map(|attempt| {
+                attempt
+                    .property_test
+                    .as_ref()
+                    .and_then(|property_test| property_test.seed.clone())
+            });
+            if let Some(seed) = seed {
+                seeds
+                    .entry(test.binary_id.clone())
+                    .or_default()
+                    .insert(test.name.clone(), seed);
+            }
+        }
+        Self { seeds }
+    }
+
+    /// Returns the number of tests with a recorded seed.
+    pub fn len(&self) -> usize {
+        self.seeds.values().map(|names| names.len()).sum()
+    }
+
+    /// Returns true if no seeds were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the binary ID and name of each test with a recorded seed.
+    pub fn tests(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.seeds.iter().flat_map(|(binary_id, names)| {
+            names
+                .keys()
+                .map(move |name| (binary_id.as_str(), name.as_str()))
+        })
+    }
+
+    /// Returns the seed recorded for the given test, if any.
+    pub fn seed(&self, binary_id: &str, name: &str) -> Option<&str> {
+        self.seeds
+            .get(binary_id)?
+            .get(name)
+            .map(|seed| seed.as_str())
+    }
+}
+
+/// Chooses the proptest seed for an attempt: the replayed seed if there is one, then any seed set
+/// by env files or the environment nextest is run in, and otherwise a random one.
+pub(crate) fn choose_seed(replay_seed: Option<&str>, env_vars: &[(String, String)]) -> String {
+    if let Some(seed) = replay_seed {
+        return seed.to_owned();
+    }
+    env_vars
+        .iter()
+        .find(|(key, _)| key == PROPTEST_RNG_SEED_ENV)
+        .map(|(_, value)| value.clone())
+        .or_else(|| std::env::var(PROPTEST_RNG_SEED_ENV).ok())
+        .unwrap_or_else(|| format!("{}", Uuid::new_v4().as_u128() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_property_test_failures() {
+        let proptest_stderr = "\
+proptest: Saving this and future failures in /ws/proptest-regressions/lib.txt
+proptest: If this test was run on a CI system, you may wish to add the following line to your copy of the file. (You may need to create it.)
+cc 4a1f0e9d2b7c # shrinks to x = 5
+thread 'tests::small' panicked at src/lib.rs:10:1:
+Test failed: assertion failed: x < 5; minimal failing input: x = 5
+\tsuccesses: 3
+\tlocal rejects: 0
+";
+        assert_eq!(
+            PropertyTestFailure::detect(b"", proptest_stderr.as_bytes(), Some("1234")),
+            Some(PropertyTestFailure {
+                framework: PropertyTestFramework::Proptest,
+                seed: Some("1234".to_owned()),
+                minimal_input: Some("x = 5".to_owned()),
+                regression: Some("cc 4a1f0e9d2b7c # shrinks to x = 5".to_owned()),
+            })
+        );
+
+        // Older versions of Rust print the panic message in quotes.
+        let old_panic = "thread 'tests::small' panicked at 'Test failed: nope; minimal failing \
+            input: s = \"\"'\n";
+        assert_eq!(
+            PropertyTestFailure::detect(old_panic.as_bytes(), b"", None)
+                .unwrap()
+                .minimal_input
+                .as_deref(),
+            Some("s = \"\"")
+        );
+
+        let quickcheck =
+            "thread 'prop' panicked at '[quickcheck] TEST FAILED. Arguments: ([1, 0])'";
+        assert_eq!(
+            PropertyTestFailure::detect(b"", quickcheck.as_bytes(), Some("1234")),
+            Some(PropertyTestFailure {
+                framework: PropertyTestFramework::Quickcheck,
+                seed: None,
+                minimal_input: Some("([1, 0])".to_owned()),
+                regression: None,
+            }),
+        );
+
+        for not_a_failure in [
+            "thread 'main' panicked at 'assertion failed', src/lib.rs:1:1",
+            "cc: command not found",
+            "cc xyz # not a hash",
+        ] {
+            assert_eq!(
+                PropertyTestFailure::detect(not_a_failure.as_bytes(), b"", Some("1")),
+                None,
+                "{:?}",
+                not_a_failure
+            );
+        }
+
+        assert_eq!(choose_seed(Some("7"), &[]), "7");
+        let env_vars = [(PROPTEST_RNG_SEED_ENV.to_owned(), "42".to_owned())];
+        assert_eq!(choose_seed(None, &env_vars), "42");
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Quarantining known-flaky tests, so that they don't block CI while they're being fixed.
+//!
+//! Quarantined tests are still run, but their failures don't fail the run, and don't trigger
+//! `fail-fast`. They're counted and reported separately instead, and marked as quarantined in the
+//! run report, so that teams can keep track of them and burn the list down over time.
+//!
+//! The quarantine set is specified per profile, through a filter expression, a file listing test
+//! IDs, or both:
+//!
+//! ```toml
+//! [profile.ci.quarantine]
+//! filter = "test(~flaky_)"
+//! file = ".config/nextest-quarantine.txt"
+//! ```
+//!
+//! The file is relative to the workspace root, and lists one test ID per line. As with
+//! [renames](crate::renames), an ID is a binary ID, optionally followed by a space and a test name.
+//! A binary ID on its own quarantines every test in the binary, and a name ending with `::`
+//! quarantines every test in that module. Blank lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! # Flaky since the switch to the new scheduler.
+//! my-crate tests::races_with_timer
+//! my-crate::integration network::
+//! ```
+
+use crate::{
+    errors::QuarantineError,
+    filter_expr::{FilterExpr, TestQuery},
+    renames::TestPattern,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+use std::fs;
+
+/// The quarantine settings of a profile, as specified in the config.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct QuarantineConfig {
+    #[serde(default)]
+    filter: Option<FilterExpr>,
+    #[serde(default)]
+    file: Option<Utf8PathBuf>,
+}
+
+impl QuarantineConfig {
+    /// Returns the filter expression for quarantined tests, if any.
+    pub fn filter(&self) -> Option<&FilterExpr> {
+        self.filter.as_ref()
+    }
+
+    /// Returns the file listing quarantined tests, relative to the workspace root, if any.
+    pub fn file(&self) -> Option<&Utf8Path> {
+        self.file.as_deref()
+    }
+}
+
+/// The set of quarantined tests: their failures don't fail the run.
+#[derive(Clone, Debug, Default)]
+pub struct Quarantine {
+    filter: Option<FilterExpr>,
+    patterns: Vec<TestPattern>,
+}
+
+impl Quarantine {
+    /// Creates the quarantine set from a profile's settings, reading the file of test IDs it
+    /// refers to, if any.
+    pub fn load(
+        workspace_root: &Utf8Path,
+        config: &QuarantineConfig,
+    ) -> Result<Self, QuarantineError> {
+        let patterns = match &config.file {
+            Some(file) => {
+                let path = workspace_root.join(file);
+                let contents =
+                    fs::read_to_string(&path).map_err(|error| QuarantineError::Read {
+                        path: path.clone(),
+                        error,
+                    })?;
+                Self::parse_file(&path, &contents)?
+            }
+            None => vec![],
+        };
+        Ok(Self {
+            filter: config.filter.clone(),
+            patterns,
+        })
+    }
+
+    /// Returns true if no tests can be quarantined.
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_none() && self.patterns.is_empty()
+    }
+
+    /// Returns true if the given test is quarantined.
+    pub fn contains(&self, query: &TestQuery<'_>) -> bool {
+        self.filter
+            .as_ref()
+            .is_some_and(|filter| filter.matches_test(query))
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.matches(query.binary_id, query.test_name))
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    fn parse_file(path: &Utf8Path, contents: &str) -> Result<Vec<TestPattern>, QuarantineError> {
+        contents
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line_number, line)| {
+                TestPattern::parse(line).map_err(|message| QuarantineError::Invalid {
+                    path: path.to_owned(),
+                    line_number,
+                    message,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nextest_metadata::BuildPlatform;
+
+    #[test]
+    fn quarantine_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_root = Utf8Path::from_path(dir.path()).unwrap();
+        std::fs::create_dir(workspace_root.join(".config")).unwrap();
+        std::fs::write(
+            workspace_root.join(".config/nextest-quarantine.txt"),
+            "# Flaky.\nmy-crate tests::races\n\nmy-crate::integration network::\n  other-crate  \n",
+        )
+        .unwrap();
+
+        let config: QuarantineConfig = toml::from_str(
+            r#"
+                filter = "test(~flaky_)"
+                file = ".config/nextest-quarantine.txt"
+            "#,
+        )
+        .unwrap();
+        let quarantine = Quarantine::load(workspace_root, &config).unwrap();
+        assert!(!quarantine.is_empty());
+
+        let query = |binary_id, test_name| TestQuery {
+            binary_id,
+            package_name: "my-crate",
+            test_name,
+            platform: BuildPlatform::Target,
+        };
+        for (binary_id, test_name, expected) in [
+            ("my-crate", "tests::races", true),
+            ("my-crate", "tests::races_too", false),
+            ("my-crate", "tests::flaky_io", true),
+            ("my-crate::integration", "network::timeout", true),
+            ("my-crate::integration", "disk::full", false),
+            ("other-crate", "anything", true),
+        ] {
+            assert_eq!(
+                quarantine.contains(&query(binary_id, test_name)),
+                expected,
+                "{} {}",
+                binary_id,
+                test_name
+            );
+        }
+
+        assert!(
+            Quarantine::load(workspace_root, &QuarantineConfig::default())
+                .unwrap()
+                .is_empty()
+        );
+
+        let config: QuarantineConfig = toml::from_str(r#"file = "missing.txt""#).unwrap();
+        assert!(matches!(
+            Quarantine::load(workspace_root, &config),
+            Err(QuarantineError::Read { .. })
+        ));
+    }
+}
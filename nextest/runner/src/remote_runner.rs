@@ -0,0 +1,407 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Running test binaries on a remote host over SSH.
+//!
+//! A `target-runner` rule can specify a remote host instead of a command, for example to run tests
+//! on an embedded board or a machine with a different architecture:
+//!
+//! ```toml
+//! [[profile.default.target-runner]]
+//! platform = "aarch64-unknown-linux-gnu"
+//! remote = { host = "ci@arm-board", dir = "/tmp/nextest", ssh-args = ["-p", "2222"], scp-args = ["-P", "2222"] }
+//! ```
+//!
+//! Before a test binary is first run, it is copied with `scp` to `<dir>/.nextest/bin` on the remote
+//! host, along with any dynamic libraries in the directories it's linked against, which are copied
+//! to `<dir>/.nextest/lib`. Each test is then run through `ssh`, which streams its output back.
+//!
+//! The remote host is expected to have a POSIX shell. Paths in the workspace are mapped to the same
+//! paths relative to `dir`: tests are run in the mapped working directory, which is created if it
+//! doesn't exist, and the environment variables nextest sets for the test are passed along with
+//! their paths mapped. Other files, such as test fixtures, aren't copied over, and files written by
+//! tests on the remote host (such as log files for nextest to attach to the report) aren't copied
+//! back.
+
+use crate::path_mapper::PathMapper;
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::{cmd, Expression};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fmt, io,
+    path::Path,
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+/// A remote host that test binaries are copied to and run on over SSH.
+///
+/// Obtained through [`TargetRunnerRule::remote`](crate::target_runner::TargetRunnerRule::remote).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SshRemote {
+    host: String,
+    dir: String,
+    #[serde(default)]
+    ssh_args: Vec<String>,
+    #[serde(default)]
+    scp_args: Vec<String>,
+}
+
+impl SshRemote {
+    /// Creates a new remote, with the host to connect to (as passed to `ssh`) and the directory on
+    /// it that binaries are copied to and paths in the workspace are mapped to.
+    pub fn new(host: impl Into<String>, dir: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            dir: dir.into(),
+            ssh_args: vec![],
+            scp_args: vec![],
+        }
+    }
+
+    /// Sets additional arguments to pass to `ssh`, before the host.
+    pub fn set_ssh_args(&mut self, ssh_args: Vec<String>) -> &mut Self {
+        self.ssh_args = ssh_args;
+        self
+    }
+
+    /// Sets additional arguments to pass to `scp`, before the files to copy.
+    pub fn set_scp_args(&mut self, scp_args: Vec<String>) -> &mut Self {
+        self.scp_args = scp_args;
+        self
+    }
+
+    /// Returns the host to connect to.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Returns the directory on the remote host that paths in the workspace are mapped to.
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    /// Returns the additional arguments passed to `ssh`.
+    pub fn ssh_args(&self) -> &[String] {
+        &self.ssh_args
+    }
+
+    /// Returns the additional arguments passed to `scp`.
+    pub fn scp_args(&self) -> &[String] {
+        &self.scp_args
+    }
+}
+
+/// Runs test binaries on an [`SshRemote`], copying them and their libraries over as needed.
+pub(crate) struct RemoteRunner {
+    remote: SshRemote,
+    path_mapper: PathMapper,
+    copied: Mutex<CopyState>,
+}
+
+// Tracks what has already been copied to the remote host.
+#[derive(Debug, Default)]
+struct CopyState {
+    binaries: HashSet<Utf8PathBuf>,
+    // Library directories, and whether they have any dynamic libraries in them.
+    lib_dirs: HashMap<Utf8PathBuf, bool>,
+}
+
+impl RemoteRunner {
+    pub(crate) fn new(remote: SshRemote, workspace_root: &Utf8Path) -> Self {
+        let mut path_mapper = PathMapper::new();
+        path_mapper.add(workspace_root, remote.dir.clone());
+        Self {
+            remote,
+            path_mapper,
+            copied: Mutex::new(CopyState::default()),
+        }
+    }
+
+    pub(crate) fn remote(&self) -> &SshRemote {
+        &self.remote
+    }
+
+    /// Returns the `ssh` command that runs the given binary on the remote host.
+    ///
+    /// The binary and the dynamic libraries in `dylib_dirs` are copied over, and the command run on
+    /// the remote host is determined, right before the command is spawned. This is so that the
+    /// working directory and environment variables set on the expression later are picked up.
+    pub(crate) fn make_command(
+        self: &Arc<Self>,
+        binary_path: &Utf8Path,
+        args: Vec<&str>,
+        dylib_dirs: Vec<Utf8PathBuf>,
+    ) -> Expression {
+        let this = self.clone();
+        let binary_path = binary_path.to_owned();
+        let args: Vec<String> = args.into_iter().map(|arg| arg.to_owned()).collect();
+        cmd("ssh", self.ssh_command_args()).before_spawn(move |command| {
+            let remote_binary = this.copy_binary(&binary_path)?;
+            let remote_lib_dir = this.copy_libs(&dylib_dirs)?;
+            let script = remote_script(
+                &this.path_mapper,
+                &this.remote.dir,
+                command.get_current_dir(),
+                command.get_envs(),
+                &remote_binary,
+                &args,
+                remote_lib_dir.as_deref(),
+            );
+            command.arg(script);
+            Ok(())
+        })
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    fn ssh_command_args(&self) -> Vec<&str> {
+        self.remote
+            .ssh_args
+            .iter()
+            .map(|arg| arg.as_str())
+            .chain(std::iter::once(self.remote.host.as_str()))
+            .collect()
+    }
+
+    fn bin_dir(&self) -> String {
+        format!("{}/.nextest/bin", self.remote.dir)
+    }
+
+    fn lib_dir(&self) -> String {
+        format!("{}/.nextest/lib", self.remote.dir)
+    }
+
+    fn copy_binary(&self, binary_path: &Utf8Path) -> io::Result<String> {
+        let file_name = binary_path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("test binary '{}' has no file name", binary_path),
+            )
+        })?;
+        let remote_binary = format!("{}/{}", self.bin_dir(), file_name);
+
+        // Hold the lock while copying, so that tests in the same binary wait for the copy rather
+        // than starting one of their own.
+        let mut copied = self.copied.lock().expect("lock is not poisoned");
+        if !copied.binaries.contains(binary_path) {
+            self.copy_files(&[binary_path.to_owned()], &self.bin_dir())?;
+            copied.binaries.insert(binary_path.to_owned());
+        }
+        Ok(remote_binary)
+    }
+
+    // Returns the remote directory the libraries were copied to, or None if there are none.
+    fn copy_libs(&self, dylib_dirs: &[Utf8PathBuf]) -> io::Result<Option<String>> {
+        let mut copied = self.copied.lock().expect("lock is not poisoned");
+        let mut any_libs = false;
+        for dylib_dir in dylib_dirs {
+            let has_libs = match copied.lib_dirs.get(dylib_dir) {
+                Some(&has_libs) => has_libs,
+                None => {
+                    let libs = dylibs_in(dylib_dir)?;
+                    if !libs.is_empty() {
+                        self.copy_files(&libs, &self.lib_dir())?;
+                    }
+                    copied.lib_dirs.insert(dylib_dir.clone(), !libs.is_empty());
+                    !libs.is_empty()
+                }
+            };
+            any_libs |= has_libs;
+        }
+        Ok(any_libs.then(|| self.lib_dir()))
+    }
+
+    fn copy_files(&self, files: &[Utf8PathBuf], remote_dir: &str) -> io::Result<()> {
+        let mut mkdir = Command::new("ssh");
+        mkdir
+            .args(self.ssh_command_args())
+            .arg(format!("mkdir -p {}", shell_quote(remote_dir)));
+        run_checked(mkdir, || {
+            format!("creating '{}' on {}", remote_dir, self.remote.host)
+        })?;
+
+        let mut scp = Command::new("scp");
+        scp.arg("-q")
+            .args(&self.remote.scp_args)
+            .args(files)
+            .arg(format!("{}:{}/", self.remote.host, remote_dir));
+        run_checked(scp, || {
+            format!(
+                "copying {} file(s) to '{}' on {}",
+                files.len(),
+                remote_dir,
+                self.remote.host
+            )
+        })
+    }
+}
+
+impl fmt::Debug for RemoteRunner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RemoteRunner")
+            .field("remote", &self.remote)
+            .field("path_mapper", &self.path_mapper)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for RemoteRunner {
+    fn eq(&self, other: &Self) -> bool {
+        self.remote == other.remote && self.path_mapper == other.path_mapper
+    }
+}
+
+impl Eq for RemoteRunner {}
+
+/// Returns the shell script that runs a binary on a remote host or device, in the working directory
+/// and with the environment variables of the test mapped to their paths there.
+///
+/// `remote_dir` is the directory that the workspace is mapped to, used if the test has no working
+/// directory.
+pub(crate) fn remote_script<'a>(
+    path_mapper: &PathMapper,
+    remote_dir: &str,
+    cwd: Option<&Path>,
+    envs: impl IntoIterator<Item = (&'a OsStr, Option<&'a OsStr>)>,
+    remote_binary: &str,
+    args: &[String],
+    remote_lib_dir: Option<&str>,
+) -> String {
+    let remote_cwd = cwd
+        .map(|cwd| path_mapper.map_value(&cwd.to_string_lossy()))
+        .unwrap_or_else(|| remote_dir.to_owned());
+
+    let mut env_args: Vec<String> = path_mapper
+        .mapped_env(envs)
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    if let Some(remote_lib_dir) = remote_lib_dir {
+        env_args.push(format!("LD_LIBRARY_PATH={}", remote_lib_dir));
+    }
+
+    let command = env_args
+        .iter()
+        .map(|arg| arg.as_str())
+        .chain(std::iter::once(remote_binary))
+        .chain(args.iter().map(|arg| arg.as_str()))
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let remote_cwd = shell_quote(&remote_cwd);
+    format!(
+        "mkdir -p {} && cd {} && exec env {}",
+        remote_cwd, remote_cwd, command
+    )
+}
+
+pub(crate) fn dylibs_in(dir: &Utf8Path) -> io::Result<Vec<Utf8PathBuf>> {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error),
+    };
+    let mut libs = vec![];
+    for entry in entries {
+        // Skip files with non-UTF-8 names: they aren't produced by Cargo.
+        let path = match Utf8PathBuf::try_from(entry?.path()) {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+        if matches!(path.extension(), Some("so" | "dylib")) && path.is_file() {
+            libs.push(path);
+        }
+    }
+    Ok(libs)
+}
+
+pub(crate) fn run_checked(
+    mut command: Command,
+    description: impl FnOnce() -> String,
+) -> io::Result<()> {
+    let output = command.output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "{} failed ({}): {}",
+        description(),
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )))
+}
+
+// Quotes a word for a POSIX shell.
+pub(crate) fn shell_quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@,+%".contains(c))
+    {
+        return word.to_owned();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn make_remote_script() {
+        let runner = RemoteRunner::new(
+            SshRemote::new("ci@board", "/tmp/nextest"),
+            "/workspace".as_ref(),
+        );
+        let envs = [
+            (
+                OsStr::new("CARGO_MANIFEST_DIR"),
+                Some(OsStr::new("/workspace/my-crate")),
+            ),
+            (OsStr::new("NEXTEST_MESSAGE"), Some(OsStr::new("it's here"))),
+            (
+                OsStr::new("OTHER_DIR"),
+                Some(OsStr::new("/workspace-other")),
+            ),
+            (OsStr::new("REMOVED"), None),
+        ];
+        let script = remote_script(
+            &runner.path_mapper,
+            &runner.remote.dir,
+            Some(Path::new("/workspace/my-crate")),
+            envs,
+            "/tmp/nextest/.nextest/bin/my_test-1234",
+            &["--exact".to_owned(), "tests::foo bar".to_owned()],
+            Some("/tmp/nextest/.nextest/lib"),
+        );
+        assert_eq!(
+            script,
+            "mkdir -p /tmp/nextest/my-crate && cd /tmp/nextest/my-crate && exec env \
+             CARGO_MANIFEST_DIR=/tmp/nextest/my-crate \
+             'NEXTEST_MESSAGE=it'\\''s here' \
+             OTHER_DIR=/workspace-other \
+             LD_LIBRARY_PATH=/tmp/nextest/.nextest/lib \
+             /tmp/nextest/.nextest/bin/my_test-1234 --exact 'tests::foo bar'"
+        );
+
+        let script = remote_script(
+            &runner.path_mapper,
+            &runner.remote.dir,
+            None,
+            [],
+            "/bin/true",
+            &[],
+            None,
+        );
+        assert_eq!(
+            script,
+            "mkdir -p /tmp/nextest && cd /tmp/nextest && exec env /bin/true"
+        );
+    }
+}
@@ -0,0 +1,289 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Tracking tests across renames, so that data from previous runs still applies to them.
+//!
+//! Nextest uses the [report](crate::run_report) of the previous run for `run --failed` and for
+//! [timing data](crate::timings). Reports identify tests by their binary ID and name, so renaming a
+//! test, a module or a test binary would otherwise discard everything known about the tests
+//! involved.
+//!
+//! Renames are listed in `.config/nextest-renames.toml` in the workspace root, as pairs of test IDs:
+//!
+//! ```toml
+//! # A single test.
+//! [[rename]]
+//! from = "my-crate tests::old_name"
+//! to = "my-crate tests::new_name"
+//!
+//! # All tests in a module: names ending with "::" match every test under the module.
+//! [[rename]]
+//! from = "my-crate old_module::"
+//! to = "my-crate new_module::"
+//!
+//! # All tests in a binary.
+//! [[rename]]
+//! from = "my-crate::old_suite"
+//! to = "my-crate::new_suite"
+//! ```
+//!
+//! Each ID is a binary ID, optionally followed by a space and a test name, just like tests are
+//! displayed in nextest output. Renames are applied in order to the tests in previous reports, so
+//! later renames can build on earlier ones. Entries can be removed once no report from before the
+//! rename is in use.
+
+use crate::errors::RenamesError;
+use camino::Utf8Path;
+use nextest_metadata::RunReportSummary;
+use serde::Deserialize;
+use std::{fs, io};
+
+/// Renames of tests, test modules and test binaries, applied to data from previous runs.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TestRenames {
+    renames: Vec<TestRename>,
+}
+
+impl TestRenames {
+    /// The location of the renames file within the workspace root: `.config/nextest-renames.toml`.
+    pub const RENAMES_PATH: &'static str = ".config/nextest-renames.toml";
+
+    /// Reads renames from `.config/nextest-renames.toml` in the given workspace root.
+    ///
+    /// If the file doesn't exist, no tests are renamed.
+    pub fn load(workspace_root: &Utf8Path) -> Result<Self, RenamesError> {
+        let path = workspace_root.join(Self::RENAMES_PATH);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&path, &contents),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(RenamesError::Read { path, error }),
+        }
+    }
+
+    /// Returns true if no tests are renamed.
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty()
+    }
+
+    /// Returns the current binary ID and name of a test with the given binary ID and name in a
+    /// previous run.
+    pub fn rename(&self, binary_id: &str, name: &str) -> (String, String) {
+        let mut current = (binary_id.to_owned(), name.to_owned());
+        for rename in &self.renames {
+            if let Some(renamed) = rename.apply(&current.0, &current.1) {
+                current = renamed;
+            }
+        }
+        current
+    }
+
+    /// Renames the tests in a report written out by a previous run, keeping them sorted by binary
+    /// ID and name.
+    pub fn apply_to_report(&self, summary: &mut RunReportSummary) {
+        if self.is_empty() {
+            return;
+        }
+        for test in &mut summary.tests {
+            let (binary_id, name) = self.rename(&test.binary_id, &test.name);
+            test.binary_id = binary_id;
+            test.name = name;
+        }
+        summary
+            .tests
+            .sort_by(|a, b| (&a.binary_id, &a.name).cmp(&(&b.binary_id, &b.name)));
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    fn parse(path: &Utf8Path, contents: &str) -> Result<Self, RenamesError> {
+        let file: RenamesFileImpl =
+            toml::from_str(contents).map_err(|error| RenamesError::Parse {
+                path: path.to_owned(),
+                error,
+            })?;
+        let renames = file
+            .rename
+            .into_iter()
+            .map(|rename| {
+                TestRename::new(&rename.from, &rename.to).map_err(|message| RenamesError::Invalid {
+                    path: path.to_owned(),
+                    from: rename.from.clone(),
+                    to: rename.to.clone(),
+                    message,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { renames })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct TestRename {
+    from: TestPattern,
+    to: TestPattern,
+}
+
+impl TestRename {
+    fn new(from: &str, to: &str) -> Result<Self, String> {
+        let from = TestPattern::parse(from)?;
+        let to = TestPattern::parse(to)?;
+        if std::mem::discriminant(&from.name) != std::mem::discriminant(&to.name) {
+            return Err(
+                "both IDs must be binaries, modules (names ending with \"::\") or tests".to_owned(),
+            );
+        }
+        Ok(Self { from, to })
+    }
+
+    // Returns the renamed binary ID and name, or None if this rename doesn't apply.
+    fn apply(&self, binary_id: &str, name: &str) -> Option<(String, String)> {
+        if binary_id != self.from.binary_id {
+            return None;
+        }
+        let name = match (&self.from.name, &self.to.name) {
+            (NamePattern::Binary, _) => name.to_owned(),
+            (NamePattern::Module(from), NamePattern::Module(to)) => {
+                format!("{}{}", to, name.strip_prefix(from.as_str())?)
+            }
+            (NamePattern::Test(from), NamePattern::Test(to)) => {
+                if name != from {
+                    return None;
+                }
+                to.clone()
+            }
+            _ => unreachable!("both patterns are checked to be of the same kind"),
+        };
+        Some((self.to.binary_id.clone(), name))
+    }
+}
+
+// A binary ID, optionally followed by a module or test name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct TestPattern {
+    binary_id: String,
+    name: NamePattern,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum NamePattern {
+    // Every test in the binary.
+    Binary,
+    // Every test whose name starts with this prefix, which ends with "::".
+    Module(String),
+    // The test with exactly this name.
+    Test(String),
+}
+
+impl TestPattern {
+    pub(crate) fn parse(id: &str) -> Result<Self, String> {
+        let (binary_id, name) = match id.trim().split_once(' ') {
+            Some((binary_id, name)) => (binary_id, Some(name.trim())),
+            None => (id.trim(), None),
+        };
+        if binary_id.is_empty() || name == Some("") {
+            return Err(format!("invalid test ID '{}'", id));
+        }
+        let name = match name {
+            None => NamePattern::Binary,
+            Some(name) if name.ends_with("::") => NamePattern::Module(name.to_owned()),
+            Some(name) => NamePattern::Test(name.to_owned()),
+        };
+        Ok(Self {
+            binary_id: binary_id.to_owned(),
+            name,
+        })
+    }
+
+    pub(crate) fn matches(&self, binary_id: &str, name: &str) -> bool {
+        binary_id == self.binary_id
+            && match &self.name {
+                NamePattern::Binary => true,
+                NamePattern::Module(prefix) => name.starts_with(prefix.as_str()),
+                NamePattern::Test(test_name) => name == test_name,
+            }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RenamesFileImpl {
+    #[serde(default)]
+    rename: Vec<RenameImpl>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RenameImpl {
+    from: String,
+    to: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_renames() {
+        let contents = r#"
+            [[rename]]
+            from = "my-crate tests::old_name"
+            to = "my-crate tests::new_name"
+
+            [[rename]]
+            from = "my-crate old_module::"
+            to = "my-crate new_module::"
+
+            [[rename]]
+            from = "my-crate::old_suite"
+            to = "my-crate::new_suite"
+
+            [[rename]]
+            from = "my-crate::new_suite tests::a"
+            to = "my-crate::new_suite tests::b"
+        "#;
+        let renames =
+            TestRenames::parse(Utf8Path::new("renames.toml"), contents).expect("renames are valid");
+
+        let rename = |binary_id, name| renames.rename(binary_id, name);
+        assert_eq!(
+            rename("my-crate", "tests::old_name"),
+            ("my-crate".to_owned(), "tests::new_name".to_owned())
+        );
+        assert_eq!(
+            rename("my-crate", "tests::old_name_2"),
+            ("my-crate".to_owned(), "tests::old_name_2".to_owned()),
+            "test names must match exactly"
+        );
+        assert_eq!(
+            rename("my-crate", "old_module::inner::test"),
+            ("my-crate".to_owned(), "new_module::inner::test".to_owned())
+        );
+        assert_eq!(
+            rename("my-crate", "old_module_2::test"),
+            ("my-crate".to_owned(), "old_module_2::test".to_owned()),
+            "module prefixes end at a path separator"
+        );
+        assert_eq!(
+            rename("my-crate::old_suite", "tests::a"),
+            ("my-crate::new_suite".to_owned(), "tests::b".to_owned()),
+            "renames are applied in order"
+        );
+        assert_eq!(
+            rename("other-crate", "tests::old_name"),
+            ("other-crate".to_owned(), "tests::old_name".to_owned()),
+            "binary IDs must match"
+        );
+
+        for (from, to) in [
+            ("my-crate", "my-crate tests::a"),
+            ("my-crate tests::", "my-crate tests::a"),
+            ("", "my-crate"),
+        ] {
+            let contents = format!("[[rename]]\nfrom = {:?}\nto = {:?}", from, to);
+            TestRenames::parse(Utf8Path::new("renames.toml"), &contents)
+                .expect_err("rename is invalid");
+        }
+    }
+}
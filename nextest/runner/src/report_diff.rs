@@ -0,0 +1,383 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Comparing the results of two runs.
+//!
+//! `cargo nextest report diff <old> <new>` reads two [run reports](crate::run_report), such as the
+//! report of a run on the main branch and the report of a run on a pull request, and lists the
+//! tests whose results changed between them:
+//!
+//! * tests that newly fail: they fail in the new run, but passed, were skipped or weren't run in
+//!   the old one.
+//! * tests that newly pass: they pass in the new run, and failed in the old one.
+//! * tests that are newly flaky: they only passed after being retried in the new run, but passed
+//!   the first time, were skipped or weren't run in the old one.
+//! * tests that are significantly slower: they passed in both runs, and the last attempt in the new
+//!   run took at least [`SlowdownThreshold::ratio`] times as long as in the old run, and at least
+//!   [`SlowdownThreshold::min`] longer.
+//!
+//! Newly failing and newly flaky tests are regressions, and make the command fail, so that it can
+//! be used as a status check. Slower tests are reported, but aren't regressions, since durations
+//! vary between machines.
+
+use crate::{errors::RunStoreError, helpers::write_test_name, test_list::OutputFormat};
+use nextest_metadata::{
+    ReportDiffSummary, RunReportSummary, TestDiffSummary, TestReportStatus, TestReportSummary,
+};
+use owo_colors::{OwoColorize, Style};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    time::Duration,
+};
+
+/// How much slower a test must be in the new run to be reported as slower.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SlowdownThreshold {
+    ratio: f64,
+    min: Duration,
+}
+
+impl SlowdownThreshold {
+    /// The default ratio between the new and old durations: 1.5, or 50% slower.
+    pub const DEFAULT_RATIO: f64 = 1.5;
+
+    /// The default minimum difference between the new and old durations: 100ms.
+    pub const DEFAULT_MIN: Duration = Duration::from_millis(100);
+
+    /// Creates a new threshold. Tests are reported as slower if their new duration is at least
+    /// `ratio` times their old duration, and at least `min` longer.
+    pub fn new(ratio: f64, min: Duration) -> Self {
+        Self { ratio, min }
+    }
+
+    /// Returns the minimum ratio between the new and old durations.
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Returns the minimum difference between the new and old durations.
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    fn is_slower(&self, old: f64, new: f64) -> bool {
+        new >= old * self.ratio && new - old >= self.min.as_secs_f64()
+    }
+}
+
+impl Default for SlowdownThreshold {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_RATIO, Self::DEFAULT_MIN)
+    }
+}
+
+/// The differences between two run reports.
+#[derive(Clone, Debug)]
+pub struct ReportDiff {
+    summary: ReportDiffSummary,
+    styles: Box<Styles>,
+}
+
+impl ReportDiff {
+    /// Compares the report of a new run against the report of an old one.
+    pub fn new(
+        old: &RunReportSummary,
+        new: &RunReportSummary,
+        threshold: SlowdownThreshold,
+    ) -> Self {
+        let old_tests: HashMap<_, _> = old
+            .tests
+            .iter()
+            .map(|test| (test_key(test), test))
+            .collect();
+
+        let mut summary = ReportDiffSummary {
+            old_run_id: old.metadata.run_id,
+            new_run_id: new.metadata.run_id,
+            newly_failing: vec![],
+            newly_passing: vec![],
+            newly_flaky: vec![],
+            slower: vec![],
+        };
+        for new_test in &new.tests {
+            let old_test = old_tests.get(&test_key(new_test)).copied();
+            let old_status = old_test.map(|test| test.status);
+            let diff = TestDiffSummary {
+                binary_id: new_test.binary_id.clone(),
+                name: new_test.name.clone(),
+                variant: new_test.variant.clone(),
+                old_status,
+                new_status: new_test.status,
+                old_time_taken: old_test.and_then(last_time_taken),
+                new_time_taken: last_time_taken(new_test),
+            };
+
+            let was_success = old_status.is_none_or(|status| status.is_success());
+            match new_test.status {
+                status if !status.is_success() => {
+                    if was_success {
+                        summary.newly_failing.push(diff);
+                    }
+                }
+                TestReportStatus::Skipped => {}
+                status => {
+                    if !was_success {
+                        summary.newly_passing.push(diff);
+                    } else if status == TestReportStatus::Flaky
+                        && old_status != Some(TestReportStatus::Flaky)
+                    {
+                        summary.newly_flaky.push(diff);
+                    } else if old_status.is_some_and(|status| status != TestReportStatus::Skipped) {
+                        if let (Some(old_time), Some(new_time)) =
+                            (diff.old_time_taken, diff.new_time_taken)
+                        {
+                            if threshold.is_slower(old_time, new_time) {
+                                summary.slower.push(diff);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Self {
+            summary,
+            styles: Box::default(),
+        }
+    }
+
+    /// Returns the underlying summary.
+    pub fn summary(&self) -> &ReportDiffSummary {
+        &self.summary
+    }
+
+    /// Returns true if any tests newly failed or became flaky.
+    pub fn has_regressions(&self) -> bool {
+        self.summary.has_regressions()
+    }
+
+    /// Colorizes output.
+    pub fn colorize(&mut self) {
+        self.styles.colorize();
+    }
+
+    /// Writes out the differences in the given format.
+    pub fn write(
+        &self,
+        output_format: OutputFormat,
+        writer: impl Write,
+    ) -> Result<(), RunStoreError> {
+        match output_format {
+            OutputFormat::Plain => self.write_plain(writer).map_err(RunStoreError::Io),
+            OutputFormat::Serializable(format) => format
+                .to_writer(&self.summary, writer)
+                .map_err(RunStoreError::Json),
+        }
+    }
+
+    fn write_plain(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{:>12} run {} with run {}",
+            "Comparing".style(self.styles.count),
+            self.summary.old_run_id,
+            self.summary.new_run_id,
+        )?;
+
+        let sections = [
+            ("NEW FAIL", self.styles.fail, &self.summary.newly_failing),
+            ("NEW PASS", self.styles.pass, &self.summary.newly_passing),
+            ("NEW FLAKY", self.styles.retry, &self.summary.newly_flaky),
+            ("SLOWER", self.styles.slow, &self.summary.slower),
+        ];
+        for (label, style, tests) in sections {
+            for test in tests {
+                self.write_test(label, style, test, &mut writer)?;
+            }
+        }
+
+        writeln!(
+            writer,
+            "{:>12} {} newly failing, {} newly passing, {} newly flaky, {} slower",
+            "Summary".style(self.styles.count),
+            self.summary.newly_failing.len().style(self.styles.count),
+            self.summary.newly_passing.len().style(self.styles.count),
+            self.summary.newly_flaky.len().style(self.styles.count),
+            self.summary.slower.len().style(self.styles.count),
+        )
+    }
+
+    fn write_test(
+        &self,
+        label: &str,
+        style: Style,
+        test: &TestDiffSummary,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        write!(writer, "{:>12} ", label.style(style))?;
+        match test.new_time_taken {
+            Some(time_taken) => write!(writer, "[{:>8.3}s] ", time_taken)?,
+            None => write!(writer, "[         ] ")?,
+        }
+        write!(writer, "{} ", test.binary_id.style(self.styles.binary_id))?;
+        write_test_name(&test.name, self.styles.test_name, &mut writer)?;
+        if let Some(variant) = &test.variant {
+            write!(writer, " [{}]", variant)?;
+        }
+
+        match (test.old_status, test.old_time_taken, test.new_time_taken) {
+            (None, _, _) => writeln!(writer, ": {} (new test)", test.new_status),
+            (Some(old_status), _, _) if old_status != test.new_status => {
+                writeln!(writer, ": {} (was {})", test.new_status, old_status)
+            }
+            (Some(_), Some(old_time), Some(new_time)) => writeln!(
+                writer,
+                ": was {:.3}s, {:.2}x as long",
+                old_time,
+                new_time / old_time
+            ),
+            (Some(_), _, _) => writeln!(writer),
+        }
+    }
+}
+
+fn test_key(test: &TestReportSummary) -> (&str, &str, Option<&str>) {
+    (&test.binary_id, &test.name, test.variant.as_deref())
+}
+
+fn last_time_taken(test: &TestReportSummary) -> Option<f64> {
+    test.attempts.last().map(|attempt| attempt.time_taken)
+}
+
+#[derive(Clone, Debug, Default)]
+struct Styles {
+    count: Style,
+    pass: Style,
+    retry: Style,
+    fail: Style,
+    slow: Style,
+    binary_id: Style,
+    test_name: Style,
+}
+
+impl Styles {
+    fn colorize(&mut self) {
+        self.count = Style::new().bold();
+        self.pass = Style::new().green().bold();
+        self.retry = Style::new().magenta().bold();
+        self.fail = Style::new().red().bold();
+        self.slow = Style::new().yellow().bold();
+        self.binary_id = Style::new().magenta().bold();
+        self.test_name = Style::new().blue().bold();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nextest_metadata::{RunMetadata, TestAttemptSummary};
+    use uuid::Uuid;
+
+    #[test]
+    fn diff_reports() {
+        let report = |run_id: u128, tests: &[(&str, f64, TestReportStatus)]| RunReportSummary {
+            metadata: RunMetadata {
+                run_id: Uuid::from_u128(run_id),
+                nextest_version: "0.1.0".to_owned(),
+                profile_name: "ci".to_owned(),
+                host_triple: None,
+                target_triple: None,
+                invocation: vec![],
+            },
+            success: true,
+            time_taken: 1.0,
+            tests: tests
+                .iter()
+                .map(|&(name, time_taken, status)| TestReportSummary {
+                    binary_id: "my-crate".to_owned(),
+                    name: name.to_owned(),
+                    variant: None,
+                    status,
+                    skip_reason: None,
+                    quarantined: false,
+                    time_taken,
+                    attempts: vec![TestAttemptSummary {
+                        attempt: 1,
+                        status,
+                        time_taken,
+                        stdout_path: None,
+                        stderr_path: None,
+                        attachments: vec![],
+                        abort_reason: None,
+                        core_dump_path: None,
+                        sanitizer_summary: None,
+                        coverage_profile_path: None,
+                        property_test: None,
+                        peak_rss: None,
+                    }],
+                })
+                .collect(),
+        };
+
+        use TestReportStatus::*;
+        let old = report(
+            1,
+            &[
+                ("breaks", 1.0, Pass),
+                ("fixed", 1.0, Fail),
+                ("flakes", 1.0, Pass),
+                ("still-flaky", 1.0, Flaky),
+                ("slows", 1.0, Pass),
+                ("slows-a-bit", 1.0, Pass),
+                ("fast", 0.01, Pass),
+                ("still-broken", 1.0, Timeout),
+            ],
+        );
+        let new = report(
+            2,
+            &[
+                ("added", 1.0, Fail),
+                ("breaks", 1.0, ExecFail),
+                ("fast", 0.05, Pass),
+                ("fixed", 1.0, Pass),
+                ("flakes", 1.0, Flaky),
+                ("slows", 2.0, Pass),
+                ("slows-a-bit", 1.2, Pass),
+                ("still-broken", 1.0, Fail),
+                ("still-flaky", 1.0, Flaky),
+            ],
+        );
+        let diff = ReportDiff::new(&old, &new, SlowdownThreshold::default());
+        let names = |tests: &[TestDiffSummary]| {
+            tests
+                .iter()
+                .map(|test| test.name.clone())
+                .collect::<Vec<_>>()
+        };
+        let summary = diff.summary();
+        assert_eq!(names(&summary.newly_failing), ["added", "breaks"]);
+        assert_eq!(summary.newly_failing[0].old_status, None);
+        assert_eq!(names(&summary.newly_passing), ["fixed"]);
+        assert_eq!(names(&summary.newly_flaky), ["flakes"]);
+        assert_eq!(
+            names(&summary.slower),
+            ["slows"],
+            "tests must be both 1.5x and 100ms slower"
+        );
+        assert!(diff.has_regressions());
+        assert!(!ReportDiff::new(&old, &old, SlowdownThreshold::default()).has_regressions());
+
+        let mut output = vec![];
+        diff.write(OutputFormat::Plain, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        for line in [
+            "NEW FAIL [   1.000s] my-crate added: fail (new test)",
+            "NEW FAIL [   1.000s] my-crate breaks: exec-fail (was pass)",
+            "SLOWER [   2.000s] my-crate slows: was 1.000s, 2.00x as long",
+            "Summary 2 newly failing, 1 newly passing, 1 newly flaky, 1 slower",
+        ] {
+            assert!(output.contains(line), "{:?} not in:\n{}", line, output);
+        }
+    }
+}
@@ -6,20 +6,34 @@
 //! The main structure in this module is [`TestReporter`].
 
 mod aggregator;
+mod diff;
+mod github;
+mod group;
+mod metrics;
+mod progress;
 
 use crate::{
     config::NextestProfile,
-    errors::{StatusLevelParseError, TestOutputDisplayParseError, WriteEventError},
+    disk_usage::format_bytes,
+    errors::{
+        ReporterModeParseError, StatusLevelParseError, TestOutputDisplayParseError, WriteEventError,
+    },
     helpers::write_test_name,
-    reporter::aggregator::EventAggregator,
-    runner::{ExecuteStatus, ExecutionDescription, ExecutionResult, ExecutionStatuses, RunStats},
+    reporter::{aggregator::EventAggregator, progress::ProgressBar},
+    runner::{
+        ExecuteStatus, ExecutionDescription, ExecutionResult, ExecutionStatuses, RunSnapshot,
+        RunStats,
+    },
+    stopwatch::ClockInstant,
+    test_groups::TestGroupHook,
     test_list::{TestInstance, TestList},
 };
 use debug_ignore::DebugIgnore;
-use nextest_metadata::MismatchReason;
+use nextest_metadata::{MismatchReason, RunMetadata};
 use owo_colors::{OwoColorize, Style};
 use serde::Deserialize;
 use std::{
+    cmp::Reverse,
     fmt, io,
     io::Write,
     str::FromStr,
@@ -41,6 +55,11 @@ pub enum TestOutputDisplay {
     /// Show output at the end of execution.
     Final,
 
+    /// Show output on execution completion, framed by headers, but only if no other output was
+    /// shown within the profile's `streamed-output-interval`. Otherwise, show it at the end of the
+    /// run.
+    Streamed,
+
     /// Never show output.
     Never,
 }
@@ -48,14 +67,16 @@ pub enum TestOutputDisplay {
 impl TestOutputDisplay {
     /// String representations of all known variants.
     pub fn variants() -> &'static [&'static str] {
-        &["immediate", "immediate-final", "final", "never"]
+        &["immediate", "immediate-final", "final", "streamed", "never"]
     }
 
     /// Returns true if test output is shown immediately.
     pub fn is_immediate(self) -> bool {
         match self {
             TestOutputDisplay::Immediate | TestOutputDisplay::ImmediateFinal => true,
-            TestOutputDisplay::Final | TestOutputDisplay::Never => false,
+            TestOutputDisplay::Final | TestOutputDisplay::Streamed | TestOutputDisplay::Never => {
+                false
+            }
         }
     }
 
@@ -63,9 +84,17 @@ impl TestOutputDisplay {
     pub fn is_final(self) -> bool {
         match self {
             TestOutputDisplay::Final | TestOutputDisplay::ImmediateFinal => true,
-            TestOutputDisplay::Immediate | TestOutputDisplay::Never => false,
+            TestOutputDisplay::Immediate
+            | TestOutputDisplay::Streamed
+            | TestOutputDisplay::Never => false,
         }
     }
+
+    /// Returns true if test output is shown on completion or at the end of the run, depending on
+    /// when output was last shown.
+    pub fn is_streamed(self) -> bool {
+        self == TestOutputDisplay::Streamed
+    }
 }
 
 impl FromStr for TestOutputDisplay {
@@ -76,6 +105,7 @@ impl FromStr for TestOutputDisplay {
             "immediate" => TestOutputDisplay::Immediate,
             "immediate-final" => TestOutputDisplay::ImmediateFinal,
             "final" => TestOutputDisplay::Final,
+            "streamed" => TestOutputDisplay::Streamed,
             "never" => TestOutputDisplay::Never,
             other => return Err(TestOutputDisplayParseError::new(other)),
         };
@@ -89,6 +119,7 @@ impl fmt::Display for TestOutputDisplay {
             TestOutputDisplay::Immediate => write!(f, "immediate"),
             TestOutputDisplay::ImmediateFinal => write!(f, "immediate-final"),
             TestOutputDisplay::Final => write!(f, "final"),
+            TestOutputDisplay::Streamed => write!(f, "streamed"),
             TestOutputDisplay::Never => write!(f, "never"),
         }
     }
@@ -114,6 +145,12 @@ pub enum StatusLevel {
     /// Output information about slow tests, and all variants above.
     Slow,
 
+    /// Output passing tests that leaked subprocesses, and all variants above.
+    ///
+    /// A test leaks subprocesses if its standard output or standard error are kept open for
+    /// longer than [`LEAK_TIMEOUT`](crate::runner::LEAK_TIMEOUT) after it exits.
+    Leak,
+
     /// Output passing tests in addition to all variants above.
     Pass,
 
@@ -127,7 +164,9 @@ pub enum StatusLevel {
 impl StatusLevel {
     /// Returns string representations of all known variants.
     pub fn variants() -> &'static [&'static str] {
-        &["none", "fail", "retry", "slow", "pass", "skip", "all"]
+        &[
+            "none", "fail", "retry", "slow", "leak", "pass", "skip", "all",
+        ]
     }
 }
 
@@ -140,6 +179,7 @@ impl FromStr for StatusLevel {
             "fail" => StatusLevel::Fail,
             "retry" => StatusLevel::Retry,
             "slow" => StatusLevel::Slow,
+            "leak" => StatusLevel::Leak,
             "pass" => StatusLevel::Pass,
             "skip" => StatusLevel::Skip,
             "all" => StatusLevel::All,
@@ -156,6 +196,7 @@ impl fmt::Display for StatusLevel {
             StatusLevel::Fail => write!(f, "fail"),
             StatusLevel::Retry => write!(f, "retry"),
             StatusLevel::Slow => write!(f, "slow"),
+            StatusLevel::Leak => write!(f, "leak"),
             StatusLevel::Pass => write!(f, "pass"),
             StatusLevel::Skip => write!(f, "skip"),
             StatusLevel::All => write!(f, "all"),
@@ -163,13 +204,73 @@ impl fmt::Display for StatusLevel {
     }
 }
 
+/// The mode the reporter runs in.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ReporterMode {
+    /// Human-readable output only.
+    #[default]
+    Default,
+
+    /// Human-readable output, plus [workflow
+    /// commands](https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions)
+    /// that annotate failing tests in GitHub Actions.
+    Github,
+}
+
+impl ReporterMode {
+    /// String representations of all known variants.
+    pub fn variants() -> &'static [&'static str] {
+        &["default", "github"]
+    }
+
+    /// Returns [`Github`](Self::Github) if running within GitHub Actions, and
+    /// [`Default`](Self::Default) otherwise.
+    pub fn detect() -> Self {
+        match std::env::var("GITHUB_ACTIONS").as_deref() {
+            Ok("true") => ReporterMode::Github,
+            _ => ReporterMode::Default,
+        }
+    }
+}
+
+impl FromStr for ReporterMode {
+    type Err = ReporterModeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "default" => ReporterMode::Default,
+            "github" => ReporterMode::Github,
+            other => return Err(ReporterModeParseError::new(other)),
+        };
+        Ok(val)
+    }
+}
+
+impl fmt::Display for ReporterMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReporterMode::Default => write!(f, "default"),
+            ReporterMode::Github => write!(f, "github"),
+        }
+    }
+}
+
 /// Test reporter builder.
 #[derive(Debug, Default)]
 pub struct TestReporterBuilder {
     no_capture: bool,
+    no_output_processing: bool,
     failure_output: Option<TestOutputDisplay>,
     success_output: Option<TestOutputDisplay>,
+    final_output_lines: Option<usize>,
     status_level: Option<StatusLevel>,
+    final_status_level: Option<StatusLevel>,
+    highlight_diffs: Option<bool>,
+    mode: ReporterMode,
+    run_metadata: Option<RunMetadata>,
+    show_skipped_binaries: bool,
+    progress_bar: bool,
 }
 
 impl TestReporterBuilder {
@@ -182,6 +283,16 @@ impl TestReporterBuilder {
         self
     }
 
+    /// Sets whether captured test output is written out exactly as it was produced.
+    ///
+    /// By default, ANSI escapes are stripped from test output and assertion failures are
+    /// highlighted if [`set_highlight_diffs`](Self::set_highlight_diffs) is enabled. With this set,
+    /// neither happens.
+    pub fn set_no_output_processing(&mut self, no_output_processing: bool) -> &mut Self {
+        self.no_output_processing = no_output_processing;
+        self
+    }
+
     /// Sets the conditions under which test failures are output.
     pub fn set_failure_output(&mut self, failure_output: TestOutputDisplay) -> &mut Self {
         self.failure_output = Some(failure_output);
@@ -194,11 +305,60 @@ impl TestReporterBuilder {
         self
     }
 
+    /// Sets the number of lines of standard output and standard error printed for each test whose
+    /// output is printed again at the end of the run. Only the last lines are printed.
+    pub fn set_final_output_lines(&mut self, final_output_lines: usize) -> &mut Self {
+        self.final_output_lines = Some(final_output_lines);
+        self
+    }
+
     /// Sets the kinds of statuses to output.
     pub fn set_status_level(&mut self, status_level: StatusLevel) -> &mut Self {
         self.status_level = Some(status_level);
         self
     }
+
+    /// Sets the kinds of statuses to list again at the end of the run, after the summary.
+    pub fn set_final_status_level(&mut self, final_status_level: StatusLevel) -> &mut Self {
+        self.final_status_level = Some(final_status_level);
+        self
+    }
+
+    /// Sets whether assertion failures in test output are highlighted.
+    pub fn set_highlight_diffs(&mut self, highlight_diffs: bool) -> &mut Self {
+        self.highlight_diffs = Some(highlight_diffs);
+        self
+    }
+
+    /// Sets the reporter mode.
+    pub fn set_mode(&mut self, mode: ReporterMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets information identifying the run, included in the final summary and the JUnit report.
+    pub fn set_run_metadata(&mut self, run_metadata: RunMetadata) -> &mut Self {
+        self.run_metadata = Some(run_metadata);
+        self
+    }
+
+    /// Sets whether to list the binaries that weren't run because the filter couldn't match any
+    /// tests in them. If false, only the number of such binaries is printed.
+    pub fn set_show_skipped_binaries(&mut self, show_skipped_binaries: bool) -> &mut Self {
+        self.show_skipped_binaries = show_skipped_binaries;
+        self
+    }
+
+    /// Sets whether to draw a progress bar, along with the tests that are currently running, at the
+    /// bottom of the output. This should only be set if the output is a terminal.
+    ///
+    /// The progress bar is refreshed on the events produced at the
+    /// [`progress_interval`](TestReporter::progress_interval) of the reporter. It isn't drawn in
+    /// no-capture mode, since tests write to the terminal directly.
+    pub fn set_progress_bar(&mut self, progress_bar: bool) -> &mut Self {
+        self.progress_bar = progress_bar;
+        self
+    }
 }
 
 impl TestReporterBuilder {
@@ -214,7 +374,7 @@ impl TestReporterBuilder {
             .map(|(_, info)| info.binary_id.len())
             .max()
             .unwrap_or_default();
-        let aggregator = EventAggregator::new(profile);
+        let aggregator = EventAggregator::new(profile, self.run_metadata.clone());
 
         let status_level = self.status_level.unwrap_or_else(|| profile.status_level());
         let status_level = match self.no_capture {
@@ -222,6 +382,9 @@ impl TestReporterBuilder {
             true => status_level.max(StatusLevel::Pass),
             false => status_level,
         };
+        let final_status_level = self
+            .final_status_level
+            .unwrap_or_else(|| profile.final_status_level());
         // failure_output and success_output are meaningless if the runner isn't capturing any
         // output.
         let failure_output = match self.no_capture {
@@ -237,15 +400,37 @@ impl TestReporterBuilder {
                 .unwrap_or_else(|| profile.success_output()),
         };
 
+        let final_output_lines = self
+            .final_output_lines
+            .or_else(|| profile.final_output_lines());
+
+        let highlight_diffs = self
+            .highlight_diffs
+            .unwrap_or_else(|| profile.highlight_diffs());
+
         TestReporter {
             status_level,
+            final_status_level,
             failure_output,
             success_output,
+            final_output_lines,
+            streamed_output_interval: profile.streamed_output_interval(),
+            highlight_diffs,
+            no_output_processing: self.no_output_processing,
+            run_metadata: self.run_metadata.clone(),
+            show_skipped_binaries: self.show_skipped_binaries,
             no_capture: self.no_capture,
+            mode: self.mode,
             binary_id_width,
             styles,
             cancel_status: None,
             final_outputs: DebugIgnore(vec![]),
+            last_streamed: None,
+            final_statuses: DebugIgnore(vec![]),
+            slow_tests: DebugIgnore(vec![]),
+            overflowed: DebugIgnore(vec![]),
+            disk_usage: DebugIgnore(vec![]),
+            progress_bar: (self.progress_bar && !self.no_capture).then(ProgressBar::new),
             metadata_reporter: aggregator,
         }
     }
@@ -254,9 +439,17 @@ impl TestReporterBuilder {
 /// Functionality to report test results to stderr and JUnit
 pub struct TestReporter<'a> {
     status_level: StatusLevel,
+    final_status_level: StatusLevel,
     failure_output: TestOutputDisplay,
     success_output: TestOutputDisplay,
+    final_output_lines: Option<usize>,
+    streamed_output_interval: Duration,
+    highlight_diffs: bool,
+    no_output_processing: bool,
+    run_metadata: Option<RunMetadata>,
+    show_skipped_binaries: bool,
     no_capture: bool,
+    mode: ReporterMode,
     binary_id_width: usize,
     styles: Box<Styles>,
 
@@ -264,6 +457,14 @@ pub struct TestReporter<'a> {
     // with factoring out the different reporters below.
     cancel_status: Option<CancelReason>,
     final_outputs: DebugIgnore<Vec<(TestInstance<'a>, ExecuteStatus)>>,
+    // When the output of a test was last printed in streamed mode.
+    last_streamed: Option<ClockInstant>,
+    final_statuses: DebugIgnore<Vec<(TestInstance<'a>, FinalStatus)>>,
+    // Tests that are running and have been reported as slow.
+    slow_tests: DebugIgnore<Vec<TestInstance<'a>>>,
+    overflowed: DebugIgnore<Vec<TestInstance<'a>>>,
+    disk_usage: DebugIgnore<Vec<(TestInstance<'a>, u64)>>,
+    progress_bar: Option<ProgressBar<'a>>,
 
     metadata_reporter: EventAggregator<'a>,
 }
@@ -274,6 +475,14 @@ impl<'a> TestReporter<'a> {
         self.styles.colorize();
     }
 
+    /// Returns the interval at which the runner should produce [`TestEvent::RunProgress`] events,
+    /// if this reporter draws a progress bar.
+    pub fn progress_interval(&self) -> Option<Duration> {
+        self.progress_bar
+            .as_ref()
+            .map(|_| ProgressBar::REFRESH_INTERVAL)
+    }
+
     /// Report a test event.
     pub fn report_event(
         &mut self,
@@ -293,8 +502,20 @@ impl<'a> TestReporter<'a> {
         event: TestEvent<'a>,
         writer: impl Write,
     ) -> Result<(), WriteEventError> {
-        self.write_event_impl(&event, writer)
-            .map_err(WriteEventError::Io)?;
+        if self.progress_bar.is_some() {
+            // Write out the event first, so that the progress bar can be redrawn below it.
+            let mut output = Vec::new();
+            self.write_event_impl(&event, &mut output)
+                .map_err(WriteEventError::Io)?;
+            let progress_bar = self.progress_bar.as_mut().expect("checked above");
+            progress_bar.update(&event);
+            progress_bar
+                .write(&output, &self.styles, writer)
+                .map_err(WriteEventError::Io)?;
+        } else {
+            self.write_event_impl(&event, writer)
+                .map_err(WriteEventError::Io)?;
+        }
         self.metadata_reporter.write_event(event)?;
         Ok(())
     }
@@ -305,7 +526,10 @@ impl<'a> TestReporter<'a> {
         mut writer: impl Write,
     ) -> io::Result<()> {
         match event {
-            TestEvent::RunStarted { test_list } => {
+            TestEvent::RunStarted {
+                test_list,
+                run_count,
+            } => {
                 write!(writer, "{:>12} ", "Starting".style(self.styles.pass))?;
 
                 let count_style = self.styles.count;
@@ -313,16 +537,40 @@ impl<'a> TestReporter<'a> {
                 write!(
                     writer,
                     "{} tests across {} binaries",
-                    test_list.run_count().style(count_style),
+                    run_count.style(count_style),
                     test_list.binary_count().style(count_style),
                 )?;
 
                 let skip_count = test_list.skip_count();
-                if skip_count > 0 {
-                    write!(writer, " ({} skipped)", skip_count.style(count_style))?;
+                let skipped_binary_count = test_list.skipped_binary_count();
+                match (skip_count, skipped_binary_count) {
+                    (0, 0) => {}
+                    (skip_count, 0) => {
+                        write!(writer, " ({} skipped)", skip_count.style(count_style))?;
+                    }
+                    (skip_count, skipped_binary_count) => {
+                        write!(
+                            writer,
+                            " ({} skipped; {}/{} binaries skipped)",
+                            skip_count.style(count_style),
+                            skipped_binary_count.style(count_style),
+                            (test_list.binary_count() + skipped_binary_count).style(count_style),
+                        )?;
+                    }
                 }
 
                 writeln!(writer)?;
+
+                if self.show_skipped_binaries {
+                    for binary_id in test_list.skipped_binaries() {
+                        writeln!(
+                            writer,
+                            "{:>12} {}",
+                            "SKIP BINARY".style(self.styles.skip),
+                            binary_id.style(self.styles.test_list.binary_id),
+                        )?;
+                    }
+                }
             }
             TestEvent::TestStarted { test_instance } => {
                 // In no-capture mode, print out a test start event.
@@ -341,6 +589,9 @@ impl<'a> TestReporter<'a> {
                 test_instance,
                 elapsed,
             } => {
+                if !self.slow_tests.contains(test_instance) {
+                    self.slow_tests.push(*test_instance);
+                }
                 if self.status_level >= StatusLevel::Slow {
                     write!(writer, "{:>12} ", "SLOW".style(self.styles.skip))?;
                     self.write_slow_duration(*elapsed, &mut writer)?;
@@ -370,7 +621,7 @@ impl<'a> TestReporter<'a> {
                         "only failing tests are retried"
                     );
                     if self.failure_output.is_immediate() {
-                        self.write_run_status(test_instance, run_status, true, &mut writer)?;
+                        self.write_run_status(test_instance, run_status, true, None, &mut writer)?;
                     }
 
                     // The final output doesn't show retries.
@@ -381,52 +632,49 @@ impl<'a> TestReporter<'a> {
                 run_statuses,
             } => {
                 let describe = run_statuses.describe();
+                let slow = match self
+                    .slow_tests
+                    .iter()
+                    .position(|slow_test| slow_test == test_instance)
+                {
+                    Some(idx) => {
+                        self.slow_tests.swap_remove(idx);
+                        true
+                    }
+                    None => false,
+                };
+                let final_level = match slow {
+                    true => describe.status_level().min(StatusLevel::Slow),
+                    false => describe.status_level(),
+                };
+                if self.final_status_level >= final_level {
+                    self.final_statuses.push((
+                        *test_instance,
+                        FinalStatus::Finished {
+                            run_statuses: run_statuses.clone(),
+                            slow,
+                        },
+                    ));
+                }
 
-                if self.status_level >= describe.status_level() {
-                    // First, print the status.
-                    let last_status = match describe {
-                        ExecutionDescription::Success {
-                            single_status: run_status,
-                        } => {
-                            write!(writer, "{:>12} ", "PASS".style(self.styles.pass))?;
-                            run_status
-                        }
-                        ExecutionDescription::Flaky { last_status, .. } => {
-                            // Use the skip color to also represent a flaky test.
-                            write!(
-                                writer,
-                                "{:>12} ",
-                                format!("TRY {} PASS", last_status.attempt).style(self.styles.skip)
-                            )?;
-                            last_status
-                        }
-                        ExecutionDescription::Failure { last_status, .. } => {
-                            let status_str = match last_status.result {
-                                ExecutionResult::Fail => "FAIL",
-                                ExecutionResult::ExecFail => "XFAIL",
-                                ExecutionResult::Pass => unreachable!("this is a failing test"),
-                            };
-
-                            if last_status.attempt == 1 {
-                                write!(writer, "{:>12} ", status_str.style(self.styles.fail))?;
-                            } else {
-                                write!(
-                                    writer,
-                                    "{:>12} ",
-                                    format!("TRY {} {}", last_status.attempt, status_str)
-                                        .style(self.styles.fail)
-                                )?;
-                            }
-                            last_status
-                        }
-                    };
-
-                    // Next, print the time taken.
-                    self.write_duration(last_status.time_taken, &mut writer)?;
+                let disk_usage = run_statuses
+                    .iter()
+                    .map(|run_status| run_status.disk_usage)
+                    .max()
+                    .unwrap_or_default();
+                if disk_usage > 0 {
+                    self.disk_usage.push((*test_instance, disk_usage));
+                }
 
-                    // Print the name of the test.
-                    self.write_instance(*test_instance, &mut writer)?;
-                    writeln!(writer)?;
+                if self.status_level >= describe.status_level() {
+                    self.write_status_line(
+                        *test_instance,
+                        &describe,
+                        false,
+                        run_statuses.is_quarantined(),
+                        &mut writer,
+                    )?;
+                    let last_status = run_statuses.last_status();
 
                     // If the test failed to execute, print its output and error status.
                     // (don't print out test failures after Ctrl-C)
@@ -436,26 +684,79 @@ impl<'a> TestReporter<'a> {
                             false => self.failure_output,
                         };
                         if test_output_display.is_immediate() {
-                            self.write_run_status(test_instance, last_status, false, &mut writer)?;
+                            self.write_run_status(
+                                test_instance,
+                                last_status,
+                                false,
+                                None,
+                                &mut writer,
+                            )?;
                         }
                         if test_output_display.is_final() {
                             self.final_outputs
                                 .push((*test_instance, last_status.clone()));
                         }
+                        if test_output_display.is_streamed() {
+                            if self.start_streamed_output(ClockInstant::now()) {
+                                self.write_run_status(
+                                    test_instance,
+                                    last_status,
+                                    false,
+                                    None,
+                                    &mut writer,
+                                )?;
+                                self.write_output_end(test_instance, last_status, &mut writer)?;
+                            } else {
+                                writeln!(
+                                    writer,
+                                    "{:>12} output deferred to the end of the run",
+                                    "",
+                                )?;
+                                self.final_outputs
+                                    .push((*test_instance, last_status.clone()));
+                            }
+                        }
+                    }
+                }
+
+                // Annotations are written out regardless of the status level.
+                if self.mode == ReporterMode::Github
+                    && self.cancel_status < Some(CancelReason::Signal)
+                {
+                    if let ExecutionDescription::Failure { last_status, .. } = describe {
+                        github::write_error_annotation(test_instance, last_status, &mut writer)?;
                     }
                 }
             }
             TestEvent::TestSkipped {
                 test_instance,
-                reason: _reason,
+                reason,
             } => {
+                // Tests that overflowed are listed at the end, regardless of the status level.
+                if *reason == MismatchReason::Overflow {
+                    self.overflowed.push(*test_instance);
+                }
+                if self.final_status_level >= StatusLevel::Skip {
+                    self.final_statuses
+                        .push((*test_instance, FinalStatus::Skipped));
+                }
                 if self.status_level >= StatusLevel::Skip {
-                    write!(writer, "{:>12} ", "SKIP".style(self.styles.skip))?;
-                    // same spacing [   0.034s]
-                    write!(writer, "[         ] ")?;
-
-                    self.write_instance(*test_instance, &mut writer)?;
-                    writeln!(writer)?;
+                    self.write_skip_line(*test_instance, &mut writer)?;
+                }
+            }
+            TestEvent::TestGroupHookFailed { group, hook, error } => {
+                let label = match hook {
+                    TestGroupHook::Setup => "SETUP FAIL",
+                    TestGroupHook::Teardown => "TEARDOWN FAIL",
+                };
+                write!(writer, "{:>12} ", label.style(self.styles.fail))?;
+                writeln!(
+                    writer,
+                    "[         ] test group {}",
+                    group.style(self.styles.count)
+                )?;
+                for line in error.lines() {
+                    writeln!(writer, "{:>12}   {}", "", line)?;
                 }
             }
             TestEvent::RunBeginCancel { running, reason } => {
@@ -475,6 +776,12 @@ impl<'a> TestReporter<'a> {
                     running.style(self.styles.count)
                 )?;
             }
+            TestEvent::RunSnapshot { snapshot } => {
+                self.write_snapshot(snapshot, &mut writer)?;
+            }
+            TestEvent::RunProgress { .. } => {
+                // This is only used to refresh the progress bar.
+            }
 
             TestEvent::RunFinished {
                 start_time: _start_time,
@@ -484,13 +791,30 @@ impl<'a> TestReporter<'a> {
                         initial_run_count,
                         final_run_count,
                         passed,
+                        runtime_skipped,
                         flaky,
                         failed,
                         exec_failed,
+                        disk_quota_exceeded,
+                        timed_out,
                         skipped,
+                        overflowed,
+                        timing_predictions,
+                        timing_misses,
+                        skipped_binaries,
+                        group_hooks_failed,
+                        quarantined,
+                        expected_failures,
+                        unexpected_passes,
                     },
             } => {
-                let summary_style = if *failed > 0 || *exec_failed > 0 {
+                let summary_style = if *failed > 0
+                    || *exec_failed > 0
+                    || *disk_quota_exceeded > 0
+                    || *timed_out > 0
+                    || *unexpected_passes > 0
+                    || *group_hooks_failed > 0
+                {
                     self.styles.fail
                 } else {
                     self.styles.pass
@@ -524,6 +848,15 @@ impl<'a> TestReporter<'a> {
                 }
                 write!(writer, ", ")?;
 
+                if *runtime_skipped > 0 {
+                    write!(
+                        writer,
+                        "{} {}, ",
+                        runtime_skipped.style(self.styles.count),
+                        "skipped at runtime".style(self.styles.skip),
+                    )?;
+                }
+
                 if *failed > 0 {
                     write!(
                         writer,
@@ -542,6 +875,51 @@ impl<'a> TestReporter<'a> {
                     )?;
                 }
 
+                if *disk_quota_exceeded > 0 {
+                    write!(
+                        writer,
+                        "{} {}, ",
+                        disk_quota_exceeded.style(self.styles.count),
+                        "disk quota exceeded".style(self.styles.fail),
+                    )?;
+                }
+
+                if *timed_out > 0 {
+                    write!(
+                        writer,
+                        "{} {}, ",
+                        timed_out.style(self.styles.count),
+                        "timed out".style(self.styles.fail),
+                    )?;
+                }
+
+                if *unexpected_passes > 0 {
+                    write!(
+                        writer,
+                        "{} {}, ",
+                        unexpected_passes.style(self.styles.count),
+                        "passed unexpectedly".style(self.styles.fail),
+                    )?;
+                }
+
+                if *expected_failures > 0 {
+                    write!(
+                        writer,
+                        "{} {}, ",
+                        expected_failures.style(self.styles.count),
+                        "failed as expected".style(self.styles.pass),
+                    )?;
+                }
+
+                if *quarantined > 0 {
+                    write!(
+                        writer,
+                        "{} {}, ",
+                        quarantined.style(self.styles.count),
+                        "quarantined".style(self.styles.skip),
+                    )?;
+                }
+
                 write!(
                     writer,
                     "{} {}",
@@ -549,14 +927,137 @@ impl<'a> TestReporter<'a> {
                     "skipped".style(self.styles.skip),
                 )?;
 
+                if *overflowed > 0 {
+                    write!(
+                        writer,
+                        ", {} {}",
+                        overflowed.style(self.styles.count),
+                        "overflowed".style(self.styles.skip),
+                    )?;
+                }
+
+                if *skipped_binaries > 0 {
+                    write!(
+                        writer,
+                        ", {} {}",
+                        skipped_binaries.style(self.styles.count),
+                        "binaries skipped".style(self.styles.skip),
+                    )?;
+                }
+
+                if *group_hooks_failed > 0 {
+                    write!(
+                        writer,
+                        ", {} {}",
+                        group_hooks_failed.style(self.styles.count),
+                        "group hooks failed".style(self.styles.fail),
+                    )?;
+                }
+
                 writeln!(writer)?;
 
+                if let Some(run_metadata) = &self.run_metadata {
+                    write!(
+                        writer,
+                        "{:>12} {} (nextest {}, profile {}",
+                        "Run ID".style(summary_style),
+                        run_metadata.run_id,
+                        run_metadata.nextest_version,
+                        run_metadata.profile_name.style(self.styles.count),
+                    )?;
+                    if let Some(triple) = run_metadata
+                        .target_triple
+                        .as_ref()
+                        .or(run_metadata.host_triple.as_ref())
+                    {
+                        write!(writer, ", {}", triple)?;
+                    }
+                    writeln!(writer, ")")?;
+                }
+
+                for (test_instance, final_status) in self.final_statuses.iter() {
+                    match final_status {
+                        FinalStatus::Finished { run_statuses, slow } => {
+                            self.write_status_line(
+                                *test_instance,
+                                &run_statuses.describe(),
+                                *slow,
+                                run_statuses.is_quarantined(),
+                                &mut writer,
+                            )?;
+                        }
+                        FinalStatus::Skipped => {
+                            self.write_skip_line(*test_instance, &mut writer)?;
+                        }
+                    }
+                }
+
+                if *timing_misses > 0 {
+                    writeln!(
+                        writer,
+                        "{:>12} no timing data for {}/{} tests, used the default estimate",
+                        "TIMINGS".style(self.styles.skip),
+                        timing_misses.style(self.styles.count),
+                        timing_predictions.style(self.styles.count),
+                    )?;
+                }
+
+                if !self.overflowed.is_empty() {
+                    self.write_overflowed(&mut writer)?;
+                }
+
+                if !self.disk_usage.is_empty() {
+                    self.write_disk_usage(&mut writer)?;
+                }
+
                 // Don't print out test failures if canceled due to Ctrl-C.
                 if self.status_level >= StatusLevel::Fail
                     && self.cancel_status < Some(CancelReason::Signal)
                 {
-                    for (test_instance, run_status) in &*self.final_outputs {
-                        self.write_run_status(test_instance, run_status, false, &mut writer)?;
+                    // Failures with identical output are printed once, followed by the list of
+                    // other tests that failed the same way.
+                    let fingerprints =
+                        self.final_outputs
+                            .iter()
+                            .map(|(test_instance, run_status)| {
+                                (!run_status.result.is_success()).then(|| {
+                                    group::output_fingerprint(
+                                        test_instance.name,
+                                        run_status.stdout(),
+                                        run_status.stderr(),
+                                    )
+                                })
+                            });
+                    let failed_count = self
+                        .final_outputs
+                        .iter()
+                        .filter(|(_, run_status)| !run_status.result.is_success())
+                        .count();
+                    if failed_count > 0 {
+                        writeln!(
+                            writer,
+                            "{:>12} output of {} failed {}:",
+                            "FAILURES".style(self.styles.fail),
+                            failed_count.style(self.styles.count),
+                            if failed_count == 1 { "test" } else { "tests" },
+                        )?;
+                    }
+                    for indexes in group::group_by_fingerprint(fingerprints) {
+                        let (test_instance, run_status) = &self.final_outputs[indexes[0]];
+                        self.write_run_status(
+                            test_instance,
+                            run_status,
+                            false,
+                            self.final_output_lines,
+                            &mut writer,
+                        )?;
+                        if indexes.len() > 1 {
+                            let others: Vec<_> = indexes[1..]
+                                .iter()
+                                .map(|&idx| self.final_outputs[idx].0)
+                                .collect();
+                            self.write_identical_failures(&others, &mut writer)?;
+                        }
                     }
                 }
             }
@@ -565,6 +1066,182 @@ impl<'a> TestReporter<'a> {
         Ok(())
     }
 
+    fn write_snapshot(&self, snapshot: &RunSnapshot<'a>, mut writer: impl Write) -> io::Result<()> {
+        let count_style = self.styles.count;
+
+        write!(writer, "{:>12} ", "Snapshot".style(self.styles.pass))?;
+        self.write_duration(snapshot.elapsed, &mut writer)?;
+        writeln!(
+            writer,
+            "{} running, {} queued, {} finished ({} passed, {} failed, {} skipped)",
+            snapshot.running.len().style(count_style),
+            snapshot.queued.style(count_style),
+            snapshot.run_stats.final_run_count.style(count_style),
+            snapshot.run_stats.passed.style(count_style),
+            (snapshot.run_stats.failed
+                + snapshot.run_stats.exec_failed
+                + snapshot.run_stats.disk_quota_exceeded
+                + snapshot.run_stats.timed_out)
+                .style(count_style),
+            snapshot.run_stats.skipped.style(count_style),
+        )?;
+
+        for running in &snapshot.running {
+            let status_str = if running.attempt > 1 {
+                format!("TRY {} RUNNING", running.attempt)
+            } else {
+                "RUNNING".to_owned()
+            };
+            write!(writer, "{:>12} ", status_str.style(self.styles.skip))?;
+            self.write_duration(running.elapsed, &mut writer)?;
+            self.write_instance(running.test_instance, &mut writer)?;
+            writeln!(writer)?;
+        }
+
+        for finished in &snapshot.recently_finished {
+            let (status_str, style) = match finished.result {
+                ExecutionResult::Pass => ("RECENT PASS", self.styles.pass),
+                ExecutionResult::Fail => ("RECENT FAIL", self.styles.fail),
+                ExecutionResult::ExecFail => ("RECENT XFAIL", self.styles.fail),
+                ExecutionResult::DiskQuotaExceeded => ("RECENT QUOTA", self.styles.fail),
+                ExecutionResult::Timeout => ("RECENT TIMEOUT", self.styles.fail),
+                ExecutionResult::ExpectedFail => ("RECENT EXPECT FAIL", self.styles.pass),
+                ExecutionResult::UnexpectedPass => ("RECENT UNEXP PASS", self.styles.fail),
+                ExecutionResult::Skip => ("RECENT SKIP", self.styles.skip),
+            };
+            write!(writer, "{:>12} ", status_str.style(style))?;
+            self.write_duration(finished.time_taken, &mut writer)?;
+            self.write_instance(finished.test_instance, &mut writer)?;
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the line describing how a test finished. If `slow` is true, passing tests are shown
+    /// as slow instead. If `quarantined` is true, failures are shown as not failing the run.
+    fn write_status_line(
+        &self,
+        test_instance: TestInstance<'a>,
+        describe: &ExecutionDescription<'_>,
+        slow: bool,
+        quarantined: bool,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let last_status = match describe {
+            ExecutionDescription::Success {
+                single_status: run_status,
+            } => {
+                let (status_str, style) = if run_status.leaked {
+                    ("LEAK", self.styles.skip)
+                } else if run_status.result == ExecutionResult::ExpectedFail {
+                    ("EXPECT FAIL", self.styles.pass)
+                } else if slow {
+                    ("SLOW", self.styles.skip)
+                } else {
+                    ("PASS", self.styles.pass)
+                };
+                write!(writer, "{:>12} ", status_str.style(style))?;
+                run_status
+            }
+            ExecutionDescription::Flaky { last_status, .. } => {
+                let status_str = match last_status.result {
+                    ExecutionResult::ExpectedFail => "EXPECT FAIL",
+                    _ => "PASS",
+                };
+                // Use the skip color to also represent a flaky test.
+                write!(
+                    writer,
+                    "{:>12} ",
+                    format!("TRY {} {}", last_status.attempt, status_str).style(self.styles.skip)
+                )?;
+                last_status
+            }
+            ExecutionDescription::Failure { last_status, .. } => {
+                let status_str = match last_status.result {
+                    ExecutionResult::Fail => match &last_status.sanitizer_report {
+                        Some(report) => report.sanitizer.label(),
+                        None => "FAIL",
+                    },
+                    ExecutionResult::ExecFail => "XFAIL",
+                    ExecutionResult::DiskQuotaExceeded => "QUOTA",
+                    ExecutionResult::Timeout => "TIMEOUT",
+                    ExecutionResult::UnexpectedPass => "UNEXP PASS",
+                    ExecutionResult::Pass
+                    | ExecutionResult::Skip
+                    | ExecutionResult::ExpectedFail => {
+                        unreachable!("this is a failing test")
+                    }
+                };
+
+                // Quarantined failures don't fail the run, so use the skip color for them.
+                let style = match quarantined {
+                    true => self.styles.skip,
+                    false => self.styles.fail,
+                };
+                if last_status.attempt == 1 {
+                    write!(writer, "{:>12} ", status_str.style(style))?;
+                } else {
+                    write!(
+                        writer,
+                        "{:>12} ",
+                        format!("TRY {} {}", last_status.attempt, status_str).style(style)
+                    )?;
+                }
+                last_status
+            }
+            ExecutionDescription::Skipped { last_status, .. } => {
+                write!(writer, "{:>12} ", "SKIP".style(self.styles.skip))?;
+                last_status
+            }
+        };
+
+        // Next, print the time taken.
+        self.write_duration(last_status.time_taken, &mut writer)?;
+
+        // Print the name of the test.
+        self.write_instance(test_instance, &mut writer)?;
+        if quarantined && matches!(describe, ExecutionDescription::Failure { .. }) {
+            write!(writer, " ({})", "quarantined".style(self.styles.skip))?;
+        }
+        if let Some(skip_reason) = last_status
+            .skip_reason
+            .as_deref()
+            .filter(|skip_reason| !skip_reason.is_empty())
+        {
+            write!(writer, ": {}", skip_reason.style(self.styles.skip))?;
+        }
+        if let ExecutionDescription::Failure { .. } = describe {
+            if let Some(report) = &last_status.sanitizer_report {
+                write!(writer, ": {}", report.summary.style(self.styles.fail))?;
+            } else if let Some(minimal_input) = last_status
+                .property_test_failure
+                .as_ref()
+                .and_then(|failure| failure.minimal_input.as_deref())
+            {
+                write!(
+                    writer,
+                    ": minimal failing input: {}",
+                    minimal_input.style(self.styles.fail)
+                )?;
+            }
+        }
+        writeln!(writer)
+    }
+
+    fn write_skip_line(
+        &self,
+        test_instance: TestInstance<'a>,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        write!(writer, "{:>12} ", "SKIP".style(self.styles.skip))?;
+        // same spacing [   0.034s]
+        write!(writer, "[         ] ")?;
+
+        self.write_instance(test_instance, &mut writer)?;
+        writeln!(writer)
+    }
+
     fn write_instance(&self, instance: TestInstance<'a>, mut writer: impl Write) -> io::Result<()> {
         write!(
             writer,
@@ -576,7 +1253,11 @@ impl<'a> TestReporter<'a> {
             width = self.binary_id_width
         )?;
 
-        write_test_name(instance.name, self.styles.test_list.test_name, writer)
+        write_test_name(instance.name, self.styles.test_list.test_name, &mut writer)?;
+        if let Some(variant) = instance.variant {
+            write!(writer, " {}", variant)?;
+        }
+        Ok(())
     }
 
     fn write_duration(&self, duration: Duration, mut writer: impl Write) -> io::Result<()> {
@@ -601,6 +1282,7 @@ impl<'a> TestReporter<'a> {
         test_instance: &TestInstance<'a>,
         run_status: &ExecuteStatus,
         is_retry: bool,
+        max_lines: Option<usize>,
         mut writer: impl Write,
     ) -> io::Result<()> {
         let (header_style, _output_style) = if is_retry {
@@ -619,14 +1301,7 @@ impl<'a> TestReporter<'a> {
             self.write_instance(*test_instance, &mut writer)?;
             writeln!(writer, "{}", " ---".style(header_style))?;
 
-            {
-                // Strip ANSI escapes from the output in case some test framework doesn't check for
-                // ttys before producing color output.
-                // TODO: apply output style once https://github.com/jam1garner/owo-colors/issues/41 is
-                // fixed
-                let mut no_color = strip_ansi_escapes::Writer::new(&mut writer);
-                no_color.write_all(run_status.stdout())?;
-            }
+            self.write_test_output(run_status.stdout(), max_lines, &mut writer)?;
         }
 
         if !run_status.stderr().is_empty() {
@@ -637,19 +1312,176 @@ impl<'a> TestReporter<'a> {
             self.write_instance(*test_instance, &mut writer)?;
             writeln!(writer, "{}", " ---".style(header_style))?;
 
-            {
-                // Strip ANSI escapes from the output in case some test framework doesn't check for
-                // ttys before producing color output.
-                // TODO: apply output style once https://github.com/jam1garner/owo-colors/issues/41 is
-                // fixed
+            self.write_test_output(run_status.stderr(), max_lines, &mut writer)?;
+        }
+
+        for attachment in &run_status.attachments {
+            write!(writer, "\n{}", "--- ".style(header_style))?;
+            self.write_attempt(run_status, header_style, &mut writer)?;
+            // The spacing is to align test instances.
+            write!(writer, "{}", " LOG:                ".style(header_style))?;
+            self.write_instance(*test_instance, &mut writer)?;
+            writeln!(
+                writer,
+                "{}",
+                format!(" ({}) ---", attachment.path).style(header_style)
+            )?;
+
+            if self.no_output_processing {
+                writer.write_all(&attachment.contents)?;
+            } else {
                 let mut no_color = strip_ansi_escapes::Writer::new(&mut writer);
-                no_color.write_all(run_status.stderr())?;
+                no_color.write_all(&attachment.contents)?;
             }
         }
 
         writeln!(writer)
     }
 
+    /// Returns true if the output of a test finishing at `now` should be printed in streamed mode,
+    /// recording that it was.
+    fn start_streamed_output(&mut self, now: ClockInstant) -> bool {
+        let allowed = self.last_streamed.is_none_or(|last_streamed| {
+            now.saturating_duration_since(last_streamed) >= self.streamed_output_interval
+        });
+        if allowed {
+            self.last_streamed = Some(now);
+        }
+        allowed
+    }
+
+    /// Marks the end of the output of a test printed in streamed mode, so that it's clear where
+    /// the output of each test ends.
+    fn write_output_end(
+        &self,
+        test_instance: &TestInstance<'a>,
+        run_status: &ExecuteStatus,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let header_style = if run_status.result.is_success() {
+            self.styles.pass
+        } else {
+            self.styles.fail
+        };
+        write!(writer, "{}", "--- ".style(header_style))?;
+        self.write_attempt(run_status, header_style, &mut writer)?;
+        // The spacing is to align test instances.
+        write!(writer, "{}", " END OF OUTPUT:      ".style(header_style))?;
+        self.write_instance(*test_instance, &mut writer)?;
+        writeln!(writer, "{}", " ---".style(header_style))
+    }
+
+    fn write_overflowed(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{:>12} {} {} not run because the maximum duration would have been exceeded:",
+            "OVERFLOW".style(self.styles.skip),
+            self.overflowed.len().style(self.styles.count),
+            if self.overflowed.len() == 1 {
+                "test"
+            } else {
+                "tests"
+            },
+        )?;
+        for test_instance in self.overflowed.iter() {
+            write!(writer, "{:>12} ", "")?;
+            self.write_instance(*test_instance, &mut writer)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_disk_usage(&self, mut writer: impl Write) -> io::Result<()> {
+        // The number of tests listed.
+        const DISK_USAGE_COUNT: usize = 5;
+
+        let mut disk_usage: Vec<_> = self.disk_usage.iter().collect();
+        // Largest first. The sort is stable, so ties stay in the order tests finished in.
+        disk_usage.sort_by_key(|(_, usage)| Reverse(*usage));
+        disk_usage.truncate(DISK_USAGE_COUNT);
+
+        writeln!(
+            writer,
+            "{:>12} tests that wrote the most to disk:",
+            "DISK USAGE".style(self.styles.skip),
+        )?;
+        for (test_instance, usage) in disk_usage {
+            write!(
+                writer,
+                "{:>12} ",
+                format_bytes(*usage).style(self.styles.count)
+            )?;
+            self.write_instance(*test_instance, &mut writer)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_identical_failures(
+        &self,
+        test_instances: &[TestInstance<'a>],
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{:>12} {} more {} with identical output:",
+            "SAME".style(self.styles.fail),
+            test_instances.len().style(self.styles.count),
+            if test_instances.len() == 1 {
+                "test"
+            } else {
+                "tests"
+            },
+        )?;
+        for test_instance in test_instances {
+            write!(writer, "{:>12} ", "")?;
+            self.write_instance(*test_instance, &mut writer)?;
+            writeln!(writer)?;
+        }
+        writeln!(writer)
+    }
+
+    fn write_test_output(
+        &self,
+        output: &[u8],
+        max_lines: Option<usize>,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let output = match max_lines {
+            Some(max_lines) => {
+                let (tail, omitted) = output_tail(output, max_lines);
+                if omitted > 0 {
+                    writeln!(
+                        writer,
+                        "{}",
+                        format!("({} earlier lines omitted)", omitted).style(self.styles.skip)
+                    )?;
+                }
+                tail
+            }
+            None => output,
+        };
+
+        if self.no_output_processing {
+            return writer.write_all(output);
+        }
+
+        // Strip ANSI escapes from the output in case some test framework doesn't check for ttys
+        // before producing color output.
+        // TODO: apply output style once https://github.com/jam1garner/owo-colors/issues/41 is
+        // fixed
+        if self.highlight_diffs {
+            let stripped = strip_ansi_escapes::strip(output)?;
+            match std::str::from_utf8(&stripped) {
+                Ok(stripped) => diff::write_highlighted(stripped, &self.styles.diff, writer),
+                Err(_) => writer.write_all(&stripped),
+            }
+        } else {
+            let mut no_color = strip_ansi_escapes::Writer::new(&mut writer);
+            no_color.write_all(output)
+        }
+    }
+
     fn write_attempt(
         &self,
         run_status: &ExecuteStatus,
@@ -686,8 +1518,14 @@ pub enum TestEvent<'a> {
     RunStarted {
         /// The list of tests that will be run.
         ///
-        /// The methods on the test list indicate the number of
+        /// The methods on the test list indicate the number of tests and binaries.
         test_list: &'a TestList<'a>,
+
+        /// The number of test instances that will be run.
+        ///
+        /// This may be greater than [`TestList::run_count`] if tests are expanded through
+        /// multiplex rules.
+        run_count: usize,
     },
 
     // TODO: add events for BinaryStarted and BinaryFinished? May want a slightly different way to
@@ -716,7 +1554,7 @@ pub enum TestEvent<'a> {
         test_instance: TestInstance<'a>,
 
         /// The status of this attempt to run the test. Will never be success.
-        run_status: ExecuteStatus,
+        run_status: Box<ExecuteStatus>,
     },
 
     /// A test finished running.
@@ -737,6 +1575,20 @@ pub enum TestEvent<'a> {
         reason: MismatchReason,
     },
 
+    /// The setup or teardown command of a test group failed.
+    ///
+    /// See [`test_groups`](crate::test_groups) for more.
+    TestGroupHookFailed {
+        /// The name of the test group.
+        group: &'a str,
+
+        /// The command that failed.
+        hook: TestGroupHook,
+
+        /// A description of the failure, including the output of the command.
+        error: String,
+    },
+
     /// A cancellation notice was received.
     RunBeginCancel {
         /// The number of tests still running.
@@ -746,6 +1598,19 @@ pub enum TestEvent<'a> {
         reason: CancelReason,
     },
 
+    /// A snapshot of the state of the run was requested.
+    RunSnapshot {
+        /// The state of the run at the time the snapshot was requested.
+        snapshot: RunSnapshot<'a>,
+    },
+
+    /// A periodic snapshot of the state of the run, produced if a progress interval was set
+    /// through [`TestRunnerBuilder::set_progress_interval`](crate::runner::TestRunnerBuilder::set_progress_interval).
+    RunProgress {
+        /// The state of the run at the time of the snapshot.
+        snapshot: RunSnapshot<'a>,
+    },
+
     /// The test run finished.
     RunFinished {
         /// The time at which the run was started.
@@ -773,6 +1638,38 @@ pub enum CancelReason {
     Signal,
 }
 
+/// A test status listed again at the end of the run, based on the final status level.
+enum FinalStatus {
+    Finished {
+        run_statuses: ExecutionStatuses,
+        slow: bool,
+    },
+    Skipped,
+}
+
+/// Returns the last `max_lines` lines of the output, along with the number of lines before them.
+fn output_tail(output: &[u8], max_lines: usize) -> (&[u8], usize) {
+    // A trailing newline ends the last line rather than starting another one.
+    let trimmed = output.strip_suffix(b"\n").unwrap_or(output);
+    if trimmed.is_empty() {
+        return (output, 0);
+    }
+    let line_starts: Vec<_> = std::iter::once(0)
+        .chain(
+            trimmed
+                .iter()
+                .enumerate()
+                .filter(|(_, &byte)| byte == b'\n')
+                .map(|(idx, _)| idx + 1),
+        )
+        .collect();
+    let omitted = line_starts.len().saturating_sub(max_lines);
+    match line_starts.get(omitted) {
+        Some(&start) => (&output[start..], omitted),
+        None => (&output[output.len()..], omitted),
+    }
+}
+
 #[derive(Debug, Default)]
 struct Styles {
     count: Style,
@@ -783,6 +1680,7 @@ struct Styles {
     retry_output: Style,
     fail_output: Style,
     skip: Style,
+    diff: diff::DiffStyles,
     test_list: crate::test_list::Styles,
 }
 
@@ -796,6 +1694,7 @@ impl Styles {
         self.retry_output = Style::new().magenta();
         self.fail_output = Style::new().magenta();
         self.skip = Style::new().yellow().bold();
+        self.diff.colorize();
         self.test_list.colorize();
     }
 }
@@ -834,5 +1733,100 @@ mod tests {
             StatusLevel::Pass,
             "status level is pass, overriding other settings"
         );
+        assert_eq!(
+            reporter.final_status_level,
+            StatusLevel::None,
+            "final status level is unaffected"
+        );
+    }
+
+    #[test]
+    fn output_processing() {
+        let output = b"\x1b[31mcolored\x1b[0m\n  left: 1\n right: 2\n";
+        let test_list = TestList::empty();
+        let config = NextestConfig::default_config("/fake/dir");
+        let profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        let write_output = |no_output_processing: bool| {
+            let mut builder = TestReporterBuilder::default();
+            builder.set_no_output_processing(no_output_processing);
+            let mut reporter = builder.build(&test_list, &profile);
+            reporter.colorize();
+            let mut written = vec![];
+            reporter
+                .write_test_output(output, None, &mut written)
+                .unwrap();
+            written
+        };
+
+        let processed = write_output(false);
+        assert!(
+            !processed.starts_with(b"\x1b[31mcolored"),
+            "escapes from the test are stripped"
+        );
+        assert_ne!(
+            strip_ansi_escapes::strip(&processed).unwrap(),
+            processed,
+            "the assertion failure is highlighted"
+        );
+        assert_eq!(write_output(true), output, "output is written as is");
+    }
+
+    #[test]
+    fn streamed_output_rate() {
+        let test_list = TestList::empty();
+        let config = NextestConfig::default_config("/fake/dir");
+        let profile = config.profile(NextestConfig::DEFAULT_PROFILE).unwrap();
+        let mut reporter = TestReporterBuilder::default().build(&test_list, &profile);
+        let interval = reporter.streamed_output_interval;
+        assert_eq!(interval, Duration::from_secs(10), "default interval");
+
+        let start = ClockInstant::now();
+        assert!(
+            reporter.start_streamed_output(start),
+            "first output is streamed"
+        );
+        assert!(
+            !reporter.start_streamed_output(start + interval / 2),
+            "output within the interval is deferred"
+        );
+        assert!(
+            reporter.start_streamed_output(start + interval),
+            "output after the interval is streamed"
+        );
+        assert!(
+            !reporter.start_streamed_output(start + interval + interval / 2),
+            "the interval restarts from the last streamed output"
+        );
+    }
+
+    #[test]
+    fn status_level_order() {
+        let levels: Vec<StatusLevel> = StatusLevel::variants()
+            .iter()
+            .map(|level| level.parse().unwrap())
+            .collect();
+        assert!(
+            levels.windows(2).all(|pair| pair[0] < pair[1]),
+            "variants are listed in increasing order: {:?}",
+            levels
+        );
+        for level in levels {
+            assert_eq!(level.to_string().parse::<StatusLevel>().unwrap(), level);
+        }
+    }
+
+    #[test]
+    fn final_output_tail() {
+        let output = b"one\ntwo\nthree\n";
+        assert_eq!(output_tail(output, 2), (&b"two\nthree\n"[..], 1));
+        assert_eq!(output_tail(output, 3), (&output[..], 0));
+        assert_eq!(output_tail(output, 10), (&output[..], 0));
+        assert_eq!(output_tail(output, 0), (&b""[..], 3));
+        assert_eq!(
+            output_tail(b"one\ntwo", 1),
+            (&b"two"[..], 1),
+            "no trailing newline"
+        );
+        assert_eq!(output_tail(b"", 1), (&b""[..], 0));
     }
 }
@@ -6,15 +6,18 @@
 use crate::{
     config::{NextestJunitConfig, NextestProfile},
     errors::{JunitError, WriteEventError},
-    reporter::TestEvent,
+    reporter::{metrics::MetadataMetrics, TestEvent},
     runner::{ExecuteStatus, ExecutionDescription, ExecutionResult},
     test_list::TestInstance,
 };
 use camino::Utf8Path;
 use chrono::{DateTime, FixedOffset, Utc};
 use debug_ignore::DebugIgnore;
-use quick_junit::{NonSuccessKind, Report, TestCase, TestCaseStatus, TestRerun, TestSuite};
-use std::{collections::HashMap, fs::File, time::SystemTime};
+use nextest_metadata::{RunMetadata, SkipReasonSummary};
+use quick_junit::{
+    NonSuccessKind, Property, Report, TestCase, TestCaseStatus, TestRerun, TestSuite,
+};
+use std::{borrow::Cow, collections::HashMap, fs::File, time::SystemTime};
 
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
@@ -23,17 +26,29 @@ pub(crate) struct EventAggregator<'cfg> {
     // TODO: log information in a JSONable report (converting that to XML later) instead of directly
     // writing it to XML
     junit: Option<MetadataJunit<'cfg>>,
+    metrics: Option<MetadataMetrics<'cfg>>,
 }
 
 impl<'cfg> EventAggregator<'cfg> {
-    pub(crate) fn new(profile: &'cfg NextestProfile<'cfg>) -> Self {
+    pub(crate) fn new(
+        profile: &'cfg NextestProfile<'cfg>,
+        run_metadata: Option<RunMetadata>,
+    ) -> Self {
         Self {
             store_dir: profile.store_dir(),
-            junit: profile.junit().map(MetadataJunit::new),
+            junit: profile
+                .junit()
+                .map(|config| MetadataJunit::new(config, run_metadata)),
+            metrics: profile
+                .metrics()
+                .map(|config| MetadataMetrics::new(config, profile.name())),
         }
     }
 
     pub(crate) fn write_event(&mut self, event: TestEvent<'cfg>) -> Result<(), WriteEventError> {
+        if let Some(metrics) = &mut self.metrics {
+            metrics.write_event(&event)?;
+        }
         if let Some(junit) = &mut self.junit {
             junit.write_event(event)?;
         }
@@ -44,13 +59,15 @@ impl<'cfg> EventAggregator<'cfg> {
 #[derive(Clone, Debug)]
 struct MetadataJunit<'cfg> {
     config: NextestJunitConfig<'cfg>,
+    run_metadata: Option<RunMetadata>,
     test_suites: DebugIgnore<HashMap<&'cfg str, TestSuite>>,
 }
 
 impl<'cfg> MetadataJunit<'cfg> {
-    fn new(config: NextestJunitConfig<'cfg>) -> Self {
+    fn new(config: NextestJunitConfig<'cfg>, run_metadata: Option<RunMetadata>) -> Self {
         Self {
             config,
+            run_metadata,
             test_suites: DebugIgnore(HashMap::new()),
         }
     }
@@ -71,7 +88,18 @@ impl<'cfg> MetadataJunit<'cfg> {
                     match run_status.result {
                         ExecutionResult::Fail => (NonSuccessKind::Failure, "test failure"),
                         ExecutionResult::ExecFail => (NonSuccessKind::Error, "execution failure"),
-                        ExecutionResult::Pass => unreachable!("this is a failure status"),
+                        ExecutionResult::DiskQuotaExceeded => {
+                            (NonSuccessKind::Failure, "disk quota exceeded")
+                        }
+                        ExecutionResult::Timeout => (NonSuccessKind::Failure, "test timed out"),
+                        ExecutionResult::UnexpectedPass => {
+                            (NonSuccessKind::Failure, "unexpected pass")
+                        }
+                        ExecutionResult::Pass
+                        | ExecutionResult::Skip
+                        | ExecutionResult::ExpectedFail => {
+                            unreachable!("this is a failure status")
+                        }
                     }
                 }
 
@@ -93,8 +121,22 @@ impl<'cfg> MetadataJunit<'cfg> {
                         let (kind, ty) = kind_ty(first_status);
                         let mut testcase_status = TestCaseStatus::non_success(kind);
                         testcase_status.set_type(ty);
+                        if let Some(report) = &first_status.sanitizer_report {
+                            testcase_status.set_message(report.summary.clone());
+                        } else if let Some(abort_status) = first_status.abort_status {
+                            testcase_status
+                                .set_message(format!("test aborted with {}", abort_status));
+                        }
                         (testcase_status, first_status, retries)
                     }
+                    ExecutionDescription::Skipped {
+                        last_status,
+                        prior_statuses,
+                    } => {
+                        let skip_reason =
+                            SkipReasonSummary::runtime(last_status.skip_reason.as_deref());
+                        (skipped_status(&skip_reason), last_status, prior_statuses)
+                    }
                 };
 
                 for rerun in reruns {
@@ -112,7 +154,7 @@ impl<'cfg> MetadataJunit<'cfg> {
 
                 // TODO: set message/description on testcase_status?
 
-                let mut testcase = TestCase::new(test_instance.name, testcase_status);
+                let mut testcase = TestCase::new(testcase_name(&test_instance), testcase_status);
                 testcase
                     .set_classname(&test_instance.bin_info.binary_id)
                     .set_timestamp(to_datetime(main_status.start_time))
@@ -126,25 +168,33 @@ impl<'cfg> MetadataJunit<'cfg> {
                 if !main_status.result.is_success() {
                     // TODO: use the Arc wrapper, don't clone the system out and system err bytes
                     testcase
-                        .set_system_out_lossy(main_status.stdout())
+                        .set_system_out_lossy(with_attachments(main_status))
                         .set_system_err_lossy(main_status.stderr());
                 }
 
                 testsuite.add_test_case(testcase);
             }
-            TestEvent::TestSkipped { .. } => {
-                // TODO: report skipped tests? causes issues if we want to aggregate runs across
-                // skipped and non-skipped tests. Probably needs to be made configurable.
-
-                // let testsuite = self.testsuite_for(test_instance);
-                //
-                // let mut testcase_status = TestcaseStatus::skipped();
-                // testcase_status.set_message(format!("Skipped: {}", reason));
-                // let testcase = Testcase::new(test_instance.name, testcase_status);
-                //
-                // testsuite.add_testcase(testcase);
+            TestEvent::TestSkipped {
+                test_instance,
+                reason,
+            } => {
+                // Reporting skipped tests causes issues when aggregating runs across skipped and
+                // non-skipped tests, so this can be turned off.
+                if self.config.report_skipped() {
+                    let skip_reason = SkipReasonSummary::from_filter(
+                        reason,
+                        test_instance.test_info.ignore_reason.as_deref(),
+                    );
+                    let mut testcase =
+                        TestCase::new(testcase_name(&test_instance), skipped_status(&skip_reason));
+                    testcase.set_classname(&test_instance.bin_info.binary_id);
+                    self.testsuite_for(test_instance).add_test_case(testcase);
+                }
             }
-            TestEvent::RunBeginCancel { .. } => {}
+            TestEvent::TestGroupHookFailed { .. }
+            | TestEvent::RunBeginCancel { .. }
+            | TestEvent::RunSnapshot { .. }
+            | TestEvent::RunProgress { .. } => {}
             TestEvent::RunFinished {
                 start_time,
                 elapsed,
@@ -152,6 +202,9 @@ impl<'cfg> MetadataJunit<'cfg> {
             } => {
                 // Write out the report to the given file.
                 let mut report = Report::new(self.config.report_name());
+                if let Some(run_metadata) = &self.run_metadata {
+                    report.set_uuid(run_metadata.run_id);
+                }
                 report
                     .set_timestamp(to_datetime(start_time))
                     .set_time(elapsed)
@@ -179,10 +232,83 @@ impl<'cfg> MetadataJunit<'cfg> {
     }
 
     fn testsuite_for(&mut self, test_instance: TestInstance<'cfg>) -> &mut TestSuite {
+        let run_metadata = self.run_metadata.as_ref();
         self.test_suites
             .entry(&test_instance.bin_info.binary_id)
-            .or_insert_with(|| TestSuite::new(&test_instance.bin_info.binary_id))
+            .or_insert_with(|| {
+                let mut test_suite = TestSuite::new(&test_instance.bin_info.binary_id);
+                // Many tools only look at properties, so include run metadata in each test suite.
+                if let Some(run_metadata) = run_metadata {
+                    test_suite.add_properties(run_metadata_properties(run_metadata));
+                }
+                test_suite
+            })
+    }
+}
+
+fn run_metadata_properties(run_metadata: &RunMetadata) -> Vec<Property> {
+    let mut properties = vec![
+        Property::new("nextest.run-id", run_metadata.run_id.to_string()),
+        Property::new("nextest.version", &run_metadata.nextest_version),
+        Property::new("nextest.profile", &run_metadata.profile_name),
+    ];
+    if let Some(host_triple) = &run_metadata.host_triple {
+        properties.push(Property::new("nextest.host-triple", host_triple));
+    }
+    if let Some(target_triple) = &run_metadata.target_triple {
+        properties.push(Property::new("nextest.target-triple", target_triple));
+    }
+    properties.push(Property::new(
+        "nextest.invocation",
+        run_metadata.invocation.join(" "),
+    ));
+    properties
+}
+
+fn testcase_name(test_instance: &TestInstance<'_>) -> String {
+    match test_instance.variant {
+        Some(variant) => format!("{} {}", test_instance.name, variant),
+        None => test_instance.name.to_owned(),
+    }
+}
+
+/// Returns a skipped status with the message and type set from the skip reason, e.g.
+/// `<skipped message="ignored: slow" type="ignored"/>`.
+fn skipped_status(skip_reason: &SkipReasonSummary) -> TestCaseStatus {
+    let mut testcase_status = TestCaseStatus::skipped();
+    testcase_status
+        .set_message(skip_reason.message.as_str())
+        .set_type(skip_reason.kind.as_str());
+    testcase_status
+}
+
+/// Returns standard output followed by references to any attachments, in the format understood by
+/// the Jenkins JUnit attachments plugin.
+///
+/// Files that output was written to are attached before other files.
+fn with_attachments(run_status: &ExecuteStatus) -> Cow<'_, [u8]> {
+    let paths: Vec<_> = [&run_status.stdout_path, &run_status.stderr_path]
+        .into_iter()
+        .flatten()
+        .chain(
+            run_status
+                .attachments
+                .iter()
+                .map(|attachment| &attachment.path),
+        )
+        .collect();
+    if paths.is_empty() {
+        return Cow::Borrowed(run_status.stdout());
+    }
+
+    let mut system_out = run_status.stdout().to_vec();
+    if !system_out.is_empty() && !system_out.ends_with(b"\n") {
+        system_out.push(b'\n');
+    }
+    for path in paths {
+        system_out.extend_from_slice(format!("[[ATTACHMENT|{}]]\n", path).as_bytes());
     }
+    Cow::Owned(system_out)
 }
 
 fn to_datetime(system_time: SystemTime) -> DateTime<FixedOffset> {
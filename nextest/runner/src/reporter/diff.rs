@@ -0,0 +1,343 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Highlighting for assertion failures in test output.
+//!
+//! Two formats are recognized:
+//!
+//! * The `left: ...` / `right: ...` lines printed by the standard library's `assert_eq!` and
+//!   `assert_ne!`. The two values are diffed by character and the differences are emphasized.
+//!   Values too long to be diffed quickly are colored without emphasis.
+//! * The `Diff < left / right > :` blocks printed by `pretty_assertions`. Color is stripped from
+//!   test output before it's displayed, so these blocks are recolored here.
+//!
+//! Everything else is passed through unchanged.
+
+use owo_colors::{OwoColorize, Style};
+use std::{io, io::Write};
+
+#[derive(Debug, Default)]
+pub(super) struct DiffStyles {
+    pub(super) removed: Style,
+    pub(super) added: Style,
+    pub(super) removed_emph: Style,
+    pub(super) added_emph: Style,
+}
+
+impl DiffStyles {
+    pub(super) fn colorize(&mut self) {
+        self.removed = Style::new().red();
+        self.added = Style::new().green();
+        self.removed_emph = Style::new().red().bold().underline();
+        self.added_emph = Style::new().green().bold().underline();
+    }
+}
+
+/// Writes out test output, highlighting any assertion failures found in it.
+///
+/// `output` is expected to have had ANSI escapes stripped from it.
+pub(super) fn write_highlighted(
+    output: &str,
+    styles: &DiffStyles,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let lines: Vec<&str> = output.split_inclusive('\n').collect();
+    let mut in_pretty_diff = false;
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let line = lines[idx];
+
+        if is_pretty_diff_header(line) {
+            in_pretty_diff = true;
+            write!(writer, "{}", line)?;
+            idx += 1;
+            continue;
+        }
+
+        if in_pretty_diff {
+            // A single removed line followed by a single added line is diffed inline, the same
+            // way pretty_assertions does.
+            let single_change = line.starts_with('<')
+                && lines.get(idx + 1).is_some_and(|next| next.starts_with('>'))
+                && !lines.get(idx + 2).is_some_and(|next| next.starts_with('>'))
+                && !(idx > 0 && lines[idx - 1].starts_with('<'));
+            if single_change {
+                let (left, left_end) = split_line_ending(&line[1..]);
+                let (right, right_end) = split_line_ending(&lines[idx + 1][1..]);
+                let diff = char_diff(left, right);
+                write!(writer, "{}", "<".style(styles.removed))?;
+                write_diffed(left, diff.as_deref(), Side::Left, styles, &mut writer)?;
+                write!(writer, "{}{}", left_end, ">".style(styles.added))?;
+                write_diffed(right, diff.as_deref(), Side::Right, styles, &mut writer)?;
+                write!(writer, "{}", right_end)?;
+                idx += 2;
+                continue;
+            }
+
+            if line.starts_with('<') {
+                write_styled_line(line, styles.removed, &mut writer)?;
+            } else if line.starts_with('>') {
+                write_styled_line(line, styles.added, &mut writer)?;
+            } else if line.starts_with(' ') {
+                write!(writer, "{}", line)?;
+            } else {
+                in_pretty_diff = false;
+                write!(writer, "{}", line)?;
+            }
+            idx += 1;
+            continue;
+        }
+
+        if let Some(next) = lines.get(idx + 1) {
+            if let (Some(left), Some(right)) = (
+                AssertValue::parse(line, "left:"),
+                AssertValue::parse(next, "right:"),
+            ) {
+                if left.value != right.value {
+                    let diff = char_diff(left.value, right.value);
+                    left.write(diff.as_deref(), Side::Left, styles, &mut writer)?;
+                    right.write(diff.as_deref(), Side::Right, styles, &mut writer)?;
+                    idx += 2;
+                    continue;
+                }
+            }
+        }
+
+        write!(writer, "{}", line)?;
+        idx += 1;
+    }
+
+    Ok(())
+}
+
+fn is_pretty_diff_header(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with("Diff < ") && line.ends_with(" > :")
+}
+
+fn write_styled_line(line: &str, style: Style, mut writer: impl Write) -> io::Result<()> {
+    let (line, line_end) = split_line_ending(line);
+    write!(writer, "{}{}", line.style(style), line_end)
+}
+
+fn split_line_ending(line: &str) -> (&str, &str) {
+    let trimmed = line.trim_end_matches(&['\r', '\n'][..]);
+    (trimmed, &line[trimmed.len()..])
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// The largest product of the lengths of two values that are diffed by character. Computing the
+/// diff takes time and memory proportional to this product.
+const MAX_DIFF_CELLS: usize = 1 << 20;
+
+/// Returns the character diff between `left` and `right`, or `None` if they're too long to diff.
+fn char_diff(left: &str, right: &str) -> Option<Vec<diff::Result<char>>> {
+    if left.len().saturating_mul(right.len()) > MAX_DIFF_CELLS {
+        return None;
+    }
+    Some(diff::chars(left, right))
+}
+
+/// Writes out one side of a character diff, where `value` is that side's text. If there's no diff,
+/// `value` is written out without emphasis.
+fn write_diffed(
+    value: &str,
+    diff: Option<&[diff::Result<char>]>,
+    side: Side,
+    styles: &DiffStyles,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let (style, emph_style) = match side {
+        Side::Left => (styles.removed, styles.removed_emph),
+        Side::Right => (styles.added, styles.added_emph),
+    };
+    let Some(diff) = diff else {
+        return write!(writer, "{}", value.style(style));
+    };
+
+    // Group runs of characters with the same style together to avoid emitting escapes for every
+    // character.
+    let mut run = String::new();
+    let mut run_emph = false;
+    for result in diff {
+        let (c, emph) = match (result, side) {
+            (diff::Result::Both(c, _), _) => (*c, false),
+            (diff::Result::Left(c), Side::Left) | (diff::Result::Right(c), Side::Right) => {
+                (*c, true)
+            }
+            _ => continue,
+        };
+        if emph != run_emph && !run.is_empty() {
+            let run_style = if run_emph { emph_style } else { style };
+            write!(writer, "{}", run.style(run_style))?;
+            run.clear();
+        }
+        run_emph = emph;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        let run_style = if run_emph { emph_style } else { style };
+        write!(writer, "{}", run.style(run_style))?;
+    }
+    Ok(())
+}
+
+/// A `left: ...` or `right: ...` line printed by `assert_eq!`.
+#[derive(Debug, Eq, PartialEq)]
+struct AssertValue<'a> {
+    prefix: &'a str,
+    value: &'a str,
+    suffix: &'a str,
+}
+
+impl<'a> AssertValue<'a> {
+    fn parse(line: &'a str, label: &str) -> Option<Self> {
+        let label_start = line.find(label)?;
+        if !line[..label_start].trim().is_empty() {
+            return None;
+        }
+        let value_start = label_start + label.len() + 1;
+        if line.get(label_start + label.len()..value_start) != Some(" ") {
+            return None;
+        }
+        let rest = &line[value_start..];
+
+        // Older versions of Rust wrap the value in backticks, and may follow it with the rest of
+        // the panic message: "  left: `1`," / " right: `2`: message', src/lib.rs:1:1".
+        let value_len = match rest.strip_prefix('`') {
+            Some(quoted) => {
+                let end = quoted.match_indices('`').map(|(idx, _)| idx).find(|&idx| {
+                    matches!(
+                        quoted[idx + 1..].chars().next(),
+                        None | Some(',' | ':' | '\'' | '\r' | '\n')
+                    )
+                })?;
+                end + 2
+            }
+            None => split_line_ending(rest).0.len(),
+        };
+
+        Some(Self {
+            prefix: &line[..value_start],
+            value: &rest[..value_len],
+            suffix: &rest[value_len..],
+        })
+    }
+
+    fn write(
+        &self,
+        diff: Option<&[diff::Result<char>]>,
+        side: Side,
+        styles: &DiffStyles,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        write!(writer, "{}", self.prefix)?;
+        write_diffed(self.value, diff, side, styles, &mut writer)?;
+        write!(writer, "{}", self.suffix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_assert_value() {
+        let value = AssertValue::parse("  left: 4\n", "left:").expect("left value parsed");
+        assert_eq!(value.prefix, "  left: ");
+        assert_eq!(value.value, "4");
+        assert_eq!(value.suffix, "\n");
+
+        let value = AssertValue::parse(" right: `\"a`b\"`: message', src/lib.rs:1:1\n", "right:")
+            .expect("backtick-quoted right value parsed");
+        assert_eq!(value.value, "`\"a`b\"`");
+        assert_eq!(value.suffix, ": message', src/lib.rs:1:1\n");
+
+        assert_eq!(AssertValue::parse("not left: 4\n", "left:"), None);
+    }
+
+    #[test]
+    fn highlight() {
+        let output = "\
+thread 'foo' panicked at src/lib.rs:1:1:
+assertion `left == right` failed
+  left: \"abc\"
+ right: \"abd\"
+Diff < left / right > :
+ unchanged
+<removed
+>added
+";
+        let mut styles = DiffStyles::default();
+
+        let mut plain = vec![];
+        write_highlighted(output, &styles, &mut plain).unwrap();
+        assert_eq!(
+            String::from_utf8(plain).unwrap(),
+            output,
+            "output is unchanged without colors"
+        );
+
+        styles.colorize();
+        let mut colored = vec![];
+        write_highlighted(output, &styles, &mut colored).unwrap();
+        let colored = String::from_utf8(colored).unwrap();
+        assert!(
+            colored.contains(&format!(
+                "  left: {}{}{}\n",
+                "\"ab".style(styles.removed),
+                "c".style(styles.removed_emph),
+                "\"".style(styles.removed)
+            )),
+            "left value is diffed: {}",
+            colored
+        );
+        assert!(
+            colored.contains(&format!(
+                "{}{}\n",
+                "d".style(styles.added_emph),
+                "\"".style(styles.added)
+            )),
+            "right value is diffed: {}",
+            colored
+        );
+        assert_eq!(
+            strip_ansi_escapes::strip(&colored).unwrap(),
+            output.as_bytes(),
+            "only colors are added"
+        );
+    }
+
+    #[test]
+    fn highlight_long_values() {
+        let left = "a".repeat(2000);
+        let right = "b".repeat(2000);
+        assert_eq!(
+            char_diff(&left, &right),
+            None,
+            "values are too long to diff"
+        );
+
+        let output = format!("  left: {}\n right: {}\n", left, right);
+        let mut styles = DiffStyles::default();
+        styles.colorize();
+        let mut colored = vec![];
+        write_highlighted(&output, &styles, &mut colored).unwrap();
+        let colored = String::from_utf8(colored).unwrap();
+        assert_eq!(
+            colored,
+            format!(
+                "  left: {}\n right: {}\n",
+                left.style(styles.removed),
+                right.style(styles.added)
+            ),
+            "long values are colored without emphasis"
+        );
+    }
+}
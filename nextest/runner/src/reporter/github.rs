@@ -0,0 +1,209 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Support for GitHub Actions workflow commands.
+//!
+//! Failing tests are reported as `::error` commands, which GitHub shows inline in pull request
+//! diffs. See [the GitHub documentation] for more about workflow commands.
+//!
+//! [the GitHub documentation]: https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+
+use crate::{
+    runner::{ExecuteStatus, ExecutionResult},
+    test_list::TestInstance,
+};
+use std::io::{self, Write};
+
+/// Writes out an `::error` workflow command for a failing test.
+pub(super) fn write_error_annotation(
+    test_instance: &TestInstance<'_>,
+    run_status: &ExecuteStatus,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let mut title = format!(
+        "{} {}",
+        test_instance.bin_info.binary_id, test_instance.name
+    );
+    if let Some(variant) = test_instance.variant {
+        title.push_str(&format!(" {}", variant));
+    }
+
+    let stderr = String::from_utf8_lossy(run_status.stderr());
+    let panic_info = PanicInfo::parse(&stderr);
+
+    write!(writer, "::error ")?;
+    if let Some(location) = panic_info.as_ref().and_then(|info| info.location) {
+        write!(
+            writer,
+            "file={},line={},col={},",
+            escape_property(location.file),
+            location.line,
+            location.col
+        )?;
+    }
+    write!(writer, "title={}::", escape_property(&title))?;
+
+    let mut message = match run_status.result {
+        ExecutionResult::ExecFail => "test failed to execute".to_owned(),
+        ExecutionResult::DiskQuotaExceeded => "test exceeded its disk quota".to_owned(),
+        ExecutionResult::Timeout => "test exceeded its time limit".to_owned(),
+        ExecutionResult::Fail => match &run_status.sanitizer_report {
+            Some(report) => format!("test failed with a sanitizer error: {}", report.summary),
+            None => "test failed".to_owned(),
+        },
+        ExecutionResult::UnexpectedPass => "test passed, but was expected to fail".to_owned(),
+        ExecutionResult::Pass | ExecutionResult::Skip | ExecutionResult::ExpectedFail => {
+            "test failed".to_owned()
+        }
+    };
+    if let Some(info) = &panic_info {
+        message.push('\n');
+        message.push_str(info.message.trim_end());
+    }
+    writeln!(writer, "{}", escape_data(&message))
+}
+
+/// Information about a panic, parsed out of a test's standard error.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct PanicInfo<'a> {
+    message: &'a str,
+    location: Option<PanicLocation<'a>>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct PanicLocation<'a> {
+    file: &'a str,
+    line: u32,
+    col: u32,
+}
+
+impl<'a> PanicInfo<'a> {
+    const PANICKED_AT: &'static str = "' panicked at ";
+
+    /// Parses the first panic message in the given output.
+    ///
+    /// Both the format used by Rust 1.73 and later:
+    ///
+    /// ```text
+    /// thread 'test_name' panicked at src/lib.rs:10:5:
+    /// message
+    /// ```
+    ///
+    /// and the format used by earlier versions:
+    ///
+    /// ```text
+    /// thread 'test_name' panicked at 'message', src/lib.rs:10:5
+    /// ```
+    ///
+    /// are supported.
+    fn parse(output: &'a str) -> Option<Self> {
+        let start = output
+            .match_indices(Self::PANICKED_AT)
+            .map(|(idx, _)| idx)
+            .find(|&idx| {
+                let line_start = output[..idx].rfind('\n').map_or(0, |n| n + 1);
+                output[line_start..].starts_with("thread '")
+            })?;
+        let rest = &output[start + Self::PANICKED_AT.len()..];
+
+        if let Some(rest) = rest.strip_prefix('\'') {
+            // Old format: the message is quoted and followed by the location.
+            let end = rest.find("\nnote: ").unwrap_or(rest.len());
+            let (message, location) = rest[..end].rsplit_once("', ")?;
+            let location = location.lines().next().unwrap_or_default();
+            Some(Self {
+                message,
+                location: PanicLocation::parse(location),
+            })
+        } else {
+            // New format: the location is followed by a colon and the message on the next lines.
+            let (location, message) = rest.split_once('\n').unwrap_or((rest, ""));
+            let end = message
+                .find("\nnote: ")
+                .or_else(|| message.find("\nstack backtrace:"))
+                .unwrap_or(message.len());
+            Some(Self {
+                message: &message[..end],
+                location: PanicLocation::parse(location.trim_end().trim_end_matches(':')),
+            })
+        }
+    }
+}
+
+impl<'a> PanicLocation<'a> {
+    fn parse(location: &'a str) -> Option<Self> {
+        let mut parts = location.rsplitn(3, ':');
+        let col = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next()?;
+        Some(Self { file, line, col })
+    }
+}
+
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn parse_panic_info() {
+        let new_format = indoc! {"
+            running 1 test
+            thread 'test_failure_assert' panicked at tests/basic.rs:9:5:
+            assertion `left == right` failed: this is an assertion
+              left: 4
+             right: 5
+            note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+        "};
+        assert_eq!(
+            PanicInfo::parse(new_format),
+            Some(PanicInfo {
+                message:
+                    "assertion `left == right` failed: this is an assertion\n  left: 4\n right: 5",
+                location: Some(PanicLocation {
+                    file: "tests/basic.rs",
+                    line: 9,
+                    col: 5
+                }),
+            })
+        );
+
+        let old_format = indoc! {"
+            thread 'main' panicked at 'assertion failed: `(left == right)`
+              left: `4`,
+             right: `5`: this is an assertion', tests/basic.rs:9:5
+            note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+        "};
+        assert_eq!(
+            PanicInfo::parse(old_format),
+            Some(PanicInfo {
+                message: "assertion failed: `(left == right)`\n  left: `4`,\n right: `5`: this is an assertion",
+                location: Some(PanicLocation {
+                    file: "tests/basic.rs",
+                    line: 9,
+                    col: 5
+                }),
+            })
+        );
+
+        assert_eq!(PanicInfo::parse("Error: \"this is an error\"\n"), None);
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(escape_data("50% done\nnext"), "50%25 done%0Anext");
+        assert_eq!(escape_property("a::b, c"), "a%3A%3Ab%2C c");
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Grouping of failures with identical output in the final summary.
+//!
+//! When many tests fail for the same reason (for example, a shared fixture is missing), printing
+//! the output of each one drowns out everything else. Instead, failures are grouped by a
+//! fingerprint of their output: one representative output is printed for each group, followed by
+//! the list of other tests in it.
+//!
+//! Before fingerprinting, output is normalized so that incidental differences don't split up
+//! groups: ANSI escapes are stripped, the name of the test is masked out, and libtest's `test
+//! result:` line (which includes timings) is dropped.
+
+use std::hash::Hasher;
+use twox_hash::XxHash64;
+
+/// Returns a fingerprint of the output of a failed test, for grouping identical failures.
+pub(super) fn output_fingerprint(test_name: &str, stdout: &[u8], stderr: &[u8]) -> u64 {
+    let mut hasher = XxHash64::default();
+    for output in [stdout, stderr] {
+        let stripped = strip_ansi_escapes::strip(output).unwrap_or_else(|_| output.to_vec());
+        let output = String::from_utf8_lossy(&stripped);
+        for line in output.lines() {
+            if line.starts_with("test result: ") {
+                continue;
+            }
+            hasher.write(line.replace(test_name, "<test>").as_bytes());
+            hasher.write_u8(b'\n');
+        }
+        // Separate stdout from stderr.
+        hasher.write_u8(0);
+    }
+    hasher.finish()
+}
+
+/// Groups items by their fingerprints, returning indexes in order of first appearance.
+///
+/// Items without a fingerprint are never grouped with others.
+pub(super) fn group_by_fingerprint(
+    fingerprints: impl IntoIterator<Item = Option<u64>>,
+) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(Option<u64>, Vec<usize>)> = vec![];
+    for (idx, fingerprint) in fingerprints.into_iter().enumerate() {
+        let existing = fingerprint.and_then(|fingerprint| {
+            groups
+                .iter_mut()
+                .find(|(group_fingerprint, _)| *group_fingerprint == Some(fingerprint))
+        });
+        match existing {
+            Some((_, indexes)) => indexes.push(idx),
+            None => groups.push((fingerprint, vec![idx])),
+        }
+    }
+    groups.into_iter().map(|(_, indexes)| indexes).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_and_group() {
+        let failure = |name: &str| {
+            let stdout = format!(
+                "\nrunning 1 test\ntest {} ... FAILED\n\nfailures:\n\ntest result: FAILED. 0 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.0{}s\n",
+                name,
+                name.len()
+            );
+            let stderr = format!(
+                "thread '{}' panicked at src/fixtures.rs:10:5:\n\x1b[31mfixture missing\x1b[0m\n",
+                name
+            );
+            output_fingerprint(name, stdout.as_bytes(), stderr.as_bytes())
+        };
+
+        let fingerprints = [
+            Some(failure("tests::first")),
+            Some(output_fingerprint("tests::other", b"", b"different output")),
+            Some(failure("tests::second_test")),
+            None,
+            Some(failure("tests::third")),
+            None,
+        ];
+        assert_eq!(fingerprints[0], fingerprints[2], "identical failures match");
+        assert_ne!(
+            fingerprints[0], fingerprints[1],
+            "different failures differ"
+        );
+        assert_ne!(
+            output_fingerprint("t", b"a", b""),
+            output_fingerprint("t", b"", b"a"),
+            "stdout and stderr are distinguished"
+        );
+
+        assert_eq!(
+            group_by_fingerprint(fingerprints),
+            [vec![0, 2, 4], vec![1], vec![3], vec![5]]
+        );
+    }
+}
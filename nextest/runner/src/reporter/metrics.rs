@@ -0,0 +1,349 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Metrics for a run, in the Prometheus text format.
+
+use crate::{
+    config::NextestMetricsConfig, errors::WriteEventError, reporter::TestEvent, runner::RunStats,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// The timeout for each step of pushing metrics to a Pushgateway.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug)]
+pub(super) struct MetadataMetrics<'cfg> {
+    config: NextestMetricsConfig<'cfg>,
+    profile_name: &'cfg str,
+    // The total time taken by the tests in each binary, including retries.
+    binary_durations: BTreeMap<&'cfg str, Duration>,
+}
+
+impl<'cfg> MetadataMetrics<'cfg> {
+    pub(super) fn new(config: NextestMetricsConfig<'cfg>, profile_name: &'cfg str) -> Self {
+        Self {
+            config,
+            profile_name,
+            binary_durations: BTreeMap::new(),
+        }
+    }
+
+    pub(super) fn write_event(&mut self, event: &TestEvent<'cfg>) -> Result<(), WriteEventError> {
+        match event {
+            TestEvent::TestFinished {
+                test_instance,
+                run_statuses,
+            } => {
+                *self
+                    .binary_durations
+                    .entry(&test_instance.bin_info.binary_id)
+                    .or_default() += run_statuses
+                    .iter()
+                    .map(|run_status| run_status.time_taken)
+                    .sum::<Duration>();
+            }
+            TestEvent::RunFinished {
+                elapsed, run_stats, ..
+            } => {
+                let metrics = self.render(*elapsed, run_stats, SystemTime::now());
+                if let Some(path) = self.config.path() {
+                    // The textfile collector may read the file at any time, so write it to a
+                    // temporary file first and then rename it into place.
+                    let fs_err = |error| WriteEventError::Fs {
+                        file: path.to_path_buf(),
+                        error,
+                    };
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent).map_err(fs_err)?;
+                    }
+                    let temp_path = path.with_extension("prom.tmp");
+                    fs::write(&temp_path, &metrics).map_err(fs_err)?;
+                    fs::rename(&temp_path, path).map_err(fs_err)?;
+                }
+                if let Some(url) = self.config.pushgateway() {
+                    push(url, self.config.job(), &metrics).map_err(|error| {
+                        WriteEventError::Pushgateway {
+                            url: url.to_owned(),
+                            error,
+                        }
+                    })?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn render(&self, elapsed: Duration, run_stats: &RunStats, now: SystemTime) -> String {
+        let profile = escape_label(self.profile_name);
+        let mut out = String::new();
+
+        let mut gauge = |name: &str, help: &str, samples: &[(Option<(&str, &str)>, f64)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for (label, value) in samples {
+                let _ = match label {
+                    Some((key, label_value)) => writeln!(
+                        out,
+                        "{}{{profile=\"{}\",{}=\"{}\"}} {}",
+                        name,
+                        profile,
+                        key,
+                        escape_label(label_value),
+                        value
+                    ),
+                    None => writeln!(out, "{}{{profile=\"{}\"}} {}", name, profile, value),
+                };
+            }
+        };
+
+        gauge(
+            "nextest_run_success",
+            "Whether the latest run succeeded.",
+            &[(None, if run_stats.is_success() { 1.0 } else { 0.0 })],
+        );
+        gauge(
+            "nextest_run_duration_seconds",
+            "The wall time taken by the latest run.",
+            &[(None, elapsed.as_secs_f64())],
+        );
+        gauge(
+            "nextest_run_timestamp_seconds",
+            "The time the latest run finished at, in seconds since the Unix epoch.",
+            &[(
+                None,
+                now.duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64(),
+            )],
+        );
+        let results = [
+            ("run", run_stats.final_run_count),
+            ("passed", run_stats.passed),
+            ("flaky", run_stats.flaky),
+            ("failed", run_stats.failed),
+            ("exec-failed", run_stats.exec_failed),
+            ("disk-quota-exceeded", run_stats.disk_quota_exceeded),
+            ("timed-out", run_stats.timed_out),
+            ("quarantined", run_stats.quarantined),
+            ("expected-fail", run_stats.expected_failures),
+            ("unexpected-pass", run_stats.unexpected_passes),
+            ("skipped", run_stats.skipped),
+            ("runtime-skipped", run_stats.runtime_skipped),
+        ];
+        let samples: Vec<_> = results
+            .iter()
+            .map(|(result, count)| (Some(("result", *result)), *count as f64))
+            .collect();
+        gauge(
+            "nextest_run_tests",
+            "The number of tests in the latest run, by result. Flaky tests are also counted as passed.",
+            &samples,
+        );
+        let samples: Vec<_> = self
+            .binary_durations
+            .iter()
+            .map(|(binary_id, duration)| (Some(("binary_id", *binary_id)), duration.as_secs_f64()))
+            .collect();
+        gauge(
+            "nextest_binary_duration_seconds",
+            "The total time taken by the tests in each binary in the latest run, including retries.",
+            &samples,
+        );
+
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Pushes metrics to the Pushgateway at `url`, replacing the metrics previously pushed for the job.
+fn push(url: &str, job: &str, metrics: &str) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_owned());
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid("only http:// URLs are supported"))?;
+    let (authority, base_path) = match rest.find('/') {
+        Some(idx) => rest.split_at(idx),
+        None => (rest, ""),
+    };
+    if authority.is_empty() {
+        return Err(invalid("URL has no host"));
+    }
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => {
+            (host, port.parse().map_err(|_| invalid("invalid port"))?)
+        }
+        _ => (authority, 80),
+    };
+
+    let addr = host
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid("host could not be resolved"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, PUSH_TIMEOUT)?;
+    stream.set_read_timeout(Some(PUSH_TIMEOUT))?;
+    stream.set_write_timeout(Some(PUSH_TIMEOUT))?;
+
+    write!(
+        stream,
+        "PUT {}/metrics/job/{} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        base_path.trim_end_matches('/'),
+        encode_path_segment(job),
+        authority,
+        metrics.len(),
+        metrics,
+    )?;
+    stream.flush()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "unexpected response from Pushgateway: {}",
+            status_line
+        ))),
+    }
+}
+
+// Percent-encodes everything except unreserved characters.
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NextestConfig;
+    use camino::Utf8PathBuf;
+    use std::{io::BufRead, net::TcpListener};
+
+    #[test]
+    fn render_and_push() {
+        let config_contents = r#"
+            [profile.ci.metrics]
+            path = "metrics.prom"
+        "#;
+        let dir = tempfile::tempdir().expect("created temp dir");
+        let config_path =
+            Utf8PathBuf::try_from(dir.path().join("nextest.toml")).expect("path is valid UTF-8");
+        fs::write(&config_path, config_contents).expect("wrote config");
+        let config =
+            NextestConfig::from_sources("/fake/dir", Some(&config_path)).expect("config is valid");
+        let profile = config.profile("ci").expect("ci profile exists");
+        let metrics_config = profile.metrics().expect("metrics are configured");
+        assert_eq!(
+            metrics_config.path().map(|path| path.as_str()),
+            Some("/fake/dir/target/nextest/ci/metrics.prom")
+        );
+        assert_eq!(metrics_config.job(), "nextest");
+        assert!(
+            config
+                .profile(NextestConfig::DEFAULT_PROFILE)
+                .expect("default profile exists")
+                .metrics()
+                .is_none(),
+            "metrics aren't configured by default"
+        );
+
+        let mut metrics = MetadataMetrics::new(metrics_config, "ci");
+        metrics
+            .binary_durations
+            .insert("my-crate::\"quoted\"", Duration::from_millis(1500));
+        let run_stats = RunStats {
+            initial_run_count: 3,
+            final_run_count: 3,
+            passed: 2,
+            flaky: 1,
+            failed: 1,
+            ..RunStats::default()
+        };
+        let rendered = metrics.render(
+            Duration::from_secs(2),
+            &run_stats,
+            UNIX_EPOCH + Duration::from_secs(100),
+        );
+        for line in [
+            "# TYPE nextest_run_success gauge",
+            "nextest_run_success{profile=\"ci\"} 0",
+            "nextest_run_duration_seconds{profile=\"ci\"} 2",
+            "nextest_run_timestamp_seconds{profile=\"ci\"} 100",
+            "nextest_run_tests{profile=\"ci\",result=\"passed\"} 2",
+            "nextest_run_tests{profile=\"ci\",result=\"flaky\"} 1",
+            "nextest_binary_duration_seconds{profile=\"ci\",binary_id=\"my-crate::\\\"quoted\\\"\"} 1.5",
+        ] {
+            assert!(
+                rendered.lines().any(|rendered_line| rendered_line == line),
+                "line {:?} in rendered metrics:\n{}",
+                line,
+                rendered
+            );
+        }
+
+        // Push to a fake Pushgateway that accepts the request.
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bound listener");
+        let addr = listener.local_addr().expect("listener has an address");
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().expect("accepted connection");
+            let mut reader = io::BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).expect("read request");
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).expect("read header");
+                if header == "\r\n" {
+                    break;
+                }
+                if let Some(value) = header.strip_prefix("Content-Length: ") {
+                    content_length = value.trim().parse().expect("valid content length");
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).expect("read body");
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .expect("wrote response");
+            (
+                request_line,
+                String::from_utf8(body).expect("body is UTF-8"),
+            )
+        });
+        push(&format!("http://{}/prefix/", addr), "nextest ci", &rendered).expect("push succeeded");
+        let (request_line, body) = server.join().expect("server thread didn't panic");
+        assert_eq!(
+            request_line,
+            "PUT /prefix/metrics/job/nextest%20ci HTTP/1.1\r\n"
+        );
+        assert_eq!(body, rendered);
+
+        push("https://example.com", "nextest", "").expect_err("https isn't supported");
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! A progress bar drawn at the bottom of the terminal while tests are running.
+
+use super::{Styles, TestEvent};
+use crate::{
+    helpers::{terminal_width, write_truncated},
+    runner::RunSnapshot,
+};
+use owo_colors::Style;
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+#[derive(Clone, Debug)]
+pub(super) struct ProgressBar<'a> {
+    // The most recent snapshot of the run. The progress bar isn't drawn until the first one arrives.
+    snapshot: Option<RunSnapshot<'a>>,
+    // The number of lines the progress bar currently takes up on screen.
+    drawn_lines: usize,
+    finished: bool,
+}
+
+impl<'a> ProgressBar<'a> {
+    /// How often the progress bar is refreshed.
+    pub(super) const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+    // The maximum number of running tests listed below the bar.
+    const MAX_RUNNING_SHOWN: usize = 8;
+
+    // The width of the bar itself, excluding the brackets around it.
+    const BAR_WIDTH: usize = 25;
+
+    pub(super) fn new() -> Self {
+        Self {
+            snapshot: None,
+            drawn_lines: 0,
+            finished: false,
+        }
+    }
+
+    pub(super) fn update(&mut self, event: &TestEvent<'a>) {
+        match event {
+            TestEvent::RunProgress { snapshot } | TestEvent::RunSnapshot { snapshot } => {
+                self.snapshot = Some(snapshot.clone());
+            }
+            TestEvent::RunFinished { .. } => {
+                self.finished = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes out `output` in place of the progress bar, then redraws the progress bar below it.
+    ///
+    /// Everything is written in one go to avoid flickering.
+    pub(super) fn write(
+        &mut self,
+        output: &[u8],
+        styles: &Styles,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(output.len() + 1024);
+        self.clear(&mut buf)?;
+        buf.extend_from_slice(output);
+        if !self.finished {
+            self.draw(styles, terminal_width(), &mut buf)?;
+        }
+        writer.write_all(&buf)?;
+        writer.flush()
+    }
+
+    fn clear(&mut self, mut writer: impl Write) -> io::Result<()> {
+        if self.drawn_lines == 0 {
+            return Ok(());
+        }
+        // The cursor is at the end of the last line of the progress bar: move it to the start of
+        // the first line, then clear everything below it.
+        write!(writer, "\r")?;
+        if self.drawn_lines > 1 {
+            write!(writer, "\x1b[{}A", self.drawn_lines - 1)?;
+        }
+        write!(writer, "\x1b[J")?;
+        self.drawn_lines = 0;
+        Ok(())
+    }
+
+    fn draw(&mut self, styles: &Styles, width: usize, mut writer: impl Write) -> io::Result<()> {
+        let snapshot = match &self.snapshot {
+            Some(snapshot) => snapshot,
+            None => return Ok(()),
+        };
+        let run_stats = &snapshot.run_stats;
+        let total = run_stats.initial_run_count;
+        let finished = run_stats.final_run_count;
+        let failed = run_stats.failed
+            + run_stats.exec_failed
+            + run_stats.disk_quota_exceeded
+            + run_stats.timed_out;
+
+        let mut lines = vec![vec![
+            (format!("{:>12}", "Running"), styles.pass),
+            (
+                format!(
+                    " [{}] [{}] ",
+                    format_elapsed(snapshot.elapsed),
+                    render_bar(finished, total, Self::BAR_WIDTH)
+                ),
+                Style::new(),
+            ),
+            (format!("{}/{}", finished, total), styles.count),
+            (
+                format!(
+                    ": {} running, {} passed, {} failed, {} skipped",
+                    snapshot.running.len(),
+                    run_stats.passed,
+                    failed,
+                    run_stats.skipped,
+                ),
+                Style::new(),
+            ),
+        ]];
+
+        // List the tests that have been running the longest first.
+        let mut running: Vec<_> = snapshot.running.iter().collect();
+        running.sort_by_key(|test| std::cmp::Reverse(test.elapsed));
+        for test in running.iter().take(Self::MAX_RUNNING_SHOWN) {
+            let status = if test.attempt > 1 {
+                format!("TRY {} RUN", test.attempt)
+            } else {
+                "RUNNING".to_owned()
+            };
+            let mut line = vec![
+                (format!("{:>12}", status), styles.skip),
+                (
+                    format!(" [{:>8.3?}s] ", test.elapsed.as_secs_f64()),
+                    Style::new(),
+                ),
+                (
+                    test.test_instance.bin_info.binary_id.clone(),
+                    styles.test_list.binary_id,
+                ),
+                (format!(" {}", test.test_instance.name), Style::new()),
+            ];
+            if let Some(variant) = test.test_instance.variant {
+                line.push((format!(" {}", variant), Style::new()));
+            }
+            lines.push(line);
+        }
+        if running.len() > Self::MAX_RUNNING_SHOWN {
+            lines.push(vec![(
+                format!(
+                    "{:>12} ... and {} more",
+                    "",
+                    running.len() - Self::MAX_RUNNING_SHOWN
+                ),
+                Style::new(),
+            )]);
+        }
+
+        for (idx, line) in lines.iter().enumerate() {
+            if idx > 0 {
+                writeln!(writer)?;
+            }
+            write_truncated(line, width, &mut writer)?;
+        }
+        self.drawn_lines = lines.len();
+        Ok(())
+    }
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+fn render_bar(finished: usize, total: usize, width: usize) -> String {
+    let filled = (finished.min(total) * width)
+        .checked_div(total)
+        .unwrap_or(width);
+    let mut bar = "=".repeat(filled);
+    if filled < width {
+        bar.push('>');
+        bar.push_str(&" ".repeat(width - filled - 1));
+    }
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::RunStats;
+
+    #[test]
+    fn draw_and_clear() {
+        assert_eq!(render_bar(0, 4, 8), ">       ");
+        assert_eq!(render_bar(2, 4, 8), "====>   ");
+        assert_eq!(render_bar(4, 4, 8), "========");
+        assert_eq!(render_bar(0, 0, 8), "========");
+        assert_eq!(format_elapsed(Duration::from_secs(3723)), "01:02:03");
+
+        let mut progress_bar = ProgressBar::new();
+        let styles = Styles::default();
+        let mut out = Vec::new();
+        progress_bar
+            .write(b"before\n", &styles, &mut out)
+            .expect("wrote to buffer");
+        assert_eq!(out, b"before\n", "nothing drawn before the first snapshot");
+
+        progress_bar.update(&TestEvent::RunProgress {
+            snapshot: RunSnapshot {
+                elapsed: Duration::from_secs(61),
+                running: vec![],
+                queued: 2,
+                recently_finished: vec![],
+                run_stats: RunStats {
+                    initial_run_count: 4,
+                    final_run_count: 2,
+                    passed: 1,
+                    failed: 1,
+                    ..RunStats::default()
+                },
+            },
+        });
+        let mut out = Vec::new();
+        progress_bar
+            .draw(&styles, 60, &mut out)
+            .expect("wrote to buffer");
+        let expected = "     Running [00:01:01] [============>            ] 2/4: 0 ";
+        assert_eq!(String::from_utf8(out).expect("valid UTF-8"), expected);
+        assert_eq!(expected.len(), 59, "truncated to one less than the width");
+        assert_eq!(progress_bar.drawn_lines, 1);
+
+        let mut out = Vec::new();
+        progress_bar.clear(&mut out).expect("wrote to buffer");
+        assert_eq!(out, b"\r\x1b[J");
+        assert_eq!(progress_bar.drawn_lines, 0);
+    }
+}
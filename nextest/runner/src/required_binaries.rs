@@ -0,0 +1,100 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Workspace binaries that tests need in order to run, such as a server binary under test.
+//!
+//! Cargo only sets `CARGO_BIN_EXE_<name>` for binaries in the same package as an integration
+//! test. Binaries in other packages can be declared in `.config/nextest.toml`:
+//!
+//! ```toml
+//! [[required-binaries]]
+//! filter = "package(my-client)"
+//! package = "my-server"
+//! binary = "my-server"
+//! env = "MY_SERVER_BIN"
+//! ```
+//!
+//! Before tests are listed, nextest builds each required binary with `cargo build`, using the same
+//! build options (such as the profile, target and target directory) as the tests. The absolute
+//! path to the binary is then set as the environment variable `env` while running tests in the
+//! binaries that `filter` could match tests in.
+//!
+//! These paths are recorded in the test list summary, so tests run through `--test-list-file` get
+//! them without building anything.
+
+use crate::{errors::RequiredBinaryError, filter_expr::FilterExpr};
+use camino::Utf8PathBuf;
+use cargo_metadata::Message;
+use guppy::{graph::PackageGraph, PackageId};
+use serde::Deserialize;
+use std::io;
+
+/// A workspace binary that tests need in order to run.
+///
+/// Obtained through
+/// [`NextestConfig::required_binaries`](crate::config::NextestConfig::required_binaries).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RequiredBinary {
+    filter: FilterExpr,
+    package: String,
+    binary: String,
+    env: String,
+}
+
+impl RequiredBinary {
+    /// Returns the filter expression that selects the test binaries that need this binary.
+    pub fn filter(&self) -> &FilterExpr {
+        &self.filter
+    }
+
+    /// Returns the name of the workspace package this binary is in.
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    /// Returns the name of the binary target.
+    pub fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    /// Returns the name of the environment variable that the path to this binary is set as.
+    pub fn env(&self) -> &str {
+        &self.env
+    }
+
+    /// Finds the path to this binary in the messages produced by `cargo build`.
+    pub fn path_from_messages(
+        &self,
+        graph: &PackageGraph,
+        reader: impl io::BufRead,
+    ) -> Result<Utf8PathBuf, RequiredBinaryError> {
+        for message in Message::parse_stream(reader) {
+            let message = message.map_err(RequiredBinaryError::ReadMessages)?;
+            let artifact = match message {
+                Message::CompilerArtifact(artifact) => artifact,
+                _ => continue,
+            };
+            let executable = match artifact.executable {
+                Some(executable) => executable,
+                None => continue,
+            };
+            if artifact.target.name != self.binary
+                || !artifact.target.kind.iter().any(|kind| kind == "bin")
+            {
+                continue;
+            }
+            let package = graph
+                .metadata(&PackageId::new(artifact.package_id.repr))
+                .map_err(RequiredBinaryError::PackageGraph)?;
+            if package.name() == self.package {
+                return Ok(executable);
+            }
+        }
+
+        Err(RequiredBinaryError::NotBuilt {
+            package: self.package.clone(),
+            binary: self.binary.clone(),
+        })
+    }
+}
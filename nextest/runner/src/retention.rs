@@ -0,0 +1,395 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Removing old runs from the store, so that it doesn't grow without bounds.
+//!
+//! Each run writes its summary, test logs, captured output and coverage data to its own directory,
+//! `runs/<run-id>` in the profile's store directory. A retention policy limits how many of these
+//! are kept:
+//!
+//! ```toml
+//! [store.retention]
+//! max-runs = 50
+//! max-size = "2GiB"
+//! max-age = "14d"
+//! ```
+//!
+//! Runs are considered from newest to oldest. Runs older than `max-age` are removed, and once
+//! keeping a run would go over `max-runs` or `max-size`, it's removed along with every run older
+//! than it. The newest finished run is always kept, as are runs that look like they're still in
+//! progress: the run advertised in the store directory, runs that hold the lock on their
+//! [`RunStore::LOCK_FILE_NAME`], and runs without a summary that had a file written to them in the
+//! last [`UNFINISHED_GRACE_PERIOD`].
+//!
+//! The policy is applied to the profile's store directory at the end of each `cargo nextest run`,
+//! and to the store directories of all profiles by `cargo nextest store gc`.
+
+use crate::{
+    disk_usage::{dir_size, DiskQuota},
+    errors::RunStoreError,
+    run_store::RunStore,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::CurrentRunSummary;
+use serde::Deserialize;
+use std::{
+    fs, io,
+    time::{Duration, SystemTime},
+};
+use uuid::Uuid;
+
+/// How recently a file must have been written to a run without a summary for it to be considered
+/// in progress, and never removed.
+pub const UNFINISHED_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Limits on the runs kept in the store.
+///
+/// Obtained through [`NextestConfig::store_retention`](crate::config::NextestConfig::store_retention).
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    max_runs: Option<usize>,
+    #[serde(default)]
+    max_size: Option<DiskQuota>,
+    #[serde(default, with = "humantime_serde")]
+    max_age: Option<Duration>,
+}
+
+/// The outcome of applying a [`RetentionPolicy`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RetentionSummary {
+    /// The number of runs that were removed.
+    pub removed_runs: usize,
+
+    /// The total size of the runs that were removed, in bytes.
+    pub removed_bytes: u64,
+
+    /// The number of runs that were kept.
+    pub kept_runs: usize,
+
+    /// The total size of the runs that were kept, in bytes.
+    pub kept_bytes: u64,
+}
+
+impl RetentionSummary {
+    fn add(&mut self, other: &RetentionSummary) {
+        self.removed_runs += other.removed_runs;
+        self.removed_bytes += other.removed_bytes;
+        self.kept_runs += other.kept_runs;
+        self.kept_bytes += other.kept_bytes;
+    }
+}
+
+#[derive(Debug)]
+struct StoredRun {
+    run_id: Option<Uuid>,
+    dir: Utf8PathBuf,
+    modified: SystemTime,
+    finished: bool,
+    locked: bool,
+    size: u64,
+}
+
+impl RetentionPolicy {
+    /// Returns the maximum number of runs kept, if specified.
+    pub fn max_runs(&self) -> Option<usize> {
+        self.max_runs
+    }
+
+    /// Returns the maximum total size of the runs kept, if specified.
+    pub fn max_size(&self) -> Option<DiskQuota> {
+        self.max_size
+    }
+
+    /// Returns the maximum age of the runs kept, if specified.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Returns true if the policy keeps every run.
+    pub fn keeps_everything(&self) -> bool {
+        self.max_runs.is_none() && self.max_size.is_none() && self.max_age.is_none()
+    }
+
+    /// Removes the runs the policy doesn't keep from a profile's store directory.
+    pub fn apply(&self, profile_store_dir: &Utf8Path) -> Result<RetentionSummary, RunStoreError> {
+        let mut runs = read_runs(&RunStore::runs_dir(profile_store_dir))?;
+        runs.sort_by_key(|run| std::cmp::Reverse(run.modified));
+        let advertised = fs::read_to_string(RunStore::current_run_path(profile_store_dir))
+            .ok()
+            .and_then(|json| CurrentRunSummary::parse_json(json).ok())
+            .map(|current_run| current_run.run.metadata.run_id);
+
+        // The report of the latest run refers to the newest finished run.
+        let newest_finished = runs.iter().position(|run| run.finished);
+        let now = SystemTime::now();
+        let age = |run: &StoredRun| now.duration_since(run.modified).unwrap_or_default();
+        let mut summary = RetentionSummary::default();
+        let mut full = false;
+        for (index, run) in runs.iter().enumerate() {
+            let in_progress = run.run_id.is_some() && run.run_id == advertised
+                || run.locked
+                || !run.finished && age(run) < UNFINISHED_GRACE_PERIOD;
+            let expired = self.max_age.is_some_and(|max_age| age(run) > max_age);
+            full = full
+                || self
+                    .max_runs
+                    .is_some_and(|max_runs| summary.kept_runs >= max_runs)
+                || self
+                    .max_size
+                    .is_some_and(|max_size| summary.kept_bytes + run.size > max_size.bytes());
+            if Some(index) == newest_finished || in_progress || !(expired || full) {
+                summary.kept_runs += 1;
+                summary.kept_bytes += run.size;
+                continue;
+            }
+            fs::remove_dir_all(&run.dir).map_err(|error| RunStoreError::Fs {
+                path: run.dir.clone(),
+                error,
+            })?;
+            summary.removed_runs += 1;
+            summary.removed_bytes += run.size;
+        }
+        Ok(summary)
+    }
+
+    /// Applies the policy to the store directory of every profile, and of nested invocations with
+    /// each profile, under the given store directory.
+    pub fn apply_all(&self, store_dir: &Utf8Path) -> Result<RetentionSummary, RunStoreError> {
+        let mut summary = RetentionSummary::default();
+        for profile_store_dir in subdirs(store_dir)? {
+            summary.add(&self.apply(&profile_store_dir)?);
+            for nested_dir in subdirs(&profile_store_dir)? {
+                if nested_dir
+                    .file_name()
+                    .is_some_and(|name| name.starts_with("nested-"))
+                {
+                    summary.add(&self.apply(&nested_dir)?);
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Returns the directories within `dir`, or nothing if it doesn't exist.
+fn subdirs(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>, RunStoreError> {
+    let fs_error = |error| RunStoreError::Fs {
+        path: dir.to_owned(),
+        error,
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(fs_error(error)),
+    };
+    let mut dirs = vec![];
+    for entry in entries {
+        let entry = entry.map_err(fs_error)?;
+        if !entry.file_type().map_err(fs_error)?.is_dir() {
+            continue;
+        }
+        // Directories that aren't valid UTF-8 weren't created by nextest.
+        if let Ok(path) = Utf8PathBuf::try_from(entry.path()) {
+            dirs.push(path);
+        }
+    }
+    Ok(dirs)
+}
+
+fn read_runs(runs_dir: &Utf8Path) -> Result<Vec<StoredRun>, RunStoreError> {
+    subdirs(runs_dir)?
+        .into_iter()
+        .map(|dir| {
+            let summary_path = dir.join(RunStore::SUMMARY_FILE_NAME);
+            let finished = summary_path.exists();
+            // A finished run's directory is last written to when its summary is. The directory of a
+            // run in progress doesn't have its own modification time updated when files deep
+            // within it are written to, so look for the newest file instead.
+            let modified = if finished {
+                fs::metadata(&summary_path).and_then(|metadata| metadata.modified())
+            } else {
+                newest_modified(&dir)
+            }
+            .map_err(|error| RunStoreError::Fs {
+                path: dir.clone(),
+                error,
+            })?;
+            Ok(StoredRun {
+                run_id: dir.file_name().and_then(|name| name.parse().ok()),
+                modified,
+                finished,
+                locked: is_locked(&dir.join(RunStore::LOCK_FILE_NAME)),
+                size: dir_size(&dir),
+                dir,
+            })
+        })
+        .collect()
+}
+
+/// Returns true if another process holds the lock on the given lock file.
+fn is_locked(lock_path: &Utf8Path) -> bool {
+    let lock = match fs::File::open(lock_path) {
+        Ok(lock) => lock,
+        Err(_) => return false,
+    };
+    match lock.try_lock_shared() {
+        Ok(()) => false,
+        Err(fs::TryLockError::WouldBlock) => true,
+        // If the lock can't be checked, err on the side of keeping the run.
+        Err(fs::TryLockError::Error(_)) => true,
+    }
+}
+
+/// Returns the newest modification time of `dir` and everything within it.
+///
+/// Files can be removed by a run in progress while this is looking at them, so anything that can't
+/// be read within `dir` is ignored.
+fn newest_modified(dir: &Utf8Path) -> io::Result<SystemTime> {
+    let mut newest = fs::symlink_metadata(dir)?.modified()?;
+    let mut dirs = vec![dir.as_std_path().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if let Ok(modified) = metadata.modified() {
+                newest = newest.max(modified);
+            }
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    Ok(newest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let profile_store_dir = store_dir.join("default");
+        let runs_dir = RunStore::runs_dir(&profile_store_dir);
+
+        // Five finished runs of 100 bytes each, from oldest to newest, and one unfinished run.
+        let run_ids: Vec<_> = (0..5).map(Uuid::from_u128).collect();
+        for (index, run_id) in run_ids.iter().enumerate() {
+            let run_dir = runs_dir.join(run_id.hyphenated().to_string());
+            fs::create_dir_all(&run_dir).unwrap();
+            fs::write(run_dir.join("output"), [0u8; 100]).unwrap();
+            let summary_path = run_dir.join(RunStore::SUMMARY_FILE_NAME);
+            fs::write(&summary_path, "{}").unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(3600 * (10 - index as u64));
+            fs::File::options()
+                .write(true)
+                .open(&summary_path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        fs::create_dir_all(runs_dir.join("in-progress")).unwrap();
+        let remaining = || {
+            let mut names: Vec<_> = subdirs(&runs_dir)
+                .unwrap()
+                .into_iter()
+                .map(|dir| dir.file_name().unwrap().to_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        let name = |index: usize| run_ids[index].hyphenated().to_string();
+
+        assert!(RetentionPolicy::default().keeps_everything());
+        let summary = RetentionPolicy::default()
+            .apply(&profile_store_dir)
+            .unwrap();
+        assert_eq!(summary.removed_runs, 0);
+        assert_eq!(summary.kept_runs, 6);
+
+        let max_age = RetentionPolicy {
+            max_age: Some(Duration::from_secs(3600 * 8 + 60)),
+            ..RetentionPolicy::default()
+        };
+        let summary = max_age.apply_all(&store_dir).unwrap();
+        assert_eq!(summary.removed_runs, 2, "runs 0 and 1 are too old");
+        assert_eq!(summary.removed_bytes, 2 * 102);
+
+        // The unfinished run and run 4 take up the first two spots.
+        let max_runs = RetentionPolicy {
+            max_runs: Some(2),
+            ..RetentionPolicy::default()
+        };
+        max_runs.apply(&profile_store_dir).unwrap();
+        let mut expected = vec![name(4), "in-progress".to_owned()];
+        expected.sort();
+        assert_eq!(remaining(), expected);
+
+        let max_size = RetentionPolicy {
+            max_size: Some(DiskQuota::new(0)),
+            ..RetentionPolicy::default()
+        };
+        let summary = max_size.apply(&profile_store_dir).unwrap();
+        assert_eq!(
+            summary.removed_runs, 0,
+            "the newest and in-progress runs are kept"
+        );
+        assert_eq!(remaining(), expected);
+    }
+
+    #[test]
+    fn unfinished_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let profile_store_dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let runs_dir = RunStore::runs_dir(&profile_store_dir);
+        let two_hours_ago = SystemTime::now() - Duration::from_secs(2 * 3600);
+        let make_run = |name: &str| {
+            let run_dir = runs_dir.join(name);
+            let log_path = run_dir.join("logs/my-crate/test.0.log");
+            fs::create_dir_all(log_path.parent().unwrap()).unwrap();
+            fs::write(&log_path, "log").unwrap();
+            let lock = fs::File::create(run_dir.join(RunStore::LOCK_FILE_NAME)).unwrap();
+            for path in [
+                log_path.as_path(),
+                log_path.parent().unwrap(),
+                &run_dir.join("logs"),
+                &run_dir.join(RunStore::LOCK_FILE_NAME),
+                &run_dir,
+            ] {
+                fs::File::open(path)
+                    .unwrap()
+                    .set_modified(two_hours_ago)
+                    .unwrap();
+            }
+            (run_dir, lock)
+        };
+
+        // A run that's still going, and one that crashed two hours ago.
+        let (live_dir, live_lock) = make_run("live");
+        live_lock.lock().unwrap();
+        let (crashed_dir, _crashed_lock) = make_run("crashed");
+        // A run that's still going, and was written to recently.
+        let (recent_dir, _recent_lock) = make_run("recent");
+        fs::write(recent_dir.join("logs/my-crate/test.1.log"), "log").unwrap();
+
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(60)),
+            ..RetentionPolicy::default()
+        };
+        let summary = policy.apply(&profile_store_dir).unwrap();
+        assert_eq!(summary.removed_runs, 1);
+        assert!(live_dir.exists(), "locked run is kept");
+        assert!(!crashed_dir.exists(), "unlocked old run is removed");
+        assert!(
+            recent_dir.exists(),
+            "run with a recently written file is kept"
+        );
+    }
+}
@@ -0,0 +1,391 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Persistent reports of the results of every test in a run.
+//!
+//! During a run, a [`RunReportRecorder`] saves the captured output of each test attempt to the
+//! run directory. At the end of the run, it writes out a [`RunReportSummary`] to
+//! [`RunStore::report_path`], referencing those files.
+//!
+//! Output is saved even if it was also written to the profile's `store.output-dir`, since that
+//! directory is cleared by the next run while the run directory is kept around.
+//!
+//! A report written out by a previous run can be read back and displayed with [`RunReport`].
+
+use crate::{
+    errors::RunStoreError,
+    helpers::write_test_name,
+    renames::TestRenames,
+    reporter::TestEvent,
+    run_store::{write_atomic, RunStore},
+    runner::{ExecuteStatus, ExecutionDescription, ExecutionResult, ExecutionStatuses},
+    test_list::{OutputFormat, TestInstance},
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::{
+    RunReportSummary, SkipReasonSummary, TestAttemptSummary, TestReportStatus, TestReportSummary,
+};
+use owo_colors::{OwoColorize, Style};
+use std::{fs, io, io::Write};
+
+/// Records the results of a run as events are produced, and writes out a report at the end.
+#[derive(Debug)]
+pub struct RunReportRecorder<'a> {
+    run_store: &'a RunStore,
+    tests: Vec<TestReportSummary>,
+}
+
+impl<'a> RunReportRecorder<'a> {
+    /// Creates a new recorder for the given run.
+    pub fn new(run_store: &'a RunStore) -> Self {
+        Self {
+            run_store,
+            tests: vec![],
+        }
+    }
+
+    /// Records a test event.
+    ///
+    /// Output captured for each test attempt is written out as it's received, and the report is
+    /// written out once the run finishes.
+    pub fn write_event(&mut self, event: &TestEvent<'_>) -> Result<(), RunStoreError> {
+        match event {
+            TestEvent::TestFinished {
+                test_instance,
+                run_statuses,
+            } => {
+                let (status, skip_reason) = test_status(run_statuses);
+                let attempts = run_statuses
+                    .iter()
+                    .map(|run_status| self.record_attempt(test_instance, run_status))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.tests.push(TestReportSummary {
+                    binary_id: test_instance.bin_info.binary_id.clone(),
+                    name: test_instance.name.to_owned(),
+                    variant: test_instance.variant.map(|variant| variant.to_string()),
+                    status,
+                    skip_reason,
+                    quarantined: run_statuses.is_quarantined(),
+                    time_taken: attempts.iter().map(|attempt| attempt.time_taken).sum(),
+                    attempts,
+                });
+            }
+            TestEvent::TestSkipped {
+                test_instance,
+                reason,
+            } => {
+                self.tests.push(TestReportSummary {
+                    binary_id: test_instance.bin_info.binary_id.clone(),
+                    name: test_instance.name.to_owned(),
+                    variant: test_instance.variant.map(|variant| variant.to_string()),
+                    status: TestReportStatus::Skipped,
+                    skip_reason: Some(SkipReasonSummary::from_filter(
+                        *reason,
+                        test_instance.test_info.ignore_reason.as_deref(),
+                    )),
+                    quarantined: false,
+                    time_taken: 0.0,
+                    attempts: vec![],
+                });
+            }
+            TestEvent::RunFinished {
+                elapsed, run_stats, ..
+            } => {
+                let mut tests = std::mem::take(&mut self.tests);
+                tests.sort_by(|a, b| {
+                    (&a.binary_id, &a.name, &a.variant).cmp(&(&b.binary_id, &b.name, &b.variant))
+                });
+                let summary = RunReportSummary {
+                    metadata: self.run_store.metadata().clone(),
+                    success: run_stats.is_success(),
+                    time_taken: elapsed.as_secs_f64(),
+                    tests,
+                };
+                let json = serde_json::to_vec_pretty(&summary).map_err(RunStoreError::Json)?;
+                write_atomic(&self.run_store.report_path(), &json)?;
+            }
+            TestEvent::RunStarted { .. }
+            | TestEvent::TestStarted { .. }
+            | TestEvent::TestSlow { .. }
+            | TestEvent::TestRetry { .. }
+            | TestEvent::TestGroupHookFailed { .. }
+            | TestEvent::RunBeginCancel { .. }
+            | TestEvent::RunSnapshot { .. }
+            | TestEvent::RunProgress { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn record_attempt(
+        &self,
+        test_instance: &TestInstance<'_>,
+        run_status: &ExecuteStatus,
+    ) -> Result<TestAttemptSummary, RunStoreError> {
+        let save_output = |output: &[u8], extension: &str| {
+            if output.is_empty() {
+                return Ok(None);
+            }
+            let path = self.run_store.test_file_path(
+                "output",
+                test_instance,
+                run_status.attempt,
+                extension,
+            )?;
+            fs::write(&path, output).map_err(|error| RunStoreError::Fs {
+                path: path.clone(),
+                error,
+            })?;
+            Ok(Some(path))
+        };
+
+        Ok(TestAttemptSummary {
+            attempt: run_status.attempt,
+            status: attempt_status(run_status.result),
+            time_taken: run_status.time_taken.as_secs_f64(),
+            stdout_path: save_output(run_status.stdout(), "stdout")?,
+            stderr_path: save_output(run_status.stderr(), "stderr")?,
+            attachments: run_status
+                .attachments
+                .iter()
+                .map(|attachment| attachment.path.clone())
+                .collect(),
+            abort_reason: run_status
+                .abort_status
+                .map(|abort_status| abort_status.to_string()),
+            core_dump_path: run_status.core_dump.clone(),
+            sanitizer_summary: run_status
+                .sanitizer_report
+                .as_ref()
+                .map(|report| report.summary.clone()),
+            coverage_profile_path: run_status.coverage_profile.clone(),
+            property_test: run_status
+                .property_test_failure
+                .as_ref()
+                .map(|failure| failure.to_summary()),
+            peak_rss: run_status.peak_rss,
+        })
+    }
+}
+
+/// Returns the overall status of a test that finished running, and why it was skipped if it skipped
+/// itself at runtime.
+pub(crate) fn test_status(
+    run_statuses: &ExecutionStatuses,
+) -> (TestReportStatus, Option<SkipReasonSummary>) {
+    match run_statuses.describe() {
+        ExecutionDescription::Success { .. } => (TestReportStatus::Pass, None),
+        ExecutionDescription::Flaky { .. } => (TestReportStatus::Flaky, None),
+        ExecutionDescription::Failure { last_status, .. } => {
+            (attempt_status(last_status.result), None)
+        }
+        ExecutionDescription::Skipped { last_status, .. } => (
+            TestReportStatus::Skipped,
+            Some(SkipReasonSummary::runtime(
+                last_status.skip_reason.as_deref(),
+            )),
+        ),
+    }
+}
+
+pub(crate) fn attempt_status(result: ExecutionResult) -> TestReportStatus {
+    match result {
+        ExecutionResult::Pass => TestReportStatus::Pass,
+        ExecutionResult::Fail => TestReportStatus::Fail,
+        ExecutionResult::ExecFail => TestReportStatus::ExecFail,
+        ExecutionResult::Skip => TestReportStatus::Skipped,
+        ExecutionResult::DiskQuotaExceeded => TestReportStatus::DiskQuotaExceeded,
+        ExecutionResult::Timeout => TestReportStatus::Timeout,
+        ExecutionResult::ExpectedFail => TestReportStatus::ExpectedFail,
+        ExecutionResult::UnexpectedPass => TestReportStatus::UnexpectedPass,
+    }
+}
+
+/// A report written out by a previous run, read back for display.
+#[derive(Clone, Debug)]
+pub struct RunReport {
+    summary: RunReportSummary,
+    styles: Box<Styles>,
+}
+
+impl RunReport {
+    /// Reads the report at the given path.
+    pub fn read(path: &Utf8Path) -> Result<Self, RunStoreError> {
+        let json = fs::read_to_string(path).map_err(|error| RunStoreError::Fs {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let summary = RunReportSummary::parse_json(json).map_err(RunStoreError::Json)?;
+        Ok(Self {
+            summary,
+            styles: Box::new(Styles::default()),
+        })
+    }
+
+    /// Returns the underlying summary.
+    pub fn summary(&self) -> &RunReportSummary {
+        &self.summary
+    }
+
+    /// Renames the tests in this report to their current names.
+    ///
+    /// See [`renames`](crate::renames) for more.
+    pub fn apply_renames(&mut self, renames: &TestRenames) {
+        renames.apply_to_report(&mut self.summary);
+    }
+
+    /// Retains only the tests with one of the given statuses, and whose names contain one of the
+    /// given strings.
+    ///
+    /// An empty list of statuses or names matches everything.
+    pub fn filter(&mut self, statuses: &[TestReportStatus], names: &[impl AsRef<str>]) {
+        self.summary.tests.retain(|test| {
+            (statuses.is_empty() || statuses.contains(&test.status))
+                && (names.is_empty() || names.iter().any(|name| test.name.contains(name.as_ref())))
+        });
+    }
+
+    /// Colorizes output.
+    pub fn colorize(&mut self) {
+        self.styles.colorize();
+    }
+
+    /// Writes out the report in the given format.
+    pub fn write(
+        &self,
+        output_format: OutputFormat,
+        writer: impl Write,
+    ) -> Result<(), RunStoreError> {
+        match output_format {
+            OutputFormat::Plain => self.write_plain(writer).map_err(RunStoreError::Io),
+            OutputFormat::Serializable(format) => format
+                .to_writer(&self.summary, writer)
+                .map_err(RunStoreError::Json),
+        }
+    }
+
+    fn write_plain(&self, mut writer: impl Write) -> io::Result<()> {
+        let metadata = &self.summary.metadata;
+        let (result_style, result_str) = if self.summary.success {
+            (self.styles.pass, "passed")
+        } else {
+            (self.styles.fail, "failed")
+        };
+        writeln!(
+            writer,
+            "{:>12} {} (nextest {}, profile {}): {} in {:.3}s",
+            "Run ID".style(result_style),
+            metadata.run_id,
+            metadata.nextest_version,
+            metadata.profile_name.style(self.styles.count),
+            result_str.style(result_style),
+            self.summary.time_taken,
+        )?;
+
+        let binary_id_width = self
+            .summary
+            .tests
+            .iter()
+            .map(|test| test.binary_id.len())
+            .max()
+            .unwrap_or_default();
+
+        for test in &self.summary.tests {
+            let (status_str, style) = match test.status {
+                TestReportStatus::Pass => ("PASS", self.styles.pass),
+                TestReportStatus::Flaky => ("FLAKY", self.styles.retry),
+                TestReportStatus::Fail => ("FAIL", self.styles.fail),
+                TestReportStatus::ExecFail => ("EXECFAIL", self.styles.fail),
+                TestReportStatus::Skipped => ("SKIP", self.styles.skip),
+                TestReportStatus::DiskQuotaExceeded => ("QUOTA", self.styles.fail),
+                TestReportStatus::Timeout => ("TIMEOUT", self.styles.fail),
+                TestReportStatus::ExpectedFail => ("EXPECT FAIL", self.styles.pass),
+                TestReportStatus::UnexpectedPass => ("UNEXP PASS", self.styles.fail),
+            };
+            write!(writer, "{:>12} ", status_str.style(style))?;
+            if test.attempts.is_empty() {
+                write!(writer, "[         ] ")?;
+            } else {
+                write!(writer, "[{:>8.3}s] ", test.time_taken)?;
+            }
+            write!(
+                writer,
+                "{:>width$} ",
+                test.binary_id.style(self.styles.binary_id),
+                width = binary_id_width
+            )?;
+            write_test_name(&test.name, self.styles.test_name, &mut writer)?;
+            if let Some(variant) = &test.variant {
+                write!(writer, " [{}]", variant)?;
+            }
+            if test.attempts.len() > 1 {
+                write!(writer, " ({} attempts)", test.attempts.len())?;
+            }
+            if let Some(skip_reason) = &test.skip_reason {
+                write!(writer, ": {}", skip_reason.message)?;
+            }
+            if let Some(summary) = test
+                .attempts
+                .last()
+                .and_then(|attempt| attempt.sanitizer_summary.as_ref())
+                .filter(|_| !test.status.is_success())
+            {
+                write!(writer, ": {}", summary.style(self.styles.fail))?;
+            }
+            writeln!(writer)?;
+
+            // For failed tests, point to the output of the last attempt.
+            if !test.status.is_success() {
+                if let Some(last_attempt) = test.attempts.last() {
+                    let paths = [
+                        ("stdout", last_attempt.stdout_path.as_ref()),
+                        ("stderr", last_attempt.stderr_path.as_ref()),
+                        ("core", last_attempt.core_dump_path.as_ref()),
+                    ];
+                    for (name, path) in paths {
+                        if let Some(path) = path {
+                            self.write_path(name, path, &mut writer)?;
+                        }
+                    }
+                    for path in &last_attempt.attachments {
+                        self.write_path("log", path, &mut writer)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_path(&self, name: &str, path: &Utf8PathBuf, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{:>12} {}: {}",
+            "",
+            name.style(self.styles.count),
+            path
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Styles {
+    count: Style,
+    pass: Style,
+    retry: Style,
+    fail: Style,
+    skip: Style,
+    binary_id: Style,
+    test_name: Style,
+}
+
+impl Styles {
+    fn colorize(&mut self) {
+        self.count = Style::new().bold();
+        self.pass = Style::new().green().bold();
+        self.retry = Style::new().magenta().bold();
+        self.fail = Style::new().red().bold();
+        self.skip = Style::new().yellow().bold();
+        self.binary_id = Style::new().magenta().bold();
+        self.test_name = Style::new().blue().bold();
+    }
+}
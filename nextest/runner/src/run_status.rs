@@ -0,0 +1,507 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Following a run in progress from another process.
+//!
+//! While `cargo nextest run` is running, it listens on a Unix domain socket and advertises it in
+//! `current-run.json` in the profile's store directory (see [`CurrentRunSummary`]). `cargo nextest
+//! status` reads that file, connects to the socket, and prints the progress of the run as it
+//! happens, so that a long local run can be followed from a second terminal or a CI sidecar:
+//!
+//! ```sh
+//! cargo nextest status --profile ci
+//! ```
+//!
+//! Each client is sent the [`RunEvent`]s published so far, followed by new events as they happen,
+//! one line of JSON each. The output of tests isn't sent. Clients that attach late see every test
+//! that has already finished, and clients that disconnect don't affect the run.
+//!
+//! This is only supported on Unix. It's independent of `--event-stream`, where nextest connects to
+//! a socket that another tool listens on.
+
+use crate::{
+    errors::RunStatusError,
+    event_stream::to_run_events,
+    helpers::write_test_name,
+    reporter::TestEvent,
+    run_store::{write_atomic, RunStore},
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::{CurrentRunSummary, RunEvent, TestEventId, TestReportStatus};
+use owo_colors::{OwoColorize, Style};
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+/// Serves the events of a run to clients that attach to it while it's in progress.
+///
+/// The run is advertised in the profile's store directory until this is dropped.
+pub struct StatusServer<'a> {
+    run_store: &'a RunStore,
+    socket_path: Utf8PathBuf,
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    // Every event published so far, as lines of JSON, replayed to clients as they attach.
+    history: Vec<u8>,
+    clients: Vec<Box<dyn Write + Send>>,
+    closed: bool,
+}
+
+impl<'a> StatusServer<'a> {
+    /// Starts listening for clients, and advertises the run in the profile's store directory.
+    pub fn start(run_store: &'a RunStore) -> Result<Self, RunStatusError> {
+        // Socket paths are limited to around 100 bytes, so the socket can't live in the store
+        // directory.
+        let socket_path = Utf8PathBuf::try_from(std::env::temp_dir())
+            .unwrap_or_else(|_| "/tmp".into())
+            .join(format!("nextest-{}.sock", run_store.run_id().simple()));
+        if cfg!(not(unix)) {
+            return Err(RunStatusError::Unsupported);
+        }
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        listen(&socket_path, shared.clone()).map_err(|error| RunStatusError::Bind {
+            path: socket_path.clone(),
+            error,
+        })?;
+
+        let current_run = CurrentRunSummary {
+            run: run_store.info_summary(),
+            pid: std::process::id(),
+            socket_path: socket_path.clone(),
+        };
+        let json = serde_json::to_vec(&current_run).expect("current run is serializable");
+        let server = Self {
+            run_store,
+            socket_path,
+            shared,
+        };
+        write_atomic(&run_store.own_current_run_path(), &json)
+            .map_err(RunStatusError::Advertise)?;
+        Ok(server)
+    }
+
+    /// Sends a test event to the clients that are attached, as zero or more [`RunEvent`]s.
+    ///
+    /// Clients that have disconnected are dropped.
+    pub fn write_event(&mut self, event: &TestEvent<'_>) {
+        let mut lines = vec![];
+        for run_event in to_run_events(self.run_store, event) {
+            if matches!(run_event, RunEvent::TestOutput { .. }) {
+                continue;
+            }
+            serde_json::to_writer(&mut lines, &run_event).expect("run event is serializable");
+            lines.push(b'\n');
+        }
+        if lines.is_empty() {
+            return;
+        }
+
+        let mut shared = self.shared.lock().expect("lock is not poisoned");
+        shared.history.extend_from_slice(&lines);
+        shared.clients.retain_mut(|client| {
+            client
+                .write_all(&lines)
+                .and_then(|()| client.flush())
+                .is_ok()
+        });
+    }
+}
+
+impl<'a> fmt::Debug for StatusServer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StatusServer")
+            .field("run_store", &self.run_store)
+            .field("socket_path", &self.socket_path)
+            .finish()
+    }
+}
+
+impl<'a> Drop for StatusServer<'a> {
+    fn drop(&mut self) {
+        {
+            let mut shared = self.shared.lock().expect("lock is not poisoned");
+            shared.closed = true;
+            // Dropping the connections lets clients know that the run is over.
+            shared.clients.clear();
+        }
+        // Wake up the thread accepting clients, so that it sees that the server is closed.
+        let _ = connect(&self.socket_path);
+        let _ = fs::remove_file(&self.socket_path);
+
+        // A later run with the same profile may have replaced the advertisement.
+        let current_run_path = self.run_store.own_current_run_path();
+        let is_current = fs::read_to_string(&current_run_path)
+            .ok()
+            .and_then(|json| CurrentRunSummary::parse_json(json).ok())
+            .is_some_and(|current_run| current_run.run.metadata.run_id == self.run_store.run_id());
+        if is_current {
+            let _ = fs::remove_file(&current_run_path);
+        }
+    }
+}
+
+/// Follows the events of a run in progress, as a client of its [`StatusServer`].
+pub struct RunWatcher {
+    current_run: CurrentRunSummary,
+    reader: BufReader<Box<dyn Read + Send>>,
+}
+
+impl RunWatcher {
+    /// Attaches to the run in progress advertised in the given profile store directory.
+    pub fn attach(profile_store_dir: &Utf8Path) -> Result<Self, RunStatusError> {
+        let path = RunStore::current_run_path(profile_store_dir);
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(RunStatusError::NotRunning { path })
+            }
+            Err(error) => return Err(RunStatusError::Read(error)),
+        };
+        let current_run =
+            CurrentRunSummary::parse_json(&json).map_err(|error| RunStatusError::Parse {
+                path: path.clone(),
+                error,
+            })?;
+        // The run may have been killed before it could remove the advertisement.
+        let stream =
+            connect(&current_run.socket_path).map_err(|_| RunStatusError::NotRunning { path })?;
+        Ok(Self {
+            current_run,
+            reader: BufReader::new(stream),
+        })
+    }
+
+    /// Returns the run that's being followed.
+    pub fn current_run(&self) -> &CurrentRunSummary {
+        &self.current_run
+    }
+
+    /// Waits for the next event of the run, returning `None` once the run is over.
+    pub fn next_event(&mut self) -> Result<Option<RunEvent>, RunStatusError> {
+        read_event(&mut self.reader).map_err(RunStatusError::Read)
+    }
+}
+
+impl fmt::Debug for RunWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RunWatcher")
+            .field("current_run", &self.current_run)
+            .finish()
+    }
+}
+
+/// Reads the next event, skipping lines that aren't recognized.
+fn read_event(mut reader: impl BufRead) -> io::Result<Option<RunEvent>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if let Ok(event) = RunEvent::parse_json(&line) {
+            return Ok(Some(event));
+        }
+    }
+}
+
+/// Prints the progress of a run from its [`RunEvent`]s, for `cargo nextest status`.
+#[derive(Debug, Default)]
+pub struct RunStatusPrinter {
+    test_count: usize,
+    finished: usize,
+    styles: Box<Styles>,
+}
+
+impl RunStatusPrinter {
+    /// Creates a new printer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Colorizes output.
+    pub fn colorize(&mut self) {
+        self.styles.colorize();
+    }
+
+    /// Writes out the progress made by an event, if any.
+    pub fn write_event(&mut self, event: &RunEvent, mut writer: impl Write) -> io::Result<()> {
+        match event {
+            RunEvent::RunStarted { run, test_count } => {
+                self.test_count = *test_count;
+                writeln!(
+                    writer,
+                    "{:>12} run {} (profile {}): {} tests",
+                    "Attached".style(self.styles.pass),
+                    run.metadata.run_id,
+                    run.metadata.profile_name.style(self.styles.count),
+                    test_count.style(self.styles.count),
+                )?;
+            }
+            RunEvent::TestSlow { test, elapsed } => {
+                write!(
+                    writer,
+                    "{:>12} [{:>8.3}s] ",
+                    "SLOW".style(self.styles.skip),
+                    elapsed
+                )?;
+                self.write_test(test, &mut writer)?;
+            }
+            RunEvent::TestRetry {
+                test,
+                attempt,
+                time_taken,
+                ..
+            } => {
+                write!(
+                    writer,
+                    "{:>12} [{:>8.3}s] ",
+                    format!("TRY {} FAIL", attempt).style(self.styles.retry),
+                    time_taken
+                )?;
+                self.write_test(test, &mut writer)?;
+            }
+            RunEvent::TestFinished {
+                test,
+                status,
+                time_taken,
+                ..
+            } => {
+                self.finished += 1;
+                let (status_str, style) = match status {
+                    TestReportStatus::Pass => ("PASS", self.styles.pass),
+                    TestReportStatus::Flaky => ("FLAKY", self.styles.retry),
+                    TestReportStatus::Fail => ("FAIL", self.styles.fail),
+                    TestReportStatus::ExecFail => ("EXECFAIL", self.styles.fail),
+                    TestReportStatus::Skipped => ("SKIP", self.styles.skip),
+                    TestReportStatus::DiskQuotaExceeded => ("QUOTA", self.styles.fail),
+                    TestReportStatus::Timeout => ("TIMEOUT", self.styles.fail),
+                    TestReportStatus::ExpectedFail => ("EXPECT FAIL", self.styles.pass),
+                    TestReportStatus::UnexpectedPass => ("UNEXP PASS", self.styles.fail),
+                };
+                let count_width = self.test_count.to_string().len();
+                write!(
+                    writer,
+                    "{:>12} [{:>8.3}s] [{:>width$}/{}] ",
+                    status_str.style(style),
+                    time_taken,
+                    self.finished,
+                    self.test_count,
+                    width = count_width,
+                )?;
+                self.write_test(test, &mut writer)?;
+            }
+            RunEvent::RunCanceled { reason, running } => {
+                writeln!(
+                    writer,
+                    "{:>12} due to {}: {} tests still running",
+                    "Canceling".style(self.styles.fail),
+                    reason,
+                    running.style(self.styles.count),
+                )?;
+            }
+            RunEvent::RunFinished {
+                summary,
+                time_taken,
+            } => {
+                let style = if summary.success {
+                    self.styles.pass
+                } else {
+                    self.styles.fail
+                };
+                writeln!(
+                    writer,
+                    "{:>12} [{:>8.3}s] {} tests run: {} passed, {} failed, {} skipped",
+                    "Summary".style(style),
+                    time_taken,
+                    summary.final_run_count.style(self.styles.count),
+                    summary.passed.style(self.styles.pass),
+                    (summary.failed + summary.exec_failed + summary.timed_out)
+                        .style(self.styles.fail),
+                    (summary.skipped + summary.runtime_skipped).style(self.styles.skip),
+                )?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn write_test(&self, test: &TestEventId, mut writer: impl Write) -> io::Result<()> {
+        write!(writer, "{} ", test.binary_id.style(self.styles.binary_id))?;
+        write_test_name(&test.name, self.styles.test_name, &mut writer)?;
+        if let Some(variant) = &test.variant {
+            write!(writer, " [{}]", variant)?;
+        }
+        writeln!(writer)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Styles {
+    count: Style,
+    pass: Style,
+    retry: Style,
+    fail: Style,
+    skip: Style,
+    binary_id: Style,
+    test_name: Style,
+}
+
+impl Styles {
+    fn colorize(&mut self) {
+        self.count = Style::new().bold();
+        self.pass = Style::new().green().bold();
+        self.retry = Style::new().magenta().bold();
+        self.fail = Style::new().red().bold();
+        self.skip = Style::new().yellow().bold();
+        self.binary_id = Style::new().magenta().bold();
+        self.test_name = Style::new().blue().bold();
+    }
+}
+
+/// Accepts clients on a new thread, sending each one the events published so far.
+#[cfg(unix)]
+fn listen(socket_path: &Utf8Path, shared: Arc<Mutex<Shared>>) -> io::Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    // Left over from an earlier run with the same ID, which is very unlikely.
+    let _ = fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut shared = shared.lock().expect("lock is not poisoned");
+            if shared.closed {
+                break;
+            }
+            if let Ok(mut stream) = stream {
+                if stream.write_all(&shared.history).is_ok() {
+                    shared.clients.push(Box::new(stream));
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen(_socket_path: &Utf8Path, _shared: Arc<Mutex<Shared>>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix domain sockets are not supported",
+    ))
+}
+
+#[cfg(unix)]
+fn connect(socket_path: &Utf8Path) -> io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(std::os::unix::net::UnixStream::connect(
+        socket_path,
+    )?))
+}
+
+#[cfg(not(unix))]
+fn connect(_socket_path: &Utf8Path) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix domain sockets are not supported",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nextest_metadata::{RunInfoSummary, RunMetadata, RunSummary};
+
+    #[test]
+    fn print_run_status() {
+        let metadata = RunMetadata {
+            run_id: Default::default(),
+            nextest_version: "0.1.0".to_owned(),
+            profile_name: "default".to_owned(),
+            host_triple: None,
+            target_triple: None,
+            invocation: vec![],
+        };
+        let test = |name: &str| TestEventId {
+            binary_id: "my-crate".to_owned(),
+            name: name.to_owned(),
+            variant: None,
+        };
+        let run_started = RunEvent::RunStarted {
+            run: RunInfoSummary {
+                metadata: metadata.clone(),
+                run_dir: "/store/runs/0".into(),
+                summary_path: "/store/runs/0/summary.json".into(),
+                junit_path: None,
+                report_path: "/store/run-report.json".into(),
+                env_files: vec![],
+            },
+            test_count: 2,
+        };
+        let finished = |name: &str, status| RunEvent::TestFinished {
+            test: test(name),
+            status,
+            skip_reason: None,
+            attempts: 1,
+            time_taken: 0.5,
+        };
+        let summary = RunSummary {
+            metadata: metadata.clone(),
+            success: false,
+            initial_run_count: 2,
+            final_run_count: 2,
+            passed: 1,
+            runtime_skipped: 0,
+            flaky: 0,
+            failed: 1,
+            exec_failed: 0,
+            disk_quota_exceeded: 0,
+            timed_out: 0,
+            quarantined: 0,
+            expected_failures: 0,
+            unexpected_passes: 0,
+            skipped: 0,
+            overflowed: 0,
+            timing_predictions: 0,
+            timing_misses: 0,
+            skipped_binaries: 0,
+            environment: None,
+        };
+
+        // Events are read back the way they're sent, skipping lines that aren't recognized.
+        let mut stream = vec![];
+        for event in [
+            run_started,
+            RunEvent::TestStarted { test: test("a") },
+            finished("a", TestReportStatus::Pass),
+            finished("tests::b", TestReportStatus::Fail),
+            RunEvent::RunFinished {
+                summary,
+                time_taken: 1.25,
+            },
+        ] {
+            serde_json::to_writer(&mut stream, &event).unwrap();
+            stream.push(b'\n');
+            stream.extend_from_slice(b"{\"type\": \"from-the-future\"}\n");
+        }
+        let mut reader = stream.as_slice();
+        let mut printer = RunStatusPrinter::new();
+        let mut output = vec![];
+        while let Some(event) = read_event(&mut reader).unwrap() {
+            printer.write_event(&event, &mut output).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "    Attached run {} (profile default): 2 tests\n        \
+                 PASS [   0.500s] [1/2] my-crate a\n        \
+                 FAIL [   0.500s] [2/2] my-crate tests::b\n     \
+                 Summary [   1.250s] 2 tests run: 1 passed, 1 failed, 0 skipped\n",
+                metadata.run_id
+            )
+        );
+    }
+}
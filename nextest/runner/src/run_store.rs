@@ -0,0 +1,580 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Storage for artifacts specific to a single test run.
+//!
+//! Each run is assigned a unique ID, and artifacts for it are written to
+//! `<store-dir>/runs/<run-id>`. Information about where these artifacts are written is available
+//! as soon as the run starts, through [`RunStore::write_info`].
+//!
+//! # Test log files
+//!
+//! Each test is run with [`NEXTEST_TEST_LOG_FILE_ENV`] set to a path within the run directory.
+//! Tests can write verbose logs to this file instead of to standard output. Anything written to it
+//! is collected as an attachment to the test's result, and shown alongside the test's output.
+//!
+//! # Temporary directories
+//!
+//! Each test is run with [`NEXTEST_TEST_TMPDIR_ENV`] set to an empty directory within the run
+//! directory, which is removed once the test finishes. Nextest keeps track of how much each test
+//! writes to it; see [`disk_usage`](crate::disk_usage) for more.
+//!
+//! # Output files
+//!
+//! If the profile's `store.output-dir` is set, the standard output and standard error of each test
+//! attempt are also written to files in that directory, at paths that only depend on the test and
+//! the attempt. This makes it easy for CI systems to pick them up, without knowing the ID of the
+//! run. Output files written by earlier runs are removed when a run is created, so that the
+//! directory only contains output from the latest run. Anything else in the directory is left
+//! alone.
+//!
+//! # Skipping tests at runtime
+//!
+//! Each test is also run with [`NEXTEST_SKIP_FILE_ENV`] set to a path within the run directory. A
+//! test that can't run in the current environment (for example, because it needs a GPU) can
+//! create this file and then exit successfully. Nextest reports the test as skipped rather than
+//! passed, using the contents of the file as the reason, and counts it separately in summaries.
+//!
+//! ```no_run
+//! if let Ok(skip_file) = std::env::var("NEXTEST_SKIP_FILE") {
+//!     // ... if the test can't run here:
+//!     std::fs::write(skip_file, "no GPU available").unwrap();
+//!     return;
+//! }
+//! ```
+
+use crate::{
+    config::NextestProfile, env_file::EnvFiles, errors::RunStoreError, runner::RunStats,
+    test_list::TestInstance,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::{
+    EnvFileSummary, EnvironmentSummary, RunInfoSummary, RunMetadata, RunSummary,
+};
+use std::{
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, Write},
+    sync::Arc,
+};
+use twox_hash::XxHash64;
+use uuid::Uuid;
+
+/// The environment variable that names the file information about a run is written to at its
+/// start.
+pub const NEXTEST_RUN_INFO_FILE_ENV: &str = "NEXTEST_RUN_INFO_FILE";
+
+/// The environment variable nextest sets to the path of a file each test can write logs to.
+pub const NEXTEST_TEST_LOG_FILE_ENV: &str = "NEXTEST_TEST_LOG_FILE";
+
+/// The environment variable nextest sets to the path of an empty directory each test can write
+/// temporary files to.
+pub const NEXTEST_TEST_TMPDIR_ENV: &str = "NEXTEST_TEST_TMPDIR";
+
+/// The environment variable nextest sets to the path of a file each test can create to mark itself
+/// as skipped at runtime.
+pub const NEXTEST_SKIP_FILE_ENV: &str = "NEXTEST_SKIP_FILE";
+
+/// Storage for a single test run, under the profile's store directory.
+#[derive(Clone, Debug)]
+pub struct RunStore {
+    metadata: RunMetadata,
+    profile_store_dir: Utf8PathBuf,
+    run_dir: Utf8PathBuf,
+    output_dir: Option<Utf8PathBuf>,
+    junit_path: Option<Utf8PathBuf>,
+    env_files: Vec<EnvFileSummary>,
+    environment: Option<EnvironmentSummary>,
+    // Held for as long as the run is in progress, so that its directory isn't removed from under it.
+    _lock: Arc<File>,
+}
+
+impl RunStore {
+    /// The name of the file the final summary of a run is written to, within the run directory.
+    pub const SUMMARY_FILE_NAME: &'static str = "summary.json";
+
+    /// The name of the file the report for the latest run is written to, within the profile's
+    /// store directory.
+    pub const REPORT_FILE_NAME: &'static str = "run-report.json";
+
+    /// The name of the file that advertises a run while it's in progress, within the profile's
+    /// store directory.
+    ///
+    /// See [`run_status`](crate::run_status) for more.
+    pub const CURRENT_RUN_FILE_NAME: &'static str = "current-run.json";
+
+    /// The name of the file a run holds an exclusive lock on while it's in progress, within the run
+    /// directory.
+    ///
+    /// See [`retention`](crate::retention) for more.
+    pub const LOCK_FILE_NAME: &'static str = "run.lock";
+
+    /// Creates a new run with a freshly generated ID, and creates its directory on disk.
+    pub fn create(profile: &NextestProfile<'_>) -> Result<Self, RunStoreError> {
+        let run_id = Uuid::new_v4();
+        let run_dir = Self::runs_dir(profile.store_dir()).join(run_id.hyphenated().to_string());
+        fs::create_dir_all(&run_dir).map_err(|error| RunStoreError::Fs {
+            path: run_dir.clone(),
+            error,
+        })?;
+        let lock_path = run_dir.join(Self::LOCK_FILE_NAME);
+        let lock = File::create(&lock_path)
+            .and_then(|lock| lock.lock().map(|()| lock))
+            .map_err(|error| RunStoreError::Fs {
+                path: lock_path,
+                error,
+            })?;
+        let output_dir = profile.output_dir();
+        if let Some(output_dir) = &output_dir {
+            clear_output_dir(output_dir)?;
+            fs::create_dir_all(output_dir).map_err(|error| RunStoreError::Fs {
+                path: output_dir.clone(),
+                error,
+            })?;
+        }
+
+        Ok(Self {
+            metadata: RunMetadata {
+                run_id,
+                nextest_version: env!("CARGO_PKG_VERSION").to_owned(),
+                profile_name: profile.name().to_owned(),
+                host_triple: None,
+                target_triple: None,
+                invocation: std::env::args_os()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect(),
+            },
+            profile_store_dir: profile.store_dir().to_path_buf(),
+            run_dir,
+            output_dir,
+            junit_path: profile.junit().map(|junit| junit.path().to_path_buf()),
+            env_files: vec![],
+            environment: None,
+            _lock: Arc::new(lock),
+        })
+    }
+
+    /// Records the dotenv files loaded for this run in its info summary.
+    pub fn set_env_files(&mut self, env_files: &EnvFiles) -> &mut Self {
+        self.env_files = env_files.summaries();
+        self
+    }
+
+    /// Records the environment this run is performed in, in its final summary.
+    ///
+    /// See [`env_diff`](crate::env_diff) for more.
+    pub fn set_environment(&mut self, environment: EnvironmentSummary) -> &mut Self {
+        self.environment = Some(environment);
+        self
+    }
+
+    /// Returns the unique ID of this run.
+    pub fn run_id(&self) -> Uuid {
+        self.metadata.run_id
+    }
+
+    /// Returns information identifying this run.
+    pub fn metadata(&self) -> &RunMetadata {
+        &self.metadata
+    }
+
+    /// Sets the version of nextest recorded for this run.
+    ///
+    /// Defaults to the version of nextest-runner.
+    pub fn set_nextest_version(&mut self, nextest_version: impl Into<String>) -> &mut Self {
+        self.metadata.nextest_version = nextest_version.into();
+        self
+    }
+
+    /// Sets the host and target triples recorded for this run.
+    pub fn set_triples(
+        &mut self,
+        host_triple: Option<String>,
+        target_triple: Option<String>,
+    ) -> &mut Self {
+        self.metadata.host_triple = host_triple;
+        self.metadata.target_triple = target_triple;
+        self
+    }
+
+    /// Returns the directory artifacts for this run are written to.
+    pub fn run_dir(&self) -> &Utf8Path {
+        &self.run_dir
+    }
+
+    /// Returns the path the final summary of this run is written to.
+    pub fn summary_path(&self) -> Utf8PathBuf {
+        self.run_dir.join(Self::SUMMARY_FILE_NAME)
+    }
+
+    /// Returns the path the report for this run is written to at the end of the run.
+    ///
+    /// This is within the profile's store directory rather than the run directory, so that the
+    /// report for the latest run is always at the same path.
+    pub fn report_path(&self) -> Utf8PathBuf {
+        Self::latest_report_path(&self.profile_store_dir)
+    }
+
+    /// Returns the path the report for the latest run with the given profile store directory is
+    /// written to.
+    pub fn latest_report_path(profile_store_dir: &Utf8Path) -> Utf8PathBuf {
+        profile_store_dir.join(Self::REPORT_FILE_NAME)
+    }
+
+    /// Returns the directory the directories of runs with the given profile store directory are
+    /// created in.
+    pub fn runs_dir(profile_store_dir: &Utf8Path) -> Utf8PathBuf {
+        profile_store_dir.join("runs")
+    }
+
+    /// Returns the path the run in progress with the given profile store directory is advertised
+    /// at.
+    pub fn current_run_path(profile_store_dir: &Utf8Path) -> Utf8PathBuf {
+        profile_store_dir.join(Self::CURRENT_RUN_FILE_NAME)
+    }
+
+    /// Returns the path this run is advertised at while it's in progress.
+    pub(crate) fn own_current_run_path(&self) -> Utf8PathBuf {
+        Self::current_run_path(&self.profile_store_dir)
+    }
+
+    /// Returns the path the given attempt of a test can write logs to.
+    ///
+    /// The file isn't created by this method, but its parent directory is.
+    pub fn test_log_path(
+        &self,
+        test_instance: &TestInstance<'_>,
+        attempt: usize,
+    ) -> Result<Utf8PathBuf, RunStoreError> {
+        self.test_file_path("logs", test_instance, attempt, "log")
+    }
+
+    /// Returns the path of an empty directory the given attempt of a test can write temporary
+    /// files to.
+    ///
+    /// The directory is created by this method, and anything left over in it from a previous run
+    /// with the same ID is removed.
+    pub fn test_tmp_dir(
+        &self,
+        test_instance: &TestInstance<'_>,
+        attempt: usize,
+    ) -> Result<Utf8PathBuf, RunStoreError> {
+        let tmp_dir = self.test_file_path("tmp", test_instance, attempt, "tmp")?;
+        let _ = fs::remove_dir_all(&tmp_dir);
+        fs::create_dir(&tmp_dir).map_err(|error| RunStoreError::Fs {
+            path: tmp_dir.clone(),
+            error,
+        })?;
+        Ok(tmp_dir)
+    }
+
+    /// Returns the path the given output stream of a test attempt is written to, if the profile
+    /// sets `store.output-dir`. `extension` is either `"stdout"` or `"stderr"`.
+    ///
+    /// Unlike other paths, this path doesn't depend on the ID of the run. The file isn't created by
+    /// this method, but its parent directory is.
+    pub fn test_output_path(
+        &self,
+        test_instance: &TestInstance<'_>,
+        attempt: usize,
+        extension: &str,
+    ) -> Result<Option<Utf8PathBuf>, RunStoreError> {
+        self.output_dir
+            .as_deref()
+            .map(|output_dir| file_path_in(output_dir, test_instance, attempt, extension))
+            .transpose()
+    }
+
+    /// Returns the path the given attempt of a test can create to mark itself as skipped.
+    ///
+    /// The file isn't created by this method, but its parent directory is.
+    pub fn test_skip_path(
+        &self,
+        test_instance: &TestInstance<'_>,
+        attempt: usize,
+    ) -> Result<Utf8PathBuf, RunStoreError> {
+        self.test_file_path("skips", test_instance, attempt, "skip")
+    }
+
+    /// Returns the path of a file for the given attempt of a test, within `dir` in the run
+    /// directory. The parent directory is created.
+    pub(crate) fn test_file_path(
+        &self,
+        dir: &str,
+        test_instance: &TestInstance<'_>,
+        attempt: usize,
+        extension: &str,
+    ) -> Result<Utf8PathBuf, RunStoreError> {
+        file_path_in(&self.run_dir.join(dir), test_instance, attempt, extension)
+    }
+
+    /// Returns a serializable summary of where the artifacts for this run are written.
+    pub fn info_summary(&self) -> RunInfoSummary {
+        RunInfoSummary {
+            metadata: self.metadata.clone(),
+            run_dir: self.run_dir.clone(),
+            summary_path: self.summary_path(),
+            junit_path: self.junit_path.clone(),
+            report_path: self.report_path(),
+            env_files: self.env_files.clone(),
+        }
+    }
+
+    /// Writes out [`Self::info_summary`] as a single line of JSON.
+    pub fn write_info(&self, mut writer: impl Write) -> Result<(), RunStoreError> {
+        serde_json::to_writer(&mut writer, &self.info_summary()).map_err(RunStoreError::Json)?;
+        writeln!(writer).map_err(RunStoreError::Io)
+    }
+
+    /// Returns the final summary of this run, given its statistics.
+    pub fn summary(&self, run_stats: &RunStats) -> RunSummary {
+        RunSummary {
+            metadata: self.metadata.clone(),
+            success: run_stats.is_success(),
+            initial_run_count: run_stats.initial_run_count,
+            final_run_count: run_stats.final_run_count,
+            passed: run_stats.passed,
+            runtime_skipped: run_stats.runtime_skipped,
+            flaky: run_stats.flaky,
+            failed: run_stats.failed,
+            exec_failed: run_stats.exec_failed,
+            disk_quota_exceeded: run_stats.disk_quota_exceeded,
+            timed_out: run_stats.timed_out,
+            quarantined: run_stats.quarantined,
+            expected_failures: run_stats.expected_failures,
+            unexpected_passes: run_stats.unexpected_passes,
+            skipped: run_stats.skipped,
+            overflowed: run_stats.overflowed,
+            timing_predictions: run_stats.timing_predictions,
+            timing_misses: run_stats.timing_misses,
+            skipped_binaries: run_stats.skipped_binaries,
+            environment: self.environment.clone(),
+        }
+    }
+
+    /// Writes out the final summary of this run to [`Self::summary_path`].
+    ///
+    /// The summary is written to a temporary file first and then renamed into place, so tooling
+    /// polling for the summary never observes a partially written file.
+    pub fn write_summary(&self, run_stats: &RunStats) -> Result<(), RunStoreError> {
+        let json =
+            serde_json::to_vec_pretty(&self.summary(run_stats)).map_err(RunStoreError::Json)?;
+        write_atomic(&self.summary_path(), &json)
+    }
+}
+
+/// Writes to a temporary file first and then renames it into place, so tooling polling for the
+/// file never observes a partially written one.
+pub(crate) fn write_atomic(path: &Utf8Path, contents: &[u8]) -> Result<(), RunStoreError> {
+    let mut temp_path = path.to_path_buf();
+    temp_path.set_extension(match path.extension() {
+        Some(extension) => format!("{}.tmp", extension),
+        None => "tmp".to_owned(),
+    });
+    fs::write(&temp_path, contents).map_err(|error| RunStoreError::Fs {
+        path: temp_path.clone(),
+        error,
+    })?;
+    fs::rename(&temp_path, path).map_err(|error| RunStoreError::Fs {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Removes the output files written to `output_dir` by earlier runs, along with any
+/// `<binary-id>` directories left empty.
+fn clear_output_dir(output_dir: &Utf8Path) -> Result<(), RunStoreError> {
+    let entries = match fs::read_dir(output_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => {
+            return Err(RunStoreError::Fs {
+                path: output_dir.to_path_buf(),
+                error,
+            })
+        }
+    };
+    for entry in entries {
+        let entry = entry.map_err(|error| RunStoreError::Fs {
+            path: output_dir.to_path_buf(),
+            error,
+        })?;
+        // Nextest only writes to directories with UTF-8 names.
+        let Ok(binary_dir) = Utf8PathBuf::try_from(entry.path()) else {
+            continue;
+        };
+        if !binary_dir
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_dir())
+        {
+            continue;
+        }
+        let files = fs::read_dir(&binary_dir).map_err(|error| RunStoreError::Fs {
+            path: binary_dir.clone(),
+            error,
+        })?;
+        for file in files {
+            let file = file.map_err(|error| RunStoreError::Fs {
+                path: binary_dir.clone(),
+                error,
+            })?;
+            let Ok(path) = Utf8PathBuf::try_from(file.path()) else {
+                continue;
+            };
+            if matches!(path.extension(), Some("stdout" | "stderr"))
+                && path
+                    .symlink_metadata()
+                    .is_ok_and(|metadata| metadata.is_file())
+            {
+                fs::remove_file(&path).map_err(|error| RunStoreError::Fs { path, error })?;
+            }
+        }
+        // This fails if the directory contains anything nextest didn't write, which is fine.
+        let _ = fs::remove_dir(&binary_dir);
+    }
+    Ok(())
+}
+
+/// Returns the path of a file for the given attempt of a test within `dir`, as
+/// `<binary-id>/<test-name>.<attempt>.<extension>` with the binary ID and test name passed through
+/// [`sanitize_file_name`]. The parent directory is created.
+fn file_path_in(
+    dir: &Utf8Path,
+    test_instance: &TestInstance<'_>,
+    attempt: usize,
+    extension: &str,
+) -> Result<Utf8PathBuf, RunStoreError> {
+    let test_dir = dir.join(sanitize_file_name(&test_instance.bin_info.binary_id));
+    fs::create_dir_all(&test_dir).map_err(|error| RunStoreError::Fs {
+        path: test_dir.clone(),
+        error,
+    })?;
+
+    let file_name = match test_instance.variant {
+        Some(variant) => sanitize_file_name(&format!(
+            "{}-{}-{}",
+            test_instance.name, variant.env_var, variant.value
+        )),
+        None => sanitize_file_name(test_instance.name),
+    };
+    Ok(test_dir.join(format!("{}.{}.{}", file_name, attempt, extension)))
+}
+
+/// Replaces characters that may not be valid in file names with underscores.
+///
+/// Different names can be sanitized to the same string (for example, `a::b_c` and `a_b::c`), so if
+/// any characters were replaced, a hash of the original name is appended to keep them apart.
+fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    if sanitized == name {
+        return sanitized;
+    }
+    let mut hasher = XxHash64::default();
+    hasher.write(name.as_bytes());
+    format!("{}-{:08x}", sanitized, hasher.finish() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NextestConfig;
+
+    #[test]
+    fn write_info_and_summary() {
+        let workspace_root = tempfile::tempdir().expect("tempdir created");
+        let workspace_root =
+            Utf8Path::from_path(workspace_root.path()).expect("tempdir path is UTF-8");
+        let config = NextestConfig::from_sources(workspace_root, None).expect("config is valid");
+        let profile = config
+            .profile(NextestConfig::DEFAULT_PROFILE)
+            .expect("default profile exists");
+
+        let store = RunStore::create(&profile).expect("run store created");
+        assert!(store.run_dir().is_dir(), "run dir created");
+
+        let mut info = vec![];
+        store.write_info(&mut info).expect("info written");
+        let info = RunInfoSummary::parse_json(String::from_utf8(info).unwrap()).unwrap();
+        assert_eq!(info.metadata.run_id, store.run_id());
+        assert_eq!(info.metadata.profile_name, NextestConfig::DEFAULT_PROFILE);
+        assert_eq!(info.junit_path, None);
+        assert!(!info.summary_path.exists(), "summary not written yet");
+
+        let run_stats = RunStats {
+            initial_run_count: 3,
+            final_run_count: 3,
+            passed: 2,
+            failed: 1,
+            ..RunStats::default()
+        };
+        store.write_summary(&run_stats).expect("summary written");
+        let summary = fs::read_to_string(&info.summary_path).expect("summary read");
+        let summary = RunSummary::parse_json(summary).expect("summary parsed");
+        assert_eq!(&summary.metadata, store.metadata());
+        assert!(!summary.success);
+        assert_eq!(summary.passed, 2);
+        assert_eq!(summary.failed, 1);
+    }
+
+    #[test]
+    fn output_dir() {
+        let workspace_root = tempfile::tempdir().expect("tempdir created");
+        let workspace_root =
+            Utf8Path::from_path(workspace_root.path()).expect("tempdir path is UTF-8");
+        let config_path = workspace_root.join("nextest.toml");
+        fs::write(
+            &config_path,
+            "[profile.default.store]\noutput-dir = \"output\"\n",
+        )
+        .expect("config written");
+        let config =
+            NextestConfig::from_sources(workspace_root, Some(&config_path)).expect("config valid");
+        let profile = config
+            .profile(NextestConfig::DEFAULT_PROFILE)
+            .expect("default profile exists");
+        let output_dir = profile.output_dir().expect("output dir set");
+        assert_eq!(output_dir, profile.store_dir().join("output"));
+
+        RunStore::create(&profile).expect("run store created");
+        let binary_dir = output_dir.join("my-crate");
+        fs::create_dir(&binary_dir).expect("binary dir created");
+        let stale_path = binary_dir.join("stale.0.stdout");
+        fs::write(&stale_path, "stale").expect("stale output written");
+        let user_path = output_dir.join("notes.txt");
+        fs::write(&user_path, "keep").expect("user file written");
+        RunStore::create(&profile).expect("run store created");
+        assert!(output_dir.is_dir(), "output dir created");
+        assert!(!stale_path.exists(), "output from earlier runs removed");
+        assert!(!binary_dir.exists(), "empty binary dir removed");
+        assert!(user_path.exists(), "files nextest didn't write are kept");
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(sanitize_file_name("nextest-tests"), "nextest-tests");
+        assert_eq!(sanitize_file_name("tests_foo.rs"), "tests_foo.rs");
+        let sanitized = sanitize_file_name("tests::foo/bar baz.rs");
+        assert!(
+            sanitized.starts_with("tests__foo_bar_baz.rs-"),
+            "hash appended: {}",
+            sanitized
+        );
+        assert_eq!(
+            sanitized,
+            sanitize_file_name("tests::foo/bar baz.rs"),
+            "sanitizing is deterministic"
+        );
+        assert_ne!(
+            sanitize_file_name("a::b_c"),
+            sanitize_file_name("a_b::c"),
+            "names that sanitize to the same string are kept apart"
+        );
+        assert_ne!(sanitize_file_name("a::b"), "a__b");
+    }
+}
@@ -6,32 +6,98 @@
 //! The main structure in this module is [`TestRunner`].
 
 use crate::{
-    config::NextestProfile,
+    config::{
+        ExpectedResult, MultiplexRule, NextestProfile, ProfileOverride, SlowTimeout, TestOrder,
+        TestSettings, TimeLimit,
+    },
+    container::ContainerRule,
+    crash::{self, AbortStatus, CoreDumps, CrashSymbolizer},
+    disk_usage::{dir_size, format_bytes},
+    env_file::EnvFiles,
+    environment::{BinaryRun, BinaryTestOutcome},
+    fake_time,
+    line_buffering::LineBuffering,
+    llvm_coverage::{self, LlvmTools, LLVM_PROFILE_FILE_ENV},
+    nesting::{nesting_level, Jobserver, NEXTEST_LEVEL_ENV},
+    output_filter::OutputFilter,
+    output_sanitizer::OutputSanitizer,
+    property_test::{self, PropertyTestFailure, ReplaySeeds, PROPTEST_RNG_SEED_ENV},
+    quarantine::Quarantine,
     reporter::{CancelReason, StatusLevel, TestEvent},
+    run_store::{
+        RunStore, NEXTEST_SKIP_FILE_ENV, NEXTEST_TEST_LOG_FILE_ENV, NEXTEST_TEST_TMPDIR_ENV,
+    },
+    sanitizer_report::SanitizerReport,
+    selector::TestSelection,
+    semaphore::WeightedSemaphore,
     signal::{SignalEvent, SignalHandler},
-    stopwatch::{StopwatchEnd, StopwatchStart},
+    stopwatch::{ClockInstant, StopwatchEnd, StopwatchStart},
+    test_dependencies::{DependencyStates, PrerequisiteStatus, TestDependencies},
+    test_groups::{
+        TestGroupConfig, TestGroupHook, TestGroupStates, GLOBAL_TEST_GROUP, NEXTEST_TEST_GROUP_ENV,
+    },
     test_list::{TestInstance, TestList},
+    timings::TestTimings,
+    valgrind::{MemcheckReport, Valgrind},
 };
+use camino::{Utf8Path, Utf8PathBuf};
 use crossbeam_channel::{RecvTimeoutError, Sender};
-use nextest_metadata::{FilterMatch, MismatchReason};
-use rayon::{ThreadPool, ThreadPoolBuilder};
+use nextest_metadata::{BuildPlatform, FilterMatch, MismatchReason};
+use rayon::{Scope, ThreadPool, ThreadPoolBuilder};
 use std::{
+    collections::{BTreeMap, VecDeque},
     convert::Infallible,
+    io::Write,
     marker::PhantomData,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
     time::{Duration, SystemTime},
 };
 
+/// The environment variable nextest sets to the global slot of each test: an index, starting from
+/// 0, that's unique among the tests running at the same time.
+///
+/// Slots are reused once tests finish, and are always less than the number of test threads. Tests
+/// that need distinct resources, such as database schemas, ports or directories, can derive them
+/// from the slot without coordinating with each other. A test keeps its slot across retries.
+pub const NEXTEST_TEST_GLOBAL_SLOT_ENV: &str = "NEXTEST_TEST_GLOBAL_SLOT";
+
+/// The environment variable nextest sets to the slot of each test within its test group: an index,
+/// starting from 0, that's unique among the tests in the group running at the same time.
+///
+/// Tests that aren't in a [test group](crate::test_groups) see `none`.
+pub const NEXTEST_TEST_GROUP_SLOT_ENV: &str = "NEXTEST_TEST_GROUP_SLOT";
+
+/// How long a test's standard output and standard error may stay open after it exits.
+///
+/// A test whose output is kept open for longer than this, typically by a subprocess it didn't wait
+/// for, is marked as [leaky](ExecuteStatus::leaked). Leaks are only detected on Unix, and not in
+/// no-capture mode.
+pub const LEAK_TIMEOUT: Duration = Duration::from_millis(100);
+
 /// Test runner options.
 #[derive(Debug, Default)]
 pub struct TestRunnerBuilder {
     no_capture: bool,
     retries: Option<usize>,
     fail_fast: Option<bool>,
+    test_order: Option<TestOrder>,
     test_threads: Option<usize>,
+    jobserver: Option<Jobserver>,
+    run_store: Option<RunStore>,
+    env_files: EnvFiles,
+    max_duration: Option<(Duration, TestTimings)>,
+    fake_epoch: Option<i64>,
+    progress_interval: Option<Duration>,
+    selection: Option<TestSelection>,
+    valgrind: Option<bool>,
+    llvm_coverage: Option<LlvmTools>,
+    replay_seeds: Option<ReplaySeeds>,
+    track_memory: bool,
+    dependencies: Option<TestDependencies>,
+    quarantine: Option<Quarantine>,
 }
 
 impl TestRunnerBuilder {
@@ -55,17 +121,139 @@ impl TestRunnerBuilder {
         self
     }
 
+    /// Sets the order in which tests are started, overriding the profile's `test-order`.
+    pub fn set_test_order(&mut self, test_order: TestOrder) -> &mut Self {
+        self.test_order = Some(test_order);
+        self
+    }
+
     /// Sets the number of tests to run simultaneously.
     pub fn set_test_threads(&mut self, test_threads: usize) -> &mut Self {
         self.test_threads = Some(test_threads);
         self
     }
 
+    /// Sets the jobserver used to limit the number of tests running concurrently, shared with
+    /// nested nextest invocations.
+    pub fn set_jobserver(&mut self, jobserver: Jobserver) -> &mut Self {
+        self.jobserver = Some(jobserver);
+        self
+    }
+
+    /// Sets the store for this run.
+    ///
+    /// If set, each test is given a log file within the store to write to. See
+    /// [`run_store`](crate::run_store) for more.
+    pub fn set_run_store(&mut self, run_store: RunStore) -> &mut Self {
+        self.run_store = Some(run_store);
+        self
+    }
+
+    /// Sets the dotenv files whose variables are set for every test.
+    ///
+    /// See [`env_file`](crate::env_file) for more.
+    pub fn set_env_files(&mut self, env_files: EnvFiles) -> &mut Self {
+        self.env_files = env_files;
+        self
+    }
+
+    /// Sets the maximum duration for this run, typically a single shard of a larger test run.
+    ///
+    /// Before each test is started, its duration is predicted using `timings`. Once the time
+    /// elapsed since the start of the run plus the predicted duration exceeds `max_duration`, no
+    /// more tests are started. Tests that weren't started are reported as skipped with
+    /// [`MismatchReason::Overflow`], so that they can be run by a follow-up shard.
+    pub fn set_max_duration(&mut self, max_duration: Duration, timings: TestTimings) -> &mut Self {
+        self.max_duration = Some((max_duration, timings));
+        self
+    }
+
+    /// Sets the epoch of the run, in seconds since the Unix epoch, for tests that
+    /// [fake time](crate::fake_time) without a fixed epoch.
+    ///
+    /// By default, this is read from `NEXTEST_FAKE_EPOCH` in the environment, or is the time the
+    /// runner is built at.
+    pub fn set_fake_epoch(&mut self, fake_epoch: i64) -> &mut Self {
+        self.fake_epoch = Some(fake_epoch);
+        self
+    }
+
+    /// Sets the interval at which [`TestEvent::RunProgress`] events are produced while the run is
+    /// in progress, for example to refresh a progress bar.
+    ///
+    /// By default, no such events are produced.
+    pub fn set_progress_interval(&mut self, progress_interval: Duration) -> &mut Self {
+        self.progress_interval = Some(progress_interval);
+        self
+    }
+
+    /// Sets the tests chosen by an external [selector](crate::selector), which are started in the
+    /// order the selector chose.
+    ///
+    /// Unselected tests should also be skipped through [`TestList::apply_selection`]. Tests that
+    /// weren't selected but aren't skipped are started after the selected ones.
+    pub fn set_selection(&mut self, selection: TestSelection) -> &mut Self {
+        self.selection = Some(selection);
+        self
+    }
+
+    /// Sets whether Rust tests are run under valgrind, overriding the profile's `valgrind.enable`.
+    ///
+    /// See [`valgrind`](crate::valgrind) for more.
+    pub fn set_valgrind(&mut self, valgrind: bool) -> &mut Self {
+        self.valgrind = Some(valgrind);
+        self
+    }
+
+    /// Collects coverage for each test, merging the raw profiles of each test with the given tools.
+    /// Tests should be built with coverage instrumentation.
+    ///
+    /// See [`llvm_coverage`](crate::llvm_coverage) for more.
+    pub fn set_llvm_coverage(&mut self, llvm_tools: LlvmTools) -> &mut Self {
+        self.llvm_coverage = Some(llvm_tools);
+        self
+    }
+
+    /// Runs the tests with a recorded proptest seed with that seed, to replay the failing cases it
+    /// found.
+    ///
+    /// See [`property_test`](crate::property_test) for more.
+    pub fn set_replay_seeds(&mut self, replay_seeds: ReplaySeeds) -> &mut Self {
+        self.replay_seeds = Some(replay_seeds);
+        self
+    }
+
+    /// Sets whether the peak resident set size of each test is measured, which is only possible on
+    /// Linux. Measuring it means checking on each test periodically while it runs.
+    ///
+    /// See [`soak`](crate::soak) for more.
+    pub fn set_track_memory(&mut self, track_memory: bool) -> &mut Self {
+        self.track_memory = track_memory;
+        self
+    }
+
+    /// Sets the dependencies between tests: tests are started after the tests they depend on have
+    /// finished, and fail if those didn't pass.
+    ///
+    /// See [`test_dependencies`](crate::test_dependencies) for more.
+    pub fn set_dependencies(&mut self, dependencies: TestDependencies) -> &mut Self {
+        self.dependencies = Some(dependencies);
+        self
+    }
+
+    /// Sets the tests whose failures don't fail the run.
+    ///
+    /// See [`quarantine`](crate::quarantine) for more.
+    pub fn set_quarantine(&mut self, quarantine: Quarantine) -> &mut Self {
+        self.quarantine = Some(quarantine);
+        self
+    }
+
     /// Creates a new test runner.
     pub fn build<'a>(
         &self,
         test_list: &'a TestList,
-        profile: &NextestProfile<'_>,
+        profile: &NextestProfile<'a>,
         handler: SignalHandler,
     ) -> TestRunner<'a> {
         let test_threads = match self.no_capture {
@@ -74,14 +262,61 @@ impl TestRunnerBuilder {
         };
         let retries = self.retries.unwrap_or_else(|| profile.retries());
         let fail_fast = self.fail_fast.unwrap_or_else(|| profile.fail_fast());
+        let test_order = self.test_order.unwrap_or_else(|| profile.test_order());
         let slow_timeout = profile.slow_timeout();
+        let multiplex_rules = profile.multiplex_rules().collect();
+        let host_overrides = profile.overrides(BuildPlatform::Host).collect();
+        let target_overrides = profile.overrides(BuildPlatform::Target).collect();
+        let container_rules = profile.container_rules().collect();
+        let valgrind = profile.valgrind().cloned().unwrap_or_default();
+        let valgrind = self
+            .valgrind
+            .unwrap_or_else(|| valgrind.enable())
+            .then_some(valgrind);
         TestRunner {
+            workspace_root: profile.workspace_root(),
             no_capture: self.no_capture,
             // The number of tries = retries + 1.
             tries: retries + 1,
             fail_fast,
+            test_order,
             slow_timeout,
             test_list,
+            multiplex_rules,
+            host_overrides,
+            target_overrides,
+            container_rules,
+            test_groups: profile.test_groups(),
+            test_threads,
+            output_filter: profile.output_filter(),
+            output_sanitizer: profile.output_sanitizer(),
+            crash_symbolizer: profile.crash_symbolizer(),
+            core_dumps: profile.core_dumps(),
+            line_buffering: profile.line_buffering(),
+            valgrind,
+            llvm_coverage: self.llvm_coverage.clone(),
+            replay_seeds: self.replay_seeds.clone(),
+            track_memory: self.track_memory,
+            dependencies: self
+                .dependencies
+                .clone()
+                .filter(|dependencies| !dependencies.is_empty()),
+            quarantine: self
+                .quarantine
+                .clone()
+                .filter(|quarantine| !quarantine.is_empty()),
+            nesting_level: nesting_level(),
+            jobserver: self.jobserver.clone(),
+            run_store: self.run_store.clone(),
+            env_vars: self
+                .env_files
+                .vars()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+            max_duration: self.max_duration.clone(),
+            fake_epoch: self.fake_epoch.unwrap_or_else(fake_time::run_epoch),
+            progress_interval: self.progress_interval,
+            selection: self.selection.clone(),
             run_pool: ThreadPoolBuilder::new()
                 // The main run_pool closure will need its own thread.
                 .num_threads(test_threads + 1)
@@ -102,11 +337,39 @@ impl TestRunnerBuilder {
 ///
 /// Created using [`TestRunnerBuilder::build`].
 pub struct TestRunner<'a> {
+    workspace_root: &'a Utf8Path,
     no_capture: bool,
     tries: usize,
     fail_fast: bool,
-    slow_timeout: Duration,
+    test_order: TestOrder,
+    slow_timeout: SlowTimeout,
     test_list: &'a TestList<'a>,
+    multiplex_rules: Vec<&'a MultiplexRule>,
+    // The overrides for binaries built for the host and the target respectively.
+    host_overrides: Vec<&'a ProfileOverride>,
+    target_overrides: Vec<&'a ProfileOverride>,
+    container_rules: Vec<&'a ContainerRule>,
+    test_groups: &'a BTreeMap<String, TestGroupConfig>,
+    test_threads: usize,
+    output_filter: Option<&'a OutputFilter>,
+    output_sanitizer: Option<&'a OutputSanitizer>,
+    crash_symbolizer: Option<&'a CrashSymbolizer>,
+    core_dumps: Option<&'a CoreDumps>,
+    line_buffering: Option<&'a LineBuffering>,
+    valgrind: Option<Valgrind>,
+    llvm_coverage: Option<LlvmTools>,
+    replay_seeds: Option<ReplaySeeds>,
+    track_memory: bool,
+    dependencies: Option<TestDependencies>,
+    quarantine: Option<Quarantine>,
+    nesting_level: usize,
+    jobserver: Option<Jobserver>,
+    run_store: Option<RunStore>,
+    env_vars: Vec<(String, String)>,
+    max_duration: Option<(Duration, TestTimings)>,
+    fake_epoch: i64,
+    progress_interval: Option<Duration>,
+    selection: Option<TestSelection>,
     run_pool: ThreadPool,
     wait_pool: ThreadPool,
     handler: SignalHandler,
@@ -143,15 +406,50 @@ impl<'a> TestRunner<'a> {
         // This is move so that sender is moved into it. When the scope finishes the sender is
         // dropped, and the receiver below completes iteration.
 
-        let canceled = AtomicBool::new(false);
-        let canceled_ref = &canceled;
-
-        let mut ctx = CallbackContext::new(callback, self.test_list.run_count(), self.fail_fast);
+        let test_instances = self.expand_instances();
+        let mut run_count = 0;
+        // The number of tests to run in each test group, so that the group is torn down once the
+        // last one finishes.
+        let mut group_counts = BTreeMap::new();
+        for test_instance in &test_instances {
+            if test_instance.test_info.filter_match.is_match() {
+                run_count += 1;
+                if let Some(group) = self.settings_for(test_instance).test_group() {
+                    *group_counts.entry(group).or_insert(0) += 1;
+                }
+            }
+        }
+        let state = RunState {
+            canceled: AtomicBool::new(false),
+            // Each test takes up one or more slots while it runs.
+            slots: WeightedSemaphore::new(self.test_threads),
+            // Used to enforce the maximum duration, if any.
+            budget_start: StopwatchStart::now(),
+            budget_exhausted: AtomicBool::new(false),
+            // Tracks how often timing data was missing for a test.
+            timing_predictions: AtomicUsize::new(0),
+            timing_misses: AtomicUsize::new(0),
+            group_states: TestGroupStates::new(
+                self.workspace_root,
+                self.test_groups,
+                &group_counts,
+            ),
+            dependency_states: self.dependencies.as_ref().map(|dependencies| {
+                DependencyStates::new(dependencies, test_instances.iter().copied())
+            }),
+        };
+        let state_ref = &state;
+        let mut ctx = CallbackContext::new(
+            callback,
+            run_count,
+            self.test_list.skipped_binary_count(),
+            self.fail_fast,
+        );
 
         // Send the initial event.
         // (Don't need to set the canceled atomic if this fails because the run hasn't started
         // yet.)
-        ctx.run_started(self.test_list)?;
+        ctx.run_started(self.test_list, run_count)?;
 
         // Stores the first error that occurred. This error is propagated up.
         let mut first_error = None;
@@ -165,71 +463,29 @@ impl<'a> TestRunner<'a> {
         // XXX rayon requires its scope callback to be Send, there's no good reason for it but
         // there's also no other well-maintained scoped threadpool :(
         self.run_pool.scope(move |run_scope| {
-            self.test_list.iter_tests().for_each(|test_instance| {
-                if canceled_ref.load(Ordering::Acquire) {
+            for test_instance in test_instances {
+                if state_ref.canceled.load(Ordering::Acquire) {
                     // Check for test cancellation.
-                    return;
+                    break;
                 }
 
-                let this_run_sender = run_sender.clone();
-                run_scope.spawn(move |_| {
-                    if canceled_ref.load(Ordering::Acquire) {
-                        // Check for test cancellation.
-                        return;
-                    }
-
-                    if let FilterMatch::Mismatch { reason } = test_instance.test_info.filter_match {
-                        // Failure to send means the receiver was dropped.
-                        let _ = this_run_sender.send(InternalTestEvent::Skipped {
-                            test_instance,
-                            reason,
-                        });
-                        return;
-                    }
-
-                    // Failure to send means the receiver was dropped.
-                    let _ = this_run_sender.send(InternalTestEvent::Started { test_instance });
-
-                    let mut run_statuses = vec![];
-
-                    loop {
-                        let attempt = run_statuses.len() + 1;
-
-                        let run_status = self
-                            .run_test(test_instance, attempt, &this_run_sender)
-                            .into_external(attempt, self.tries);
-
-                        if run_status.result.is_success() {
-                            // The test succeeded.
-                            run_statuses.push(run_status);
-                            break;
-                        } else if attempt < self.tries {
-                            // Retry this test: send a retry event, then retry the loop.
-                            let _ = this_run_sender.send(InternalTestEvent::Retry {
-                                test_instance,
-                                run_status: run_status.clone(),
-                            });
-                            run_statuses.push(run_status);
-                        } else {
-                            // This test failed and is out of retries.
-                            run_statuses.push(run_status);
-                            break;
-                        }
-                    }
-
-                    // At this point, either:
-                    // * the test has succeeded, or
-                    // * the test has failed and we've run out of retries.
-                    // In either case, the test is finished.
-                    let _ = this_run_sender.send(InternalTestEvent::Finished {
-                        test_instance,
-                        run_statuses: ExecutionStatuses::new(run_statuses),
-                    });
-                })
-            });
+                // Tests that depend on others are spawned once those have finished.
+                let ready = match &state_ref.dependency_states {
+                    Some(dependency_states) => dependency_states.start(test_instance),
+                    None => true,
+                };
+                if ready {
+                    self.spawn_test(run_scope, state_ref, test_instance, run_sender.clone());
+                }
+            }
 
             drop(run_sender);
 
+            let progress_ticker = match self.progress_interval {
+                Some(interval) => crossbeam_channel::tick(interval),
+                None => crossbeam_channel::never(),
+            };
+
             loop {
                 let internal_event = crossbeam_channel::select! {
                     recv(run_receiver) -> internal_event => {
@@ -251,6 +507,7 @@ impl<'a> TestRunner<'a> {
                             }
                         }
                     },
+                    recv(progress_ticker) -> _ => InternalEvent::ProgressTick,
                 };
 
                 match ctx_mut.handle_event(internal_event) {
@@ -259,7 +516,7 @@ impl<'a> TestRunner<'a> {
                         // If an error happens, it is because either the callback failed or
                         // a cancellation notice was received. If the callback failed, we need
                         // to send a further cancellation notice as well.
-                        canceled_ref.store(true, Ordering::Release);
+                        state_ref.canceled.store(true, Ordering::Release);
 
                         match err {
                             InternalError::Error(err) => {
@@ -290,6 +547,26 @@ impl<'a> TestRunner<'a> {
             Ok(())
         })?;
 
+        // If the run was canceled, some groups may not have been torn down yet.
+        let mut hook_failures = vec![];
+        state.group_states.finish_run(|group, hook, error| {
+            hook_failures.push(InternalTestEvent::GroupHookFailed {
+                group,
+                hook,
+                error: error.to_owned(),
+            })
+        });
+        for event in hook_failures {
+            if let Err(InternalError::Error(err)) = ctx.handle_event(InternalEvent::Test(event)) {
+                if first_error.is_none() {
+                    first_error = Some(err);
+                }
+            }
+        }
+
+        ctx.run_stats.timing_predictions = state.timing_predictions.load(Ordering::Relaxed);
+        ctx.run_stats.timing_misses = state.timing_misses.load(Ordering::Relaxed);
+
         match ctx.run_finished() {
             Ok(()) => {}
             Err(err) => {
@@ -309,22 +586,403 @@ impl<'a> TestRunner<'a> {
     // Helper methods
     // ---
 
+    /// Spawns a task on `scope` that runs the given test.
+    fn spawn_test<'scope>(
+        &'scope self,
+        scope: &Scope<'scope>,
+        state: &'scope RunState<'scope, 'a>,
+        test_instance: TestInstance<'a>,
+        run_sender: Sender<InternalTestEvent<'a>>,
+    ) {
+        scope.spawn(move |scope| self.run_instance(scope, state, test_instance, run_sender));
+    }
+
+    /// Runs the given test, retrying it if necessary, and spawns the tests that were waiting for
+    /// it to finish.
+    fn run_instance<'scope>(
+        &'scope self,
+        scope: &Scope<'scope>,
+        state: &'scope RunState<'scope, 'a>,
+        test_instance: TestInstance<'a>,
+        this_run_sender: Sender<InternalTestEvent<'a>>,
+    ) {
+        if state.canceled.load(Ordering::Acquire) {
+            // Check for test cancellation.
+            return;
+        }
+
+        if let FilterMatch::Mismatch { reason } = test_instance.test_info.filter_match {
+            // Failure to send means the receiver was dropped.
+            let _ = this_run_sender.send(InternalTestEvent::Skipped {
+                test_instance,
+                reason,
+            });
+            return;
+        }
+
+        // Tests that depend on this one are spawned once it and any other tests they depend on
+        // have finished.
+        let finish_dependency = |passed| {
+            if let Some(dependency_states) = &state.dependency_states {
+                for dependent in dependency_states.finish(&test_instance, passed) {
+                    self.spawn_test(scope, state, dependent, this_run_sender.clone());
+                }
+            }
+        };
+        let prerequisite_status = match &state.dependency_states {
+            Some(dependency_states) => dependency_states.prerequisite_status(&test_instance),
+            None => PrerequisiteStatus::Passed,
+        };
+        match prerequisite_status {
+            PrerequisiteStatus::Passed => {}
+            PrerequisiteStatus::Failed(failed) => {
+                let _ = this_run_sender.send(InternalTestEvent::Started { test_instance });
+                let stderr = format!(
+                    "nextest: not run because tests it depends on didn't pass:\n{}\n",
+                    failed
+                        .iter()
+                        .map(|test| format!("  {}", test))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                );
+                let mut run_status = InternalExecuteStatus::exec_fail(stderr.into_bytes())
+                    .into_external(1, self.tries);
+                self.write_output(&test_instance, &mut run_status);
+                let _ = this_run_sender.send(InternalTestEvent::Finished {
+                    test_instance,
+                    run_statuses: ExecutionStatuses::new(
+                        vec![run_status],
+                        self.is_quarantined(&test_instance),
+                    ),
+                });
+                finish_dependency(false);
+                if let Some(group) = self.settings_for(&test_instance).test_group() {
+                    state.group_states.finish_test(group, None, |hook, error| {
+                        let _ = this_run_sender.send(InternalTestEvent::GroupHookFailed {
+                            group,
+                            hook,
+                            error: error.to_owned(),
+                        });
+                    });
+                }
+                return;
+            }
+        }
+
+        // Wait for enough slots to free up, and then for a jobserver token if any,
+        // before starting the test. Both are held across retries.
+        let settings = self.settings_for(&test_instance);
+        let slots = state
+            .slots
+            .acquire(settings.threads_required().compute(self.test_threads));
+        let token = self
+            .jobserver
+            .as_ref()
+            .map(|jobserver| jobserver.acquire())
+            .transpose();
+
+        let group = settings.test_group();
+        let send_hook_failed = |group, hook, error: &str| {
+            let _ = this_run_sender.send(InternalTestEvent::GroupHookFailed {
+                group,
+                hook,
+                error: error.to_owned(),
+            });
+        };
+
+        // Running the test without a token would go over the jobserver's limit.
+        let _token = match token {
+            Ok(token) => token,
+            Err(error) => {
+                let _ = this_run_sender.send(InternalTestEvent::Started { test_instance });
+                let stderr = format!(
+                    "nextest: not run because a jobserver token couldn't be \
+                     acquired: {}\n",
+                    error
+                );
+                let mut run_status = InternalExecuteStatus::exec_fail(stderr.into_bytes())
+                    .into_external(1, self.tries);
+                self.write_output(&test_instance, &mut run_status);
+                let _ = this_run_sender.send(InternalTestEvent::Finished {
+                    test_instance,
+                    run_statuses: ExecutionStatuses::new(
+                        vec![run_status],
+                        self.is_quarantined(&test_instance),
+                    ),
+                });
+                finish_dependency(false);
+                if let Some(group) = group {
+                    state.group_states.finish_test(group, None, |hook, error| {
+                        send_hook_failed(group, hook, error)
+                    });
+                }
+                return;
+            }
+        };
+
+        if let Some((max_duration, timings)) = &self.max_duration {
+            // Once the budget is exhausted, don't start any more tests, even ones
+            // predicted to be short enough to fit.
+            let exhausted = state.budget_exhausted.load(Ordering::Acquire) || {
+                state.timing_predictions.fetch_add(1, Ordering::Relaxed);
+                let predicted = timings.get(&test_instance).unwrap_or_else(|| {
+                    state.timing_misses.fetch_add(1, Ordering::Relaxed);
+                    timings.default_estimate()
+                });
+                state.budget_start.elapsed() + predicted > *max_duration
+            };
+            if exhausted {
+                state.budget_exhausted.store(true, Ordering::Release);
+                let _ = this_run_sender.send(InternalTestEvent::Skipped {
+                    test_instance,
+                    reason: MismatchReason::Overflow,
+                });
+                finish_dependency(false);
+                if let Some(group) = group {
+                    state.group_states.finish_test(group, None, |hook, error| {
+                        send_hook_failed(group, hook, error)
+                    });
+                }
+                return;
+            }
+        }
+
+        // Run the group's setup command if this is the first test in it. Tests in the
+        // group wait for it to finish.
+        let group_slot = group.map(|group| {
+            let slot = state
+                .group_states
+                .start_test(group, |hook, error| send_hook_failed(group, hook, error));
+            (group, slot)
+        });
+
+        // Failure to send means the receiver was dropped.
+        let _ = this_run_sender.send(InternalTestEvent::Started { test_instance });
+
+        if let Some((group, Err(error))) = group_slot {
+            // The test can't run without the group's fixtures.
+            let mut run_status =
+                InternalExecuteStatus::exec_fail(error.into_bytes()).into_external(1, self.tries);
+            self.write_output(&test_instance, &mut run_status);
+            let _ = this_run_sender.send(InternalTestEvent::Finished {
+                test_instance,
+                run_statuses: ExecutionStatuses::new(
+                    vec![run_status],
+                    self.is_quarantined(&test_instance),
+                ),
+            });
+            finish_dependency(false);
+            state.group_states.finish_test(group, None, |hook, error| {
+                send_hook_failed(group, hook, error)
+            });
+            return;
+        }
+        let group_slot = group_slot.and_then(|(group, slot)| Some((group, slot.ok()?)));
+        let test_slots = TestSlots {
+            global: slots.slot(),
+            group: group_slot,
+            group_env: group_slot
+                .map(|(group, _)| state.group_states.setup_env(group))
+                .unwrap_or_default(),
+        };
+
+        let mut run_statuses = vec![];
+
+        loop {
+            let attempt = run_statuses.len() + 1;
+
+            let mut run_status = self
+                .run_test(test_instance, attempt, &test_slots, &this_run_sender)
+                .into_external(attempt, self.tries);
+            self.write_output(&test_instance, &mut run_status);
+
+            if run_status.result.is_success()
+                || run_status.result == ExecutionResult::UnexpectedPass
+            {
+                // The test succeeded, skipped itself, or passed when it was expected
+                // to fail. Retrying it won't change anything.
+                run_statuses.push(run_status);
+                break;
+            } else if attempt < self.tries {
+                // Retry this test: send a retry event, then retry the loop.
+                let _ = this_run_sender.send(InternalTestEvent::Retry {
+                    test_instance,
+                    run_status: Box::new(run_status.clone()),
+                });
+                run_statuses.push(run_status);
+            } else {
+                // This test failed and is out of retries.
+                run_statuses.push(run_status);
+                break;
+            }
+        }
+
+        // At this point, either:
+        // * the test has succeeded, or
+        // * the test has failed and we've run out of retries.
+        // In either case, the test is finished.
+        let passed = run_statuses
+            .last()
+            .is_some_and(|run_status| run_status.result == ExecutionResult::Pass);
+        let _ = this_run_sender.send(InternalTestEvent::Finished {
+            test_instance,
+            run_statuses: ExecutionStatuses::new(run_statuses, self.is_quarantined(&test_instance)),
+        });
+        finish_dependency(passed);
+
+        // Run the group's teardown command if this was the last test in it.
+        if let Some((group, slot)) = test_slots.group {
+            state
+                .group_states
+                .finish_test(group, Some(slot), |hook, error| {
+                    send_hook_failed(group, hook, error)
+                });
+        }
+    }
+
+    /// Returns the test instances to run in the order they're started in, expanding multiplexed
+    /// tests into their variants.
+    fn expand_instances(&self) -> Vec<TestInstance<'a>> {
+        let mut test_instances: Vec<_> = self.test_list.iter_tests().collect();
+        if let Some(selection) = &self.selection {
+            // The sort is stable, so tests that weren't selected stay in their usual order.
+            test_instances.sort_by_key(|test_instance| {
+                selection.position_of(test_instance).unwrap_or(usize::MAX)
+            });
+        } else if self.test_order == TestOrder::RoundRobin {
+            test_instances = round_robin(test_instances, |test_instance| {
+                test_instance.bin_info.binary_id.as_str()
+            });
+        }
+        test_instances
+            .into_iter()
+            .flat_map(move |test_instance| {
+                // Skipped tests are reported once, not once per variant.
+                let rule = match test_instance.test_info.filter_match {
+                    FilterMatch::Matches => self.settings_for(&test_instance).multiplex(),
+                    FilterMatch::Mismatch { .. } => None,
+                };
+                match rule {
+                    Some(rule) => rule
+                        .variants()
+                        .map(|variant| test_instance.with_variant(variant))
+                        .collect(),
+                    None => vec![test_instance],
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the settings for the given test, resolved the same way as
+    /// [`NextestProfile::settings_for`], with the retries specified for this run.
+    fn settings_for(&self, test_instance: &TestInstance<'_>) -> TestSettings<'a> {
+        let overrides = match test_instance.bin_info.build_platform {
+            BuildPlatform::Host => &self.host_overrides,
+            BuildPlatform::Target => &self.target_overrides,
+        };
+        TestSettings::resolve(
+            self.tries - 1,
+            self.slow_timeout,
+            overrides.iter().copied(),
+            self.container_rules.iter().copied(),
+            self.multiplex_rules.iter().copied(),
+            &test_instance.to_query(),
+        )
+    }
+
+    /// Returns true if the given test's failures don't fail the run.
+    fn is_quarantined(&self, test_instance: &TestInstance<'_>) -> bool {
+        self.quarantine
+            .as_ref()
+            .is_some_and(|quarantine| quarantine.contains(&test_instance.to_query()))
+    }
+
+    // Rust tests in environments that run binaries as a whole, such as probe-attached devices, take
+    // their results out of a run of the binary, unless a container rule applies to them.
+    fn binary_run_for(
+        &self,
+        test_instance: &TestInstance<'_>,
+        attempt: usize,
+    ) -> Option<Arc<dyn BinaryRun>> {
+        if test_instance.synthetic.is_some()
+            || self.settings_for(test_instance).container().is_some()
+        {
+            return None;
+        }
+        self.test_list
+            .target_runner()
+            .for_platform(test_instance.bin_info.build_platform)?
+            .environment()
+            .clone()
+            .start_binary_run(test_instance.binary, attempt)
+    }
+
+    /// Writes the output of a test attempt to the files for it in the output directory, if one is
+    /// configured.
+    ///
+    /// Failing to write output shouldn't cause the test to fail, so errors are ignored, and the
+    /// paths are only recorded for output that was written.
+    fn write_output(&self, test: &TestInstance<'a>, run_status: &mut ExecuteStatus) {
+        let Some(run_store) = &self.run_store else {
+            return;
+        };
+        let write = |output: &[u8], extension: &str| {
+            let path = run_store
+                .test_output_path(test, run_status.attempt, extension)
+                .ok()??;
+            std::fs::write(&path, output).ok()?;
+            Some(path)
+        };
+        let stdout_path = write(run_status.stdout(), "stdout");
+        let stderr_path = write(run_status.stderr(), "stderr");
+        run_status.stdout_path = stdout_path;
+        run_status.stderr_path = stderr_path;
+    }
+
     /// Run an individual test in its own process.
     fn run_test(
         &self,
         test: TestInstance<'a>,
         attempt: usize,
+        slots: &TestSlots<'a>,
         run_sender: &Sender<InternalTestEvent<'a>>,
     ) -> InternalExecuteStatus {
         let stopwatch = StopwatchStart::now();
 
-        match self.run_test_inner(test, attempt, &stopwatch, run_sender) {
-            Ok(run_status) => run_status,
+        match self.run_test_inner(test, attempt, slots, &stopwatch, run_sender) {
+            Ok(mut run_status) => {
+                if run_status.result == ExecutionResult::Fail {
+                    // Sanitizers print their reports to standard error by default.
+                    run_status.sanitizer_report = SanitizerReport::detect(&run_status.stderr)
+                        .or_else(|| SanitizerReport::detect(&run_status.stdout));
+                }
+                let settings = self.settings_for(&test);
+                if let Some(time_limit) = settings.time_limit() {
+                    run_status.apply_time_limit(time_limit);
+                }
+                run_status.apply_expected_result(settings.expected_result());
+                // Output is sanitized after it has been filtered, so that filters see all of it.
+                if let Some(output_sanitizer) = self.output_sanitizer {
+                    run_status.stdout = output_sanitizer.sanitize(run_status.stdout);
+                    run_status.stderr = output_sanitizer.sanitize(run_status.stderr);
+                }
+                run_status
+            }
             Err(_) => InternalExecuteStatus {
                 // TODO: can we return more information in stdout/stderr? investigate this
                 stdout: vec![],
                 stderr: vec![],
+                attachments: vec![],
+                skip_reason: None,
                 result: ExecutionResult::ExecFail,
+                leaked: false,
+                disk_usage: 0,
+                abort_status: None,
+                core_dump: None,
+                sanitizer_report: None,
+                property_test_failure: None,
+                coverage_profile: None,
+                peak_rss: None,
                 stopwatch_end: stopwatch.end(),
             },
         }
@@ -334,65 +992,508 @@ impl<'a> TestRunner<'a> {
         &self,
         test: TestInstance<'a>,
         attempt: usize,
+        slots: &TestSlots<'a>,
         stopwatch: &StopwatchStart,
         run_sender: &Sender<InternalTestEvent<'a>>,
     ) -> std::io::Result<InternalExecuteStatus> {
+        if let Some(binary_run) = self.binary_run_for(&test, attempt) {
+            return self.run_binary_test(&*binary_run, test, stopwatch, run_sender);
+        }
+        let settings = self.settings_for(&test);
+
+        // Failing to set up the valgrind report means the test isn't run under valgrind.
+        let valgrind_xml = self.valgrind.as_ref().and_then(|_| {
+            let path = self
+                .run_store
+                .as_ref()?
+                .test_file_path("valgrind", &test, attempt, "xml")
+                .ok()?;
+            let _ = std::fs::remove_file(&path);
+            Some(path)
+        });
         let cmd = test
-            .make_expression()
+            .make_expression(
+                self.test_list.build_meta(),
+                self.test_list.target_runner(),
+                settings.container(),
+                self.valgrind.as_ref().zip(valgrind_xml.as_deref()),
+            )
             .unchecked()
             // Debug environment variable for testing.
-            .env("__NEXTEST_ATTEMPT", format!("{}", attempt));
+            .env("__NEXTEST_ATTEMPT", format!("{}", attempt))
+            .env(NEXTEST_LEVEL_ENV, format!("{}", self.nesting_level + 1))
+            .env(NEXTEST_TEST_GLOBAL_SLOT_ENV, format!("{}", slots.global));
+        // The seed is chosen here, rather than by proptest, so that it can be recorded.
+        let proptest_seed = property_test::choose_seed(
+            self.replay_seeds
+                .as_ref()
+                .and_then(|seeds| seeds.seed(test.bin_info.binary_id.as_str(), test.name)),
+            &self.env_vars,
+        );
+        let cmd = cmd.env(PROPTEST_RNG_SEED_ENV, &proptest_seed);
+        let cmd = match slots.group {
+            Some((group, slot)) => slots
+                .group_env
+                .iter()
+                .fold(cmd, |cmd, (key, value)| cmd.env(key, value))
+                .env(NEXTEST_TEST_GROUP_ENV, group)
+                .env(NEXTEST_TEST_GROUP_SLOT_ENV, format!("{}", slot)),
+            None => cmd
+                .env(NEXTEST_TEST_GROUP_ENV, GLOBAL_TEST_GROUP)
+                .env(NEXTEST_TEST_GROUP_SLOT_ENV, "none"),
+        };
+        let cmd = match self.core_dumps {
+            Some(core_dumps) => core_dumps.apply(cmd),
+            None => cmd,
+        };
+
+        // Forced locale settings and fake time take precedence over env files, which are set
+        // further out.
+        let cmd = settings
+            .env()
+            .fold(cmd, |cmd, (key, value)| cmd.env(key, value));
+        let cmd = settings
+            .fake_time()
+            .into_iter()
+            .flat_map(|fake_time| fake_time.vars(self.fake_epoch, self.workspace_root))
+            .fold(cmd, |cmd, (key, value)| cmd.env(key, value));
+
+        // Coverage is only collected for tests run locally, which can write to the run directory.
+        let coverage_dir = self
+            .llvm_coverage
+            .as_ref()
+            .filter(|_| {
+                settings.container().is_none()
+                    && self
+                        .test_list
+                        .target_runner()
+                        .for_platform(test.bin_info.build_platform)
+                        .is_none()
+            })
+            .and_then(|_| self.coverage_dir(&test, attempt));
+        let cmd = match &coverage_dir {
+            Some(coverage_dir) => cmd.env(
+                LLVM_PROFILE_FILE_ENV,
+                llvm_coverage::profile_file_pattern(coverage_dir),
+            ),
+            None => cmd,
+        };
+
+        // Failing to set up the log file shouldn't cause the test to fail, so ignore errors here.
+        let log_path = self
+            .run_store
+            .as_ref()
+            .and_then(|run_store| run_store.test_log_path(&test, attempt).ok());
+        let cmd = match &log_path {
+            Some(log_path) => {
+                // Remove any log left over from a previous run with the same ID.
+                let _ = std::fs::remove_file(log_path);
+                cmd.env(NEXTEST_TEST_LOG_FILE_ENV, log_path)
+            }
+            None => cmd,
+        };
+
+        // Unlike the log file, tests may rely on their temporary directory existing and being
+        // empty, so don't run them if it can't be set up.
+        let tmp_dir = match self
+            .run_store
+            .as_ref()
+            .map(|run_store| run_store.test_tmp_dir(&test, attempt))
+            .transpose()
+        {
+            Ok(tmp_dir) => tmp_dir,
+            Err(error) => {
+                let mut message = format!(
+                    "nextest: not run because its temporary directory couldn't be set up: {}",
+                    error
+                );
+                if let Some(source) = std::error::Error::source(&error) {
+                    message.push_str(&format!(": {}", source));
+                }
+                message.push('\n');
+                let mut run_status = InternalExecuteStatus::exec_fail(message.into_bytes());
+                run_status.stopwatch_end = stopwatch.end();
+                return Ok(run_status);
+            }
+        };
+        let cmd = match &tmp_dir {
+            Some(tmp_dir) => cmd.env(NEXTEST_TEST_TMPDIR_ENV, tmp_dir),
+            None => cmd,
+        };
+
+        let skip_path = self
+            .run_store
+            .as_ref()
+            .and_then(|run_store| run_store.test_skip_path(&test, attempt).ok());
+        let cmd = match &skip_path {
+            Some(skip_path) => {
+                let _ = std::fs::remove_file(skip_path);
+                cmd.env(NEXTEST_SKIP_FILE_ENV, skip_path)
+            }
+            None => cmd,
+        };
+
+        // Variables from env files override the environment nextest is run in, but not the
+        // variables set by nextest above. (With duct, environment variables set on inner
+        // expressions take precedence over outer ones.)
+        let cmd = self
+            .env_vars
+            .iter()
+            .fold(cmd, |cmd, (key, value)| cmd.env(key, value));
+        // Env files can in turn override the variables that turn on line buffering.
+        let cmd = match self.line_buffering {
+            Some(line_buffering) => line_buffering.apply(cmd),
+            None => cmd,
+        };
+
+        let cmd = match &self.jobserver {
+            Some(jobserver) => {
+                let jobserver = jobserver.clone();
+                cmd.before_spawn(move |cmd| {
+                    jobserver.configure(cmd);
+                    Ok(())
+                })
+            }
+            None => cmd,
+        };
 
         let cmd = if self.no_capture {
             cmd
         } else {
-            // Capture stdout and stderr.
-            cmd.stdout_capture().stderr_capture()
+            // Capture stdout and stderr. Standard input may be used to interact with nextest, so
+            // don't let tests read from it.
+            cmd.stdin_null().stdout_capture().stderr_capture()
         };
 
         let handle = cmd.start()?;
-
-        self.wait_pool.in_place_scope(|s| {
-            let (sender, receiver) = crossbeam_channel::bounded::<()>(1);
+        let pid = handle.pids().last().copied();
+        // Tests are killed once they've gone over their time limit by its grace period. Whether
+        // they fail is decided once they've finished.
+        let kill_at = settings
+            .time_limit()
+            .and_then(|time_limit| time_limit.kill_after())
+            .map(|kill_after| ClockInstant::now() + kill_after);
+
+        let track_memory = self.track_memory;
+        let (exited_at, peak_rss) = self.wait_pool.in_place_scope(|s| {
+            let (sender, receiver) =
+                crossbeam_channel::bounded::<(Option<ClockInstant>, Option<u64>)>(1);
             let wait_handle = &handle;
 
             // Spawn a task on the threadpool that waits for the test to finish.
             s.spawn(move |_| {
+                // Note when the process exits before waiting for its output to be closed, so that
+                // leaked subprocesses can be detected.
+                let (exited_at, peak_rss) = match pid {
+                    Some(pid) if track_memory => wait_for_exit_tracking_memory(pid),
+                    Some(pid) => (wait_for_exit(pid), None),
+                    None => (None, None),
+                };
                 // This thread is just waiting for the test to finish, we'll handle the output in the main thread
                 let _ = wait_handle.wait();
                 // We don't care if the receiver got the message or not
-                let _ = sender.send(());
+                let _ = sender.send((exited_at, peak_rss));
             });
 
-            // Continue waiting for the test to finish with a timeout, logging at slow-timeout
-            // intervals
-            while let Err(error) = receiver.recv_timeout(self.slow_timeout) {
-                match error {
-                    RecvTimeoutError::Timeout => {
-                        let _ = run_sender.send(InternalTestEvent::Slow {
-                            test_instance: test,
-                            elapsed: stopwatch.elapsed(),
-                        });
-                    }
-                    RecvTimeoutError::Disconnected => {
-                        unreachable!("Waiting thread should never drop the sender")
+            // Continue waiting for the test to finish, logging as configured by slow-timeout
+            self.wait_reporting_slow(test, stopwatch, run_sender, |timeout| {
+                let until_kill =
+                    kill_at.map(|kill_at| kill_at.saturating_duration_since(ClockInstant::now()));
+                let wait_for = match (timeout, until_kill) {
+                    (Some(timeout), Some(until_kill)) => Some(timeout.min(until_kill)),
+                    (timeout, until_kill) => timeout.or(until_kill),
+                };
+                let timed_out = match wait_for {
+                    Some(wait_for) => match receiver.recv_timeout(wait_for) {
+                        Ok(exit) => return Some(exit),
+                        Err(RecvTimeoutError::Timeout) => true,
+                        Err(RecvTimeoutError::Disconnected) => {
+                            unreachable!("Waiting thread should never drop the sender")
+                        }
+                    },
+                    None => false,
+                };
+                if timed_out {
+                    if kill_at.is_none_or(|kill_at| ClockInstant::now() < kill_at) {
+                        // Time to report the test as slow.
+                        return None;
                     }
+                    let _ = handle.kill();
                 }
-            }
+                Some(
+                    receiver
+                        .recv()
+                        .expect("Waiting thread should never drop the sender"),
+                )
+            })
         });
+        let leaked = exited_at.is_some_and(|exited_at| exited_at.elapsed() > LEAK_TIMEOUT);
 
         let output = handle.into_output()?;
-
-        let status = if output.status.success() {
-            ExecutionResult::Pass
+        // Don't count time spent filtering output towards the test.
+        let stopwatch_end = stopwatch.end();
+
+        let mut attachments = log_path
+            .and_then(|log_path| {
+                let contents = std::fs::read(&log_path).ok()?;
+                (!contents.is_empty()).then(|| TestAttachment {
+                    path: log_path,
+                    contents: Arc::new(contents),
+                })
+            })
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        // The temporary directory is only for the duration of the test, so remove it once its size
+        // has been recorded.
+        let tmp_usage = tmp_dir.map_or(0, |tmp_dir| {
+            let size = dir_size(&tmp_dir);
+            let _ = std::fs::remove_dir_all(&tmp_dir);
+            size
+        });
+        let disk_usage = tmp_usage
+            + attachments
+                .iter()
+                .map(|attachment| attachment.contents.len() as u64)
+                .sum::<u64>();
+
+        // A test that exits successfully after creating its skip file skipped itself at runtime.
+        let skip_reason = skip_path
+            .filter(|_| output.status.success())
+            .and_then(|skip_path| std::fs::read(&skip_path).ok())
+            .map(|reason| String::from_utf8_lossy(&reason).trim().to_owned());
+
+        // Filters might remove the failure message, so look at the unfiltered output.
+        let property_test_failure = (skip_reason.is_none() && !output.status.success())
+            .then(|| {
+                PropertyTestFailure::detect(&output.stdout, &output.stderr, Some(&proptest_seed))
+            })
+            .flatten();
+        let (status, stdout, stderr) = if skip_reason.is_some() {
+            (ExecutionResult::Skip, output.stdout, output.stderr)
+        } else if output.status.success() {
+            (ExecutionResult::Pass, output.stdout, output.stderr)
         } else {
-            ExecutionResult::Fail
+            // Only the output of failed tests is displayed by default, so only filter that.
+            let (stdout, stderr) = match self.output_filter {
+                Some(output_filter) => {
+                    output_filter.filter_or_fallback(&test, output.stdout, output.stderr)
+                }
+                None => (output.stdout, output.stderr),
+            };
+            (ExecutionResult::Fail, stdout, stderr)
         };
+
+        // Exceeding the quota fails the test even if it would otherwise have passed.
+        let (status, mut stderr) = match settings.disk_quota() {
+            Some(quota) if disk_usage > quota.bytes() => {
+                let mut stderr = stderr;
+                let _ = writeln!(
+                    stderr,
+                    "\nnextest: test wrote {} to disk, exceeding its quota of {}",
+                    format_bytes(disk_usage),
+                    quota,
+                );
+                (ExecutionResult::DiskQuotaExceeded, stderr)
+            }
+            _ => (status, stderr),
+        };
+
+        // Tests that abort usually don't get to print why, so decode their exit status.
+        let abort_status = AbortStatus::from_exit_status(output.status);
+        let mut core_dump = None;
+        if let Some(abort_status) = abort_status {
+            let _ = writeln!(stderr, "\nnextest: test aborted with {}", abort_status);
+            core_dump = self.collect_core_dump(&test, attempt, pid);
+            if let Some(core_dump) = &core_dump {
+                let _ = writeln!(stderr, "nextest: core dump saved to {}", core_dump);
+            }
+            if let Some(attachment) =
+                self.symbolize_crash(&test, attempt, pid, abort_status, core_dump.as_deref())
+            {
+                attachments.push(attachment);
+            }
+        }
+
+        let status = match (&self.valgrind, &valgrind_xml) {
+            (Some(valgrind), Some(xml_path)) => {
+                let (status, attachment) =
+                    apply_memcheck_report(valgrind, xml_path, status, &mut stderr);
+                attachments.extend(attachment);
+                status
+            }
+            _ => status,
+        };
+
+        let coverage_profile = match (&self.llvm_coverage, coverage_dir) {
+            (Some(llvm_tools), Some(coverage_dir)) => {
+                let profile = coverage_dir.with_extension("profdata");
+                llvm_tools
+                    .merge_test_profiles(&coverage_dir, &profile)
+                    .then_some(profile)
+            }
+            _ => None,
+        };
+
         Ok(InternalExecuteStatus {
-            stdout: output.stdout,
-            stderr: output.stderr,
+            stdout,
+            stderr,
+            attachments,
+            skip_reason,
             result: status,
-            stopwatch_end: stopwatch.end(),
+            leaked,
+            disk_usage,
+            abort_status,
+            core_dump,
+            sanitizer_report: None,
+            property_test_failure,
+            coverage_profile,
+            peak_rss,
+            stopwatch_end,
+        })
+    }
+
+    /// Creates the directory the given attempt of a test writes its raw coverage profiles to.
+    ///
+    /// Failing to set it up means that coverage isn't collected for the test.
+    fn coverage_dir(&self, test: &TestInstance<'a>, attempt: usize) -> Option<Utf8PathBuf> {
+        let dir = self
+            .run_store
+            .as_ref()?
+            .test_file_path("coverage", test, attempt, "profraw")
+            .ok()?;
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).ok()?;
+        Some(dir)
+    }
+
+    /// Moves the core dump of a test that aborted into the run directory, if core dumps are
+    /// enabled and one was written.
+    fn collect_core_dump(
+        &self,
+        test: &TestInstance<'a>,
+        attempt: usize,
+        pid: Option<u32>,
+    ) -> Option<Utf8PathBuf> {
+        let core_dumps = self.core_dumps?;
+        let extension = if cfg!(windows) { "dmp" } else { "core" };
+        let dest = self
+            .run_store
+            .as_ref()?
+            .test_file_path("cores", test, attempt, extension)
+            .ok()?;
+        core_dumps.collect(test.cwd(), pid, &dest).then_some(dest)
+    }
+
+    /// Runs the crash symbolizer, if configured, for a test that aborted. Its output is saved to the
+    /// run directory and returned as an attachment.
+    fn symbolize_crash(
+        &self,
+        test: &TestInstance<'a>,
+        attempt: usize,
+        pid: Option<u32>,
+        abort_status: AbortStatus,
+        core_dump: Option<&Utf8Path>,
+    ) -> Option<TestAttachment> {
+        let symbolizer = self.crash_symbolizer?;
+        let path = self
+            .run_store
+            .as_ref()?
+            .test_file_path("backtraces", test, attempt, "txt")
+            .ok()?;
+        let contents = crash::run_symbolizer(
+            symbolizer,
+            self.workspace_root,
+            &test.bin_info.binary_path,
+            pid,
+            abort_status,
+            core_dump,
+        );
+        std::fs::write(&path, &contents).ok()?;
+        Some(TestAttachment {
+            path,
+            contents: Arc::new(contents),
+        })
+    }
+
+    /// Calls `wait` until it returns a value, reporting the test as slow in between as configured
+    /// by the slow timeout.
+    ///
+    /// `wait` is passed how long to wait for, or `None` if it should wait until it can return a
+    /// value.
+    fn wait_reporting_slow<T>(
+        &self,
+        test: TestInstance<'a>,
+        stopwatch: &StopwatchStart,
+        run_sender: &Sender<InternalTestEvent<'a>>,
+        mut wait: impl FnMut(Option<Duration>) -> Option<T>,
+    ) -> T {
+        let mut reported = 0;
+        loop {
+            let next_wait = self.slow_timeout.next_wait(reported);
+            let slow_at = next_wait.map(|next_wait| ClockInstant::now() + next_wait);
+            let mut timeout = next_wait;
+            loop {
+                if let Some(value) = wait(timeout) {
+                    return value;
+                }
+                // Waits with a timeout keep going while the host is suspended on some platforms.
+                // If this one ended early by nextest's clock, wait out the rest of it.
+                let remaining = slow_at
+                    .map(|slow_at| slow_at.saturating_duration_since(ClockInstant::now()))
+                    .filter(|remaining| !remaining.is_zero());
+                match remaining {
+                    Some(remaining) => timeout = Some(remaining),
+                    None => break,
+                }
+            }
+            reported += 1;
+            let _ = run_sender.send(InternalTestEvent::Slow {
+                test_instance: test,
+                elapsed: stopwatch.elapsed(),
+            });
+        }
+    }
+
+    /// Takes the result of a test out of a run of its whole binary.
+    fn run_binary_test(
+        &self,
+        binary_run: &dyn BinaryRun,
+        test: TestInstance<'a>,
+        stopwatch: &StopwatchStart,
+        run_sender: &Sender<InternalTestEvent<'a>>,
+    ) -> std::io::Result<InternalExecuteStatus> {
+        // As with other tests, report the test as slow while waiting for the run.
+        self.wait_reporting_slow(test, stopwatch, run_sender, |timeout| {
+            binary_run.wait_timeout(timeout).then_some(())
+        });
+
+        let name = test.test_info.listed_name.as_deref().unwrap_or(test.name);
+        let test_result = binary_run.test_result(name)?;
+        let (result, skip_reason) = match test_result.outcome {
+            BinaryTestOutcome::Pass => (ExecutionResult::Pass, None),
+            BinaryTestOutcome::Fail => (ExecutionResult::Fail, None),
+            BinaryTestOutcome::Skip(reason) => (ExecutionResult::Skip, Some(reason)),
+        };
+        Ok(InternalExecuteStatus {
+            stdout: test_result.stdout,
+            stderr: test_result.stderr,
+            attachments: vec![],
+            skip_reason,
+            result,
+            leaked: false,
+            disk_usage: 0,
+            abort_status: None,
+            core_dump: None,
+            sanitizer_report: None,
+            property_test_failure: None,
+            coverage_profile: None,
+            peak_rss: None,
+            // The test's own time in the run, rather than the time spent waiting for it.
+            stopwatch_end: StopwatchEnd {
+                duration: test_result.duration,
+                ..stopwatch.end()
+            },
         })
     }
 }
@@ -402,12 +1503,27 @@ impl<'a> TestRunner<'a> {
 pub struct ExecutionStatuses {
     /// This is guaranteed to be non-empty.
     statuses: Vec<ExecuteStatus>,
+    quarantined: bool,
 }
 
 #[allow(clippy::len_without_is_empty)] // RunStatuses is never empty
 impl ExecutionStatuses {
-    fn new(statuses: Vec<ExecuteStatus>) -> Self {
-        Self { statuses }
+    fn new(statuses: Vec<ExecuteStatus>, quarantined: bool) -> Self {
+        Self {
+            statuses,
+            quarantined,
+        }
+    }
+
+    /// Returns true if the test is [quarantined](crate::quarantine): if it failed, the failure
+    /// doesn't fail the run.
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantined
+    }
+
+    /// Returns true if the test is quarantined and failed.
+    pub fn is_quarantined_failure(&self) -> bool {
+        self.quarantined && !self.last_status().result.is_success()
     }
 
     /// Returns the last execution status.
@@ -420,7 +1536,7 @@ impl ExecutionStatuses {
     }
 
     /// Iterates over all the statuses.
-    pub fn iter(&self) -> impl Iterator<Item = &'_ ExecuteStatus> + DoubleEndedIterator + '_ {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &'_ ExecuteStatus> + '_ {
         self.statuses.iter()
     }
 
@@ -432,7 +1548,12 @@ impl ExecutionStatuses {
     /// Returns a description of self.
     pub fn describe(&self) -> ExecutionDescription<'_> {
         let last_status = self.last_status();
-        if last_status.result.is_success() {
+        if last_status.result == ExecutionResult::Skip {
+            ExecutionDescription::Skipped {
+                last_status,
+                prior_statuses: &self.statuses[..self.statuses.len() - 1],
+            }
+        } else if last_status.result.is_success() {
             if self.statuses.len() > 1 {
                 ExecutionDescription::Flaky {
                     last_status,
@@ -490,15 +1611,30 @@ pub enum ExecutionDescription<'a> {
         /// May be empty.
         retries: &'a [ExecuteStatus],
     },
+
+    /// The test skipped itself at runtime, possibly after failing earlier attempts.
+    ///
+    /// See [`NEXTEST_SKIP_FILE_ENV`] for how tests skip themselves.
+    Skipped {
+        /// The last status, in which the test skipped itself.
+        last_status: &'a ExecuteStatus,
+
+        /// Previous statuses, none of which are successes. Usually empty.
+        prior_statuses: &'a [ExecuteStatus],
+    },
 }
 
 impl<'a> ExecutionDescription<'a> {
     /// Returns the status level for this `RunDescribe`.
     pub fn status_level(&self) -> StatusLevel {
         match self {
+            ExecutionDescription::Success { single_status } if single_status.leaked => {
+                StatusLevel::Leak
+            }
             ExecutionDescription::Success { .. } => StatusLevel::Pass,
             ExecutionDescription::Flaky { .. } => StatusLevel::Retry,
             ExecutionDescription::Failure { .. } => StatusLevel::Fail,
+            ExecutionDescription::Skipped { .. } => StatusLevel::Skip,
         }
     }
 }
@@ -512,12 +1648,51 @@ pub struct ExecuteStatus {
     pub total_attempts: usize,
     /// Standard output and standard error for this test.
     pub stdout_stderr: Arc<(Vec<u8>, Vec<u8>)>,
-    /// The result of execution this test: pass, fail or execution error.
+    /// The file standard output was written to, if the profile sets `store.output-dir`.
+    ///
+    /// See [`RunStore::test_output_path`] for more.
+    pub stdout_path: Option<Utf8PathBuf>,
+    /// The file standard error was written to, if the profile sets `store.output-dir`.
+    pub stderr_path: Option<Utf8PathBuf>,
+    /// Files collected alongside the output of this test, such as its log file.
+    pub attachments: Vec<TestAttachment>,
+    /// The reason given by the test for skipping itself, if `result` is
+    /// [`ExecutionResult::Skip`]. May be empty.
+    pub skip_reason: Option<String>,
+    /// The result of execution this test: pass, fail, execution error or skip.
     pub result: ExecutionResult,
+    /// Whether the test's standard output or standard error were kept open for longer than
+    /// [`LEAK_TIMEOUT`] after it exited, typically by a subprocess that it didn't wait for.
+    pub leaked: bool,
     /// The time at which the test started.
     pub start_time: SystemTime,
     /// The time it took for the test to run.
     pub time_taken: Duration,
+    /// The number of bytes the test wrote to its temporary directory and log file.
+    pub disk_usage: u64,
+    /// Why the test aborted, if it was killed by a signal or crashed.
+    pub abort_status: Option<AbortStatus>,
+    /// The core dump of the test collected into the run directory, if it aborted with core dumps
+    /// enabled.
+    ///
+    /// See [`crash`](crate::crash) for more.
+    pub core_dump: Option<Utf8PathBuf>,
+    /// The report of the sanitizer that made the test fail, if any.
+    ///
+    /// See [`sanitizer_report`](crate::sanitizer_report) for more.
+    pub sanitizer_report: Option<SanitizerReport>,
+    /// The failing case found by a property-based testing framework, if the test failed that way.
+    ///
+    /// See [`property_test`](crate::property_test) for more.
+    pub property_test_failure: Option<PropertyTestFailure>,
+    /// The coverage profile the test wrote, merged into the run directory, if coverage is
+    /// collected.
+    ///
+    /// See [`llvm_coverage`](crate::llvm_coverage) for more.
+    pub coverage_profile: Option<Utf8PathBuf>,
+    /// The peak resident set size of the test, in bytes, if memory is
+    /// [tracked](TestRunnerBuilder::set_track_memory).
+    pub peak_rss: Option<u64>,
 }
 
 impl ExecuteStatus {
@@ -532,26 +1707,176 @@ impl ExecuteStatus {
     }
 }
 
+/// A file collected alongside the output of a test.
+#[derive(Clone, Debug)]
+pub struct TestAttachment {
+    /// The path to the file.
+    pub path: Utf8PathBuf,
+    /// The contents of the file.
+    pub contents: Arc<Vec<u8>>,
+}
+
 struct InternalExecuteStatus {
     stdout: Vec<u8>,
     stderr: Vec<u8>,
+    attachments: Vec<TestAttachment>,
+    skip_reason: Option<String>,
     result: ExecutionResult,
+    leaked: bool,
+    disk_usage: u64,
+    abort_status: Option<AbortStatus>,
+    core_dump: Option<Utf8PathBuf>,
+    sanitizer_report: Option<SanitizerReport>,
+    property_test_failure: Option<PropertyTestFailure>,
+    coverage_profile: Option<Utf8PathBuf>,
+    peak_rss: Option<u64>,
     stopwatch_end: StopwatchEnd,
 }
 
 impl InternalExecuteStatus {
+    /// Returns the status of a test that couldn't be run, with the reason in its standard error.
+    fn exec_fail(stderr: Vec<u8>) -> Self {
+        Self {
+            stdout: vec![],
+            stderr,
+            attachments: vec![],
+            skip_reason: None,
+            result: ExecutionResult::ExecFail,
+            leaked: false,
+            disk_usage: 0,
+            abort_status: None,
+            core_dump: None,
+            sanitizer_report: None,
+            property_test_failure: None,
+            coverage_profile: None,
+            peak_rss: None,
+            stopwatch_end: StopwatchStart::now().end(),
+        }
+    }
+
+    /// Fails the test with [`ExecutionResult::Timeout`] if it passed or failed after running for
+    /// longer than its time limit, noting why in its standard error.
+    fn apply_time_limit(&mut self, time_limit: TimeLimit) {
+        let duration = self.stopwatch_end.duration;
+        if duration <= time_limit.limit()
+            || !matches!(self.result, ExecutionResult::Pass | ExecutionResult::Fail)
+        {
+            return;
+        }
+        self.result = ExecutionResult::Timeout;
+        let killed = time_limit
+            .kill_after()
+            .is_some_and(|kill_after| duration >= kill_after);
+        let _ = writeln!(
+            self.stderr,
+            "\nnextest: test {} after {:.3}s, exceeding its time limit of {:.3}s",
+            if killed { "was killed" } else { "finished" },
+            duration.as_secs_f64(),
+            time_limit.limit().as_secs_f64(),
+        );
+    }
+
+    /// Turns a failure into [`ExecutionResult::ExpectedFail`] and a pass into
+    /// [`ExecutionResult::UnexpectedPass`] if the test is expected to fail, noting why in its
+    /// standard error.
+    fn apply_expected_result(&mut self, expected_result: ExpectedResult) {
+        if expected_result != ExpectedResult::Fail {
+            return;
+        }
+        let note = match self.result {
+            ExecutionResult::Fail => {
+                self.result = ExecutionResult::ExpectedFail;
+                "failed, as expected"
+            }
+            ExecutionResult::Pass => {
+                self.result = ExecutionResult::UnexpectedPass;
+                "passed, but was expected to fail"
+            }
+            _ => return,
+        };
+        let _ = writeln!(self.stderr, "\nnextest: test {}", note);
+    }
+
     fn into_external(self, attempt: usize, total_attempts: usize) -> ExecuteStatus {
         ExecuteStatus {
             attempt,
             total_attempts,
             stdout_stderr: Arc::new((self.stdout, self.stderr)),
+            stdout_path: None,
+            stderr_path: None,
+            attachments: self.attachments,
+            skip_reason: self.skip_reason,
             result: self.result,
+            leaked: self.leaked,
             start_time: self.stopwatch_end.start_time,
             time_taken: self.stopwatch_end.duration,
+            disk_usage: self.disk_usage,
+            abort_status: self.abort_status,
+            core_dump: self.core_dump,
+            sanitizer_report: self.sanitizer_report,
+            property_test_failure: self.property_test_failure,
+            coverage_profile: self.coverage_profile,
+            peak_rss: self.peak_rss,
         }
     }
 }
 
+/// A snapshot of the state of a test run, produced on request while the run is in progress.
+///
+/// A snapshot is requested through SIGQUIT, or in interactive mode by pressing `.`. See
+/// [`SignalHandler`] for more. Snapshots are also produced periodically if a
+/// [progress interval](TestRunnerBuilder::set_progress_interval) is set.
+#[derive(Clone, Debug)]
+pub struct RunSnapshot<'a> {
+    /// The amount of time that has elapsed since the beginning of the run.
+    pub elapsed: Duration,
+
+    /// Tests that are currently running, in the order they were started.
+    pub running: Vec<RunningTestSnapshot<'a>>,
+
+    /// The number of tests that haven't started running yet.
+    pub queued: usize,
+
+    /// The most recently finished tests, most recent first.
+    ///
+    /// At most [`Self::RECENTLY_FINISHED_COUNT`] tests are included.
+    pub recently_finished: Vec<FinishedTestSnapshot<'a>>,
+
+    /// Statistics for the run so far.
+    pub run_stats: RunStats,
+}
+
+impl<'a> RunSnapshot<'a> {
+    /// The maximum number of recently finished tests included in a snapshot.
+    pub const RECENTLY_FINISHED_COUNT: usize = 5;
+}
+
+/// A test that is currently running, as part of a [`RunSnapshot`].
+#[derive(Clone, Debug)]
+pub struct RunningTestSnapshot<'a> {
+    /// The test instance that is running.
+    pub test_instance: TestInstance<'a>,
+
+    /// The current attempt. In the range `[1, total_attempts]`.
+    pub attempt: usize,
+
+    /// The amount of time that has elapsed since the first attempt of the test started.
+    pub elapsed: Duration,
+}
+
+/// A test that finished recently, as part of a [`RunSnapshot`].
+#[derive(Clone, Debug)]
+pub struct FinishedTestSnapshot<'a> {
+    /// The test instance that finished.
+    pub test_instance: TestInstance<'a>,
+
+    /// The result of the last attempt to run the test.
+    pub result: ExecutionResult,
+
+    /// The time the last attempt took to run.
+    pub time_taken: Duration,
+}
+
 /// Statistics for a test run.
 #[derive(Copy, Clone, Default, Debug)]
 pub struct RunStats {
@@ -566,6 +1891,10 @@ pub struct RunStats {
     /// The number of tests that passed. Includes `flaky`.
     pub passed: usize,
 
+    /// The number of tests that skipped themselves at runtime. These are not included in
+    /// `passed` or `skipped`.
+    pub runtime_skipped: usize,
+
     /// The number of tests that passed on retry.
     pub flaky: usize,
 
@@ -575,22 +1904,71 @@ pub struct RunStats {
     /// The number of tests that encountered an execution failure.
     pub exec_failed: usize,
 
+    /// The number of tests that wrote more to disk than their disk quota allows.
+    pub disk_quota_exceeded: usize,
+
+    /// The number of tests that took longer than their time limit.
+    pub timed_out: usize,
+
     /// The number of tests that were skipped.
     pub skipped: usize,
+
+    /// The number of tests that weren't run because the maximum duration for the run would have
+    /// been exceeded. These are not included in `skipped`.
+    pub overflowed: usize,
+
+    /// The number of tests whose duration was predicted to enforce the maximum duration for the
+    /// run.
+    pub timing_predictions: usize,
+
+    /// The number of those predictions that used the default estimate, because there was no timing
+    /// data for the test.
+    pub timing_misses: usize,
+
+    /// The number of test binaries that weren't run at all, because the filter couldn't match any
+    /// tests in them. Tests in these binaries aren't included in `skipped`.
+    pub skipped_binaries: usize,
+
+    /// The number of setup and teardown commands for [test groups](crate::test_groups) that
+    /// failed.
+    pub group_hooks_failed: usize,
+
+    /// The number of [quarantined](crate::quarantine) tests that failed. These are not included
+    /// in `failed`, `exec_failed`, `disk_quota_exceeded` or `timed_out`.
+    pub quarantined: usize,
+
+    /// The number of tests that failed, and were [expected to](crate::config::ExpectedResult).
+    /// These are not included in `passed` or `failed`.
+    pub expected_failures: usize,
+
+    /// The number of tests that passed, but were [expected to fail](crate::config::ExpectedResult).
+    /// These are not included in `passed`.
+    pub unexpected_passes: usize,
 }
 
 impl RunStats {
     /// Returns true if this run is considered a success.
     ///
     /// A run can be marked as failed if any of the following are true:
-    /// * the run was canceled: the initial run count is greater than the final run count
+    /// * the run was canceled: the initial run count is greater than the final run count, not
+    ///   counting tests that overflowed the maximum duration
     /// * any tests failed
     /// * any tests encountered an execution failure
+    /// * any tests exceeded their disk quota
+    /// * any tests took longer than their time limit
+    /// * any tests passed when they were expected to fail
+    /// * the setup or teardown command of a test group failed
     pub fn is_success(&self) -> bool {
-        if self.initial_run_count > self.final_run_count {
+        if self.initial_run_count > self.final_run_count + self.overflowed {
             return false;
         }
-        if self.failed > 0 || self.exec_failed > 0 {
+        if self.failed > 0
+            || self.exec_failed > 0
+            || self.disk_quota_exceeded > 0
+            || self.timed_out > 0
+            || self.unexpected_passes > 0
+            || self.group_hooks_failed > 0
+        {
             return false;
         }
         true
@@ -607,6 +1985,10 @@ impl RunStats {
         // This is not likely to matter much in practice since failures are likely to be of the
         // same type.
         let last_status = run_statuses.last_status();
+        if run_statuses.is_quarantined_failure() {
+            self.quarantined += 1;
+            return;
+        }
         match last_status.result {
             ExecutionResult::Pass => {
                 self.passed += 1;
@@ -616,47 +1998,69 @@ impl RunStats {
             }
             ExecutionResult::Fail => self.failed += 1,
             ExecutionResult::ExecFail => self.exec_failed += 1,
+            ExecutionResult::DiskQuotaExceeded => self.disk_quota_exceeded += 1,
+            ExecutionResult::Timeout => self.timed_out += 1,
+            ExecutionResult::Skip => self.runtime_skipped += 1,
+            ExecutionResult::ExpectedFail => self.expected_failures += 1,
+            ExecutionResult::UnexpectedPass => self.unexpected_passes += 1,
         }
     }
 }
 
-struct CallbackContext<F, E> {
+struct CallbackContext<'a, F, E> {
     callback: F,
     stopwatch: StopwatchStart,
     run_stats: RunStats,
     fail_fast: bool,
-    running: usize,
+    // Tests that are currently running, in the order they were started.
+    running: Vec<RunningTest<'a>>,
+    // The most recently finished tests, most recent first.
+    recently_finished: VecDeque<FinishedTestSnapshot<'a>>,
     cancel_state: Option<CancelReason>,
     phantom: PhantomData<E>,
 }
 
-impl<'a, F, E> CallbackContext<F, E>
+impl<'a, F, E> CallbackContext<'a, F, E>
 where
     F: FnMut(TestEvent<'a>) -> Result<(), E> + Send,
 {
-    fn new(callback: F, initial_run_count: usize, fail_fast: bool) -> Self {
+    fn new(
+        callback: F,
+        initial_run_count: usize,
+        skipped_binaries: usize,
+        fail_fast: bool,
+    ) -> Self {
         Self {
             callback,
             stopwatch: StopwatchStart::now(),
             run_stats: RunStats {
                 initial_run_count,
+                skipped_binaries,
                 ..RunStats::default()
             },
             fail_fast,
-            running: 0,
+            running: Vec::new(),
+            recently_finished: VecDeque::with_capacity(RunSnapshot::RECENTLY_FINISHED_COUNT),
             cancel_state: None,
             phantom: PhantomData,
         }
     }
 
-    fn run_started(&mut self, test_list: &'a TestList) -> Result<(), E> {
-        (self.callback)(TestEvent::RunStarted { test_list })
+    fn run_started(&mut self, test_list: &'a TestList, run_count: usize) -> Result<(), E> {
+        (self.callback)(TestEvent::RunStarted {
+            test_list,
+            run_count,
+        })
     }
 
     fn handle_event(&mut self, event: InternalEvent<'a>) -> Result<(), InternalError<E>> {
         match event {
             InternalEvent::Test(InternalTestEvent::Started { test_instance }) => {
-                self.running += 1;
+                self.running.push(RunningTest {
+                    test_instance,
+                    attempt: 1,
+                    stopwatch: StopwatchStart::now(),
+                });
                 (self.callback)(TestEvent::TestStarted { test_instance })
                     .map_err(InternalError::Error)
             }
@@ -671,20 +2075,38 @@ where
             InternalEvent::Test(InternalTestEvent::Retry {
                 test_instance,
                 run_status,
-            }) => (self.callback)(TestEvent::TestRetry {
-                test_instance,
-                run_status,
-            })
-            .map_err(InternalError::Error),
+            }) => {
+                if let Some(running) = self.running_mut(&test_instance) {
+                    running.attempt = run_status.attempt + 1;
+                }
+                (self.callback)(TestEvent::TestRetry {
+                    test_instance,
+                    run_status,
+                })
+                .map_err(InternalError::Error)
+            }
             InternalEvent::Test(InternalTestEvent::Finished {
                 test_instance,
                 run_statuses,
             }) => {
-                self.running -= 1;
+                self.running
+                    .retain(|running| running.test_instance != test_instance);
                 self.run_stats.on_test_finished(&run_statuses);
 
+                let last_status = run_statuses.last_status();
+                if self.recently_finished.len() == RunSnapshot::RECENTLY_FINISHED_COUNT {
+                    self.recently_finished.pop_back();
+                }
+                self.recently_finished.push_front(FinishedTestSnapshot {
+                    test_instance,
+                    result: last_status.result,
+                    time_taken: last_status.time_taken,
+                });
+
                 // should this run be canceled because of a failure?
-                let fail_cancel = self.fail_fast && !run_statuses.last_status().result.is_success();
+                let fail_cancel = self.fail_fast
+                    && !run_statuses.last_status().result.is_success()
+                    && !run_statuses.is_quarantined();
 
                 (self.callback)(TestEvent::TestFinished {
                     test_instance,
@@ -705,13 +2127,21 @@ where
                 test_instance,
                 reason,
             }) => {
-                self.run_stats.skipped += 1;
+                match reason {
+                    MismatchReason::Overflow => self.run_stats.overflowed += 1,
+                    _ => self.run_stats.skipped += 1,
+                }
                 (self.callback)(TestEvent::TestSkipped {
                     test_instance,
                     reason,
                 })
                 .map_err(InternalError::Error)
             }
+            InternalEvent::Test(InternalTestEvent::GroupHookFailed { group, hook, error }) => {
+                self.run_stats.group_hooks_failed += 1;
+                (self.callback)(TestEvent::TestGroupHookFailed { group, hook, error })
+                    .map_err(InternalError::Error)
+            }
             InternalEvent::Signal(SignalEvent::Interrupted) => {
                 if self.cancel_state == Some(CancelReason::Signal) {
                     // Ctrl-C was pressed twice -- panic in this case.
@@ -722,6 +2152,42 @@ where
                     self.begin_cancel(CancelReason::Signal).err(),
                 ))
             }
+            InternalEvent::Signal(SignalEvent::Info) => {
+                let snapshot = self.snapshot();
+                (self.callback)(TestEvent::RunSnapshot { snapshot }).map_err(InternalError::Error)
+            }
+            InternalEvent::ProgressTick => {
+                let snapshot = self.snapshot();
+                (self.callback)(TestEvent::RunProgress { snapshot }).map_err(InternalError::Error)
+            }
+        }
+    }
+
+    fn running_mut(&mut self, test_instance: &TestInstance<'a>) -> Option<&mut RunningTest<'a>> {
+        self.running
+            .iter_mut()
+            .find(|running| &running.test_instance == test_instance)
+    }
+
+    fn snapshot(&self) -> RunSnapshot<'a> {
+        let queued = self
+            .run_stats
+            .initial_run_count
+            .saturating_sub(self.run_stats.final_run_count + self.running.len());
+        RunSnapshot {
+            elapsed: self.stopwatch.elapsed(),
+            running: self
+                .running
+                .iter()
+                .map(|running| RunningTestSnapshot {
+                    test_instance: running.test_instance,
+                    attempt: running.attempt,
+                    elapsed: running.stopwatch.elapsed(),
+                })
+                .collect(),
+            queued,
+            recently_finished: self.recently_finished.iter().cloned().collect(),
+            run_stats: self.run_stats,
         }
     }
 
@@ -731,7 +2197,7 @@ where
         if self.cancel_state < Some(reason) {
             self.cancel_state = Some(reason);
             (self.callback)(TestEvent::RunBeginCancel {
-                running: self.running,
+                running: self.running.len(),
                 reason,
             })?;
         }
@@ -748,10 +2214,18 @@ where
     }
 }
 
+struct RunningTest<'a> {
+    test_instance: TestInstance<'a>,
+    attempt: usize,
+    // Measured from when the first attempt started.
+    stopwatch: StopwatchStart,
+}
+
 #[derive(Debug)]
 enum InternalEvent<'a> {
     Test(InternalTestEvent<'a>),
     Signal(SignalEvent),
+    ProgressTick,
 }
 
 #[derive(Debug)]
@@ -765,7 +2239,7 @@ enum InternalTestEvent<'a> {
     },
     Retry {
         test_instance: TestInstance<'a>,
-        run_status: ExecuteStatus,
+        run_status: Box<ExecuteStatus>,
     },
     Finished {
         test_instance: TestInstance<'a>,
@@ -775,6 +2249,34 @@ enum InternalTestEvent<'a> {
         test_instance: TestInstance<'a>,
         reason: MismatchReason,
     },
+    GroupHookFailed {
+        group: &'a str,
+        hook: TestGroupHook,
+        error: String,
+    },
+}
+
+/// State shared by the tests in a run while it's in progress.
+#[derive(Debug)]
+struct RunState<'r, 'a> {
+    canceled: AtomicBool,
+    slots: WeightedSemaphore,
+    budget_start: StopwatchStart,
+    budget_exhausted: AtomicBool,
+    timing_predictions: AtomicUsize,
+    timing_misses: AtomicUsize,
+    group_states: TestGroupStates<'a>,
+    dependency_states: Option<DependencyStates<'r, 'a>>,
+}
+
+/// The slots a test holds while it runs.
+#[derive(Clone, Debug)]
+struct TestSlots<'a> {
+    global: usize,
+    // The test group and the slot within it, if the test is in a group.
+    group: Option<(&'a str, usize)>,
+    // The environment variables written by the setup command of the test's group.
+    group_env: Vec<(String, String)>,
 }
 
 #[derive(Debug)]
@@ -793,18 +2295,184 @@ pub enum ExecutionResult {
     Fail,
     /// An error occurred while executing the test.
     ExecFail,
+    /// The test skipped itself at runtime.
+    ///
+    /// See [`NEXTEST_SKIP_FILE_ENV`] for how tests skip themselves.
+    Skip,
+    /// The test wrote more to disk than its disk quota allows.
+    ///
+    /// See [`disk_usage`](crate::disk_usage) for more.
+    DiskQuotaExceeded,
+    /// The test took longer than its time limit, whether or not it finished on its own.
+    ///
+    /// See [`TimeLimit`](crate::config::TimeLimit) for more.
+    Timeout,
+    /// The test failed, and was expected to.
+    ///
+    /// See [`ExpectedResult`] for more.
+    ExpectedFail,
+    /// The test passed, but was expected to fail.
+    ///
+    /// See [`ExpectedResult`] for more.
+    UnexpectedPass,
 }
 
 impl ExecutionResult {
     /// Returns true if the test was successful.
+    ///
+    /// Tests that skipped themselves are considered successful, since they aren't retried and
+    /// don't fail the run. So are tests that failed when they were expected to.
     pub fn is_success(self) -> bool {
         match self {
-            ExecutionResult::Pass => true,
-            ExecutionResult::Fail | ExecutionResult::ExecFail => false,
+            ExecutionResult::Pass | ExecutionResult::Skip | ExecutionResult::ExpectedFail => true,
+            ExecutionResult::Fail
+            | ExecutionResult::ExecFail
+            | ExecutionResult::DiskQuotaExceeded
+            | ExecutionResult::Timeout
+            | ExecutionResult::UnexpectedPass => false,
+        }
+    }
+}
+
+/// Waits for the process to exit without reaping it, so that it can still be waited for through
+/// its handle. Returns the time at which it exited.
+#[cfg(unix)]
+fn wait_for_exit(pid: u32) -> Option<ClockInstant> {
+    loop {
+        // SAFETY: siginfo_t is a plain C struct, for which all zeroes is a valid value.
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: waitid only writes to the siginfo_t struct passed in. WNOWAIT leaves the process
+        // in a waitable state.
+        let ret = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if ret == 0 {
+            return Some(ClockInstant::now());
+        }
+        if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+            return None;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_for_exit(_pid: u32) -> Option<ClockInstant> {
+    None
+}
+
+/// Like [`wait_for_exit`], but also samples the peak resident set size of the process while it
+/// runs, returning the last sample taken before it exited.
+#[cfg(target_os = "linux")]
+fn wait_for_exit_tracking_memory(pid: u32) -> (Option<ClockInstant>, Option<u64>) {
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+    let mut peak_rss = None;
+    loop {
+        // SAFETY: siginfo_t is a plain C struct, for which all zeroes is a valid value.
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: waitid only writes to the siginfo_t struct passed in. WNOWAIT leaves the process
+        // in a waitable state.
+        let ret = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT | libc::WNOHANG,
+            )
+        };
+        if ret != 0 {
+            if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return (None, peak_rss);
+        }
+        // With WNOHANG, the process ID is left as zero if the process hasn't exited yet.
+        // SAFETY: waitid filled in the siginfo_t struct.
+        if unsafe { info.si_pid() } != 0 {
+            return (Some(ClockInstant::now()), peak_rss);
         }
+        // The peak only ever goes up, but it can't be read once the process has exited.
+        peak_rss = crate::soak::peak_rss(pid).or(peak_rss);
+        std::thread::sleep(SAMPLE_INTERVAL);
     }
 }
 
+#[cfg(not(target_os = "linux"))]
+fn wait_for_exit_tracking_memory(pid: u32) -> (Option<ClockInstant>, Option<u64>) {
+    (wait_for_exit(pid), None)
+}
+
+/// Fails a test that passed or failed if its Memcheck report has errors that fail it, listing them
+/// in its standard error. Returns the report as an attachment, if valgrind wrote one.
+fn apply_memcheck_report(
+    valgrind: &Valgrind,
+    xml_path: &Utf8Path,
+    status: ExecutionResult,
+    stderr: &mut Vec<u8>,
+) -> (ExecutionResult, Option<TestAttachment>) {
+    // No report is written for tests that aren't run under valgrind, such as tests in containers.
+    let Ok(xml) = std::fs::read(xml_path) else {
+        return (status, None);
+    };
+    let report = MemcheckReport::parse(&String::from_utf8_lossy(&xml));
+    let attachment = TestAttachment {
+        path: xml_path.to_owned(),
+        contents: Arc::new(xml),
+    };
+    let failures: Vec<_> = report.failures(valgrind.fail_on_leaks()).collect();
+    if failures.is_empty() || !matches!(status, ExecutionResult::Pass | ExecutionResult::Fail) {
+        return (status, Some(attachment));
+    }
+
+    let _ = writeln!(
+        stderr,
+        "\nnextest: valgrind found {} {}:",
+        failures.len(),
+        if failures.len() == 1 {
+            "error"
+        } else {
+            "errors"
+        },
+    );
+    for error in failures {
+        let _ = writeln!(stderr, "  {}: {}", error.kind, error.what);
+    }
+    (ExecutionResult::Fail, Some(attachment))
+}
+
+/// Reorders items so that they're taken from each group in turn, in the order the groups first
+/// appear in. Items within a group keep their relative order.
+fn round_robin<T, K: Eq>(items: Vec<T>, key: impl Fn(&T) -> K) -> Vec<T> {
+    let mut groups: Vec<(K, VecDeque<T>)> = vec![];
+    for item in items {
+        let item_key = key(&item);
+        match groups
+            .iter_mut()
+            .find(|(group_key, _)| *group_key == item_key)
+        {
+            Some((_, group)) => group.push_back(item),
+            None => groups.push((item_key, VecDeque::from([item]))),
+        }
+    }
+
+    let mut ordered = vec![];
+    while !groups.is_empty() {
+        groups.retain_mut(|(_, group)| match group.pop_front() {
+            Some(item) => {
+                ordered.push(item);
+                true
+            }
+            None => false,
+        });
+    }
+    ordered
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -833,6 +2501,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn round_robin_order() {
+        let tests = vec![("a", 1), ("a", 2), ("a", 3), ("b", 1), ("c", 1), ("c", 2)];
+        assert_eq!(
+            round_robin(tests, |(binary, _)| *binary),
+            [("a", 1), ("b", 1), ("c", 1), ("a", 2), ("c", 2), ("a", 3)]
+        );
+        assert!(round_robin(Vec::<&str>::new(), |test| *test).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wait_for_exit_without_reaping() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        assert!(wait_for_exit(child.id()).is_some(), "process exited");
+        // The process can still be waited for, and its exit status collected.
+        assert_eq!(child.wait().unwrap().code(), Some(3));
+        assert!(
+            wait_for_exit(child.id()).is_none(),
+            "reaped process can't be waited for"
+        );
+    }
+
     #[test]
     fn test_is_success() {
         assert!(RunStats::default().is_success(), "empty run => success");
@@ -874,6 +2568,46 @@ mod tests {
             .is_success(),
             "exec failed => failure"
         );
+        assert!(
+            !RunStats {
+                initial_run_count: 42,
+                final_run_count: 42,
+                disk_quota_exceeded: 1,
+                ..RunStats::default()
+            }
+            .is_success(),
+            "disk quota exceeded => failure"
+        );
+        assert!(
+            !RunStats {
+                initial_run_count: 42,
+                final_run_count: 42,
+                timed_out: 1,
+                ..RunStats::default()
+            }
+            .is_success(),
+            "timed out => failure"
+        );
+        assert!(
+            RunStats {
+                initial_run_count: 42,
+                final_run_count: 42,
+                expected_failures: 1,
+                ..RunStats::default()
+            }
+            .is_success(),
+            "expected failure => not considered a failure"
+        );
+        assert!(
+            !RunStats {
+                initial_run_count: 42,
+                final_run_count: 42,
+                unexpected_passes: 1,
+                ..RunStats::default()
+            }
+            .is_success(),
+            "unexpected pass => failure"
+        );
         assert!(
             RunStats {
                 initial_run_count: 42,
@@ -0,0 +1,351 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Starting and stopping the environment a target runner needs, such as an emulator.
+//!
+//! Runners like QEMU system emulators or the Android emulator need to be booted before tests can
+//! be run through them. Rather than requiring a wrapper script around nextest, a `target-runner`
+//! rule can specify commands that are run once per run:
+//!
+//! ```toml
+//! [[profile.default.target-runner]]
+//! platform = "x86_64-linux-android"
+//! command = ["scripts/adb-runner.sh"]
+//! # Boots the emulator. If a readiness probe is specified, this runs in the background for the
+//! # duration of the run; otherwise, nextest waits for it to exit.
+//! start = ["emulator", "-avd", "nextest", "-no-window"]
+//! # Run repeatedly until it succeeds, before any tests are listed or run.
+//! ready = ["sh", "-c", "adb shell getprop sys.boot_completed | grep -q 1"]
+//! # How long to wait for the probe to succeed. Defaults to 60 seconds.
+//! ready-timeout = "120s"
+//! # Run after all tests have finished.
+//! stop = ["adb", "emu", "kill"]
+//! ```
+//!
+//! All commands are run in the workspace root, and relative program paths that contain a `/` are
+//! relative to it. If a start command running in the background is still running once the stop
+//! command has finished (or if there's no stop command), it is killed.
+
+use crate::{errors::RunnerLifecycleError, stopwatch::ClockInstant};
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::{cmd, Handle};
+use std::{thread, time::Duration};
+
+/// Commands that start and stop the environment a target runner needs, run once per test run.
+///
+/// Obtained through
+/// [`TargetRunnerRule::lifecycle`](crate::target_runner::TargetRunnerRule::lifecycle).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RunnerLifecycle {
+    start: Option<Vec<String>>,
+    ready: Option<Vec<String>>,
+    ready_timeout: Duration,
+    stop: Option<Vec<String>>,
+    dir: Utf8PathBuf,
+}
+
+impl RunnerLifecycle {
+    /// The default time to wait for the readiness probe to succeed: 60 seconds.
+    pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+    // How often the readiness probe is run.
+    const READY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Creates a new lifecycle from the commands to start the environment with, to check if it's
+    /// ready and to stop it with. Returns `None` if none of the commands are specified.
+    pub fn new(
+        start: Option<Vec<String>>,
+        ready: Option<Vec<String>>,
+        ready_timeout: Option<Duration>,
+        stop: Option<Vec<String>>,
+    ) -> Option<Self> {
+        let non_empty = |command: Option<Vec<String>>| command.filter(|words| !words.is_empty());
+        let (start, ready, stop) = (non_empty(start), non_empty(ready), non_empty(stop));
+        if start.is_none() && ready.is_none() && stop.is_none() {
+            return None;
+        }
+        Some(Self {
+            start,
+            ready,
+            ready_timeout: ready_timeout.unwrap_or(Self::DEFAULT_READY_TIMEOUT),
+            stop,
+            dir: Utf8PathBuf::from("."),
+        })
+    }
+
+    /// Returns the command that starts the environment, if any.
+    pub fn start(&self) -> Option<&[String]> {
+        self.start.as_deref()
+    }
+
+    /// Returns the command that is run until it succeeds to check if the environment is ready, if
+    /// any.
+    pub fn ready(&self) -> Option<&[String]> {
+        self.ready.as_deref()
+    }
+
+    /// Returns how long to wait for the readiness probe to succeed.
+    pub fn ready_timeout(&self) -> Duration {
+        self.ready_timeout
+    }
+
+    /// Returns the command that stops the environment, if any.
+    pub fn stop(&self) -> Option<&[String]> {
+        self.stop.as_deref()
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    // Returns this lifecycle with its commands run in the given directory, and relative program
+    // paths resolved against it.
+    pub(crate) fn in_dir(&self, dir: &Utf8Path, resolve: impl Fn(&str) -> String) -> Self {
+        let resolve_command = |command: &Option<Vec<String>>| {
+            command.as_ref().map(|words| {
+                let mut words = words.clone();
+                words[0] = resolve(&words[0]);
+                words
+            })
+        };
+        Self {
+            start: resolve_command(&self.start),
+            ready: resolve_command(&self.ready),
+            ready_timeout: self.ready_timeout,
+            stop: resolve_command(&self.stop),
+            dir: dir.to_owned(),
+        }
+    }
+
+    // Starts the environment, returning the start command if it's still running in the
+    // background.
+    fn run_start(&self) -> Result<Option<Handle>, RunnerLifecycleError> {
+        let ready = match &self.ready {
+            Some(ready) => ready,
+            None => {
+                if let Some(start) = &self.start {
+                    self.run_to_completion(start)?;
+                }
+                return Ok(None);
+            }
+        };
+
+        let handle = match &self.start {
+            Some(start) => Some(
+                cmd(&start[0], &start[1..])
+                    .dir(&self.dir)
+                    .stdin_null()
+                    .stdout_null()
+                    .stderr_null()
+                    .unchecked()
+                    .start()
+                    .map_err(|error| RunnerLifecycleError::Exec {
+                        command: start.join(" "),
+                        error,
+                    })?,
+            ),
+            None => None,
+        };
+
+        let deadline = ClockInstant::now() + self.ready_timeout;
+        loop {
+            let status = cmd(&ready[0], &ready[1..])
+                .dir(&self.dir)
+                .stdin_null()
+                .stdout_null()
+                .stderr_null()
+                .unchecked()
+                .run();
+            match status {
+                Ok(output) if output.status.success() => return Ok(handle),
+                Ok(_) => {}
+                Err(error) => {
+                    kill(handle.as_ref());
+                    return Err(RunnerLifecycleError::Exec {
+                        command: ready.join(" "),
+                        error,
+                    });
+                }
+            }
+
+            // A start command that exits successfully may have left the environment running in
+            // the background, so only failures are reported.
+            if let (Some(handle), Some(start)) = (&handle, &self.start) {
+                if let Ok(Some(output)) = handle.try_wait() {
+                    if !output.status.success() {
+                        return Err(RunnerLifecycleError::Failed {
+                            command: start.join(" "),
+                            status: output.status,
+                        });
+                    }
+                }
+            }
+
+            if ClockInstant::now() >= deadline {
+                kill(handle.as_ref());
+                return Err(RunnerLifecycleError::NotReady {
+                    command: ready.join(" "),
+                    timeout: self.ready_timeout,
+                });
+            }
+            thread::sleep(Self::READY_POLL_INTERVAL);
+        }
+    }
+
+    fn run_stop(&self, handle: Option<Handle>) -> Result<(), RunnerLifecycleError> {
+        let res = match &self.stop {
+            Some(stop) => self.run_to_completion(stop),
+            None => Ok(()),
+        };
+        kill(handle.as_ref());
+        res
+    }
+
+    fn run_to_completion(&self, command: &[String]) -> Result<(), RunnerLifecycleError> {
+        let output = cmd(&command[0], &command[1..])
+            .dir(&self.dir)
+            .stdin_null()
+            .stdout_null()
+            .stderr_null()
+            .unchecked()
+            .run()
+            .map_err(|error| RunnerLifecycleError::Exec {
+                command: command.join(" "),
+                error,
+            })?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(RunnerLifecycleError::Failed {
+                command: command.join(" "),
+                status: output.status,
+            })
+        }
+    }
+}
+
+/// The environments started for a test run, which are stopped when this is dropped.
+///
+/// Returned by [`TargetRunner::start_lifecycles`](crate::target_runner::TargetRunner::start_lifecycles).
+#[derive(Debug, Default)]
+#[must_use = "environments are stopped when the guard is dropped"]
+pub struct RunnerLifecycleGuard {
+    started: Vec<(RunnerLifecycle, Option<Handle>)>,
+}
+
+impl RunnerLifecycleGuard {
+    /// Starts the given lifecycles in order, waiting for each one to be ready.
+    ///
+    /// If one of them fails to start, the ones started before it are stopped.
+    pub(crate) fn start<'a>(
+        lifecycles: impl IntoIterator<Item = &'a RunnerLifecycle>,
+    ) -> Result<Self, RunnerLifecycleError> {
+        let mut guard = Self::default();
+        for lifecycle in lifecycles {
+            let handle = lifecycle.run_start()?;
+            guard.started.push((lifecycle.clone(), handle));
+        }
+        Ok(guard)
+    }
+
+    /// Stops the started environments in reverse order, returning the first error encountered.
+    ///
+    /// All environments are stopped even if stopping one of them fails.
+    pub fn stop(mut self) -> Result<(), RunnerLifecycleError> {
+        self.stop_impl()
+    }
+
+    fn stop_impl(&mut self) -> Result<(), RunnerLifecycleError> {
+        let mut res = Ok(());
+        while let Some((lifecycle, handle)) = self.started.pop() {
+            let stop_res = lifecycle.run_stop(handle);
+            if res.is_ok() {
+                res = stop_res;
+            }
+        }
+        res
+    }
+}
+
+impl Drop for RunnerLifecycleGuard {
+    fn drop(&mut self) {
+        // Errors can't be reported here: call stop() to see them.
+        let _ = self.stop_impl();
+    }
+}
+
+fn kill(handle: Option<&Handle>) {
+    if let Some(handle) = handle {
+        let _ = handle.kill();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Option<Vec<String>> {
+        Some(vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()])
+    }
+
+    #[test]
+    fn start_and_stop() {
+        let dir = tempfile::tempdir().expect("created temp dir");
+        let dir = Utf8Path::from_path(dir.path()).expect("temp dir is valid UTF-8");
+        let resolve = |program: &str| program.to_owned();
+
+        assert_eq!(RunnerLifecycle::new(None, Some(vec![]), None, None), None);
+
+        // The start command runs in the background until the probe sees the marker it writes.
+        let lifecycle = RunnerLifecycle::new(
+            sh("touch booted && sleep 60"),
+            sh("test -f booted"),
+            Some(Duration::from_secs(10)),
+            sh("touch stopped"),
+        )
+        .expect("commands are specified")
+        .in_dir(dir, resolve);
+        let guard = RunnerLifecycleGuard::start([&lifecycle]).expect("lifecycle started");
+        assert!(dir.join("booted").exists());
+        assert!(!dir.join("stopped").exists());
+        guard.stop().expect("lifecycle stopped");
+        assert!(dir.join("stopped").exists());
+
+        // Without a probe, the start command is waited for.
+        let lifecycle = RunnerLifecycle::new(sh("exit 3"), None, None, None)
+            .expect("commands are specified")
+            .in_dir(dir, resolve);
+        let error = RunnerLifecycleGuard::start([&lifecycle]).expect_err("start command fails");
+        assert!(
+            matches!(error, RunnerLifecycleError::Failed { .. }),
+            "unexpected error: {:?}",
+            error
+        );
+
+        let lifecycle = RunnerLifecycle::new(
+            sh("sleep 60"),
+            sh("false"),
+            Some(Duration::from_secs(1)),
+            None,
+        )
+        .expect("commands are specified")
+        .in_dir(dir, resolve);
+        let error = RunnerLifecycleGuard::start([&lifecycle]).expect_err("probe never succeeds");
+        assert!(
+            matches!(error, RunnerLifecycleError::NotReady { .. }),
+            "unexpected error: {:?}",
+            error
+        );
+
+        // Environments started before a failing one are stopped.
+        std::fs::remove_file(dir.join("stopped")).expect("removed marker");
+        let first = RunnerLifecycle::new(None, None, None, sh("touch stopped"))
+            .expect("commands are specified")
+            .in_dir(dir, resolve);
+        let second = RunnerLifecycle::new(sh("false"), None, None, None)
+            .expect("commands are specified")
+            .in_dir(dir, resolve);
+        RunnerLifecycleGuard::start([&first, &second]).expect_err("second lifecycle fails");
+        assert!(dir.join("stopped").exists());
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Detection of reports from sanitizers such as AddressSanitizer in the output of failed tests.
+//!
+//! Tests built with `-Zsanitizer` print a report when the sanitizer finds an error, and then
+//! usually exit with a failure. Nextest looks for these reports in the output of failed tests, so
+//! that such failures can be told apart from ordinary test failures: they're shown with the
+//! sanitizer's name instead of `FAIL`, followed by the summary line of the report:
+//!
+//! ```text
+//!         ASAN [   0.020s] my-crate tests::use_after_free: AddressSanitizer: heap-use-after-free src/lib.rs:12 in foo
+//! ```
+//!
+//! The summary is also used as the failure message in JUnit reports, and is stored in run reports.
+//! Only the first report in a test's output is looked at.
+
+use std::fmt;
+
+/// A sanitizer that can report errors in tests.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Sanitizer {
+    /// AddressSanitizer.
+    Address,
+    /// Hardware-assisted AddressSanitizer.
+    HwAddress,
+    /// LeakSanitizer, on its own or as part of AddressSanitizer.
+    Leak,
+    /// MemorySanitizer.
+    Memory,
+    /// ThreadSanitizer.
+    Thread,
+    /// UndefinedBehaviorSanitizer.
+    UndefinedBehavior,
+}
+
+impl Sanitizer {
+    /// Returns the name the sanitizer uses for itself in its reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "AddressSanitizer",
+            Sanitizer::HwAddress => "HWAddressSanitizer",
+            Sanitizer::Leak => "LeakSanitizer",
+            Sanitizer::Memory => "MemorySanitizer",
+            Sanitizer::Thread => "ThreadSanitizer",
+            Sanitizer::UndefinedBehavior => "UndefinedBehaviorSanitizer",
+        }
+    }
+
+    /// Returns a short label for the sanitizer, such as `ASAN`.
+    pub fn label(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "ASAN",
+            Sanitizer::HwAddress => "HWASAN",
+            Sanitizer::Leak => "LSAN",
+            Sanitizer::Memory => "MSAN",
+            Sanitizer::Thread => "TSAN",
+            Sanitizer::UndefinedBehavior => "UBSAN",
+        }
+    }
+
+    const ALL: [Sanitizer; 6] = [
+        Sanitizer::Address,
+        Sanitizer::HwAddress,
+        Sanitizer::Leak,
+        Sanitizer::Memory,
+        Sanitizer::Thread,
+        Sanitizer::UndefinedBehavior,
+    ];
+
+    /// Returns the sanitizer with the given name.
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|sanitizer| sanitizer.name() == name)
+    }
+}
+
+impl fmt::Display for Sanitizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A report printed by a sanitizer in the output of a test.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SanitizerReport {
+    /// The sanitizer that found the error.
+    pub sanitizer: Sanitizer,
+
+    /// The summary of the error, such as
+    /// `AddressSanitizer: heap-use-after-free src/lib.rs:12 in foo`.
+    ///
+    /// This is the `SUMMARY:` line of the report without its prefix, or the line that starts the
+    /// report if there's no summary line.
+    pub summary: String,
+}
+
+impl SanitizerReport {
+    /// Looks for a sanitizer report in the output of a test.
+    pub fn detect(output: &[u8]) -> Option<Self> {
+        let output = String::from_utf8_lossy(output);
+        let mut start = None;
+        for line in output.lines() {
+            let summary = line.trim().strip_prefix("SUMMARY: ");
+            let summary_sanitizer = summary
+                .and_then(|summary| summary.split_once(": "))
+                .and_then(|(name, _)| Sanitizer::from_name(name));
+            if let (Some(summary), Some(summary_sanitizer)) = (summary, summary_sanitizer) {
+                // The start of the report names the sanitizer more precisely: for example, leaks
+                // found by LeakSanitizer as part of AddressSanitizer are summarized as
+                // AddressSanitizer.
+                let sanitizer = start.map_or(summary_sanitizer, |(sanitizer, _)| sanitizer);
+                return Some(Self {
+                    sanitizer,
+                    summary: summary.to_owned(),
+                });
+            }
+            if start.is_none() {
+                start = parse_report_start(line);
+            }
+        }
+        start.map(|(sanitizer, summary)| Self {
+            sanitizer,
+            summary: summary.to_owned(),
+        })
+    }
+}
+
+/// Parses the line that starts a report, such as `==123==ERROR: AddressSanitizer: ...` or
+/// `src/lib.rs:3:5: runtime error: ...`, into the sanitizer and the rest of the line.
+fn parse_report_start(line: &str) -> Option<(Sanitizer, &str)> {
+    for marker in ["ERROR: ", "WARNING: "] {
+        if let Some((_, rest)) = line.split_once(marker) {
+            if let Some((name, _)) = rest.split_once(": ") {
+                if let Some(sanitizer) = Sanitizer::from_name(name) {
+                    return Some((sanitizer, rest));
+                }
+            }
+        }
+    }
+    // UndefinedBehaviorSanitizer reports start with the location of the error.
+    line.contains(": runtime error: ")
+        .then(|| (Sanitizer::UndefinedBehavior, line.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_reports() {
+        let asan = "running 1 test\n\
+            =================================================================\n\
+            ==4242==ERROR: AddressSanitizer: heap-use-after-free on address 0x602000000010\n\
+            READ of size 4 at 0x602000000010 thread T0\n\
+            SUMMARY: AddressSanitizer: heap-use-after-free src/lib.rs:12 in foo\n\
+            ==4242==ABORTING\n";
+        assert_eq!(
+            SanitizerReport::detect(asan.as_bytes()),
+            Some(SanitizerReport {
+                sanitizer: Sanitizer::Address,
+                summary: "AddressSanitizer: heap-use-after-free src/lib.rs:12 in foo".to_owned(),
+            })
+        );
+
+        let leak = "==7==ERROR: LeakSanitizer: detected memory leaks\n\
+            SUMMARY: AddressSanitizer: 24 byte(s) leaked in 1 allocation(s).\n";
+        let report = SanitizerReport::detect(leak.as_bytes()).unwrap();
+        assert_eq!(report.sanitizer, Sanitizer::Leak, "named by the start");
+        assert_eq!(
+            report.summary,
+            "AddressSanitizer: 24 byte(s) leaked in 1 allocation(s)."
+        );
+
+        let tsan = "WARNING: ThreadSanitizer: data race (pid=99)\n  Write of size 8\n";
+        assert_eq!(
+            SanitizerReport::detect(tsan.as_bytes()),
+            Some(SanitizerReport {
+                sanitizer: Sanitizer::Thread,
+                summary: "ThreadSanitizer: data race (pid=99)".to_owned(),
+            }),
+            "without a summary line, the start of the report is used"
+        );
+
+        let ubsan = "src/lib.rs:3:5: runtime error: signed integer overflow\n";
+        assert_eq!(
+            SanitizerReport::detect(ubsan.as_bytes()).unwrap().sanitizer,
+            Sanitizer::UndefinedBehavior
+        );
+
+        for not_a_report in [
+            "thread 'main' panicked at 'assertion failed', src/lib.rs:1:1",
+            "SUMMARY: all tests passed",
+            "ERROR: connection refused: retrying",
+        ] {
+            assert_eq!(
+                SanitizerReport::detect(not_a_report.as_bytes()),
+                None,
+                "{:?}",
+                not_a_report
+            );
+        }
+    }
+}
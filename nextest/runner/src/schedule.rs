@@ -0,0 +1,128 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Running tests on a schedule, without a CI system.
+//!
+//! `cargo nextest schedule run --every <interval>` runs `cargo nextest run` over and over, for
+//! continuous soak testing on a local machine or an edge device. Each run is a separate
+//! `cargo nextest run` process, so changes to the workspace are picked up by the next run.
+//!
+//! Runs start at multiples of the interval after the first one. If a run takes longer than the
+//! interval, the runs it overlapped with are skipped rather than started late, one after the other.
+//!
+//! The result of each run is appended to `history.jsonl` in the profile's store directory, as one
+//! line of JSON in the format of [`ScheduledRunSummary`].
+
+use crate::{errors::RunStoreError, stopwatch::ClockInstant};
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::ScheduledRunSummary;
+use std::{fs::OpenOptions, io::Write, time::Duration};
+
+/// When scheduled runs start.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    start: ClockInstant,
+    every: Duration,
+}
+
+impl Schedule {
+    /// Creates a schedule of runs that start every `every`, with the first one starting now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `every` is zero.
+    pub fn new(every: Duration) -> Self {
+        assert!(!every.is_zero(), "runs can't be scheduled every 0s");
+        Self {
+            start: ClockInstant::now(),
+            every,
+        }
+    }
+
+    /// Returns how long to wait before the next run starts.
+    pub fn until_next(&self) -> Duration {
+        self.until_next_after(self.start.elapsed())
+    }
+
+    fn until_next_after(&self, elapsed: Duration) -> Duration {
+        let every = self.every.as_nanos();
+        let next = (elapsed.as_nanos() / every + 1) * every;
+        Duration::from_nanos((next - elapsed.as_nanos()) as u64)
+    }
+}
+
+/// The history of scheduled runs, in `history.jsonl` in a profile's store directory.
+#[derive(Clone, Debug)]
+pub struct ScheduleHistory {
+    path: Utf8PathBuf,
+}
+
+impl ScheduleHistory {
+    /// Returns the history of scheduled runs within a profile's store directory.
+    pub fn new(profile_store_dir: &Utf8Path) -> Self {
+        Self {
+            path: profile_store_dir.join("history.jsonl"),
+        }
+    }
+
+    /// Returns the path the history is written to.
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Appends a run to the history, creating the history if it doesn't exist yet.
+    pub fn append(&self, summary: &ScheduledRunSummary) -> Result<(), RunStoreError> {
+        let mut line = serde_json::to_vec(summary).map_err(RunStoreError::Json)?;
+        line.push(b'\n');
+        // The line is written with a single call, so that readers never see part of it.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|error| RunStoreError::Fs {
+                path: self.path.clone(),
+                error,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule_and_history() {
+        let schedule = Schedule::new(Duration::from_secs(10));
+        let secs = |secs| Duration::from_secs(secs);
+        assert_eq!(schedule.until_next_after(secs(3)), secs(7));
+        assert_eq!(
+            schedule.until_next_after(secs(25)),
+            secs(5),
+            "runs that would overlap are skipped"
+        );
+        assert_eq!(schedule.until_next_after(secs(10)), secs(10));
+
+        let dir = tempfile::tempdir().unwrap();
+        let store_dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let history = ScheduleHistory::new(&store_dir);
+        let summaries: Vec<_> = [Some(0), None]
+            .into_iter()
+            .map(|exit_code| ScheduledRunSummary {
+                started_at: "2022-03-01T12:00:00+00:00".to_owned(),
+                time_taken: 1.5,
+                exit_code,
+                run: None,
+            })
+            .collect();
+        for summary in &summaries {
+            history.append(summary).unwrap();
+        }
+        let contents = std::fs::read_to_string(history.path()).unwrap();
+        let parsed: Vec<_> = contents
+            .lines()
+            .map(|line| ScheduledRunSummary::parse_json(line).unwrap())
+            .collect();
+        assert_eq!(parsed, summaries);
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! External selectors: programs that choose which tests to run, and in what order.
+//!
+//! Test selection systems, for example ones that use machine learning to predict which tests are
+//! likely to fail for a change, can plug into nextest as selectors. Selectors are defined at the top
+//! level of the config:
+//!
+//! ```toml
+//! [selectors.predictive]
+//! command = ["predictive-selector", "--model", "models/latest"]
+//! timeout = "30s"
+//! ```
+//!
+//! and used with `cargo nextest run --selector predictive`.
+//!
+//! Once the test list has been built and filtered, the selector is started in the workspace root.
+//! Nextest writes a [`SelectorRequest`] to its standard input as a single line of JSON and closes
+//! it. The request includes the protocol versions nextest supports and the test list, in the same
+//! format as `cargo nextest list --message-format json`.
+//!
+//! The selector replies with a [`SelectorResponse`] on a single line of its standard output, then
+//! exits. The response lists the tests to run, in the order they should be started. Tests that
+//! aren't listed are skipped, and reported as such.
+//!
+//! If the selector doesn't exit within its timeout (by default, 60 seconds), exits with a non-zero
+//! status, or doesn't reply with a valid response, nextest prints a warning and falls back to
+//! running all the tests in the usual order. Selection is an optimization, so a broken selector
+//! shouldn't block test runs.
+
+use crate::{
+    config::NextestConfig,
+    errors::SelectorError,
+    test_list::{TestInstance, TestList},
+};
+use camino::Utf8Path;
+use nextest_metadata::{SelectorRequest, SelectorResponse};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// The configuration for an external selector.
+///
+/// Obtained through [`NextestConfig::selectors`](crate::config::NextestConfig::selectors).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SelectorConfig {
+    command: Vec<String>,
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    timeout: Duration,
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl SelectorConfig {
+    /// Returns the command the selector is run with.
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    /// Returns how long the selector has to reply before nextest falls back to running all tests.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// An external selector defined in the config.
+#[derive(Clone, Debug)]
+pub struct Selector<'cfg> {
+    name: &'cfg str,
+    config: &'cfg SelectorConfig,
+}
+
+impl<'cfg> Selector<'cfg> {
+    /// Looks up the selector with the given name.
+    pub fn new(config: &'cfg NextestConfig, name: &str) -> Result<Self, SelectorError> {
+        config
+            .selectors()
+            .get_key_value(name)
+            .map(|(name, config)| Self { name, config })
+            .ok_or_else(|| SelectorError::NotFound {
+                name: name.to_owned(),
+            })
+    }
+
+    /// Returns the name of this selector.
+    pub fn name(&self) -> &'cfg str {
+        self.name
+    }
+
+    /// Runs the selector in `workspace_root`, and returns the tests it chose out of `test_list`.
+    pub fn select(
+        &self,
+        workspace_root: &Utf8Path,
+        test_list: &TestList<'_>,
+        nextest_version: &str,
+    ) -> Result<TestSelection, SelectorError> {
+        let name = self.name.to_owned();
+        let (program, args) = self
+            .config
+            .command
+            .split_first()
+            .ok_or_else(|| SelectorError::EmptyCommand { name: name.clone() })?;
+        let request = serde_json::to_vec(&SelectorRequest::new(
+            nextest_version,
+            test_list.to_summary(),
+        ))
+        .expect("serializing a selector request can't fail");
+
+        let mut child = Command::new(program)
+            .args(args)
+            .current_dir(workspace_root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| SelectorError::Spawn {
+                name: name.clone(),
+                error,
+            })?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+
+        // Write the request and read the response on separate threads, so that a large test list
+        // can't deadlock against the selector's output, and so that the selector can time out.
+        let (sender, receiver) = mpsc::channel();
+        let writer = thread::spawn(move || {
+            // Errors are ignored: the selector may exit without reading the whole request, and
+            // its response is what matters.
+            let _ = stdin
+                .write_all(&request)
+                .and_then(|()| stdin.write_all(b"\n"));
+        });
+        thread::spawn(move || {
+            let mut output = String::new();
+            let result = stdout.read_to_string(&mut output).map(|_| output);
+            let _ = sender.send(result);
+        });
+
+        let output = match receiver.recv_timeout(self.config.timeout) {
+            Ok(result) => result,
+            Err(_) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(SelectorError::TimedOut {
+                    name,
+                    timeout: self.config.timeout,
+                });
+            }
+        };
+        let status = child.wait();
+        let _ = writer.join();
+        let output = output.map_err(|error| SelectorError::Io {
+            name: name.clone(),
+            error,
+        })?;
+        let status = status.map_err(|error| SelectorError::Io {
+            name: name.clone(),
+            error,
+        })?;
+        if !status.success() {
+            return Err(SelectorError::Exited { name, status });
+        }
+
+        let line = output
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default();
+        let response: SelectorResponse =
+            serde_json::from_str(line).map_err(|error| SelectorError::InvalidResponse {
+                name: name.clone(),
+                reason: format!("{:?} isn't a valid response: {}", line, error),
+            })?;
+        if response.protocol_version != SelectorRequest::PROTOCOL_VERSION {
+            return Err(SelectorError::InvalidResponse {
+                name,
+                reason: format!(
+                    "chose protocol version {}, but nextest only supports {}",
+                    response.protocol_version,
+                    SelectorRequest::PROTOCOL_VERSION
+                ),
+            });
+        }
+        Ok(TestSelection::new(response))
+    }
+}
+
+/// The tests chosen by an external selector, in the order they should be started.
+///
+/// Unselected tests are skipped through [`TestList::apply_selection`], and selected tests are run
+/// in order through
+/// [`TestRunnerBuilder::set_selection`](crate::runner::TestRunnerBuilder::set_selection).
+#[derive(Clone, Debug, Default)]
+pub struct TestSelection {
+    // Binary ID -> test name -> position in the order chosen by the selector.
+    positions: HashMap<String, HashMap<String, usize>>,
+    len: usize,
+}
+
+impl TestSelection {
+    /// Creates a new selection from a selector's response.
+    ///
+    /// If a test is listed more than once, its first position is used.
+    pub fn new(response: SelectorResponse) -> Self {
+        let mut positions: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        let mut len = 0;
+        for test in response.tests {
+            positions
+                .entry(test.binary_id)
+                .or_default()
+                .entry(test.name)
+                .or_insert_with(|| {
+                    len += 1;
+                    len - 1
+                });
+        }
+        Self { positions, len }
+    }
+
+    /// Returns the number of tests that were selected.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no tests were selected.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the position of the given test in the order chosen by the selector, or `None` if it
+    /// wasn't selected.
+    pub fn position(&self, binary_id: &str, name: &str) -> Option<usize> {
+        self.positions.get(binary_id)?.get(name).copied()
+    }
+
+    pub(crate) fn position_of(&self, test_instance: &TestInstance<'_>) -> Option<usize> {
+        self.position(&test_instance.bin_info.binary_id, test_instance.name)
+    }
+}
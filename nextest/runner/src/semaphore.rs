@@ -0,0 +1,161 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! A counting semaphore where each acquirer can take several permits at once.
+//!
+//! This is used to limit the number of tests running at the same time, where some tests take up
+//! more than one slot (see [`ThreadsRequired`](crate::config::ThreadsRequired)).
+//!
+//! Permits are granted in the order they were requested. Otherwise, a test requiring many permits
+//! could wait indefinitely while tests requiring fewer keep taking them as soon as they're released.
+//!
+//! Each acquirer is also assigned a slot: the smallest index, starting from 0, that no other holder
+//! has. Since each holder takes at least one permit, slots are always less than the total number of
+//! permits.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Condvar, Mutex},
+};
+
+#[derive(Debug)]
+pub(crate) struct WeightedSemaphore {
+    state: Mutex<SemaphoreState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct SemaphoreState {
+    available: usize,
+    // Slots that aren't held by anyone.
+    free_slots: BTreeSet<usize>,
+    // The ticket handed out to the next caller of acquire.
+    next_ticket: u64,
+    // The ticket of the caller currently allowed to take permits.
+    serving: u64,
+}
+
+impl WeightedSemaphore {
+    pub(crate) fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(SemaphoreState {
+                available: permits,
+                free_slots: (0..permits).collect(),
+                next_ticket: 0,
+                serving: 0,
+            }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Blocks until `permits` permits are available, and takes them. The permits are released
+    /// when the returned guard is dropped.
+    ///
+    /// `permits` must be at least 1, and must not exceed the total number of permits, or this will
+    /// block forever.
+    pub(crate) fn acquire(&self, permits: usize) -> SemaphoreGuard<'_> {
+        debug_assert!(permits > 0, "at least one permit must be acquired");
+        let mut state = self.state.lock().expect("semaphore lock poisoned");
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        while state.serving != ticket || state.available < permits {
+            state = self.condvar.wait(state).expect("semaphore lock poisoned");
+        }
+        state.available -= permits;
+        let slot = state
+            .free_slots
+            .pop_first()
+            .expect("each holder takes at least one permit, so a slot is free");
+        state.serving += 1;
+        // The next caller in line may be able to take permits now.
+        self.condvar.notify_all();
+        SemaphoreGuard {
+            semaphore: self,
+            permits,
+            slot,
+        }
+    }
+
+    fn release(&self, permits: usize, slot: usize) {
+        let mut state = self.state.lock().expect("semaphore lock poisoned");
+        state.available += permits;
+        state.free_slots.insert(slot);
+        self.condvar.notify_all();
+    }
+}
+
+/// Permits taken from a [`WeightedSemaphore`], released on drop.
+#[derive(Debug)]
+pub(crate) struct SemaphoreGuard<'a> {
+    semaphore: &'a WeightedSemaphore,
+    permits: usize,
+    slot: usize,
+}
+
+impl<'a> SemaphoreGuard<'a> {
+    /// Returns the slot assigned to this holder.
+    pub(crate) fn slot(&self) -> usize {
+        self.slot
+    }
+}
+
+impl<'a> Drop for SemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.semaphore.release(self.permits, self.slot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn weighted_permits() {
+        let semaphore = WeightedSemaphore::new(4);
+        let in_use = AtomicUsize::new(0);
+        let max_in_use = AtomicUsize::new(0);
+        let slots_in_use = Mutex::new(BTreeSet::new());
+
+        thread::scope(|scope| {
+            for permits in [1, 3, 2, 4, 1, 1, 2] {
+                let semaphore = &semaphore;
+                let in_use = &in_use;
+                let max_in_use = &max_in_use;
+                let slots_in_use = &slots_in_use;
+                scope.spawn(move || {
+                    let guard = semaphore.acquire(permits);
+                    let slot = guard.slot();
+                    assert!(slot < 4, "slot {} is less than the number of permits", slot);
+                    assert!(
+                        slots_in_use.lock().unwrap().insert(slot),
+                        "slot {} isn't held by anyone else",
+                        slot
+                    );
+                    let now = in_use.fetch_add(permits, Ordering::SeqCst) + permits;
+                    max_in_use.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    in_use.fetch_sub(permits, Ordering::SeqCst);
+                    slots_in_use.lock().unwrap().remove(&slot);
+                });
+            }
+        });
+
+        let max_in_use = max_in_use.load(Ordering::SeqCst);
+        assert!(
+            max_in_use <= 4,
+            "at most 4 permits in use at a time (actual: {})",
+            max_in_use
+        );
+        assert_eq!(in_use.load(Ordering::SeqCst), 0, "all permits released");
+        assert_eq!(
+            semaphore.acquire(1).slot(),
+            0,
+            "the smallest free slot is assigned"
+        );
+    }
+}
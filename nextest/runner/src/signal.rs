@@ -2,8 +2,36 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 //! Support for handling signals in nextest.
+//!
+//! In addition to Ctrl-C, nextest responds to SIGQUIT (Ctrl-\ in most terminals) by printing out a
+//! snapshot of the state of the test run, without terminating it. In interactive mode, pressing `.`
+//! does the same.
 
-use crossbeam_channel::Receiver;
+use crossbeam_channel::{Receiver, Sender};
+use once_cell::sync::Lazy;
+use std::{io, sync::Mutex};
+
+// Signal handlers can only be registered once per process, so they forward signals to whichever
+// `SignalHandler` was created most recently.
+static FORWARDER: Lazy<Mutex<Forwarder>> = Lazy::new(|| {
+    Mutex::new(Forwarder {
+        registered: false,
+        sender: None,
+    })
+});
+
+#[derive(Debug)]
+struct Forwarder {
+    registered: bool,
+    sender: Option<Sender<SignalEvent>>,
+}
+
+fn forward(event: SignalEvent) {
+    let forwarder = FORWARDER.lock().expect("forwarder lock isn't poisoned");
+    if let Some(sender) = &forwarder.sender {
+        let _ = sender.send(event);
+    }
+}
 
 /// A receiver that generates signals if ctrl-c is pressed.
 ///
@@ -12,31 +40,185 @@ use crossbeam_channel::Receiver;
 #[derive(Debug)]
 pub struct SignalHandler {
     pub(crate) receiver: Receiver<SignalEvent>,
+    sender: Option<Sender<SignalEvent>>,
+    #[cfg(unix)]
+    terminal_guard: Option<imp::TerminalGuard>,
 }
 
 impl SignalHandler {
-    /// Creates a new `SignalReceiver` that handles Ctrl-C errors.
+    /// Creates a new `SignalReceiver` that handles Ctrl-C errors, and SIGQUIT on Unix platforms.
     ///
-    /// Errors if a signal handler has already been registered in this process. Only one signal
-    /// handler can be registered for a process at any given time.
+    /// This can be called more than once, for example for runs started one after the other: signals
+    /// are sent to the most recently created handler.
+    ///
+    /// Errors if a Ctrl-C handler has been registered in this process by something other than
+    /// nextest.
     pub fn new() -> Result<Self, ctrlc::Error> {
+        let mut forwarder = FORWARDER.lock().expect("forwarder lock isn't poisoned");
+        if !forwarder.registered {
+            ctrlc::set_handler(|| forward(SignalEvent::Interrupted))?;
+            #[cfg(unix)]
+            imp::forward_sigquit().map_err(ctrlc::Error::System)?;
+            forwarder.registered = true;
+        }
         let (sender, receiver) = crossbeam_channel::unbounded();
-        ctrlc::set_handler(move || {
-            let _ = sender.send(SignalEvent::Interrupted);
-        })?;
+        forwarder.sender = Some(sender.clone());
 
-        Ok(Self { receiver })
+        Ok(Self {
+            receiver,
+            sender: Some(sender),
+            #[cfg(unix)]
+            terminal_guard: None,
+        })
     }
 
     /// Creates a new `SignalReceiver` that does nothing.
     pub fn noop() -> Self {
         let (_sender, receiver) = crossbeam_channel::bounded(1);
-        Self { receiver }
+        Self {
+            receiver,
+            sender: None,
+            #[cfg(unix)]
+            terminal_guard: None,
+        }
+    }
+
+    /// Listens for the `.` keystroke on standard input, treating it the same as SIGQUIT.
+    ///
+    /// This only has an effect if standard input is a terminal, on Unix platforms. The terminal is
+    /// switched out of line-buffered mode so that the keystroke is seen immediately, and restored
+    /// once this handler is dropped. Tests must not read from standard input while this is active.
+    pub fn listen_for_keystrokes(&mut self) -> io::Result<()> {
+        #[cfg(unix)]
+        if let Some(sender) = &self.sender {
+            self.terminal_guard = imp::listen_for_keystrokes(sender.clone())?;
+        }
+        Ok(())
     }
 }
 
-// Just a single-valued enum for now, might have more information in the future.
+/// An event produced by a [`SignalHandler`].
 #[derive(Debug)]
 pub(crate) enum SignalEvent {
+    /// The run was interrupted, and should be canceled.
     Interrupted,
+
+    /// A snapshot of the state of the run was requested.
+    Info,
+}
+
+#[cfg(unix)]
+pub(crate) use imp::TerminalGuard;
+
+#[cfg(unix)]
+mod imp {
+    use super::{forward, SignalEvent};
+    use crossbeam_channel::Sender;
+    use signal_hook::{consts::SIGQUIT, iterator::Signals};
+    use std::{
+        io::{self, Read},
+        mem::MaybeUninit,
+        thread,
+    };
+
+    pub(super) fn forward_sigquit() -> io::Result<()> {
+        let mut signals = Signals::new([SIGQUIT])?;
+        thread::Builder::new()
+            .name("nextest-sigquit".to_owned())
+            .spawn(move || {
+                for _ in signals.forever() {
+                    forward(SignalEvent::Info);
+                }
+            })?;
+        Ok(())
+    }
+
+    pub(super) fn listen_for_keystrokes(
+        sender: Sender<SignalEvent>,
+    ) -> io::Result<Option<TerminalGuard>> {
+        // SAFETY: isatty is always safe to call.
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return Ok(None);
+        }
+        let guard = TerminalGuard::new()?;
+
+        thread::Builder::new()
+            .name("nextest-input".to_owned())
+            .spawn(move || {
+                let stdin = io::stdin();
+                for byte in stdin.lock().bytes() {
+                    match byte {
+                        Ok(b'.') => {
+                            if sender.send(SignalEvent::Info).is_err() {
+                                break;
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(_) => break,
+                    }
+                }
+            })?;
+        Ok(Some(guard))
+    }
+
+    /// Puts the terminal into non-canonical mode without echo, restoring it on drop.
+    #[derive(Debug)]
+    pub(crate) struct TerminalGuard {
+        original: libc::termios,
+    }
+
+    impl TerminalGuard {
+        pub(crate) fn new() -> io::Result<Self> {
+            let mut original = MaybeUninit::uninit();
+            // SAFETY: tcgetattr initializes the termios struct if it returns 0.
+            let original = unsafe {
+                if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                original.assume_init()
+            };
+
+            let mut raw = original;
+            // Keep ISIG so that Ctrl-C and Ctrl-\ continue to generate signals.
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            // SAFETY: raw is a valid termios struct.
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { original })
+        }
+
+        /// Sets whether Ctrl-C and Ctrl-\ generate signals, rather than being read as keystrokes.
+        pub(crate) fn set_signals(&self, enabled: bool) -> io::Result<()> {
+            let mut current = MaybeUninit::uninit();
+            // SAFETY: tcgetattr initializes the termios struct if it returns 0.
+            let mut current = unsafe {
+                if libc::tcgetattr(libc::STDIN_FILENO, current.as_mut_ptr()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                current.assume_init()
+            };
+            if enabled {
+                current.c_lflag |= libc::ISIG;
+            } else {
+                current.c_lflag &= !libc::ISIG;
+            }
+            // SAFETY: current is a valid termios struct.
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &current) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for TerminalGuard {
+        fn drop(&mut self) {
+            // SAFETY: original is the valid termios struct obtained in new.
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+            }
+        }
+    }
 }
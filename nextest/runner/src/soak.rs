@@ -0,0 +1,338 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Soak testing: running the same tests over and over, watching for resources that keep growing.
+//!
+//! With `cargo nextest run --soak <duration>`, the selected tests are run in a loop until the given
+//! amount of time has passed. Each iteration is a separate run, with its own run store. The time
+//! taken by each iteration and by each test in it is recorded, along with the peak resident set
+//! size (RSS) of each test, measured on Linux.
+//!
+//! A test whose peak RSS grew over at least [`MIN_LEAK_SAMPLES`] iterations without ever shrinking
+//! is flagged as a probable leak. Tests run in a new process every time, so such growth usually
+//! comes from state kept outside the test, such as a database or files that a stateful integration
+//! test keeps adding to.
+//!
+//! The results are written to `soak.json` in the profile's store directory after each iteration,
+//! and are summarized at the end. Soaking stops early if an iteration is canceled, for example with
+//! Ctrl-C or by `fail-fast`.
+
+use crate::{
+    disk_usage::format_bytes, errors::RunStoreError, reporter::TestEvent, run_store::write_atomic,
+    runner::RunStats, stopwatch::ClockInstant,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::{SoakIterationSummary, SoakSummary, SoakTestSummary};
+use owo_colors::OwoColorize;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The minimum number of iterations a test's peak RSS must have been measured in for it to be
+/// flagged as a probable leak.
+pub const MIN_LEAK_SAMPLES: usize = 3;
+
+/// Records the results of each iteration of a soak test.
+#[derive(Debug)]
+pub struct SoakTracker {
+    start: ClockInstant,
+    duration: Duration,
+    iterations: Vec<SoakIterationSummary>,
+    current_run_id: Option<Uuid>,
+    // (binary ID, test name, variant) -> results in each iteration.
+    tests: BTreeMap<(String, String, Option<String>), SoakTestRecord>,
+    colorize: bool,
+}
+
+#[derive(Debug, Default)]
+struct SoakTestRecord {
+    durations: Vec<Option<f64>>,
+    peak_rss: Vec<Option<u64>>,
+}
+
+impl SoakTracker {
+    /// Creates a tracker for a soak test that lasts for `duration`, starting now.
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            start: ClockInstant::now(),
+            duration,
+            iterations: vec![],
+            current_run_id: None,
+            tests: BTreeMap::new(),
+            colorize: false,
+        }
+    }
+
+    /// Returns the path the soak summary is written to, within a profile's store directory.
+    pub fn summary_path(profile_store_dir: &Utf8Path) -> Utf8PathBuf {
+        profile_store_dir.join("soak.json")
+    }
+
+    /// Colorizes the summary written by [`write_report`](Self::write_report).
+    pub fn colorize(&mut self) {
+        self.colorize = true;
+    }
+
+    /// Returns true if the soak test should go on to another iteration after the one that
+    /// produced `run_stats`.
+    pub fn should_continue(&self, run_stats: &RunStats) -> bool {
+        let canceled =
+            run_stats.initial_run_count > run_stats.final_run_count + run_stats.overflowed;
+        !canceled && self.start.elapsed() < self.duration
+    }
+
+    /// Returns true if every iteration so far was successful.
+    pub fn is_success(&self) -> bool {
+        self.iterations.iter().all(|iteration| iteration.success)
+    }
+
+    /// Starts recording a new iteration, run with the given ID.
+    pub fn start_iteration(&mut self, run_id: Uuid) {
+        self.current_run_id = Some(run_id);
+    }
+
+    /// Records an event from the current iteration.
+    pub fn record_event(&mut self, event: &TestEvent<'_>) {
+        let iteration = self.iterations.len();
+        match event {
+            TestEvent::TestFinished {
+                test_instance,
+                run_statuses,
+            } => {
+                let last_status = run_statuses.last_status();
+                let record = self
+                    .tests
+                    .entry((
+                        test_instance.bin_info.binary_id.to_string(),
+                        test_instance.name.to_owned(),
+                        test_instance.variant.map(|variant| variant.to_string()),
+                    ))
+                    .or_default();
+                record.durations.resize(iteration, None);
+                record.peak_rss.resize(iteration, None);
+                record
+                    .durations
+                    .push(Some(last_status.time_taken.as_secs_f64()));
+                record.peak_rss.push(last_status.peak_rss);
+            }
+            TestEvent::RunFinished {
+                elapsed, run_stats, ..
+            } => {
+                if let Some(run_id) = self.current_run_id.take() {
+                    self.iterations.push(SoakIterationSummary {
+                        run_id,
+                        time_taken: elapsed.as_secs_f64(),
+                        success: run_stats.is_success(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the summary of the iterations recorded so far.
+    pub fn to_summary(&self) -> SoakSummary {
+        let iterations = self.iterations.len();
+        SoakSummary {
+            iterations: self.iterations.clone(),
+            tests: self
+                .tests
+                .iter()
+                .map(|((binary_id, name, variant), record)| {
+                    let mut durations = record.durations.clone();
+                    let mut peak_rss = record.peak_rss.clone();
+                    durations.resize(iterations, None);
+                    peak_rss.resize(iterations, None);
+                    SoakTestSummary {
+                        binary_id: binary_id.clone(),
+                        name: name.clone(),
+                        variant: variant.clone(),
+                        probable_leak: is_probable_leak(&peak_rss),
+                        durations,
+                        peak_rss,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the summary of the iterations recorded so far to `path`, replacing it atomically.
+    pub fn write(&self, path: &Utf8Path) -> Result<(), RunStoreError> {
+        let json = serde_json::to_vec_pretty(&self.to_summary()).expect("soak summary serializes");
+        write_atomic(path, &json)
+    }
+
+    /// Writes out a human-readable summary of the soak test, including the tests flagged as probable
+    /// leaks.
+    pub fn write_report(&self, mut writer: impl Write) -> io::Result<()> {
+        let summary = self.to_summary();
+        let failed = summary
+            .iterations
+            .iter()
+            .filter(|iteration| !iteration.success)
+            .count();
+        let durations: Vec<_> = summary
+            .iterations
+            .iter()
+            .map(|iteration| iteration.time_taken)
+            .collect();
+        let (min, max) = durations
+            .iter()
+            .fold((f64::INFINITY, 0.0f64), |(min, max), &duration| {
+                (min.min(duration), max.max(duration))
+            });
+        let mean = durations.iter().sum::<f64>() / durations.len().max(1) as f64;
+
+        let label = "Soak";
+        if self.colorize {
+            write!(writer, "{:>12} ", label.bold().green())?;
+        } else {
+            write!(writer, "{:>12} ", label)?;
+        }
+        write!(
+            writer,
+            "[{:>8.3}s] {} {}, {} failed",
+            self.start.elapsed().as_secs_f64(),
+            durations.len(),
+            if durations.len() == 1 {
+                "iteration"
+            } else {
+                "iterations"
+            },
+            failed,
+        )?;
+        if !durations.is_empty() {
+            write!(
+                writer,
+                "; run time min {:.3}s, mean {:.3}s, max {:.3}s",
+                min, mean, max
+            )?;
+        }
+        writeln!(writer)?;
+
+        for test in summary.tests.iter().filter(|test| test.probable_leak) {
+            let samples: Vec<_> = test.peak_rss.iter().flatten().collect();
+            let label = "LEAK?";
+            if self.colorize {
+                write!(writer, "{:>12} ", label.bold().yellow())?;
+            } else {
+                write!(writer, "{:>12} ", label)?;
+            }
+            write!(writer, "{} {}", test.binary_id, test.name)?;
+            if let Some(variant) = &test.variant {
+                write!(writer, " [{}]", variant)?;
+            }
+            writeln!(
+                writer,
+                ": peak RSS grew from {} to {} over {} iterations",
+                format_bytes(*samples[0]),
+                format_bytes(*samples[samples.len() - 1]),
+                samples.len(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the measured peak RSS values grew over at least [`MIN_LEAK_SAMPLES`] iterations
+/// without ever shrinking.
+fn is_probable_leak(peak_rss: &[Option<u64>]) -> bool {
+    let samples: Vec<u64> = peak_rss.iter().flatten().copied().collect();
+    samples.len() >= MIN_LEAK_SAMPLES
+        && samples.windows(2).all(|pair| pair[0] <= pair[1])
+        && samples[0] < samples[samples.len() - 1]
+}
+
+/// Returns the peak resident set size of the running process with the given ID, in bytes.
+///
+/// This is read from `/proc`, so it's only available on Linux, and not once the process has
+/// exited.
+#[cfg(target_os = "linux")]
+pub(crate) fn peak_rss(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soak_leak_detection() {
+        assert!(is_probable_leak(&[Some(10), Some(10), None, Some(12)]));
+        assert!(
+            !is_probable_leak(&[Some(10), Some(12)]),
+            "too few samples to tell"
+        );
+        assert!(!is_probable_leak(&[Some(10), Some(10), Some(10)]), "flat");
+        assert!(!is_probable_leak(&[Some(10), Some(12), Some(11), Some(13)]));
+
+        let mut tracker = SoakTracker::new(Duration::from_secs(3600));
+        let key = ("my-crate".to_owned(), "grows".to_owned(), None);
+        tracker.tests.insert(
+            key.clone(),
+            SoakTestRecord {
+                durations: vec![Some(1.0), None, Some(1.5)],
+                peak_rss: vec![Some(1 << 20), None, Some(3 << 20)],
+            },
+        );
+        for run_id in 0..4 {
+            tracker.iterations.push(SoakIterationSummary {
+                run_id: Uuid::from_u128(run_id),
+                time_taken: 1.0 + run_id as f64,
+                success: run_id != 2,
+            });
+        }
+        tracker
+            .tests
+            .get_mut(&key)
+            .unwrap()
+            .peak_rss
+            .push(Some(4 << 20));
+        tracker
+            .tests
+            .get_mut(&key)
+            .unwrap()
+            .durations
+            .push(Some(2.0));
+
+        let summary = tracker.to_summary();
+        assert_eq!(summary.iterations.len(), 4);
+        assert!(summary.tests[0].probable_leak);
+        assert_eq!(summary.tests[0].durations.len(), 4);
+        assert!(!tracker.is_success());
+
+        let mut report = vec![];
+        tracker.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(
+            report.contains("4 iterations, 1 failed; run time min 1.000s, mean 2.500s, max 4.000s"),
+            "{}",
+            report
+        );
+        assert!(
+            report.contains(
+                "LEAK? my-crate grows: peak RSS grew from 1.0 MiB to 4.0 MiB over 3 iterations"
+            ),
+            "{}",
+            report
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn peak_rss_of_self() {
+        assert!(peak_rss(std::process::id()).is_some_and(|rss| rss > 0));
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Measurement of the fixed cost of starting test binaries.
+//!
+//! Nextest runs each test in its own process, so the time a test binary takes to start up and exit
+//! is paid once for every test in it. A binary that does a lot of work before running any tests,
+//! for example in static initializers, or that is slow to load, can make a run much slower than its
+//! tests are.
+//!
+//! [`StartupOverheads::measure`] runs each binary a few times with a filter that matches none of
+//! its tests, and records the fastest time from spawning it to its exit. Binaries are ordered by
+//! the total overhead across their tests, and the ones above a threshold are reported as slow to
+//! start, along with ways to speed them up. This is printed out by `cargo nextest measure-startup`.
+//!
+//! Binaries with a custom harness that ignores the filter run all their tests while being measured.
+//! Binaries that are run as a whole on a probe-attached device aren't measured.
+
+use crate::{
+    errors::StartupError,
+    stopwatch::ClockInstant,
+    test_list::{make_binary_command, OutputFormat, TestList},
+};
+use nextest_metadata::{BinaryStartupSummary, StartupSummary};
+use std::{io::Write, time::Duration};
+
+/// The test name passed to binaries to measure their startup overhead, which no test is expected
+/// to have.
+pub const STARTUP_PROBE_FILTER: &str = "__nextest_startup_probe__";
+
+/// How long each test binary in a test list takes to start up and exit.
+#[derive(Clone, Debug)]
+pub struct StartupOverheads {
+    runs: usize,
+    threshold: Duration,
+    binaries: Vec<BinaryStartup>,
+}
+
+/// How long a single test binary takes to start up and exit, within [`StartupOverheads`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BinaryStartup {
+    /// The unique identifier of the binary.
+    pub binary_id: String,
+
+    /// The fastest time from spawning the binary to its exit.
+    pub overhead: Duration,
+
+    /// The number of tests in the binary that match the filter.
+    pub test_count: usize,
+}
+
+impl BinaryStartup {
+    /// Returns the overhead paid across all the tests in the binary.
+    pub fn total_overhead(&self) -> Duration {
+        self.overhead * self.test_count as u32
+    }
+}
+
+impl StartupOverheads {
+    /// Measures the binaries in the test list with tests that match the filter, running each of
+    /// them `runs` times. Binaries that take longer than `threshold` to start are reported as slow.
+    pub fn measure(
+        test_list: &TestList<'_>,
+        runs: usize,
+        threshold: Duration,
+    ) -> Result<Self, StartupError> {
+        let mut binaries = vec![];
+        for (binary_path, suite) in test_list.iter() {
+            let test_count = suite
+                .testcases
+                .values()
+                .filter(|case| case.filter_match.is_match())
+                .count();
+            // Suites of synthetic tests don't have a binary.
+            if test_count == 0 || binary_path.as_str().is_empty() {
+                continue;
+            }
+            let runner = test_list.target_runner().for_platform(suite.build_platform);
+            if runner.is_some_and(|runner| runner.probe().is_some()) {
+                continue;
+            }
+
+            let mut overhead = Duration::MAX;
+            for _ in 0..runs.max(1) {
+                let cmd = make_binary_command(
+                    binary_path,
+                    suite.build_platform,
+                    vec!["--exact", STARTUP_PROBE_FILTER],
+                    test_list.build_meta(),
+                    runner,
+                )
+                .dir(&suite.cwd)
+                .stdout_null()
+                .stderr_null()
+                .unchecked();
+                let start = ClockInstant::now();
+                cmd.run().map_err(|error| StartupError::Exec {
+                    binary_id: suite.binary_id.clone(),
+                    error,
+                })?;
+                overhead = overhead.min(start.elapsed());
+            }
+            binaries.push(BinaryStartup {
+                binary_id: suite.binary_id.clone(),
+                overhead,
+                test_count,
+            });
+        }
+
+        Ok(Self::new(runs.max(1), threshold, binaries))
+    }
+
+    /// Creates a new set of measurements, ordering the binaries by their total overhead.
+    pub fn new(runs: usize, threshold: Duration, mut binaries: Vec<BinaryStartup>) -> Self {
+        binaries.sort_by(|a, b| {
+            b.total_overhead()
+                .cmp(&a.total_overhead())
+                .then_with(|| a.binary_id.cmp(&b.binary_id))
+        });
+        Self {
+            runs,
+            threshold,
+            binaries,
+        }
+    }
+
+    /// Returns the binaries that were measured, ordered by their total overhead, largest first.
+    pub fn binaries(&self) -> &[BinaryStartup] {
+        &self.binaries
+    }
+
+    /// Returns the binaries that take longer than the threshold to start.
+    pub fn slow_binaries(&self) -> impl Iterator<Item = &BinaryStartup> + '_ {
+        self.binaries
+            .iter()
+            .filter(move |binary| binary.overhead > self.threshold)
+    }
+
+    /// Returns a serializable summary of the measurements.
+    pub fn to_summary(&self) -> StartupSummary {
+        StartupSummary {
+            runs: self.runs,
+            threshold: self.threshold.as_secs_f64(),
+            binaries: self
+                .binaries
+                .iter()
+                .map(|binary| BinaryStartupSummary {
+                    binary_id: binary.binary_id.clone(),
+                    overhead: binary.overhead.as_secs_f64(),
+                    test_count: binary.test_count,
+                    slow: binary.overhead > self.threshold,
+                })
+                .collect(),
+        }
+    }
+
+    /// Outputs the measurements in the given format.
+    pub fn write(
+        &self,
+        output_format: OutputFormat,
+        writer: impl Write,
+    ) -> Result<(), StartupError> {
+        match output_format {
+            OutputFormat::Plain => self.write_plain(writer).map_err(StartupError::Write),
+            OutputFormat::Serializable(format) => format
+                .to_writer(&self.to_summary(), writer)
+                .map_err(StartupError::Json),
+        }
+    }
+
+    fn write_plain(&self, mut writer: impl Write) -> std::io::Result<()> {
+        for binary in &self.binaries {
+            writeln!(
+                writer,
+                "{:>9.3}s {:>6} tests {:>9.3}s total  {}{}",
+                binary.overhead.as_secs_f64(),
+                binary.test_count,
+                binary.total_overhead().as_secs_f64(),
+                binary.binary_id,
+                if binary.overhead > self.threshold {
+                    "  (slow to start)"
+                } else {
+                    ""
+                },
+            )?;
+        }
+
+        let slow_count = self.slow_binaries().count();
+        if slow_count > 0 {
+            writeln!(
+                writer,
+                "\n{} {} took longer than {:.3}s to start (fastest of {} {}). Each test is run in \
+                 its own process, so this is paid once for every test. Common causes are:",
+                slow_count,
+                if slow_count == 1 {
+                    "binary"
+                } else {
+                    "binaries"
+                },
+                self.threshold.as_secs_f64(),
+                self.runs,
+                if self.runs == 1 { "run" } else { "runs" },
+            )?;
+            writeln!(
+                writer,
+                "  * expensive setup in static initializers or at the start of main, which can \
+                 often be done lazily by the tests that need it"
+            )?;
+            writeln!(
+                writer,
+                "  * loading many or large dynamic libraries, which building with static linking \
+                 or fewer dependencies avoids"
+            )?;
+            writeln!(
+                writer,
+                "  * slow target runners or emulators, which start up again for every test"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_order_and_summary() {
+        let millis = Duration::from_millis;
+        let overheads = StartupOverheads::new(
+            3,
+            millis(250),
+            vec![
+                BinaryStartup {
+                    binary_id: "fast-many".to_owned(),
+                    overhead: millis(10),
+                    test_count: 100,
+                },
+                BinaryStartup {
+                    binary_id: "slow-few".to_owned(),
+                    overhead: millis(400),
+                    test_count: 2,
+                },
+            ],
+        );
+        let binary_ids: Vec<_> = overheads
+            .binaries()
+            .iter()
+            .map(|binary| binary.binary_id.as_str())
+            .collect();
+        assert_eq!(
+            binary_ids,
+            ["fast-many", "slow-few"],
+            "ordered by total overhead: 1s vs 0.8s"
+        );
+        let slow: Vec<_> = overheads
+            .slow_binaries()
+            .map(|binary| binary.binary_id.as_str())
+            .collect();
+        assert_eq!(slow, ["slow-few"]);
+
+        let summary = overheads.to_summary();
+        assert_eq!(summary.runs, 3);
+        assert_eq!(summary.threshold, 0.25);
+        assert!(!summary.binaries[0].slow);
+        assert!(summary.binaries[1].slow);
+
+        let mut plain = vec![];
+        overheads
+            .write(OutputFormat::Plain, &mut plain)
+            .expect("writing to a vec succeeds");
+        let plain = String::from_utf8(plain).unwrap();
+        assert!(plain.contains("slow-few  (slow to start)"), "{}", plain);
+        assert!(
+            plain.contains("1 binary took longer than 0.250s to start (fastest of 3 runs)"),
+            "{}",
+            plain
+        );
+    }
+}
@@ -4,43 +4,160 @@
 //! Stopwatch for tracking how long it takes to run tests.
 //!
 //! Tests need to track a start time and a duration. For that we use a combination of a `SystemTime`
-//! (realtime clock) and an `Instant` (monotonic clock). Once the stopwatch transitions to the "end"
-//! state, we can report the elapsed time using the monotonic clock.
+//! (realtime clock) and a [`ClockInstant`] (monotonic clock). Once the stopwatch transitions to the
+//! "end" state, we can report the elapsed time using the monotonic clock.
+//!
+//! All durations and timeouts in nextest, such as slow and leak timeouts, are measured with
+//! [`ClockInstant`]. It's never affected by changes to the system clock, and on Linux and macOS it
+//! stops while the host is suspended, so that a laptop going to sleep in the middle of a run neither
+//! makes tests seem to take hours nor makes them time out as soon as it wakes up.
+
+use std::{
+    ops::Add,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// A point in time on nextest's monotonic clock.
+///
+/// This wraps the standard library's [`Instant`], which is `CLOCK_MONOTONIC` on Linux and Android
+/// and `CLOCK_UPTIME_RAW` on macOS and iOS: neither counts time spent suspended. Elsewhere, whether
+/// time spent suspended counts depends on the platform.
+///
+/// All times that durations or timeouts are measured from should be taken with
+/// [`ClockInstant::now`], so that they're measured the same way everywhere.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ClockInstant {
+    instant: Instant,
+}
+
+impl ClockInstant {
+    /// Returns the current time.
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+        }
+    }
+
+    /// Returns the time that has passed since this instant, or zero if it's in the future.
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        Self::now().saturating_duration_since(*self)
+    }
 
-use std::time::{Duration, Instant, SystemTime};
+    /// Returns the time from `earlier` to this instant, or zero if `earlier` is later.
+    #[inline]
+    pub fn saturating_duration_since(&self, earlier: ClockInstant) -> Duration {
+        self.instant.saturating_duration_since(earlier.instant)
+    }
+}
+
+impl Add<Duration> for ClockInstant {
+    type Output = ClockInstant;
+
+    fn add(self, duration: Duration) -> ClockInstant {
+        ClockInstant {
+            instant: self.instant + duration,
+        }
+    }
+}
 
 /// The start state of a stopwatch.
 #[derive(Clone, Debug)]
-pub(crate) struct StopwatchStart {
+pub struct StopwatchStart {
     start_time: SystemTime,
-    instant: Instant,
+    instant: ClockInstant,
 }
 
 impl StopwatchStart {
-    pub(crate) fn now() -> Self {
+    /// Starts a stopwatch.
+    pub fn now() -> Self {
         Self {
             // These two syscalls will happen imperceptibly close to each other, which is good
             // enough for our purposes.
             start_time: SystemTime::now(),
-            instant: Instant::now(),
+            instant: ClockInstant::now(),
         }
     }
 
+    /// Returns the time that has passed since the stopwatch was started.
     #[inline]
-    pub(crate) fn elapsed(&self) -> Duration {
+    pub fn elapsed(&self) -> Duration {
         self.instant.elapsed()
     }
 
-    pub(crate) fn end(&self) -> StopwatchEnd {
+    /// Stops the stopwatch.
+    pub fn end(&self) -> StopwatchEnd {
         StopwatchEnd {
+            // The end time is derived from the start time and the duration, rather than read off
+            // the system clock, so that the two are consistent even if the system clock changes.
             start_time: self.start_time,
             duration: self.instant.elapsed(),
         }
     }
 }
 
+/// The end state of a stopwatch.
 #[derive(Clone, Debug)]
-pub(crate) struct StopwatchEnd {
-    pub(crate) start_time: SystemTime,
-    pub(crate) duration: Duration,
+pub struct StopwatchEnd {
+    /// The time the stopwatch was started at, on the system clock.
+    pub start_time: SystemTime,
+    /// The time that passed between starting and stopping the stopwatch, on the monotonic clock.
+    pub duration: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_instant() {
+        let start = ClockInstant::now();
+        std::thread::sleep(Duration::from_millis(20));
+        let end = ClockInstant::now();
+        assert!(end > start);
+        assert!(end.saturating_duration_since(start) >= Duration::from_millis(20));
+        assert!(start.elapsed() >= end.saturating_duration_since(start));
+        assert_eq!(
+            start.saturating_duration_since(end),
+            Duration::ZERO,
+            "durations saturate at zero"
+        );
+
+        // Deadlines are instants in the future.
+        let deadline = start + Duration::from_secs(1);
+        assert!(deadline > start);
+        assert_eq!(
+            deadline.saturating_duration_since(start),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            (end + Duration::from_secs(60)).elapsed(),
+            Duration::ZERO,
+            "instants in the future haven't elapsed"
+        );
+    }
+
+    #[test]
+    fn stopwatch() {
+        let before = SystemTime::now();
+        let stopwatch = StopwatchStart::now();
+        std::thread::sleep(Duration::from_millis(20));
+        let elapsed = stopwatch.elapsed();
+        let end = stopwatch.end();
+
+        assert_eq!(
+            end.start_time, stopwatch.start_time,
+            "the start time is kept as is"
+        );
+        assert!(end.start_time >= before);
+        assert!(elapsed >= Duration::from_millis(20));
+        assert!(
+            end.duration >= elapsed,
+            "the duration is measured when the stopwatch is stopped"
+        );
+        assert!(
+            stopwatch.elapsed() >= end.duration,
+            "a stopwatch can keep being read after it's stopped"
+        );
+    }
 }
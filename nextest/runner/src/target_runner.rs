@@ -0,0 +1,1149 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Support for running test binaries through a wrapper, such as an emulator when
+//! cross-compiling.
+//!
+//! The runner for a platform is looked up from these sources, in order of precedence:
+//!
+//! 1. The `target-runner` rules of the nextest profile, the first of which whose `platform` matches
+//!    is used. `platform` is either a target triple or a `cfg()` expression:
+//!
+//!    ```toml
+//!    [[profile.default.target-runner]]
+//!    platform = "cfg(target_arch = \"aarch64\")"
+//!    command = ["qemu-aarch64", "-L", "/usr/aarch64-linux-gnu"]
+//!    # Optional: a different command to list tests with. An empty list means that test binaries
+//!    # are listed without a runner.
+//!    list-command = ["qemu-aarch64-static"]
+//!    ```
+//!
+//!    Relative program paths that contain a `/` are relative to the workspace root. Instead of
+//!    `command`, a rule can specify a `remote` host to copy test binaries to and run them on over
+//!    SSH, `adb` devices to push test binaries to and run them on, or an embedded device to flash
+//!    test binaries to through a `probe`: see [`remote_runner`](crate::remote_runner),
+//!    [`adb_runner`](crate::adb_runner) and [`probe_runner`](crate::probe_runner) for more. A
+//!    rule can also specify commands
+//!    that start and stop an emulator once per run: see [`runner_lifecycle`](crate::runner_lifecycle)
+//!    for more.
+//! 2. The `CARGO_TARGET_<triple>_RUNNER` environment variable, where `<triple>` is the target
+//!    triple in uppercase with dashes and dots replaced by underscores.
+//! 3. The `target.<triple>.runner` and `target.<cfg>.runner` keys in Cargo config files, read from
+//!    `.cargo/config.toml` (or `.cargo/config`) in the current directory and its ancestors, and
+//!    then from the Cargo home directory. The closest file that specifies a runner for the
+//!    platform wins. Relative program paths that contain a `/` are relative to the directory
+//!    containing `.cargo`.
+//!
+//! Runners from the environment and from Cargo config files are used both to list and to run
+//! tests.
+//!
+//! For WASI targets, if none of these sources specify a runner, the WebAssembly runtime configured
+//! for the profile is used: see [`wasm`](crate::wasm) for more.
+//!
+//! Each runner lists and runs tests in an execution environment: a wrapper command, a WebAssembly
+//! runtime, a remote host or a device. Binaries for platforms without a runner are run locally.
+//!
+//! With `cargo nextest run --miri`, none of these sources are used: binaries for both platforms are
+//! listed and run through `cargo-miri runner`, which interprets them under Miri. See
+//! [`TargetRunner::set_miri`].
+
+use crate::{
+    adb_runner::{AdbDevices, AdbRunner},
+    environment::{ExecutionEnvironment, WasmRunner, WrapperCommand},
+    errors::{RunnerLifecycleError, TargetRunnerError, TargetRunnerRuleParseError},
+    probe_runner::{ProbeDevice, ProbeRunner},
+    remote_runner::{RemoteRunner, SshRemote},
+    runner_lifecycle::{RunnerLifecycle, RunnerLifecycleGuard},
+    wasm::{self, WasmRuntime},
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use nextest_metadata::BuildPlatform;
+use serde::{Deserialize, Deserializer};
+use std::{env, fmt, sync::Arc, time::Duration};
+use target_spec::{Platform, TargetFeatures, TargetSpec};
+
+/// The runners used to list and run test binaries for each [`BuildPlatform`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TargetRunner {
+    host: PlatformRunners,
+    target: PlatformRunners,
+}
+
+impl TargetRunner {
+    /// Looks up runners for the given host and target triples, with the given `target-runner`
+    /// rules from a nextest profile.
+    ///
+    /// `cwd` is the directory Cargo config files are looked up from, and `workspace_root` is the
+    /// directory that relative paths in rules are relative to. If the target triple is `None`,
+    /// tests aren't being cross-compiled and binaries for both platforms use the host runners.
+    /// `wasm_runtime` is used for WASI targets without a runner.
+    pub fn new<'a>(
+        rules: impl IntoIterator<Item = &'a TargetRunnerRule> + Clone,
+        workspace_root: &Utf8Path,
+        cwd: &Utf8Path,
+        host_triple: Option<&str>,
+        target_triple: Option<&str>,
+        wasm_runtime: WasmRuntime,
+    ) -> Result<Self, TargetRunnerError> {
+        let lookup = |triple: Option<&str>| match triple {
+            Some(triple) => {
+                PlatformRunners::lookup(rules.clone(), workspace_root, cwd, triple, wasm_runtime)
+            }
+            None => Ok(PlatformRunners::default()),
+        };
+        let host = lookup(host_triple)?;
+        let target = match target_triple {
+            Some(target_triple) => lookup(Some(target_triple))?,
+            None => host.clone(),
+        };
+        Ok(Self { host, target })
+    }
+
+    /// Returns the runner used to run tests in binaries built for the given platform, if any.
+    pub fn for_platform(&self, build_platform: BuildPlatform) -> Option<&PlatformRunner> {
+        self.runners(build_platform).run.as_ref()
+    }
+
+    /// Returns the runner used to list tests in binaries built for the given platform, if any.
+    pub fn for_listing(&self, build_platform: BuildPlatform) -> Option<&PlatformRunner> {
+        self.runners(build_platform).list.as_ref()
+    }
+
+    /// Starts the environments the runners for both platforms need, such as emulators, and waits
+    /// for them to be ready.
+    ///
+    /// This should be called before tests are listed. The environments are stopped once the
+    /// returned guard is dropped.
+    pub fn start_lifecycles(&self) -> Result<RunnerLifecycleGuard, RunnerLifecycleError> {
+        let mut lifecycles: Vec<&RunnerLifecycle> = vec![];
+        for lifecycle in [&self.host.lifecycle, &self.target.lifecycle]
+            .into_iter()
+            .flatten()
+        {
+            // If the host and target use the same rule, its environment is only started once.
+            if !lifecycles.contains(&lifecycle) {
+                lifecycles.push(lifecycle);
+            }
+        }
+        RunnerLifecycleGuard::start(lifecycles)
+    }
+
+    /// Replaces the devices that runners for `adb` devices run tests on, for example with devices
+    /// selected on the command line.
+    pub fn set_adb_devices(&mut self, devices: Vec<String>) -> &mut Self {
+        // Runners that shared the state of what has been pushed to devices keep sharing it.
+        let mut replaced: Vec<(Arc<dyn ExecutionEnvironment>, Arc<dyn ExecutionEnvironment>)> =
+            vec![];
+        for runner in [
+            &mut self.host.run,
+            &mut self.host.list,
+            &mut self.target.run,
+            &mut self.target.list,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let adb = match runner.environment_as::<AdbRunner>() {
+                Some(adb) => adb,
+                None => continue,
+            };
+            let new_adb = match replaced
+                .iter()
+                .find(|(old, _)| Arc::ptr_eq(old, &runner.environment))
+            {
+                Some((_, new_adb)) => new_adb.clone(),
+                None => {
+                    let new_adb: Arc<dyn ExecutionEnvironment> =
+                        Arc::new(adb.with_devices(devices.clone()));
+                    replaced.push((runner.environment.clone(), new_adb.clone()));
+                    new_adb
+                }
+            };
+            runner.environment = new_adb;
+        }
+        self
+    }
+
+    /// Replaces the runners for both platforms with `cargo-miri runner`, which interprets test
+    /// binaries built by `cargo miri test --no-run` under Miri.
+    ///
+    /// The binaries built by Miri aren't native executables, so they're listed through the runner
+    /// as well, and other runners (along with the environments they start) aren't used.
+    pub fn set_miri(&mut self) -> &mut Self {
+        let runner = PlatformRunner::parse(MIRI_RUNNER, RunnerSource::Miri)
+            .expect("the Miri runner is valid");
+        let runners = PlatformRunners {
+            run: Some(runner.clone()),
+            list: Some(runner),
+            lifecycle: None,
+        };
+        self.host = runners.clone();
+        self.target = runners;
+        self
+    }
+
+    fn runners(&self, build_platform: BuildPlatform) -> &PlatformRunners {
+        match build_platform {
+            BuildPlatform::Host => &self.host,
+            BuildPlatform::Target => &self.target,
+        }
+    }
+}
+
+/// The command that interprets test binaries built by `cargo miri` under Miri.
+const MIRI_RUNNER: &str = "cargo-miri runner";
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct PlatformRunners {
+    run: Option<PlatformRunner>,
+    list: Option<PlatformRunner>,
+    lifecycle: Option<RunnerLifecycle>,
+}
+
+impl PlatformRunners {
+    fn lookup<'a>(
+        rules: impl IntoIterator<Item = &'a TargetRunnerRule>,
+        workspace_root: &Utf8Path,
+        cwd: &Utf8Path,
+        triple: &str,
+        wasm_runtime: WasmRuntime,
+    ) -> Result<Self, TargetRunnerError> {
+        let runners = Self::lookup_configured(rules, workspace_root, cwd, triple)?;
+        if !wasm::is_wasi_triple(triple) {
+            return Ok(runners);
+        }
+
+        // WASI binaries can only be run through a WebAssembly runtime.
+        match runners.run {
+            Some(run) => Ok(Self {
+                run: Some(run.detect_wasm()),
+                list: runners.list.map(PlatformRunner::detect_wasm),
+                lifecycle: runners.lifecycle,
+            }),
+            None => {
+                let runner = PlatformRunner::from_wasm_runtime(wasm_runtime);
+                Ok(Self {
+                    run: Some(runner.clone()),
+                    list: Some(runner),
+                    lifecycle: runners.lifecycle,
+                })
+            }
+        }
+    }
+
+    fn lookup_configured<'a>(
+        rules: impl IntoIterator<Item = &'a TargetRunnerRule>,
+        workspace_root: &Utf8Path,
+        cwd: &Utf8Path,
+        triple: &str,
+    ) -> Result<Self, TargetRunnerError> {
+        // Triples unknown to target-spec, such as custom targets, can still be matched exactly.
+        let platform = Platform::new(triple.to_owned(), TargetFeatures::Unknown).ok();
+
+        if let Some(rule) = rules
+            .into_iter()
+            .find(|rule| rule.matches(triple, platform.as_ref()))
+        {
+            let source = RunnerSource::NextestConfig {
+                platform: rule.platform_str.clone(),
+            };
+            let run = match (&rule.remote, &rule.adb, &rule.probe) {
+                (Some(remote), _, _) => Some(PlatformRunner::from_remote(
+                    remote,
+                    workspace_root,
+                    source.clone(),
+                )),
+                (None, Some(adb), _) => Some(PlatformRunner::from_adb(
+                    adb,
+                    workspace_root,
+                    source.clone(),
+                )),
+                (None, None, Some(probe)) => {
+                    Some(PlatformRunner::from_probe(probe, source.clone()))
+                }
+                (None, None, None) => {
+                    PlatformRunner::from_command(&rule.command, workspace_root, source.clone())
+                }
+            };
+            let list = match &rule.list_command {
+                Some(list_command) => {
+                    PlatformRunner::from_command(list_command, workspace_root, source)
+                }
+                None => run.clone(),
+            };
+            let lifecycle = rule.lifecycle.as_ref().map(|lifecycle| {
+                lifecycle.in_dir(workspace_root, |program| {
+                    resolve_program(program, workspace_root)
+                })
+            });
+            return Ok(Self {
+                run,
+                list,
+                lifecycle,
+            });
+        }
+
+        let runner = match PlatformRunner::from_env(triple) {
+            Some(runner) => Some(runner),
+            None => PlatformRunner::from_cargo_configs(cwd, triple, platform.as_ref())?,
+        };
+        Ok(Self {
+            run: runner.clone(),
+            list: runner,
+            lifecycle: None,
+        })
+    }
+}
+
+/// A runner for binaries built for a single platform.
+///
+/// Each runner lists and runs tests in an execution environment, such as a wrapper command, a
+/// remote host or a device.
+#[derive(Clone, Debug)]
+pub struct PlatformRunner {
+    program: String,
+    args: Vec<String>,
+    environment: Arc<dyn ExecutionEnvironment>,
+    source: RunnerSource,
+}
+
+impl PlatformRunner {
+    /// Parses a runner from a string of whitespace-separated words: the program followed by its
+    /// arguments. Returns `None` if the string is empty.
+    pub fn parse(runner: &str, source: RunnerSource) -> Option<Self> {
+        let mut words = runner.split_whitespace().map(|word| word.to_owned());
+        let program = words.next()?;
+        let args: Vec<String> = words.collect();
+        Some(Self {
+            environment: Arc::new(WrapperCommand {
+                program: program.clone(),
+                args: args.clone(),
+            }),
+            program,
+            args,
+            source,
+        })
+    }
+
+    /// Returns the program to run. The test binary is passed in to it after [`Self::args`].
+    ///
+    /// For runners on a remote host, this is `ssh`, and the command run on the remote host is
+    /// passed in after the arguments instead. Similarly, for runners on `adb` devices, this is
+    /// `adb`, and for runners on probe-attached devices, this is `probe-rs`.
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// Returns the arguments passed to the program before the test binary.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns the remote host test binaries are copied to and run on, if this is a remote runner.
+    pub fn remote(&self) -> Option<&SshRemote> {
+        self.environment_as::<RemoteRunner>()
+            .map(|remote| remote.remote())
+    }
+
+    /// Returns the devices test binaries are pushed to and run on, if this is an `adb` runner.
+    pub fn adb(&self) -> Option<&AdbDevices> {
+        self.environment_as::<AdbRunner>().map(|adb| adb.devices())
+    }
+
+    /// Returns the device test binaries are flashed to and run on, if this is a runner for a
+    /// probe-attached device.
+    pub fn probe(&self) -> Option<&ProbeDevice> {
+        self.environment_as::<ProbeRunner>()
+            .map(|probe| probe.device())
+    }
+
+    /// Returns the WebAssembly runtime this runner is, if it runs WASI binaries.
+    pub fn wasm_runtime(&self) -> Option<WasmRuntime> {
+        self.environment_as::<WasmRunner>().map(|wasm| wasm.runtime)
+    }
+
+    /// Returns where this runner was specified.
+    pub fn source(&self) -> &RunnerSource {
+        &self.source
+    }
+
+    /// Returns the environment that tests are listed and run in.
+    pub(crate) fn environment(&self) -> &Arc<dyn ExecutionEnvironment> {
+        &self.environment
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    fn from_command(command: &[String], base_dir: &Utf8Path, source: RunnerSource) -> Option<Self> {
+        let (program, args) = command.split_first()?;
+        let program = resolve_program(program, base_dir);
+        Some(Self {
+            environment: Arc::new(WrapperCommand {
+                program: program.clone(),
+                args: args.to_vec(),
+            }),
+            program,
+            args: args.to_vec(),
+            source,
+        })
+    }
+
+    fn from_remote(remote: &SshRemote, workspace_root: &Utf8Path, source: RunnerSource) -> Self {
+        let args = remote
+            .ssh_args()
+            .iter()
+            .cloned()
+            .chain(std::iter::once(remote.host().to_owned()))
+            .collect();
+        Self {
+            program: "ssh".to_owned(),
+            args,
+            environment: Arc::new(RemoteRunner::new(remote.clone(), workspace_root)),
+            source,
+        }
+    }
+
+    fn from_adb(adb: &AdbDevices, workspace_root: &Utf8Path, source: RunnerSource) -> Self {
+        Self {
+            program: "adb".to_owned(),
+            args: adb.adb_args().to_vec(),
+            environment: Arc::new(AdbRunner::new(adb.clone(), workspace_root)),
+            source,
+        }
+    }
+
+    fn from_probe(probe: &ProbeDevice, source: RunnerSource) -> Self {
+        Self {
+            program: "probe-rs".to_owned(),
+            args: probe.run_args(),
+            environment: Arc::new(ProbeRunner::new(probe.clone())),
+            source,
+        }
+    }
+
+    fn from_wasm_runtime(wasm_runtime: WasmRuntime) -> Self {
+        Self {
+            program: wasm_runtime.program().to_owned(),
+            args: vec![],
+            environment: Arc::new(WasmRunner {
+                runtime: wasm_runtime,
+                program: wasm_runtime.program().to_owned(),
+                args: vec![],
+            }),
+            source: RunnerSource::WasmRuntime,
+        }
+    }
+
+    // Marks wrapper commands whose program is a WebAssembly runtime as such, so that arguments and
+    // environment variables are passed through to the module.
+    fn detect_wasm(mut self) -> Self {
+        let runtime = self
+            .environment_as::<WrapperCommand>()
+            .and_then(|wrapper| WasmRuntime::from_program(&wrapper.program));
+        if let Some(runtime) = runtime {
+            self.environment = Arc::new(WasmRunner {
+                runtime,
+                program: self.program.clone(),
+                args: self.args.clone(),
+            });
+        }
+        self
+    }
+
+    fn environment_as<T: ExecutionEnvironment>(&self) -> Option<&T> {
+        self.environment.as_any().downcast_ref::<T>()
+    }
+
+    fn from_env(triple: &str) -> Option<Self> {
+        let var = runner_env_var(triple);
+        let runner = env::var(&var).ok()?;
+        Self::parse(&runner, RunnerSource::Env(var))
+    }
+
+    fn from_cargo_configs(
+        cwd: &Utf8Path,
+        triple: &str,
+        platform: Option<&Platform>,
+    ) -> Result<Option<Self>, TargetRunnerError> {
+        for config_path in cargo_config_paths(cwd) {
+            let contents = match std::fs::read_to_string(&config_path) {
+                Ok(contents) => contents,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => {
+                    return Err(TargetRunnerError::CargoConfigRead {
+                        path: config_path,
+                        error,
+                    })
+                }
+            };
+            let config: toml::Value =
+                toml::from_str(&contents).map_err(|error| TargetRunnerError::CargoConfigParse {
+                    path: config_path.clone(),
+                    error,
+                })?;
+            if let Some(runner) = Self::from_cargo_config(&config, &config_path, triple, platform)?
+            {
+                return Ok(Some(runner));
+            }
+        }
+        Ok(None)
+    }
+
+    fn from_cargo_config(
+        config: &toml::Value,
+        config_path: &Utf8Path,
+        triple: &str,
+        platform: Option<&Platform>,
+    ) -> Result<Option<Self>, TargetRunnerError> {
+        let targets = match config.get("target").and_then(|target| target.as_table()) {
+            Some(targets) => targets,
+            None => return Ok(None),
+        };
+
+        // As with Cargo, runners for the exact triple take precedence over cfg() runners.
+        let runner = targets
+            .get(triple)
+            .and_then(|target| target.get("runner"))
+            .or_else(|| {
+                let platform = platform?;
+                targets.iter().find_map(|(key, target)| {
+                    let spec = key
+                        .starts_with("cfg(")
+                        .then(|| TargetSpec::new(key.clone()).ok())
+                        .flatten()?;
+                    (spec.eval(platform) == Some(true))
+                        .then(|| target.get("runner"))
+                        .flatten()
+                })
+            });
+        let runner = match runner {
+            Some(runner) => runner,
+            None => return Ok(None),
+        };
+
+        // Relative paths are relative to the directory containing .cargo.
+        let base_dir = config_path
+            .parent()
+            .and_then(|cargo_dir| cargo_dir.parent())
+            .unwrap_or(config_path);
+        let source = RunnerSource::CargoConfig(config_path.to_owned());
+        let invalid = || TargetRunnerError::InvalidCargoRunner {
+            path: config_path.to_owned(),
+            triple: triple.to_owned(),
+        };
+        let command: Vec<String> = match runner {
+            toml::Value::String(runner) => runner
+                .split_whitespace()
+                .map(|word| word.to_owned())
+                .collect(),
+            toml::Value::Array(words) => words
+                .iter()
+                .map(|word| word.as_str().map(|word| word.to_owned()))
+                .collect::<Option<_>>()
+                .ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+        Self::from_command(&command, base_dir, source)
+            .map(Some)
+            .ok_or_else(invalid)
+    }
+}
+
+impl PartialEq for PlatformRunner {
+    fn eq(&self, other: &Self) -> bool {
+        self.program == other.program
+            && self.args == other.args
+            && self.environment.eq_dyn(other.environment.as_ref())
+            && self.source == other.source
+    }
+}
+
+impl Eq for PlatformRunner {}
+
+/// Where a [`PlatformRunner`] was specified.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RunnerSource {
+    /// A `target-runner` rule in the nextest config, with the given platform.
+    NextestConfig {
+        /// The platform the rule applies to, as specified in the config.
+        platform: String,
+    },
+
+    /// The given environment variable.
+    Env(String),
+
+    /// The Cargo config file at the given path.
+    CargoConfig(Utf8PathBuf),
+
+    /// The `wasm-runtime` setting of the nextest profile, used for WASI targets without a runner.
+    WasmRuntime,
+
+    /// The `--miri` option, which runs tests through `cargo-miri runner`.
+    Miri,
+}
+
+impl fmt::Display for RunnerSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunnerSource::NextestConfig { platform } => {
+                write!(f, "nextest config (platform '{}')", platform)
+            }
+            RunnerSource::Env(var) => write!(f, "environment variable {}", var),
+            RunnerSource::CargoConfig(path) => write!(f, "Cargo config '{}'", path),
+            RunnerSource::WasmRuntime => write!(f, "nextest config (wasm-runtime)"),
+            RunnerSource::Miri => write!(f, "the --miri option"),
+        }
+    }
+}
+
+/// A rule that specifies the runner for platforms matching a target triple or `cfg()` expression.
+///
+/// Obtained through
+/// [`NextestProfile::target_runner_rules`](crate::config::NextestProfile::target_runner_rules).
+#[derive(Clone, Debug)]
+pub struct TargetRunnerRule {
+    platform: TargetSpec,
+    platform_str: String,
+    command: Vec<String>,
+    remote: Option<SshRemote>,
+    adb: Option<AdbDevices>,
+    probe: Option<ProbeDevice>,
+    list_command: Option<Vec<String>>,
+    lifecycle: Option<RunnerLifecycle>,
+}
+
+impl TargetRunnerRule {
+    /// Parses a rule from a platform specification, a command to run tests with and an optional
+    /// command to list tests with.
+    pub fn new(
+        platform: impl Into<String>,
+        command: Vec<String>,
+        list_command: Option<Vec<String>>,
+    ) -> Result<Self, TargetRunnerRuleParseError> {
+        let platform_str = platform.into();
+        let platform = TargetSpec::new(platform_str.clone())
+            .map_err(|error| TargetRunnerRuleParseError::new(platform_str.clone(), error))?;
+        if command.is_empty() {
+            return Err(TargetRunnerRuleParseError::empty_command(platform_str));
+        }
+        Ok(Self {
+            platform,
+            platform_str,
+            command,
+            remote: None,
+            adb: None,
+            probe: None,
+            list_command,
+            lifecycle: None,
+        })
+    }
+
+    /// Parses a rule from a platform specification, a remote host to run tests on and an optional
+    /// command to list tests with.
+    pub fn new_remote(
+        platform: impl Into<String>,
+        remote: SshRemote,
+        list_command: Option<Vec<String>>,
+    ) -> Result<Self, TargetRunnerRuleParseError> {
+        let platform_str = platform.into();
+        let platform = TargetSpec::new(platform_str.clone())
+            .map_err(|error| TargetRunnerRuleParseError::new(platform_str.clone(), error))?;
+        Ok(Self {
+            platform,
+            platform_str,
+            command: vec![],
+            remote: Some(remote),
+            adb: None,
+            probe: None,
+            list_command,
+            lifecycle: None,
+        })
+    }
+
+    /// Parses a rule from a platform specification, `adb` devices to run tests on and an optional
+    /// command to list tests with.
+    pub fn new_adb(
+        platform: impl Into<String>,
+        adb: AdbDevices,
+        list_command: Option<Vec<String>>,
+    ) -> Result<Self, TargetRunnerRuleParseError> {
+        let platform_str = platform.into();
+        let platform = TargetSpec::new(platform_str.clone())
+            .map_err(|error| TargetRunnerRuleParseError::new(platform_str.clone(), error))?;
+        Ok(Self {
+            platform,
+            platform_str,
+            command: vec![],
+            remote: None,
+            adb: Some(adb),
+            probe: None,
+            list_command,
+            lifecycle: None,
+        })
+    }
+
+    /// Parses a rule from a platform specification, a probe-attached device to run tests on and an
+    /// optional command to list tests with.
+    pub fn new_probe(
+        platform: impl Into<String>,
+        probe: ProbeDevice,
+        list_command: Option<Vec<String>>,
+    ) -> Result<Self, TargetRunnerRuleParseError> {
+        let platform_str = platform.into();
+        let platform = TargetSpec::new(platform_str.clone())
+            .map_err(|error| TargetRunnerRuleParseError::new(platform_str.clone(), error))?;
+        Ok(Self {
+            platform,
+            platform_str,
+            command: vec![],
+            remote: None,
+            adb: None,
+            probe: Some(probe),
+            list_command,
+            lifecycle: None,
+        })
+    }
+
+    /// Returns the platform this rule applies to, as specified.
+    pub fn platform(&self) -> &str {
+        &self.platform_str
+    }
+
+    /// Returns the command used to run tests: the program followed by its arguments. This is empty
+    /// for rules that run tests on a remote host, on `adb` devices or on a probe-attached device.
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    /// Returns the remote host tests are run on, if specified instead of a command.
+    pub fn remote(&self) -> Option<&SshRemote> {
+        self.remote.as_ref()
+    }
+
+    /// Returns the `adb` devices tests are run on, if specified instead of a command.
+    pub fn adb(&self) -> Option<&AdbDevices> {
+        self.adb.as_ref()
+    }
+
+    /// Returns the probe-attached device tests are run on, if specified instead of a command.
+    pub fn probe(&self) -> Option<&ProbeDevice> {
+        self.probe.as_ref()
+    }
+
+    /// Returns the command used to list tests, if it's different from [`Self::command`]. An empty
+    /// command means that tests are listed without a runner.
+    pub fn list_command(&self) -> Option<&[String]> {
+        self.list_command.as_deref()
+    }
+
+    /// Returns the commands that start and stop the environment the runner needs, if any.
+    pub fn lifecycle(&self) -> Option<&RunnerLifecycle> {
+        self.lifecycle.as_ref()
+    }
+
+    /// Sets the commands that start and stop the environment the runner needs.
+    pub fn set_lifecycle(&mut self, lifecycle: RunnerLifecycle) -> &mut Self {
+        self.lifecycle = Some(lifecycle);
+        self
+    }
+
+    fn matches(&self, triple: &str, platform: Option<&Platform>) -> bool {
+        if self.platform_str == triple {
+            return true;
+        }
+        platform.is_some_and(|platform| self.platform.eval(platform) == Some(true))
+    }
+}
+
+impl<'de> Deserialize<'de> for TargetRunnerRule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct TargetRunnerRuleImpl {
+            platform: String,
+            #[serde(default)]
+            command: Option<Vec<String>>,
+            #[serde(default)]
+            remote: Option<SshRemote>,
+            #[serde(default)]
+            adb: Option<AdbDevices>,
+            #[serde(default)]
+            probe: Option<ProbeDevice>,
+            #[serde(default)]
+            list_command: Option<Vec<String>>,
+            #[serde(default)]
+            start: Option<Vec<String>>,
+            #[serde(default)]
+            ready: Option<Vec<String>>,
+            #[serde(default, with = "humantime_serde")]
+            ready_timeout: Option<Duration>,
+            #[serde(default)]
+            stop: Option<Vec<String>>,
+        }
+
+        let TargetRunnerRuleImpl {
+            platform,
+            command,
+            remote,
+            adb,
+            probe,
+            list_command,
+            start,
+            ready,
+            ready_timeout,
+            stop,
+        } = TargetRunnerRuleImpl::deserialize(deserializer)?;
+        let rule = match (command, remote, adb, probe) {
+            (Some(command), None, None, None) => Self::new(platform, command, list_command),
+            (None, Some(remote), None, None) => Self::new_remote(platform, remote, list_command),
+            (None, None, Some(adb), None) => Self::new_adb(platform, adb, list_command),
+            (None, None, None, Some(probe)) => Self::new_probe(platform, probe, list_command),
+            (None, None, None, None) => Err(TargetRunnerRuleParseError::empty_command(platform)),
+            _ => Err(TargetRunnerRuleParseError::multiple_runners(platform)),
+        };
+        let mut rule = rule.map_err(serde::de::Error::custom)?;
+        if let Some(lifecycle) = RunnerLifecycle::new(start, ready, ready_timeout, stop) {
+            rule.set_lifecycle(lifecycle);
+        }
+        Ok(rule)
+    }
+}
+
+fn runner_env_var(triple: &str) -> String {
+    format!(
+        "CARGO_TARGET_{}_RUNNER",
+        triple.to_ascii_uppercase().replace(['-', '.'], "_")
+    )
+}
+
+// Makes relative program paths with a separator in them relative to the given directory. Bare
+// program names are looked up in PATH.
+fn resolve_program(program: &str, base_dir: &Utf8Path) -> String {
+    let path = Utf8Path::new(program);
+    if path.is_relative() && path.components().count() > 1 {
+        base_dir.join(path).into_string()
+    } else {
+        program.to_owned()
+    }
+}
+
+// Returns the Cargo config files that may apply to the given directory, closest first.
+fn cargo_config_paths(cwd: &Utf8Path) -> Vec<Utf8PathBuf> {
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(std::path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".cargo")))
+        .and_then(|cargo_home| Utf8PathBuf::try_from(cargo_home).ok());
+
+    let mut cargo_dirs: Vec<_> = cwd.ancestors().map(|dir| dir.join(".cargo")).collect();
+    if let Some(cargo_home) = cargo_home {
+        if !cargo_dirs.contains(&cargo_home) {
+            cargo_dirs.push(cargo_home);
+        }
+    }
+
+    // Cargo reads config.toml if it exists, and config otherwise.
+    cargo_dirs
+        .into_iter()
+        .filter_map(|cargo_dir| {
+            ["config.toml", "config"]
+                .into_iter()
+                .map(|file_name| cargo_dir.join(file_name))
+                .find(|path| path.is_file())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_runner() {
+        assert_eq!(
+            runner_env_var("aarch64-unknown-linux-gnu"),
+            "CARGO_TARGET_AARCH64_UNKNOWN_LINUX_GNU_RUNNER"
+        );
+        assert_eq!(
+            runner_env_var("thumbv7em-none-eabihf.json"),
+            "CARGO_TARGET_THUMBV7EM_NONE_EABIHF_JSON_RUNNER"
+        );
+
+        let source = RunnerSource::Env("FAKE".to_owned());
+        let runner =
+            PlatformRunner::parse("qemu-aarch64  -L /usr/aarch64-linux-gnu", source.clone())
+                .expect("runner is valid");
+        assert_eq!(runner.program(), "qemu-aarch64");
+        assert_eq!(runner.args(), ["-L", "/usr/aarch64-linux-gnu"]);
+        assert_eq!(PlatformRunner::parse("  ", source), None);
+
+        let target_runner = TargetRunner {
+            host: PlatformRunners::default(),
+            target: PlatformRunners {
+                run: Some(runner.clone()),
+                list: None,
+                lifecycle: None,
+            },
+        };
+        assert_eq!(
+            target_runner.for_platform(BuildPlatform::Target),
+            Some(&runner)
+        );
+        assert_eq!(target_runner.for_listing(BuildPlatform::Target), None);
+        assert_eq!(
+            target_runner.for_platform(BuildPlatform::Host),
+            None,
+            "host binaries aren't run through the target runner"
+        );
+
+        let mut miri = target_runner.clone();
+        miri.set_miri();
+        for build_platform in [BuildPlatform::Host, BuildPlatform::Target] {
+            let runner = miri.for_platform(build_platform).expect("runner is set");
+            assert_eq!(runner.program(), "cargo-miri");
+            assert_eq!(runner.args(), ["runner"]);
+            assert_eq!(runner.source(), &RunnerSource::Miri);
+            assert_eq!(miri.for_listing(build_platform), Some(runner));
+        }
+    }
+
+    #[test]
+    fn fallback_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let cwd = root.join("workspace/crate");
+        std::fs::create_dir_all(cwd.join(".cargo")).unwrap();
+        std::fs::create_dir_all(root.join(".cargo")).unwrap();
+        std::fs::write(
+            cwd.join(".cargo/config.toml"),
+            r#"
+                [target.'cfg(target_arch = "aarch64")']
+                runner = "qemu-aarch64 -L /sysroot"
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            root.join(".cargo/config"),
+            r#"
+                [target.aarch64-unknown-linux-gnu]
+                runner = ["./outer-runner"]
+
+                [target.riscv64gc-unknown-linux-gnu]
+                runner = ["./tools/riscv-runner", "--fast"]
+
+                [target.wasm32-wasip2]
+                runner = "wasmer --enable-threads"
+            "#,
+        )
+        .unwrap();
+
+        let mut android_rule = TargetRunnerRule::new(
+            "cfg(target_os = \"android\")",
+            vec!["tools/android-runner".to_owned()],
+            Some(vec![]),
+        )
+        .unwrap();
+        android_rule.set_lifecycle(
+            RunnerLifecycle::new(
+                Some(vec!["tools/boot-emulator".to_owned()]),
+                Some(vec!["adb".to_owned(), "wait-for-device".to_owned()]),
+                None,
+                None,
+            )
+            .expect("commands are specified"),
+        );
+        let rules = vec![android_rule];
+        let lookup = |triple| {
+            PlatformRunners::lookup(
+                &rules,
+                "/workspace".as_ref(),
+                &cwd,
+                triple,
+                WasmRuntime::Wasmtime,
+            )
+            .expect("config files are valid")
+        };
+
+        // The nextest config is used first, with a different list runner.
+        let android = lookup("aarch64-linux-android");
+        let run = android.run.expect("runner is set");
+        assert_eq!(run.program(), "/workspace/tools/android-runner");
+        assert!(matches!(run.source(), RunnerSource::NextestConfig { .. }));
+        assert_eq!(
+            android.list, None,
+            "empty list command lists without a runner"
+        );
+        let lifecycle = android.lifecycle.expect("lifecycle is set");
+        assert_eq!(
+            lifecycle.start(),
+            Some(&["/workspace/tools/boot-emulator".to_owned()][..])
+        );
+        assert_eq!(
+            lifecycle.ready(),
+            Some(&["adb".to_owned(), "wait-for-device".to_owned()][..])
+        );
+        assert_eq!(
+            lifecycle.ready_timeout(),
+            RunnerLifecycle::DEFAULT_READY_TIMEOUT
+        );
+
+        // The closest Cargo config wins, even if it only has a cfg() runner.
+        let aarch64 = lookup("aarch64-unknown-linux-gnu");
+        let run = aarch64.run.expect("runner is set");
+        assert_eq!(run.program(), "qemu-aarch64");
+        assert_eq!(run.args(), ["-L", "/sysroot"]);
+        assert_eq!(
+            run.source(),
+            &RunnerSource::CargoConfig(cwd.join(".cargo/config.toml"))
+        );
+        assert_eq!(aarch64.list.as_ref(), Some(&run));
+
+        // Outer Cargo configs are read too, with paths relative to the directory containing
+        // .cargo.
+        let riscv = lookup("riscv64gc-unknown-linux-gnu");
+        let run = riscv.run.expect("runner is set");
+        assert_eq!(run.program(), root.join("./tools/riscv-runner").as_str());
+        assert_eq!(run.args(), ["--fast"]);
+
+        assert_eq!(
+            lookup("x86_64-unknown-linux-gnu"),
+            PlatformRunners::default()
+        );
+
+        // WASI targets fall back to the configured runtime, and runners that are runtimes are
+        // detected.
+        let wasip1 = lookup("wasm32-wasip1");
+        let run = wasip1.run.expect("runner is set");
+        assert_eq!(run.program(), "wasmtime");
+        assert_eq!(run.wasm_runtime(), Some(WasmRuntime::Wasmtime));
+        assert_eq!(run.source(), &RunnerSource::WasmRuntime);
+        assert_eq!(wasip1.list.as_ref(), Some(&run));
+
+        let wasip2 = lookup("wasm32-wasip2");
+        let run = wasip2.run.expect("runner is set");
+        assert_eq!(run.program(), "wasmer");
+        assert_eq!(run.args(), ["--enable-threads"]);
+        assert_eq!(run.wasm_runtime(), Some(WasmRuntime::Wasmer));
+    }
+
+    #[test]
+    fn adb_rules() {
+        let rule: TargetRunnerRule = toml::from_str(
+            r#"
+                platform = "cfg(target_os = \"android\")"
+                adb = { devices = ["emulator-5554"], adb-args = ["-H", "adb-server"] }
+            "#,
+        )
+        .expect("rule is valid");
+        let adb = rule.adb().expect("adb devices are set");
+        assert_eq!(adb.devices(), ["emulator-5554"]);
+        assert_eq!(adb.dir(), AdbDevices::DEFAULT_DIR);
+
+        let rules = [rule];
+        let mut target_runner = TargetRunner::new(
+            &rules,
+            "/workspace".as_ref(),
+            "/workspace".as_ref(),
+            Some("x86_64-unknown-linux-gnu"),
+            Some("aarch64-linux-android"),
+            WasmRuntime::Wasmtime,
+        )
+        .expect("runners are valid");
+        let run = target_runner
+            .for_platform(BuildPlatform::Target)
+            .expect("runner is set");
+        assert_eq!(run.program(), "adb");
+        assert_eq!(run.args(), ["-H", "adb-server"]);
+
+        target_runner.set_adb_devices(vec!["R58M123ABC".to_owned(), "R58M456DEF".to_owned()]);
+        let run = target_runner
+            .for_platform(BuildPlatform::Target)
+            .expect("runner is set");
+        assert_eq!(
+            run.adb().expect("runner is an adb runner").devices(),
+            ["R58M123ABC", "R58M456DEF"]
+        );
+        assert!(
+            Arc::ptr_eq(
+                run.environment(),
+                target_runner
+                    .for_listing(BuildPlatform::Target)
+                    .expect("list runner is set")
+                    .environment(),
+            ),
+            "list and run runners share what has been pushed"
+        );
+
+        toml::from_str::<TargetRunnerRule>(
+            r#"
+                platform = "aarch64-linux-android"
+                command = ["runner"]
+                adb = {}
+            "#,
+        )
+        .expect_err("command and adb devices are both specified");
+    }
+
+    #[test]
+    fn probe_rules() {
+        let rule: TargetRunnerRule = toml::from_str(
+            r#"
+                platform = "thumbv7em-none-eabihf"
+                probe = { chip = "nRF52840_xxAA", probe = "1366:1015", timeout = "30s" }
+            "#,
+        )
+        .expect("rule is valid");
+        let probe = rule.probe().expect("probe is set");
+        assert_eq!(probe.chip(), "nRF52840_xxAA");
+        assert_eq!(probe.timeout(), Duration::from_secs(30));
+
+        let rules = [rule];
+        let target_runner = TargetRunner::new(
+            &rules,
+            "/workspace".as_ref(),
+            "/workspace".as_ref(),
+            Some("x86_64-unknown-linux-gnu"),
+            Some("thumbv7em-none-eabihf"),
+            WasmRuntime::Wasmtime,
+        )
+        .expect("runners are valid");
+        let run = target_runner
+            .for_platform(BuildPlatform::Target)
+            .expect("runner is set");
+        assert_eq!(run.program(), "probe-rs");
+        assert_eq!(
+            run.args(),
+            ["run", "--chip", "nRF52840_xxAA", "--probe", "1366:1015"]
+        );
+        assert!(
+            Arc::ptr_eq(
+                run.environment(),
+                target_runner
+                    .for_listing(BuildPlatform::Target)
+                    .expect("list runner is set")
+                    .environment(),
+            ),
+            "tests reuse the run used to list them"
+        );
+
+        toml::from_str::<TargetRunnerRule>(
+            r#"
+                platform = "thumbv7em-none-eabihf"
+                adb = {}
+                probe = { chip = "nRF52840_xxAA" }
+            "#,
+        )
+        .expect_err("adb devices and a probe are both specified");
+    }
+
+    #[test]
+    fn invalid_rules() {
+        TargetRunnerRule::new("cfg(not-valid", vec!["runner".to_owned()], None)
+            .expect_err("invalid cfg expression");
+        TargetRunnerRule::new("x86_64-unknown-linux-gnu", vec![], None).expect_err("empty command");
+    }
+}
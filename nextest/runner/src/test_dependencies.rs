@@ -0,0 +1,401 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Declaring that some tests must run after others have passed.
+//!
+//! Some tests rely on state set up by other tests: for example, integration tests that need a
+//! database migrated by a migration test. Such dependencies are declared in the profile:
+//!
+//! ```toml
+//! [[profile.default.dependencies]]
+//! filter = "test(integration_)"
+//! depends-on = "test(migration)"
+//! ```
+//!
+//! Each test matching `filter` is only started once every test matching `depends-on` has finished.
+//! If any of them didn't pass, the dependent test isn't run, and fails with an execution error
+//! instead. Tests that depend on it fail in turn. Tests that skip themselves at runtime or fail as
+//! expected don't count as passing. A test never depends on itself, but the variants
+//! of a [multiplexed](crate::config::MultiplexRule) test all have to finish before tests that
+//! depend on it are started.
+//!
+//! Only tests that are run are taken into account: tests that are filtered out don't hold up the
+//! tests that depend on them. Dependencies are resolved into a graph of the tests to run before the
+//! run starts, and tests that depend on each other in a cycle are reported as an error. A test is
+//! only handed to a test thread once the tests it depends on have finished, so tests waiting for
+//! others never hold up a thread.
+
+use crate::{
+    errors::TestDependencyError,
+    filter_expr::FilterExpr,
+    test_list::{TestInstance, TestList},
+};
+use nextest_metadata::FilterMatch;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Mutex};
+
+/// A rule that makes the tests matching a filter expression run after the tests matching another.
+///
+/// Obtained through
+/// [`NextestProfile::dependency_rules`](crate::config::NextestProfile::dependency_rules).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DependencyRule {
+    filter: FilterExpr,
+    depends_on: FilterExpr,
+}
+
+impl DependencyRule {
+    /// Returns the filter expression that selects the tests that have dependencies.
+    pub fn filter(&self) -> &FilterExpr {
+        &self.filter
+    }
+
+    /// Returns the filter expression that selects the tests they depend on.
+    pub fn depends_on(&self) -> &FilterExpr {
+        &self.depends_on
+    }
+}
+
+/// The graph of dependencies between the tests to be run.
+#[derive(Clone, Debug, Default)]
+pub struct TestDependencies {
+    // (binary ID, test name) -> index into the vectors below.
+    indexes: HashMap<(String, String), usize>,
+    names: Vec<(String, String)>,
+    prerequisites: Vec<Vec<usize>>,
+}
+
+impl TestDependencies {
+    /// Resolves the given rules into dependencies between the tests to be run in `test_list`.
+    ///
+    /// Returns an error if tests depend on each other in a cycle.
+    pub fn new<'a>(
+        rules: impl IntoIterator<Item = &'a DependencyRule>,
+        test_list: &TestList<'_>,
+    ) -> Result<Self, TestDependencyError> {
+        let mut dependencies = Self::default();
+        let mut queries = vec![];
+        for test_instance in test_list.iter_tests() {
+            if test_instance.test_info.filter_match != FilterMatch::Matches {
+                continue;
+            }
+            dependencies.indexes.insert(
+                (
+                    test_instance.bin_info.binary_id.to_string(),
+                    test_instance.name.to_owned(),
+                ),
+                dependencies.names.len(),
+            );
+            dependencies.names.push((
+                test_instance.bin_info.binary_id.to_string(),
+                test_instance.name.to_owned(),
+            ));
+            queries.push(test_instance.to_query());
+        }
+        dependencies.prerequisites = vec![vec![]; queries.len()];
+
+        for rule in rules {
+            let prerequisites: Vec<_> = (0..queries.len())
+                .filter(|&index| rule.depends_on.matches_test(&queries[index]))
+                .collect();
+            if prerequisites.is_empty() {
+                continue;
+            }
+            for (index, query) in queries.iter().enumerate() {
+                if rule.filter.matches_test(query) {
+                    dependencies.prerequisites[index].extend(
+                        prerequisites
+                            .iter()
+                            .copied()
+                            .filter(|&prerequisite| prerequisite != index),
+                    );
+                }
+            }
+        }
+        for prerequisites in &mut dependencies.prerequisites {
+            prerequisites.sort_unstable();
+            prerequisites.dedup();
+        }
+
+        dependencies.check_cycles()?;
+        Ok(dependencies)
+    }
+
+    /// Returns true if no test depends on another.
+    pub fn is_empty(&self) -> bool {
+        self.prerequisites
+            .iter()
+            .all(|prerequisites| prerequisites.is_empty())
+    }
+
+    /// Returns the binary ID and name of each test the given test depends on.
+    pub fn prerequisites_of(
+        &self,
+        binary_id: &str,
+        name: &str,
+    ) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.indexes
+            .get(&(binary_id.to_owned(), name.to_owned()))
+            .into_iter()
+            .flat_map(move |&index| {
+                self.prerequisites[index].iter().map(move |&prerequisite| {
+                    let (binary_id, name) = &self.names[prerequisite];
+                    (binary_id.as_str(), name.as_str())
+                })
+            })
+    }
+
+    fn index(&self, test_instance: &TestInstance<'_>) -> Option<usize> {
+        self.indexes
+            .get(&(
+                test_instance.bin_info.binary_id.to_string(),
+                test_instance.name.to_owned(),
+            ))
+            .copied()
+    }
+
+    /// Sorts the tests topologically, failing if there's a cycle.
+    fn check_cycles(&self) -> Result<(), TestDependencyError> {
+        let count = self.names.len();
+        let mut dependents = vec![vec![]; count];
+        let mut remaining: Vec<usize> = self.prerequisites.iter().map(|p| p.len()).collect();
+        for (index, prerequisites) in self.prerequisites.iter().enumerate() {
+            for &prerequisite in prerequisites {
+                dependents[prerequisite].push(index);
+            }
+        }
+
+        let mut ready: Vec<usize> = (0..count).filter(|&index| remaining[index] == 0).collect();
+        let mut sorted = 0;
+        while let Some(index) = ready.pop() {
+            sorted += 1;
+            for &dependent in &dependents[index] {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.push(dependent);
+                }
+            }
+        }
+        if sorted == count {
+            return Ok(());
+        }
+
+        // Every test left over is in a cycle or depends on one. Following unsorted prerequisites
+        // from any of them leads around a cycle.
+        let start = (0..count)
+            .find(|&index| remaining[index] > 0)
+            .expect("some test wasn't sorted");
+        let mut path = vec![start];
+        let mut current = start;
+        loop {
+            current = *self.prerequisites[current]
+                .iter()
+                .find(|&&prerequisite| remaining[prerequisite] > 0)
+                .expect("unsorted test has an unsorted prerequisite");
+            if let Some(position) = path.iter().position(|&index| index == current) {
+                let mut cycle: Vec<_> = path[position..].to_vec();
+                cycle.push(current);
+                // Prerequisites were followed, so reverse the path to list dependents first.
+                cycle.reverse();
+                return Err(TestDependencyError::Cycle {
+                    tests: cycle
+                        .into_iter()
+                        .map(|index| {
+                            let (binary_id, name) = &self.names[index];
+                            format!("{} {}", binary_id, name)
+                        })
+                        .collect(),
+                });
+            }
+            path.push(current);
+        }
+    }
+}
+
+/// Tracks which tests have finished while a run is in progress, so that tests are only started
+/// once the tests they depend on have finished.
+#[derive(Debug)]
+pub(crate) struct DependencyStates<'d, 'a> {
+    dependencies: &'d TestDependencies,
+    // The tests that depend on each test.
+    dependents: Vec<Vec<usize>>,
+    states: Mutex<Vec<TestState<'a>>>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct TestState<'a> {
+    // The number of instances of the test that haven't finished yet.
+    remaining: usize,
+    failed: bool,
+    // The number of tests this test depends on that haven't finished yet.
+    unfinished_prerequisites: usize,
+    // Instances of the test held back until the tests it depends on finish.
+    waiting: Vec<TestInstance<'a>>,
+}
+
+/// The outcome of the tests a test depends on.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum PrerequisiteStatus {
+    /// Every test it depends on passed.
+    Passed,
+    /// Some of the tests it depends on didn't pass. Contains their binary IDs and names.
+    Failed(Vec<String>),
+}
+
+impl<'d, 'a> DependencyStates<'d, 'a> {
+    /// Creates the states for a run of the given test instances, which may include several
+    /// variants of the same test.
+    pub(crate) fn new(
+        dependencies: &'d TestDependencies,
+        test_instances: impl IntoIterator<Item = TestInstance<'a>>,
+    ) -> Self {
+        let mut states = vec![TestState::default(); dependencies.names.len()];
+        for test_instance in test_instances {
+            if let Some(index) = dependencies.index(&test_instance) {
+                states[index].remaining += 1;
+            }
+        }
+
+        let mut dependents = vec![vec![]; states.len()];
+        for (index, prerequisites) in dependencies.prerequisites.iter().enumerate() {
+            for &prerequisite in prerequisites {
+                if states[prerequisite].remaining > 0 {
+                    dependents[prerequisite].push(index);
+                    states[index].unfinished_prerequisites += 1;
+                }
+            }
+        }
+
+        Self {
+            dependencies,
+            dependents,
+            states: Mutex::new(states),
+        }
+    }
+
+    /// Returns true if the given test can be started now.
+    ///
+    /// Otherwise, the test is held back and returned by [`finish`](Self::finish) once the last
+    /// test it depends on finishes.
+    pub(crate) fn start(&self, test_instance: TestInstance<'a>) -> bool {
+        let Some(index) = self.dependencies.index(&test_instance) else {
+            return true;
+        };
+        let mut states = self.states.lock().expect("dependency states lock poisoned");
+        let state = &mut states[index];
+        if state.unfinished_prerequisites == 0 {
+            return true;
+        }
+        state.waiting.push(test_instance);
+        false
+    }
+
+    /// Returns whether the tests the given test depends on passed.
+    ///
+    /// This must only be called once the test has been started.
+    pub(crate) fn prerequisite_status(
+        &self,
+        test_instance: &TestInstance<'_>,
+    ) -> PrerequisiteStatus {
+        let Some(index) = self.dependencies.index(test_instance) else {
+            return PrerequisiteStatus::Passed;
+        };
+        let states = self.states.lock().expect("dependency states lock poisoned");
+        let failed: Vec<_> = self.dependencies.prerequisites[index]
+            .iter()
+            .filter(|&&prerequisite| states[prerequisite].failed)
+            .map(|&prerequisite| {
+                let (binary_id, name) = &self.dependencies.names[prerequisite];
+                format!("{} {}", binary_id, name)
+            })
+            .collect();
+        if failed.is_empty() {
+            PrerequisiteStatus::Passed
+        } else {
+            PrerequisiteStatus::Failed(failed)
+        }
+    }
+
+    /// Records that an instance of the given test finished.
+    ///
+    /// Returns the tests that were held back and can now be started.
+    pub(crate) fn finish(
+        &self,
+        test_instance: &TestInstance<'_>,
+        passed: bool,
+    ) -> Vec<TestInstance<'a>> {
+        let Some(index) = self.dependencies.index(test_instance) else {
+            return vec![];
+        };
+        let mut states = self.states.lock().expect("dependency states lock poisoned");
+        let state = &mut states[index];
+        state.remaining = state.remaining.saturating_sub(1);
+        state.failed |= !passed;
+        if state.remaining > 0 {
+            return vec![];
+        }
+
+        let mut ready = vec![];
+        for &dependent in &self.dependents[index] {
+            let state = &mut states[dependent];
+            state.unfinished_prerequisites -= 1;
+            if state.unfinished_prerequisites == 0 {
+                ready.append(&mut state.waiting);
+            }
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependencies(prerequisites: Vec<Vec<usize>>) -> TestDependencies {
+        let names: Vec<_> = (0..prerequisites.len())
+            .map(|index| ("my-crate".to_owned(), format!("test_{}", index)))
+            .collect();
+        TestDependencies {
+            indexes: names
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(index, name)| (name, index))
+                .collect(),
+            names,
+            prerequisites,
+        }
+    }
+
+    #[test]
+    fn dependency_cycles() {
+        // test_2 depends on test_0 and test_1, which depends on test_0; test_3 is independent.
+        let chain = dependencies(vec![vec![], vec![0], vec![0, 1], vec![]]);
+        assert!(!chain.is_empty());
+        assert!(chain.check_cycles().is_ok());
+        assert_eq!(
+            chain
+                .prerequisites_of("my-crate", "test_2")
+                .collect::<Vec<_>>(),
+            vec![("my-crate", "test_0"), ("my-crate", "test_1")],
+        );
+        assert_eq!(chain.prerequisites_of("my-crate", "missing").count(), 0);
+
+        assert!(dependencies(vec![vec![], vec![]]).is_empty());
+
+        // test_0 depends on a cycle between test_1 and test_2.
+        let cycle = dependencies(vec![vec![1], vec![2], vec![1]]);
+        match cycle.check_cycles() {
+            Err(TestDependencyError::Cycle { tests }) => assert_eq!(
+                tests,
+                vec![
+                    "my-crate test_1".to_owned(),
+                    "my-crate test_2".to_owned(),
+                    "my-crate test_1".to_owned(),
+                ],
+            ),
+            other => panic!("expected a cycle, found {:?}", other),
+        }
+    }
+}
@@ -6,19 +6,26 @@
 //! The main structure in this module is [`TestFilter`], which is created by a [`TestFilterBuilder`].
 
 use crate::{
+    changed::ChangedPackages,
     errors::RunIgnoredParseError,
+    filter_expr::{BinaryQuery, FilterExpr},
     partition::{Partitioner, PartitionerBuilder},
 };
 use aho_corasick::AhoCorasick;
 use nextest_metadata::{FilterMatch, MismatchReason};
-use std::{fmt, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
 
 /// Whether to run ignored tests.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub enum RunIgnored {
     /// Only run tests that aren't ignored.
     ///
     /// This is the default.
+    #[default]
     Default,
 
     /// Only run tests that are ignored.
@@ -35,12 +42,6 @@ impl RunIgnored {
     }
 }
 
-impl Default for RunIgnored {
-    fn default() -> Self {
-        RunIgnored::Default
-    }
-}
-
 impl fmt::Display for RunIgnored {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -71,6 +72,10 @@ pub struct TestFilterBuilder {
     run_ignored: RunIgnored,
     partitioner_builder: Option<PartitionerBuilder>,
     name_match: NameMatch,
+    // Binary ID -> test names.
+    rerun_tests: Option<HashMap<String, HashSet<String>>>,
+    changed_packages: Option<ChangedPackages>,
+    exprs: Vec<FilterExpr>,
 }
 
 #[derive(Clone, Debug)]
@@ -97,6 +102,9 @@ impl TestFilterBuilder {
             run_ignored,
             partitioner_builder,
             name_match,
+            rerun_tests: None,
+            changed_packages: None,
+            exprs: vec![],
         }
     }
 
@@ -106,13 +114,84 @@ impl TestFilterBuilder {
             run_ignored,
             partitioner_builder: None,
             name_match: NameMatch::MatchAll,
+            rerun_tests: None,
+            changed_packages: None,
+            exprs: vec![],
+        }
+    }
+
+    /// Restricts this filter to the given tests from a previous run, identified by binary ID and
+    /// test name.
+    ///
+    /// Tests must also match the other criteria in this filter. An empty list matches no tests.
+    pub fn set_rerun_tests<B: Into<String>, N: Into<String>>(
+        &mut self,
+        tests: impl IntoIterator<Item = (B, N)>,
+    ) -> &mut Self {
+        let mut rerun_tests: HashMap<String, HashSet<String>> = HashMap::new();
+        for (binary_id, name) in tests {
+            rerun_tests
+                .entry(binary_id.into())
+                .or_default()
+                .insert(name.into());
         }
+        self.rerun_tests = Some(rerun_tests);
+        self
+    }
+
+    /// Restricts this filter to tests in the given packages.
+    ///
+    /// Tests must also match the other criteria in this filter.
+    pub fn set_changed_packages(&mut self, changed_packages: ChangedPackages) -> &mut Self {
+        self.changed_packages = Some(changed_packages);
+        self
+    }
+
+    /// Restricts this filter to tests that match at least one of the given filter expressions.
+    ///
+    /// Tests must also match the other criteria in this filter. An empty list of expressions
+    /// doesn't restrict tests.
+    pub fn set_exprs(&mut self, exprs: impl IntoIterator<Item = FilterExpr>) -> &mut Self {
+        self.exprs = exprs.into_iter().collect();
+        self
     }
 
-    /// Creates a new test filter scoped to a single binary.
+    /// Returns false if no test in the given binary can match this filter, and true if some might.
+    ///
+    /// Binaries for which this returns false don't need to be run to list their tests.
+    pub fn could_match_binary(&self, query: &BinaryQuery<'_>) -> bool {
+        if let Some(changed_packages) = &self.changed_packages {
+            if !changed_packages.contains(query.package_id) {
+                return false;
+            }
+        }
+        if let Some(rerun_tests) = &self.rerun_tests {
+            if !rerun_tests.contains_key(query.binary_id) {
+                return false;
+            }
+        }
+        self.exprs.is_empty() || self.exprs.iter().any(|expr| expr.could_match_binary(query))
+    }
+
+    /// Returns true if the given binary, which turned out not to contain any tests, matches this
+    /// filter.
+    ///
+    /// Empty binaries match unless they couldn't have matched in the first place, or filter
+    /// expressions are specified and none of them match the binary. See the `empty-binary()`
+    /// predicate in [`filter_expr`](crate::filter_expr).
+    pub fn matches_empty_binary(&self, query: &BinaryQuery<'_>) -> bool {
+        self.could_match_binary(query)
+            && (self.exprs.is_empty()
+                || self
+                    .exprs
+                    .iter()
+                    .any(|expr| expr.matches_empty_binary(query)))
+    }
+
+    /// Creates a new test filter scoped to the given binary.
     ///
     /// This test filter may be stateful.
-    pub fn build(&self) -> TestFilter<'_> {
+    pub fn build<'a>(&'a self, query: BinaryQuery<'a>) -> TestFilter<'a> {
         let partitioner = self
             .partitioner_builder
             .as_ref()
@@ -120,6 +199,15 @@ impl TestFilterBuilder {
         TestFilter {
             builder: self,
             partitioner,
+            rerun_tests: self
+                .rerun_tests
+                .as_ref()
+                .map(|rerun_tests| rerun_tests.get(query.binary_id)),
+            unchanged: self
+                .changed_packages
+                .as_ref()
+                .is_some_and(|changed_packages| !changed_packages.contains(query.package_id)),
+            query,
         }
     }
 }
@@ -129,25 +217,27 @@ impl TestFilterBuilder {
 pub struct TestFilter<'builder> {
     builder: &'builder TestFilterBuilder,
     partitioner: Option<Box<dyn Partitioner>>,
+    // Some(None) means that tests are being rerun, but none from this binary.
+    rerun_tests: Option<Option<&'builder HashSet<String>>>,
+    // True if only tests in changed packages are being run, and this binary's package hasn't
+    // changed.
+    unchanged: bool,
+    query: BinaryQuery<'builder>,
 }
 
 impl<'filter> TestFilter<'filter> {
     /// Returns an enum describing the match status of this filter.
     pub fn filter_match(&mut self, test_name: &str, ignored: bool) -> FilterMatch {
         match self.builder.run_ignored {
-            RunIgnored::IgnoredOnly => {
-                if !ignored {
-                    return FilterMatch::Mismatch {
-                        reason: MismatchReason::Ignored,
-                    };
-                }
+            RunIgnored::IgnoredOnly if !ignored => {
+                return FilterMatch::Mismatch {
+                    reason: MismatchReason::Ignored,
+                };
             }
-            RunIgnored::Default => {
-                if ignored {
-                    return FilterMatch::Mismatch {
-                        reason: MismatchReason::Ignored,
-                    };
-                }
+            RunIgnored::Default if ignored => {
+                return FilterMatch::Mismatch {
+                    reason: MismatchReason::Ignored,
+                };
             }
             _ => {}
         };
@@ -162,6 +252,34 @@ impl<'filter> TestFilter<'filter> {
             };
         }
 
+        if !self.builder.exprs.is_empty() {
+            let query = self.query.to_test_query(test_name);
+            if !self
+                .builder
+                .exprs
+                .iter()
+                .any(|expr| expr.matches_test(&query))
+            {
+                return FilterMatch::Mismatch {
+                    reason: MismatchReason::Expression,
+                };
+            }
+        }
+
+        if self.unchanged {
+            return FilterMatch::Mismatch {
+                reason: MismatchReason::Unchanged,
+            };
+        }
+
+        if let Some(rerun_tests) = self.rerun_tests {
+            if !rerun_tests.is_some_and(|rerun_tests| rerun_tests.contains(test_name)) {
+                return FilterMatch::Mismatch {
+                    reason: MismatchReason::Rerun,
+                };
+            }
+        }
+
         let partition_match = match &mut self.partitioner {
             Some(partitioner) => partitioner.test_matches(test_name),
             None => true,
@@ -179,6 +297,8 @@ impl<'filter> TestFilter<'filter> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use guppy::PackageId;
+    use nextest_metadata::BuildPlatform;
     use proptest::{collection::vec, prelude::*};
 
     proptest! {
@@ -186,7 +306,8 @@ mod tests {
         fn proptest_empty(test_names in vec(any::<String>(), 0..16)) {
             let patterns: &[String] = &[];
             let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, patterns);
-            let mut single_filter = test_filter.build();
+            let package_id = fake_package_id();
+            let mut single_filter = test_filter.build(binary_query("fake-id", &package_id));
             for test_name in test_names {
                 prop_assert!(single_filter.filter_match(&test_name, false).is_match());
             }
@@ -196,7 +317,8 @@ mod tests {
         #[test]
         fn proptest_exact(test_names in vec(any::<String>(), 0..16)) {
             let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &test_names);
-            let mut single_filter = test_filter.build();
+            let package_id = fake_package_id();
+            let mut single_filter = test_filter.build(binary_query("fake-id", &package_id));
             for test_name in test_names {
                 prop_assert!(single_filter.filter_match(&test_name, false).is_match());
             }
@@ -215,7 +337,8 @@ mod tests {
             }
 
             let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &patterns);
-            let mut single_filter = test_filter.build();
+            let package_id = fake_package_id();
+            let mut single_filter = test_filter.build(binary_query("fake-id", &package_id));
             for test_name in test_names {
                 prop_assert!(single_filter.filter_match(&test_name, false).is_match());
             }
@@ -228,14 +351,144 @@ mod tests {
             prefix in any::<String>(),
             suffix in any::<String>(),
         ) {
-            prop_assume!(!substring.is_empty() && !(prefix.is_empty() && suffix.is_empty()));
+            prop_assume!(!substring.is_empty() && (!prefix.is_empty() || !suffix.is_empty()));
             let pattern = prefix + &substring + &suffix;
             let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &[&pattern]);
-            let mut single_filter = test_filter.build();
+            let package_id = fake_package_id();
+            let mut single_filter = test_filter.build(binary_query("fake-id", &package_id));
             prop_assert!(!single_filter.filter_match(&substring, false).is_match());
         }
     }
 
+    #[test]
+    fn rerun_tests() {
+        let mut test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["foo"]);
+        test_filter.set_rerun_tests([
+            ("bin-a", "foo::one"),
+            ("bin-a", "bar"),
+            ("bin-b", "foo::two"),
+        ]);
+
+        let package_id = fake_package_id();
+        let mut filter_a = test_filter.build(binary_query("bin-a", &package_id));
+        assert!(filter_a.filter_match("foo::one", false).is_match());
+        assert_eq!(
+            filter_a.filter_match("foo::two", false),
+            FilterMatch::Mismatch {
+                reason: MismatchReason::Rerun
+            },
+            "only tests from this binary are rerun"
+        );
+        assert_eq!(
+            filter_a.filter_match("bar", false),
+            FilterMatch::Mismatch {
+                reason: MismatchReason::String
+            },
+            "string filters still apply"
+        );
+
+        let mut filter_c = test_filter.build(binary_query("bin-c", &package_id));
+        assert!(
+            !filter_c.filter_match("foo::one", false).is_match(),
+            "binaries without tests to rerun match nothing"
+        );
+        assert!(test_filter.could_match_binary(&binary_query("bin-a", &package_id)));
+        assert!(
+            !test_filter.could_match_binary(&binary_query("bin-c", &package_id)),
+            "binaries without tests to rerun don't need to be listed"
+        );
+    }
+
+    #[test]
+    fn changed_packages() {
+        let changed_id = PackageId::new("changed");
+        let mut test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["foo"]);
+        test_filter.set_changed_packages([changed_id.clone()].into_iter().collect());
+
+        let mut changed_filter = test_filter.build(binary_query("bin-a", &changed_id));
+        assert!(changed_filter.filter_match("foo", false).is_match());
+        assert_eq!(
+            changed_filter.filter_match("bar", false),
+            FilterMatch::Mismatch {
+                reason: MismatchReason::String
+            },
+            "string filters still apply"
+        );
+
+        let package_id = fake_package_id();
+        let mut unchanged_filter = test_filter.build(binary_query("bin-b", &package_id));
+        assert_eq!(
+            unchanged_filter.filter_match("foo", false),
+            FilterMatch::Mismatch {
+                reason: MismatchReason::Unchanged
+            },
+            "tests in unchanged packages don't match"
+        );
+        assert!(
+            !test_filter.could_match_binary(&binary_query("bin-b", &package_id)),
+            "binaries in unchanged packages don't need to be listed"
+        );
+    }
+
+    #[test]
+    fn exprs() {
+        let mut test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["foo"]);
+        test_filter.set_exprs([
+            "binary(bin-a) & test(one)".parse().unwrap(),
+            "binary(bin-b)".parse().unwrap(),
+        ]);
+
+        let package_id = fake_package_id();
+        let mut filter_a = test_filter.build(binary_query("bin-a", &package_id));
+        assert!(filter_a.filter_match("foo::one", false).is_match());
+        assert_eq!(
+            filter_a.filter_match("foo::two", false),
+            FilterMatch::Mismatch {
+                reason: MismatchReason::Expression
+            },
+            "tests must match an expression"
+        );
+        let mut filter_b = test_filter.build(binary_query("bin-b", &package_id));
+        assert!(
+            filter_b.filter_match("foo::two", false).is_match(),
+            "tests can match any expression"
+        );
+
+        assert!(test_filter.could_match_binary(&binary_query("bin-a", &package_id)));
+        assert!(test_filter.could_match_binary(&binary_query("bin-b", &package_id)));
+        assert!(
+            !test_filter.could_match_binary(&binary_query("bin-c", &package_id)),
+            "binaries no expression can match don't need to be listed"
+        );
+
+        assert!(
+            !test_filter.matches_empty_binary(&binary_query("bin-a", &package_id)),
+            "empty binaries don't match test() predicates"
+        );
+        assert!(test_filter.matches_empty_binary(&binary_query("bin-b", &package_id)));
+
+        let mut test_filter = TestFilterBuilder::any(RunIgnored::Default);
+        assert!(
+            test_filter.matches_empty_binary(&binary_query("bin-a", &package_id)),
+            "empty binaries match without expressions"
+        );
+        test_filter.set_exprs(["empty-binary()".parse().unwrap()]);
+        assert!(test_filter.matches_empty_binary(&binary_query("bin-a", &package_id)));
+    }
+
+    fn fake_package_id() -> PackageId {
+        PackageId::new("fake-package")
+    }
+
+    fn binary_query<'a>(binary_id: &'a str, package_id: &'a PackageId) -> BinaryQuery<'a> {
+        BinaryQuery {
+            package_id,
+            package_name: "fake-package",
+            binary_id,
+            platform: BuildPlatform::Target,
+        }
+    }
+
     // /// Creates a fake test binary instance.
     // fn make_test_binary() -> TestBinary {
     //     TestBinary {
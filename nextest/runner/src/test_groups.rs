@@ -0,0 +1,463 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Test groups, and the fixtures shared by the tests in them.
+//!
+//! Tests are assigned to a group through the `test-group` setting of an override, and groups are
+//! defined at the top level of the config:
+//!
+//! ```toml
+//! [test-groups.db]
+//! setup = ["scripts/start-db.sh"]
+//! teardown = ["scripts/stop-db.sh"]
+//!
+//! [[profile.default.overrides]]
+//! filter = "package(db-tests)"
+//! test-group = "db"
+//! ```
+//!
+//! A group's `setup` command is run right before the first test in the group starts, and tests in
+//! the group wait for it to finish. Its `teardown` command is run once the last test in the group
+//! finishes, or at the end of the run if the run was canceled before then. This lets tests share an
+//! expensive fixture, such as a database server, that's managed by nextest.
+//!
+//! Both commands are run in the workspace root, with [`NEXTEST_TEST_GROUP_ENV`] set to the name of
+//! the group. If the setup command fails, the tests in the group fail without being run, and the
+//! teardown command isn't run. The teardown command is run whether or not the tests passed.
+//!
+//! The setup command can pass environment variables to the tests in the group and to the teardown
+//! command, such as the ID of a container it started, by writing them as `KEY=value` lines to the
+//! file at [`NEXTEST_ENV`], in the same format as [env files](crate::env_file).
+//!
+//! If the group sets a `timeout`, each command is killed once it has run for that long, and is
+//! treated as having failed.
+
+use crate::{env_file::parse_env_file, stopwatch::ClockInstant};
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::cmd;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    sync::Mutex,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The environment variable nextest sets to the name of the test group of each test, and while
+/// running the setup and teardown commands of a group.
+///
+/// Tests that aren't in a group see `@global`.
+pub const NEXTEST_TEST_GROUP_ENV: &str = "NEXTEST_TEST_GROUP";
+
+/// The value of [`NEXTEST_TEST_GROUP_ENV`] for tests that aren't in a group.
+pub const GLOBAL_TEST_GROUP: &str = "@global";
+
+/// The environment variable nextest sets while running the setup command of a group, to the path of
+/// a file that the command can write environment variables to.
+pub const NEXTEST_ENV: &str = "NEXTEST_ENV";
+
+/// The configuration for a test group.
+///
+/// Obtained through [`NextestProfile::test_groups`](crate::config::NextestProfile::test_groups).
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestGroupConfig {
+    #[serde(default)]
+    setup: Option<Vec<String>>,
+    #[serde(default)]
+    teardown: Option<Vec<String>>,
+    #[serde(default, with = "humantime_serde")]
+    timeout: Option<Duration>,
+}
+
+impl TestGroupConfig {
+    /// Returns the command run before the first test in the group starts, if specified.
+    pub fn setup(&self) -> Option<&[String]> {
+        self.setup.as_deref()
+    }
+
+    /// Returns the command run after the last test in the group finishes, if specified.
+    pub fn teardown(&self) -> Option<&[String]> {
+        self.teardown.as_deref()
+    }
+
+    /// Returns how long the setup and teardown commands may each run for before they're killed, if
+    /// specified.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+/// A command run for a test group.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TestGroupHook {
+    /// The setup command.
+    Setup,
+
+    /// The teardown command.
+    Teardown,
+}
+
+impl fmt::Display for TestGroupHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestGroupHook::Setup => write!(f, "setup"),
+            TestGroupHook::Teardown => write!(f, "teardown"),
+        }
+    }
+}
+
+/// Tracks the tests in each group during a run, and runs setup and teardown commands as needed.
+#[derive(Debug)]
+pub(crate) struct TestGroupStates<'a> {
+    workspace_root: Utf8PathBuf,
+    groups: BTreeMap<&'a str, Mutex<GroupState<'a>>>,
+}
+
+#[derive(Debug)]
+struct GroupState<'a> {
+    config: &'a TestGroupConfig,
+    // The number of tests in the group that haven't finished yet.
+    remaining: usize,
+    setup: SetupState,
+    // The group slots held by running tests.
+    running_slots: BTreeSet<usize>,
+    torn_down: bool,
+}
+
+#[derive(Clone, Debug)]
+enum SetupState {
+    NotRun,
+    // Contains the environment variables written by the setup command.
+    Succeeded(Vec<(String, String)>),
+    Failed(String),
+}
+
+impl<'a> TestGroupStates<'a> {
+    /// Creates a new tracker, given the configs of all groups and the number of tests to run in
+    /// each of them. Groups without any tests to run are ignored.
+    pub(crate) fn new(
+        workspace_root: &Utf8Path,
+        configs: &'a BTreeMap<String, TestGroupConfig>,
+        counts: &BTreeMap<&str, usize>,
+    ) -> Self {
+        let groups = configs
+            .iter()
+            .filter_map(|(name, config)| {
+                let remaining = counts.get(name.as_str()).copied().unwrap_or(0);
+                let state = GroupState {
+                    config,
+                    remaining,
+                    setup: SetupState::NotRun,
+                    running_slots: BTreeSet::new(),
+                    torn_down: false,
+                };
+                (remaining > 0).then(|| (name.as_str(), Mutex::new(state)))
+            })
+            .collect();
+        Self {
+            workspace_root: workspace_root.to_owned(),
+            groups,
+        }
+    }
+
+    /// Called before a test in the group starts. Runs the setup command if this is the first test,
+    /// calling `on_failure` with the error if it fails.
+    ///
+    /// Returns the group slot of the test: the smallest index, starting from 0, that isn't held by
+    /// another running test in the group. Returns an error if the setup command failed.
+    pub(crate) fn start_test(
+        &self,
+        group: &str,
+        on_failure: impl FnOnce(TestGroupHook, &str),
+    ) -> Result<usize, String> {
+        let mut state = self.lock(group);
+        if let SetupState::NotRun = state.setup {
+            // The lock is held while the setup command runs, so that other tests in the group wait
+            // for it.
+            state.setup = match self.run_hook(group, TestGroupHook::Setup, state.config, &[]) {
+                Ok(env) => SetupState::Succeeded(env),
+                Err(error) => {
+                    on_failure(TestGroupHook::Setup, &error);
+                    SetupState::Failed(error)
+                }
+            };
+        }
+        if let SetupState::Failed(error) = &state.setup {
+            return Err(format!(
+                "setup for test group '{}' failed: {}",
+                group, error
+            ));
+        }
+
+        let slot = (0..)
+            .find(|slot| !state.running_slots.contains(slot))
+            .expect("there are fewer running tests than slots");
+        state.running_slots.insert(slot);
+        Ok(slot)
+    }
+
+    /// Returns the environment variables written by the group's setup command, to be set for the
+    /// tests in the group.
+    pub(crate) fn setup_env(&self, group: &str) -> Vec<(String, String)> {
+        match &self.lock(group).setup {
+            SetupState::Succeeded(env) => env.clone(),
+            SetupState::NotRun | SetupState::Failed(_) => vec![],
+        }
+    }
+
+    /// Called once a test in the group finishes or is skipped, with its group slot if it was
+    /// started. Runs the teardown command if this was the last test, calling `on_failure` with the
+    /// error if it fails.
+    pub(crate) fn finish_test(
+        &self,
+        group: &str,
+        slot: Option<usize>,
+        on_failure: impl FnOnce(TestGroupHook, &str),
+    ) {
+        let mut state = self.lock(group);
+        if let Some(slot) = slot {
+            state.running_slots.remove(&slot);
+        }
+        state.remaining = state.remaining.saturating_sub(1);
+        if state.remaining == 0 {
+            self.teardown(group, &mut state, on_failure);
+        }
+    }
+
+    /// Runs the teardown command for groups that were set up but not torn down, for example because
+    /// the run was canceled.
+    pub(crate) fn finish_run(&self, mut on_failure: impl FnMut(&'a str, TestGroupHook, &str)) {
+        for (&group, state) in &self.groups {
+            let mut state = state.lock().expect("group lock poisoned");
+            self.teardown(group, &mut state, |hook, error| {
+                on_failure(group, hook, error)
+            });
+        }
+    }
+
+    // ---
+    // Helper methods
+    // ---
+
+    fn lock(&self, group: &str) -> std::sync::MutexGuard<'_, GroupState<'a>> {
+        self.groups
+            .get(group)
+            .unwrap_or_else(|| panic!("test group '{}' has tests to run", group))
+            .lock()
+            .expect("group lock poisoned")
+    }
+
+    fn teardown(
+        &self,
+        group: &str,
+        state: &mut GroupState<'a>,
+        on_failure: impl FnOnce(TestGroupHook, &str),
+    ) {
+        let env = match &state.setup {
+            SetupState::Succeeded(env) if !state.torn_down => env.clone(),
+            _ => return,
+        };
+        state.torn_down = true;
+        if let Err(error) = self.run_hook(group, TestGroupHook::Teardown, state.config, &env) {
+            on_failure(TestGroupHook::Teardown, &error);
+        }
+    }
+
+    /// Runs a command for the group with the given environment variables, returning the variables
+    /// it wrote to the file at [`NEXTEST_ENV`].
+    fn run_hook(
+        &self,
+        group: &str,
+        hook: TestGroupHook,
+        config: &TestGroupConfig,
+        env: &[(String, String)],
+    ) -> Result<Vec<(String, String)>, String> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        let command = match hook {
+            TestGroupHook::Setup => config.setup(),
+            TestGroupHook::Teardown => config.teardown(),
+        };
+        let (program, args) = match command.and_then(|command| command.split_first()) {
+            Some(command) => command,
+            None => return Ok(vec![]),
+        };
+
+        // Output is written to a file rather than a pipe, so that processes left running in the
+        // background by a command that timed out don't keep nextest waiting for its output.
+        let hook_dir = std::env::temp_dir().join(format!("nextest-{}-{}", hook, Uuid::new_v4()));
+        std::fs::create_dir_all(&hook_dir).map_err(|error| {
+            format!(
+                "failed to create directory '{}': {}",
+                hook_dir.display(),
+                error
+            )
+        })?;
+        let output_path = hook_dir.join("output");
+        let env_path = hook_dir.join("env");
+        let res = (|| {
+            let mut expression = cmd(program, args)
+                .dir(&self.workspace_root)
+                .env(NEXTEST_TEST_GROUP_ENV, group)
+                .stdin_null()
+                .stderr_to_stdout()
+                .stdout_path(&output_path)
+                .unchecked();
+            for (key, value) in env {
+                expression = expression.env(key, value);
+            }
+            if hook == TestGroupHook::Setup {
+                expression = expression.env(NEXTEST_ENV, &env_path);
+            }
+            let handle = expression
+                .start()
+                .map_err(|error| format!("failed to run '{}': {}", program, error))?;
+
+            let deadline = config
+                .timeout()
+                .map(|timeout| (ClockInstant::now() + timeout, timeout));
+            let output = loop {
+                match handle.try_wait() {
+                    Ok(Some(output)) => break output.clone(),
+                    Ok(None) => {}
+                    Err(error) => {
+                        return Err(format!("failed to wait for '{}': {}", program, error))
+                    }
+                }
+                if let Some((deadline, timeout)) = deadline {
+                    if ClockInstant::now() >= deadline {
+                        let _ = handle.kill();
+                        return Err(format!(
+                            "'{}' timed out after {:?}\n{}",
+                            program,
+                            timeout,
+                            read_output(&output_path),
+                        ));
+                    }
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            };
+            if !output.status.success() {
+                return Err(format!(
+                    "'{}' exited with {}\n{}",
+                    program,
+                    output.status,
+                    read_output(&output_path),
+                ));
+            }
+
+            match std::fs::read_to_string(&env_path) {
+                Ok(contents) => parse_env_file(&contents).map_err(|(line, message)| {
+                    format!(
+                        "'{}' wrote an invalid {} on line {}: {}",
+                        program, NEXTEST_ENV, line, message
+                    )
+                }),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+                Err(error) => Err(format!(
+                    "failed to read {} of '{}': {}",
+                    NEXTEST_ENV, program, error
+                )),
+            }
+        })();
+        let _ = std::fs::remove_dir_all(&hook_dir);
+        res
+    }
+}
+
+fn read_output(path: &std::path::Path) -> String {
+    let output = std::fs::read(path).unwrap_or_default();
+    String::from_utf8_lossy(&output).trim_end().to_owned()
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_and_teardown() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let sh = |script: &str| Some(vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()]);
+        let configs: BTreeMap<_, _> = [
+            (
+                "db".to_owned(),
+                TestGroupConfig {
+                    setup: sh("echo \"$NEXTEST_TEST_GROUP\" >> hooks.log; \
+                        echo CONTAINER_ID=abc123 > \"$NEXTEST_ENV\""),
+                    teardown: sh("echo \"teardown $CONTAINER_ID\" >> hooks.log"),
+                    timeout: None,
+                },
+            ),
+            (
+                "broken".to_owned(),
+                TestGroupConfig {
+                    setup: sh("echo no database; exit 1"),
+                    teardown: sh("echo teardown-broken >> hooks.log"),
+                    timeout: None,
+                },
+            ),
+            (
+                "slow".to_owned(),
+                TestGroupConfig {
+                    setup: sh("echo starting; sleep 10"),
+                    teardown: None,
+                    timeout: Some(Duration::from_millis(100)),
+                },
+            ),
+            ("unused".to_owned(), TestGroupConfig::default()),
+        ]
+        .into_iter()
+        .collect();
+        let counts = [("db", 2), ("broken", 1), ("slow", 1)]
+            .into_iter()
+            .collect();
+        let states = TestGroupStates::new(&root, &configs, &counts);
+        let log = || std::fs::read_to_string(root.join("hooks.log")).unwrap_or_default();
+        let no_failure = |hook, error: &str| panic!("{} failed: {}", hook, error);
+
+        let first = states.start_test("db", no_failure).expect("setup succeeds");
+        let second = states.start_test("db", no_failure).expect("setup succeeds");
+        assert_eq!((first, second), (0, 1));
+        assert_eq!(log(), "db\n", "setup is run once");
+        assert_eq!(
+            states.setup_env("db"),
+            vec![("CONTAINER_ID".to_owned(), "abc123".to_owned())]
+        );
+
+        states.finish_test("db", Some(first), no_failure);
+        assert_eq!(log(), "db\n", "teardown waits for the last test");
+        states.finish_test("db", Some(second), no_failure);
+        assert_eq!(
+            log(),
+            "db\nteardown abc123\n",
+            "teardown sees the setup's env"
+        );
+
+        let mut failures = vec![];
+        states
+            .start_test("broken", |hook, error| {
+                failures.push((hook, error.to_owned()))
+            })
+            .expect_err("setup fails");
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, TestGroupHook::Setup);
+        assert!(failures[0].1.contains("no database"), "output is included");
+
+        let error = states
+            .start_test("slow", |_, _| {})
+            .expect_err("setup times out");
+        assert!(error.contains("timed out after 100ms"), "{}", error);
+        assert!(error.contains("starting"), "output is included: {}", error);
+
+        states.finish_run(|group, hook, error| panic!("{} {} failed: {}", group, hook, error));
+        assert_eq!(
+            log(),
+            "db\nteardown abc123\n",
+            "failed setups aren't torn down"
+        );
+    }
+}
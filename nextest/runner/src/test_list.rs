@@ -4,26 +4,201 @@
 //! Support for building and querying lists of test instances.
 //!
 //! The main data structure in this module is [`TestList`].
+//!
+//! # Dynamic test discovery
+//!
+//! Test binaries are listed by running them with `--list --format terse`, which prints out one
+//! `<test name>: test` line per test. Custom harnesses (`harness = false`) that generate tests at
+//! runtime, for example one per fixture file, can instead opt into a structured protocol so that
+//! each generated test is scheduled and reported as a first-class test:
+//!
+//! * While listing tests, nextest sets [`DISCOVERY_PROTOCOL_ENV`] to `1`.
+//! * A harness that supports the protocol prints [`DISCOVERY_PROTOCOL_HEADER`] as its first line,
+//!   followed by one JSON object per line of the form `{"name": "<test name>", "ignored": false}`.
+//!   The `ignored` field is optional and defaults to false. An optional `ignore_reason` field
+//!   describes why a test is ignored.
+//! * Since the output includes ignored tests, nextest doesn't run the binary with `--ignored`.
+//!
+//! Each test is then run as `<binary> <test name> --exact --nocapture`, as with any other test.
 
 mod output_format;
 pub use output_format::*;
 
 use crate::{
-    errors::{FromMessagesError, ParseTestListError, WriteTestListError},
+    build_meta::RustBuildMeta,
+    cache::NextestCache,
+    config::{MissingCwd, NextestProfile},
+    container::ContainerRule,
+    coverage::CoverageSelection,
+    environment::{BinaryCommand, ExecutionEnvironment, Local},
+    errors::{
+        EmptyBinariesParseError, FromMessagesError, FromSummaryError, ParseTestListError,
+        SyntheticTestError, WriteTestListError,
+    },
+    filter_expr::{BinaryQuery, FilterExpr, TestQuery},
     helpers::write_test_name,
+    llvm_coverage::LLVM_PROFILE_FILE_ENV,
+    required_binaries::RequiredBinary,
+    selector::TestSelection,
+    target_runner::{PlatformRunner, TargetRunner},
     test_filter::TestFilterBuilder,
+    valgrind::Valgrind,
 };
 use camino::{Utf8Path, Utf8PathBuf};
 use cargo_metadata::Message;
 use duct::{cmd, Expression};
 use guppy::{
-    graph::{PackageGraph, PackageMetadata},
+    graph::{BuildTargetId, PackageGraph, PackageMetadata},
     PackageId,
 };
-use nextest_metadata::{RustTestCaseSummary, RustTestSuiteSummary, TestListSummary};
+use nextest_metadata::{
+    BinaryListSummary, BuildPlatform, FilterMatch, MismatchReason, RustTestBinarySummary,
+    RustTestCaseSummary, RustTestSuiteSummary, TestListSummary,
+};
 use once_cell::sync::OnceCell;
 use owo_colors::{OwoColorize, Style};
-use std::{collections::BTreeMap, io, io::Write, path::Path};
+use serde::Deserialize;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt, io,
+    io::Write,
+    str::FromStr,
+    sync::Arc,
+};
+
+/// The environment variable set while listing tests, indicating that nextest supports the
+/// [dynamic test discovery protocol](self#dynamic-test-discovery).
+pub const DISCOVERY_PROTOCOL_ENV: &str = "NEXTEST_DISCOVERY_PROTOCOL";
+
+/// The first line of list output that uses the
+/// [dynamic test discovery protocol](self#dynamic-test-discovery).
+pub const DISCOVERY_PROTOCOL_HEADER: &str = "nextest-discovery v1";
+
+/// Settings for running test binaries to list the tests within them.
+///
+/// Accepted as input to [`TestList::new_with_settings`].
+#[derive(Clone, Debug, Default)]
+pub struct ListSettings<'a> {
+    cache: Option<&'a NextestCache>,
+    ignore_reasons: bool,
+    empty_binaries: EmptyBinaries,
+    build_meta: RustBuildMeta,
+    target_runner: TargetRunner,
+    missing_cwd: MissingCwd,
+    discard_coverage: bool,
+}
+
+impl<'a> ListSettings<'a> {
+    /// Reuses the `--list` output of binaries that haven't changed since they were last listed.
+    pub fn set_cache(&mut self, cache: &'a NextestCache) -> &mut Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Collects the reasons tests are ignored, as specified through `#[ignore = "reason"]`.
+    ///
+    /// This relies on libtest's unstable JSON output format, which is enabled by setting
+    /// `RUSTC_BOOTSTRAP=1` while listing tests. If a binary fails to list tests in this format,
+    /// for example because it uses a custom harness, it is listed in the regular format instead.
+    pub fn set_ignore_reasons(&mut self, ignore_reasons: bool) -> &mut Self {
+        self.ignore_reasons = ignore_reasons;
+        self
+    }
+
+    /// Sets what to do with test binaries that don't contain any tests.
+    ///
+    /// This only applies to binaries matched by the `empty-binary()` filter predicate, or to all
+    /// empty binaries if no filter expressions are specified. Other empty binaries are always
+    /// skipped.
+    pub fn set_empty_binaries(&mut self, empty_binaries: EmptyBinaries) -> &mut Self {
+        self.empty_binaries = empty_binaries;
+        self
+    }
+
+    /// Sets information about how test binaries were built, such as the Rust library directory for
+    /// each platform.
+    ///
+    /// The output directories of the binaries in the list are added to this.
+    pub fn set_build_meta(&mut self, build_meta: RustBuildMeta) -> &mut Self {
+        self.build_meta = build_meta;
+        self
+    }
+
+    /// Sets the runners used to run test binaries, both while listing and running tests.
+    pub fn set_target_runner(&mut self, target_runner: TargetRunner) -> &mut Self {
+        self.target_runner = target_runner;
+        self
+    }
+
+    /// Returns the runners used to run test binaries.
+    pub fn target_runner(&self) -> &TargetRunner {
+        &self.target_runner
+    }
+
+    /// Sets what to do if the directory tests in a package are run in doesn't exist.
+    ///
+    /// By default, creating the test list fails.
+    pub fn set_missing_cwd(&mut self, missing_cwd: MissingCwd) -> &mut Self {
+        self.missing_cwd = missing_cwd;
+        self
+    }
+
+    /// Discards the raw coverage profiles that instrumented binaries write while listing tests.
+    ///
+    /// Otherwise, they're written to the directory each binary is listed in.
+    pub fn set_discard_coverage(&mut self, discard_coverage: bool) -> &mut Self {
+        self.discard_coverage = discard_coverage;
+        self
+    }
+}
+
+/// What to do with test binaries that don't contain any tests.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum EmptyBinaries {
+    /// Show empty binaries in the test list.
+    ///
+    /// This is the default.
+    #[default]
+    List,
+
+    /// Leave empty binaries out of the test list.
+    Hide,
+
+    /// Fail creating the test list if any binaries are empty.
+    Fail,
+}
+
+impl EmptyBinaries {
+    /// String representations of all known variants.
+    pub fn variants() -> &'static [&'static str] {
+        &["list", "hide", "fail"]
+    }
+}
+
+impl fmt::Display for EmptyBinaries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmptyBinaries::List => write!(f, "list"),
+            EmptyBinaries::Hide => write!(f, "hide"),
+            EmptyBinaries::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+impl FromStr for EmptyBinaries {
+    type Err = EmptyBinariesParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "list" => EmptyBinaries::List,
+            "hide" => EmptyBinaries::Hide,
+            "fail" => EmptyBinaries::Fail,
+            other => return Err(EmptyBinariesParseError::new(other)),
+        };
+        Ok(val)
+    }
+}
 
 /// A Rust test binary built by Cargo. This artifact hasn't been run yet so there's no information
 /// about the tests within it.
@@ -47,6 +222,9 @@ pub struct RustTestArtifact<'g> {
     /// The working directory that this test should be executed in. If None, the current directory
     /// will not be changed.
     pub cwd: Utf8PathBuf,
+
+    /// The platform this binary was built for.
+    pub build_platform: BuildPlatform,
 }
 
 impl<'g> RustTestArtifact<'g> {
@@ -80,19 +258,24 @@ impl<'g> RustTestArtifact<'g> {
                             })
                             .to_path_buf();
 
-                        // Construct the binary ID from the package and build target.
-                        let mut binary_id = package.name().to_owned();
-                        if artifact.target.name != package.name() {
-                            binary_id.push_str("::");
-                            binary_id.push_str(&artifact.target.name);
-                        }
-
+                        // Procedural macros are always built for the host, even when
+                        // cross-compiling.
+                        let build_platform =
+                            if artifact.target.kind.iter().any(|kind| kind == "proc-macro") {
+                                BuildPlatform::Host
+                            } else {
+                                BuildPlatform::Target
+                            };
+
+                        let kind = artifact.target.kind.first().map_or("lib", String::as_str);
+                        let binary_id = make_binary_id(package.name(), kind, &artifact.target.name);
                         binaries.push(RustTestArtifact {
                             binary_id,
                             package,
                             binary_path: binary,
                             binary_name: artifact.target.name,
                             cwd,
+                            build_platform,
                         })
                     }
                 }
@@ -104,18 +287,351 @@ impl<'g> RustTestArtifact<'g> {
 
         Ok(binaries)
     }
+
+    /// Returns a [`BinaryQuery`] for this artifact, used to filter tests within it.
+    pub fn to_query(&self) -> BinaryQuery<'_> {
+        BinaryQuery {
+            package_id: self.package.id(),
+            package_name: self.package.name(),
+            binary_id: &self.binary_id,
+            platform: self.build_platform,
+        }
+    }
+}
+
+/// A test target that Cargo didn't build, because features it requires weren't enabled.
+///
+/// Cargo skips such targets silently unless they're selected by name. Only library, binary and
+/// integration test targets in packages that had tests built are considered, since those are the
+/// targets tested by default.
+#[derive(Clone, Debug)]
+pub struct SkippedTestTarget<'g> {
+    /// The unique identifier the test binary for this target would have had.
+    pub binary_id: String,
+
+    /// Metadata for the package this target is a part of.
+    pub package: PackageMetadata<'g>,
+
+    /// The name of the target.
+    pub target_name: String,
+
+    /// The features required by the target that weren't enabled.
+    pub missing_features: Vec<String>,
+}
+
+impl<'g> SkippedTestTarget<'g> {
+    /// Parses Cargo messages from the given `BufRead` and returns the test targets that weren't
+    /// built because their required features weren't enabled.
+    pub fn from_messages(
+        graph: &'g PackageGraph,
+        reader: impl io::BufRead,
+    ) -> Result<Vec<Self>, FromMessagesError> {
+        // Package ID -> features enabled for it.
+        let mut enabled_features: BTreeMap<PackageId, BTreeSet<String>> = BTreeMap::new();
+        // Package ID -> names of targets built as tests.
+        let mut test_targets: BTreeMap<PackageId, BTreeSet<String>> = BTreeMap::new();
+
+        for message in Message::parse_stream(reader) {
+            let message = message.map_err(FromMessagesError::ReadMessages)?;
+            if let Message::CompilerArtifact(artifact) = message {
+                let package_id = PackageId::new(artifact.package_id.repr);
+                if artifact.profile.test {
+                    test_targets
+                        .entry(package_id.clone())
+                        .or_default()
+                        .insert(artifact.target.name);
+                }
+                enabled_features
+                    .entry(package_id)
+                    .or_default()
+                    .extend(artifact.features);
+            }
+        }
+
+        let mut skipped = vec![];
+        for (package_id, built) in &test_targets {
+            let package = graph
+                .metadata(package_id)
+                .map_err(FromMessagesError::PackageGraph)?;
+            let enabled = &enabled_features[package_id];
+            for target in package.build_targets() {
+                let kind = match target.id() {
+                    BuildTargetId::Binary(_) => "bin",
+                    BuildTargetId::Test(_) => "test",
+                    // Library targets can't have required features.
+                    _ => continue,
+                };
+                if built.contains(target.name()) {
+                    continue;
+                }
+                // Features of dependencies ("dep/feature") aren't reported by Cargo, so they can't
+                // be checked.
+                let missing_features: Vec<_> = target
+                    .required_features()
+                    .iter()
+                    .filter(|feature| !feature.contains('/') && !enabled.contains(*feature))
+                    .cloned()
+                    .collect();
+                if !missing_features.is_empty() {
+                    skipped.push(SkippedTestTarget {
+                        binary_id: make_binary_id(package.name(), kind, target.name()),
+                        package,
+                        target_name: target.name().to_owned(),
+                        missing_features,
+                    });
+                }
+            }
+        }
+
+        Ok(skipped)
+    }
+}
+
+// Constructs the binary ID from the package name, and the kind and name of the build target (as
+// reported by Cargo, e.g. "lib" or "test"). The ID doesn't depend on where the binary is, so
+// binaries can be matched up across target directories and machines:
+//
+// * library targets: `<package name>`
+// * integration tests: `<package name>::<target name>`
+// * other targets: `<package name>::<kind>/<target name>`, e.g. `my-package::bin/my-binary`
+fn make_binary_id(package_name: &str, kind: &str, target_name: &str) -> String {
+    match kind {
+        "lib" | "rlib" | "dylib" | "cdylib" | "staticlib" | "proc-macro" => package_name.to_owned(),
+        "test" => format!("{}::{}", package_name, target_name),
+        other => format!("{}::{}/{}", package_name, other, target_name),
+    }
+}
+
+/// List of test binaries built by Cargo, without running them to find the tests within them.
+///
+/// This is useful for distributing test binaries, rather than individual tests, across machines.
+#[derive(Clone, Debug)]
+pub struct BinaryList<'g> {
+    rust_binaries: Vec<RustTestArtifact<'g>>,
+    styles: Box<Styles>,
+}
+
+impl<'g> BinaryList<'g> {
+    /// Creates a new binary list from the given artifacts, keeping the binaries that the filter
+    /// could match tests in.
+    pub fn new(
+        test_artifacts: impl IntoIterator<Item = RustTestArtifact<'g>>,
+        filter: &TestFilterBuilder,
+    ) -> Self {
+        let mut rust_binaries: Vec<_> = test_artifacts
+            .into_iter()
+            .filter(|test_binary| filter.could_match_binary(&test_binary.to_query()))
+            .collect();
+        rust_binaries.sort_by(|a, b| a.binary_id.cmp(&b.binary_id));
+        Self {
+            rust_binaries,
+            styles: Box::new(Styles::default()),
+        }
+    }
+
+    /// Colorizes output.
+    pub fn colorize(&mut self) {
+        self.styles.colorize();
+    }
+
+    /// Returns the number of binaries in this list.
+    pub fn binary_count(&self) -> usize {
+        self.rust_binaries.len()
+    }
+
+    /// Iterates over the binaries in this list, sorted by binary ID.
+    pub fn iter(&self) -> impl Iterator<Item = &RustTestArtifact<'g>> + '_ {
+        self.rust_binaries.iter()
+    }
+
+    /// Constructs a serializable summary for this binary list.
+    pub fn to_summary(&self) -> BinaryListSummary {
+        let mut summary = BinaryListSummary::default();
+        summary.rust_binaries = self
+            .rust_binaries
+            .iter()
+            .map(|test_binary| {
+                let binary = RustTestBinarySummary {
+                    binary_id: test_binary.binary_id.clone(),
+                    binary_name: test_binary.binary_name.clone(),
+                    package_id: test_binary.package.id().repr().to_owned(),
+                    binary_path: test_binary.binary_path.clone(),
+                    build_platform: test_binary.build_platform,
+                };
+                (test_binary.binary_id.clone(), binary)
+            })
+            .collect();
+        summary
+    }
+
+    /// Outputs this list to the given writer.
+    ///
+    /// The plain format lists each binary ID along with the path to the binary.
+    pub fn write(
+        &self,
+        output_format: OutputFormat,
+        writer: impl Write,
+    ) -> Result<(), WriteTestListError> {
+        match output_format {
+            OutputFormat::Plain => self.write_plain(writer).map_err(WriteTestListError::Io),
+            OutputFormat::Serializable(format) => format
+                .to_writer(&self.to_summary(), writer)
+                .map_err(WriteTestListError::Json),
+        }
+    }
+
+    /// Outputs this list as a string with the given format.
+    pub fn to_string(&self, output_format: OutputFormat) -> Result<String, WriteTestListError> {
+        let mut buf = Vec::with_capacity(1024);
+        self.write(output_format, &mut buf)?;
+        Ok(String::from_utf8(buf).expect("buffer is valid UTF-8"))
+    }
+
+    fn write_plain(&self, mut writer: impl Write) -> io::Result<()> {
+        for test_binary in &self.rust_binaries {
+            writeln!(
+                writer,
+                "{} {}",
+                test_binary.binary_id.style(self.styles.binary_id),
+                test_binary.binary_path
+            )?;
+        }
+        Ok(())
+    }
 }
 
 /// List of test instances, obtained by querying the [`RustTestArtifact`] instances generated by Cargo.
 #[derive(Clone, Debug)]
 pub struct TestList<'g> {
     test_count: usize,
-    rust_suites: BTreeMap<Utf8PathBuf, RustTestSuite<'g>>,
+    // Binary ID -> suite.
+    rust_suites: BTreeMap<String, RustTestSuite<'g>>,
+    skipped_binaries: BTreeSet<String>,
+    // Binary ID -> suite.
+    synthetic_suites: BTreeMap<String, SyntheticSuite<'g>>,
+    build_meta: RustBuildMeta,
+    target_runner: TargetRunner,
     styles: Box<Styles>,
     // Computed on first access.
     skip_count: OnceCell<usize>,
 }
 
+#[derive(Clone, Debug)]
+struct SyntheticSuite<'g> {
+    info: RustTestSuite<'g>,
+    // Test name -> test.
+    tests: BTreeMap<String, SyntheticTest>,
+}
+
+/// A test that isn't part of a Rust test binary, such as a check implemented as a shell script.
+///
+/// Synthetic tests are added to a [`TestList`] through [`TestList::add_synthetic_tests`], and are
+/// then scheduled and reported alongside Rust tests. A synthetic test passes if its command exits
+/// with code 0.
+///
+/// Created through [`SyntheticTestBuilder`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyntheticTest {
+    name: String,
+    program: Utf8PathBuf,
+    args: Vec<String>,
+    cwd: Option<Utf8PathBuf>,
+    env: BTreeMap<String, String>,
+}
+
+impl SyntheticTest {
+    /// Returns the name of this test.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the program run for this test.
+    pub fn program(&self) -> &Utf8Path {
+        &self.program
+    }
+
+    /// Returns the arguments passed to the program.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns the directory the program is run in, if set.
+    ///
+    /// If this isn't set, the program is run in the directory containing the package's
+    /// `Cargo.toml`, like Rust tests.
+    pub fn cwd(&self) -> Option<&Utf8Path> {
+        self.cwd.as_deref()
+    }
+
+    /// Returns the environment variables set for the program, in addition to the ones set for Rust
+    /// tests.
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+}
+
+/// A builder for [`SyntheticTest`] instances.
+#[derive(Clone, Debug)]
+pub struct SyntheticTestBuilder {
+    name: String,
+    program: Utf8PathBuf,
+    args: Vec<String>,
+    cwd: Option<Utf8PathBuf>,
+    env: BTreeMap<String, String>,
+}
+
+impl SyntheticTestBuilder {
+    /// Creates a new builder for a test with the given name, which runs the given program.
+    ///
+    /// The program is looked up in `PATH` if it isn't a path.
+    pub fn new(name: impl Into<String>, program: impl Into<Utf8PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            program: program.into(),
+            args: vec![],
+            cwd: None,
+            env: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an argument to the end of the command.
+    pub fn add_arg(&mut self, arg: impl Into<String>) -> &mut Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Adds several arguments to the end of the command.
+    pub fn add_args(&mut self, args: impl IntoIterator<Item = impl Into<String>>) -> &mut Self {
+        for arg in args {
+            self.add_arg(arg);
+        }
+        self
+    }
+
+    /// Sets the directory to run the command in.
+    pub fn set_cwd(&mut self, cwd: impl Into<Utf8PathBuf>) -> &mut Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Sets an environment variable for the command.
+    pub fn set_env(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Creates a new synthetic test.
+    pub fn build(&self) -> SyntheticTest {
+        SyntheticTest {
+            name: self.name.clone(),
+            program: self.program.clone(),
+            args: self.args.clone(),
+            cwd: self.cwd.clone(),
+            env: self.env.clone(),
+        }
+    }
+}
+
 /// A suite of tests within a single Rust test binary.
 ///
 /// This is a representation of [`nextest_metadata::RustTestSuiteSummary`] used internally by the runner.
@@ -124,6 +640,17 @@ pub struct RustTestSuite<'g> {
     /// A unique identifier for this binary.
     pub binary_id: String,
 
+    /// The path to the binary.
+    ///
+    /// This is empty for suites of synthetic tests, since each synthetic test runs its own
+    /// program.
+    pub binary_path: Utf8PathBuf,
+
+    /// The platform this binary was built for.
+    ///
+    /// This is always the target platform for suites of synthetic tests.
+    pub build_platform: BuildPlatform,
+
     /// Package metadata.
     pub package: PackageMetadata<'g>,
 
@@ -134,22 +661,90 @@ pub struct RustTestSuite<'g> {
     /// will not be changed.
     pub cwd: Utf8PathBuf,
 
+    /// Paths to the workspace binaries that tests in this binary need in order to run, keyed by
+    /// the environment variable each path is set as.
+    ///
+    /// See [`required_binaries`](crate::required_binaries) for more.
+    pub required_binaries: BTreeMap<String, Utf8PathBuf>,
+
     /// Test case names and other information about them.
     pub testcases: BTreeMap<String, RustTestCaseSummary>,
 }
 
+impl<'g> RustTestSuite<'g> {
+    /// Returns a [`BinaryQuery`] for this suite, used to match it against filter expressions.
+    pub fn to_query(&self) -> BinaryQuery<'_> {
+        BinaryQuery {
+            package_id: self.package.id(),
+            package_name: self.package.name(),
+            binary_id: &self.binary_id,
+            platform: self.build_platform,
+        }
+    }
+}
+
 impl<'g> TestList<'g> {
     /// Creates a new test list by running the given command and applying the specified filter.
     pub fn new(
         test_artifacts: impl IntoIterator<Item = RustTestArtifact<'g>>,
         filter: &TestFilterBuilder,
+    ) -> Result<Self, ParseTestListError> {
+        Self::new_with_settings(test_artifacts, filter, &ListSettings::default())
+    }
+
+    /// Creates a new test list like [`Self::new`], but reuses the `--list` output of binaries that
+    /// haven't changed since they were last listed.
+    ///
+    /// This is a shorthand for [`Self::new_with_settings`] with [`ListSettings::set_cache`].
+    pub fn new_with_cache(
+        test_artifacts: impl IntoIterator<Item = RustTestArtifact<'g>>,
+        filter: &TestFilterBuilder,
+        cache: &NextestCache,
+    ) -> Result<Self, ParseTestListError> {
+        let mut settings = ListSettings::default();
+        settings.set_cache(cache);
+        Self::new_with_settings(test_artifacts, filter, &settings)
+    }
+
+    /// Creates a new test list like [`Self::new`], with the given settings for listing tests.
+    ///
+    /// Binaries that the filter can't match any tests in, for example because filter expressions
+    /// only select other binaries, aren't run to list their tests and are left out of the list.
+    /// Binaries without any tests are handled as described in
+    /// [`ListSettings::set_empty_binaries`].
+    pub fn new_with_settings(
+        test_artifacts: impl IntoIterator<Item = RustTestArtifact<'g>>,
+        filter: &TestFilterBuilder,
+        settings: &ListSettings<'_>,
     ) -> Result<Self, ParseTestListError> {
         let mut test_count = 0;
 
+        let (test_artifacts, skipped_artifacts): (Vec<_>, Vec<_>) = test_artifacts
+            .into_iter()
+            .partition(|test_binary| filter.could_match_binary(&test_binary.to_query()));
+        let skipped_binaries = skipped_artifacts
+            .into_iter()
+            .map(|test_binary| test_binary.binary_id)
+            .collect();
+
+        let mut build_meta = settings.build_meta.clone();
+        for test_binary in &test_artifacts {
+            build_meta.add_binary(test_binary.build_platform, &test_binary.binary_path);
+        }
+
         let test_artifacts = test_artifacts
             .into_iter()
-            .map(|test_binary| {
-                let (non_ignored, ignored) = test_binary.exec()?;
+            .map(|mut test_binary| {
+                test_binary.cwd = settings
+                    .missing_cwd
+                    .resolve(
+                        &test_binary.binary_id,
+                        &test_binary.cwd,
+                        test_binary.package.graph().workspace().root(),
+                    )
+                    .map_err(ParseTestListError::MissingCwd)?;
+                let (non_ignored, ignored) =
+                    test_binary.exec(settings, &build_meta, &settings.target_runner)?;
                 let (bin, info) = Self::process_output(
                     test_binary,
                     filter,
@@ -161,12 +756,18 @@ impl<'g> TestList<'g> {
             })
             .collect::<Result<BTreeMap<_, _>, _>>()?;
 
-        Ok(Self {
+        let mut test_list = Self {
             rust_suites: test_artifacts,
+            skipped_binaries,
             test_count,
             styles: Box::new(Styles::default()),
+            synthetic_suites: BTreeMap::new(),
+            build_meta,
+            target_runner: settings.target_runner.clone(),
             skip_count: OnceCell::new(),
-        })
+        };
+        test_list.handle_empty_binaries(filter, settings.empty_binaries)?;
+        Ok(test_list)
     }
 
     /// Creates a new test list with the given binary names and outputs.
@@ -192,14 +793,188 @@ impl<'g> TestList<'g> {
             })
             .collect::<Result<BTreeMap<_, _>, _>>()?;
 
-        Ok(Self {
+        let mut test_list = Self {
             rust_suites: test_artifacts,
+            skipped_binaries: BTreeSet::new(),
+            test_count,
+            styles: Box::new(Styles::default()),
+            synthetic_suites: BTreeMap::new(),
+            build_meta: RustBuildMeta::default(),
+            target_runner: TargetRunner::default(),
+            skip_count: OnceCell::new(),
+        };
+        test_list.handle_empty_binaries(filter, EmptyBinaries::List)?;
+        Ok(test_list)
+    }
+
+    /// Creates a new test list from a previously serialized [`TestListSummary`], without running
+    /// test binaries to list the tests within them.
+    ///
+    /// This lets a test list be computed once and then distributed, for example with each machine
+    /// getting its own subset of tests. The binaries must exist at the paths recorded in the
+    /// summary.
+    ///
+    /// Tests that didn't match the filter when the summary was created are still skipped, and the
+    /// given filter is applied to the rest. Of the settings, only the build metadata, target runner
+    /// and what to do about missing directories are used.
+    pub fn from_summary(
+        graph: &'g PackageGraph,
+        summary: TestListSummary,
+        filter: &TestFilterBuilder,
+        settings: &ListSettings<'_>,
+    ) -> Result<Self, FromSummaryError> {
+        let mut test_count = 0;
+        let mut build_meta = settings.build_meta.clone();
+
+        let rust_suites = summary
+            .rust_suites
+            .into_iter()
+            .map(|(binary_id, suite)| {
+                let package = graph
+                    .metadata(&PackageId::new(suite.package_id))
+                    .map_err(FromSummaryError::PackageGraph)?;
+                if !suite.binary_path.is_file() {
+                    return Err(FromSummaryError::MissingBinary {
+                        binary_id,
+                        binary_path: suite.binary_path,
+                    });
+                }
+
+                let cwd = settings
+                    .missing_cwd
+                    .resolve(&binary_id, &suite.cwd, graph.workspace().root())
+                    .map_err(FromSummaryError::MissingCwd)?;
+
+                let mut testcases = suite.testcases;
+                {
+                    let query = BinaryQuery {
+                        package_id: package.id(),
+                        package_name: package.name(),
+                        binary_id: &binary_id,
+                        platform: suite.build_platform,
+                    };
+                    // As with listing, ignored and non-ignored tests are filtered separately.
+                    let mut non_ignored_filter = filter.build(query);
+                    let mut ignored_filter = filter.build(query);
+                    for (name, info) in &mut testcases {
+                        if info.filter_match.is_match() {
+                            let test_filter = if info.ignored {
+                                &mut ignored_filter
+                            } else {
+                                &mut non_ignored_filter
+                            };
+                            info.filter_match = test_filter.filter_match(name, info.ignored);
+                        }
+                    }
+                }
+                test_count += testcases.len();
+                build_meta.add_binary(suite.build_platform, &suite.binary_path);
+
+                Ok((
+                    binary_id.clone(),
+                    RustTestSuite {
+                        binary_id,
+                        binary_path: suite.binary_path,
+                        build_platform: suite.build_platform,
+                        package,
+                        binary_name: suite.binary_name,
+                        cwd,
+                        required_binaries: suite.required_binaries,
+                        testcases,
+                    },
+                ))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        Ok(Self {
+            rust_suites,
+            skipped_binaries: summary.skipped_binaries,
             test_count,
             styles: Box::new(Styles::default()),
+            synthetic_suites: BTreeMap::new(),
+            build_meta,
+            target_runner: settings.target_runner.clone(),
             skip_count: OnceCell::new(),
         })
     }
 
+    /// Adds synthetic tests to this list, as a suite with the binary ID
+    /// `<package name>::<suite name>`.
+    ///
+    /// The filter is applied to synthetic tests like it is to Rust tests. Synthetic tests are run
+    /// and counted along with Rust tests, but aren't included in [`Self::to_summary`].
+    pub fn add_synthetic_tests(
+        &mut self,
+        package: PackageMetadata<'g>,
+        suite_name: &str,
+        tests: impl IntoIterator<Item = SyntheticTest>,
+        filter: &TestFilterBuilder,
+    ) -> Result<(), SyntheticTestError> {
+        let binary_id = format!("{}::{}", package.name(), suite_name);
+        if self.synthetic_suites.contains_key(&binary_id)
+            || self.rust_suites.contains_key(&binary_id)
+        {
+            return Err(SyntheticTestError::DuplicateSuite { binary_id });
+        }
+
+        let mut synthetic_tests = BTreeMap::new();
+        for test in tests {
+            if synthetic_tests.contains_key(&test.name) {
+                return Err(SyntheticTestError::DuplicateTest {
+                    binary_id,
+                    name: test.name,
+                });
+            }
+            synthetic_tests.insert(test.name.clone(), test);
+        }
+
+        let query = BinaryQuery {
+            package_id: package.id(),
+            package_name: package.name(),
+            binary_id: &binary_id,
+            platform: BuildPlatform::Target,
+        };
+        let mut test_filter = filter.build(query);
+        let testcases: BTreeMap<_, _> = synthetic_tests
+            .keys()
+            .map(|name| {
+                let summary = RustTestCaseSummary {
+                    ignored: false,
+                    ignore_reason: None,
+                    listed_name: None,
+                    filter_match: test_filter.filter_match(name, false),
+                };
+                (name.clone(), summary)
+            })
+            .collect();
+
+        let cwd = package
+            .manifest_path()
+            .parent()
+            .expect("manifest path has a parent")
+            .to_path_buf();
+        self.test_count += testcases.len();
+        self.synthetic_suites.insert(
+            binary_id.clone(),
+            SyntheticSuite {
+                info: RustTestSuite {
+                    binary_id,
+                    binary_path: Utf8PathBuf::new(),
+                    build_platform: BuildPlatform::Target,
+                    package,
+                    binary_name: suite_name.to_owned(),
+                    cwd,
+                    required_binaries: BTreeMap::new(),
+                    testcases,
+                },
+                tests: synthetic_tests,
+            },
+        );
+        // The skip count has to be computed again.
+        self.skip_count = OnceCell::new();
+        Ok(())
+    }
+
     /// Colorizes output.
     pub fn colorize(&mut self) {
         self.styles.colorize();
@@ -226,36 +1001,162 @@ impl<'g> TestList<'g> {
         self.test_count - self.skip_count()
     }
 
-    /// Returns the total number of binaries that contain tests.
+    /// Returns the total number of binaries that contain tests, counting each suite of synthetic
+    /// tests as a binary.
     pub fn binary_count(&self) -> usize {
-        self.rust_suites.len()
+        self.rust_suites.len() + self.synthetic_suites.len()
+    }
+
+    /// Returns the number of binaries that weren't listed, because the filter couldn't match any
+    /// tests in them.
+    pub fn skipped_binary_count(&self) -> usize {
+        self.skipped_binaries.len()
+    }
+
+    /// Iterates over the unique identifiers of binaries that weren't listed, because the filter
+    /// couldn't match any tests in them.
+    pub fn skipped_binaries(&self) -> impl Iterator<Item = &str> + '_ {
+        self.skipped_binaries
+            .iter()
+            .map(|binary_id| binary_id.as_str())
+    }
+
+    /// Skips the tests that an external [selector](crate::selector) didn't choose, with
+    /// [`MismatchReason::Unselected`].
+    pub fn apply_selection(&mut self, selection: &TestSelection) {
+        self.skip_tests(MismatchReason::Unselected, |query| {
+            selection
+                .position(query.binary_id, query.test_name)
+                .is_none()
+        });
+    }
+
+    /// Skips the tests in a [coverage map](crate::coverage) that don't cover any changed code, with
+    /// [`MismatchReason::Uncovered`].
+    pub fn apply_coverage_selection(&mut self, selection: &CoverageSelection) {
+        self.skip_tests(MismatchReason::Uncovered, |query| {
+            selection.is_uncovered(query.binary_id, query.test_name)
+        });
+    }
+
+    /// Skips the tests that don't match a profile's [default
+    /// filter](NextestProfile::default_filter), with [`MismatchReason::DefaultFilter`].
+    ///
+    /// Tests that explicit filters already skipped keep their reason, so that it's possible to tell
+    /// which tests `--ignore-default-filter` would run.
+    pub fn apply_default_filter(&mut self, default_filter: &FilterExpr) {
+        self.skip_tests(MismatchReason::DefaultFilter, |query| {
+            !default_filter.matches_test(query)
+        });
+    }
+
+    /// Skips the tests that an override in the profile skips, with [`MismatchReason::Override`].
+    ///
+    /// Overrides are only considered if their `platform` matches the profile's
+    /// [host](NextestProfile::with_host_triple) or [target](NextestProfile::with_target_triple)
+    /// triple, depending on the platform each binary was built for.
+    pub fn apply_skip_overrides(&mut self, profile: &NextestProfile<'_>) {
+        self.skip_tests(MismatchReason::Override, |query| {
+            profile.settings_for(query).skip()
+        });
+    }
+
+    /// Skips the tests whose names don't contain `filter`, with [`MismatchReason::String`].
+    ///
+    /// This narrows down a list that has already been filtered, for example to re-run some of its
+    /// tests.
+    pub fn apply_string_filter(&mut self, filter: &str) {
+        self.skip_tests(MismatchReason::String, |query| {
+            !query.test_name.contains(filter)
+        });
+    }
+
+    // Skips the tests that would otherwise be run, for which `skip` returns true.
+    fn skip_tests(&mut self, reason: MismatchReason, skip: impl Fn(&TestQuery<'_>) -> bool) {
+        let suites = self.rust_suites.values_mut().chain(
+            self.synthetic_suites
+                .values_mut()
+                .map(|suite| &mut suite.info),
+        );
+        for suite in suites {
+            for (name, test_info) in &mut suite.testcases {
+                let query = TestQuery {
+                    binary_id: &suite.binary_id,
+                    package_name: suite.package.name(),
+                    test_name: name,
+                    platform: suite.build_platform,
+                };
+                if test_info.filter_match.is_match() && skip(&query) {
+                    test_info.filter_match = FilterMatch::Mismatch { reason };
+                }
+            }
+        }
+        self.skip_count = OnceCell::new();
+    }
+
+    /// Sets the path to a required binary for the suites that its filter could match tests in,
+    /// including suites of synthetic tests.
+    pub fn add_required_binary(&mut self, required_binary: &RequiredBinary, path: &Utf8Path) {
+        let suites = self.rust_suites.values_mut().chain(
+            self.synthetic_suites
+                .values_mut()
+                .map(|suite| &mut suite.info),
+        );
+        for suite in suites {
+            if required_binary
+                .filter()
+                .could_match_binary(&suite.to_query())
+            {
+                suite
+                    .required_binaries
+                    .insert(required_binary.env().to_owned(), path.to_owned());
+            }
+        }
     }
 
-    /// Returns the tests for a given binary, or `None` if the binary wasn't in the list.
-    pub fn get(&self, test_bin: impl AsRef<Utf8Path>) -> Option<&RustTestSuite> {
-        self.rust_suites.get(test_bin.as_ref())
+    /// Returns information about how the test binaries in this list were built.
+    pub fn build_meta(&self) -> &RustBuildMeta {
+        &self.build_meta
+    }
+
+    /// Returns the runners used to run test binaries.
+    pub fn target_runner(&self) -> &TargetRunner {
+        &self.target_runner
+    }
+
+    /// Returns the tests for the binary with the given ID, or `None` if the binary wasn't in the
+    /// list.
+    pub fn get(&self, binary_id: &str) -> Option<&RustTestSuite<'_>> {
+        self.rust_suites.get(binary_id)
     }
 
     /// Constructs a serializble summary for this test list.
     pub fn to_summary(&self) -> TestListSummary {
         let rust_suites = self
             .rust_suites
-            .iter()
-            .map(|(binary_path, info)| {
+            .values()
+            .map(|info| {
                 let testsuite = RustTestSuiteSummary {
                     package_name: info.package.name().to_owned(),
                     binary_name: info.binary_name.clone(),
                     package_id: info.package.id().repr().to_owned(),
-                    binary_path: binary_path.clone(),
+                    binary_path: info.binary_path.clone(),
+                    build_platform: info.build_platform,
                     cwd: info.cwd.clone(),
+                    required_binaries: info.required_binaries.clone(),
                     testcases: info.testcases.clone(),
                 };
                 (info.binary_id.clone(), testsuite)
             })
             .collect();
         let mut summary = TestListSummary::default();
-        summary.test_count = self.test_count;
+        summary.test_count = self
+            .rust_suites
+            .values()
+            .map(|suite| suite.testcases.len())
+            .sum();
         summary.rust_suites = rust_suites;
+        summary.skipped_binaries = self.skipped_binaries.clone();
         summary
     }
 
@@ -273,20 +1174,86 @@ impl<'g> TestList<'g> {
         }
     }
 
-    /// Iterates over all the test binaries.
-    pub fn iter(&self) -> impl Iterator<Item = (&Utf8Path, &RustTestSuite)> + '_ {
+    /// Outputs this list to the given writer in a delimited format, with a header row followed by
+    /// one row per test.
+    ///
+    /// The columns are the package name, binary ID, test name, whether the test is ignored,
+    /// whether it matches the filter (`matches`, or the reason it doesn't) and the platform its
+    /// binary was built for.
+    pub fn write_delimited(
+        &self,
+        format: DelimitedFormat,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        format.write_row(
+            [
+                "package",
+                "binary-id",
+                "test-name",
+                "ignored",
+                "filter-match",
+                "platform",
+            ],
+            &mut writer,
+        )?;
+        for test in self.iter_tests() {
+            let filter_match = match test.test_info.filter_match {
+                FilterMatch::Matches => "matches",
+                FilterMatch::Mismatch { reason } => reason.as_str(),
+            };
+            format.write_row(
+                [
+                    test.bin_info.package.name(),
+                    &test.bin_info.binary_id,
+                    test.name,
+                    if test.test_info.ignored {
+                        "true"
+                    } else {
+                        "false"
+                    },
+                    filter_match,
+                    test.bin_info.build_platform.as_str(),
+                ],
+                &mut writer,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Iterates over all the test binaries, sorted by binary ID.
+    pub fn iter(&self) -> impl Iterator<Item = (&Utf8Path, &RustTestSuite<'_>)> + '_ {
         self.rust_suites
-            .iter()
-            .map(|(path, info)| (path.as_path(), info))
+            .values()
+            .map(|info| (info.binary_path.as_path(), info))
+    }
+
+    /// Iterates over tests that were renamed because their binary listed several tests with the
+    /// same name.
+    ///
+    /// The name each test was listed under is available as
+    /// [`RustTestCaseSummary::listed_name`].
+    pub fn renamed_tests(&self) -> impl Iterator<Item = TestInstance<'_>> + '_ {
+        self.iter_tests()
+            .filter(|instance| instance.test_info.listed_name.is_some())
     }
 
     /// Iterates over the list of tests, returning the path and test name.
     pub fn iter_tests(&self) -> impl Iterator<Item = TestInstance<'_>> + '_ {
-        self.rust_suites.iter().flat_map(|(test_bin, bin_info)| {
+        let rust_tests = self.rust_suites.values().flat_map(|bin_info| {
             bin_info.testcases.iter().map(move |(name, test_info)| {
-                TestInstance::new(name, test_bin, bin_info, test_info)
+                TestInstance::new(name, &bin_info.binary_path, bin_info, test_info)
             })
-        })
+        });
+        let synthetic_tests = self.synthetic_suites.values().flat_map(|suite| {
+            suite.info.testcases.iter().map(move |(name, test_info)| {
+                let synthetic = &suite.tests[name];
+                TestInstance {
+                    synthetic: Some(synthetic),
+                    ..TestInstance::new(name, &synthetic.program, &suite.info, test_info)
+                }
+            })
+        });
+        rust_tests.chain(synthetic_tests)
     }
 
     /// Outputs this list as a string with the given format.
@@ -307,7 +1274,11 @@ impl<'g> TestList<'g> {
         Self {
             test_count: 0,
             rust_suites: BTreeMap::new(),
+            skipped_binaries: BTreeSet::new(),
             styles: Box::new(Styles::default()),
+            synthetic_suites: BTreeMap::new(),
+            build_meta: RustBuildMeta::default(),
+            target_runner: TargetRunner::default(),
             skip_count: OnceCell::new(),
         }
     }
@@ -317,30 +1288,51 @@ impl<'g> TestList<'g> {
         filter: &TestFilterBuilder,
         non_ignored: impl AsRef<str>,
         ignored: impl AsRef<str>,
-    ) -> Result<(Utf8PathBuf, RustTestSuite<'g>), ParseTestListError> {
+    ) -> Result<(String, RustTestSuite<'g>), ParseTestListError> {
         let mut tests = BTreeMap::new();
 
+        // Each output is checked for duplicates separately, since the same tests can appear in
+        // both.
+        let mut non_ignored_listed = Self::parse(non_ignored.as_ref(), false)?;
+        rename_duplicates(&mut non_ignored_listed);
+        let mut ignored_listed = Self::parse(ignored.as_ref(), true)?;
+        rename_duplicates(&mut ignored_listed);
+
+        let (mut non_ignored_tests, mut ignored_tests): (Vec<_>, Vec<_>) = non_ignored_listed
+            .into_iter()
+            .chain(ignored_listed)
+            .partition(|test| !test.ignored);
+        non_ignored_tests.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        ignored_tests.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
         // Treat ignored and non-ignored as separate sets of single filters, so that partitioning
         // based on one doesn't affect the other.
-        let mut non_ignored_filter = filter.build();
-        for test_name in Self::parse(non_ignored.as_ref())? {
+        let mut non_ignored_filter = filter.build(test_binary.to_query());
+        for test in non_ignored_tests {
+            let filter_match = non_ignored_filter.filter_match(&test.name, false);
             tests.insert(
-                test_name.into(),
+                test.name.into_owned(),
                 RustTestCaseSummary {
                     ignored: false,
-                    filter_match: non_ignored_filter.filter_match(test_name, false),
+                    ignore_reason: None,
+                    listed_name: test.listed_name.map(Cow::into_owned),
+                    filter_match,
                 },
             );
         }
 
-        let mut ignored_filter = filter.build();
-        for test_name in Self::parse(ignored.as_ref())? {
-            // TODO: catch dups
+        let mut ignored_filter = filter.build(test_binary.to_query());
+        for test in ignored_tests {
+            // Tests listed by both the ignored and non-ignored outputs are deliberately replaced
+            // here, since terse output lists ignored tests without --ignored as well.
+            let filter_match = ignored_filter.filter_match(&test.name, true);
             tests.insert(
-                test_name.into(),
+                test.name.into_owned(),
                 RustTestCaseSummary {
                     ignored: true,
-                    filter_match: ignored_filter.filter_match(test_name, true),
+                    ignore_reason: test.ignore_reason.map(Cow::into_owned),
+                    listed_name: test.listed_name.map(Cow::into_owned),
+                    filter_match,
                 },
             );
         }
@@ -351,59 +1343,166 @@ impl<'g> TestList<'g> {
             binary_path,
             binary_name,
             cwd,
+            build_platform,
         } = test_binary;
 
         Ok((
-            binary_path,
+            binary_id.clone(),
             RustTestSuite {
                 binary_id,
+                binary_path,
+                build_platform,
                 package,
                 binary_name,
                 testcases: tests,
                 cwd,
+                required_binaries: BTreeMap::new(),
             },
         ))
     }
 
-    /// Parses the output of --list --format terse and returns a sorted list.
-    fn parse(list_output: &str) -> Result<Vec<&'_ str>, ParseTestListError> {
-        let mut list = Self::parse_impl(list_output).collect::<Result<Vec<_>, _>>()?;
-        list.sort_unstable();
-        Ok(list)
-    }
+    /// Parses the output of --list --format terse, --list --format json, or output that uses the
+    /// discovery protocol.
+    ///
+    /// Tests listed in the terse format are marked as ignored if `ignored` is true.
+    fn parse(list_output: &str, ignored: bool) -> Result<Vec<ListedTest<'_>>, ParseTestListError> {
+        let mut lines = list_output.lines();
+        let first_line = list_output.lines().next();
+        if first_line.is_some_and(|line| line.starts_with('{')) {
+            return lines
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(
+                    |line| match serde_json::from_str::<LibtestListEvent<'_>>(line) {
+                        Ok(event) => event.into_listed_test().map(Ok),
+                        Err(err) => Some(Err(ParseTestListError::parse_line(
+                            format!("line '{}' is not a valid libtest JSON event: {}", line, err),
+                            list_output,
+                        ))),
+                    },
+                )
+                .collect();
+        }
+        if first_line == Some(DISCOVERY_PROTOCOL_HEADER) {
+            lines.next();
+            return lines
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    serde_json::from_str(line).map_err(|err| {
+                        ParseTestListError::parse_line(
+                            format!("line '{}' is not a valid discovered test: {}", line, err),
+                            list_output,
+                        )
+                    })
+                })
+                .collect();
+        }
 
-    fn parse_impl(
-        list_output: &str,
-    ) -> impl Iterator<Item = Result<&'_ str, ParseTestListError>> + '_ {
         // The output is in the form:
         // <test name>: test
         // <test name>: test
         // ...
+        lines
+            .map(|line| match line.strip_suffix(": test") {
+                Some(name) => Ok(ListedTest {
+                    name: name.into(),
+                    ignored,
+                    ignore_reason: None,
+                    listed_name: None,
+                }),
+                None => Err(ParseTestListError::parse_line(
+                    format!("line '{}' did not end with the string ': test'", line),
+                    list_output,
+                )),
+            })
+            .collect()
+    }
+
+    // Skips empty binaries not selected by the filter, then applies `empty_binaries` to the rest.
+    fn handle_empty_binaries(
+        &mut self,
+        filter: &TestFilterBuilder,
+        empty_binaries: EmptyBinaries,
+    ) -> Result<(), ParseTestListError> {
+        let empty_ids: Vec<_> = self
+            .rust_suites
+            .iter()
+            .filter(|(_, suite)| suite.testcases.is_empty())
+            .map(|(binary_id, _)| binary_id.clone())
+            .collect();
 
-        list_output.lines().map(move |line| {
-            line.strip_suffix(": test").ok_or_else(|| {
-                ParseTestListError::parse_line(
-                    format!("line '{}' did not end with the string ': test'", line),
-                    list_output,
-                )
-            })
-        })
+        let mut selected = vec![];
+        for binary_id in empty_ids {
+            if filter.matches_empty_binary(&self.rust_suites[&binary_id].to_query()) {
+                selected.push(binary_id);
+            } else {
+                self.rust_suites.remove(&binary_id);
+                self.skipped_binaries.insert(binary_id);
+            }
+        }
+
+        match empty_binaries {
+            EmptyBinaries::List => {}
+            EmptyBinaries::Hide => {
+                for binary_id in &selected {
+                    self.rust_suites.remove(binary_id);
+                }
+            }
+            EmptyBinaries::Fail => {
+                if !selected.is_empty() {
+                    return Err(ParseTestListError::EmptyBinaries {
+                        binary_ids: selected,
+                    });
+                }
+            }
+        }
+        Ok(())
     }
 
     fn write_plain(&self, mut writer: impl Write) -> io::Result<()> {
-        for (test_bin, info) in &self.rust_suites {
+        for info in self.rust_suites.values() {
             writeln!(writer, "{}:", info.binary_id.style(self.styles.binary_id))?;
-            writeln!(writer, "  {} {}", "bin:".style(self.styles.field), test_bin)?;
+            writeln!(
+                writer,
+                "  {} {}",
+                "bin:".style(self.styles.field),
+                info.binary_path
+            )?;
             writeln!(writer, "  {} {}", "cwd:".style(self.styles.field), info.cwd)?;
+            if info.testcases.is_empty() {
+                writeln!(writer, "    (no tests)")?;
+                continue;
+            }
+            self.write_testcases(&info.testcases, &mut writer)?;
+        }
+        for suite in self.synthetic_suites.values() {
+            let info = &suite.info;
+            writeln!(writer, "{}:", info.binary_id.style(self.styles.binary_id))?;
+            writeln!(writer, "  {} synthetic", "bin:".style(self.styles.field))?;
+            writeln!(writer, "  {} {}", "cwd:".style(self.styles.field), info.cwd)?;
+            self.write_testcases(&info.testcases, &mut writer)?;
+        }
+        Ok(())
+    }
 
-            let mut indented = indent_write::io::IndentWriter::new("    ", &mut writer);
-            for (name, info) in &info.testcases {
-                write_test_name(name, self.styles.test_name, &mut indented)?;
-                if !info.filter_match.is_match() {
-                    write!(indented, " (skipped)")?;
-                }
-                writeln!(indented)?;
+    fn write_testcases(
+        &self,
+        testcases: &BTreeMap<String, RustTestCaseSummary>,
+        writer: impl Write,
+    ) -> io::Result<()> {
+        let mut indented = indent_write::io::IndentWriter::new("    ", writer);
+        for (name, info) in testcases {
+            write_test_name(name, self.styles.test_name, &mut indented)?;
+            let mut notes = vec![];
+            if !info.filter_match.is_match() {
+                notes.push(Cow::Borrowed("skipped"));
+            }
+            if let Some(reason) = &info.ignore_reason {
+                notes.push(Cow::Owned(format!("ignored: {}", reason)));
+            }
+            if !notes.is_empty() {
+                write!(indented, " ({})", notes.join(", "))?;
             }
+            writeln!(indented)?;
         }
         Ok(())
     }
@@ -411,19 +1510,115 @@ impl<'g> TestList<'g> {
 
 impl<'g> RustTestArtifact<'g> {
     /// Run this binary with and without --ignored and get the corresponding outputs.
-    fn exec(&self) -> Result<(String, String), ParseTestListError> {
-        let non_ignored = self.exec_single(false)?;
-        let ignored = self.exec_single(true)?;
+    ///
+    /// If the binary supports the discovery protocol, its output already includes ignored tests,
+    /// so the binary is not run with --ignored.
+    ///
+    /// Outputs are reused from and written to the cache, if provided. Outputs from binaries that
+    /// support the discovery protocol aren't cached, since they can depend on files other than the
+    /// binary.
+    fn exec(
+        &self,
+        settings: &ListSettings<'_>,
+        build_meta: &RustBuildMeta,
+        target_runner: &TargetRunner,
+    ) -> Result<(String, String), ParseTestListError> {
+        let format = if settings.ignore_reasons {
+            "json"
+        } else {
+            "terse"
+        };
+        if let Some(outputs) = settings
+            .cache
+            .and_then(|cache| cache.get_list_output(&self.binary_path, format))
+        {
+            return Ok(outputs);
+        }
+
+        let (non_ignored, ignored) = self.exec_uncached(settings, build_meta, target_runner)?;
+        if let Some(cache) = settings.cache {
+            if non_ignored.lines().next() != Some(DISCOVERY_PROTOCOL_HEADER) {
+                cache.put_list_output(&self.binary_path, format, &non_ignored, &ignored);
+            }
+        }
+        Ok((non_ignored, ignored))
+    }
+
+    fn exec_uncached(
+        &self,
+        settings: &ListSettings<'_>,
+        build_meta: &RustBuildMeta,
+        target_runner: &TargetRunner,
+    ) -> Result<(String, String), ParseTestListError> {
+        if let Some(runner) = target_runner.for_listing(self.build_platform) {
+            if let Some(output) = runner.environment().clone().list_tests(&self.binary_path)? {
+                return Ok((output, String::new()));
+            }
+        }
+
+        let discard_coverage = settings.discard_coverage;
+        if settings.ignore_reasons {
+            if let Some(output) = self.exec_json(discard_coverage, build_meta, target_runner) {
+                return Ok((output, String::new()));
+            }
+        }
+
+        let non_ignored = self.exec_single(false, discard_coverage, build_meta, target_runner)?;
+        if non_ignored.lines().next() == Some(DISCOVERY_PROTOCOL_HEADER) {
+            return Ok((non_ignored, String::new()));
+        }
+        let ignored = self.exec_single(true, discard_coverage, build_meta, target_runner)?;
         Ok((non_ignored, ignored))
     }
 
-    fn exec_single(&self, ignored: bool) -> Result<String, ParseTestListError> {
+    /// Lists tests in libtest's unstable JSON format, which includes ignored tests along with the
+    /// reasons they're ignored.
+    ///
+    /// Returns `None` if the binary doesn't support this format.
+    fn exec_json(
+        &self,
+        discard_coverage: bool,
+        build_meta: &RustBuildMeta,
+        target_runner: &TargetRunner,
+    ) -> Option<String> {
+        let output = self
+            .list_command(
+                vec!["--list", "--format", "json", "-Z", "unstable-options"],
+                discard_coverage,
+                build_meta,
+                target_runner,
+            )
+            .env("RUSTC_BOOTSTRAP", "1")
+            .stdout_capture()
+            .stderr_null()
+            .unchecked()
+            .run()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let output = String::from_utf8(output.stdout).ok()?;
+        let first_line = output.lines().next()?;
+        let is_supported = first_line == DISCOVERY_PROTOCOL_HEADER
+            || serde_json::from_str::<LibtestListEvent<'_>>(first_line)
+                .is_ok_and(|event| event.is_discovery_start());
+        is_supported.then_some(output)
+    }
+
+    fn exec_single(
+        &self,
+        ignored: bool,
+        discard_coverage: bool,
+        build_meta: &RustBuildMeta,
+        target_runner: &TargetRunner,
+    ) -> Result<String, ParseTestListError> {
         let mut argv = vec!["--list", "--format", "terse"];
         if ignored {
             argv.push("--ignored");
         }
-        let cmd = cmd(AsRef::<Path>::as_ref(&self.binary_path), argv)
-            .dir(&self.cwd)
+        let cmd = self
+            .list_command(argv, discard_coverage, build_meta, target_runner)
             .stdout_capture();
 
         cmd.read().map_err(|error| {
@@ -437,6 +1632,126 @@ impl<'g> RustTestArtifact<'g> {
             )
         })
     }
+
+    /// Returns the command that lists tests in this binary with the given arguments.
+    fn list_command(
+        &self,
+        args: Vec<&str>,
+        discard_coverage: bool,
+        build_meta: &RustBuildMeta,
+        target_runner: &TargetRunner,
+    ) -> Expression {
+        let cmd = make_binary_command(
+            &self.binary_path,
+            self.build_platform,
+            args,
+            build_meta,
+            target_runner.for_listing(self.build_platform),
+        )
+        .dir(&self.cwd)
+        .env(DISCOVERY_PROTOCOL_ENV, "1");
+        if discard_coverage {
+            let null_device = if cfg!(windows) { "NUL" } else { "/dev/null" };
+            cmd.env(LLVM_PROFILE_FILE_ENV, null_device)
+        } else {
+            cmd
+        }
+    }
+}
+
+// Creates a command that runs a test binary with the given arguments. The binary is run through the
+// given runner, if any, and can load dynamic libraries built along with it.
+pub(crate) fn make_binary_command(
+    binary_path: &Utf8Path,
+    build_platform: BuildPlatform,
+    args: Vec<&str>,
+    build_meta: &RustBuildMeta,
+    runner: Option<&PlatformRunner>,
+) -> Expression {
+    let environment: Arc<dyn ExecutionEnvironment> = match runner {
+        Some(runner) => runner.environment().clone(),
+        None => Arc::new(Local),
+    };
+    environment.make_command(BinaryCommand {
+        binary_path,
+        args,
+        build_platform,
+        build_meta,
+    })
+}
+
+/// A test as printed out by a test binary while listing tests.
+#[derive(Clone, Debug, Deserialize)]
+struct ListedTest<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(default)]
+    ignored: bool,
+    #[serde(default, borrow)]
+    ignore_reason: Option<Cow<'a, str>>,
+    // Set if this test was renamed because its name was a duplicate.
+    #[serde(skip)]
+    listed_name: Option<Cow<'a, str>>,
+}
+
+// Renames tests with duplicate names, which some custom harnesses produce, so that each test is
+// tracked separately. The first test keeps its name and later ones get a ` #2`, ` #3` etc suffix,
+// in the order they were listed in.
+fn rename_duplicates(tests: &mut [ListedTest<'_>]) {
+    let mut taken: HashSet<String> = tests.iter().map(|test| test.name.to_string()).collect();
+    let mut seen = HashSet::new();
+    for test in tests {
+        if seen.insert(test.name.to_string()) {
+            continue;
+        }
+        let new_name = (2..)
+            .map(|n| format!("{} #{}", test.name, n))
+            .find(|candidate| !taken.contains(candidate))
+            .expect("an unused name exists");
+        taken.insert(new_name.clone());
+        test.listed_name = Some(std::mem::replace(&mut test.name, new_name.into()));
+    }
+}
+
+/// An event printed out by libtest while listing tests in the JSON format.
+///
+/// The output is of the form:
+///
+/// ```text
+/// { "type": "suite", "event": "discovery" }
+/// { "type": "test", "event": "discovered", "name": "tests::foo", "ignore": true, "ignore_message": "reason", ... }
+/// { "type": "suite", "event": "completed", ... }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+struct LibtestListEvent<'a> {
+    #[serde(rename = "type", borrow)]
+    ty: Cow<'a, str>,
+    #[serde(borrow)]
+    event: Cow<'a, str>,
+    #[serde(default, borrow)]
+    name: Option<Cow<'a, str>>,
+    #[serde(default)]
+    ignore: bool,
+    #[serde(default, borrow)]
+    ignore_message: Option<Cow<'a, str>>,
+}
+
+impl<'a> LibtestListEvent<'a> {
+    fn is_discovery_start(&self) -> bool {
+        self.ty == "suite" && self.event == "discovery"
+    }
+
+    fn into_listed_test(self) -> Option<ListedTest<'a>> {
+        if self.ty != "test" || self.event != "discovered" {
+            return None;
+        }
+        Some(ListedTest {
+            name: self.name?,
+            ignored: self.ignore,
+            ignore_reason: self.ignore_message.filter(|message| !message.is_empty()),
+            listed_name: None,
+        })
+    }
 }
 
 /// Represents a single test with its associated binary.
@@ -453,6 +1768,12 @@ pub struct TestInstance<'a> {
 
     /// Information about the test.
     pub test_info: &'a RustTestCaseSummary,
+
+    /// The variant of this test, if the test was expanded through a multiplex rule.
+    pub variant: Option<TestVariant<'a>>,
+
+    /// The synthetic test, if this isn't a Rust test. `binary` is the program run for the test.
+    pub synthetic: Option<&'a SyntheticTest>,
 }
 
 impl<'a> TestInstance<'a> {
@@ -468,21 +1789,95 @@ impl<'a> TestInstance<'a> {
             binary: binary.as_ref(),
             bin_info,
             test_info,
+            variant: None,
+            synthetic: None,
         }
     }
 
-    /// Creates the command expression for this test instance.
-    pub(crate) fn make_expression(&self) -> Expression {
-        // TODO: non-rust tests
-        let mut args = vec!["--exact", self.name, "--nocapture"];
-        if self.test_info.ignored {
-            args.push("--ignored");
+    /// Returns a copy of this test instance with the given variant.
+    pub fn with_variant(self, variant: TestVariant<'a>) -> Self {
+        Self {
+            variant: Some(variant),
+            ..self
+        }
+    }
+
+    /// Returns the query used to match this test against filter expressions.
+    pub fn to_query(&self) -> TestQuery<'a> {
+        TestQuery {
+            binary_id: &self.bin_info.binary_id,
+            package_name: self.bin_info.package.name(),
+            test_name: self.name,
+            platform: self.bin_info.build_platform,
         }
+    }
+
+    /// Creates the command expression for this test instance.
+    ///
+    /// Rust tests are run through the runner for the platform their binary was built for, if any.
+    /// Returns the working directory the test is run in.
+    pub(crate) fn cwd(&self) -> &'a Utf8Path {
+        self.synthetic
+            .and_then(|synthetic| synthetic.cwd.as_deref())
+            .unwrap_or(&self.bin_info.cwd)
+    }
+
+    /// Returns the command that runs this test.
+    ///
+    /// Rust tests are run in `container` if it's specified. Otherwise, they're run under
+    /// `valgrind` if it's specified and there's no target runner for their platform, with its
+    /// report written to the given path.
+    pub(crate) fn make_expression(
+        &self,
+        build_meta: &RustBuildMeta,
+        target_runner: &TargetRunner,
+        container: Option<&ContainerRule>,
+        valgrind: Option<(&Valgrind, &Utf8Path)>,
+    ) -> Expression {
+        let cmd = match self.synthetic {
+            // Pass the program as a string so that duct looks up bare names in PATH.
+            Some(synthetic) => cmd(synthetic.program.as_str(), &synthetic.args).dir(self.cwd()),
+            None => {
+                // Tests renamed because of duplicate names are run by the name they were listed
+                // under.
+                let name = self.test_info.listed_name.as_deref().unwrap_or(self.name);
+                let mut args = vec!["--exact", name, "--nocapture"];
+                if self.test_info.ignored {
+                    args.push("--ignored");
+                }
+                let build_platform = self.bin_info.build_platform;
+                let workspace_root = self.bin_info.package.graph().workspace().root();
+                let runner = target_runner.for_platform(build_platform);
+                match (container, valgrind) {
+                    (Some(container), _) => container.make_command(
+                        self.binary,
+                        build_platform,
+                        args,
+                        workspace_root,
+                        build_meta,
+                    ),
+                    (None, Some((valgrind, xml_path))) if runner.is_none() => valgrind
+                        .make_command(
+                            BinaryCommand {
+                                binary_path: self.binary,
+                                args,
+                                build_platform,
+                                build_meta,
+                            },
+                            workspace_root,
+                            xml_path,
+                        ),
+                    (None, _) => {
+                        make_binary_command(self.binary, build_platform, args, build_meta, runner)
+                    }
+                }
+                .dir(&self.bin_info.cwd)
+            }
+        };
 
         let package = self.bin_info.package;
 
-        let cmd = cmd(AsRef::<Path>::as_ref(self.binary), args)
-            .dir(&self.bin_info.cwd)
+        let mut cmd = cmd
             // These environment variables are set at runtime by cargo test:
             // https://doc.rust-lang.org/cargo/reference/environment-variables.html#environment-variables-cargo-sets-for-crates
             .env(
@@ -522,8 +1917,37 @@ impl<'a> TestInstance<'a> {
                 "CARGO_PKG_REPOSITORY",
                 package.repository().unwrap_or_default(),
             );
+        for (env, path) in &self.bin_info.required_binaries {
+            cmd = cmd.env(env, path);
+        }
+        if let Some(synthetic) = self.synthetic {
+            for (key, value) in &synthetic.env {
+                cmd = cmd.env(key, value);
+            }
+        }
+
+        match self.variant {
+            Some(variant) => cmd.env(variant.env_var, variant.value),
+            None => cmd,
+        }
+    }
+}
+
+/// A variant of a test, produced by a [`MultiplexRule`](crate::config::MultiplexRule).
+///
+/// Each variant is run with the environment variable set to the given value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TestVariant<'a> {
+    /// The name of the environment variable.
+    pub env_var: &'a str,
+
+    /// The value of the environment variable for this variant.
+    pub value: &'a str,
+}
 
-        cmd
+impl<'a> fmt::Display for TestVariant<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}={}]", self.env_var, self.value)
     }
 }
 
@@ -570,6 +1994,7 @@ mod tests {
         let fake_binary_name = "fake-binary".to_owned();
         let fake_binary_id = "fake-package::fake-binary".to_owned();
         let test_binary = RustTestArtifact {
+            build_platform: BuildPlatform::Target,
             binary_path: "/fake/binary".into(),
             cwd: fake_cwd.clone(),
             package: package_metadata(),
@@ -584,26 +2009,37 @@ mod tests {
         assert_eq!(
             test_list.rust_suites,
             btreemap! {
-                "/fake/binary".into() => RustTestSuite {
+                "fake-package::fake-binary".to_owned() => RustTestSuite {
+                    binary_path: "/fake/binary".into(),
+                    build_platform: BuildPlatform::Target,
                     testcases: btreemap! {
                         "tests::foo::test_bar".to_owned() => RustTestCaseSummary {
                             ignored: false,
+                            ignore_reason: None,
+                            listed_name: None,
                             filter_match: FilterMatch::Matches,
                         },
                         "tests::baz::test_quux".to_owned() => RustTestCaseSummary {
                             ignored: false,
+                            ignore_reason: None,
+                            listed_name: None,
                             filter_match: FilterMatch::Matches,
                         },
                         "tests::ignored::test_bar".to_owned() => RustTestCaseSummary {
                             ignored: true,
+                            ignore_reason: None,
+                            listed_name: None,
                             filter_match: FilterMatch::Mismatch { reason: MismatchReason::Ignored },
                         },
                         "tests::baz::test_ignored".to_owned() => RustTestCaseSummary {
                             ignored: true,
+                            ignore_reason: None,
+                            listed_name: None,
                             filter_match: FilterMatch::Mismatch { reason: MismatchReason::Ignored },
                         },
                     },
                     cwd: fake_cwd,
+                    required_binaries: BTreeMap::new(),
                     package: package_metadata(),
                     binary_name: fake_binary_name,
                     binary_id: fake_binary_id,
@@ -630,6 +2066,7 @@ mod tests {
                   "binary-name": "fake-binary",
                   "package-id": "metadata-helper 0.1.0 (path+file:///Users/fakeuser/local/testcrates/metadata/metadata-helper)",
                   "binary-path": "/fake/binary",
+                  "build-platform": "target",
                   "cwd": "/fake/cwd",
                   "testcases": {
                     "tests::baz::test_ignored": {
@@ -660,7 +2097,8 @@ mod tests {
                     }
                   }
                 }
-              }
+              },
+              "skipped-binaries": []
             }"#};
 
         assert_eq!(
@@ -681,6 +2119,359 @@ mod tests {
                 .expect("json-pretty succeeded"),
             EXPECTED_JSON_PRETTY
         );
+
+        let mut csv = vec![];
+        test_list
+            .write_delimited(DelimitedFormat::Csv, &mut csv)
+            .expect("csv succeeded");
+        assert_eq!(
+            String::from_utf8(csv).expect("csv is valid UTF-8"),
+            indoc! {"
+                package,binary-id,test-name,ignored,filter-match,platform
+                metadata-helper,fake-package::fake-binary,tests::baz::test_ignored,true,ignored,target
+                metadata-helper,fake-package::fake-binary,tests::baz::test_quux,false,matches,target
+                metadata-helper,fake-package::fake-binary,tests::foo::test_bar,false,matches,target
+                metadata-helper,fake-package::fake-binary,tests::ignored::test_bar,true,ignored,target
+            "}
+        );
+    }
+
+    #[test]
+    fn empty_binaries() {
+        let make_list = |filter: &TestFilterBuilder, empty_binaries| {
+            let artifacts = ["empty-a", "empty-b", "full"].into_iter().map(|name| {
+                let test_binary = RustTestArtifact {
+                    build_platform: BuildPlatform::Target,
+                    binary_path: format!("/fake/{}", name).into(),
+                    cwd: "/fake/cwd".into(),
+                    package: package_metadata(),
+                    binary_name: name.to_owned(),
+                    binary_id: format!("fake-package::{}", name),
+                };
+                let output = if name == "full" {
+                    "test_one: test\n"
+                } else {
+                    ""
+                };
+                (test_binary, output, "")
+            });
+            let mut test_list =
+                TestList::new_with_outputs(artifacts, filter).expect("valid output");
+            test_list
+                .handle_empty_binaries(filter, empty_binaries)
+                .map(|()| test_list)
+        };
+        let binary_ids = |test_list: &TestList<'_>| {
+            test_list
+                .iter()
+                .map(|(_, suite)| suite.binary_id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let any_filter = TestFilterBuilder::any(RunIgnored::Default);
+        let test_list = make_list(&any_filter, EmptyBinaries::List).expect("list succeeded");
+        assert_eq!(test_list.binary_count(), 3);
+        assert_eq!(
+            test_list
+                .to_string(OutputFormat::Plain)
+                .expect("plain succeeded"),
+            indoc! {"
+                fake-package::empty-a:
+                  bin: /fake/empty-a
+                  cwd: /fake/cwd
+                    (no tests)
+                fake-package::empty-b:
+                  bin: /fake/empty-b
+                  cwd: /fake/cwd
+                    (no tests)
+                fake-package::full:
+                  bin: /fake/full
+                  cwd: /fake/cwd
+                    test_one
+            "}
+        );
+
+        let test_list = make_list(&any_filter, EmptyBinaries::Hide).expect("hide succeeded");
+        assert_eq!(binary_ids(&test_list), vec!["fake-package::full"]);
+        assert_eq!(
+            test_list.skipped_binary_count(),
+            0,
+            "hidden binaries aren't skipped"
+        );
+
+        match make_list(&any_filter, EmptyBinaries::Fail) {
+            Err(ParseTestListError::EmptyBinaries { binary_ids }) => {
+                assert_eq!(
+                    binary_ids,
+                    vec!["fake-package::empty-a", "fake-package::empty-b"]
+                );
+            }
+            other => panic!(
+                "expected empty binaries error, found {:?}",
+                other.map(|_| ())
+            ),
+        }
+
+        // Only empty binaries selected by the filter are subject to the setting.
+        let mut expr_filter = TestFilterBuilder::any(RunIgnored::Default);
+        expr_filter.set_exprs([
+            "test(test_one)".parse().unwrap(),
+            "empty-binary() & binary(fake-package::empty-a)"
+                .parse()
+                .unwrap(),
+        ]);
+        let test_list = make_list(&expr_filter, EmptyBinaries::List).expect("list succeeded");
+        assert_eq!(
+            binary_ids(&test_list),
+            vec!["fake-package::empty-a", "fake-package::full"]
+        );
+        assert_eq!(
+            test_list.skipped_binaries().collect::<Vec<_>>(),
+            vec!["fake-package::empty-b"]
+        );
+        match make_list(&expr_filter, EmptyBinaries::Fail) {
+            Err(ParseTestListError::EmptyBinaries { binary_ids }) => {
+                assert_eq!(binary_ids, vec!["fake-package::empty-a"]);
+            }
+            other => panic!(
+                "expected empty binaries error, found {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+
+    #[test]
+    fn binary_ids() {
+        assert_eq!(
+            make_binary_id("my-package", "lib", "my_package"),
+            "my-package"
+        );
+        assert_eq!(
+            make_binary_id("my-package", "proc-macro", "my_package"),
+            "my-package"
+        );
+        assert_eq!(
+            make_binary_id("my-package", "test", "basic"),
+            "my-package::basic"
+        );
+        assert_eq!(
+            make_binary_id("my-package", "test", "my-package"),
+            "my-package::my-package",
+            "integration tests don't collide with the library"
+        );
+        assert_eq!(
+            make_binary_id("my-package", "test", "my_package"),
+            "my-package::my_package",
+            "integration tests named after the library crate keep their target name"
+        );
+        assert_eq!(
+            make_binary_id("my-package", "bin", "basic"),
+            "my-package::bin/basic",
+            "binaries don't collide with integration tests"
+        );
+        assert_eq!(
+            make_binary_id("my-package", "example", "demo"),
+            "my-package::example/demo"
+        );
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let non_ignored_output = indoc! {"
+            tests::dup: test
+            tests::other: test
+            tests::dup: test
+            tests::dup #2: test
+            tests::dup: test
+            tests::ignored: test
+            tests::ignored: test
+        "};
+        let ignored_output = indoc! {"
+            tests::ignored: test
+            tests::ignored: test
+        "};
+
+        let test_filter = TestFilterBuilder::any(RunIgnored::All);
+        let test_binary = RustTestArtifact {
+            build_platform: BuildPlatform::Target,
+            binary_path: "/fake/binary".into(),
+            cwd: "/fake/cwd".into(),
+            package: package_metadata(),
+            binary_name: "fake-binary".to_owned(),
+            binary_id: "fake-package::fake-binary".to_owned(),
+        };
+        let test_list = TestList::new_with_outputs(
+            iter::once((test_binary, &non_ignored_output, &ignored_output)),
+            &test_filter,
+        )
+        .expect("valid output");
+
+        assert_eq!(test_list.test_count(), 7, "duplicate tests are counted");
+        let tests: Vec<_> = test_list
+            .iter_tests()
+            .map(|instance| {
+                (
+                    instance.name,
+                    instance.test_info.listed_name.as_deref(),
+                    instance.test_info.ignored,
+                )
+            })
+            .collect();
+        assert_eq!(
+            tests,
+            vec![
+                ("tests::dup", None, false),
+                ("tests::dup #2", None, false),
+                ("tests::dup #3", Some("tests::dup"), false),
+                ("tests::dup #4", Some("tests::dup"), false),
+                ("tests::ignored", None, true),
+                ("tests::ignored #2", Some("tests::ignored"), true),
+                ("tests::other", None, false),
+            ]
+        );
+        assert_eq!(test_list.renamed_tests().count(), 3);
+
+        let renamed = test_list
+            .iter_tests()
+            .find(|instance| instance.name == "tests::dup #3")
+            .expect("renamed test exists");
+        let expression = format!(
+            "{:?}",
+            renamed.make_expression(
+                test_list.build_meta(),
+                test_list.target_runner(),
+                None,
+                None,
+            )
+        );
+        assert!(
+            expression.contains("\"tests::dup\"") && !expression.contains("#3"),
+            "renamed tests are run by their listed name: {}",
+            expression
+        );
+    }
+
+    #[test]
+    fn test_parse_discovery_protocol() {
+        let output = indoc! {r#"
+            nextest-discovery v1
+            {"name": "fixtures/b.txt"}
+            {"name": "fixtures/a: test.txt", "ignored": false}
+            {"name": "fixtures/\"quoted\".txt", "ignored": true, "ignore_reason": "slow"}
+        "#};
+
+        let test_filter = TestFilterBuilder::any(RunIgnored::Default);
+        let test_binary = RustTestArtifact {
+            build_platform: BuildPlatform::Target,
+            binary_path: "/fake/binary".into(),
+            cwd: "/fake/cwd".into(),
+            package: package_metadata(),
+            binary_name: "fake-binary".to_owned(),
+            binary_id: "fake-package::fake-binary".to_owned(),
+        };
+        let test_list =
+            TestList::new_with_outputs(iter::once((test_binary, output, "")), &test_filter)
+                .expect("valid output");
+        let testcases = &test_list.rust_suites["fake-package::fake-binary"].testcases;
+        assert_eq!(
+            testcases,
+            &btreemap! {
+                "fixtures/a: test.txt".to_owned() => RustTestCaseSummary {
+                    ignored: false,
+                    ignore_reason: None,
+                    listed_name: None,
+                    filter_match: FilterMatch::Matches,
+                },
+                "fixtures/b.txt".to_owned() => RustTestCaseSummary {
+                    ignored: false,
+                    ignore_reason: None,
+                    listed_name: None,
+                    filter_match: FilterMatch::Matches,
+                },
+                "fixtures/\"quoted\".txt".to_owned() => RustTestCaseSummary {
+                    ignored: true,
+                    ignore_reason: Some("slow".to_owned()),
+                    listed_name: None,
+                    filter_match: FilterMatch::Mismatch { reason: MismatchReason::Ignored },
+                },
+            }
+        );
+
+        let invalid = indoc! {"
+            nextest-discovery v1
+            fixtures/a.txt: test
+        "};
+        let test_binary = RustTestArtifact {
+            build_platform: BuildPlatform::Target,
+            binary_path: "/fake/binary".into(),
+            cwd: "/fake/cwd".into(),
+            package: package_metadata(),
+            binary_name: "fake-binary".to_owned(),
+            binary_id: "fake-package::fake-binary".to_owned(),
+        };
+        TestList::new_with_outputs(iter::once((test_binary, invalid, "")), &test_filter)
+            .expect_err("terse lines are invalid in discovery output");
+    }
+
+    #[test]
+    fn test_parse_libtest_json() {
+        let output = indoc! {r#"
+            { "type": "suite", "event": "discovery" }
+            { "type": "test", "event": "discovered", "name": "tests::foo", "ignore": false, "ignore_message": "", "source_path": "src/lib.rs", "start_line": 3, "start_col": 12, "end_line": 3, "end_col": 16 }
+            { "type": "test", "event": "discovered", "name": "tests::ignored", "ignore": true, "ignore_message": "needs network", "source_path": "src/lib.rs", "start_line": 4, "start_col": 40, "end_line": 4, "end_col": 43 }
+            { "type": "test", "event": "discovered", "name": "tests::ignored_no_reason", "ignore": true, "ignore_message": "", "source_path": "src/lib.rs", "start_line": 5, "start_col": 22, "end_line": 5, "end_col": 26 }
+            { "type": "suite", "event": "completed", "tests": 3, "benchmarks": 0, "total": 3, "ignored": 2 }
+        "#};
+
+        let test_filter = TestFilterBuilder::any(RunIgnored::Default);
+        let test_binary = RustTestArtifact {
+            build_platform: BuildPlatform::Target,
+            binary_path: "/fake/binary".into(),
+            cwd: "/fake/cwd".into(),
+            package: package_metadata(),
+            binary_name: "fake-binary".to_owned(),
+            binary_id: "fake-package::fake-binary".to_owned(),
+        };
+        let test_list =
+            TestList::new_with_outputs(iter::once((test_binary, output, "")), &test_filter)
+                .expect("valid output");
+        let testcases = &test_list.rust_suites["fake-package::fake-binary"].testcases;
+        assert_eq!(
+            testcases,
+            &btreemap! {
+                "tests::foo".to_owned() => RustTestCaseSummary {
+                    ignored: false,
+                    ignore_reason: None,
+                    listed_name: None,
+                    filter_match: FilterMatch::Matches,
+                },
+                "tests::ignored".to_owned() => RustTestCaseSummary {
+                    ignored: true,
+                    ignore_reason: Some("needs network".to_owned()),
+                    listed_name: None,
+                    filter_match: FilterMatch::Mismatch { reason: MismatchReason::Ignored },
+                },
+                "tests::ignored_no_reason".to_owned() => RustTestCaseSummary {
+                    ignored: true,
+                    ignore_reason: None,
+                    listed_name: None,
+                    filter_match: FilterMatch::Mismatch { reason: MismatchReason::Ignored },
+                },
+            }
+        );
+        assert_eq!(
+            test_list
+                .to_string(OutputFormat::Plain)
+                .expect("plain succeeded"),
+            indoc! {"
+                fake-package::fake-binary:
+                  bin: /fake/binary
+                  cwd: /fake/cwd
+                    tests::foo
+                    tests::ignored (skipped, ignored: needs network)
+                    tests::ignored_no_reason (skipped)
+            "}
+        );
     }
 
     static PACKAGE_GRAPH_FIXTURE: Lazy<PackageGraph> = Lazy::new(|| {
@@ -692,6 +2483,56 @@ mod tests {
     });
 
     static PACKAGE_METADATA_ID: &str = "metadata-helper 0.1.0 (path+file:///Users/fakeuser/local/testcrates/metadata/metadata-helper)";
+    #[test]
+    fn test_apply_default_filter() {
+        let non_ignored_output = indoc! {"
+            tests::foo::test_bar: test
+            tests::baz::test_quux: test
+            tests::slow::test_big: test
+        "};
+        let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["quux", "slow"]);
+        let test_binary = RustTestArtifact {
+            build_platform: BuildPlatform::Target,
+            binary_path: "/fake/binary".into(),
+            cwd: "/fake/cwd".into(),
+            package: package_metadata(),
+            binary_name: "fake-binary".to_owned(),
+            binary_id: "fake-package::fake-binary".to_owned(),
+        };
+        let mut test_list = TestList::new_with_outputs(
+            iter::once((test_binary, &non_ignored_output, &"")),
+            &test_filter,
+        )
+        .expect("valid output");
+
+        let default_filter: FilterExpr = "not test(~slow)".parse().unwrap();
+        test_list.apply_default_filter(&default_filter);
+
+        let filter_matches: Vec<_> = test_list
+            .iter_tests()
+            .map(|instance| (instance.name, instance.test_info.filter_match))
+            .collect();
+        assert_eq!(
+            filter_matches,
+            [
+                ("tests::baz::test_quux", FilterMatch::Matches),
+                (
+                    "tests::foo::test_bar",
+                    FilterMatch::Mismatch {
+                        reason: MismatchReason::String
+                    },
+                ),
+                (
+                    "tests::slow::test_big",
+                    FilterMatch::Mismatch {
+                        reason: MismatchReason::DefaultFilter
+                    },
+                ),
+            ]
+        );
+        assert_eq!(test_list.skip_count(), 2);
+    }
+
     fn package_metadata() -> PackageMetadata<'static> {
         PACKAGE_GRAPH_FIXTURE
             .metadata(&PackageId::new(PACKAGE_METADATA_ID))
@@ -1,18 +1,19 @@
 // Copyright (c) The diem-devtools Contributors
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-// clippy complains about the Arbitrary impl for OutputFormat
-#![allow(clippy::unit_arg)]
+// clippy and rustc complain about the Arbitrary impl for OutputFormat
+#![allow(clippy::unit_arg, non_local_definitions)]
 
 use crate::errors::OutputFormatParseError;
 use serde::Serialize;
 use std::{fmt, io, str::FromStr};
 
 /// Output formats for nextest.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
 pub enum OutputFormat {
     /// A plain, user-readable output format.
+    #[default]
     Plain,
 
     /// Machine-readable output format.
@@ -50,12 +51,6 @@ impl FromStr for OutputFormat {
     }
 }
 
-impl Default for OutputFormat {
-    fn default() -> Self {
-        OutputFormat::Plain
-    }
-}
-
 /// A serialized, machine-readable output format.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
@@ -80,6 +75,47 @@ impl SerializableFormat {
     }
 }
 
+/// A delimited, one-row-per-test output format for test lists, for spreadsheets and shell pipelines.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DelimitedFormat {
+    /// Comma-separated values. Fields containing commas, quotes or newlines are quoted.
+    Csv,
+    /// Tab-separated values. Tabs and newlines within fields are replaced with spaces.
+    Tsv,
+}
+
+impl DelimitedFormat {
+    /// Writes a row of fields in this format to the writer.
+    pub fn write_row<'a>(
+        self,
+        fields: impl IntoIterator<Item = &'a str>,
+        mut writer: impl io::Write,
+    ) -> io::Result<()> {
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                writer.write_all(self.separator().as_bytes())?;
+            }
+            match self {
+                DelimitedFormat::Csv if field.contains([',', '"', '\n', '\r']) => {
+                    write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+                }
+                DelimitedFormat::Csv => writer.write_all(field.as_bytes())?,
+                DelimitedFormat::Tsv => {
+                    writer.write_all(field.replace(['\t', '\n', '\r'], " ").as_bytes())?
+                }
+            }
+        }
+        writeln!(writer)
+    }
+
+    fn separator(self) -> &'static str {
+        match self {
+            DelimitedFormat::Csv => ",",
+            DelimitedFormat::Tsv => "\t",
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -90,4 +126,21 @@ mod test {
             variant.parse::<OutputFormat>().expect("variant is valid");
         }
     }
+
+    #[test]
+    fn delimited_rows() {
+        let fields = ["my-crate", "tests::a,b", "say \"hi\"", "tab\there"];
+        let mut csv = vec![];
+        DelimitedFormat::Csv.write_row(fields, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "my-crate,\"tests::a,b\",\"say \"\"hi\"\"\",tab\there\n"
+        );
+        let mut tsv = vec![];
+        DelimitedFormat::Tsv.write_row(fields, &mut tsv).unwrap();
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "my-crate\ttests::a,b\tsay \"hi\"\ttab here\n"
+        );
+    }
 }
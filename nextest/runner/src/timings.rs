@@ -0,0 +1,434 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Predicting how long tests will take, based on previous runs.
+//!
+//! Timing data is read from the [report](crate::run_report) written out by a previous run, or from
+//! a [`TimingDatabase`] merged from the reports of several runs. It's used to enforce a maximum
+//! duration for a shard: see [`TestRunnerBuilder::set_max_duration`].
+//!
+//! # Timing databases
+//!
+//! When tests are split across CI shards, each shard only has timing data for its own tests.
+//! `cargo nextest timings merge` combines the run reports of every shard, along with the timing
+//! database from earlier merges, into a single timing database that can be committed or cached
+//! centrally:
+//!
+//! ```sh
+//! cargo nextest timings merge --output timings.json timings.json shard-*/run-report.json
+//! ```
+//!
+//! Inputs are merged in order. If a test is in several inputs, its duration is an exponentially
+//! weighted moving average of the durations in each of them, with `--weight` given to the newer
+//! duration. Each run is only merged once: merging a report whose run is already in the database
+//! has no effect, so merges can be checkpointed and repeated.
+//!
+//! [`TestRunnerBuilder::set_max_duration`]: crate::runner::TestRunnerBuilder::set_max_duration
+
+use crate::{
+    errors::TimingDatabaseError, renames::TestRenames, run_store::write_atomic,
+    test_list::TestInstance,
+};
+use camino::Utf8Path;
+use nextest_metadata::{
+    RunReportSummary, TestReportStatus, TestTimingSummary, TimingDatabaseSummary,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Predicted durations for tests, based on a previous run.
+///
+/// Tests without timing data, for example because they were added since the previous run or
+/// because there's no previous run at all, are predicted to take a default estimate. This is
+/// configured through [`NextestProfile::default_duration_estimate`].
+///
+/// [`NextestProfile::default_duration_estimate`]: crate::config::NextestProfile::default_duration_estimate
+#[derive(Clone, Debug, Default)]
+pub struct TestTimings {
+    // (binary ID, test name, variant) -> duration.
+    durations: HashMap<(String, String, Option<String>), Duration>,
+    // Used for tests that have no timing data.
+    default_estimate: Duration,
+}
+
+impl TestTimings {
+    /// Creates a new `TestTimings` without any timing data, where every test is predicted to take
+    /// the given default estimate.
+    pub fn new(default_estimate: Duration) -> Self {
+        Self {
+            durations: HashMap::new(),
+            default_estimate,
+        }
+    }
+
+    /// Reads timing data from the report of a previous run.
+    ///
+    /// The duration of the last attempt of each test that was run is used as its prediction. Tests
+    /// that weren't run are predicted to take the given default estimate.
+    pub fn from_report(report: &RunReportSummary, default_estimate: Duration) -> Self {
+        let durations: HashMap<_, _> = report
+            .tests
+            .iter()
+            .filter(|test| test.status != TestReportStatus::Skipped)
+            .filter_map(|test| {
+                let last_attempt = test.attempts.last()?;
+                Some((
+                    (
+                        test.binary_id.clone(),
+                        test.name.clone(),
+                        test.variant.clone(),
+                    ),
+                    Duration::from_secs_f64(last_attempt.time_taken),
+                ))
+            })
+            .collect();
+        Self {
+            durations,
+            default_estimate,
+        }
+    }
+
+    /// Reads timing data from a timing database.
+    pub fn from_database(database: &TimingDatabase, default_estimate: Duration) -> Self {
+        let durations = database
+            .tests
+            .iter()
+            .map(|(key, timing)| (key.clone(), Duration::from_secs_f64(timing.duration)))
+            .collect();
+        Self {
+            durations,
+            default_estimate,
+        }
+    }
+
+    /// Returns the number of tests with timing data.
+    pub fn len(&self) -> usize {
+        self.durations.len()
+    }
+
+    /// Returns true if there's no timing data.
+    pub fn is_empty(&self) -> bool {
+        self.durations.is_empty()
+    }
+
+    /// Returns the duration predicted for tests without timing data.
+    pub fn default_estimate(&self) -> Duration {
+        self.default_estimate
+    }
+
+    /// Returns the duration the given test took in the previous run, or `None` if there's no
+    /// timing data for it.
+    pub fn get(&self, test_instance: &TestInstance<'_>) -> Option<Duration> {
+        let key = (
+            test_instance.bin_info.binary_id.clone(),
+            test_instance.name.to_owned(),
+            test_instance.variant.map(|variant| variant.to_string()),
+        );
+        self.durations.get(&key).copied()
+    }
+
+    /// Returns the predicted duration for the given test, falling back to the default estimate.
+    pub fn predict(&self, test_instance: &TestInstance<'_>) -> Duration {
+        self.get(test_instance).unwrap_or(self.default_estimate)
+    }
+}
+
+/// The durations of tests, merged from the reports of several runs.
+///
+/// See the [module documentation](self) for more.
+#[derive(Clone, Debug, Default)]
+pub struct TimingDatabase {
+    run_ids: BTreeSet<Uuid>,
+    // (binary ID, test name, variant) -> timing.
+    tests: BTreeMap<(String, String, Option<String>), TestTiming>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TestTiming {
+    duration: f64,
+    samples: usize,
+}
+
+/// An input to [`TimingDatabase::merge`].
+#[derive(Clone, Debug)]
+pub enum TimingInput {
+    /// The report of a single run.
+    Report(RunReportSummary),
+
+    /// A timing database.
+    Database(TimingDatabase),
+}
+
+impl TimingInput {
+    /// Reads a timing database or a run report from the given path.
+    pub fn read(path: &Utf8Path) -> Result<Self, TimingDatabaseError> {
+        let json = fs::read_to_string(path).map_err(|error| TimingDatabaseError::Read {
+            path: path.to_owned(),
+            error,
+        })?;
+        if let Ok(summary) = TimingDatabaseSummary::parse_json(&json) {
+            return Ok(TimingInput::Database(TimingDatabase::from_summary(summary)));
+        }
+        RunReportSummary::parse_json(&json)
+            .map(TimingInput::Report)
+            .map_err(|error| TimingDatabaseError::Parse {
+                path: path.to_owned(),
+                error,
+            })
+    }
+}
+
+impl TimingDatabase {
+    /// The default weight given to newer durations by [`Self::merge`].
+    pub const DEFAULT_WEIGHT: f64 = 0.5;
+
+    /// Creates a new, empty timing database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a timing database from its serialized form.
+    pub fn from_summary(summary: TimingDatabaseSummary) -> Self {
+        let tests = summary
+            .timings
+            .into_iter()
+            .map(|timing| {
+                (
+                    (timing.binary_id, timing.name, timing.variant),
+                    TestTiming {
+                        duration: timing.duration,
+                        samples: timing.samples,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            run_ids: summary.run_ids,
+            tests,
+        }
+    }
+
+    /// Returns the number of tests in the database.
+    pub fn len(&self) -> usize {
+        self.tests.len()
+    }
+
+    /// Returns true if the database has no tests.
+    pub fn is_empty(&self) -> bool {
+        self.tests.is_empty()
+    }
+
+    /// Merges a run report or another database into this one, giving `weight` (between 0 and 1)
+    /// to the newer duration of tests that are in both.
+    ///
+    /// Returns false, and leaves the database unchanged, if every run in the input has already
+    /// been merged in.
+    pub fn merge(&mut self, input: &TimingInput, weight: f64) -> bool {
+        match input {
+            TimingInput::Report(report) => {
+                if !self.run_ids.insert(report.metadata.run_id) {
+                    return false;
+                }
+                // As with predictions, the duration of the last attempt of each test that was run
+                // is used.
+                for test in &report.tests {
+                    if test.status == TestReportStatus::Skipped {
+                        continue;
+                    }
+                    if let Some(last_attempt) = test.attempts.last() {
+                        self.add(
+                            (
+                                test.binary_id.clone(),
+                                test.name.clone(),
+                                test.variant.clone(),
+                            ),
+                            TestTiming {
+                                duration: last_attempt.time_taken,
+                                samples: 1,
+                            },
+                            weight,
+                        );
+                    }
+                }
+            }
+            TimingInput::Database(database) => {
+                if !database.run_ids.is_empty() && database.run_ids.is_subset(&self.run_ids) {
+                    return false;
+                }
+                self.run_ids.extend(database.run_ids.iter().copied());
+                for (key, timing) in &database.tests {
+                    self.add(key.clone(), *timing, weight);
+                }
+            }
+        }
+        true
+    }
+
+    /// Renames the tests in this database to their current names.
+    ///
+    /// See [`renames`](crate::renames) for more.
+    pub fn apply_renames(&mut self, renames: &TestRenames) {
+        if renames.is_empty() {
+            return;
+        }
+        let tests = std::mem::take(&mut self.tests);
+        for ((binary_id, name, variant), timing) in tests {
+            let (binary_id, name) = renames.rename(&binary_id, &name);
+            self.tests.insert((binary_id, name, variant), timing);
+        }
+    }
+
+    /// Returns the serialized form of this database.
+    pub fn to_summary(&self) -> TimingDatabaseSummary {
+        TimingDatabaseSummary {
+            run_ids: self.run_ids.clone(),
+            timings: self
+                .tests
+                .iter()
+                .map(|((binary_id, name, variant), timing)| TestTimingSummary {
+                    binary_id: binary_id.clone(),
+                    name: name.clone(),
+                    variant: variant.clone(),
+                    duration: timing.duration,
+                    samples: timing.samples,
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes out this database to the given path.
+    ///
+    /// The database is written to a temporary file first and then renamed into place, so an
+    /// interrupted merge never leaves a partially written database behind.
+    pub fn write(&self, path: &Utf8Path) -> Result<(), TimingDatabaseError> {
+        let json =
+            serde_json::to_vec_pretty(&self.to_summary()).expect("timing database is serializable");
+        write_atomic(path, &json).map_err(TimingDatabaseError::Write)
+    }
+
+    fn add(&mut self, key: (String, String, Option<String>), timing: TestTiming, weight: f64) {
+        self.tests
+            .entry(key)
+            .and_modify(|existing| {
+                existing.duration = existing.duration * (1.0 - weight) + timing.duration * weight;
+                existing.samples += timing.samples;
+            })
+            .or_insert(timing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nextest_metadata::{RunMetadata, TestAttemptSummary, TestReportSummary};
+
+    #[test]
+    fn merge_timing_databases() {
+        let report = |run_id: u128, tests: &[(&str, f64, TestReportStatus)]| {
+            TimingInput::Report(RunReportSummary {
+                metadata: RunMetadata {
+                    run_id: Uuid::from_u128(run_id),
+                    nextest_version: "0.1.0".to_owned(),
+                    profile_name: "ci".to_owned(),
+                    host_triple: None,
+                    target_triple: None,
+                    invocation: vec![],
+                },
+                success: true,
+                time_taken: 1.0,
+                tests: tests
+                    .iter()
+                    .map(|&(name, time_taken, status)| TestReportSummary {
+                        binary_id: "my-crate".to_owned(),
+                        name: name.to_owned(),
+                        variant: None,
+                        status,
+                        skip_reason: None,
+                        quarantined: false,
+                        time_taken,
+                        attempts: vec![TestAttemptSummary {
+                            attempt: 1,
+                            status,
+                            time_taken,
+                            stdout_path: None,
+                            stderr_path: None,
+                            attachments: vec![],
+                            abort_reason: None,
+                            core_dump_path: None,
+                            sanitizer_summary: None,
+                            coverage_profile_path: None,
+                            property_test: None,
+                            peak_rss: None,
+                        }],
+                    })
+                    .collect(),
+            })
+        };
+
+        // Two shards of the same CI run, with different tests.
+        let mut database = TimingDatabase::new();
+        assert!(database.merge(&report(1, &[("a", 2.0, TestReportStatus::Pass)]), 0.5));
+        assert!(database.merge(
+            &report(
+                2,
+                &[
+                    ("b", 4.0, TestReportStatus::Fail),
+                    ("skipped", 0.0, TestReportStatus::Skipped),
+                ]
+            ),
+            0.5
+        ));
+        assert_eq!(database.len(), 2, "skipped tests aren't recorded");
+
+        // The checkpoint is merged into a database with a newer run.
+        let checkpoint = TimingDatabaseSummary::parse_json(
+            serde_json::to_string(&database.to_summary()).unwrap(),
+        )
+        .unwrap();
+        let mut merged = TimingDatabase::new();
+        assert!(merged.merge(
+            &TimingInput::Database(TimingDatabase::from_summary(checkpoint)),
+            0.25
+        ));
+        assert!(merged.merge(&report(3, &[("a", 6.0, TestReportStatus::Pass)]), 0.25));
+        assert!(
+            !merged.merge(&report(1, &[("a", 100.0, TestReportStatus::Pass)]), 0.25),
+            "runs are only merged once"
+        );
+        assert!(
+            !merged.merge(&TimingInput::Database(database.clone()), 0.25),
+            "databases that were already merged are skipped"
+        );
+
+        let summary = merged.to_summary();
+        assert_eq!(
+            summary
+                .run_ids
+                .iter()
+                .map(|id| id.as_u128())
+                .collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        let timings: Vec<_> = summary
+            .timings
+            .iter()
+            .map(|timing| (timing.name.as_str(), timing.duration, timing.samples))
+            .collect();
+        assert_eq!(timings, [("a", 3.0, 2), ("b", 4.0, 1)]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = camino::Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let db_path = root.join("timings.json");
+        merged.write(&db_path).unwrap();
+        match TimingInput::read(&db_path).unwrap() {
+            TimingInput::Database(read) => assert_eq!(read.to_summary(), summary),
+            TimingInput::Report(_) => panic!("expected a timing database"),
+        }
+        let predictions = TestTimings::from_database(&merged, Duration::from_secs(1));
+        assert_eq!(predictions.len(), 2);
+    }
+}
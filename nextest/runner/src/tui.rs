@@ -0,0 +1,800 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! An interactive terminal UI for test runs, shown by `cargo nextest run --tui`.
+//!
+//! The TUI takes over the terminal while it's active. It has:
+//!
+//! * a header with the progress of the run,
+//! * a pane with the tests that are currently running, longest-running first,
+//! * a pane with the tests that failed. The selected failure can be expanded to show its captured
+//!   output, and
+//! * once the run has finished, a filter box to re-run the tests whose names contain a string.
+//!
+//! [`Tui`] doesn't run tests itself. The events of a run are passed in through
+//! [`Tui::report_event`], and once the run has finished, [`Tui::wait_for_action`] returns what the
+//! user wants to do next.
+
+use crate::{
+    errors::TuiError,
+    helpers::{terminal_size, write_truncated},
+    reporter::TestEvent,
+    runner::{ExecutionResult, RunStats},
+    stopwatch::ClockInstant,
+    test_list::TestInstance,
+};
+use crossbeam_channel::Receiver;
+use owo_colors::Style;
+use std::{
+    fmt,
+    io::{self, Write},
+    time::Duration,
+};
+
+/// What the user wants to do once a run has finished.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TuiAction {
+    /// Re-run the tests from the original run whose names contain `filter`.
+    ///
+    /// An empty filter re-runs all of them.
+    Rerun {
+        /// The string to filter tests by.
+        filter: String,
+    },
+
+    /// Exit the TUI.
+    Quit,
+}
+
+/// An interactive terminal UI for test runs.
+///
+/// The terminal is restored once the `Tui` is dropped.
+pub struct Tui {
+    state: TuiState,
+    styles: TuiStyles,
+    keys: Receiver<Key>,
+    last_draw: Option<ClockInstant>,
+    #[cfg(unix)]
+    terminal: imp::Terminal,
+}
+
+impl Tui {
+    /// How often the runner should produce [`TestEvent::RunProgress`] events while the TUI is
+    /// shown.
+    pub const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Takes over the terminal: switches to the alternate screen, and starts reading keystrokes
+    /// from standard input.
+    ///
+    /// Standard input and standard error must both be terminals. Only supported on Unix platforms.
+    pub fn new() -> Result<Self, TuiError> {
+        #[cfg(unix)]
+        {
+            let (terminal, keys) = imp::Terminal::new()?;
+            Ok(Self {
+                state: TuiState::default(),
+                styles: TuiStyles::default(),
+                keys,
+                last_draw: None,
+                terminal,
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            Err(TuiError::Unsupported)
+        }
+    }
+
+    /// Uses colors in the TUI.
+    pub fn colorize(&mut self) {
+        self.styles.colorize();
+    }
+
+    /// Updates the TUI with an event from the run, and handles any keys pressed since the last
+    /// event.
+    pub fn report_event(&mut self, event: &TestEvent<'_>, filter: Option<&str>) -> io::Result<()> {
+        let redraw = match event {
+            TestEvent::RunStarted { .. } => {
+                self.state.start_run(filter);
+                #[cfg(unix)]
+                self.terminal.set_signals(true)?;
+                true
+            }
+            TestEvent::RunProgress { .. } | TestEvent::RunFinished { .. } => true,
+            _ => false,
+        };
+        self.state.update(event);
+        while let Ok(key) = self.keys.try_recv() {
+            // Actions are only taken once the run has finished.
+            self.state.handle_key(key);
+        }
+
+        let due = match self.last_draw {
+            Some(last_draw) => last_draw.elapsed() >= Self::REFRESH_INTERVAL,
+            None => true,
+        };
+        if redraw || due {
+            self.draw()?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the user to choose what to do, once a run has finished.
+    pub fn wait_for_action(&mut self) -> io::Result<TuiAction> {
+        // Ctrl-C is read as a keystroke so that it can be used to exit.
+        #[cfg(unix)]
+        self.terminal.set_signals(false)?;
+        loop {
+            self.draw()?;
+            let key = match self.keys.recv() {
+                Ok(key) => key,
+                Err(_) => return Ok(TuiAction::Quit),
+            };
+            if let Some(action) = self.state.handle_key(key) {
+                return Ok(action);
+            }
+        }
+    }
+
+    /// Returns the statistics for the last run shown.
+    pub fn run_stats(&self) -> &RunStats {
+        &self.state.run_stats
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (width, height) = terminal_size();
+        let lines = self.state.render(&self.styles, height);
+
+        let mut buf = Vec::with_capacity(width * height);
+        write!(buf, "\x1b[H")?;
+        for (idx, line) in lines.iter().enumerate() {
+            if idx > 0 {
+                write!(buf, "\r\n")?;
+            }
+            write_truncated(line, width, &mut buf)?;
+            write!(buf, "\x1b[K")?;
+        }
+        write!(buf, "\x1b[J")?;
+
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        stderr.write_all(&buf)?;
+        stderr.flush()?;
+        self.last_draw = Some(ClockInstant::now());
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Tui {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tui")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct TuiStyles {
+    title: Style,
+    count: Style,
+    pass: Style,
+    fail: Style,
+    skip: Style,
+    selected: Style,
+}
+
+impl TuiStyles {
+    fn colorize(&mut self) {
+        self.title = Style::new().bold();
+        self.count = Style::new().bold();
+        self.pass = Style::new().green().bold();
+        self.fail = Style::new().red().bold();
+        self.skip = Style::new().yellow().bold();
+        self.selected = Style::new().reversed();
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct TuiState {
+    // The number of runs started so far, and the filter for the current one.
+    run_number: usize,
+    filter: Option<String>,
+
+    finished: bool,
+    elapsed: Duration,
+    run_stats: RunStats,
+    running: Vec<RunningTest>,
+    failures: Vec<Failure>,
+
+    // The failure that's selected, whether its output is shown, and how far its output is scrolled.
+    selected: usize,
+    expanded: bool,
+    scroll: usize,
+
+    // Some while the filter box is being typed into.
+    filter_input: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct RunningTest {
+    name: String,
+    attempt: usize,
+    elapsed: Duration,
+}
+
+#[derive(Clone, Debug)]
+struct Failure {
+    name: String,
+    status: String,
+    time_taken: Duration,
+    output: Vec<String>,
+}
+
+impl TuiState {
+    // The number of lines that paging up or down in the output moves by.
+    const PAGE_SIZE: usize = 10;
+
+    fn start_run(&mut self, filter: Option<&str>) {
+        *self = Self {
+            run_number: self.run_number + 1,
+            filter: filter.map(|filter| filter.to_owned()),
+            ..Self::default()
+        };
+    }
+
+    fn update(&mut self, event: &TestEvent<'_>) {
+        match event {
+            TestEvent::RunStarted { run_count, .. } => {
+                self.run_stats.initial_run_count = *run_count;
+            }
+            TestEvent::RunProgress { snapshot } | TestEvent::RunSnapshot { snapshot } => {
+                self.elapsed = snapshot.elapsed;
+                self.run_stats = snapshot.run_stats;
+                self.running = snapshot
+                    .running
+                    .iter()
+                    .map(|test| RunningTest {
+                        name: test_name(&test.test_instance),
+                        attempt: test.attempt,
+                        elapsed: test.elapsed,
+                    })
+                    .collect();
+                // List the tests that have been running the longest first.
+                self.running
+                    .sort_by_key(|test| std::cmp::Reverse(test.elapsed));
+            }
+            TestEvent::TestFinished {
+                test_instance,
+                run_statuses,
+            } => {
+                let last_status = run_statuses.last_status();
+                let status = match last_status.result {
+                    ExecutionResult::Pass
+                    | ExecutionResult::Skip
+                    | ExecutionResult::ExpectedFail => return,
+                    ExecutionResult::Fail => match &last_status.sanitizer_report {
+                        Some(report) => report.sanitizer.label(),
+                        None => "FAIL",
+                    },
+                    ExecutionResult::ExecFail => "XFAIL",
+                    ExecutionResult::DiskQuotaExceeded => "QUOTA",
+                    ExecutionResult::Timeout => "TIMEOUT",
+                    ExecutionResult::UnexpectedPass => "UNEXP PASS",
+                };
+                let status = if last_status.attempt > 1 {
+                    format!("TRY {} {}", last_status.attempt, status)
+                } else {
+                    status.to_owned()
+                };
+
+                let mut output = Vec::new();
+                for (header, bytes) in [
+                    ("--- STDOUT ---", last_status.stdout()),
+                    ("--- STDERR ---", last_status.stderr()),
+                ] {
+                    if bytes.is_empty() {
+                        continue;
+                    }
+                    let stripped =
+                        strip_ansi_escapes::strip(bytes).unwrap_or_else(|_| bytes.to_vec());
+                    output.push(header.to_owned());
+                    output.extend(
+                        String::from_utf8_lossy(&stripped)
+                            .lines()
+                            .map(|line| line.replace('\t', "    ")),
+                    );
+                }
+                self.failures.push(Failure {
+                    name: test_name(test_instance),
+                    status,
+                    time_taken: last_status.time_taken,
+                    output,
+                });
+            }
+            TestEvent::RunFinished {
+                elapsed, run_stats, ..
+            } => {
+                self.finished = true;
+                self.elapsed = *elapsed;
+                self.run_stats = *run_stats;
+                self.running.clear();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_key(&mut self, key: Key) -> Option<TuiAction> {
+        if let Some(input) = &mut self.filter_input {
+            match key {
+                Key::Char(c) => input.push(c),
+                Key::Backspace => {
+                    input.pop();
+                }
+                Key::Enter => {
+                    let filter = input.clone();
+                    self.filter_input = None;
+                    return Some(TuiAction::Rerun { filter });
+                }
+                Key::Escape | Key::Interrupt => self.filter_input = None,
+                _ => {}
+            }
+            return None;
+        }
+
+        match key {
+            Key::Up | Key::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+                self.scroll = 0;
+            }
+            Key::Down | Key::Char('j') if self.selected + 1 < self.failures.len() => {
+                self.selected += 1;
+                self.scroll = 0;
+            }
+            Key::Enter | Key::Char(' ') => {
+                self.expanded = !self.expanded;
+                self.scroll = 0;
+            }
+            Key::PageUp => self.scroll = self.scroll.saturating_sub(Self::PAGE_SIZE),
+            Key::PageDown => {
+                let output_len = self
+                    .failures
+                    .get(self.selected)
+                    .map_or(0, |failure| failure.output.len());
+                self.scroll = (self.scroll + Self::PAGE_SIZE).min(output_len.saturating_sub(1));
+            }
+            Key::Char('/') if self.finished => self.filter_input = Some(String::new()),
+            Key::Char('r') if self.finished => {
+                return Some(TuiAction::Rerun {
+                    filter: self.filter.clone().unwrap_or_default(),
+                })
+            }
+            Key::Char('q') | Key::Escape | Key::Interrupt if self.finished => {
+                return Some(TuiAction::Quit)
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn render(&self, styles: &TuiStyles, height: usize) -> Vec<Vec<(String, Style)>> {
+        let run_stats = &self.run_stats;
+        let failed = run_stats.failed
+            + run_stats.exec_failed
+            + run_stats.disk_quota_exceeded
+            + run_stats.timed_out;
+
+        let mut header = vec![
+            ("nextest".to_owned(), styles.title),
+            (format!(" run {}", self.run_number), Style::new()),
+        ];
+        if let Some(filter) = &self.filter {
+            header.push((format!(" [filter: {}]", filter), Style::new()));
+        }
+        let status = if self.finished {
+            (
+                "  Finished",
+                if failed > 0 { styles.fail } else { styles.pass },
+            )
+        } else {
+            ("  Running", styles.pass)
+        };
+        header.extend([
+            (status.0.to_owned(), status.1),
+            (
+                format!(" [{:>9.3}s] ", self.elapsed.as_secs_f64()),
+                Style::new(),
+            ),
+            (
+                format!(
+                    "{}/{}",
+                    run_stats.final_run_count, run_stats.initial_run_count
+                ),
+                styles.count,
+            ),
+            (
+                format!(
+                    ": {} running, {} passed, {} failed, {} skipped",
+                    self.running.len(),
+                    run_stats.passed,
+                    failed,
+                    run_stats.skipped,
+                ),
+                Style::new(),
+            ),
+        ]);
+        let mut lines = vec![header];
+
+        // The running pane takes up at most a third of the screen, so that failures stay visible.
+        lines.push(pane_title(
+            &format!("Running ({})", self.running.len()),
+            styles,
+        ));
+        let max_running = (height.saturating_sub(6) / 3).max(1);
+        if self.running.is_empty() {
+            lines.push(vec![(placeholder(self.finished, "running"), Style::new())]);
+        }
+        for test in self.running.iter().take(max_running) {
+            let status = if test.attempt > 1 {
+                format!("TRY {} RUN", test.attempt)
+            } else {
+                "RUNNING".to_owned()
+            };
+            lines.push(vec![
+                (format!("{:>12}", status), styles.skip),
+                (
+                    format!(" [{:>8.3}s] {}", test.elapsed.as_secs_f64(), test.name),
+                    Style::new(),
+                ),
+            ]);
+        }
+        if self.running.len() > max_running {
+            lines.push(vec![(
+                format!(
+                    "{:>12} ... and {} more",
+                    "",
+                    self.running.len() - max_running
+                ),
+                Style::new(),
+            )]);
+        }
+
+        lines.push(pane_title(
+            &format!("Failures ({})", self.failures.len()),
+            styles,
+        ));
+        // Leave room for the last line, which has the filter box or the keys that can be pressed.
+        let available = height.saturating_sub(lines.len() + 1);
+        if self.failures.is_empty() {
+            lines.push(vec![(placeholder(self.finished, "failed"), Style::new())]);
+        } else if self.expanded {
+            let failure = &self.failures[self.selected];
+            lines.push(self.failure_line(self.selected, failure, styles));
+            if failure.output.is_empty() {
+                lines.push(vec![("    (no output)".to_owned(), Style::new())]);
+            }
+            lines.extend(
+                failure
+                    .output
+                    .iter()
+                    .skip(self.scroll)
+                    .take(available.saturating_sub(1))
+                    .map(|line| vec![(format!("    {}", line), Style::new())]),
+            );
+        } else {
+            // Scroll the list so that the selected failure is visible.
+            let start = (self.selected + 1).saturating_sub(available.max(1));
+            lines.extend(
+                self.failures
+                    .iter()
+                    .enumerate()
+                    .skip(start)
+                    .take(available)
+                    .map(|(idx, failure)| self.failure_line(idx, failure, styles)),
+            );
+        }
+
+        // Fill the rest of the screen, then add the last line.
+        lines.truncate(height.saturating_sub(1));
+        lines.resize(height.saturating_sub(1), vec![]);
+        let last_line = match &self.filter_input {
+            Some(input) => vec![
+                ("Re-run tests matching: ".to_owned(), styles.title),
+                (format!("{}_", input), Style::new()),
+                ("  (enter: re-run, esc: cancel)".to_owned(), Style::new()),
+            ],
+            None if self.finished => vec![(
+                "up/down: select  enter: show output  pgup/pgdn: scroll  \
+                 /: filter and re-run  r: re-run  q: quit"
+                    .to_owned(),
+                Style::new(),
+            )],
+            None => vec![(
+                "up/down: select  enter: show output  pgup/pgdn: scroll  ctrl-c: cancel run"
+                    .to_owned(),
+                Style::new(),
+            )],
+        };
+        if height > 0 {
+            lines.push(last_line);
+        }
+        lines
+    }
+
+    fn failure_line(
+        &self,
+        idx: usize,
+        failure: &Failure,
+        styles: &TuiStyles,
+    ) -> Vec<(String, Style)> {
+        let selected = idx == self.selected;
+        let (marker, style) = if selected {
+            ("> ", styles.selected)
+        } else {
+            ("  ", Style::new())
+        };
+        vec![
+            (marker.to_owned(), style),
+            (format!("{:>10}", failure.status), styles.fail),
+            (
+                format!(
+                    " [{:>8.3}s] {}",
+                    failure.time_taken.as_secs_f64(),
+                    failure.name
+                ),
+                style,
+            ),
+        ]
+    }
+}
+
+fn test_name(test_instance: &TestInstance<'_>) -> String {
+    let mut name = format!(
+        "{} {}",
+        test_instance.bin_info.binary_id, test_instance.name
+    );
+    if let Some(variant) = test_instance.variant {
+        name.push_str(&format!(" {}", variant));
+    }
+    name
+}
+
+fn pane_title(title: &str, styles: &TuiStyles) -> Vec<(String, Style)> {
+    vec![
+        ("── ".to_owned(), Style::new()),
+        (title.to_owned(), styles.title),
+        (format!(" {}", "─".repeat(200)), Style::new()),
+    ]
+}
+
+fn placeholder(finished: bool, what: &str) -> String {
+    if finished {
+        format!("  No tests {}", what)
+    } else {
+        format!("  No tests {} yet", what)
+    }
+}
+
+/// A key pressed by the user.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Escape,
+    Backspace,
+    Interrupt,
+    Char(char),
+}
+
+/// Parses the keys in a chunk of input read from the terminal.
+fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let input = String::from_utf8_lossy(input);
+    let mut chars = input.chars().peekable();
+    let mut keys = Vec::new();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' => match chars.peek() {
+                // Escape sequences for cursor keys start with either ESC [ or ESC O.
+                Some('[') | Some('O') => {
+                    chars.next();
+                    match chars.next() {
+                        Some('A') => Key::Up,
+                        Some('B') => Key::Down,
+                        Some('5') if chars.next_if_eq(&'~').is_some() => Key::PageUp,
+                        Some('6') if chars.next_if_eq(&'~').is_some() => Key::PageDown,
+                        _ => continue,
+                    }
+                }
+                _ => Key::Escape,
+            },
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x03' => Key::Interrupt,
+            c if !c.is_control() => Key::Char(c),
+            _ => continue,
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::{parse_keys, Key};
+    use crate::{errors::TuiError, signal::TerminalGuard};
+    use crossbeam_channel::Receiver;
+    use std::{
+        io::{self, Read, Write},
+        thread,
+    };
+
+    /// Switches to the alternate screen and reads keystrokes, restoring the terminal on drop.
+    pub(super) struct Terminal {
+        guard: TerminalGuard,
+    }
+
+    impl Terminal {
+        pub(super) fn new() -> Result<(Self, Receiver<Key>), TuiError> {
+            // SAFETY: isatty is always safe to call.
+            let is_terminal = |fd| unsafe { libc::isatty(fd) } == 1;
+            if !is_terminal(libc::STDIN_FILENO) || !is_terminal(libc::STDERR_FILENO) {
+                return Err(TuiError::NotATerminal);
+            }
+            let guard = TerminalGuard::new().map_err(TuiError::Terminal)?;
+
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            thread::Builder::new()
+                .name("nextest-tui-input".to_owned())
+                .spawn(move || {
+                    let stdin = io::stdin();
+                    let mut stdin = stdin.lock();
+                    let mut buf = [0; 64];
+                    loop {
+                        let n = match stdin.read(&mut buf) {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => n,
+                        };
+                        for key in parse_keys(&buf[..n]) {
+                            if sender.send(key).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                })
+                .map_err(TuiError::Terminal)?;
+
+            // Switch to the alternate screen and hide the cursor.
+            let mut stderr = io::stderr();
+            write!(stderr, "\x1b[?1049h\x1b[?25l")
+                .and_then(|()| stderr.flush())
+                .map_err(TuiError::Terminal)?;
+            Ok((Self { guard }, receiver))
+        }
+
+        pub(super) fn set_signals(&self, enabled: bool) -> io::Result<()> {
+            self.guard.set_signals(enabled)
+        }
+    }
+
+    impl Drop for Terminal {
+        fn drop(&mut self) {
+            // Show the cursor and switch back to the main screen. The guard then restores the
+            // terminal mode.
+            let mut stderr = io::stderr();
+            let _ = write!(stderr, "\x1b[?25h\x1b[?1049l");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_terminal_input() {
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1bOBj\r/ab\x7f\x1b\x1b[5~\x1b[6~\x03\x1b[Z"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::Char('j'),
+                Key::Enter,
+                Key::Char('/'),
+                Key::Char('a'),
+                Key::Char('b'),
+                Key::Backspace,
+                Key::Escape,
+                Key::PageUp,
+                Key::PageDown,
+                Key::Interrupt,
+            ]
+        );
+    }
+
+    fn rendered_text(state: &TuiState, height: usize) -> Vec<String> {
+        state
+            .render(&TuiStyles::default(), height)
+            .into_iter()
+            .map(|line| line.into_iter().map(|(text, _)| text).collect())
+            .collect()
+    }
+
+    #[test]
+    fn browse_failures_and_rerun() {
+        let mut state = TuiState::default();
+        state.start_run(None);
+        state.running.push(RunningTest {
+            name: "my-crate slow_test".to_owned(),
+            attempt: 2,
+            elapsed: Duration::from_millis(1500),
+        });
+        for name in ["first", "second"] {
+            state.failures.push(Failure {
+                name: format!("my-crate {}", name),
+                status: "FAIL".to_owned(),
+                time_taken: Duration::from_millis(20),
+                output: (0..30)
+                    .map(|idx| format!("{} line {}", name, idx))
+                    .collect(),
+            });
+        }
+
+        let lines = rendered_text(&state, 12);
+        assert_eq!(lines.len(), 12, "the whole screen is drawn");
+        assert!(lines[0].starts_with("nextest run 1  Running"));
+        assert_eq!(lines[2], "   TRY 2 RUN [   1.500s] my-crate slow_test");
+        assert!(lines[3].starts_with("── Failures (2) ─"));
+        assert_eq!(lines[4], ">       FAIL [   0.020s] my-crate first");
+        assert_eq!(lines[5], "        FAIL [   0.020s] my-crate second");
+        assert!(lines[11].ends_with("ctrl-c: cancel run"));
+
+        // Select the second failure and show its output.
+        assert_eq!(state.handle_key(Key::Down), None);
+        assert_eq!(state.handle_key(Key::Enter), None);
+        state.handle_key(Key::PageDown);
+        let lines = rendered_text(&state, 12);
+        assert_eq!(lines[4], ">       FAIL [   0.020s] my-crate second");
+        assert_eq!(lines[5], "    second line 10");
+        assert_eq!(lines[10], "    second line 15");
+
+        // Actions can't be taken until the run has finished.
+        assert_eq!(state.handle_key(Key::Char('q')), None);
+        assert_eq!(state.handle_key(Key::Char('/')), None);
+        assert_eq!(state.filter_input, None);
+
+        state.finished = true;
+        state.running.clear();
+        state.handle_key(Key::Char('/'));
+        for c in "secx".chars() {
+            state.handle_key(Key::Char(c));
+        }
+        state.handle_key(Key::Backspace);
+        let lines = rendered_text(&state, 12);
+        assert!(lines[2].contains("No tests running"));
+        assert!(lines[11].starts_with("Re-run tests matching: sec_"));
+        assert_eq!(
+            state.handle_key(Key::Enter),
+            Some(TuiAction::Rerun {
+                filter: "sec".to_owned()
+            })
+        );
+
+        // The next run starts afresh, and shows the filter it was started with.
+        state.start_run(Some("sec"));
+        let lines = rendered_text(&state, 12);
+        assert!(lines[0].starts_with("nextest run 2 [filter: sec]"));
+        assert!(lines[4].contains("No tests failed yet"));
+        assert_eq!(state.handle_key(Key::Char('q')), None);
+        state.finished = true;
+        assert_eq!(
+            state.handle_key(Key::Char('r')),
+            Some(TuiAction::Rerun {
+                filter: "sec".to_owned()
+            })
+        );
+        assert_eq!(state.handle_key(Key::Interrupt), Some(TuiAction::Quit));
+    }
+}
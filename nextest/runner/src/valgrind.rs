@@ -0,0 +1,278 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Running tests under Valgrind's Memcheck.
+//!
+//! With `cargo nextest run --run-wrapper valgrind`, or with `enable = true` in the profile's
+//! `valgrind` table, each Rust test is run under `valgrind`, which writes its findings for the test
+//! to an XML file in the run directory:
+//!
+//! ```toml
+//! [profile.ci.valgrind]
+//! enable = true
+//! command = ["valgrind"]
+//! suppressions = ["valgrind.supp"]
+//! args = ["--track-origins=yes"]
+//! fail-on-leaks = true
+//! ```
+//!
+//! * `command` is the program to run, along with any arguments that come before nextest's. It
+//!   defaults to `["valgrind"]`.
+//! * `suppressions` are suppression files, relative to the workspace root.
+//! * `args` are passed to valgrind after nextest's own arguments, so they can override them.
+//! * `fail-on-leaks` (the default) fails tests that definitely leak memory.
+//!
+//! After a test exits, its XML report is read. Memory errors, such as invalid reads, fail the test
+//! even if it exited successfully, as do definite leaks with `fail-on-leaks`. The errors are listed
+//! at the end of the test's standard error, and the report is attached to its result.
+//!
+//! Tests are only run under valgrind if they're run locally: tests in containers, tests run through
+//! a target runner, and tests that aren't written in Rust are run as usual.
+
+use crate::environment::{BinaryCommand, ExecutionEnvironment, WrapperCommand};
+use camino::{Utf8Path, Utf8PathBuf};
+use duct::Expression;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// How tests are run under valgrind.
+///
+/// Obtained through [`NextestProfile::valgrind`](crate::config::NextestProfile::valgrind).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Valgrind {
+    #[serde(default)]
+    enable: bool,
+    #[serde(default = "default_command")]
+    command: Vec<String>,
+    #[serde(default)]
+    suppressions: Vec<Utf8PathBuf>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default = "default_fail_on_leaks")]
+    fail_on_leaks: bool,
+}
+
+fn default_command() -> Vec<String> {
+    vec!["valgrind".to_owned()]
+}
+
+fn default_fail_on_leaks() -> bool {
+    true
+}
+
+impl Default for Valgrind {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            command: default_command(),
+            suppressions: vec![],
+            args: vec![],
+            fail_on_leaks: default_fail_on_leaks(),
+        }
+    }
+}
+
+impl Valgrind {
+    /// Returns true if tests are run under valgrind by default for this profile.
+    pub fn enable(&self) -> bool {
+        self.enable
+    }
+
+    /// Returns the program run, along with the arguments that come before nextest's.
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    /// Returns the suppression files, relative to the workspace root.
+    pub fn suppressions(&self) -> &[Utf8PathBuf] {
+        &self.suppressions
+    }
+
+    /// Returns the extra arguments passed to valgrind.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns true if tests that definitely leak memory fail.
+    pub fn fail_on_leaks(&self) -> bool {
+        self.fail_on_leaks
+    }
+
+    /// Returns the command that runs a test binary under valgrind, writing its report to
+    /// `xml_path`.
+    pub(crate) fn make_command(
+        &self,
+        binary: BinaryCommand<'_>,
+        workspace_root: &Utf8Path,
+        xml_path: &Utf8Path,
+    ) -> Expression {
+        let (program, command_args) = match self.command.split_first() {
+            Some((program, args)) => (program.clone(), args.to_vec()),
+            None => ("valgrind".to_owned(), vec![]),
+        };
+        let mut args = command_args;
+        args.extend([
+            "--leak-check=full".to_owned(),
+            // Only the test binary is checked, not the programs it runs.
+            "--child-silent-after-fork=yes".to_owned(),
+            "--xml=yes".to_owned(),
+            format!("--xml-file={}", xml_path),
+        ]);
+        args.extend(
+            self.suppressions
+                .iter()
+                .map(|path| format!("--suppressions={}", workspace_root.join(path))),
+        );
+        args.extend(self.args.iter().cloned());
+        Arc::new(WrapperCommand { program, args }).make_command(binary)
+    }
+}
+
+/// The errors in a Memcheck XML report.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemcheckReport {
+    /// The errors found, in the order they were reported.
+    pub errors: Vec<MemcheckError>,
+}
+
+/// An error within a [`MemcheckReport`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MemcheckError {
+    /// The kind of error, such as `InvalidRead` or `Leak_DefinitelyLost`.
+    pub kind: String,
+
+    /// The description of the error.
+    pub what: String,
+}
+
+impl MemcheckError {
+    /// Returns true if this error is a leak, of any kind.
+    pub fn is_leak(&self) -> bool {
+        self.kind.starts_with("Leak_")
+    }
+}
+
+impl MemcheckReport {
+    /// Parses a report written by `valgrind --xml=yes`.
+    ///
+    /// Only the kind and description of each error are read. A report that was cut off, for
+    /// example because the test was killed, contains the errors up to that point.
+    pub fn parse(xml: &str) -> Self {
+        let mut errors = vec![];
+        let mut rest = xml;
+        while let Some(start) = rest.find("<error>") {
+            rest = &rest[start + "<error>".len()..];
+            let Some(end) = rest.find("</error>") else {
+                break;
+            };
+            let error = &rest[..end];
+            rest = &rest[end..];
+
+            let Some(kind) = element_text(error, "kind") else {
+                continue;
+            };
+            // Leaks are described in an <xwhat> element, with the description in its <text>.
+            let what = element_text(error, "what")
+                .or_else(|| {
+                    element_text(error, "xwhat").and_then(|xwhat| element_text(xwhat, "text"))
+                })
+                .unwrap_or_default();
+            errors.push(MemcheckError {
+                kind: unescape(kind),
+                what: unescape(what),
+            });
+        }
+        Self { errors }
+    }
+
+    /// Returns the errors that fail the test: every error other than leaks, and definite leaks if
+    /// `fail_on_leaks` is true.
+    pub fn failures(&self, fail_on_leaks: bool) -> impl Iterator<Item = &MemcheckError> + '_ {
+        self.errors.iter().filter(move |error| {
+            !error.is_leak() || (fail_on_leaks && error.kind == "Leak_DefinitelyLost")
+        })
+    }
+}
+
+/// Returns the text inside the first element with the given name.
+fn element_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(xml[start..end].trim())
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_memcheck_report() {
+        let xml = r#"<?xml version="1.0"?>
+<valgrindoutput>
+<protocolversion>4</protocolversion>
+<error>
+  <unique>0x0</unique>
+  <tid>1</tid>
+  <kind>InvalidRead</kind>
+  <what>Invalid read of size 4</what>
+  <stack><frame><fn>foo&lt;u32&gt;</fn></frame></stack>
+</error>
+<error>
+  <unique>0x1</unique>
+  <kind>Leak_DefinitelyLost</kind>
+  <xwhat>
+    <text>24 bytes in 1 blocks are definitely lost in loss record 1 of 2</text>
+    <leakedbytes>24</leakedbytes>
+  </xwhat>
+</error>
+<error>
+  <unique>0x2</unique>
+  <kind>Leak_PossiblyLost</kind>
+  <xwhat><text>8 bytes in 1 blocks are possibly lost</text></xwhat>
+</error>
+<error>
+  <unique>0x3</unique>
+  <kind>InvalidFree</kind>
+"#;
+        let report = MemcheckReport::parse(xml);
+        let kinds: Vec<_> = report
+            .errors
+            .iter()
+            .map(|error| error.kind.as_str())
+            .collect();
+        assert_eq!(
+            kinds,
+            ["InvalidRead", "Leak_DefinitelyLost", "Leak_PossiblyLost"],
+            "the cut-off error is left out"
+        );
+        assert_eq!(report.errors[0].what, "Invalid read of size 4");
+        assert_eq!(
+            report.errors[1].what,
+            "24 bytes in 1 blocks are definitely lost in loss record 1 of 2"
+        );
+
+        let failures: Vec<_> = report
+            .failures(true)
+            .map(|error| error.kind.as_str())
+            .collect();
+        assert_eq!(failures, ["InvalidRead", "Leak_DefinitelyLost"]);
+        let failures: Vec<_> = report
+            .failures(false)
+            .map(|error| error.kind.as_str())
+            .collect();
+        assert_eq!(failures, ["InvalidRead"]);
+
+        assert_eq!(MemcheckReport::parse(""), MemcheckReport::default());
+    }
+}
@@ -0,0 +1,489 @@
+// Copyright (c) The diem-devtools Contributors
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Running WebAssembly test binaries built for WASI targets, such as `wasm32-wasip1`.
+//!
+//! Test binaries built for WASI can't be run directly: they're run through a WebAssembly runtime
+//! instead. If no [target runner](crate::target_runner) is configured for a WASI target, the
+//! runtime named by the `wasm-runtime` setting of the profile is used:
+//!
+//! ```toml
+//! [profile.default]
+//! # Either "wasmtime" (the default) or "wasmer", looked up in PATH.
+//! wasm-runtime = "wasmtime"
+//! ```
+//!
+//! Target runners whose program is `wasmtime` or `wasmer` are treated the same way, so that a
+//! runner set through `CARGO_TARGET_WASM32_WASIP1_RUNNER` or Cargo config also works.
+//!
+//! Unlike native processes, WebAssembly modules don't inherit the environment or see the file
+//! system of the process running them. Binaries are run as `<runtime> run <runner args> --dir .
+//! --dir <cwd> --env KEY=VALUE... <binary> <args>`: the working directory of the test is made
+//! available both as `.` and under its own path, and the environment variables nextest sets for
+//! the test are passed in. Variables inherited from the environment nextest is run in aren't
+//! passed in.
+//!
+//! # Test components
+//!
+//! Binaries built for `wasm32-wasip2` are WebAssembly components. Instead of a libtest harness, a
+//! component can export each of its tests as a function that takes no arguments, with a name that
+//! starts with `test-`:
+//!
+//! ```wit
+//! world tests {
+//!     export test-adds-numbers: func();
+//!     export test-parses-input: func();
+//! }
+//! ```
+//!
+//! Such a component isn't run to list its tests: its exports are read instead, and each one is
+//! listed as a test named after the rest of the export name, such as `adds-numbers`. Each test is
+//! run as `wasmtime run <runner args> --dir . --dir <cwd> --env KEY=VALUE... --invoke
+//! 'test-adds-numbers()' <component>`, and fails if the function traps, for example because it
+//! panicked. Invoking exported functions requires Wasmtime.
+//!
+//! Components that don't export any tests are listed and run like any other test binary.
+
+use crate::test_list::DISCOVERY_PROTOCOL_HEADER;
+use camino::Utf8Path;
+use duct::{cmd, Expression};
+use serde::Deserialize;
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// A WebAssembly runtime used to run test binaries built for WASI targets.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WasmRuntime {
+    /// [Wasmtime](https://wasmtime.dev).
+    Wasmtime,
+
+    /// [Wasmer](https://wasmer.io).
+    Wasmer,
+}
+
+impl WasmRuntime {
+    /// Returns the name of the program for this runtime.
+    pub fn program(self) -> &'static str {
+        match self {
+            WasmRuntime::Wasmtime => "wasmtime",
+            WasmRuntime::Wasmer => "wasmer",
+        }
+    }
+
+    /// Returns the runtime the given program is, based on its file name.
+    pub(crate) fn from_program(program: &str) -> Option<Self> {
+        match Utf8Path::new(program).file_stem()? {
+            "wasmtime" => Some(WasmRuntime::Wasmtime),
+            "wasmer" => Some(WasmRuntime::Wasmer),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WasmRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.program())
+    }
+}
+
+/// Returns true if binaries built for the given target triple are WebAssembly modules for WASI.
+pub fn is_wasi_triple(triple: &str) -> bool {
+    triple.starts_with("wasm32-wasi")
+}
+
+/// The prefix of the names of the functions a component exports as tests.
+pub const COMPONENT_TEST_PREFIX: &str = "test-";
+
+/// Returns the tests the component at the given path exports, in the
+/// [discovery protocol](crate::test_list#dynamic-test-discovery) format.
+///
+/// Returns `None` if the binary isn't a component, or doesn't export any tests.
+pub(crate) fn list_component_tests(
+    binary_path: &Utf8Path,
+    runtime: WasmRuntime,
+) -> io::Result<Option<String>> {
+    let tests = match component_tests(binary_path)? {
+        Some(tests) => tests,
+        None => return Ok(None),
+    };
+    if runtime != WasmRuntime::Wasmtime {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} can't invoke functions exported by components: use wasmtime instead",
+                runtime
+            ),
+        ));
+    }
+
+    let mut output = format!("{}\n", DISCOVERY_PROTOCOL_HEADER);
+    for name in tests {
+        output.push_str(&serde_json::json!({ "name": name }).to_string());
+        output.push('\n');
+    }
+    Ok(Some(output))
+}
+
+/// Returns the command that runs the given module through a runtime, with the runtime's program
+/// and arguments.
+///
+/// The working directory and environment variables of the test are determined right before the
+/// command is spawned, so that the ones set on the expression later are picked up.
+pub(crate) fn make_command(
+    program: &str,
+    runner_args: &[String],
+    binary_path: &Utf8Path,
+    args: Vec<&str>,
+) -> Expression {
+    // Both runtimes need the "run" subcommand to pass arguments through to the module.
+    let runtime_args: Vec<String> = match runner_args.first().map(|arg| arg.as_str()) {
+        Some("run") => runner_args.to_vec(),
+        _ => std::iter::once("run".to_owned())
+            .chain(runner_args.iter().cloned())
+            .collect(),
+    };
+    let module = binary_path.to_string();
+    let args: Vec<String> = args.into_iter().map(|arg| arg.to_owned()).collect();
+    cmd(program, runtime_args).before_spawn(move |command| {
+        let invoke = component_invocation(Utf8Path::new(&module), &args)?;
+        let run_args = run_args(
+            command.get_current_dir(),
+            command.get_envs(),
+            &module,
+            invoke.as_deref(),
+            &args,
+        );
+        command.args(run_args);
+        Ok(())
+    })
+}
+
+// Returns the function to invoke if a test exported by a component is being run, as `--exact <test
+// name>`.
+fn component_invocation(module: &Utf8Path, args: &[String]) -> io::Result<Option<String>> {
+    let name = match args {
+        [exact, name, ..] if exact == "--exact" => name,
+        _ => return Ok(None),
+    };
+    Ok(component_tests(module)?.map(|_| format!("{}{}()", COMPONENT_TEST_PREFIX, name)))
+}
+
+// Returns the arguments to the runtime that depend on the test: the directories and environment
+// variables to pass in, followed by the module and the arguments to it. If a function is invoked,
+// the arguments aren't passed in.
+fn run_args<'a>(
+    cwd: Option<&Path>,
+    envs: impl IntoIterator<Item = (&'a OsStr, Option<&'a OsStr>)>,
+    module: &str,
+    invoke: Option<&str>,
+    args: &[String],
+) -> Vec<String> {
+    let mut run_args = vec!["--dir".to_owned(), ".".to_owned()];
+    if let Some(cwd) = cwd {
+        run_args.extend(["--dir".to_owned(), cwd.to_string_lossy().into_owned()]);
+    }
+    let mut envs: Vec<_> = envs
+        .into_iter()
+        .filter_map(|(key, value)| {
+            Some(format!(
+                "{}={}",
+                key.to_string_lossy(),
+                value?.to_string_lossy()
+            ))
+        })
+        .collect();
+    envs.sort_unstable();
+    for env in envs {
+        run_args.extend(["--env".to_owned(), env]);
+    }
+    match invoke {
+        Some(invoke) => {
+            run_args.extend(["--invoke".to_owned(), invoke.to_owned(), module.to_owned()]);
+        }
+        None => {
+            run_args.push(module.to_owned());
+            run_args.extend(args.iter().cloned());
+        }
+    }
+    run_args
+}
+
+// ---
+// Reading component exports
+// ---
+
+// The magic number, version and layer that components start with. Core modules have a different
+// version and layer.
+const COMPONENT_PREAMBLE: [u8; 8] = *b"\0asm\x0d\x00\x01\x00";
+const EXPORT_SECTION_ID: u8 = 11;
+const CORE_SORT: u8 = 0x00;
+const FUNC_SORT: u8 = 0x01;
+
+// Returns the names of the tests the component at the given path exports, or `None` if it isn't a
+// component or doesn't export any tests.
+fn component_tests(path: &Utf8Path) -> io::Result<Option<Vec<String>>> {
+    read_component_tests(BufReader::new(File::open(path)?))
+}
+
+// Only the top-level sections of the component are looked at: the ones that aren't exports are
+// skipped over without being read.
+fn read_component_tests(mut reader: impl Read + Seek) -> io::Result<Option<Vec<String>>> {
+    let mut preamble = [0; 8];
+    match reader.read_exact(&mut preamble) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    if preamble != COMPONENT_PREAMBLE {
+        return Ok(None);
+    }
+
+    let mut tests = vec![];
+    loop {
+        let mut id = [0];
+        if reader.read(&mut id)? == 0 {
+            break;
+        }
+        let size = read_u32(&mut reader)?;
+        if id[0] == EXPORT_SECTION_ID {
+            let mut section = vec![0; size as usize];
+            reader.read_exact(&mut section)?;
+            read_exports(&section, &mut tests)?;
+        } else {
+            reader.seek(SeekFrom::Current(i64::from(size)))?;
+        }
+    }
+    Ok((!tests.is_empty()).then_some(tests))
+}
+
+fn read_exports(mut section: &[u8], tests: &mut Vec<String>) -> io::Result<()> {
+    let count = read_u32(&mut section)?;
+    for _ in 0..count {
+        // The name is prefixed by its kind, which doesn't matter here.
+        match read_byte(&mut section)? {
+            0x00 | 0x01 => {}
+            kind => {
+                return Err(invalid_data(format!(
+                    "unknown export name kind {:#x}",
+                    kind
+                )))
+            }
+        }
+        let name = read_string(&mut section)?;
+        let sort = read_byte(&mut section)?;
+        if sort == CORE_SORT {
+            read_byte(&mut section)?;
+        }
+        read_u32(&mut section)?;
+        // An optional type ascription follows.
+        if read_byte(&mut section)? == 0x01 {
+            skip_extern_desc(&mut section)?;
+        }
+
+        if sort == FUNC_SORT {
+            if let Some(test) = name.strip_prefix(COMPONENT_TEST_PREFIX) {
+                tests.push(test.to_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_extern_desc(reader: &mut impl Read) -> io::Result<()> {
+    match read_byte(reader)? {
+        // Core modules: 0x11 followed by a type index.
+        0x00 => {
+            read_byte(reader)?;
+            read_u32(reader)?;
+        }
+        // Functions, components and instances: a type index.
+        0x01 | 0x04 | 0x05 => {
+            read_u32(reader)?;
+        }
+        // Values: either a value index, or a value type. Value types are either a single byte, or
+        // a type index.
+        0x02 => {
+            read_byte(reader)?;
+            read_u32(reader)?;
+        }
+        // Types: either a type index, or a resource with no further data.
+        0x03 => {
+            if read_byte(reader)? == 0x00 {
+                read_u32(reader)?;
+            }
+        }
+        kind => return Err(invalid_data(format!("unknown extern kind {:#x}", kind))),
+    }
+    Ok(())
+}
+
+fn read_byte(reader: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+// Reads an unsigned LEB128 integer. Since only the length of the integer matters while skipping
+// over signed ones, this also works for those.
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut result = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = read_byte(reader)?;
+        result |= u32::from(byte & 0x7f).checked_shl(shift).unwrap_or(0);
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(invalid_data("integer is too long".to_owned()))
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(reader)?;
+    let mut buf = vec![];
+    reader.take(u64::from(len)).read_to_end(&mut buf)?;
+    if buf.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(buf).map_err(|_| invalid_data("name isn't valid UTF-8".to_owned()))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wasm_args() {
+        assert!(is_wasi_triple("wasm32-wasip1"));
+        assert!(is_wasi_triple("wasm32-wasi"));
+        assert!(!is_wasi_triple("wasm32-unknown-unknown"));
+        assert_eq!(
+            WasmRuntime::from_program("/opt/wasmtime/bin/wasmtime"),
+            Some(WasmRuntime::Wasmtime)
+        );
+        assert_eq!(
+            WasmRuntime::from_program("wasmer.exe"),
+            Some(WasmRuntime::Wasmer)
+        );
+        assert_eq!(WasmRuntime::from_program("qemu-aarch64"), None);
+
+        let envs = [
+            (OsStr::new("NEXTEST_RUN_ID"), Some(OsStr::new("1234"))),
+            (OsStr::new("CARGO_PKG_NAME"), Some(OsStr::new("my-crate"))),
+            (OsStr::new("REMOVED"), None),
+        ];
+        assert_eq!(
+            run_args(
+                Some(Path::new("/workspace/my-crate")),
+                envs,
+                "/workspace/target/wasm32-wasip1/debug/deps/my_test-1234.wasm",
+                None,
+                &[
+                    "--list".to_owned(),
+                    "--format".to_owned(),
+                    "terse".to_owned()
+                ],
+            ),
+            [
+                "--dir",
+                ".",
+                "--dir",
+                "/workspace/my-crate",
+                "--env",
+                "CARGO_PKG_NAME=my-crate",
+                "--env",
+                "NEXTEST_RUN_ID=1234",
+                "/workspace/target/wasm32-wasip1/debug/deps/my_test-1234.wasm",
+                "--list",
+                "--format",
+                "terse",
+            ]
+        );
+    }
+
+    #[test]
+    fn component_exports() {
+        fn export(name: &str, sort: &[u8], desc: Option<&[u8]>) -> Vec<u8> {
+            let mut export = vec![0x00, name.len() as u8];
+            export.extend(name.as_bytes());
+            export.extend(sort);
+            match desc {
+                Some(desc) => {
+                    export.push(0x01);
+                    export.extend(desc);
+                }
+                None => export.push(0x00),
+            }
+            export
+        }
+
+        let mut exports = vec![4];
+        exports.extend(export("test-adds-numbers", &[FUNC_SORT, 0x00], None));
+        exports.extend(export(
+            "wasi:cli/run@0.2.0",
+            &[0x05, 0x01],
+            Some(&[0x05, 0x02]),
+        ));
+        exports.extend(export("helper", &[FUNC_SORT, 0x01], None));
+        exports.extend(export(
+            "test-parses-input",
+            &[FUNC_SORT, 0x82, 0x01],
+            Some(&[0x01, 0x03]),
+        ));
+
+        let mut component = COMPONENT_PREAMBLE.to_vec();
+        // A custom section, which is skipped over.
+        component.extend([0x00, 0x03, 0xaa, 0xbb, 0xcc]);
+        component.extend([EXPORT_SECTION_ID, exports.len() as u8]);
+        component.extend(exports);
+        assert_eq!(
+            read_component_tests(io::Cursor::new(&component)).expect("component is valid"),
+            Some(vec!["adds-numbers".to_owned(), "parses-input".to_owned()])
+        );
+
+        // Core modules, other files and components without test exports aren't listed this way.
+        let core_module = b"\0asm\x01\x00\x00\x00\x00\x00";
+        assert_eq!(
+            read_component_tests(io::Cursor::new(core_module)).unwrap(),
+            None
+        );
+        assert_eq!(read_component_tests(io::Cursor::new(b"#!")).unwrap(), None);
+        assert_eq!(
+            read_component_tests(io::Cursor::new(COMPONENT_PREAMBLE)).unwrap(),
+            None
+        );
+
+        // Truncated sections are errors.
+        let mut truncated = COMPONENT_PREAMBLE.to_vec();
+        truncated.extend([EXPORT_SECTION_ID, 0x04, 0x01, 0x00, 0x05]);
+        assert!(read_component_tests(io::Cursor::new(&truncated)).is_err());
+
+        assert_eq!(
+            run_args(
+                None,
+                [(OsStr::new("NEXTEST_RUN_ID"), Some(OsStr::new("1234")))],
+                "tests.wasm",
+                Some("test-adds-numbers()"),
+                &["--exact".to_owned(), "adds-numbers".to_owned()],
+            ),
+            [
+                "--dir",
+                ".",
+                "--env",
+                "NEXTEST_RUN_ID=1234",
+                "--invoke",
+                "test-adds-numbers()",
+                "tests.wasm",
+            ]
+        );
+    }
+}
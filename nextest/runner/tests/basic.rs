@@ -8,17 +8,29 @@ use color_eyre::eyre::Result;
 use duct::cmd;
 use guppy::{graph::PackageGraph, MetadataCommand};
 use maplit::btreemap;
-use nextest_metadata::{FilterMatch, MismatchReason};
+use nextest_metadata::{
+    BuildPlatform, FilterMatch, MismatchReason, RunReportSummary, SkipKind, TestReportStatus,
+};
 use nextest_runner::{
     config::NextestConfig,
-    reporter::TestEvent,
+    env_file::EnvFiles,
+    errors::{FromSummaryError, SyntheticTestError},
+    fingerprint::WorkspaceFingerprint,
+    reporter::{TestEvent, TestReporterBuilder},
+    run_report::{RunReport, RunReportRecorder},
+    run_store::RunStore,
     runner::{
         ExecutionDescription, ExecutionResult, ExecutionStatuses, RunStats, TestRunner,
         TestRunnerBuilder,
     },
     signal::SignalHandler,
+    test_dependencies::TestDependencies,
     test_filter::{RunIgnored, TestFilterBuilder},
-    test_list::{RustTestArtifact, TestList},
+    test_list::{
+        BinaryList, ListSettings, OutputFormat, RustTestArtifact, SkippedTestTarget,
+        SyntheticTestBuilder, TestList,
+    },
+    timings::TestTimings,
 };
 use once_cell::sync::Lazy;
 use pretty_assertions::assert_eq;
@@ -26,6 +38,7 @@ use std::{
     collections::{BTreeMap, HashMap},
     env, fmt,
     io::Cursor,
+    time::Duration,
 };
 
 #[derive(Copy, Clone, Debug)]
@@ -77,6 +90,7 @@ static EXPECTED_TESTS: Lazy<BTreeMap<&'static str, Vec<TestFixture>>> = Lazy::ne
         "nextest-tests::basic" => vec![
             TestFixture { name: "test_cargo_env_vars", status: FixtureStatus::Pass },
             TestFixture { name: "test_cwd", status: FixtureStatus::Pass },
+            TestFixture { name: "test_env_file", status: FixtureStatus::Pass },
             TestFixture { name: "test_failure_assert", status: FixtureStatus::Fail },
             TestFixture { name: "test_failure_error", status: FixtureStatus::Fail },
             TestFixture { name: "test_failure_should_panic", status: FixtureStatus::Fail },
@@ -84,6 +98,9 @@ static EXPECTED_TESTS: Lazy<BTreeMap<&'static str, Vec<TestFixture>>> = Lazy::ne
             TestFixture { name: "test_flaky_mod_3", status: FixtureStatus::Flaky { pass_attempt: 3 } },
             TestFixture { name: "test_ignored", status: FixtureStatus::IgnoredPass },
             TestFixture { name: "test_ignored_fail", status: FixtureStatus::IgnoredFail },
+            TestFixture { name: "test_log_file", status: FixtureStatus::Pass },
+            TestFixture { name: "test_multiplex_param", status: FixtureStatus::Pass },
+            TestFixture { name: "test_runtime_skip", status: FixtureStatus::Pass },
             TestFixture { name: "test_success", status: FixtureStatus::Pass },
             TestFixture { name: "test_success_should_panic", status: FixtureStatus::Pass },
         ],
@@ -112,10 +129,12 @@ static PACKAGE_GRAPH: Lazy<PackageGraph> = Lazy::new(|| {
         .expect("building package graph failed")
 });
 
+static FIXTURE_MESSAGES: Lazy<Vec<u8>> = Lazy::new(init_fixture_messages);
+
 static FIXTURE_TARGETS: Lazy<BTreeMap<String, RustTestArtifact<'static>>> =
     Lazy::new(init_fixture_targets);
 
-fn init_fixture_targets() -> BTreeMap<String, RustTestArtifact<'static>> {
+fn init_fixture_messages() -> Vec<u8> {
     // TODO: actually productionize this, probably requires moving x into this repo
     let cmd_name = match env::var("CARGO") {
         Ok(v) => v,
@@ -123,8 +142,6 @@ fn init_fixture_targets() -> BTreeMap<String, RustTestArtifact<'static>> {
         Err(err) => panic!("error obtaining CARGO env var: {}", err),
     };
 
-    let graph = &*PACKAGE_GRAPH;
-
     let expr = cmd!(
         cmd_name,
         "test",
@@ -136,8 +153,13 @@ fn init_fixture_targets() -> BTreeMap<String, RustTestArtifact<'static>> {
     .stdout_capture();
 
     let output = expr.run().expect("cargo test --no-run failed");
+    output.stdout
+}
+
+fn init_fixture_targets() -> BTreeMap<String, RustTestArtifact<'static>> {
+    let graph = &*PACKAGE_GRAPH;
     let test_artifacts =
-        RustTestArtifact::from_messages(graph, Cursor::new(output.stdout)).unwrap();
+        RustTestArtifact::from_messages(graph, Cursor::new(&*FIXTURE_MESSAGES)).unwrap();
 
     test_artifacts
         .into_iter()
@@ -157,8 +179,9 @@ fn test_list_tests() -> Result<()> {
             .get(*name)
             .unwrap_or_else(|| panic!("unexpected test name {}", name));
         let info = test_list
-            .get(&test_binary.binary_path)
-            .unwrap_or_else(|| panic!("test list not found for {}", test_binary.binary_path));
+            .get(name)
+            .unwrap_or_else(|| panic!("test list not found for {}", name));
+        assert_eq!(info.binary_path, test_binary.binary_path);
         let tests: Vec<_> = info
             .testcases
             .iter()
@@ -170,6 +193,183 @@ fn test_list_tests() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_binary_list() -> Result<()> {
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let binary_list = BinaryList::new(test_bins, &TestFilterBuilder::any(RunIgnored::Default));
+    let summary = binary_list.to_summary();
+    assert_eq!(
+        summary.rust_binaries.keys().collect::<Vec<_>>(),
+        ["nextest-tests", "nextest-tests::basic"],
+    );
+    let basic = &summary.rust_binaries["nextest-tests::basic"];
+    assert_eq!(
+        basic.binary_path,
+        FIXTURE_TARGETS["nextest-tests::basic"].binary_path
+    );
+    assert_eq!(basic.build_platform, BuildPlatform::Target);
+
+    let mut test_filter = TestFilterBuilder::any(RunIgnored::Default);
+    test_filter.set_exprs(["binary(nextest-tests::basic)".parse()?]);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let binary_list = BinaryList::new(test_bins, &test_filter);
+    assert_eq!(
+        binary_list.to_string(OutputFormat::Plain)?,
+        format!("nextest-tests::basic {}\n", basic.binary_path),
+        "binaries the expression can't match aren't listed"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_list_from_summary() -> Result<()> {
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &TestFilterBuilder::any(RunIgnored::Default))?;
+    let mut summary = test_list.to_summary();
+
+    // Hand out a subset of the tests in the basic binary, as an external scheduler would.
+    let basic = summary
+        .rust_suites
+        .get_mut("nextest-tests::basic")
+        .expect("basic binary is listed");
+    basic
+        .testcases
+        .retain(|name, _| name.starts_with("test_success"));
+    basic
+        .testcases
+        .get_mut("test_success_should_panic")
+        .expect("test is listed")
+        .filter_match = FilterMatch::Mismatch {
+        reason: MismatchReason::Partition,
+    };
+
+    let mut test_filter = TestFilterBuilder::any(RunIgnored::Default);
+    test_filter.set_exprs(["binary(nextest-tests::basic)".parse()?]);
+    let test_list = TestList::from_summary(
+        &PACKAGE_GRAPH,
+        summary.clone(),
+        &test_filter,
+        &ListSettings::default(),
+    )?;
+    assert_eq!(
+        test_list.test_count(),
+        3,
+        "tests are taken from the summary"
+    );
+    let matching: Vec<_> = test_list
+        .iter_tests()
+        .filter(|instance| instance.test_info.filter_match.is_match())
+        .map(|instance| instance.name)
+        .collect();
+    assert_eq!(
+        matching,
+        ["test_success"],
+        "tests skipped in the summary stay skipped, and the filter applies to the rest"
+    );
+
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+    let runner = TestRunnerBuilder::default().build(&test_list, &profile, SignalHandler::noop());
+    let (_, run_stats) = execute_collect(&runner);
+    assert_eq!(run_stats.final_run_count, 1);
+    assert_eq!(run_stats.passed, 1);
+
+    summary
+        .rust_suites
+        .get_mut("nextest-tests::basic")
+        .expect("basic binary is listed")
+        .binary_path = "/nonexistent/binary".into();
+    let error = TestList::from_summary(
+        &PACKAGE_GRAPH,
+        summary,
+        &test_filter,
+        &ListSettings::default(),
+    )
+    .expect_err("missing binaries are reported");
+    assert!(
+        matches!(error, FromSummaryError::MissingBinary { ref binary_id, .. } if binary_id == "nextest-tests::basic"),
+        "unexpected error: {}",
+        error
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_list_sparse() -> Result<()> {
+    let mut test_filter = TestFilterBuilder::any(RunIgnored::Default);
+    test_filter.set_exprs(["binary(nextest-tests::basic) & test(test_success)".parse()?]);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+
+    assert_eq!(
+        test_list.binary_count(),
+        1,
+        "binaries the expression can't match aren't listed"
+    );
+    let info = test_list
+        .get("nextest-tests::basic")
+        .expect("matching binary is listed");
+    let matching: Vec<_> = info
+        .testcases
+        .iter()
+        .filter(|(_, info)| info.filter_match.is_match())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(matching, ["test_success", "test_success_should_panic"]);
+
+    assert_eq!(
+        test_list.skipped_binaries().collect::<Vec<_>>(),
+        ["nextest-tests"],
+        "binaries that weren't listed are tracked"
+    );
+    let summary = test_list.to_summary();
+    assert_eq!(
+        summary.skipped_binaries.iter().collect::<Vec<_>>(),
+        ["nextest-tests"]
+    );
+
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+    let runner = TestRunnerBuilder::default().build(&test_list, &profile, SignalHandler::noop());
+    let (_, run_stats) = execute_collect(&runner);
+    assert_eq!(run_stats.final_run_count, 2);
+    assert_eq!(
+        run_stats.skipped_binaries, 1,
+        "skipped binaries are counted separately from skipped tests"
+    );
+    assert!(run_stats.is_success());
+
+    Ok(())
+}
+
+#[test]
+fn test_skipped_targets() -> Result<()> {
+    let skipped =
+        SkippedTestTarget::from_messages(&PACKAGE_GRAPH, Cursor::new(&*FIXTURE_MESSAGES))?;
+    let skipped: Vec<_> = skipped
+        .iter()
+        .map(|target| (target.binary_id.as_str(), target.missing_features.clone()))
+        .collect();
+    assert_eq!(
+        skipped,
+        [("nextest-tests::required_features", vec!["extra".to_owned()])],
+        "targets with required features that aren't enabled are skipped"
+    );
+    assert!(
+        !FIXTURE_TARGETS.contains_key("nextest-tests::required_features"),
+        "skipped targets aren't built"
+    );
+    Ok(())
+}
+
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
 struct InstanceValue<'a> {
@@ -212,7 +412,7 @@ fn test_run() -> Result<()> {
     let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
     let test_list = TestList::new(test_bins, &test_filter)?;
     let config =
-        NextestConfig::from_sources(&workspace_root(), None).expect("loaded fixture config");
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
     let profile = config
         .profile(NextestConfig::DEFAULT_PROFILE)
         .expect("default config is valid");
@@ -261,7 +461,7 @@ fn test_run_ignored() -> Result<()> {
     let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
     let test_list = TestList::new(test_bins, &test_filter)?;
     let config =
-        NextestConfig::from_sources(&workspace_root(), None).expect("loaded fixture config");
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
     let profile = config
         .profile(NextestConfig::DEFAULT_PROFILE)
         .expect("default config is valid");
@@ -310,7 +510,7 @@ fn test_retries() -> Result<()> {
     let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
     let test_list = TestList::new(test_bins, &test_filter)?;
     let config =
-        NextestConfig::from_sources(&workspace_root(), None).expect("loaded fixture config");
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
     let profile = config
         .profile("with-retries")
         .expect("with-retries config is valid");
@@ -391,6 +591,8 @@ fn test_retries() -> Result<()> {
                             }
                             first_status.result == ExecutionResult::Fail
                         }
+                        // Tests only skip themselves when run with a run store.
+                        ExecutionDescription::Skipped { .. } => false,
                     }
                 }
             };
@@ -407,6 +609,856 @@ fn test_retries() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_multiplex() -> Result<()> {
+    let test_filter = TestFilterBuilder::any(RunIgnored::Default);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile("with-multiplex")
+        .expect("with-multiplex config is valid");
+
+    let runner = TestRunnerBuilder::default().build(&test_list, &profile, SignalHandler::noop());
+
+    let mut run_count = None;
+    let mut variants = Vec::new();
+    runner.execute(|event| match event {
+        TestEvent::RunStarted { run_count: n, .. } => run_count = Some(n),
+        TestEvent::TestFinished {
+            test_instance,
+            run_statuses,
+        } => {
+            if let Some(variant) = test_instance.variant {
+                assert_eq!(
+                    test_instance.name, "test_multiplex_param",
+                    "only the multiplexed test has variants"
+                );
+                assert_eq!(
+                    run_statuses.last_status().result,
+                    ExecutionResult::Pass,
+                    "variant {} passed",
+                    variant
+                );
+                variants.push(variant.value.to_owned());
+            }
+        }
+        _ => {}
+    });
+
+    variants.sort_unstable();
+    assert_eq!(variants, ["a", "b", "c"], "each variant was run once");
+    assert_eq!(
+        run_count,
+        Some(test_list.run_count() + 2),
+        "run count includes the extra variants"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dependencies() -> Result<()> {
+    let test_filter = TestFilterBuilder::any(RunIgnored::Default);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile("with-dependencies")
+        .expect("with-dependencies config is valid");
+    let dependencies = TestDependencies::new(profile.dependency_rules(), &test_list)?;
+
+    // With a single test thread, a dependent test holding up the thread would never finish.
+    let runner = TestRunnerBuilder::default()
+        .set_test_threads(1)
+        .set_dependencies(dependencies)
+        .build(&test_list, &profile, SignalHandler::noop());
+
+    let mut finished = Vec::new();
+    runner.execute(|event| {
+        if let TestEvent::TestFinished {
+            test_instance,
+            run_statuses,
+        } = event
+        {
+            let status = run_statuses.last_status();
+            finished.push((
+                test_instance.name,
+                status.result,
+                String::from_utf8_lossy(status.stderr()).into_owned(),
+            ));
+        }
+    });
+    let position = |name| {
+        finished
+            .iter()
+            .position(|(finished_name, _, _)| *finished_name == name)
+            .unwrap_or_else(|| panic!("{} finished", name))
+    };
+
+    // test_cwd would normally be run first, but waits for test_success, which passes.
+    assert!(
+        position("test_success") < position("test_cwd"),
+        "test_cwd finished after test_success: {:?}",
+        finished
+    );
+    assert_eq!(finished[position("test_cwd")].1, ExecutionResult::Pass);
+
+    // test_cargo_env_vars depends on test_failure_assert, which fails, so it isn't run.
+    assert!(position("test_failure_assert") < position("test_cargo_env_vars"));
+    let (_, result, stderr) = &finished[position("test_cargo_env_vars")];
+    assert_eq!(*result, ExecutionResult::ExecFail);
+    assert!(
+        stderr.contains("tests it depends on didn't pass")
+            && stderr.contains("test_failure_assert"),
+        "failed dependency noted in stderr: {}",
+        stderr
+    );
+    Ok(())
+}
+
+#[test]
+fn test_log_file_attachment() -> Result<()> {
+    let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["test_log_file"]);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+    let run_store = RunStore::create(&profile)?;
+
+    let runner = TestRunnerBuilder::default().set_run_store(run_store).build(
+        &test_list,
+        &profile,
+        SignalHandler::noop(),
+    );
+
+    let mut attachments = Vec::new();
+    runner.execute(|event| {
+        if let TestEvent::TestFinished {
+            test_instance,
+            run_statuses,
+        } = event
+        {
+            if test_instance.name == "test_log_file" {
+                attachments.extend(run_statuses.last_status().attachments.clone());
+            }
+        }
+    });
+
+    assert_eq!(attachments.len(), 1, "log file collected");
+    assert_eq!(
+        attachments[0].contents.as_slice(),
+        b"this is a log line\n",
+        "log file contents collected"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_tmp_dir_failure() -> Result<()> {
+    let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["test_success"]);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+    let run_store = RunStore::create(&profile)?;
+    // A file where the temporary directories are created stops them from being set up.
+    std::fs::write(run_store.run_dir().join("tmp"), "")?;
+
+    let runner = TestRunnerBuilder::default().set_run_store(run_store).build(
+        &test_list,
+        &profile,
+        SignalHandler::noop(),
+    );
+
+    let mut statuses = Vec::new();
+    runner.execute(|event| {
+        if let TestEvent::TestFinished { run_statuses, .. } = event {
+            let status = run_statuses.last_status();
+            statuses.push((
+                status.result,
+                String::from_utf8_lossy(status.stderr()).into_owned(),
+            ));
+        }
+    });
+
+    assert!(!statuses.is_empty(), "tests were finished");
+    for (result, stderr) in statuses {
+        assert_eq!(result, ExecutionResult::ExecFail);
+        assert!(
+            stderr.contains("temporary directory couldn't be set up"),
+            "reason noted in stderr: {}",
+            stderr
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_run_report() -> Result<()> {
+    let test_filter = TestFilterBuilder::new(
+        RunIgnored::Default,
+        None,
+        &[
+            "test_success",
+            "test_failure_assert",
+            "test_flaky_mod_2",
+            "test_ignored",
+        ],
+    );
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile("with-retries")
+        .expect("with-retries config is valid");
+    std::fs::create_dir_all(profile.store_dir())?;
+    let run_store = RunStore::create(&profile)?;
+
+    let runner = TestRunnerBuilder::default().build(&test_list, &profile, SignalHandler::noop());
+    let mut recorder = RunReportRecorder::new(&run_store);
+    runner.try_execute(|event| recorder.write_event(&event))?;
+
+    let report_path = RunStore::latest_report_path(profile.store_dir());
+    assert_eq!(report_path, run_store.report_path());
+    let summary = RunReportSummary::parse_json(std::fs::read_to_string(&report_path)?)?;
+    assert_eq!(&summary.metadata, run_store.metadata());
+    assert!(!summary.success, "run should be marked failed");
+
+    // Tests that didn't match the filter are recorded as skipped as well.
+    let statuses: BTreeMap<_, _> = summary
+        .tests
+        .iter()
+        .filter(|test| test.binary_id == "nextest-tests::basic")
+        .map(|test| (test.name.as_str(), (test.status, test.attempts.len())))
+        .collect();
+    for (name, expected) in [
+        ("test_cwd", (TestReportStatus::Skipped, 0)),
+        ("test_failure_assert", (TestReportStatus::Fail, 3)),
+        ("test_flaky_mod_2", (TestReportStatus::Flaky, 2)),
+        ("test_ignored", (TestReportStatus::Skipped, 0)),
+        ("test_success", (TestReportStatus::Pass, 1)),
+        ("test_success_should_panic", (TestReportStatus::Pass, 1)),
+    ] {
+        assert_eq!(statuses.get(name), Some(&expected), "status for {}", name);
+    }
+    let skip_kinds: BTreeMap<_, _> = summary
+        .tests
+        .iter()
+        .filter(|test| test.binary_id == "nextest-tests::basic")
+        .filter_map(|test| Some((test.name.as_str(), test.skip_reason.as_ref()?.kind)))
+        .collect();
+    assert_eq!(skip_kinds.get("test_cwd"), Some(&SkipKind::String));
+    assert_eq!(skip_kinds.get("test_ignored"), Some(&SkipKind::Ignored));
+    assert_eq!(
+        skip_kinds.get("test_success"),
+        None,
+        "no skip reason for passing tests"
+    );
+
+    let failure = summary
+        .tests
+        .iter()
+        .find(|test| test.name == "test_failure_assert")
+        .expect("test_failure_assert in report");
+    let stderr_path = failure.attempts[2]
+        .stderr_path
+        .as_ref()
+        .expect("stderr recorded for failing attempt");
+    assert!(
+        std::fs::read_to_string(stderr_path)?.contains("this is an assertion"),
+        "stderr of failing attempt saved"
+    );
+
+    let mut report = RunReport::read(&report_path)?;
+    report.filter(&[TestReportStatus::Fail, TestReportStatus::Flaky], &["mod"]);
+    let names: Vec<_> = report
+        .summary()
+        .tests
+        .iter()
+        .map(|test| test.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        ["test_flaky_mod_2"],
+        "report filtered by status and name"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_event_stream() -> Result<()> {
+    use nextest_metadata::{OutputStream, RunEvent, TestEventId};
+    use nextest_runner::event_stream::EventStream;
+    use std::{
+        io::{BufRead, BufReader},
+        os::unix::net::UnixListener,
+    };
+
+    let test_filter = TestFilterBuilder::new(
+        RunIgnored::Default,
+        None,
+        &["test_success", "test_flaky_mod_2"],
+    );
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile("with-retries")
+        .expect("with-retries config is valid");
+    std::fs::create_dir_all(profile.store_dir())?;
+    let run_store = RunStore::create(&profile)?;
+
+    let socket_dir = tempfile::tempdir()?;
+    let socket_path = Utf8PathBuf::try_from(socket_dir.path().join("events.sock"))?;
+    let listener = UnixListener::bind(&socket_path)?;
+    let reader = std::thread::spawn(move || -> std::io::Result<Vec<RunEvent>> {
+        let (stream, _) = listener.accept()?;
+        Ok(BufReader::new(stream)
+            .lines()
+            .map(|line| RunEvent::parse_json(line.expect("line read")).expect("valid event"))
+            .collect())
+    });
+
+    let runner = TestRunnerBuilder::default().build(&test_list, &profile, SignalHandler::noop());
+    let mut event_stream = EventStream::connect(&run_store, &socket_path)?;
+    runner.execute(|event| event_stream.write_event(&event));
+    // Dropping the stream closes the connection.
+    std::mem::drop(event_stream);
+    let events = reader.join().expect("reader thread didn't panic")?;
+
+    match (events.first(), events.last()) {
+        (Some(RunEvent::RunStarted { run, .. }), Some(RunEvent::RunFinished { summary, .. })) => {
+            assert_eq!(&run.metadata, run_store.metadata());
+            assert!(summary.success, "run should be marked successful");
+        }
+        other => panic!("unexpected first and last events: {:?}", other),
+    }
+
+    let is_flaky = |test: &TestEventId| test.name == "test_flaky_mod_2";
+    let retries = events
+        .iter()
+        .filter(|event| matches!(event, RunEvent::TestRetry { test, .. } if is_flaky(test)))
+        .count();
+    assert_eq!(retries, 1, "flaky test retried once");
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            RunEvent::TestFinished { test, status: TestReportStatus::Flaky, attempts: 2, .. }
+                if is_flaky(test)
+        )),
+        "flaky test finished as flaky"
+    );
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            RunEvent::TestOutput { test, attempt: 1, stream: OutputStream::Stdout, data }
+                if is_flaky(test) && data.contains("running 1 test")
+        )),
+        "output of first attempt published"
+    );
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, RunEvent::TestSkipped { .. })),
+        "tests not matching the filter published as skipped"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_custom_reporter() -> Result<()> {
+    use nextest_metadata::{ReporterHandshake, RunEvent};
+    use nextest_runner::{custom_reporter::CustomReporter, errors::CustomReporterError};
+
+    let dir = tempfile::tempdir()?;
+    let dir_path = Utf8PathBuf::try_from(dir.path().to_path_buf())?;
+    let config_path = dir_path.join("nextest.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            r#"
+            [reporters.custom.capture]
+            command = ["sh", "-c", "read -r hello; echo \"$hello\" > '{dir}/hello'; echo '{{\"protocol-version\": 1}}'; cat > '{dir}/events'"]
+
+            [reporters.custom.future]
+            command = ["sh", "-c", "read -r hello; echo '{{\"protocol-version\": 99}}'"]
+
+            [reporters.custom.failing]
+            command = ["sh", "-c", "read -r hello; echo '{{\"protocol-version\": 1}}'; exit 3"]
+            "#,
+            dir = dir_path
+        ),
+    )?;
+    let config = NextestConfig::from_sources(workspace_root(), Some(&config_path))
+        .expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+    std::fs::create_dir_all(profile.store_dir())?;
+    let run_store = RunStore::create(&profile)?;
+    let reporters = config.custom_reporters();
+
+    let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["test_success"]);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let runner = TestRunnerBuilder::default().build(&test_list, &profile, SignalHandler::noop());
+    let mut reporter = CustomReporter::start(
+        "capture",
+        &reporters["capture"],
+        &workspace_root(),
+        &run_store,
+        "0.1.0",
+    )?;
+    runner.execute(|event| reporter.write_event(&event));
+    reporter.finish()?;
+
+    let hello = ReporterHandshake::parse_json(std::fs::read_to_string(dir_path.join("hello"))?)?;
+    assert_eq!(hello, ReporterHandshake::new("0.1.0"));
+    let events: Vec<_> = std::fs::read_to_string(dir_path.join("events"))?
+        .lines()
+        .map(|line| RunEvent::parse_json(line).expect("valid event"))
+        .collect();
+    assert!(
+        matches!(events.first(), Some(RunEvent::RunStarted { .. })),
+        "first event is run-started"
+    );
+    match events.last() {
+        Some(RunEvent::RunFinished { summary, .. }) => {
+            assert!(summary.success, "run should be marked successful");
+        }
+        other => panic!("unexpected last event: {:?}", other),
+    }
+
+    let err = CustomReporter::start(
+        "future",
+        &reporters["future"],
+        &workspace_root(),
+        &run_store,
+        "0.1.0",
+    )
+    .expect_err("protocol version is unsupported");
+    assert!(
+        matches!(err, CustomReporterError::Handshake { .. }),
+        "unexpected error: {}",
+        err
+    );
+
+    let reporter = CustomReporter::start(
+        "failing",
+        &reporters["failing"],
+        &workspace_root(),
+        &run_store,
+        "0.1.0",
+    )?;
+    let err = reporter.finish().expect_err("reporter exits with status 3");
+    assert!(
+        matches!(err, CustomReporterError::Exited { status, .. } if status.code() == Some(3)),
+        "unexpected error: {}",
+        err
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_selector() -> Result<()> {
+    use nextest_runner::{errors::SelectorError, selector::Selector};
+
+    let dir = tempfile::tempdir()?;
+    let config_path = Utf8PathBuf::try_from(dir.path().join("nextest.toml"))?;
+    std::fs::write(
+        &config_path,
+        r#"
+        [selectors.pick-two]
+        command = ["sh", "-c", "cat > /dev/null; echo '{\"protocol-version\": 1, \"tests\": [{\"binary-id\": \"nextest-tests::basic\", \"name\": \"test_success_should_panic\"}, {\"binary-id\": \"nextest-tests::basic\", \"name\": \"test_success\"}, {\"binary-id\": \"missing\", \"name\": \"missing\"}]}'"]
+
+        [selectors.failing]
+        command = ["sh", "-c", "exit 1"]
+
+        [selectors.slow]
+        command = ["sleep", "5"]
+        timeout = "100ms"
+        "#,
+    )?;
+    let config = NextestConfig::from_sources(workspace_root(), Some(&config_path))
+        .expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+
+    let test_filter = TestFilterBuilder::any(RunIgnored::Default);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let mut test_list = TestList::new(test_bins, &test_filter)?;
+
+    let selection = Selector::new(&config, "pick-two")
+        .expect("selector is defined")
+        .select(&workspace_root(), &test_list, "0.1.0")?;
+    assert_eq!(selection.len(), 3);
+    test_list.apply_selection(&selection);
+    assert_eq!(test_list.run_count(), 2, "only selected tests are run");
+
+    let runner = TestRunnerBuilder::default()
+        .set_test_threads(1)
+        .set_selection(selection)
+        .build(&test_list, &profile, SignalHandler::noop());
+    let mut started = vec![];
+    let mut unselected = 0;
+    runner.execute(|event| match event {
+        TestEvent::TestStarted { test_instance } => started.push(test_instance.name.to_owned()),
+        TestEvent::TestSkipped {
+            reason: MismatchReason::Unselected,
+            ..
+        } => unselected += 1,
+        _ => {}
+    });
+    assert_eq!(
+        started,
+        ["test_success_should_panic", "test_success"],
+        "selected tests are started in the chosen order"
+    );
+    let expected_unselected = test_list
+        .iter_tests()
+        .filter(|instance| {
+            instance.test_info.filter_match
+                == FilterMatch::Mismatch {
+                    reason: MismatchReason::Unselected,
+                }
+        })
+        .count();
+    assert_eq!(unselected, expected_unselected);
+
+    for (name, description) in [("failing", "non-zero exit"), ("slow", "timeout")] {
+        let err = Selector::new(&config, name)
+            .expect("selector is defined")
+            .select(&workspace_root(), &test_list, "0.1.0")
+            .expect_err(description);
+        assert!(
+            matches!(
+                (name, &err),
+                ("failing", SelectorError::Exited { .. })
+                    | ("slow", SelectorError::TimedOut { .. })
+            ),
+            "unexpected error for {}: {}",
+            name,
+            err
+        );
+    }
+    Selector::new(&config, "missing").expect_err("selector isn't defined");
+
+    Ok(())
+}
+
+#[test]
+fn test_runtime_skip() -> Result<()> {
+    let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["test_runtime_skip"]);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+    let run_store = RunStore::create(&profile)?;
+
+    let runner = TestRunnerBuilder::default().set_run_store(run_store).build(
+        &test_list,
+        &profile,
+        SignalHandler::noop(),
+    );
+
+    let (instance_statuses, run_stats) = execute_collect(&runner);
+    let statuses: Vec<_> = instance_statuses
+        .values()
+        .filter_map(|value| match &value.status {
+            InstanceStatus::Finished(run_statuses) => {
+                let last_status = run_statuses.last_status();
+                Some((last_status.result, last_status.skip_reason.clone()))
+            }
+            InstanceStatus::Skipped(_) => None,
+        })
+        .collect();
+    assert_eq!(
+        statuses,
+        [(
+            ExecutionResult::Skip,
+            Some("required hardware not present".to_owned())
+        )],
+        "test_runtime_skip skipped itself"
+    );
+    assert_eq!(
+        run_stats.runtime_skipped, 1,
+        "counted as skipped at runtime"
+    );
+    assert_eq!(run_stats.passed, 0, "not counted as passed");
+    assert!(run_stats.is_success(), "skipping doesn't fail the run");
+    Ok(())
+}
+
+#[test]
+fn test_max_duration() -> Result<()> {
+    let test_filter = TestFilterBuilder::any(RunIgnored::Default);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+
+    // With a budget of zero, no test can be started.
+    let runner = TestRunnerBuilder::default()
+        .set_max_duration(Duration::ZERO, TestTimings::default())
+        .build(&test_list, &profile, SignalHandler::noop());
+
+    let (instance_statuses, run_stats) = execute_collect(&runner);
+    let overflowed = instance_statuses
+        .values()
+        .filter(|value| match &value.status {
+            InstanceStatus::Skipped(reason) => *reason == MismatchReason::Overflow,
+            InstanceStatus::Finished(_) => panic!("no tests should be run"),
+        })
+        .count();
+    assert_eq!(
+        overflowed, run_stats.initial_run_count,
+        "every test that would be run overflowed"
+    );
+    assert_eq!(run_stats.overflowed, overflowed, "overflowed tests counted");
+    assert!(run_stats.timing_predictions > 0, "at least one prediction");
+    assert_eq!(
+        run_stats.timing_misses, run_stats.timing_predictions,
+        "without timing data, every prediction misses"
+    );
+    assert!(run_stats.is_success(), "overflowing doesn't fail the run");
+    Ok(())
+}
+
+#[test]
+fn test_junit_skip_reasons() -> Result<()> {
+    let test_filter = TestFilterBuilder::new(
+        RunIgnored::Default,
+        None,
+        &["test_runtime_skip", "test_ignored"],
+    );
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile("with-junit")
+        .expect("with-junit config is valid");
+    let junit_path = profile.junit().expect("junit configured").path().to_owned();
+    let run_store = RunStore::create(&profile)?;
+
+    let runner = TestRunnerBuilder::default().set_run_store(run_store).build(
+        &test_list,
+        &profile,
+        SignalHandler::noop(),
+    );
+    let mut reporter = TestReporterBuilder::default().build(&test_list, &profile);
+    runner.try_execute(|event| reporter.report_event(event, std::io::sink()))?;
+
+    let junit = std::fs::read_to_string(&junit_path)?;
+    for expected in [
+        r#"<testcase name="test_runtime_skip" classname="nextest-tests::basic""#,
+        r#"<skipped message="required hardware not present" type="runtime"/>"#,
+        r#"<skipped message="ignored" type="ignored"/>"#,
+        r#"<skipped message="test does not match the provided string filters" type="string"/>"#,
+    ] {
+        assert!(
+            junit.contains(expected),
+            "JUnit report contains `{}`:\n{}",
+            expected,
+            junit
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_env_files() -> Result<()> {
+    let test_filter = TestFilterBuilder::new(RunIgnored::Default, None, &["test_env_file"]);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile("with-env-files")
+        .expect("with-env-files config is valid");
+    let env_files = EnvFiles::load(&profile)?;
+    assert!(
+        env_files
+            .vars()
+            .any(|(key, value)| key == "NEXTEST_ENV_FILE_VAR" && value == "from env file"),
+        "variable loaded from env file"
+    );
+    let summaries = env_files.summaries();
+    assert!(
+        summaries
+            .iter()
+            .any(|summary| summary.keys.contains("NEXTEST_ENV_FILE_VAR")),
+        "variable name recorded"
+    );
+    let summaries_json = serde_json::to_string(&summaries)?;
+    assert!(
+        !summaries_json.contains("from env file"),
+        "variable values aren't recorded: {}",
+        summaries_json
+    );
+
+    let runner = TestRunnerBuilder::default().set_env_files(env_files).build(
+        &test_list,
+        &profile,
+        SignalHandler::noop(),
+    );
+
+    let (instance_statuses, _) = execute_collect(&runner);
+    let statuses: Vec<_> = instance_statuses
+        .values()
+        .filter_map(|value| match &value.status {
+            InstanceStatus::Finished(run_statuses) => Some(run_statuses.last_status().result),
+            InstanceStatus::Skipped(_) => None,
+        })
+        .collect();
+    assert_eq!(statuses, [ExecutionResult::Pass], "test_env_file passed");
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_output_filter() -> Result<()> {
+    let test_filter = TestFilterBuilder::new(
+        RunIgnored::Default,
+        None,
+        &["test_success", "test_failure_assert"],
+    );
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let test_list = TestList::new(test_bins, &test_filter)?;
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile("with-output-filter")
+        .expect("with-output-filter config is valid");
+
+    let runner = TestRunnerBuilder::default().build(&test_list, &profile, SignalHandler::noop());
+
+    let mut stderrs = HashMap::new();
+    runner.execute(|event| {
+        if let TestEvent::TestFinished {
+            test_instance,
+            run_statuses,
+        } = event
+        {
+            let stderr = String::from_utf8_lossy(run_statuses.last_status().stderr()).into_owned();
+            stderrs.insert(test_instance.name, stderr);
+        }
+    });
+
+    let failure_stderr = &stderrs["test_failure_assert"];
+    assert!(
+        failure_stderr.contains("THIS IS AN ASSERTION"),
+        "output of failed test is filtered: {}",
+        failure_stderr
+    );
+    assert!(
+        !stderrs["test_success"].contains("TEST"),
+        "output of passing test is not filtered"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_synthetic_tests() -> Result<()> {
+    let test_filter =
+        TestFilterBuilder::new(RunIgnored::Default, None, &["test_success", "check_"]);
+    let test_bins: Vec<_> = FIXTURE_TARGETS.values().cloned().collect();
+    let mut test_list = TestList::new(test_bins, &test_filter)?;
+    let rust_test_count = test_list.test_count();
+
+    let package = FIXTURE_TARGETS["nextest-tests::basic"].package;
+    let mut env_check = SyntheticTestBuilder::new("check_env", "sh");
+    env_check
+        .add_args(["-c", "test \"$SYNTHETIC_VAR\" = expected"])
+        .set_env("SYNTHETIC_VAR", "expected");
+    let mut cwd_check = SyntheticTestBuilder::new("check_cwd", "sh");
+    cwd_check
+        .add_args(["-c", "test \"$(pwd)\" = /"])
+        .set_cwd("/");
+    let mut failing_check = SyntheticTestBuilder::new("check_failure", "sh");
+    failing_check.add_args(["-c", "exit 1"]);
+    let skipped_check = SyntheticTestBuilder::new("skipped", "false");
+    test_list.add_synthetic_tests(
+        package,
+        "checks",
+        [
+            env_check.build(),
+            cwd_check.build(),
+            failing_check.build(),
+            skipped_check.build(),
+        ],
+        &test_filter,
+    )?;
+    assert_eq!(test_list.test_count(), rust_test_count + 4);
+    assert_eq!(
+        test_list.to_summary().test_count,
+        rust_test_count,
+        "synthetic tests aren't part of the summary"
+    );
+
+    let error = test_list
+        .add_synthetic_tests(package, "checks", [], &test_filter)
+        .expect_err("suites must be unique");
+    assert!(
+        matches!(error, SyntheticTestError::DuplicateSuite { ref binary_id } if binary_id == "nextest-tests::checks"),
+        "unexpected error: {}",
+        error
+    );
+
+    let config =
+        NextestConfig::from_sources(workspace_root(), None).expect("loaded fixture config");
+    let profile = config
+        .profile(NextestConfig::DEFAULT_PROFILE)
+        .expect("default config is valid");
+    let runner = TestRunnerBuilder::default().build(&test_list, &profile, SignalHandler::noop());
+    let (instance_statuses, run_stats) = execute_collect(&runner);
+
+    let result = |name| match &instance_statuses[&(Utf8Path::new("sh"), name)].status {
+        InstanceStatus::Finished(run_statuses) => run_statuses.last_status().result,
+        InstanceStatus::Skipped(reason) => panic!("{} was skipped: {}", name, reason),
+    };
+    assert_eq!(result("check_env"), ExecutionResult::Pass);
+    assert_eq!(result("check_cwd"), ExecutionResult::Pass);
+    assert_eq!(result("check_failure"), ExecutionResult::Fail);
+    assert!(
+        matches!(
+            instance_statuses[&(Utf8Path::new("false"), "skipped")].status,
+            InstanceStatus::Skipped(MismatchReason::String)
+        ),
+        "the filter applies to synthetic tests"
+    );
+    assert_eq!(
+        instance_statuses[&(Utf8Path::new("sh"), "check_env")].binary_id,
+        "nextest-tests::checks"
+    );
+    // The three Rust tests matching test_success, along with the checks.
+    assert_eq!(run_stats.final_run_count, 6);
+
+    Ok(())
+}
+
 fn execute_collect<'a>(
     runner: &TestRunner<'a>,
 ) -> (
@@ -439,3 +1491,29 @@ fn execute_collect<'a>(
 
     (instance_statuses, run_stats)
 }
+
+#[test]
+fn test_workspace_fingerprint() -> Result<()> {
+    let fingerprint =
+        WorkspaceFingerprint::compute_with_rustc_version(&PACKAGE_GRAPH, "rustc 1.0.0")?;
+    assert_eq!(
+        fingerprint,
+        WorkspaceFingerprint::compute_with_rustc_version(&PACKAGE_GRAPH, "rustc 1.0.0")?,
+        "fingerprint is stable"
+    );
+    let summary = fingerprint.to_summary();
+    assert_eq!(summary.rustc_version, "rustc 1.0.0");
+    assert!(summary.lockfile.is_some(), "fixture has a lockfile");
+
+    let other = WorkspaceFingerprint::compute_with_rustc_version(&PACKAGE_GRAPH, "rustc 2.0.0")?;
+    let other_summary = other.to_summary();
+    assert_eq!(
+        summary.sources, other_summary.sources,
+        "sources don't depend on rustc"
+    );
+    assert_ne!(
+        summary.fingerprint, other_summary.fingerprint,
+        "fingerprint changes with rustc"
+    );
+    Ok(())
+}
@@ -5,6 +5,7 @@ use crate::serialize::serialize_report;
 use chrono::{DateTime, FixedOffset};
 use indexmap::map::IndexMap;
 use std::{io, iter, time::Duration};
+use uuid::Uuid;
 
 /// The root element of a JUnit report.
 #[derive(Clone, Debug)]
@@ -12,6 +13,12 @@ pub struct Report {
     /// The name of this report.
     pub name: String,
 
+    /// A unique identifier for this report, typically the ID of the test run that produced it.
+    ///
+    /// This is not part of the JUnit spec, but may be useful for correlating reports with other
+    /// artifacts from the same run.
+    pub uuid: Option<Uuid>,
+
     /// The time at which the first test in this report began execution.
     ///
     /// This is not part of the JUnit spec, but may be useful for some tools.
@@ -40,6 +47,7 @@ impl Report {
     pub fn new(name: impl Into<String>) -> Self {
         Self {
             name: name.into(),
+            uuid: None,
             timestamp: None,
             time: None,
             tests: 0,
@@ -49,6 +57,12 @@ impl Report {
         }
     }
 
+    /// Sets the unique identifier for this report.
+    pub fn set_uuid(&mut self, uuid: Uuid) -> &mut Self {
+        self.uuid = Some(uuid);
+        self
+    }
+
     /// Sets the start timestamp for the report.
     pub fn set_timestamp(&mut self, timestamp: impl Into<DateTime<FixedOffset>>) -> &mut Self {
         self.timestamp = Some(timestamp.into());
@@ -25,6 +25,11 @@ fn fixtures() {
 
 fn basic_report() -> Report {
     let mut report = Report::new("my-test-run");
+    report.set_uuid(
+        "0f8fad5b-d9cb-469f-a165-70867728950e"
+            .parse()
+            .expect("valid UUID"),
+    );
     report.set_timestamp(
         DateTime::parse_from_rfc2822("Thu, 1 Apr 2021 10:52:37 -0800")
             .expect("valid RFC2822 datetime"),